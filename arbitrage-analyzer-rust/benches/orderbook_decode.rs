@@ -0,0 +1,51 @@
+// Throughput of order book decoding at the depths the collectors produce.
+// Compare the two decoders with:
+//   cargo bench --bench orderbook_decode
+//   cargo bench --bench orderbook_decode --features simd-json
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde::Deserialize;
+
+#[path = "../src/codec.rs"]
+#[allow(dead_code)]
+mod codec;
+
+// Wire format of the go collector, same fields as the analyzer's OrderBook
+#[derive(Debug, Deserialize)]
+#[allow(dead_code)]
+struct OrderBook {
+    exchange: String,
+    pair: String,
+    bids: Vec<Vec<f64>>,
+    asks: Vec<Vec<f64>>,
+    timestamp: i64,
+}
+
+fn book_json(depth: usize) -> String {
+    let bids: Vec<[f64; 2]> = (0..depth).map(|i| [50_000.0 - i as f64 * 0.5, 0.1 + i as f64 * 0.013]).collect();
+    let asks: Vec<[f64; 2]> = (0..depth).map(|i| [50_000.5 + i as f64 * 0.5, 0.1 + i as f64 * 0.017]).collect();
+    serde_json::json!({
+        "exchange": "binance",
+        "pair": "BTC/USDT",
+        "bids": bids,
+        "asks": asks,
+        "timestamp": 1_699_999_999i64,
+    })
+    .to_string()
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("decode_book/{}", codec::DECODER));
+    for depth in [20, 100, 1000, 5000] {
+        let json = book_json(depth);
+        group.throughput(Throughput::Bytes(json.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &json, |b, json| {
+            // decode_book consumes its input; the clone costs the same for both decoders
+            b.iter(|| codec::decode_book::<OrderBook>(black_box(json.clone())).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
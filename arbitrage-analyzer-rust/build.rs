@@ -0,0 +1,63 @@
+// Stamps the binary with what `/buildinfo` reports: the git commit it was built
+// from and when. Builds outside a checkout (e.g. a Docker context without .git)
+// can pass GIT_SHA; SOURCE_DATE_EPOCH pins the build time for reproducible builds.
+// With `--features grpc` it also generates the gRPC server of proto/swapsleuth.proto.
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    let out = String::from_utf8(output.stdout).ok()?;
+    (output.status.success() && !out.trim().is_empty()).then(|| out.trim().to_string())
+}
+
+fn main() {
+    let sha = std::env::var("GIT_SHA").ok().or_else(|| git(&["rev-parse", "--short=12", "HEAD"])).unwrap_or_else(|| "unknown".to_string());
+    let built_at = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|raw| raw.trim().parse::<u64>().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0));
+    println!("cargo:rustc-env=BUILD_GIT_SHA={}", sha);
+    println!("cargo:rustc-env=BUILD_EPOCH_SECS={}", built_at);
+
+    // Stamp again on new commits and source changes, not only when this file changes
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        println!("cargo:rerun-if-changed={}/refs", git_dir);
+    }
+    for path in ["build.rs", "Cargo.toml", "src"] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    #[cfg(feature = "grpc")]
+    grpc_server();
+}
+
+// Generated from a description of the service rather than the .proto, so builds
+// need no protoc. The messages are written out in src/grpc.rs
+#[cfg(feature = "grpc")]
+fn grpc_server() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::proto::{}", input))
+            .output_type(format!("crate::grpc::proto::{}", output))
+            .codec_path("tonic::codec::ProstCodec")
+    };
+    let service = Service::builder()
+        .name("Analyzer")
+        .package("swapsleuth.v1")
+        .method(method("subscribe_opportunities", "SubscribeOpportunities", "SubscribeRequest", "Opportunity").server_streaming().build())
+        .method(method("pause", "Pause", "PauseRequest", "State").build())
+        .method(method("resume", "Resume", "ResumeRequest", "State").build())
+        .method(method("set_thresholds", "SetThresholds", "SetThresholdsRequest", "State").build())
+        .method(method("get_state", "GetState", "GetStateRequest", "State").build())
+        .build();
+    Builder::new().build_client(false).compile(&[service]);
+}
@@ -0,0 +1,95 @@
+-- Opportunity and execution history, written by the analyzer when DATABASE_URL is set.
+-- Applied on startup; every statement is idempotent.
+
+CREATE TABLE IF NOT EXISTS routes (
+    id            SERIAL PRIMARY KEY,
+    pair          TEXT NOT NULL,
+    buy_exchange  TEXT NOT NULL,
+    sell_exchange TEXT NOT NULL,
+    UNIQUE (pair, buy_exchange, sell_exchange)
+);
+
+CREATE TABLE IF NOT EXISTS opportunities (
+    id                    TEXT PRIMARY KEY,
+    route_id              INTEGER NOT NULL REFERENCES routes (id),
+    detected_at           TIMESTAMPTZ NOT NULL,
+    buy_price             DOUBLE PRECISION NOT NULL,
+    sell_price            DOUBLE PRECISION NOT NULL,
+    max_size              DOUBLE PRECISION NOT NULL,
+    sell_size             DOUBLE PRECISION NOT NULL,
+    gross_profit_per_unit DOUBLE PRECISION NOT NULL,
+    estimated_fees        DOUBLE PRECISION NOT NULL,
+    net_profit            DOUBLE PRECISION NOT NULL,
+    roi_percentage        DOUBLE PRECISION NOT NULL
+);
+CREATE INDEX IF NOT EXISTS opportunities_route_detected_idx ON opportunities (route_id, detected_at DESC);
+CREATE INDEX IF NOT EXISTS opportunities_detected_idx ON opportunities (detected_at DESC);
+
+CREATE TABLE IF NOT EXISTS execution_requests (
+    id             TEXT PRIMARY KEY,
+    opportunity_id TEXT NOT NULL REFERENCES opportunities (id),
+    route_id       INTEGER NOT NULL REFERENCES routes (id),
+    execution_size DOUBLE PRECISION NOT NULL,
+    created_at     TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS lifecycle_transitions (
+    id         BIGSERIAL PRIMARY KEY,
+    request_id TEXT NOT NULL REFERENCES execution_requests (id),
+    state      TEXT NOT NULL,
+    at         TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS lifecycle_transitions_request_idx ON lifecycle_transitions (request_id, at);
+
+CREATE TABLE IF NOT EXISTS execution_results (
+    request_id   TEXT PRIMARY KEY REFERENCES execution_requests (id),
+    state        TEXT NOT NULL,
+    filled_size  DOUBLE PRECISION,
+    realized_pnl DOUBLE PRECISION,
+    detail       TEXT,
+    recorded_at  TIMESTAMPTZ NOT NULL
+);
+
+-- Executor-reported fill details, used for cost attribution
+ALTER TABLE execution_results ADD COLUMN IF NOT EXISTS buy_fill_price     DOUBLE PRECISION;
+ALTER TABLE execution_results ADD COLUMN IF NOT EXISTS sell_fill_price    DOUBLE PRECISION;
+ALTER TABLE execution_results ADD COLUMN IF NOT EXISTS buy_arrival_price  DOUBLE PRECISION;
+ALTER TABLE execution_results ADD COLUMN IF NOT EXISTS sell_arrival_price DOUBLE PRECISION;
+ALTER TABLE execution_results ADD COLUMN IF NOT EXISTS fees_paid          DOUBLE PRECISION;
+ALTER TABLE execution_results ADD COLUMN IF NOT EXISTS gas_paid           DOUBLE PRECISION;
+
+-- Venue coverage the market summary shows, every MARKET_HISTORY_SECS
+CREATE TABLE IF NOT EXISTS market_summaries (
+    id                 BIGSERIAL PRIMARY KEY,
+    recorded_at        TIMESTAMPTZ NOT NULL,
+    books              BIGINT NOT NULL,
+    pairs              BIGINT NOT NULL,
+    cross_listed_pairs BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS market_summaries_recorded_idx ON market_summaries (recorded_at DESC);
+
+CREATE TABLE IF NOT EXISTS venue_coverage (
+    summary_id         BIGINT NOT NULL REFERENCES market_summaries (id) ON DELETE CASCADE,
+    exchange           TEXT NOT NULL,
+    books              BIGINT NOT NULL,
+    pairs              BIGINT NOT NULL,
+    stale_books        BIGINT NOT NULL,
+    updates_per_minute DOUBLE PRECISION NOT NULL,
+    last_update_age_ms BIGINT,
+    rejection_rate     DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (summary_id, exchange)
+);
+CREATE INDEX IF NOT EXISTS venue_coverage_exchange_idx ON venue_coverage (exchange, summary_id);
+
+-- Opportunities past HISTORY_RETENTION_DAYS, aggregated per route and UTC hour
+CREATE TABLE IF NOT EXISTS opportunity_hourly (
+    route_id             INTEGER NOT NULL REFERENCES routes (id),
+    hour                 TIMESTAMPTZ NOT NULL,
+    opportunities        BIGINT NOT NULL,
+    total_net_profit     DOUBLE PRECISION NOT NULL,
+    max_net_profit       DOUBLE PRECISION NOT NULL,
+    total_roi_percentage DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (route_id, hour)
+);
+CREATE INDEX IF NOT EXISTS opportunity_hourly_hour_idx ON opportunity_hourly (hour);
+CREATE INDEX IF NOT EXISTS market_summaries_recorded_asc_idx ON market_summaries (recorded_at);
@@ -0,0 +1,146 @@
+// Executor acknowledgements. A published execution request is expected to be
+// acknowledged (`POST /executions/<id>?state=acknowledged`, or any later state)
+// within EXECUTION_ACK_TIMEOUT_MS (default 5000; 0 disables). One still
+// `published` after that is unacknowledged: it is logged, counted in
+// `swapsleuth_execution_requests_unacked_total` and raised once as an
+// `execution_unacknowledged` event. Until the executor answers or the request
+// expires it stays in the backlog served on `GET /executions/unacked`; an answer
+// that comes after the alert is logged and counted as a late ack.
+
+use std::collections::HashSet;
+use std::sync::atomic::Ordering;
+
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::alerts::Severity;
+use crate::events::{Event, EventClass};
+use crate::lifecycle::{LifecycleTracker, RequestState, RouteKey};
+use crate::metrics::Metrics;
+use crate::{config, SpreadAnalyzer};
+
+const DEFAULT_ACK_TIMEOUT_MS: i64 = 5_000;
+
+/// A published request the executor has not acknowledged in time
+#[derive(Debug, Clone, Serialize)]
+pub struct UnackedRequest {
+    pub id: String,
+    pub route: RouteKey,
+    pub published_at: DateTime<Utc>,
+    pub waiting_ms: i64,
+}
+
+#[derive(Debug)]
+pub struct AckTracker {
+    // None disables the check
+    pub timeout: Option<Duration>,
+    // Requests already alerted on
+    alerted: HashSet<String>,
+}
+
+impl AckTracker {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        AckTracker { timeout, alerted: HashSet::new() }
+    }
+
+    pub fn from_env() -> Self {
+        let timeout_ms = config::env_or("EXECUTION_ACK_TIMEOUT_MS", DEFAULT_ACK_TIMEOUT_MS);
+        Self::new((timeout_ms > 0).then(|| Duration::milliseconds(timeout_ms)))
+    }
+
+    /// Requests in `lifecycle` published longer than the timeout ago without an ack, oldest first
+    pub fn unacked(&self, lifecycle: &LifecycleTracker, now: DateTime<Utc>) -> Vec<UnackedRequest> {
+        let Some(timeout) = self.timeout else { return Vec::new() };
+        lifecycle
+            .in_flight()
+            .into_iter()
+            .filter(|request| request.state == RequestState::Published && now - request.updated_at > timeout)
+            .map(|request| UnackedRequest {
+                id: request.id.clone(),
+                route: request.route.clone(),
+                published_at: request.updated_at,
+                waiting_ms: (now - request.updated_at).num_milliseconds(),
+            })
+            .collect()
+    }
+
+    /// Whether `id` had been alerted on; it is forgotten either way
+    pub fn settle(&mut self, id: &str) -> bool {
+        self.alerted.remove(id)
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Alert on requests that just went unacknowledged
+    pub(crate) fn check_acks(&mut self, now: DateTime<Utc>) {
+        let unacked = self.acks.unacked(&self.lifecycle, now);
+        self.metrics.unacked_execution_requests.store(unacked.len() as u64, Ordering::Relaxed);
+        for request in unacked {
+            if !self.acks.alerted.insert(request.id.clone()) {
+                continue;
+            }
+            Metrics::inc(&self.metrics.execution_requests_unacked);
+            warn!("Execution request {} on {} not acknowledged after {}ms", request.id, request.route, request.waiting_ms);
+            self.publish(
+                Event::new(
+                    EventClass::ExecutionUnacknowledged,
+                    Severity::Warning,
+                    format!("Execution request {} on {} not acknowledged after {}ms", request.id, request.route, request.waiting_ms),
+                )
+                .with_pair(&request.route.pair),
+            );
+        }
+    }
+
+    /// Note the executor's answer to a request that was alerted on
+    pub(crate) fn settle_ack(&mut self, id: &str, state: RequestState) {
+        if state == RequestState::Published || !self.acks.settle(id) || state == RequestState::Expired {
+            return;
+        }
+        Metrics::inc(&self.metrics.late_execution_acks);
+        info!("Execution request {} answered late: {}", id, state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `a` is published, `b` published and acknowledged, `c` never published
+    fn lifecycle(now: DateTime<Utc>) -> LifecycleTracker {
+        let mut lifecycle = LifecycleTracker::new(Duration::seconds(30));
+        for (id, pair) in [("a", "BTC/USDT"), ("b", "ETH/USDT"), ("c", "SOL/USDT")] {
+            lifecycle.open(id, RouteKey::new(pair, "binance", "okx"), now).unwrap();
+        }
+        lifecycle.transition("a", RequestState::Published, now).unwrap();
+        lifecycle.transition("b", RequestState::Published, now).unwrap();
+        lifecycle.transition("b", RequestState::Acknowledged, now + Duration::seconds(1)).unwrap();
+        lifecycle
+    }
+
+    #[test]
+    fn requests_published_without_an_ack_go_unacked_after_the_timeout() {
+        let now = Utc::now();
+        let lifecycle = lifecycle(now);
+        let acks = AckTracker::new(Some(Duration::seconds(5)));
+        assert!(acks.unacked(&lifecycle, now + Duration::seconds(5)).is_empty());
+        let unacked = acks.unacked(&lifecycle, now + Duration::seconds(6));
+        assert_eq!(unacked.iter().map(|r| (r.id.as_str(), r.waiting_ms)).collect::<Vec<_>>(), vec![("a", 6_000)]);
+        assert!(AckTracker::new(None).unacked(&lifecycle, now + Duration::seconds(60)).is_empty());
+    }
+
+    #[test]
+    fn unacked_and_late_acks_are_counted_once() {
+        let now = Utc::now();
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.lifecycle = lifecycle(now);
+        analyzer.acks = AckTracker::new(Some(Duration::seconds(5)));
+        analyzer.check_acks(now + Duration::seconds(6));
+        analyzer.check_acks(now + Duration::seconds(7));
+        assert_eq!(analyzer.metrics.execution_requests_unacked.load(Ordering::Relaxed), 1);
+        assert_eq!(analyzer.metrics.unacked_execution_requests.load(Ordering::Relaxed), 1);
+        analyzer.settle_ack("a", RequestState::Acknowledged);
+        assert_eq!(analyzer.metrics.late_execution_acks.load(Ordering::Relaxed), 1);
+    }
+}
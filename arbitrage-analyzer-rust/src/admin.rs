@@ -0,0 +1,379 @@
+// Admin console over a UNIX domain socket, for servers where no HTTP port may be
+// opened. With ADMIN_SOCKET set (e.g. /run/swapsleuth/admin.sock) the analyzer
+// listens on that path, readable by its owner only; started by systemd socket
+// activation (LISTEN_PID / LISTEN_FDS) it takes the socket it was handed instead.
+// `swapsleuth admin` connects and reads commands from the terminal, or runs the
+// one on its command line:
+//   help                                         the commands
+//   books [pair]                                 the cached books, as `dump-books` writes them
+//   evaluate <pair> <buy venue> <sell venue>     price one route from the cached books
+//   breaker [status | trip <reason> | reset <token>]  the kill switch
+//   pause [reason] / resume                      publishing, as the control commands
+//   tail [count]                                 published opportunities as they go out
+// Commands are answered by the analyzer loop between updates, like API requests.
+// Every reply ends with an empty line.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use log::{info, warn};
+use serde_json::{json, Value};
+
+use crate::control::ControlCommand;
+use crate::{config, ArbitrageOpportunity, SpreadAnalyzer};
+
+// How long a connection waits for the analyzer loop to answer, as for API requests
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const HELP: &str = "\
+help                                          this list
+books [pair]                                  the cached books, or those of one normalized pair
+evaluate <pair> <buy venue> <sell venue>      price one route from the cached books
+breaker [status | trip <reason> | reset <token>]  the kill switch
+pause [reason] / resume                       stop or restart publishing
+tail [count]                                  published opportunities as they go out, one JSON line each
+quit";
+
+/// ADMIN_SOCKET, unset or empty when the console is off
+pub fn socket_from_env() -> Option<PathBuf> {
+    config::env_var("ADMIN_SOCKET").ok().filter(|path| !path.trim().is_empty()).map(PathBuf::from)
+}
+
+// What a connection hands to the analyzer loop
+enum AdminMessage {
+    Command { line: String, reply: Sender<String> },
+    // Send every published opportunity here until the receiver is gone
+    Tail(Sender<String>),
+}
+
+#[derive(Debug)]
+pub struct AdminConsole {
+    requests: Receiver<AdminMessage>,
+    tails: Vec<Sender<String>>,
+    // Removed on shutdown; None when systemd owns the socket
+    path: Option<PathBuf>,
+}
+
+impl std::fmt::Debug for AdminMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdminMessage::Command { line, .. } => write!(f, "Command({:?})", line),
+            AdminMessage::Tail(_) => f.write_str("Tail"),
+        }
+    }
+}
+
+impl AdminConsole {
+    /// Answer every command received since the last poll
+    pub fn serve(&mut self, analyzer: &mut SpreadAnalyzer) {
+        for message in self.requests.try_iter() {
+            match message {
+                AdminMessage::Command { line, reply } => {
+                    // The connection may have timed out already; nothing to do then
+                    let _ = reply.send(analyzer.admin_command(&line));
+                }
+                AdminMessage::Tail(tail) => self.tails.push(tail),
+            }
+        }
+    }
+
+    /// Send `opp` to every tailing connection, forgetting those that went away
+    pub fn broadcast(&mut self, opp: &ArbitrageOpportunity) {
+        if self.tails.is_empty() {
+            return;
+        }
+        let line = serde_json::to_string(opp).unwrap_or_default();
+        self.tails.retain(|tail| tail.send(line.clone()).is_ok());
+    }
+
+    pub fn close(self) {
+        if let Some(path) = self.path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(unix)]
+mod socket {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::io::FromRawFd;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::thread;
+
+    use super::*;
+
+    // The first socket systemd passes, see sd_listen_fds(3)
+    const SD_LISTEN_FDS_START: i32 = 3;
+
+    fn activated_listener() -> Option<UnixListener> {
+        let pid: u32 = config::env_var("LISTEN_PID").ok()?.parse().ok()?;
+        let fds: u32 = config::env_var("LISTEN_FDS").ok()?.parse().ok()?;
+        if pid != std::process::id() || fds == 0 {
+            return None;
+        }
+        // SAFETY: systemd hands this process the listening socket as fd 3, and nothing else owns it
+        Some(unsafe { UnixListener::from_raw_fd(SD_LISTEN_FDS_START) })
+    }
+
+    impl AdminConsole {
+        /// Listen on the socket systemd passed in, or bind `path`; None when neither applies
+        pub fn spawn(path: Option<&Path>) -> Result<Option<Self>> {
+            let (listener, owned) = match (activated_listener(), path) {
+                (Some(listener), _) => {
+                    info!("  Admin console on the socket passed by systemd");
+                    (listener, None)
+                }
+                (None, Some(path)) => {
+                    // A socket file left by a previous run would make the bind fail
+                    if path.exists() {
+                        fs::remove_file(path)?;
+                    }
+                    let listener = UnixListener::bind(path).map_err(|e| anyhow!("Failed to bind admin socket {}: {}", path.display(), e))?;
+                    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+                    info!("  Admin console listening on {}", path.display());
+                    (listener, Some(path.to_path_buf()))
+                }
+                (None, None) => return Ok(None),
+            };
+            let (tx, requests) = mpsc::channel();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            let tx = tx.clone();
+                            thread::spawn(move || {
+                                if let Err(e) = serve_connection(stream, tx) {
+                                    warn!("Admin connection closed: {}", e);
+                                }
+                            });
+                        }
+                        Err(e) => warn!("Admin console failed to accept a connection: {}", e),
+                    }
+                }
+            });
+            Ok(Some(AdminConsole { requests, tails: Vec::new(), path: owned }))
+        }
+    }
+
+    fn serve_connection(stream: UnixStream, analyzer: Sender<AdminMessage>) -> Result<()> {
+        let mut out = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            let line = line.trim();
+            match line.split_whitespace().next() {
+                None => continue,
+                Some("quit" | "exit") => return Ok(()),
+                Some("tail") => {
+                    let count = line.split_whitespace().nth(1).map(str::parse::<usize>).transpose();
+                    let Ok(count) = count else {
+                        writeln!(out, "usage: tail [count]\n")?;
+                        continue;
+                    };
+                    let (tx, rx) = mpsc::channel();
+                    if analyzer.send(AdminMessage::Tail(tx)).is_err() {
+                        writeln!(out, "analyzer is not running\n")?;
+                        continue;
+                    }
+                    // Runs until `count` opportunities went out, or for as long as the client listens
+                    for opportunity in rx.iter().take(count.unwrap_or(usize::MAX)) {
+                        writeln!(out, "{}", opportunity)?;
+                    }
+                    writeln!(out)?;
+                }
+                Some(_) => {
+                    let (reply_tx, reply_rx) = mpsc::channel();
+                    let reply = if analyzer.send(AdminMessage::Command { line: line.to_string(), reply: reply_tx }).is_err() {
+                        "analyzer is not running".to_string()
+                    } else {
+                        reply_rx.recv_timeout(REPLY_TIMEOUT).unwrap_or_else(|_| "analyzer did not answer in time".to_string())
+                    };
+                    writeln!(out, "{}\n", reply.trim_end())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `swapsleuth admin`: run `command`, or every command typed until EOF or `quit`
+    pub fn run_client(path: &Path, command: &[String]) -> Result<()> {
+        let stream = UnixStream::connect(path).map_err(|e| anyhow!("Cannot connect to the admin console at {}: {}", path.display(), e))?;
+        let mut to_analyzer = stream.try_clone()?;
+        let mut replies = BufReader::new(stream);
+        let mut exchange = |line: &str| -> Result<()> {
+            writeln!(to_analyzer, "{}", line)?;
+            let mut reply = String::new();
+            loop {
+                reply.clear();
+                if replies.read_line(&mut reply)? == 0 || reply.trim_end().is_empty() {
+                    return Ok(());
+                }
+                print!("{}", reply);
+            }
+        };
+        if !command.is_empty() {
+            return exchange(&command.join(" "));
+        }
+
+        let stdin = std::io::stdin();
+        loop {
+            print!("swapsleuth> ");
+            std::io::stdout().flush()?;
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = line.trim();
+            if matches!(line, "quit" | "exit") {
+                return Ok(());
+            }
+            if !line.is_empty() {
+                exchange(line)?;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use socket::run_client;
+
+#[cfg(not(unix))]
+impl AdminConsole {
+    pub fn spawn(path: Option<&Path>) -> Result<Option<Self>> {
+        match path {
+            Some(_) => Err(anyhow!("ADMIN_SOCKET is set but UNIX domain sockets are not available on this platform")),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn run_client(_path: &Path, _command: &[String]) -> Result<()> {
+    Err(anyhow!("the admin console needs UNIX domain sockets"))
+}
+
+fn pretty(value: Value) -> String {
+    serde_json::to_string_pretty(&value).unwrap_or_default()
+}
+
+impl SpreadAnalyzer {
+    /// Run one console command and describe the outcome
+    pub(crate) fn admin_command(&mut self, line: &str) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let rest = |from: usize| Some(words.get(from..)?.join(" ")).filter(|rest| !rest.is_empty());
+        let applied = |result: Result<()>, state: Value| match result {
+            Ok(()) => pretty(state),
+            Err(e) => format!("error: {}", e),
+        };
+        match words.as_slice() {
+            [] | ["help"] => HELP.to_string(),
+            ["books"] | ["books", _] => {
+                let mut dump = serde_json::to_value(self.dump_books()).unwrap_or_default();
+                if let (Some(pair), Some(Value::Array(books))) = (words.get(1), dump.get_mut("books")) {
+                    let pair = pair.to_uppercase().replace("WBTC", "BTC");
+                    books.retain(|book| book["normalized_pair"] == pair.as_str());
+                }
+                pretty(dump)
+            }
+            ["evaluate", pair, buy, sell] => self.admin_evaluate(pair, buy, sell),
+            ["breaker"] | ["breaker", "status"] => pretty(json!(self.kill_switch.state())),
+            ["breaker", "trip", ..] => {
+                let command = ControlCommand::KillSwitchTrip { reason: rest(2), actor: None };
+                let result = self.apply_control_command(command, "admin");
+                applied(result, json!(self.kill_switch.state()))
+            }
+            ["breaker", "reset", token] => {
+                let command = ControlCommand::KillSwitchReset { token: token.to_string(), actor: None };
+                let result = self.apply_control_command(command, "admin");
+                applied(result, json!(self.kill_switch.state()))
+            }
+            ["pause", ..] => {
+                let result = self.apply_control_command(ControlCommand::Pause { reason: rest(1), actor: None }, "admin");
+                applied(result, json!(self.control_state()))
+            }
+            ["resume"] => {
+                let result = self.apply_control_command(ControlCommand::Resume { actor: None }, "admin");
+                applied(result, json!(self.control_state()))
+            }
+            _ => format!("unknown command: {} (try `help`)", line.trim()),
+        }
+    }
+
+    /// Price buying `pair` on `buy` and selling on `sell` from the cached books, thresholds included
+    fn admin_evaluate(&self, pair: &str, buy: &str, sell: &str) -> String {
+        let pair = pair.to_uppercase().replace("WBTC", "BTC");
+        let book = |venue: &str| {
+            self.books.values().find(|book| book.exchange.eq_ignore_ascii_case(venue) && book.pair.replace("WBTC", "BTC") == pair)
+        };
+        let (Some(buy_book), Some(sell_book)) = (book(buy), book(sell)) else {
+            return format!("error: no cached {} book on both {} and {}", pair, buy, sell);
+        };
+        let (_, _, price_adjustment) = self.normalize_pair_symbols(&buy_book.pair, &sell_book.pair);
+        let opportunity = self
+            .evaluate_opportunity_depth(&buy_book.exchange, &sell_book.exchange, &pair, &buy_book.asks, &sell_book.bids, price_adjustment)
+            .map(|mut opp| {
+                opp.book_ages = crate::staleness::StalenessPolicy::ages(buy_book, sell_book, Utc::now());
+                opp
+            });
+        let best_ask = buy_book.best_ask().map(|(price, _)| price * price_adjustment);
+        let best_bid = sell_book.best_bid().map(|(price, _)| price);
+        let spread_bps = best_ask.zip(best_bid).and_then(|(ask, bid)| crate::numeric::safe_div((bid - ask) * 10_000.0, ask));
+        pretty(json!({
+            "route": { "pair": pair, "buy_exchange": buy_book.exchange, "sell_exchange": sell_book.exchange },
+            "best_ask": best_ask,
+            "best_bid": best_bid,
+            "spread_bps": spread_bps,
+            "thresholds": self.thresholds,
+            // None: the spread does not pay its costs, or not by the thresholds
+            "opportunity": opportunity,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OrderBook;
+
+    // BTC/USDT on binance and okx, ETH/USDT on okx
+    fn analyzer() -> SpreadAnalyzer {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.books.insert("binance:BTC/USDT".to_string(), OrderBook::for_test("binance", "BTC/USDT", vec![vec![49_990.0, 1.0]], vec![vec![50_000.0, 1.0]]));
+        analyzer.books.insert("okx:BTC/USDT".to_string(), OrderBook::for_test("okx", "BTC/USDT", vec![vec![51_000.0, 1.0]], vec![vec![51_010.0, 1.0]]));
+        analyzer.books.insert("okx:ETH/USDT".to_string(), OrderBook::for_test("okx", "ETH/USDT", vec![vec![3_000.0, 1.0]], vec![vec![3_001.0, 1.0]]));
+        analyzer
+    }
+
+    #[test]
+    fn lists_help_and_books() {
+        let mut analyzer = analyzer();
+        assert!(analyzer.admin_command("help").contains("evaluate <pair>"));
+        let books: Value = serde_json::from_str(&analyzer.admin_command("books btc/usdt")).unwrap();
+        assert_eq!(books["books"].as_array().map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn evaluates_a_route_on_demand() {
+        let mut analyzer = analyzer();
+        let evaluated: Value = serde_json::from_str(&analyzer.admin_command("evaluate BTC/USDT binance okx")).unwrap();
+        assert_eq!(evaluated["spread_bps"], 200.0);
+        assert_eq!(evaluated["opportunity"]["sell_exchange"], "okx");
+        let reverse: Value = serde_json::from_str(&analyzer.admin_command("evaluate BTC/USDT okx binance")).unwrap();
+        assert!(reverse["opportunity"].is_null());
+        assert!(analyzer.admin_command("evaluate BTC/USDT binance kraken").starts_with("error"));
+    }
+
+    #[test]
+    fn pauses_and_resumes_through_control_commands() {
+        let mut analyzer = analyzer();
+        analyzer.admin_command("pause venue incident");
+        assert_eq!(analyzer.paused.as_ref().map(|pause| (pause.reason.as_str(), pause.actor.as_str())), Some(("venue incident", "admin:anonymous")));
+        analyzer.admin_command("resume");
+        assert!(analyzer.paused.is_none());
+        assert!(analyzer.admin_command("breaker reset").starts_with("unknown command"));
+    }
+}
@@ -0,0 +1,174 @@
+// Alert routing. Without ALERT_ROUTES every event goes to every sink subscribed to
+// its class (see `events.rs`). With it, each event is matched against the rules
+// in order and the first matching rule decides which of those sinks get it and at
+// what severity, e.g. critical anomalies page the on-call mailbox while routine
+// detections only reach the webhook channel. The log sink always sees its events.
+//
+// ALERT_ROUTES is a `;`-separated list of `<conditions> -> <sinks>[:<severity>]`,
+// where conditions are `&`-joined `name=value` pairs (or `*` to match anything):
+//   kind=opportunity_detected&pair=BTC/USDT&min_roi=0.5 -> email:critical;
+//   kind=opportunity_* -> webhook;
+//   kind=venue_stale&venue=uniswap-* -> email,webhook:critical
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use crate::alerts::Severity;
+use crate::events::Event;
+use crate::sources::glob_match;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    // Event class (glob), e.g. `venue_*`
+    Kind(String),
+    // Normalized pair (glob) of opportunities and other pair-specific events
+    Pair(String),
+    // Buy and sell venue (globs), written `route=binance>uniswap-*`
+    Route(String, String),
+    // The event's venue or either leg of the opportunity (glob)
+    Venue(String),
+    // ROI band in percent, inclusive
+    MinRoi(f64),
+    MaxRoi(f64),
+    MinProfit(f64),
+    MinSeverity(Severity),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteRule {
+    conditions: Vec<Condition>,
+    sinks: Vec<String>,
+    // Overrides the event's own severity when set
+    severity: Option<Severity>,
+}
+
+fn parse_f64(name: &str, value: &str) -> Result<f64> {
+    value.parse().map_err(|_| anyhow!("invalid number for {}: {:?}", name, value))
+}
+
+impl FromStr for Condition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, value) = s.split_once('=').ok_or_else(|| anyhow!("expected name=value, got {:?}", s))?;
+        let (name, value) = (name.trim(), value.trim());
+        match name {
+            "kind" => Ok(Condition::Kind(value.to_string())),
+            "pair" => Ok(Condition::Pair(value.to_string())),
+            "route" => {
+                let (buy, sell) = value.split_once('>').ok_or_else(|| anyhow!("route must be <buy>><sell>, got {:?}", value))?;
+                Ok(Condition::Route(buy.trim().to_string(), sell.trim().to_string()))
+            }
+            "venue" => Ok(Condition::Venue(value.to_string())),
+            "min_roi" => Ok(Condition::MinRoi(parse_f64(name, value)?)),
+            "max_roi" => Ok(Condition::MaxRoi(parse_f64(name, value)?)),
+            "min_profit" => Ok(Condition::MinProfit(parse_f64(name, value)?)),
+            "severity" => Ok(Condition::MinSeverity(value.parse()?)),
+            other => Err(anyhow!("unknown condition: {}", other)),
+        }
+    }
+}
+
+impl FromStr for RouteRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (conditions, target) = s.split_once("->").ok_or_else(|| anyhow!("expected <conditions> -> <sinks>, got {:?}", s))?;
+        let conditions = match conditions.trim() {
+            "" | "*" => Vec::new(),
+            list => list.split('&').map(|c| c.parse()).collect::<Result<_>>()?,
+        };
+
+        let (sinks, severity) = match target.rsplit_once(':') {
+            Some((sinks, severity)) => (sinks, Some(severity.trim().parse()?)),
+            None => (target, None),
+        };
+        let sinks: Vec<String> =
+            sinks.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect();
+        if sinks.is_empty() {
+            return Err(anyhow!("rule {:?} names no sinks", s));
+        }
+        Ok(RouteRule { conditions, sinks, severity })
+    }
+}
+
+impl Condition {
+    fn matches(&self, event: &Event) -> bool {
+        let opp = event.opportunity.as_ref();
+        match self {
+            Condition::Kind(kind) => glob_match(kind, event.class.as_str()),
+            Condition::Pair(pair) => event.pair.as_deref().is_some_and(|p| glob_match(pair, p)),
+            Condition::Venue(venue) => {
+                event.venue.as_deref().is_some_and(|v| glob_match(venue, v))
+                    || opp.is_some_and(|o| glob_match(venue, &o.buy_exchange) || glob_match(venue, &o.sell_exchange))
+            }
+            Condition::MinSeverity(min) => event.severity >= *min,
+            // Opportunity-only conditions never match other events
+            Condition::Route(buy, sell) => opp.is_some_and(|o| glob_match(buy, &o.buy_exchange) && glob_match(sell, &o.sell_exchange)),
+            Condition::MinRoi(min) => opp.is_some_and(|o| o.roi_percentage >= *min),
+            Condition::MaxRoi(max) => opp.is_some_and(|o| o.roi_percentage <= *max),
+            Condition::MinProfit(min) => opp.is_some_and(|o| o.net_profit >= *min),
+        }
+    }
+}
+
+impl RouteRule {
+    pub fn matches(&self, event: &Event) -> bool {
+        self.conditions.iter().all(|c| c.matches(event))
+    }
+
+    pub fn sinks(&self) -> &[String] {
+        &self.sinks
+    }
+
+    pub fn severity(&self) -> Option<Severity> {
+        self.severity
+    }
+}
+
+/// Parse a full ALERT_ROUTES value
+pub fn parse_rules(raw: &str) -> Result<Vec<RouteRule>> {
+    raw.split(';').map(str::trim).filter(|r| !r.is_empty()).map(|r| r.parse()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventClass;
+
+    #[test]
+    fn parses_rules() {
+        let rules = parse_rules("kind=opportunity_detected&route=binance>uniswap-*&min_roi=0.5 -> email,webhook:critical; * -> webhook").unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(
+            rules[0].conditions,
+            vec![
+                Condition::Kind("opportunity_detected".to_string()),
+                Condition::Route("binance".to_string(), "uniswap-*".to_string()),
+                Condition::MinRoi(0.5)
+            ]
+        );
+        assert_eq!(rules[0].sinks, vec!["email", "webhook"]);
+        assert_eq!(rules[0].severity, Some(Severity::Critical));
+        assert!(rules[1].conditions.is_empty() && rules[1].severity.is_none());
+
+        assert!(parse_rules("bogus=1 -> email").is_err());
+        assert!(parse_rules("kind=opportunity_detected -> :critical").is_err());
+        assert!(parse_rules("kind=opportunity_detected").is_err());
+    }
+
+    #[test]
+    fn conditions_match_event_fields() {
+        let rule: RouteRule = "kind=venue_*&venue=uniswap-*&severity=warning -> email".parse().unwrap();
+        let stale = |venue: &str, severity| Event::new(EventClass::VenueStale, severity, String::new()).with_venue(venue);
+
+        assert!(rule.matches(&stale("uniswap-v3-exact", Severity::Warning)));
+        assert!(!rule.matches(&stale("binance", Severity::Warning)));
+        assert!(!rule.matches(&stale("uniswap-v3-exact", Severity::Info)));
+
+        // Opportunity conditions never match events without an opportunity
+        let rule: RouteRule = "min_profit=0 -> email".parse().unwrap();
+        assert!(!rule.matches(&stale("binance", Severity::Critical)));
+    }
+}
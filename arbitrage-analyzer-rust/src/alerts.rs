@@ -0,0 +1,268 @@
+// Alert delivery. Sinks receive the events published on the bus (see `events.rs`).
+// The log sink is always on, the webhook sink posts JSON when ALERT_WEBHOOK_URL is set,
+// and the email sink (see `email.rs`) is enabled by SMTP_HOST.
+//
+// Webhook bodies default to the event as JSON. Integrations that expect their own
+// shape (PagerDuty, Google Chat, a custom executor) get one from a template: one
+// for events that carry an opportunity, one for the rest, in the `{name}` syntax
+// of `template.rs`. With a JSON content type, values are escaped for use inside
+// JSON strings, and templates must render valid JSON or the sink is not started.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::Serialize;
+
+use crate::email::EmailAlertSink;
+use crate::events::{Event, EventClass};
+use crate::{config, template};
+
+// The log sink receives every event it subscribes to, whatever the routing rules say
+pub const LOG_SINK: &str = "log";
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_WEBHOOK_CONTENT_TYPE: &str = "application/json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "INFO"),
+            Severity::Warning => write!(f, "WARNING"),
+            Severity::Critical => write!(f, "CRITICAL"),
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "critical" => Ok(Severity::Critical),
+            other => Err(anyhow!("unknown severity: {}", other)),
+        }
+    }
+}
+
+pub trait AlertSink: fmt::Debug + Send {
+    fn name(&self) -> &str;
+    fn send(&self, event: &Event) -> Result<()>;
+
+    /// Event classes received when <SINK>_EVENTS is unset
+    fn default_events(&self) -> HashSet<EventClass> {
+        EventClass::ALERTS.into_iter().collect()
+    }
+
+    /// Called on every housekeeping pass, for sinks that batch (e.g. digests)
+    fn flush(&self, _now: DateTime<Utc>) {}
+}
+
+#[derive(Debug)]
+pub struct LogAlertSink;
+
+impl AlertSink for LogAlertSink {
+    fn name(&self) -> &str {
+        LOG_SINK
+    }
+
+    fn send(&self, event: &Event) -> Result<()> {
+        match event.severity {
+            Severity::Info => info!("[ALERT {}] {}: {}", event.severity, event.class, event.message),
+            Severity::Warning => warn!("[ALERT {}] {}: {}", event.severity, event.class, event.message),
+            Severity::Critical => error!("[ALERT {}] {}: {}", event.severity, event.class, event.message),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct WebhookAlertSink {
+    url: String,
+    content_type: String,
+    // Bodies of events with an opportunity, and of the others; None posts the event as JSON
+    opportunity_template: Option<String>,
+    event_template: Option<String>,
+}
+
+// `<NAME>` inline, or the contents of the file at `<NAME>_FILE`
+fn webhook_template(name: &str) -> Result<Option<String>> {
+    if let Ok(path) = config::env_var(format!("{}_FILE", name)) {
+        return std::fs::read_to_string(path.trim()).map(Some).map_err(|e| anyhow!("{}_FILE {}: {}", name, path.trim(), e));
+    }
+    Ok(Some(template::from_env(name, "")).filter(|raw| !raw.trim().is_empty()))
+}
+
+// The placeholders of an event, plus `{event}`: the whole event as JSON
+fn event_vars(event: &Event) -> Vec<(&'static str, String)> {
+    vec![
+        ("kind", event.class.to_string()),
+        ("severity", event.severity.to_string().to_lowercase()),
+        ("message", event.message.clone()),
+        ("venue", event.venue.clone().unwrap_or_default()),
+        ("pair", event.pair.clone().unwrap_or_default()),
+        ("raised_at", event.raised_at.to_rfc3339()),
+        ("event", serde_json::to_string(event).unwrap_or_default()),
+    ]
+}
+
+// A value as it goes inside a JSON string, without the quotes
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::Value::String(value.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+impl WebhookAlertSink {
+    pub fn from_env(url: String) -> Result<Self> {
+        let sink = WebhookAlertSink {
+            url,
+            content_type: config::env_or("WEBHOOK_CONTENT_TYPE", DEFAULT_WEBHOOK_CONTENT_TYPE.to_string()),
+            opportunity_template: webhook_template("WEBHOOK_OPPORTUNITY_TEMPLATE")?,
+            event_template: webhook_template("WEBHOOK_EVENT_TEMPLATE")?,
+        };
+        sink.validate()?;
+        Ok(sink)
+    }
+
+    fn is_json(&self) -> bool {
+        self.content_type.to_lowercase().contains("json")
+    }
+
+    // Render both templates with placeholder values, so a broken JSON body fails startup instead of every post
+    fn validate(&self) -> Result<()> {
+        if !self.is_json() {
+            return Ok(());
+        }
+        for (name, template) in [("WEBHOOK_OPPORTUNITY_TEMPLATE", &self.opportunity_template), ("WEBHOOK_EVENT_TEMPLATE", &self.event_template)] {
+            let Some(template) = template else { continue };
+            // Zero is valid both as a number and inside a string; `{event}` is always an object
+            let vars: Vec<(&str, String)> = [
+                "id", "pair", "buy_exchange", "sell_exchange", "buy_price", "sell_price", "max_size",
+                "net_profit", "estimated_fees", "roi_percentage", "timestamp", "kind", "severity", "message", "venue", "raised_at",
+            ]
+            .into_iter()
+            .map(|var| (var, "0".to_string()))
+            .chain([("event", "{}".to_string())])
+            .collect();
+            serde_json::from_str::<serde_json::Value>(&template::render(template, &vars))
+                .map_err(|e| anyhow!("{} does not render valid JSON: {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    /// The body posted for `event`
+    fn body(&self, event: &Event) -> Result<String> {
+        let (template, mut vars) = match (&event.opportunity, &self.opportunity_template, &self.event_template) {
+            (Some(opp), Some(template), _) => (template, template::opportunity_vars(opp).into_iter().chain(event_vars(event)).collect()),
+            (None, _, Some(template)) => (template, event_vars(event)),
+            _ => return Ok(serde_json::to_string(event)?),
+        };
+        if self.is_json() {
+            for (_, value) in vars.iter_mut().filter(|(name, _)| *name != "event") {
+                *value = json_escape(value);
+            }
+        }
+        Ok(template::render(template, &vars))
+    }
+}
+
+impl AlertSink for WebhookAlertSink {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn send(&self, event: &Event) -> Result<()> {
+        ureq::post(&self.url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .set("Content-Type", &self.content_type)
+            .send_string(&self.body(event)?)
+            .map_err(|e| anyhow!("webhook {} failed: {}", self.url, e))?;
+        Ok(())
+    }
+}
+
+/// Build the sinks configured in the environment
+pub fn sinks_from_env() -> Vec<Box<dyn AlertSink>> {
+    let mut sinks: Vec<Box<dyn AlertSink>> = vec![Box::new(LogAlertSink)];
+    if let Ok(url) = crate::config::env_var("ALERT_WEBHOOK_URL") {
+        if !url.trim().is_empty() {
+            match WebhookAlertSink::from_env(url.trim().to_string()) {
+                Ok(sink) => sinks.push(Box::new(sink)),
+                Err(e) => error!("Webhook alerts disabled: {}", e),
+            }
+        }
+    }
+    match EmailAlertSink::from_env() {
+        Ok(Some(sink)) => sinks.push(Box::new(sink)),
+        Ok(None) => {}
+        Err(e) => error!("Email alerts disabled: {}", e),
+    }
+    sinks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpreadAnalyzer;
+
+    fn sink() -> WebhookAlertSink {
+        WebhookAlertSink {
+            url: "http://localhost/hook".to_string(),
+            content_type: DEFAULT_WEBHOOK_CONTENT_TYPE.to_string(),
+            opportunity_template: Some(r#"{"text": "{pair} {buy_exchange}→{sell_exchange}: ${net_profit}", "roi": {roi_percentage}}"#.to_string()),
+            event_template: None,
+        }
+    }
+
+    fn stale() -> Event {
+        Event::new(EventClass::VenueStale, Severity::Warning, "okx said \"bye\"".to_string()).with_venue("okx")
+    }
+
+    #[test]
+    fn opportunity_bodies_follow_the_template() {
+        let analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let opp = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        let detected = Event::opportunity_detected(&opp);
+        let body: serde_json::Value = serde_json::from_str(&sink().body(&detected).unwrap()).unwrap();
+        assert_eq!(body["text"], format!("BTC/USDT binance→okx: ${:.2}", opp.net_profit));
+        assert!((body["roi"].as_f64().unwrap() - opp.roi_percentage).abs() < 1e-3);
+    }
+
+    #[test]
+    fn events_without_a_template_keep_the_default_body() {
+        let stale = stale();
+        assert_eq!(sink().body(&stale).unwrap(), serde_json::to_string(&stale).unwrap());
+    }
+
+    #[test]
+    fn values_are_escaped_inside_json_strings() {
+        let mut sink = sink();
+        sink.event_template = Some(r#"{"summary": "{message}", "severity": "{severity}", "source": "{venue}", "event": {event}}"#.to_string());
+        sink.validate().unwrap();
+        let body: serde_json::Value = serde_json::from_str(&sink.body(&stale()).unwrap()).unwrap();
+        assert_eq!((body["summary"].as_str(), body["severity"].as_str(), body["event"]["venue"].as_str()), (Some("okx said \"bye\""), Some("warning"), Some("okx")));
+    }
+
+    #[test]
+    fn json_templates_must_render_valid_json() {
+        let mut sink = sink();
+        sink.event_template = Some(r#"{"summary": {message}"#.to_string());
+        assert!(sink.validate().is_err());
+        sink.content_type = "text/plain".to_string();
+        assert!(sink.validate().is_ok());
+    }
+}
@@ -0,0 +1,177 @@
+// Suggested pre-funding per venue and asset, for the rebalancer. With
+// ALLOCATION_TOTAL_CAPITAL (USD) set, routes are funded greedily in order of
+// annualized yield (see `route_yield`): each gets inventory for one average
+// trade on both legs, quote on the buy venue and base on the sell venue, until
+// the capital runs out. The last route funded may only get part of what it
+// needs. Routes without a positive yield or a USD trade size get nothing.
+//
+// The plan is recomputed with the break-even report, logged, served on
+// `GET /reports/allocation` and written as JSON to ALLOCATION_PLAN_KEY.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::Serialize;
+
+use crate::config;
+use crate::lifecycle::RouteKey;
+use crate::route_yield::YieldEstimate;
+use crate::SpreadAnalyzer;
+
+pub const DEFAULT_PLAN_KEY: &str = "analyzer:allocation_plan";
+
+#[derive(Debug, Clone)]
+pub struct AllocationConfig {
+    // USD; 0 disables the optimizer
+    pub total_capital: f64,
+    pub plan_key: String,
+}
+
+impl AllocationConfig {
+    pub fn from_env() -> Self {
+        AllocationConfig {
+            total_capital: config::env_or("ALLOCATION_TOTAL_CAPITAL", 0.0),
+            plan_key: config::env_var("ALLOCATION_PLAN_KEY").unwrap_or_else(|_| DEFAULT_PLAN_KEY.to_string()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.total_capital > 0.0
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteAllocation {
+    pub route: RouteKey,
+    pub annualized_yield_pct: f64,
+    // Both legs together
+    pub capital_usd: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VenueAllocation {
+    pub venue: String,
+    pub asset: String,
+    pub amount_usd: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AllocationPlan {
+    pub computed_at: DateTime<Utc>,
+    pub total_capital: f64,
+    pub allocated: f64,
+    // Best yield first
+    pub routes: Vec<RouteAllocation>,
+    // Summed over routes, by venue then asset
+    pub venues: Vec<VenueAllocation>,
+}
+
+/// Split `total_capital` over `routes`, best annualized yield first
+pub fn plan(total_capital: f64, routes: &[(RouteKey, YieldEstimate)], now: DateTime<Utc>) -> AllocationPlan {
+    let mut candidates: Vec<(&RouteKey, f64, f64)> = routes
+        .iter()
+        .filter_map(|(route, estimate)| {
+            let trade_usd = estimate.avg_trade_notional_usd.filter(|usd| usd.is_finite() && *usd > 0.0)?;
+            (estimate.annualized_yield_pct.is_finite() && estimate.annualized_yield_pct > 0.0)
+                .then_some((route, estimate.annualized_yield_pct, trade_usd))
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    let mut remaining = total_capital.max(0.0);
+    let mut allocations = Vec::new();
+    let mut by_venue: BTreeMap<(String, String), f64> = BTreeMap::new();
+    for (route, annualized_yield_pct, trade_usd) in candidates {
+        if remaining <= 0.0 {
+            break;
+        }
+        let capital_usd = (2.0 * trade_usd).min(remaining);
+        remaining -= capital_usd;
+
+        let mut assets = route.pair.split('/');
+        let (base, quote) = (assets.next().unwrap_or_default(), assets.next().unwrap_or_default());
+        for (venue, asset) in [(&route.buy_exchange, quote), (&route.sell_exchange, base)] {
+            *by_venue.entry((venue.clone(), asset.to_string())).or_default() += capital_usd / 2.0;
+        }
+        allocations.push(RouteAllocation { route: route.clone(), annualized_yield_pct, capital_usd });
+    }
+
+    AllocationPlan {
+        computed_at: now,
+        total_capital,
+        allocated: total_capital.max(0.0) - remaining,
+        routes: allocations,
+        venues: by_venue.into_iter().map(|((venue, asset), amount_usd)| VenueAllocation { venue, asset, amount_usd }).collect(),
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Recompute the allocation plan from the latest break-even report and hand it to the rebalancer
+    pub fn refresh_allocation_plan(&mut self) {
+        if !self.allocation.enabled() {
+            return;
+        }
+        let routes: Vec<(RouteKey, YieldEstimate)> = self
+            .break_even_reports
+            .iter()
+            .filter_map(|report| Some((report.route.clone(), report.yield_estimate.clone()?)))
+            .collect();
+        let plan = plan(self.allocation.total_capital, &routes, Utc::now());
+
+        info!(
+            "Allocation plan: ${:.0} of ${:.0} across {} routes",
+            plan.allocated,
+            plan.total_capital,
+            plan.routes.len()
+        );
+        for venue in &plan.venues {
+            info!("  Pre-fund {} {}: ${:.0}", venue.venue, venue.asset, venue.amount_usd);
+        }
+        self.publish_key(&self.allocation.plan_key, &plan, None);
+        self.allocation_plan = Some(plan);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate(annualized_yield_pct: f64, trade_usd: Option<f64>) -> YieldEstimate {
+        YieldEstimate {
+            episodes: 4,
+            observed_hours: 24.0,
+            triggers_per_day: 4.0,
+            avg_net_profit: 50.0,
+            avg_capital: 10_000.0,
+            avg_trade_notional_usd: trade_usd,
+            trades_per_year: 1_460.0,
+            annualized_yield_pct,
+        }
+    }
+
+    #[test]
+    fn funds_best_yielding_routes_first_until_capital_runs_out() {
+        let routes = [
+            (RouteKey::new("BTC/USDT", "binance", "okx"), estimate(20.0, Some(10_000.0))),
+            (RouteKey::new("ETH/USDT", "bybit", "okx"), estimate(80.0, Some(5_000.0))),
+            (RouteKey::new("BTC/USDT", "okx", "kraken"), estimate(40.0, Some(10_000.0))),
+            // No USD size, and a losing route
+            (RouteKey::new("ETH/BTC", "binance", "okx"), estimate(90.0, None)),
+            (RouteKey::new("SOL/USDT", "binance", "okx"), estimate(-5.0, Some(1_000.0))),
+        ];
+        let plan = plan(25_000.0, &routes, Utc::now());
+
+        let funded: Vec<_> = plan.routes.iter().map(|r| (r.route.pair.as_str(), r.route.buy_exchange.as_str(), r.capital_usd)).collect();
+        assert_eq!(funded, vec![("ETH/USDT", "bybit", 10_000.0), ("BTC/USDT", "okx", 15_000.0)]);
+        assert_eq!(plan.allocated, 25_000.0);
+
+        let venue = |venue: &str, asset: &str| plan.venues.iter().find(|v| v.venue == venue && v.asset == asset).map(|v| v.amount_usd);
+        assert_eq!(venue("bybit", "USDT"), Some(5_000.0));
+        assert_eq!(venue("okx", "ETH"), Some(5_000.0));
+        // The route that only got part of its capital is split the same way
+        assert_eq!(venue("okx", "USDT"), Some(7_500.0));
+        assert_eq!(venue("kraken", "BTC"), Some(7_500.0));
+        assert_eq!(plan.venues.len(), 4);
+    }
+}
@@ -0,0 +1,226 @@
+// Anomaly scoring of incoming books. Every validated book is passed, along with
+// the previous book from the same venue and pair, to each configured scorer, which
+// returns how likely the book is to be bad data, from 0 (looks normal) to 1. The
+// highest score is stored on the book:
+//  - a book scoring at least ANOMALY_REJECT_SCORE is rejected like a malformed one;
+//  - an opportunity carries the higher of its legs' scores as `anomaly_score`, and
+//    one at or over ANOMALY_FLAG_SCORE is recorded and published but gets no
+//    execution request.
+// Both thresholds default to off. Scorers are listed in ANOMALY_SCORERS; the
+// built-in `heuristic` one scores jumps of the mid price and wide spreads. A model
+// (ONNX through `tract`, say) or a rule set of your own implements `AnomalyScorer`
+// in `src/plugins.rs` and runs on every book when built with
+// `--features venue-plugins`.
+
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::info;
+
+use crate::alerts::Severity;
+use crate::events::{Event, EventClass};
+use crate::metrics::Metrics;
+use crate::{config, plugins, OrderBook, SpreadAnalyzer};
+
+const DEFAULT_JUMP_BPS: f64 = 500.0;
+const DEFAULT_SPREAD_BPS: f64 = 1_000.0;
+
+pub trait AnomalyScorer: fmt::Debug + Send + Sync {
+    /// Named in rejections and logs
+    fn name(&self) -> &str;
+    /// How likely `book` is to be bad data, in [0, 1]; `previous` is the last book
+    /// accepted for the same venue and pair. None abstains
+    fn score(&self, book: &OrderBook, previous: Option<&OrderBook>) -> Option<f64>;
+}
+
+// Scores grow linearly to 1 at a mid move of `jump_bps` since the previous book, or
+// a spread of `spread_bps` between the best bid and ask
+#[derive(Debug)]
+pub struct Heuristic {
+    pub jump_bps: f64,
+    pub spread_bps: f64,
+}
+
+impl Heuristic {
+    pub fn from_env() -> Self {
+        Heuristic {
+            jump_bps: config::env_or("ANOMALY_JUMP_BPS", DEFAULT_JUMP_BPS),
+            spread_bps: config::env_or("ANOMALY_SPREAD_BPS", DEFAULT_SPREAD_BPS),
+        }
+    }
+}
+
+fn mid(book: &OrderBook) -> Option<(f64, f64)> {
+    let ((bid, _), (ask, _)) = (book.best_bid()?, book.best_ask()?);
+    let mid = (bid + ask) / 2.0;
+    (mid > 0.0).then_some((mid, (ask - bid) / mid * 10_000.0))
+}
+
+impl AnomalyScorer for Heuristic {
+    fn name(&self) -> &str {
+        "heuristic"
+    }
+
+    fn score(&self, book: &OrderBook, previous: Option<&OrderBook>) -> Option<f64> {
+        let (mid_now, spread_bps) = mid(book)?;
+        let jump_bps = previous.and_then(mid).map_or(0.0, |(mid_before, _)| (mid_now / mid_before - 1.0).abs() * 10_000.0);
+        Some((jump_bps / self.jump_bps).max(spread_bps / self.spread_bps).clamp(0.0, 1.0))
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct AnomalyScoring {
+    scorers: Vec<Arc<dyn AnomalyScorer>>,
+    // None disables each use of the score
+    pub reject_score: Option<f64>,
+    pub flag_score: Option<f64>,
+}
+
+impl fmt::Debug for AnomalyScoring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.scorers.iter().map(|scorer| scorer.name())).finish()
+    }
+}
+
+fn threshold(var: &str) -> Option<f64> {
+    Some(config::env_or(var, 0.0)).filter(|score| *score > 0.0)
+}
+
+impl AnomalyScoring {
+    pub fn new(scorers: Vec<Arc<dyn AnomalyScorer>>, reject_score: Option<f64>, flag_score: Option<f64>) -> Self {
+        AnomalyScoring { scorers, reject_score, flag_score }
+    }
+
+    /// The scorers named in ANOMALY_SCORERS, then those this build was compiled with
+    pub fn from_env() -> Result<Self> {
+        let mut scorers: Vec<Arc<dyn AnomalyScorer>> = Vec::new();
+        for name in config::env_var("ANOMALY_SCORERS").unwrap_or_default().split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name {
+                "heuristic" => scorers.push(Arc::new(Heuristic::from_env())),
+                other => return Err(anyhow!("unknown anomaly scorer {:?}; expected heuristic", other)),
+            }
+        }
+        scorers.extend(plugins::anomaly_scorers());
+        Ok(Self::new(scorers, threshold("ANOMALY_REJECT_SCORE"), threshold("ANOMALY_FLAG_SCORE")))
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.scorers.is_empty()
+    }
+
+    /// The highest score any scorer gives `book`, and the scorer that gave it
+    pub fn score(&self, book: &OrderBook, previous: Option<&OrderBook>) -> Option<(f64, &str)> {
+        self.scorers
+            .iter()
+            .filter_map(|scorer| scorer.score(book, previous).filter(|score| score.is_finite()).map(|score| (score.clamp(0.0, 1.0), scorer.name())))
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    pub fn rejects(&self, score: f64) -> bool {
+        self.reject_score.is_some_and(|min| score >= min)
+    }
+
+    pub fn flags(&self, score: f64) -> bool {
+        self.flag_score.is_some_and(|min| score >= min)
+    }
+
+    pub fn log_config(&self) {
+        if self.enabled() {
+            let bound = |score: Option<f64>| score.map_or("off".to_string(), |score| format!("{:.2}", score));
+            info!(
+                "   - Anomaly scorers: {:?}, reject at {}, no execution at {}",
+                self,
+                bound(self.reject_score),
+                bound(self.flag_score)
+            );
+        }
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Score `book` against the one it replaces; false when the score rejects it
+    pub(crate) fn score_anomaly(&mut self, book: &mut OrderBook, now: DateTime<Utc>) -> bool {
+        if !self.anomaly.enabled() {
+            return true;
+        }
+        let key = format!("{}:{}", book.exchange, book.pair);
+        let Some((score, scorer)) = self.anomaly.score(book, self.books.get(&key)) else { return true };
+        book.anomaly_score = Some(score);
+        if !self.anomaly.rejects(score) {
+            return true;
+        }
+        let reason = format!("anomaly score {:.2} from {}", score, scorer);
+        Metrics::inc(&self.metrics.anomalous_books_rejected);
+        self.ingest_stats.record_rejected(&book.exchange);
+        self.report_rejection(&key, &book.exchange, &reason, now);
+        self.publish(Event::new(EventClass::BookRejected, Severity::Info, format!("Rejected {}: {}", key, reason)).with_venue(&book.exchange));
+        false
+    }
+}
+
+/// The higher of two legs' scores
+pub fn combined(buy: Option<f64>, sell: Option<f64>) -> Option<f64> {
+    match (buy, sell) {
+        (Some(buy), Some(sell)) => Some(buy.max(sell)),
+        (buy, sell) => buy.or(sell),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(bid: f64, ask: f64) -> OrderBook {
+        serde_json::from_value(serde_json::json!({
+            "exchange": "binance", "pair": "BTC/USDT", "bids": [[bid, 1.0]], "asks": [[ask, 1.0]], "timestamp": 0
+        }))
+        .unwrap()
+    }
+
+    #[derive(Debug)]
+    struct Fixed(f64);
+
+    impl AnomalyScorer for Fixed {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn score(&self, _book: &OrderBook, _previous: Option<&OrderBook>) -> Option<f64> {
+            Some(self.0)
+        }
+    }
+
+    fn heuristic() -> Heuristic {
+        Heuristic { jump_bps: 500.0, spread_bps: 1_000.0 }
+    }
+
+    #[test]
+    fn scores_jumps_and_wide_spreads() {
+        let heuristic = heuristic();
+        let before = book(99.9, 100.1);
+        assert!(heuristic.score(&before, None).unwrap() < 0.05);
+        // A 2.5% jump is half way to the bar
+        assert!((heuristic.score(&book(102.4, 102.6), Some(&before)).unwrap() - 0.5).abs() < 1e-9);
+        assert_eq!(heuristic.score(&book(80.0, 120.0), Some(&before)), Some(1.0));
+        assert_eq!(heuristic.score(&book(0.0, 0.0), None), None);
+    }
+
+    #[test]
+    fn keeps_the_highest_score() {
+        let before = book(99.9, 100.1);
+        let scoring = AnomalyScoring::new(vec![Arc::new(heuristic()), Arc::new(Fixed(0.7)), Arc::new(Fixed(f64::NAN))], Some(0.9), Some(0.6));
+        assert_eq!(scoring.score(&before, None), Some((0.7, "fixed")));
+        assert_eq!(scoring.score(&book(80.0, 120.0), Some(&before)), Some((1.0, "heuristic")));
+        assert_eq!(combined(Some(0.2), Some(0.4)), Some(0.4));
+        assert_eq!(combined(None, Some(0.4)), Some(0.4));
+    }
+
+    #[test]
+    fn flags_and_rejects_by_threshold() {
+        let scoring = AnomalyScoring::new(vec![Arc::new(heuristic())], Some(0.9), Some(0.6));
+        assert!(scoring.flags(0.7) && !scoring.rejects(0.7) && scoring.rejects(1.0));
+        assert!(!AnomalyScoring::default().enabled());
+    }
+}
@@ -0,0 +1,515 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde_json::json;
+use tiny_http::{Header, Response, Server};
+
+use crate::events::EventQuery;
+use crate::history::{ExecutionOutcome, OpportunityFilter};
+use crate::lifecycle::RequestState;
+use crate::market_history::{self, MarketHistoryFilter};
+use crate::seasonality::SeasonalityReport;
+use crate::stage_timing;
+use crate::staleness;
+use crate::SpreadAnalyzer;
+
+// How long the HTTP thread waits for the analyzer loop to answer a request
+const API_REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_HEALTHZ_MAX_BOOK_AGE_MS: i64 = 60_000;
+
+/// A request received by the HTTP thread, handed over to the analyzer loop.
+/// The analyzer owns all state, so every request is answered from inside `run()`.
+pub struct ApiRequest {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    // Token from an `Authorization: Bearer <token>` header, if any
+    pub bearer_token: Option<String>,
+    reply: Sender<ApiResponse>,
+}
+
+pub struct ApiResponse {
+    pub status: u16,
+    body: String,
+    content_type: &'static str,
+}
+
+impl ApiResponse {
+    pub fn ok(body: serde_json::Value) -> Self {
+        ApiResponse { status: 200, body: body.to_string(), content_type: "application/json" }
+    }
+
+    pub fn error(status: u16, message: impl Into<String>) -> Self {
+        ApiResponse {
+            status,
+            body: json!({ "error": message.into() }).to_string(),
+            content_type: "application/json",
+        }
+    }
+
+    pub fn json(status: u16, body: serde_json::Value) -> Self {
+        ApiResponse { status, body: body.to_string(), content_type: "application/json" }
+    }
+
+    // Prometheus exposition format
+    pub fn metrics(body: String) -> Self {
+        ApiResponse { status: 200, body, content_type: "text/plain; version=0.0.4" }
+    }
+
+    pub fn csv(body: String) -> Self {
+        ApiResponse { status: 200, body, content_type: "text/csv" }
+    }
+}
+
+impl ApiRequest {
+    pub fn respond(self, response: ApiResponse) {
+        // The HTTP thread may have timed out already; nothing to do then
+        let _ = self.reply.send(response);
+    }
+}
+
+/// Start the HTTP API on `addr` in a background thread.
+/// Returns the receiving end the analyzer loop polls for pending requests.
+pub fn spawn(addr: &str) -> Result<Receiver<ApiRequest>> {
+    let server = Server::http(addr).map_err(|e| anyhow!("Failed to bind API on {}: {}", addr, e))?;
+    let (tx, rx) = mpsc::channel::<ApiRequest>();
+
+    info!("  API listening on http://{}", addr);
+
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let (path, query) = split_url(request.url());
+            let bearer_token = request
+                .headers()
+                .iter()
+                .find(|h| h.field.equiv("Authorization"))
+                .and_then(|h| h.value.as_str().strip_prefix("Bearer ").map(|t| t.trim().to_string()));
+
+            let (reply_tx, reply_rx) = mpsc::channel();
+            let api_request = ApiRequest {
+                method: request.method().as_str().to_uppercase(),
+                path,
+                query,
+                bearer_token,
+                reply: reply_tx,
+            };
+
+            let response = if tx.send(api_request).is_err() {
+                // Analyzer loop is gone, nothing can answer anymore
+                ApiResponse::error(503, "analyzer is not running")
+            } else {
+                reply_rx
+                    .recv_timeout(API_REPLY_TIMEOUT)
+                    .unwrap_or_else(|_| ApiResponse::error(504, "analyzer did not answer in time"))
+            };
+
+            let content_type = Header::from_bytes("Content-Type", response.content_type).expect("static header");
+            let http_response = Response::from_string(response.body)
+                .with_status_code(response.status)
+                .with_header(content_type);
+            if let Err(e) = request.respond(http_response) {
+                error!("Failed to write API response: {}", e);
+            }
+        }
+    });
+
+    Ok(rx)
+}
+
+fn split_url(url: &str) -> (String, HashMap<String, String>) {
+    let (path, query_string) = match url.split_once('?') {
+        Some((p, q)) => (p, q),
+        None => (url, ""),
+    };
+
+    let query = query_string
+        .split('&')
+        .filter(|kv| !kv.is_empty())
+        .map(|kv| match kv.split_once('=') {
+            Some((k, v)) => (percent_decode(k), percent_decode(v)),
+            None => (percent_decode(kv), String::new()),
+        })
+        .collect();
+
+    (path.trim_end_matches('/').to_string(), query)
+}
+
+/// Escape a query value for the CLI's requests to the API
+pub fn percent_encode(raw: &str) -> String {
+    raw.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Decode `%XX` escapes and `+` in a query component; malformed escapes are kept as-is
+fn percent_decode(raw: &str) -> String {
+    let bytes = raw.as_bytes();
+    let mut out: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Liveness for probes: 503 unless a Redis source is subscribed and the newest book is fresh
+pub fn healthz(analyzer: &SpreadAnalyzer, now: DateTime<Utc>) -> ApiResponse {
+    let connected = analyzer.metrics.redis_sources_connected.load(Ordering::Relaxed);
+    let newest_book_age_ms = analyzer.books.values().filter_map(|book| staleness::data_age_ms(book, now)).min();
+    let mut problems = Vec::new();
+    if connected == 0 {
+        problems.push("no Redis source connected".to_string());
+    }
+    match newest_book_age_ms {
+        None => problems.push("no books received".to_string()),
+        Some(age) if age > analyzer.healthz_max_book_age_ms => {
+            problems.push(format!("newest book is {}ms old (max {}ms)", age, analyzer.healthz_max_book_age_ms))
+        }
+        Some(_) => {}
+    }
+    ApiResponse::json(
+        if problems.is_empty() { 200 } else { 503 },
+        json!({
+            "status": if problems.is_empty() { "ok" } else { "unhealthy" },
+            "redis_sources_connected": connected,
+            "redis_sources": analyzer.metrics.redis_sources_configured.load(Ordering::Relaxed),
+            "newest_book_age_ms": newest_book_age_ms,
+            "max_book_age_ms": analyzer.healthz_max_book_age_ms,
+            "problems": problems,
+        }),
+    )
+}
+
+/// Route a request against the analyzer state
+pub fn handle(analyzer: &mut SpreadAnalyzer, request: &ApiRequest) -> ApiResponse {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/health") => ApiResponse::ok(json!({
+            "status": "ok",
+            "mode": analyzer.mode.to_string(),
+            "books": analyzer.books.len(),
+            "kill_switch_tripped": analyzer.kill_switch.is_tripped(),
+            "counters": analyzer.counters,
+            "checkpoint": analyzer.checkpoint.status(),
+            "idle": analyzer.idle.status(),
+        })),
+        ("GET", "/healthz") => healthz(analyzer, Utc::now()),
+        ("GET", "/books") => match serde_json::to_value(analyzer.dump_books()) {
+            Ok(body) => ApiResponse::ok(body),
+            Err(e) => ApiResponse::error(500, e.to_string()),
+        },
+        ("POST", "/books/dump") => {
+            // Always a fresh name in the working directory: the API is unauthenticated
+            let path = crate::dump::default_dump_path();
+            match analyzer.dump_books_to_file(&path) {
+                Ok(count) => ApiResponse::ok(json!({ "path": path, "books": count })),
+                Err(e) => ApiResponse::error(500, e.to_string()),
+            }
+        }
+        ("GET", "/executions") => ApiResponse::ok(json!({
+            "in_flight": analyzer.lifecycle.in_flight(),
+            "recent": analyzer.lifecycle.recent().take(100).collect::<Vec<_>>(),
+            "unresolved_intents": analyzer.intents.open_intents().count(),
+            "unacked": analyzer.acks.unacked(&analyzer.lifecycle, Utc::now()).len(),
+        })),
+        ("GET", "/executions/unacked") => ApiResponse::ok(json!({
+            "timeout_ms": analyzer.acks.timeout.map(|timeout| timeout.num_milliseconds()),
+            "unacked": analyzer.acks.unacked(&analyzer.lifecycle, Utc::now()),
+        })),
+        ("GET", "/reports/cost-attribution") => {
+            let routes: Vec<_> = analyzer
+                .cost_attribution
+                .routes()
+                .into_iter()
+                .map(|(route, totals)| json!({ "route": route, "dominant": totals.dominant(), "totals": totals }))
+                .collect();
+            ApiResponse::ok(json!({
+                "routes": routes,
+                "recent": analyzer.cost_attribution.recent().take(100).collect::<Vec<_>>(),
+            }))
+        }
+        ("GET", "/reports/correlation") => {
+            let assets = match request.query.get("assets") {
+                Some(assets) => assets.split(',').map(|asset| asset.trim().to_uppercase()).filter(|asset| !asset.is_empty()).collect(),
+                None => analyzer.active_route_assets(),
+            };
+            ApiResponse::ok(json!(analyzer.correlation.matrix(assets)))
+        }
+        ("GET", "/reports/allocation") => match &analyzer.allocation_plan {
+            Some(plan) => ApiResponse::ok(json!(plan)),
+            None if analyzer.allocation.enabled() => ApiResponse::error(503, "no allocation plan computed yet"),
+            None => ApiResponse::error(404, "allocation disabled, set ALLOCATION_TOTAL_CAPITAL"),
+        },
+        ("GET", "/routes/break-even") => ApiResponse::ok(json!({
+            "refresh_secs": analyzer.break_even_refresh.as_secs(),
+            "routes": analyzer.break_even_reports,
+        })),
+        ("GET", "/routes/competition") => {
+            let routes: Vec<_> = analyzer
+                .competition
+                .all(Utc::now())
+                .into_iter()
+                .map(|(route, estimate)| json!({ "route": route, "competition": estimate }))
+                .collect();
+            ApiResponse::ok(json!({ "routes": routes }))
+        }
+        ("GET", "/routes/timing") => {
+            let routes: Vec<_> = analyzer
+                .competition
+                .all(Utc::now())
+                .into_iter()
+                .map(|(route, _)| json!({ "timing": analyzer.timing_advice(&route), "route": route }))
+                .collect();
+            ApiResponse::ok(json!({ "routes": routes }))
+        }
+        ("GET", "/routes/success") => {
+            let routes: Vec<_> = analyzer
+                .success_rates
+                .report()
+                .into_iter()
+                .map(|(route, estimate)| json!({ "route": route, "success": estimate }))
+                .collect();
+            ApiResponse::ok(json!({ "routes": routes }))
+        }
+        ("GET", "/routes/pruned") => ApiResponse::ok(json!({
+            "enabled": analyzer.route_pruning.enabled(),
+            "routes": analyzer.route_pruning.report(),
+        })),
+        ("GET", "/fees/withdrawal") => ApiResponse::ok(analyzer.withdrawal_fee_report()),
+        ("GET", "/shadow/fees") => match &analyzer.shadow_fees {
+            Some(shadow) => ApiResponse::ok(shadow.report()),
+            None => ApiResponse::error(404, "no shadow fee model, set SHADOW_FEES or SHADOW_ACCOUNT_PROFILE"),
+        },
+        ("GET", "/gas") => ApiResponse::ok(json!({
+            "chains": analyzer.gas.quotes(),
+            "oracle": analyzer.gas.oracle_reading(),
+            "regimes": analyzer.gas_history.report(),
+        })),
+        ("GET", "/venues/latency") => {
+            let venues: Vec<_> = analyzer
+                .latency
+                .board
+                .report(Utc::now())
+                .into_iter()
+                .map(|venue| json!({ "min_roi_percentage": analyzer.latency.required_roi(analyzer.thresholds.min_roi_percentage, venue.round_trip_ms), "latency": venue }))
+                .collect();
+            ApiResponse::ok(json!({ "venues": venues }))
+        }
+        ("GET", "/executor/schema") => ApiResponse::ok(analyzer.executor_schema.report()),
+        ("GET", "/balances") => ApiResponse::ok(json!({ "balances": analyzer.balances.report() })),
+        ("GET", "/pairs/pending") => ApiResponse::ok(analyzer.onboarding.report()),
+        ("GET", "/pairs/priority") => ApiResponse::ok(json!({ "pairs": analyzer.pair_priorities.report(Utc::now()) })),
+        ("GET", "/venues/canary") => ApiResponse::ok(analyzer.canary.report()),
+        ("GET", "/venues/lag") => ApiResponse::ok(json!({
+            "policy": analyzer.lag.policy.to_string(),
+            "pairs": analyzer.lag.report(),
+        })),
+        ("POST", "/competition/mempool") => {
+            let Some(venue) = request.query.get("venue") else {
+                return ApiResponse::error(400, "missing venue parameter");
+            };
+            let pending_swaps = match request.query.get("pending_swaps").map(|raw| raw.parse::<u32>()) {
+                Some(Ok(n)) => n,
+                Some(Err(_)) => return ApiResponse::error(400, "invalid pending_swaps"),
+                None => return ApiResponse::error(400, "missing pending_swaps parameter"),
+            };
+            analyzer.competition.observe_mempool(venue, pending_swaps, Utc::now());
+            ApiResponse::ok(json!({ "venue": venue, "pending_swaps": pending_swaps }))
+        }
+        ("GET", "/stats/exchanges") => ApiResponse::ok(json!({
+            "exchanges": analyzer.ingest_stats.summaries(Utc::now()),
+        })),
+        ("GET", "/stats/profit") => ApiResponse::ok(json!(analyzer.profit_rollup)),
+        ("GET", "/stats/pipeline") => ApiResponse::ok(json!({ "window": stage_timing::WINDOW, "stages": analyzer.metrics.stages.summaries() })),
+        ("GET", "/metrics") => {
+            let labels = analyzer.build_info.labels();
+            ApiResponse::metrics(analyzer.metrics.render(&labels) + &analyzer.profit_rollup.render_metrics(&labels))
+        }
+        ("GET", "/buildinfo") => ApiResponse::ok(json!(analyzer.build_info)),
+        ("GET", "/history/opportunities") => {
+            let filter = match OpportunityFilter::from_query(&request.query) {
+                Ok(filter) => filter,
+                Err(e) => return ApiResponse::error(400, e.to_string()),
+            };
+            match analyzer.history.query_opportunities(&filter) {
+                Ok(body) => ApiResponse::ok(body),
+                Err(e) => ApiResponse::error(503, e.to_string()),
+            }
+        }
+        ("GET", "/events") => match EventQuery::from_query(&request.query) {
+            Ok(query) => ApiResponse::ok(json!({ "events": analyzer.events.recent(&query) })),
+            Err(e) => ApiResponse::error(400, e.to_string()),
+        },
+        ("GET", "/history/seasonality") => {
+            let filter = match OpportunityFilter::from_query(&request.query) {
+                Ok(filter) => filter,
+                Err(e) => return ApiResponse::error(400, e.to_string()),
+            };
+            let report = match analyzer.history.seasonality(&filter) {
+                Ok(cells) => SeasonalityReport::from_cells(&cells),
+                Err(e) => return ApiResponse::error(503, e.to_string()),
+            };
+            match request.query.get("format").map(String::as_str) {
+                Some("csv") => ApiResponse::csv(report.to_csv()),
+                None | Some("json") => match serde_json::to_value(&report) {
+                    Ok(body) => ApiResponse::ok(body),
+                    Err(e) => ApiResponse::error(500, e.to_string()),
+                },
+                Some(other) => ApiResponse::error(400, format!("unknown format: {}", other)),
+            }
+        }
+        ("GET", "/history/market-summaries") => {
+            let filter = match MarketHistoryFilter::from_query(&request.query) {
+                Ok(filter) => filter,
+                Err(e) => return ApiResponse::error(400, e.to_string()),
+            };
+            match analyzer.history.market_summaries(&filter) {
+                Ok(summaries) => {
+                    let lost = market_history::lost_venues(&summaries);
+                    let next_offset = (summaries.len() as i64 == filter.limit).then_some(filter.offset + filter.limit);
+                    let summaries: Vec<serde_json::Value> = summaries
+                        .iter()
+                        .map(|summary| {
+                            let mut body = json!(summary);
+                            body["lost_venues"] = json!(lost.get(&summary.recorded_at).cloned().unwrap_or_default());
+                            body
+                        })
+                        .collect();
+                    ApiResponse::ok(json!({ "summaries": summaries, "next_offset": next_offset }))
+                }
+                Err(e) => ApiResponse::error(503, e.to_string()),
+            }
+        }
+        ("GET", "/control") => ApiResponse::ok(json!(analyzer.control_state())),
+        ("GET", "/kill-switch") => ApiResponse::ok(json!(analyzer.kill_switch.state())),
+        ("POST", "/kill-switch/trip") => {
+            let reason = request.query.get("reason").map(String::as_str).unwrap_or("tripped via API");
+            let actor = request.query.get("actor").cloned().unwrap_or_else(|| "api".to_string());
+            match analyzer.trip_kill_switch(reason, &actor) {
+                Ok(()) => ApiResponse::ok(json!(analyzer.kill_switch.state())),
+                Err(e) => ApiResponse::error(500, e.to_string()),
+            }
+        }
+        ("POST", "/kill-switch/reset") => {
+            let Some(token) = &request.bearer_token else {
+                return ApiResponse::error(401, "missing Authorization: Bearer <token> header");
+            };
+            let actor = request.query.get("actor").cloned().unwrap_or_else(|| "api".to_string());
+            match analyzer.reset_kill_switch(token, &actor) {
+                Ok(()) => ApiResponse::ok(json!(analyzer.kill_switch.state())),
+                Err(e) => ApiResponse::error(403, e.to_string()),
+            }
+        }
+        ("POST", path) if path.starts_with("/executions/") => {
+            let id = &path["/executions/".len()..];
+            let state = match request.query.get("state").map(|s| s.parse::<RequestState>()) {
+                Some(Ok(state)) => state,
+                Some(Err(e)) => return ApiResponse::error(400, e.to_string()),
+                None => return ApiResponse::error(400, "missing state parameter"),
+            };
+            let outcome = match ExecutionOutcome::from_query(&request.query) {
+                Ok(outcome) => outcome,
+                Err(e) => return ApiResponse::error(400, e.to_string()),
+            };
+            let now = Utc::now();
+            match analyzer.lifecycle.transition(id, state, now) {
+                Ok(()) => {
+                    analyzer.record_transition(id, state, now, outcome);
+                    ApiResponse::ok(json!({ "id": id, "state": state }))
+                }
+                Err(e) => ApiResponse::error(409, e.to_string()),
+            }
+        }
+        _ => ApiResponse::error(404, format!("no route for {} {}", request.method, request.path)),
+    }
+}
+
+/// Fetch a JSON document from a running analyzer's API (used by CLI subcommands)
+pub fn fetch(api_addr: &str, path: &str) -> Result<serde_json::Value> {
+    let url = format!("http://{}{}", api_addr, path);
+    let response = ureq::get(&url)
+        .timeout(API_REPLY_TIMEOUT * 2)
+        .call()
+        .map_err(|e| anyhow!("API request to {} failed: {}", url, e))?;
+    Ok(response.into_json()?)
+}
+
+/// POST to a running analyzer's API, optionally with a bearer token
+pub fn post(api_addr: &str, path: &str, bearer_token: Option<&str>) -> Result<serde_json::Value> {
+    let url = format!("http://{}{}", api_addr, path);
+    let mut request = ureq::post(&url).timeout(API_REPLY_TIMEOUT * 2);
+    if let Some(token) = bearer_token {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    match request.call() {
+        Ok(response) => Ok(response.into_json()?),
+        // Surface the API's own error message rather than just the status
+        Err(ureq::Error::Status(status, response)) => {
+            let body: serde_json::Value = response.into_json().unwrap_or_default();
+            Err(anyhow!("API request to {} failed ({}): {}", url, status, body["error"].as_str().unwrap_or("no details")))
+        }
+        Err(e) => Err(anyhow!("API request to {} failed: {}", url, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_values_are_percent_decoded() {
+        let (path, query) = split_url("/history/opportunities/?pair=BTC%2FUSDT&detail=partial+fill&bad=%zz");
+        assert_eq!(path, "/history/opportunities");
+        assert_eq!(query.get("pair").map(String::as_str), Some("BTC/USDT"));
+        assert_eq!(query.get("detail").map(String::as_str), Some("partial fill"));
+        assert_eq!(query.get("bad").map(String::as_str), Some("%zz"));
+    }
+
+    #[test]
+    fn percent_encoding_round_trips() {
+        let raw = "BTC/USDT 2024-01-01T00:00:00+02:00";
+        assert_eq!(percent_encode("BTC/USDT"), "BTC%2FUSDT");
+        assert_eq!(percent_decode(&percent_encode(raw)), raw);
+    }
+
+    #[test]
+    fn healthz_needs_a_connected_source_and_a_fresh_book() {
+        let now = Utc::now();
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        assert_eq!(healthz(&analyzer, now).status, 503);
+
+        analyzer.metrics.redis_sources_connected.store(1, Ordering::Relaxed);
+        let book: crate::OrderBook = serde_json::from_value(json!({
+            "exchange": "binance", "pair": "BTC/USDT", "bids": [[49990.0, 1.0]], "asks": [[50000.0, 1.0]],
+            "timestamp": now.timestamp_millis(),
+        }))
+        .unwrap();
+        analyzer.books.insert("orderbook:binance:BTC/USDT".to_string(), book);
+        assert_eq!(healthz(&analyzer, now).status, 200);
+        let stale = healthz(&analyzer, now + chrono::Duration::milliseconds(analyzer.healthz_max_book_age_ms + 1));
+        assert_eq!(stale.status, 503);
+        assert!(stale.body.contains("newest book is"));
+    }
+}
@@ -0,0 +1,151 @@
+// Opportunity archive in Redis, for consumers that look an opportunity up by id
+// after its pub/sub message is gone. Enabled with OPPORTUNITY_ARCHIVE=true. Every
+// recorded opportunity is kept in tiers that shrink as it ages:
+//  - `opportunity:<id>`: the full JSON, for ARCHIVE_FULL_TTL_SECS,
+//  - `opportunity:<id>:summary`: a hash of the fields needed to list and rank
+//    it, for ARCHIVE_SUMMARY_TTL_SECS,
+//  - `opportunities:archive`: the ids, scored by detection time in ms.
+// Every ARCHIVE_COMPACT_SECS the index is compacted: ids whose summary has
+// expired are removed, and at most ARCHIVE_MAX_ENTRIES of the newest are kept.
+// So Redis holds a bounded window. Older history lives in the long-term store:
+// Postgres (DATABASE_URL) and the Parquet export record every opportunity as it
+// is detected, and the archive warns at startup when neither is configured.
+
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::{config, ArbitrageOpportunity, SpreadAnalyzer};
+
+pub const INDEX_KEY: &str = "opportunities:archive";
+const DEFAULT_FULL_TTL_SECS: usize = 300;
+const DEFAULT_SUMMARY_TTL_SECS: usize = 86_400;
+const DEFAULT_MAX_ENTRIES: usize = 100_000;
+const DEFAULT_COMPACT_SECS: u64 = 60;
+
+pub fn full_key(id: &str) -> String {
+    format!("opportunity:{}", id)
+}
+
+pub fn summary_key(id: &str) -> String {
+    format!("opportunity:{}:summary", id)
+}
+
+/// The compact tier: what listing and ranking archived opportunities needs
+pub fn summary_fields(opp: &ArbitrageOpportunity) -> Vec<(String, String)> {
+    let mut fields = vec![
+        ("id", opp.id.clone()),
+        ("pair", opp.pair.clone()),
+        ("buy_exchange", opp.buy_exchange.clone()),
+        ("sell_exchange", opp.sell_exchange.clone()),
+        ("buy_price", opp.buy_price.to_string()),
+        ("sell_price", opp.sell_price.to_string()),
+        ("max_size", opp.max_size.to_string()),
+        ("net_profit", opp.net_profit.to_string()),
+        ("roi_percentage", opp.roi_percentage.to_string()),
+        ("timestamp", opp.timestamp.to_rfc3339()),
+    ];
+    if let Some(tenant) = &opp.tag.tenant {
+        fields.push(("tenant", tenant.clone()));
+    }
+    if let Some(strategy_id) = &opp.tag.strategy_id {
+        fields.push(("strategy_id", strategy_id.clone()));
+    }
+    fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect()
+}
+
+#[derive(Debug)]
+pub struct OpportunityArchive {
+    enabled: bool,
+    full_ttl_secs: usize,
+    summary_ttl_secs: usize,
+    max_entries: usize,
+    compact_interval: Duration,
+    last_compacted: Option<Instant>,
+}
+
+impl OpportunityArchive {
+    pub fn from_env() -> Self {
+        OpportunityArchive {
+            enabled: config::env_or("OPPORTUNITY_ARCHIVE", false),
+            full_ttl_secs: config::env_or("ARCHIVE_FULL_TTL_SECS", DEFAULT_FULL_TTL_SECS),
+            summary_ttl_secs: config::env_or("ARCHIVE_SUMMARY_TTL_SECS", DEFAULT_SUMMARY_TTL_SECS),
+            max_entries: config::env_or("ARCHIVE_MAX_ENTRIES", DEFAULT_MAX_ENTRIES),
+            compact_interval: Duration::from_secs(config::env_or("ARCHIVE_COMPACT_SECS", DEFAULT_COMPACT_SECS)),
+            last_compacted: None,
+        }
+    }
+
+    fn compaction_due(&mut self, now: Instant) -> bool {
+        if !self.enabled || self.last_compacted.is_some_and(|last| now.duration_since(last) < self.compact_interval) {
+            return false;
+        }
+        self.last_compacted = Some(now);
+        true
+    }
+}
+
+impl SpreadAnalyzer {
+    pub(crate) fn log_archive_config(&self) {
+        if !self.archive.enabled {
+            return;
+        }
+        info!(
+            "   - Opportunity archive: full payloads {}s, summaries {}s, at most {} indexed",
+            self.archive.full_ttl_secs, self.archive.summary_ttl_secs, self.archive.max_entries
+        );
+        if !self.history.enabled() && !self.exporter.enabled() {
+            warn!("Archived opportunities are gone after {}s: set DATABASE_URL or PARQUET_EXPORT_DIR to keep them", self.archive.summary_ttl_secs);
+        }
+    }
+
+    /// Write `opp` to every tier of the archive
+    pub(crate) fn archive_opportunity(&self, opp: &ArbitrageOpportunity) {
+        let (true, Some(publisher)) = (self.archive.enabled, &self.publisher) else { return };
+        self.publish_key(&full_key(&opp.id), opp, Some(self.archive.full_ttl_secs));
+        publisher.hash(&summary_key(&opp.id), summary_fields(opp), self.archive.summary_ttl_secs);
+        publisher.index(INDEX_KEY, &opp.id, opp.timestamp.timestamp_millis());
+    }
+
+    /// Drop index entries whose summaries have expired, and the oldest beyond ARCHIVE_MAX_ENTRIES
+    pub(crate) fn compact_archive_if_due(&mut self, now: Instant) {
+        if !self.archive.compaction_due(now) {
+            return;
+        }
+        let Some(publisher) = &self.publisher else { return };
+        let expired_before = chrono::Utc::now().timestamp_millis() - self.archive.summary_ttl_secs as i64 * 1000;
+        publisher.trim(INDEX_KEY, expired_before, self.archive.max_entries);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summaries_keep_the_listing_fields() {
+        let analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let mut opp = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        opp.tag.tenant = Some("desk-a".to_string());
+        let fields = summary_fields(&opp);
+        let field = |name: &str| fields.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+        assert_eq!(field("pair").as_deref(), Some("BTC/USDT"));
+        assert_eq!(field("net_profit"), Some(opp.net_profit.to_string()));
+        assert_eq!(field("tenant").as_deref(), Some("desk-a"));
+        assert_eq!(field("strategy_id"), None);
+        assert_eq!((full_key(&opp.id), summary_key("x")), (format!("opportunity:{}", opp.id), "opportunity:x:summary".to_string()));
+    }
+
+    #[test]
+    fn compaction_runs_at_most_once_per_interval() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.archive.enabled = true;
+        let start = Instant::now();
+        assert!(analyzer.archive.compaction_due(start));
+        assert!(!analyzer.archive.compaction_due(start + Duration::from_secs(1)));
+        assert!(analyzer.archive.compaction_due(start + analyzer.archive.compact_interval));
+        // Never when the archive is off
+        analyzer.archive.enabled = false;
+        assert!(!analyzer.archive.compaction_due(start + analyzer.archive.compact_interval * 3));
+    }
+}
@@ -0,0 +1,463 @@
+// Atomic DEX routes. A route between two Ethereum DEXes (uniswap-v3-exact,
+// sushiswap, balancer) need not be two transactions with leg risk in between:
+// both swaps go into one call to an executor contract, which makes them in order
+// and reverts both if either fails. With ATOMIC_EXECUTOR_ADDRESS set, every
+// execution request on such a route carries `atomic`, the plan:
+//  - `calldata` for the executor: a Multicall3-style `aggregate3` of the two swaps
+//    with no failure allowed. Uniswap V3 legs call SwapRouter `exactInputSingle`,
+//    SushiSwap legs the router's `swapExactTokensForTokens`, Balancer legs the
+//    Vault's `swap`; all pay out to the executor.
+//  - The buy swap spends size × buy price of the quote token for at least the
+//    size less ATOMIC_SLIPPAGE_BPS. The sell swap sells that minimum and must
+//    return at least the quote spent, so the bundle reverts rather than lose tokens.
+//  - The gas limit of one transaction making both swaps (one base cost, plus
+//    ATOMIC_OVERHEAD_GAS for the executor), its USD cost at the current gas
+//    price, and what it saves over two transactions.
+// Tokens are resolved to mainnet WETH, WBTC, USDC, USDT and DAI (ETH and BTC
+// pairs trade the wrapped tokens); EVM_TOKENS adds more as
+// `SYMBOL:address/decimals`. Uniswap V3 pools are picked by UNISWAP_V3_FEE_TIERS
+// (`pair:fee`, default 3000); a Balancer leg needs the pool in BALANCER_POOL_IDS
+// (`pair:poolId`). Routes that can't be resolved go out without a plan.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::maintenance::Chain;
+use crate::{config, ArbitrageOpportunity, SpreadAnalyzer};
+
+const UNISWAP_V3_ROUTER: &str = "0xE592427A0AEce92De3Edee1F18E0157C05861564";
+const SUSHISWAP_ROUTER: &str = "0xd9e1cE17f2641f24aE83637ab66a2cca9C378B9F";
+const BALANCER_VAULT: &str = "0xBA12222222228d8Ba445958a75a0704d566BF2C8";
+const MAINNET_TOKENS: [(&str, &str, u8); 7] = [
+    ("WETH", "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", 18),
+    ("ETH", "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", 18),
+    ("WBTC", "0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599", 8),
+    ("BTC", "0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599", 8),
+    ("USDC", "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", 6),
+    ("USDT", "0xdAC17F958D2ee523a2206206994597C13D831ec7", 6),
+    ("DAI", "0x6B175474E89094C44Da98b954EedeAC495271d0F", 18),
+];
+
+// Selectors (first 4 bytes of the keccak256 of the signature) of the calls a plan makes
+// aggregate3((address,bool,bytes)[])
+const AGGREGATE3: [u8; 4] = [0x82, 0xad, 0x56, 0xcb];
+// exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))
+const EXACT_INPUT_SINGLE: [u8; 4] = [0x41, 0x4b, 0xf3, 0x89];
+// swapExactTokensForTokens(uint256,uint256,address[],address,uint256)
+const SWAP_EXACT_TOKENS_FOR_TOKENS: [u8; 4] = [0x38, 0xed, 0x17, 0x39];
+// swap((bytes32,uint8,address,address,uint256,bytes),(address,bool,address,bool),uint256,uint256)
+const BALANCER_SWAP: [u8; 4] = [0x52, 0xbb, 0xbe, 0x29];
+
+const TX_BASE_GAS: u64 = 21_000;
+const DEFAULT_OVERHEAD_GAS: u64 = 10_000;
+const DEFAULT_SLIPPAGE_BPS: f64 = 30.0;
+const DEFAULT_DEADLINE_SECS: i64 = 60;
+const DEFAULT_UNISWAP_FEE_TIER: u32 = 3_000;
+
+fn parse_hex<const N: usize>(raw: &str) -> Result<[u8; N]> {
+    let digits = raw.trim().strip_prefix("0x").ok_or_else(|| anyhow!("{:?} is not 0x-prefixed hex", raw))?;
+    if !digits.is_ascii() || digits.len() != 2 * N {
+        bail!("{:?} is not {} bytes of hex", raw, N);
+    }
+    let mut out = [0u8; N];
+    for (idx, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&digits[2 * idx..2 * idx + 2], 16)?;
+    }
+    Ok(out)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+}
+
+// The contracts above are constants, checked by the tests
+fn contract(address: &str) -> [u8; 20] {
+    parse_hex(address).expect("contract addresses are valid")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Token {
+    address: [u8; 20],
+    decimals: u8,
+}
+
+impl FromStr for Token {
+    type Err = anyhow::Error;
+
+    /// `address/decimals`
+    fn from_str(raw: &str) -> Result<Self> {
+        let (address, decimals) = raw.split_once('/').ok_or_else(|| anyhow!("expected address/decimals, got {:?}", raw))?;
+        Ok(Token { address: parse_hex(address)?, decimals: decimals.trim().parse()? })
+    }
+}
+
+impl Token {
+    // `amount` in the token's smallest unit, rounded down
+    fn units(&self, amount: f64) -> Option<u128> {
+        let raw = (amount * 10f64.powi(self.decimals as i32)).floor();
+        (raw.is_finite() && raw >= 1.0 && raw < u128::MAX as f64).then_some(raw as u128)
+    }
+}
+
+// Just enough of the Solidity ABI encoding for the calls above
+enum Abi {
+    Word([u8; 32]),
+    Bytes(Vec<u8>),
+    Array(Vec<Abi>),
+    Tuple(Vec<Abi>),
+}
+
+impl Abi {
+    fn uint(value: u128) -> Abi {
+        let mut word = [0u8; 32];
+        word[16..].copy_from_slice(&value.to_be_bytes());
+        Abi::Word(word)
+    }
+
+    fn address(address: [u8; 20]) -> Abi {
+        let mut word = [0u8; 32];
+        word[12..].copy_from_slice(&address);
+        Abi::Word(word)
+    }
+
+    fn bool(value: bool) -> Abi {
+        Abi::uint(value as u128)
+    }
+
+    fn is_dynamic(&self) -> bool {
+        match self {
+            Abi::Word(_) => false,
+            Abi::Bytes(_) | Abi::Array(_) => true,
+            Abi::Tuple(items) => items.iter().any(Abi::is_dynamic),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Abi::Word(word) => word.to_vec(),
+            Abi::Bytes(bytes) => {
+                let mut out = encode_len(bytes.len());
+                out.extend_from_slice(bytes);
+                out.resize(32 + bytes.len().div_ceil(32) * 32, 0);
+                out
+            }
+            Abi::Array(items) => {
+                let mut out = encode_len(items.len());
+                out.extend(encode_sequence(items));
+                out
+            }
+            Abi::Tuple(items) => encode_sequence(items),
+        }
+    }
+}
+
+fn encode_len(len: usize) -> Vec<u8> {
+    Abi::uint(len as u128).encode()
+}
+
+// Heads in order, static values in place and dynamic ones as offsets to their tails
+fn encode_sequence(items: &[Abi]) -> Vec<u8> {
+    let encoded: Vec<Vec<u8>> = items.iter().map(Abi::encode).collect();
+    let head_len: usize = items.iter().zip(&encoded).map(|(item, bytes)| if item.is_dynamic() { 32 } else { bytes.len() }).sum();
+    let (mut head, mut tail) = (Vec::new(), Vec::new());
+    for (item, bytes) in items.iter().zip(encoded) {
+        if item.is_dynamic() {
+            head.extend(encode_len(head_len + tail.len()));
+            tail.extend(bytes);
+        } else {
+            head.extend(bytes);
+        }
+    }
+    head.extend(tail);
+    head
+}
+
+fn call(selector: [u8; 4], args: &[Abi]) -> Vec<u8> {
+    let mut data = selector.to_vec();
+    data.extend(encode_sequence(args));
+    data
+}
+
+/// One swap of an atomic plan; amounts in the tokens' smallest units, as decimal strings
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AtomicLeg {
+    pub venue: String,
+    pub router: String,
+    pub token_in: String,
+    pub token_out: String,
+    pub amount_in: String,
+    pub min_amount_out: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AtomicPlan {
+    pub chain: &'static str,
+    // The executor contract the transaction calls, and the call
+    pub to: String,
+    pub calldata: String,
+    pub legs: Vec<AtomicLeg>,
+    pub gas_limit: u64,
+    pub estimated_gas_usd: f64,
+    // Against each swap in a transaction of its own, as the route was priced
+    pub gas_saved_usd: f64,
+    pub deadline: DateTime<Utc>,
+}
+
+struct Swap {
+    venue: String,
+    router: [u8; 20],
+    token_in: Token,
+    token_out: Token,
+    amount_in: u128,
+    min_out: u128,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct AtomicRoutes {
+    // None: no plans
+    executor: Option<[u8; 20]>,
+    // By upper-case symbol
+    tokens: HashMap<String, Token>,
+    uniswap_fee_tiers: HashMap<String, u32>,
+    balancer_pools: HashMap<String, [u8; 32]>,
+    slippage_bps: f64,
+    deadline: Duration,
+    overhead_gas: u64,
+}
+
+impl AtomicRoutes {
+    pub fn from_env() -> Result<Self> {
+        let executor = match config::env_var("ATOMIC_EXECUTOR_ADDRESS") {
+            Ok(raw) => Some(parse_hex(&raw).map_err(|e| anyhow!("ATOMIC_EXECUTOR_ADDRESS: {}", e))?),
+            Err(_) => None,
+        };
+        let mut tokens: HashMap<String, Token> = MAINNET_TOKENS
+            .iter()
+            .map(|(symbol, address, decimals)| (symbol.to_string(), Token { address: contract(address), decimals: *decimals }))
+            .collect();
+        tokens.extend(config::env_map::<Token>("EVM_TOKENS").into_iter().map(|(symbol, token)| (symbol.to_uppercase(), token)));
+        let mut balancer_pools = HashMap::new();
+        for (pair, pool) in config::env_map::<String>("BALANCER_POOL_IDS") {
+            let pool = parse_hex(&pool).map_err(|e| anyhow!("BALANCER_POOL_IDS entry for {}: {}", pair, e))?;
+            balancer_pools.insert(pair, pool);
+        }
+        Ok(AtomicRoutes {
+            executor,
+            tokens,
+            uniswap_fee_tiers: config::env_map("UNISWAP_V3_FEE_TIERS"),
+            balancer_pools,
+            slippage_bps: config::env_or("ATOMIC_SLIPPAGE_BPS", DEFAULT_SLIPPAGE_BPS),
+            deadline: Duration::seconds(config::env_or("ATOMIC_DEADLINE_SECS", DEFAULT_DEADLINE_SECS)),
+            overhead_gas: config::env_or("ATOMIC_OVERHEAD_GAS", DEFAULT_OVERHEAD_GAS),
+        })
+    }
+
+    pub fn executor(&self) -> Option<String> {
+        self.executor.map(|executor| to_hex(&executor))
+    }
+
+    fn token(&self, symbol: &str) -> Result<Token, String> {
+        self.tokens.get(&symbol.to_uppercase()).copied().ok_or_else(|| format!("no Ethereum token for {} in EVM_TOKENS", symbol))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn swap(
+        &self,
+        venue: &str,
+        pair: &str,
+        (token_in, token_out): (Token, Token),
+        amount_in: u128,
+        min_out: u128,
+        recipient: [u8; 20],
+        deadline: DateTime<Utc>,
+    ) -> Result<Swap, String> {
+        let deadline = Abi::uint(deadline.timestamp().max(0) as u128);
+        let (router, data) = match venue {
+            "uniswap-v3-exact" => {
+                let fee = self.uniswap_fee_tiers.get(pair).copied().unwrap_or(DEFAULT_UNISWAP_FEE_TIER);
+                let params = Abi::Tuple(vec![
+                    Abi::address(token_in.address),
+                    Abi::address(token_out.address),
+                    Abi::uint(fee as u128),
+                    Abi::address(recipient),
+                    deadline,
+                    Abi::uint(amount_in),
+                    Abi::uint(min_out),
+                    // No price limit
+                    Abi::uint(0),
+                ]);
+                (contract(UNISWAP_V3_ROUTER), call(EXACT_INPUT_SINGLE, &[params]))
+            }
+            "sushiswap" => {
+                let path = Abi::Array(vec![Abi::address(token_in.address), Abi::address(token_out.address)]);
+                let args = [Abi::uint(amount_in), Abi::uint(min_out), path, Abi::address(recipient), deadline];
+                (contract(SUSHISWAP_ROUTER), call(SWAP_EXACT_TOKENS_FOR_TOKENS, &args))
+            }
+            "balancer" => {
+                let pool = self.balancer_pools.get(pair).ok_or_else(|| format!("no BALANCER_POOL_IDS entry for {}", pair))?;
+                let single_swap = Abi::Tuple(vec![
+                    Abi::Word(*pool),
+                    // GIVEN_IN
+                    Abi::uint(0),
+                    Abi::address(token_in.address),
+                    Abi::address(token_out.address),
+                    Abi::uint(amount_in),
+                    Abi::Bytes(Vec::new()),
+                ]);
+                let funds = Abi::Tuple(vec![Abi::address(recipient), Abi::bool(false), Abi::address(recipient), Abi::bool(false)]);
+                (contract(BALANCER_VAULT), call(BALANCER_SWAP, &[single_swap, funds, Abi::uint(min_out), deadline]))
+            }
+            _ => return Err(format!("{} is not an Ethereum DEX", venue)),
+        };
+        Ok(Swap { venue: venue.to_string(), router, token_in, token_out, amount_in, min_out, data })
+    }
+
+    /// Both swaps of `opp` in one executor call. `gas_per_swap` and `gas_cost_per_swap_usd`
+    /// are what the route was priced at
+    fn plan(
+        &self,
+        executor: [u8; 20],
+        opp: &ArbitrageOpportunity,
+        now: DateTime<Utc>,
+        gas_per_swap: f64,
+        gas_cost_per_swap_usd: f64,
+    ) -> Result<AtomicPlan, String> {
+        let (base, quote) = opp.pair.split_once('/').ok_or_else(|| format!("unexpected pair {:?}", opp.pair))?;
+        let (base, quote) = (self.token(base)?, self.token(quote)?);
+        let deadline = now + self.deadline;
+        let spend = quote.units(opp.max_size * opp.buy_price).ok_or("the trade rounds to nothing")?;
+        let bought = base.units(opp.max_size * (1.0 - self.slippage_bps / 10_000.0)).ok_or("the trade rounds to nothing")?;
+        let swaps = [
+            self.swap(&opp.buy_exchange, &opp.pair, (quote, base), spend, bought, executor, deadline)?,
+            self.swap(&opp.sell_exchange, &opp.pair, (base, quote), bought, spend, executor, deadline)?,
+        ];
+        let calls = swaps.iter().map(|swap| Abi::Tuple(vec![Abi::address(swap.router), Abi::bool(false), Abi::Bytes(swap.data.clone())]));
+        let calldata = call(AGGREGATE3, &[Abi::Array(calls.collect())]);
+
+        // One transaction's base cost instead of two, plus the executor's own
+        let gas_limit = ((2.0 * gas_per_swap) as u64).saturating_sub(TX_BASE_GAS) + self.overhead_gas;
+        let estimated_gas_usd = if gas_per_swap > 0.0 { gas_cost_per_swap_usd * gas_limit as f64 / gas_per_swap } else { 0.0 };
+        Ok(AtomicPlan {
+            chain: Chain::Ethereum.name(),
+            to: to_hex(&executor),
+            calldata: to_hex(&calldata),
+            legs: swaps
+                .into_iter()
+                .map(|swap| AtomicLeg {
+                    venue: swap.venue,
+                    router: to_hex(&swap.router),
+                    token_in: to_hex(&swap.token_in.address),
+                    token_out: to_hex(&swap.token_out.address),
+                    amount_in: swap.amount_in.to_string(),
+                    min_amount_out: swap.min_out.to_string(),
+                })
+                .collect(),
+            gas_limit,
+            estimated_gas_usd,
+            gas_saved_usd: 2.0 * gas_cost_per_swap_usd - estimated_gas_usd,
+            deadline,
+        })
+    }
+}
+
+impl SpreadAnalyzer {
+    /// The atomic plan of `opp` when both its legs are Ethereum DEXes and an executor is configured
+    pub(crate) fn atomic_plan(&self, opp: &ArbitrageOpportunity, now: DateTime<Utc>) -> Option<AtomicPlan> {
+        let executor = self.atomic_routes.executor?;
+        let on_ethereum = |venue: &str| Chain::of_venue(venue) == Some(Chain::Ethereum);
+        if !on_ethereum(&opp.buy_exchange) || !on_ethereum(&opp.sell_exchange) {
+            return None;
+        }
+        match self.atomic_routes.plan(executor, opp, now, self.gas.ethereum_gas_per_swap(), self.fees_config.ethereum_gas_cost) {
+            Ok(plan) => Some(plan),
+            Err(reason) => {
+                self.log_throttle.warn(
+                    &format!("atomic:{}:{}:{}", opp.pair, opp.buy_exchange, opp.sell_exchange),
+                    format_args!("No atomic plan for {} {} -> {}: {}", opp.pair, opp.buy_exchange, opp.sell_exchange, reason),
+                );
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(data: &[u8], idx: usize) -> &[u8] {
+        &data[4 + 32 * idx..4 + 32 * (idx + 1)]
+    }
+
+    fn routes_and_executor() -> (AtomicRoutes, [u8; 20]) {
+        let mut routes = AtomicRoutes::from_env().unwrap();
+        let executor = parse_hex("0x1111111111111111111111111111111111111111").unwrap();
+        routes.executor = Some(executor);
+        (routes, executor)
+    }
+
+    fn dex_opportunity(analyzer: &SpreadAnalyzer) -> ArbitrageOpportunity {
+        analyzer.evaluate_opportunity("sushiswap", "uniswap-v3-exact", "ETH/USDC", 2_500.0, 2_600.0, 10.0, 10.0).unwrap()
+    }
+
+    #[test]
+    fn builds_one_call_for_both_swaps() {
+        let (routes, executor) = routes_and_executor();
+        let analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let opp = dex_opportunity(&analyzer);
+        let plan = routes.plan(executor, &opp, Utc::now(), 150_000.0, 5.0).unwrap();
+        // 2 × 150k less one base cost, plus the executor's 10k
+        assert_eq!(plan.gas_limit, 289_000);
+        assert!((plan.estimated_gas_usd - 5.0 * 289.0 / 150.0).abs() < 1e-9 && plan.gas_saved_usd > 0.0);
+        assert_eq!(plan.legs[0].router, to_hex(&contract(SUSHISWAP_ROUTER)));
+        assert_eq!(plan.legs[0].token_in, "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+        // USDC has 6 decimals, WETH 18; the sell leg sells what the buy leg guarantees
+        assert_eq!(plan.legs[0].amount_in, ((opp.max_size * 2_500.0 * 1e6).floor() as u128).to_string());
+        assert_eq!(plan.legs[1].amount_in, plan.legs[0].min_amount_out);
+        assert_eq!(plan.legs[1].min_amount_out, plan.legs[0].amount_in);
+        assert!(plan.calldata.starts_with("0x82ad56cb"));
+        assert!(plan.calldata.contains("414bf389") && plan.calldata.contains("38ed1739"));
+    }
+
+    #[test]
+    fn v2_swaps_pass_the_path_as_an_offset() {
+        let (routes, executor) = routes_and_executor();
+        // Static arguments in place, the path as an offset past the five head words
+        let swap = routes.swap("sushiswap", "ETH/USDC", (routes.token("USDC").unwrap(), routes.token("ETH").unwrap()), 7, 3, executor, Utc::now()).unwrap();
+        assert_eq!(swap.data.len(), 4 + 32 * 8);
+        assert_eq!(word(&swap.data, 0)[31], 7);
+        assert_eq!(word(&swap.data, 2)[31], 0xa0);
+        assert_eq!(word(&swap.data, 5)[31], 2);
+        assert_eq!(&word(&swap.data, 7)[12..], &routes.token("WETH").unwrap().address);
+    }
+
+    #[test]
+    fn balancer_swaps_need_their_pool() {
+        let (mut routes, executor) = routes_and_executor();
+        let now = Utc::now();
+        let usdc_eth = (routes.token("USDC").unwrap(), routes.token("ETH").unwrap());
+        assert!(routes.swap("balancer", "ETH/USDC", usdc_eth, 7, 3, executor, now).is_err());
+        // The swap struct has a `bytes` member, so it is an offset ahead of the funds struct,
+        // both limits and its own tail
+        routes.balancer_pools.insert("ETH/USDC".to_string(), [7; 32]);
+        let swap = routes.swap("balancer", "ETH/USDC", usdc_eth, 7, 3, executor, now).unwrap();
+        assert_eq!((swap.router, swap.data.len()), (contract(BALANCER_VAULT), 4 + 32 * 14));
+        assert_eq!(word(&swap.data, 0)[31], 7 * 32);
+        assert_eq!(word(&swap.data, 7), &[7; 32]);
+    }
+
+    #[test]
+    fn only_dex_to_dex_routes_are_bundled() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.atomic_routes = routes_and_executor().0;
+        let now = Utc::now();
+        assert!(analyzer.atomic_plan(&dex_opportunity(&analyzer), now).is_some());
+        let cex = analyzer.evaluate_opportunity("binance", "uniswap-v3-exact", "ETH/USDC", 2_500.0, 2_600.0, 10.0, 10.0).unwrap();
+        assert!(analyzer.atomic_plan(&cex, now).is_none());
+    }
+}
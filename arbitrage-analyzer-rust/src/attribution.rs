@@ -0,0 +1,226 @@
+// Execution cost attribution. When the executor reports a fill, the gap between
+// the net profit estimated for the request and the one realized (the shortfall)
+// is split into the parts of the cost model that produced it, and summed per route:
+//  - latency drift: the market moved between detection and order arrival
+//    (arrival prices vs the opportunity's prices),
+//  - slippage: fill prices vs arrival prices, i.e. depth we walked or misread,
+//  - fee error: trading and withdrawal fees paid vs estimated,
+//  - gas variance: gas, transaction and transfer costs paid vs estimated.
+// Every component is in quote currency and positive when it cost us money.
+// Whatever the executor did not report lands in `residual`; without arrival
+// prices, all price movement counts as slippage.
+
+use std::collections::{HashMap, VecDeque};
+
+use serde::Serialize;
+
+use crate::history::ExecutionOutcome;
+use crate::lifecycle::RouteKey;
+
+// Attributed trades kept for `recent`
+const RECENT_TRADES: usize = 200;
+
+/// What the analyzer assumed when it emitted a request
+#[derive(Debug, Clone)]
+pub struct CostEstimate {
+    pub route: RouteKey,
+    pub size: f64,
+    pub buy_price: f64,
+    pub sell_price: f64,
+    // Percentage fees plus base lost to in-kind and withdrawal fees, at `size`
+    pub variable_fees: f64,
+    // Gas, transaction and transfer costs, independent of size
+    pub fixed_costs: f64,
+    pub net_profit: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TradeAttribution {
+    pub request_id: String,
+    pub route: RouteKey,
+    pub filled_size: f64,
+    pub expected_net: f64,
+    pub realized_net: f64,
+    pub shortfall: f64,
+    pub latency_drift: f64,
+    pub slippage: f64,
+    pub fee_error: f64,
+    pub gas_variance: f64,
+    pub residual: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RouteAttribution {
+    pub trades: usize,
+    pub shortfall: f64,
+    pub latency_drift: f64,
+    pub slippage: f64,
+    pub fee_error: f64,
+    pub gas_variance: f64,
+    pub residual: f64,
+}
+
+impl RouteAttribution {
+    fn add(&mut self, trade: &TradeAttribution) {
+        self.trades += 1;
+        self.shortfall += trade.shortfall;
+        self.latency_drift += trade.latency_drift;
+        self.slippage += trade.slippage;
+        self.fee_error += trade.fee_error;
+        self.gas_variance += trade.gas_variance;
+        self.residual += trade.residual;
+    }
+
+    /// The component furthest off in either direction, i.e. the part of the model most wrong on this route
+    pub fn dominant(&self) -> &'static str {
+        [
+            ("latency_drift", self.latency_drift),
+            ("slippage", self.slippage),
+            ("fee_error", self.fee_error),
+            ("gas_variance", self.gas_variance),
+            ("residual", self.residual),
+        ]
+        .into_iter()
+        .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+        .map_or("residual", |(name, _)| name)
+    }
+}
+
+/// Decompose the shortfall of one filled request. None when the outcome says
+/// neither what was realized nor at which prices it filled
+pub fn attribute(request_id: &str, estimate: &CostEstimate, outcome: &ExecutionOutcome) -> Option<TradeAttribution> {
+    let filled = outcome.filled_size.unwrap_or(estimate.size);
+    if filled <= 0.0 || estimate.size <= 0.0 {
+        return None;
+    }
+    let fills = outcome.buy_fill_price.zip(outcome.sell_fill_price);
+    let realized_net = outcome.realized_pnl.or_else(|| {
+        let (buy, sell) = fills?;
+        Some((sell - buy) * filled - outcome.fees_paid? - outcome.gas_paid?)
+    })?;
+
+    // Variable parts scale with the filled share, fixed costs are paid regardless
+    let fill_ratio = filled / estimate.size;
+    let expected_variable_fees = estimate.variable_fees * fill_ratio;
+    let expected_net = (estimate.net_profit + estimate.fixed_costs) * fill_ratio - estimate.fixed_costs;
+    let shortfall = expected_net - realized_net;
+
+    let arrival_buy = outcome.buy_arrival_price.unwrap_or(estimate.buy_price);
+    let arrival_sell = outcome.sell_arrival_price.unwrap_or(estimate.sell_price);
+    let latency_drift = ((arrival_buy - estimate.buy_price) + (estimate.sell_price - arrival_sell)) * filled;
+    let slippage = fills.map_or(0.0, |(buy, sell)| ((buy - arrival_buy) + (arrival_sell - sell)) * filled);
+    let fee_error = outcome.fees_paid.map_or(0.0, |paid| paid - expected_variable_fees);
+    let gas_variance = outcome.gas_paid.map_or(0.0, |paid| paid - estimate.fixed_costs);
+
+    Some(TradeAttribution {
+        request_id: request_id.to_string(),
+        route: estimate.route.clone(),
+        filled_size: filled,
+        expected_net,
+        realized_net,
+        shortfall,
+        latency_drift,
+        slippage,
+        fee_error,
+        gas_variance,
+        residual: shortfall - latency_drift - slippage - fee_error - gas_variance,
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct CostAttribution {
+    // Estimates of requests that have not reached a terminal state yet
+    pending: HashMap<String, CostEstimate>,
+    routes: HashMap<RouteKey, RouteAttribution>,
+    recent: VecDeque<TradeAttribution>,
+}
+
+impl CostAttribution {
+    pub fn open(&mut self, request_id: &str, estimate: CostEstimate) {
+        self.pending.insert(request_id.to_string(), estimate);
+    }
+
+    /// Close a request. Only fills are attributed; other terminal states just drop the estimate
+    pub fn close(&mut self, request_id: &str, filled: bool, outcome: &ExecutionOutcome) -> Option<&TradeAttribution> {
+        let estimate = self.pending.remove(request_id)?;
+        if !filled {
+            return None;
+        }
+        let trade = attribute(request_id, &estimate, outcome)?;
+        self.routes.entry(trade.route.clone()).or_default().add(&trade);
+        self.recent.push_front(trade);
+        self.recent.truncate(RECENT_TRADES);
+        self.recent.front()
+    }
+
+    /// Per-route totals, largest absolute shortfall first
+    pub fn routes(&self) -> Vec<(&RouteKey, &RouteAttribution)> {
+        let mut routes: Vec<_> = self.routes.iter().collect();
+        routes.sort_by(|a, b| b.1.shortfall.abs().total_cmp(&a.1.shortfall.abs()).then_with(|| a.0.cmp(b.0)));
+        routes
+    }
+
+    /// Attributed trades, newest first
+    pub fn recent(&self) -> impl Iterator<Item = &TradeAttribution> {
+        self.recent.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate() -> CostEstimate {
+        CostEstimate {
+            route: RouteKey::new("BTC/USDT", "binance", "okx"),
+            size: 1.0,
+            buy_price: 100_000.0,
+            sell_price: 100_300.0,
+            variable_fees: 200.0,
+            fixed_costs: 10.0,
+            net_profit: 90.0,
+        }
+    }
+
+    #[test]
+    fn splits_shortfall_into_model_components() {
+        let outcome = ExecutionOutcome {
+            filled_size: Some(1.0),
+            buy_arrival_price: Some(100_020.0),
+            sell_arrival_price: Some(100_300.0),
+            buy_fill_price: Some(100_050.0),
+            sell_fill_price: Some(100_290.0),
+            fees_paid: Some(205.0),
+            gas_paid: Some(25.0),
+            ..ExecutionOutcome::default()
+        };
+        let trade = attribute("r1", &estimate(), &outcome).unwrap();
+        // Realized: 240 spread - 205 fees - 25 gas
+        assert!((trade.realized_net - 10.0).abs() < 1e-9);
+        assert!((trade.shortfall - 80.0).abs() < 1e-9);
+        assert!((trade.latency_drift - 20.0).abs() < 1e-9);
+        assert!((trade.slippage - 40.0).abs() < 1e-9);
+        assert!((trade.fee_error - 5.0).abs() < 1e-9);
+        assert!((trade.gas_variance - 15.0).abs() < 1e-9);
+        assert!(trade.residual.abs() < 1e-9);
+    }
+
+    #[test]
+    fn aggregates_fills_per_route() {
+        let mut attribution = CostAttribution::default();
+        attribution.open("filled", estimate());
+        attribution.open("failed", estimate());
+        // Half filled, only the realized PnL reported: fixed costs still count in full
+        let outcome = ExecutionOutcome { filled_size: Some(0.5), realized_pnl: Some(0.0), ..ExecutionOutcome::default() };
+        let trade = attribution.close("filled", true, &outcome).unwrap();
+        assert!((trade.expected_net - 40.0).abs() < 1e-9);
+        assert!((trade.residual - 40.0).abs() < 1e-9);
+        assert!(attribution.close("failed", false, &ExecutionOutcome::default()).is_none());
+        assert!(attribution.close("unknown", true, &outcome).is_none());
+
+        let routes = attribution.routes();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].1.trades, 1);
+        assert_eq!(routes[0].1.dominant(), "residual");
+    }
+}
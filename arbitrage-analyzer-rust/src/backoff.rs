@@ -0,0 +1,63 @@
+// Reconnect pacing for the Redis listeners and the publisher. Each failed attempt
+// doubles the wait, from REDIS_RECONNECT_INITIAL_MS up to REDIS_RECONNECT_MAX_MS,
+// with up to a quarter of jitter so listeners that lost the same Redis don't
+// reconnect in lockstep. A connection that comes back resets the wait.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config;
+
+const DEFAULT_INITIAL_MS: u64 = 500;
+const DEFAULT_MAX_MS: u64 = 30_000;
+
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        let max = max.max(initial);
+        Backoff { initial, max, next: initial }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(
+            Duration::from_millis(config::env_or("REDIS_RECONNECT_INITIAL_MS", DEFAULT_INITIAL_MS)),
+            Duration::from_millis(config::env_or("REDIS_RECONNECT_MAX_MS", DEFAULT_MAX_MS)),
+        )
+    }
+
+    /// How long to wait before the next attempt; doubles the one after
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        // Clock nanos are spread enough for jitter, and need no RNG
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+        delay + delay.mul_f64((nanos % 1_000) as f64 / 4_000.0)
+    }
+
+    /// The connection is back; the next failure starts from the initial wait again
+    pub fn reset(&mut self) {
+        self.next = self.initial;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_up_to_the_cap_and_resets() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(500));
+        let delays: Vec<Duration> = (0..5).map(|_| backoff.next_delay()).collect();
+        for (delay, base) in delays.iter().zip([100, 200, 400, 500, 500]) {
+            let base = Duration::from_millis(base);
+            assert!(*delay >= base && *delay <= base.mul_f64(1.25), "{:?} not within jitter of {:?}", delay, base);
+        }
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_millis(125));
+    }
+}
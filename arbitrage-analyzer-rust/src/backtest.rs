@@ -0,0 +1,258 @@
+// Backtesting against recorded books. `swapsleuth --replay <file>` (or REPLAY_FILE)
+// reads newline-delimited book snapshots instead of subscribing to Redis: books as
+// the collectors write them, or the `{received_at, source, book}` lines of the book
+// archive. A file ending in `.gz` is read gzipped, so archive chunks replay as they
+// are. Books are sorted by time (the receive time when recorded, else the
+// collector's timestamp) and go through the same validation and `process_event` as
+// live ones, with the configured fees and thresholds, and the replay clock at each
+// book's time.
+//
+// REPLAY_SPEED (`--replay-speed`) paces the replay: 1 sleeps the recorded gaps
+// between books, 10 replays ten times faster, 0 (default) as fast as possible.
+// Nothing is published and no execution request is emitted; the per-pass report
+// is off unless REPORT_POLICY asks for one. At the end a summary is printed:
+// books read and rejected, opportunities found, the distinct spreads they belong
+// to, and the simulated net profit of taking each spread once, at its first
+// detection. Pairs are ranked by that profit.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::thread;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use comfy_table::Cell;
+use flate2::read::MultiGzDecoder;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::mode::Mode;
+use crate::pipeline::{self, IngestEvent};
+use crate::report::{self, OutputFormat, ReportPolicy};
+use crate::staleness;
+use crate::{config, configure_from_env, OrderBook, SpreadAnalyzer};
+
+/// A recorded book and when it was taken
+#[derive(Debug, Clone)]
+pub struct RecordedBook {
+    pub at: DateTime<Utc>,
+    pub book: OrderBook,
+}
+
+#[derive(Deserialize)]
+struct ArchivedLine {
+    received_at: Option<DateTime<Utc>>,
+    book: OrderBook,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PairResult {
+    pub pair: String,
+    pub opportunities: usize,
+    pub spreads: usize,
+    pub net_profit: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BacktestReport {
+    pub books: usize,
+    pub rejected_books: usize,
+    // Lines or stream entries that were not a book, or had no usable time
+    pub skipped_lines: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>,
+    pub opportunities: usize,
+    pub spreads: usize,
+    pub simulated_net_profit: f64,
+    // Best first
+    pub pairs: Vec<PairResult>,
+}
+
+fn parse_line(line: &str) -> Option<RecordedBook> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    if value.get("book").is_some() {
+        let archived: ArchivedLine = serde_json::from_value(value).ok()?;
+        let at = archived.received_at.or_else(|| staleness::collector_time(&archived.book))?;
+        return Some(RecordedBook { at, book: archived.book });
+    }
+    let book: OrderBook = serde_json::from_value(value).ok()?;
+    Some(RecordedBook { at: staleness::collector_time(&book)?, book })
+}
+
+/// The books in `reader`, oldest first, and how many lines were skipped
+pub fn read_books(reader: impl BufRead) -> Result<(Vec<RecordedBook>, usize)> {
+    let (mut books, mut skipped) = (Vec::new(), 0);
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_line(&line) {
+            Some(book) => books.push(book),
+            None => skipped += 1,
+        }
+    }
+    // Stable, so books recorded at the same time keep their order
+    books.sort_by_key(|book| book.at);
+    Ok((books, skipped))
+}
+
+pub(crate) fn open(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let reader: Box<dyn Read> = match path.extension().is_some_and(|ext| ext == "gz") {
+        true => Box::new(MultiGzDecoder::new(file)),
+        false => Box::new(file),
+    };
+    Ok(Box::new(BufReader::new(reader)))
+}
+
+/// Feed `books` through `analyzer`, sleeping the recorded gaps divided by `speed` when it is positive
+pub fn replay(analyzer: &mut SpreadAnalyzer, books: Vec<RecordedBook>, speed: f64) -> Result<BacktestReport> {
+    let mut report = BacktestReport { from: books.first().map(|b| b.at), to: books.last().map(|b| b.at), ..Default::default() };
+    let mut pairs: BTreeMap<String, PairResult> = BTreeMap::new();
+    let mut previous: Option<DateTime<Utc>> = None;
+    for RecordedBook { at, book } in books {
+        if let (Some(previous), true) = (previous, speed > 0.0) {
+            if let Ok(gap) = (at - previous).to_std() {
+                thread::sleep(gap.div_f64(speed));
+            }
+        }
+        previous = Some(at);
+        report.books += 1;
+
+        let expired = analyzer.live_opportunities.expire_stale(at);
+        analyzer.expire_opportunities(expired);
+        analyzer.evict_stale_books(at);
+        let key = format!("orderbook:{}:{}", book.exchange, book.pair);
+        let event = pipeline::admit_book(key, book, "replay", at);
+        if matches!(event, IngestEvent::Rejected { .. }) {
+            report.rejected_books += 1;
+        }
+        let opportunities = analyzer.process_event(event, at)?;
+        for opp in &opportunities {
+            let pair = pairs.entry(opp.pair.clone()).or_insert_with(|| PairResult { pair: opp.pair.clone(), ..Default::default() });
+            pair.opportunities += 1;
+            report.opportunities += 1;
+            // Repeats of a live spread could not be taken again
+            if analyzer.live_opportunities.first_detected_by(opp) {
+                pair.spreads += 1;
+                pair.net_profit += opp.net_profit;
+                report.spreads += 1;
+                report.simulated_net_profit += opp.net_profit;
+            }
+        }
+    }
+    report.pairs = pairs.into_values().collect();
+    report.pairs.sort_by(|a, b| b.net_profit.total_cmp(&a.net_profit).then_with(|| a.pair.cmp(&b.pair)));
+    Ok(report)
+}
+
+impl BacktestReport {
+    pub fn print(&self, format: OutputFormat) {
+        if format == OutputFormat::Jsonl {
+            let mut line = json!({ "type": "backtest" });
+            if let (Value::Object(line), Ok(Value::Object(report))) = (&mut line, serde_json::to_value(self)) {
+                line.extend(report);
+            }
+            println!("{}", line);
+            return;
+        }
+        let span = match (self.from, self.to) {
+            (Some(from), Some(to)) => format!(" from {} to {}", from.format("%Y-%m-%d %H:%M:%S"), to.format("%Y-%m-%d %H:%M:%S")),
+            _ => String::new(),
+        };
+        println!("Backtest: {} books{} ({} rejected, {} records skipped)", self.books, span, self.rejected_books, self.skipped_lines);
+        println!(
+            "{} opportunities on {} distinct spreads, simulated net profit ${:.2}",
+            self.opportunities, self.spreads, self.simulated_net_profit
+        );
+        if self.pairs.is_empty() {
+            return;
+        }
+        let mut table = report::table(&["Pair", "Opportunities", "Spreads", "Net profit"], 1..4);
+        for pair in &self.pairs {
+            table.add_row(vec![
+                Cell::new(&pair.pair),
+                Cell::new(pair.opportunities),
+                Cell::new(pair.spreads),
+                Cell::new(format!("${:.2}", pair.net_profit)),
+            ]);
+        }
+        println!("{}", table);
+        if let (Some(best), Some(worst)) = (self.pairs.first(), self.pairs.last()) {
+            println!("Best pair: {} (${:.2}), worst pair: {} (${:.2})", best.pair, best.net_profit, worst.pair, worst.net_profit);
+        }
+    }
+}
+
+/// An analyzer with the daemon's configuration that only counts; it never signals or trades
+pub fn observer(output: OutputFormat, config_path: Option<&Path>) -> Result<SpreadAnalyzer> {
+    let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379")?;
+    configure_from_env(&mut analyzer, config_path)?;
+    analyzer.mode = Mode::Observe;
+    analyzer.reporter.format = output;
+    Ok(analyzer)
+}
+
+/// The `--replay` mode of `main`
+pub fn run(path: &Path, speed: Option<f64>, output: OutputFormat, config_path: Option<&Path>) -> Result<()> {
+    let speed = speed.unwrap_or_else(|| config::env_or("REPLAY_SPEED", 0.0));
+    if !speed.is_finite() || speed < 0.0 {
+        return Err(anyhow!("replay speed must be 0 (as fast as possible) or positive, got {}", speed));
+    }
+    let mut analyzer = observer(output, config_path)?;
+    if config::env_var("REPORT_POLICY").is_err() {
+        analyzer.reporter.policy = ReportPolicy::None;
+    }
+
+    let (books, skipped) = read_books(open(path)?)?;
+    if skipped > 0 {
+        warn!("Skipped {} lines of {} that were not timestamped books", skipped, path.display());
+    }
+    info!(
+        "  Replaying {} books from {} {}",
+        books.len(),
+        path.display(),
+        if speed > 0.0 { format!("at {}x speed", speed) } else { "as fast as possible".to_string() }
+    );
+    let mut report = replay(&mut analyzer, books, speed)?;
+    report.skipped_lines = skipped;
+    report.print(output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_books_in_time_order_and_counts_each_spread_once() {
+        // Out of order on purpose; the archive line is the latest
+        let recorded = [
+            r#"{"exchange":"okx","pair":"BTC/USDT","bids":[[50800.0,1.5]],"asks":[[50810.0,1.1]],"timestamp":1704067201}"#,
+            r#"{"exchange":"binance","pair":"BTC/USDT","bids":[[49990.0,1.2]],"asks":[[50000.0,1.0]],"timestamp":1704067200000}"#,
+            "not json",
+            r#"{"received_at":"2024-01-01T00:00:02Z","source":"primary","book":{"exchange":"okx","pair":"BTC/USDT","bids":[[50790.0,1.5]],"asks":[[50800.0,1.1]],"timestamp":0}}"#,
+            r#"{"exchange":"bybit","pair":"BTC/USDT","bids":[[51000.0,1.0]],"asks":[[49000.0,1.0]],"timestamp":1704067203}"#,
+        ]
+        .join("\n");
+        let (books, skipped) = read_books(recorded.as_bytes()).unwrap();
+        assert_eq!(skipped, 1);
+        assert_eq!(books.iter().map(|b| b.book.exchange.as_str()).collect::<Vec<_>>(), vec!["binance", "okx", "okx", "bybit"]);
+
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.reporter.policy = ReportPolicy::None;
+        let report = replay(&mut analyzer, books, 0.0).unwrap();
+        assert_eq!((report.books, report.rejected_books), (4, 1));
+        // The second OKX book repeats the live binance -> okx spread
+        assert_eq!((report.opportunities, report.spreads), (2, 1));
+        assert_eq!(report.pairs.len(), 1);
+        assert!(report.simulated_net_profit > 0.0);
+        assert_eq!(report.pairs[0].net_profit, report.simulated_net_profit);
+    }
+}
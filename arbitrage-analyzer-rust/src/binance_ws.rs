@@ -0,0 +1,405 @@
+// Direct Binance depth ingestion, bypassing the Go collector and Redis for the
+// symbols in BINANCE_WS_SYMBOLS (build with `--features binance-ws`). The local
+// book follows Binance's documented snapshot + diff protocol:
+//  1. subscribe to `<symbol>@depth@100ms` and buffer the diffs,
+//  2. fetch a REST depth snapshot; if it is older than the first buffered diff, fetch again,
+//  3. drop buffered diffs already contained in the snapshot (`u <= lastUpdateId`),
+//  4. apply the rest, and every later diff, in update id order.
+// A diff whose first update id skips past the book's last one is a gap: the book
+// is discarded and rebuilt from a new snapshot. Each applied diff hands the top
+// BINANCE_WS_DEPTH levels to the analysis queue like any other book update.
+
+// Only the sync logic's tests use it without the feature
+#![cfg_attr(not(feature = "binance-ws"), allow(dead_code))]
+
+use std::collections::VecDeque;
+
+use serde::Deserialize;
+
+use crate::config;
+use crate::depth::{LevelBook, Levels};
+
+pub const EXCHANGE: &str = "binance";
+pub const SOURCE_NAME: &str = "binance-ws";
+const DEFAULT_WS_URL: &str = "wss://stream.binance.com:9443/ws";
+const DEFAULT_REST_URL: &str = "https://api.binance.com";
+const DEFAULT_DEPTH: usize = 100;
+const DEFAULT_SNAPSHOT_LIMIT: usize = 1000;
+// Diffs held while waiting for a snapshot; older ones are dropped first
+const MAX_BUFFERED_DIFFS: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct BinanceWsConfig {
+    // (exchange symbol, pair name the analyzer sees), e.g. ("BTCUSDT", "BTCUSDT")
+    pub symbols: Vec<(String, String)>,
+    pub ws_url: String,
+    pub rest_url: String,
+    // Levels per side handed to the analyzer
+    pub depth: usize,
+    pub snapshot_limit: usize,
+}
+
+impl BinanceWsConfig {
+    /// None unless BINANCE_WS_SYMBOLS lists at least one symbol, as `SYMBOL` or `SYMBOL:PAIR`
+    pub fn from_env() -> Option<Self> {
+        let raw = config::env_var("BINANCE_WS_SYMBOLS").ok()?;
+        let symbols: Vec<(String, String)> = raw
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|entry| match entry.split_once(':') {
+                Some((symbol, pair)) => (symbol.trim().to_uppercase(), pair.trim().to_string()),
+                // Same pair naming as the Go collector
+                None => (entry.to_uppercase(), entry.to_uppercase()),
+            })
+            .collect();
+        if symbols.is_empty() {
+            return None;
+        }
+        Some(BinanceWsConfig {
+            symbols,
+            ws_url: config::env_var("BINANCE_WS_URL").unwrap_or_else(|_| DEFAULT_WS_URL.to_string()),
+            rest_url: config::env_var("BINANCE_REST_URL").unwrap_or_else(|_| DEFAULT_REST_URL.to_string()),
+            depth: config::env_or("BINANCE_WS_DEPTH", DEFAULT_DEPTH),
+            snapshot_limit: config::env_or("BINANCE_SNAPSHOT_LIMIT", DEFAULT_SNAPSHOT_LIMIT),
+        })
+    }
+}
+
+/// A `depthUpdate` stream event
+#[derive(Debug, Clone, Deserialize)]
+pub struct DepthDiff {
+    #[serde(rename = "U")]
+    pub first_update_id: u64,
+    #[serde(rename = "u")]
+    pub final_update_id: u64,
+    #[serde(rename = "b")]
+    pub bids: Vec<[String; 2]>,
+    #[serde(rename = "a")]
+    pub asks: Vec<[String; 2]>,
+}
+
+/// A `GET /api/v3/depth` response
+#[derive(Debug, Clone, Deserialize)]
+pub struct DepthSnapshot {
+    #[serde(rename = "lastUpdateId")]
+    pub last_update_id: u64,
+    pub bids: Vec<[String; 2]>,
+    pub asks: Vec<[String; 2]>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncStatus {
+    // No book yet; the diff was buffered until a snapshot arrives
+    Buffering,
+    // The book is consistent and changed
+    Updated,
+    // The diff was already contained in the book
+    Ignored,
+    // The book was discarded; a new snapshot is needed
+    Resync(String),
+}
+
+#[derive(Debug, Default)]
+struct LocalBook {
+    levels: LevelBook,
+    last_update_id: u64,
+}
+
+/// Snapshot + diff synchronization for one symbol
+#[derive(Debug, Default)]
+pub struct DepthSync {
+    book: Option<LocalBook>,
+    buffer: VecDeque<DepthDiff>,
+}
+
+impl DepthSync {
+    pub fn last_update_id(&self) -> Option<u64> {
+        self.book.as_ref().map(|book| book.last_update_id)
+    }
+
+    fn discard(&mut self, reason: String) -> SyncStatus {
+        self.book = None;
+        SyncStatus::Resync(reason)
+    }
+
+    pub fn on_diff(&mut self, diff: DepthDiff) -> SyncStatus {
+        let Some(book) = &mut self.book else {
+            self.buffer.push_back(diff);
+            while self.buffer.len() > MAX_BUFFERED_DIFFS {
+                self.buffer.pop_front();
+            }
+            return SyncStatus::Buffering;
+        };
+        if diff.final_update_id <= book.last_update_id {
+            return SyncStatus::Ignored;
+        }
+        if diff.first_update_id > book.last_update_id + 1 {
+            let reason = format!("gap: expected update {}, got {}..{}", book.last_update_id + 1, diff.first_update_id, diff.final_update_id);
+            // The diff is newer than the discarded book, keep it for the next snapshot
+            self.buffer.push_back(diff);
+            return self.discard(reason);
+        }
+        match book.levels.apply(&diff.bids, &diff.asks) {
+            Ok(()) => {
+                book.last_update_id = diff.final_update_id;
+                SyncStatus::Updated
+            }
+            // Half-applied diff, the book can't be trusted anymore
+            Err(e) => self.discard(format!("malformed diff {}: {}", diff.final_update_id, e)),
+        }
+    }
+
+    pub fn on_snapshot(&mut self, snapshot: DepthSnapshot) -> SyncStatus {
+        while self.buffer.front().is_some_and(|diff| diff.final_update_id <= snapshot.last_update_id) {
+            self.buffer.pop_front();
+        }
+        if let Some(first) = self.buffer.front() {
+            if first.first_update_id > snapshot.last_update_id + 1 {
+                // Keep buffering; a newer snapshot will cover the gap
+                return SyncStatus::Resync(format!(
+                    "snapshot {} is older than buffered update {}",
+                    snapshot.last_update_id, first.first_update_id
+                ));
+            }
+        }
+
+        match LevelBook::from_levels(&snapshot.bids, &snapshot.asks) {
+            Ok(levels) => self.book = Some(LocalBook { levels, last_update_id: snapshot.last_update_id }),
+            Err(e) => return self.discard(format!("malformed snapshot {}: {}", snapshot.last_update_id, e)),
+        }
+
+        // After a gap inside the buffer the remaining diffs are buffered again for the next snapshot
+        let mut status = SyncStatus::Updated;
+        for diff in std::mem::take(&mut self.buffer) {
+            if let SyncStatus::Resync(reason) = self.on_diff(diff) {
+                status = SyncStatus::Resync(reason);
+            }
+        }
+        status
+    }
+
+    /// Top `depth` levels per side as `[price, size]`, best first; None while out of sync
+    pub fn levels(&self, depth: usize) -> Option<(Levels, Levels)> {
+        self.book.as_ref().map(|book| book.levels.top(depth))
+    }
+}
+
+#[cfg(feature = "binance-ws")]
+pub use stream::spawn;
+
+/// Without websocket support in the build the symbols are ignored
+#[cfg(not(feature = "binance-ws"))]
+pub fn spawn(
+    _config: BinanceWsConfig,
+    _queue: std::sync::Arc<crate::pipeline::BookQueue>,
+    _metrics: std::sync::Arc<crate::metrics::Metrics>,
+) {
+    log::warn!("BINANCE_WS_SYMBOLS is set but this build has no websocket support (--features binance-ws); ignoring it");
+}
+
+#[cfg(feature = "binance-ws")]
+mod stream {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use anyhow::{anyhow, Result};
+    use chrono::Utc;
+    use log::{info, warn};
+    use tungstenite::Message;
+
+    use super::{BinanceWsConfig, DepthDiff, DepthSnapshot, DepthSync, SyncStatus, EXCHANGE, SOURCE_NAME};
+    #[cfg(feature = "chaos")]
+    use crate::chaos;
+    use crate::metrics::Metrics;
+    use crate::pipeline::{BookQueue, IngestEvent};
+    use crate::OrderBook;
+
+    const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+    // A snapshot older than the stream is retried this often before reconnecting
+    const SNAPSHOT_ATTEMPTS: u32 = 5;
+    const SNAPSHOT_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+    /// One websocket per symbol, each on its own thread, feeding `queue`
+    pub fn spawn(config: BinanceWsConfig, queue: Arc<BookQueue>, metrics: Arc<Metrics>) {
+        for (symbol, pair) in config.symbols.clone() {
+            let config = config.clone();
+            let queue = queue.clone();
+            let metrics = metrics.clone();
+            thread::spawn(move || loop {
+                if let Err(e) = stream_symbol(&config, &symbol, &pair, &queue, &metrics) {
+                    warn!("Binance depth stream for {} failed: {}; reconnecting in {}s", symbol, e, RECONNECT_DELAY.as_secs());
+                }
+                thread::sleep(RECONNECT_DELAY);
+            });
+        }
+    }
+
+    fn fetch_snapshot(config: &BinanceWsConfig, symbol: &str) -> Result<DepthSnapshot> {
+        let url = format!("{}/api/v3/depth?symbol={}&limit={}", config.rest_url, symbol, config.snapshot_limit);
+        let response = ureq::get(&url).timeout(Duration::from_secs(10)).call().map_err(|e| anyhow!("snapshot request failed: {}", e))?;
+        Ok(response.into_json()?)
+    }
+
+    // Fetch snapshots until one lines up with the buffered diffs
+    fn resync(config: &BinanceWsConfig, symbol: &str, sync: &mut DepthSync, metrics: &Metrics) -> Result<SyncStatus> {
+        Metrics::inc(&metrics.binance_resyncs);
+        for _ in 0..SNAPSHOT_ATTEMPTS {
+            match sync.on_snapshot(fetch_snapshot(config, symbol)?) {
+                SyncStatus::Resync(reason) => {
+                    warn!("Binance {} snapshot unusable: {}", symbol, reason);
+                    thread::sleep(SNAPSHOT_RETRY_DELAY);
+                }
+                status => return Ok(status),
+            }
+        }
+        Err(anyhow!("no usable snapshot after {} attempts", SNAPSHOT_ATTEMPTS))
+    }
+
+    fn stream_symbol(config: &BinanceWsConfig, symbol: &str, pair: &str, queue: &BookQueue, metrics: &Metrics) -> Result<()> {
+        let url = format!("{}/{}@depth@100ms", config.ws_url, symbol.to_lowercase());
+        let (mut socket, _) = tungstenite::connect(url.as_str())?;
+        info!("  Streaming Binance depth for {} from {}", symbol, url);
+
+        let mut sync = DepthSync::default();
+        #[cfg(feature = "chaos")]
+        let mut chaos = chaos::Chaos::from_env();
+        loop {
+            let text = match socket.read()? {
+                Message::Text(text) => text,
+                Message::Close(frame) => return Err(anyhow!("closed by server: {:?}", frame)),
+                // Pings are answered by tungstenite itself
+                _ => continue,
+            };
+            #[cfg(feature = "chaos")]
+            let texts = chaos::pass(&mut chaos, text);
+            #[cfg(not(feature = "chaos"))]
+            let texts = [text];
+            for text in texts {
+                let diff: DepthDiff = serde_json::from_str(&text)?;
+
+                let mut status = sync.on_diff(diff);
+                if let SyncStatus::Resync(reason) = &status {
+                    warn!("Binance {} book out of sync ({}); resyncing from a snapshot", symbol, reason);
+                }
+                if matches!(status, SyncStatus::Buffering | SyncStatus::Resync(_)) {
+                    status = resync(config, symbol, &mut sync, metrics)?;
+                    info!("Binance {} book synced at update {}", symbol, sync.last_update_id().unwrap_or_default());
+                }
+
+                if status == SyncStatus::Updated {
+                    let Some((bids, asks)) = sync.levels(config.depth) else { continue };
+                    let book = OrderBook {
+                        exchange: EXCHANGE.to_string(),
+                        pair: pair.to_string(),
+                        bids,
+                        asks,
+                        // Same versioning as the Go collector, which stores lastUpdateId
+                        timestamp: sync.last_update_id().unwrap_or_default() as i64,
+                        slot: None,
+                        received_at: Some(Utc::now()),
+                        source: Some(SOURCE_NAME.to_string()),
+                        anomaly_score: None,
+                    };
+                    queue.push(IngestEvent::Book { key: format!("orderbook:{}:{}", EXCHANGE, pair), book });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(raw: &[(&str, &str)]) -> Vec<[String; 2]> {
+        raw.iter().map(|(p, q)| [p.to_string(), q.to_string()]).collect()
+    }
+
+    fn diff(first: u64, last: u64, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> DepthDiff {
+        DepthDiff { first_update_id: first, final_update_id: last, bids: levels(bids), asks: levels(asks) }
+    }
+
+    fn snapshot(last_update_id: u64) -> DepthSnapshot {
+        DepthSnapshot {
+            last_update_id,
+            bids: levels(&[("100.0", "1.0"), ("99.0", "2.0")]),
+            asks: levels(&[("101.0", "1.5"), ("102.0", "3.0")]),
+        }
+    }
+
+    #[test]
+    fn applies_buffered_diffs_after_the_snapshot() {
+        let mut sync = DepthSync::default();
+        // Already in the snapshot, dropped
+        assert_eq!(sync.on_diff(diff(90, 100, &[("100.0", "9.0")], &[])), SyncStatus::Buffering);
+        // Straddles the snapshot's lastUpdateId
+        assert_eq!(sync.on_diff(diff(101, 105, &[("99.0", "0")], &[("100.5", "0.5")])), SyncStatus::Buffering);
+
+        assert_eq!(sync.on_snapshot(snapshot(103)), SyncStatus::Updated);
+        assert_eq!(sync.last_update_id(), Some(105));
+        let (bids, asks) = sync.levels(10).unwrap();
+        assert_eq!(bids, vec![vec![100.0, 1.0]]);
+        assert_eq!(asks[0], vec![100.5, 0.5]);
+
+        assert_eq!(sync.on_diff(diff(104, 105, &[], &[])), SyncStatus::Ignored);
+        assert_eq!(sync.on_diff(diff(106, 107, &[("100.2", "4.0")], &[])), SyncStatus::Updated);
+        assert_eq!(sync.levels(1).unwrap().0, vec![vec![100.2, 4.0]]);
+    }
+
+    #[test]
+    fn gaps_discard_the_book() {
+        let mut sync = DepthSync::default();
+        sync.on_snapshot(snapshot(10));
+        assert!(matches!(sync.on_diff(diff(13, 14, &[], &[])), SyncStatus::Resync(_)));
+        assert!(sync.levels(10).is_none());
+
+        // The diff that revealed the gap is replayed on the next snapshot
+        assert_eq!(sync.on_snapshot(snapshot(13)), SyncStatus::Updated);
+        assert_eq!(sync.last_update_id(), Some(14));
+    }
+
+    #[cfg(feature = "chaos")]
+    #[test]
+    fn lost_and_reordered_diffs_never_rewind_the_book() {
+        use std::time::{Duration, Instant};
+
+        use crate::chaos::{Chaos, ChaosConfig};
+
+        let config =
+            ChaosConfig { drop_prob: 0.05, delay_prob: 0.0, duplicate_prob: 0.1, reorder_prob: 0.1, delay: Duration::from_secs(1), seed: 42 };
+        let mut chaos = Chaos::new(config);
+        let now = Instant::now();
+        let mut sync = DepthSync::default();
+        sync.on_snapshot(snapshot(0));
+
+        let (mut resyncs, mut ignored, mut last_seen) = (0, 0, 0);
+        for id in 1..=500 {
+            for diff in chaos.admit(diff(id, id, &[], &[]), now) {
+                match sync.on_diff(diff) {
+                    // Resynced from a snapshot of the stream as it stands
+                    SyncStatus::Resync(_) => {
+                        resyncs += 1;
+                        sync.on_snapshot(snapshot(id));
+                    }
+                    SyncStatus::Ignored => ignored += 1,
+                    _ => {}
+                }
+                let current = sync.last_update_id().unwrap_or(last_seen);
+                assert!(current >= last_seen, "book went back from {} to {}", last_seen, current);
+                last_seen = current;
+            }
+        }
+        assert!(resyncs > 0 && ignored > 0);
+    }
+
+    #[test]
+    fn rejects_snapshots_older_than_the_stream() {
+        let mut sync = DepthSync::default();
+        sync.on_diff(diff(50, 55, &[], &[]));
+        assert!(matches!(sync.on_snapshot(snapshot(40)), SyncStatus::Resync(_)));
+        assert!(sync.levels(10).is_none());
+        assert_eq!(sync.on_snapshot(snapshot(52)), SyncStatus::Updated);
+    }
+}
@@ -0,0 +1,391 @@
+// Cold-path archive of raw book snapshots in S3-compatible object storage, for
+// research that needs every book rather than what Redis still holds. Enabled with
+// BOOK_ARCHIVE_BUCKET. Every book that passes validation is queued, before its
+// levels are trimmed, to a background thread that gathers them into chunks of up
+// to BOOK_ARCHIVE_CHUNK_BOOKS books or BOOK_ARCHIVE_FLUSH_SECS, whichever comes
+// first, and uploads each as gzipped JSON Lines:
+//   <prefix>/date=2024-01-31/hour=12/books-20240131T120500.123Z-<uuid>.jsonl.gz
+// One line per book: `received_at`, `source`, and the book as collectors send it.
+// A chunk is partitioned by the time of its first book.
+//
+//  - BOOK_ARCHIVE_ENDPOINT: e.g. `http://minio:9000`; defaults to AWS S3 in
+//    BOOK_ARCHIVE_REGION (default `us-east-1`). Buckets are addressed path-style.
+//  - BOOK_ARCHIVE_PREFIX: key prefix, default `books`.
+//  - BOOK_ARCHIVE_MIN_INTERVAL_MS: archive a book at most this often per
+//    exchange and pair; 0 (default) keeps every update.
+//  - AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY / AWS_SESSION_TOKEN: credentials,
+//    used to sign uploads with SigV4.
+// The queue holds BOOK_ARCHIVE_QUEUE_BOOKS books; while uploads fall behind, new
+// books are dropped rather than slowing analysis. A chunk that fails to upload is
+// retried with backoff, then dropped.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::backoff::Backoff;
+use crate::metrics::Metrics;
+use crate::{config, OrderBook};
+
+const DEFAULT_REGION: &str = "us-east-1";
+const DEFAULT_PREFIX: &str = "books";
+const DEFAULT_CHUNK_BOOKS: usize = 10_000;
+const DEFAULT_FLUSH_SECS: u64 = 300;
+const DEFAULT_QUEUE_BOOKS: usize = 50_000;
+const UPLOAD_ATTEMPTS: usize = 3;
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct BookArchiveConfig {
+    pub endpoint: String,
+    pub bucket: String,
+    pub prefix: String,
+    pub chunk_books: usize,
+    pub flush_interval: Duration,
+    pub queue_books: usize,
+    pub min_interval_ms: i64,
+    pub signer: SigV4,
+}
+
+impl BookArchiveConfig {
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(bucket) = config::env_var("BOOK_ARCHIVE_BUCKET") else { return Ok(None) };
+        let region = config::env_var("BOOK_ARCHIVE_REGION").unwrap_or_else(|_| DEFAULT_REGION.to_string());
+        let endpoint = config::env_var("BOOK_ARCHIVE_ENDPOINT").unwrap_or_else(|_| format!("https://s3.{}.amazonaws.com", region));
+        let signer = SigV4 {
+            access_key: config::env_var("AWS_ACCESS_KEY_ID").map_err(|_| anyhow!("BOOK_ARCHIVE_BUCKET needs AWS_ACCESS_KEY_ID"))?,
+            secret_key: config::env_var("AWS_SECRET_ACCESS_KEY").map_err(|_| anyhow!("BOOK_ARCHIVE_BUCKET needs AWS_SECRET_ACCESS_KEY"))?,
+            session_token: config::env_var("AWS_SESSION_TOKEN").ok(),
+            region,
+            service: "s3".to_string(),
+        };
+        Ok(Some(BookArchiveConfig {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            bucket,
+            prefix: config::env_var("BOOK_ARCHIVE_PREFIX").unwrap_or_else(|_| DEFAULT_PREFIX.to_string()).trim_matches('/').to_string(),
+            chunk_books: config::env_or("BOOK_ARCHIVE_CHUNK_BOOKS", DEFAULT_CHUNK_BOOKS).max(1),
+            flush_interval: Duration::from_secs(config::env_or("BOOK_ARCHIVE_FLUSH_SECS", DEFAULT_FLUSH_SECS).max(1)),
+            queue_books: config::env_or("BOOK_ARCHIVE_QUEUE_BOOKS", DEFAULT_QUEUE_BOOKS).max(1),
+            min_interval_ms: config::env_or("BOOK_ARCHIVE_MIN_INTERVAL_MS", 0),
+            signer,
+        }))
+    }
+
+    /// Object key of a chunk whose first book arrived at `started`
+    pub fn chunk_key(&self, started: DateTime<Utc>) -> String {
+        format!(
+            "{}/date={}/hour={}/books-{}-{}.jsonl.gz",
+            self.prefix,
+            started.format("%Y-%m-%d"),
+            started.format("%H"),
+            started.format("%Y%m%dT%H%M%S%.3fZ"),
+            uuid::Uuid::new_v4()
+        )
+    }
+
+    fn host(&self) -> &str {
+        let authority = self.endpoint.split_once("://").map_or(self.endpoint.as_str(), |(_, rest)| rest);
+        authority.split('/').next().unwrap_or(authority)
+    }
+
+    fn upload(&self, key: &str, body: &[u8], now: DateTime<Utc>) -> Result<()> {
+        let path = format!("/{}/{}", uri_encode(&self.bucket), key.split('/').map(uri_encode).collect::<Vec<_>>().join("/"));
+        let payload_hash = hex(&hmac_sha256::Hash::hash(body));
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let mut headers = BTreeMap::from([
+            ("host".to_string(), self.host().to_string()),
+            ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+            ("x-amz-date".to_string(), amz_date),
+        ]);
+        if let Some(token) = &self.signer.session_token {
+            headers.insert("x-amz-security-token".to_string(), token.clone());
+        }
+        let authorization = self.signer.authorization("PUT", &path, &headers, &payload_hash, now);
+
+        let mut request = ureq::put(&format!("{}{}", self.endpoint, path))
+            .timeout(UPLOAD_TIMEOUT)
+            .set("Authorization", &authorization)
+            // Not `Content-Encoding`, which would have clients unpack it on download
+            .set("Content-Type", "application/gzip");
+        for (name, value) in headers.iter().filter(|(name, _)| name.as_str() != "host") {
+            request = request.set(name, value);
+        }
+        match request.send_bytes(body) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(status, response)) => {
+                Err(anyhow!("{} answered {}: {}", self.endpoint, status, response.into_string().unwrap_or_default()))
+            }
+            Err(e) => Err(anyhow!("{}: {}", self.endpoint, e)),
+        }
+    }
+}
+
+/// AWS Signature Version 4 for requests without a query string
+#[derive(Clone)]
+pub struct SigV4 {
+    pub access_key: String,
+    pub secret_key: String,
+    pub session_token: Option<String>,
+    pub region: String,
+    pub service: String,
+}
+
+impl std::fmt::Debug for SigV4 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SigV4").field("access_key", &self.access_key).field("region", &self.region).field("service", &self.service).finish()
+    }
+}
+
+impl SigV4 {
+    /// `Authorization` header signing every one of `headers` (lowercase names, `x-amz-date` included)
+    pub fn authorization(&self, method: &str, path: &str, headers: &BTreeMap<String, String>, payload_hash: &str, now: DateTime<Utc>) -> String {
+        let date = now.format("%Y%m%d").to_string();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+        let signed_headers = headers.keys().map(String::as_str).collect::<Vec<_>>().join(";");
+        let canonical_request = format!("{}\n{}\n\n{}\n{}\n{}", method, path, canonical_headers, signed_headers, payload_hash);
+
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex(&hmac_sha256::Hash::hash(canonical_request.as_bytes())));
+        let mut key = hmac_sha256::HMAC::mac(date.as_bytes(), format!("AWS4{}", self.secret_key).as_bytes());
+        for part in [self.region.as_str(), self.service.as_str(), "aws4_request"] {
+            key = hmac_sha256::HMAC::mac(part.as_bytes(), key);
+        }
+        let signature = hex(&hmac_sha256::HMAC::mac(string_to_sign.as_bytes(), key));
+        format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", self.access_key, scope, signed_headers, signature)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// One path segment, encoded as SigV4 expects
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct ArchivedBook<'a> {
+    received_at: Option<DateTime<Utc>>,
+    source: Option<&'a str>,
+    book: &'a OrderBook,
+}
+
+/// A chunk being filled: gzipped JSON Lines
+struct Chunk {
+    started: DateTime<Utc>,
+    opened: Instant,
+    books: usize,
+    encoder: GzEncoder<Vec<u8>>,
+}
+
+impl Chunk {
+    fn new(started: DateTime<Utc>) -> Self {
+        Chunk { started, opened: Instant::now(), books: 0, encoder: GzEncoder::new(Vec::new(), Compression::default()) }
+    }
+
+    fn push(&mut self, book: &OrderBook) -> Result<()> {
+        let line = serde_json::to_vec(&ArchivedBook { received_at: book.received_at, source: book.source.as_deref(), book })?;
+        self.encoder.write_all(&line)?;
+        self.encoder.write_all(b"\n")?;
+        self.books += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Vec<u8>> {
+        Ok(self.encoder.finish()?)
+    }
+}
+
+#[derive(Debug)]
+pub struct BookArchiver {
+    queue: SyncSender<OrderBook>,
+    // Signalled once the thread has uploaded what was queued before `close`
+    drained: Receiver<()>,
+    min_interval_ms: i64,
+    // Last archived receive time per `exchange:pair`
+    last: HashMap<String, DateTime<Utc>>,
+    metrics: Arc<Metrics>,
+}
+
+impl BookArchiver {
+    pub fn spawn(config: BookArchiveConfig, metrics: Arc<Metrics>) -> Self {
+        info!(
+            "  Archiving book snapshots to {}/{}/{} in chunks of up to {} books or {}s",
+            config.endpoint,
+            config.bucket,
+            config.prefix,
+            config.chunk_books,
+            config.flush_interval.as_secs()
+        );
+        let (queue, rx) = mpsc::sync_channel(config.queue_books);
+        let (drained_tx, drained) = mpsc::channel();
+        let min_interval_ms = config.min_interval_ms;
+        let thread_metrics = metrics.clone();
+        thread::spawn(move || {
+            archive_books(&config, rx, &thread_metrics);
+            let _ = drained_tx.send(());
+        });
+        BookArchiver { queue, drained, min_interval_ms, last: HashMap::new(), metrics }
+    }
+
+    /// Queue a validated book; dropped when the uploads have fallen behind
+    pub fn archive(&mut self, book: &OrderBook, now: DateTime<Utc>) {
+        if self.min_interval_ms > 0 {
+            let key = format!("{}:{}", book.exchange, book.pair);
+            if self.last.get(&key).is_some_and(|last| (now - *last).num_milliseconds() < self.min_interval_ms) {
+                return;
+            }
+            self.last.insert(key, now);
+        }
+        match self.queue.try_send(book.clone()) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => Metrics::inc(&self.metrics.book_archive_dropped),
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+
+    /// Upload the chunk in progress, waiting up to `timeout`
+    pub fn close(self, timeout: Duration) {
+        let BookArchiver { queue, drained, .. } = self;
+        drop(queue);
+        match drained.recv_timeout(timeout) {
+            Ok(()) => info!("Book archive flushed"),
+            Err(_) => warn!("Book archive still uploading after {}s, dropping the rest", timeout.as_secs()),
+        }
+    }
+}
+
+fn archive_books(config: &BookArchiveConfig, rx: Receiver<OrderBook>, metrics: &Metrics) {
+    let mut chunk: Option<Chunk> = None;
+    loop {
+        let wait = chunk.as_ref().map_or(config.flush_interval, |chunk| config.flush_interval.saturating_sub(chunk.opened.elapsed()));
+        let closed = match rx.recv_timeout(wait) {
+            Ok(book) => {
+                let current = chunk.get_or_insert_with(|| Chunk::new(book.received_at.unwrap_or_else(Utc::now)));
+                if let Err(e) = current.push(&book) {
+                    warn!("Failed to archive book {}:{}: {}", book.exchange, book.pair, e);
+                }
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        let due = chunk.as_ref().is_some_and(|chunk| chunk.books >= config.chunk_books || chunk.opened.elapsed() >= config.flush_interval);
+        if due || closed {
+            if let Some(full) = chunk.take() {
+                upload_chunk(config, full, metrics);
+            }
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+fn upload_chunk(config: &BookArchiveConfig, chunk: Chunk, metrics: &Metrics) {
+    let key = config.chunk_key(chunk.started);
+    let books = chunk.books;
+    let body = match chunk.finish() {
+        Ok(body) => body,
+        Err(e) => return warn!("Failed to compress book chunk {}: {}", key, e),
+    };
+    let mut backoff = Backoff::from_env();
+    for attempt in 1..=UPLOAD_ATTEMPTS {
+        match config.upload(&key, &body, Utc::now()) {
+            Ok(()) => {
+                info!("Archived {} books to {} ({} bytes)", books, key, body.len());
+                Metrics::inc(&metrics.book_archive_chunks);
+                return;
+            }
+            Err(e) if attempt < UPLOAD_ATTEMPTS => {
+                let delay = backoff.next_delay();
+                warn!("Uploading {} failed: {}; retrying in {}ms", key, e, delay.as_millis());
+                thread::sleep(delay);
+            }
+            Err(e) => warn!("Uploading {} failed: {}; dropping its {} books", key, e, books),
+        }
+    }
+    Metrics::inc(&metrics.book_archive_upload_failures);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::io::Read;
+
+    // The credentials of the AWS SigV4 test suite
+    fn signer() -> SigV4 {
+        SigV4 {
+            access_key: "AKIDEXAMPLE".to_string(),
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+            region: "us-east-1".to_string(),
+            service: "service".to_string(),
+        }
+    }
+
+    #[test]
+    fn signs_requests_like_sigv4() {
+        // get-vanilla from the AWS SigV4 test suite
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let headers = BTreeMap::from([
+            ("host".to_string(), "example.amazonaws.com".to_string()),
+            ("x-amz-date".to_string(), "20150830T123600Z".to_string()),
+        ]);
+        assert_eq!(
+            signer().authorization("GET", "/", &headers, &hex(&hmac_sha256::Hash::hash(b"")), now),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+        assert_eq!(uri_encode("BTC/USDT:x y"), "BTC%2FUSDT%3Ax%20y");
+    }
+
+    #[test]
+    fn chunks_are_keyed_by_day_and_hour() {
+        let config = BookArchiveConfig {
+            endpoint: "http://minio:9000".to_string(),
+            bucket: "lake".to_string(),
+            prefix: "raw/books".to_string(),
+            chunk_books: 2,
+            flush_interval: Duration::from_secs(60),
+            queue_books: 10,
+            min_interval_ms: 0,
+            signer: signer(),
+        };
+        assert_eq!(config.host(), "minio:9000");
+        let key = config.chunk_key(Utc.with_ymd_and_hms(2024, 1, 31, 7, 5, 0).unwrap());
+        assert!(key.starts_with("raw/books/date=2024-01-31/hour=07/books-20240131T070500.000Z-") && key.ends_with(".jsonl.gz"), "{}", key);
+    }
+
+    #[test]
+    fn chunks_hold_one_gzipped_book_per_line() {
+        let book: OrderBook = serde_json::from_str(r#"{"exchange":"binance","pair":"BTC/USDT","bids":[[50000,1]],"asks":[[50001,2]],"timestamp":1}"#).unwrap();
+        let mut chunk = Chunk::new(Utc::now());
+        chunk.push(&book).unwrap();
+        chunk.push(&book).unwrap();
+        let mut lines = String::new();
+        flate2::read::GzDecoder::new(chunk.finish().unwrap().as_slice()).read_to_string(&mut lines).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+        let first: serde_json::Value = serde_json::from_str(lines.lines().next().unwrap()).unwrap();
+        assert_eq!(first["book"]["asks"][0][1], 2.0);
+    }
+}
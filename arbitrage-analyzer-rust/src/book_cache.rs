@@ -0,0 +1,171 @@
+// Size budget for the in-memory `books` cache. New pairs keep appearing on the
+// DEX side, so once the cache is over its entry or byte budget the books that were
+// updated least recently are evicted. Pinned pairs are never evicted.
+//
+// Each book is also cut to a number of levels per side as it is admitted:
+// BOOK_LEVELS_BY_PAIR per normalized pair or base asset, BOOK_MAX_LEVELS for the
+// rest. Deep books where sizing needs them, a few levels for the long tail, and
+// the depth walks of the analysis stay bounded either way.
+
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
+use std::sync::atomic::Ordering;
+
+use log::info;
+
+use crate::metrics::Metrics;
+use crate::{config, OrderBook, SpreadAnalyzer};
+
+#[derive(Debug, Clone, Default)]
+pub struct BookBudget {
+    // 0 means unlimited
+    pub max_books: usize,
+    pub max_bytes: usize,
+    // Normalized pairs that are always kept, e.g. the ones we actually trade
+    pub pinned_pairs: HashSet<String>,
+    // Levels kept per side; 0 keeps them all
+    pub max_levels: usize,
+    // By normalized pair (`BTC/USDT`) or base asset (`BTC`), over `max_levels`
+    pub levels_by_pair: HashMap<String, usize>,
+}
+
+impl BookBudget {
+    pub fn from_env() -> Self {
+        BookBudget {
+            max_books: config::env_or("BOOK_CACHE_MAX_BOOKS", 0),
+            max_bytes: config::env_or::<usize>("BOOK_CACHE_MAX_MB", 0) * 1024 * 1024,
+            pinned_pairs: config::env_var("BOOK_CACHE_PINNED_PAIRS")
+                .map(|raw| crate::subscription::split_list(&raw).into_iter().collect())
+                .unwrap_or_default(),
+            max_levels: config::env_or("BOOK_MAX_LEVELS", 0),
+            levels_by_pair: config::env_map("BOOK_LEVELS_BY_PAIR"),
+        }
+    }
+
+    /// Levels per side kept for books of normalized `pair`, None for all of them
+    pub fn levels_for(&self, pair: &str) -> Option<usize> {
+        let base = pair.split('/').next().unwrap_or(pair);
+        let levels = self.levels_by_pair.get(pair).or_else(|| self.levels_by_pair.get(base)).copied().unwrap_or(self.max_levels);
+        (levels > 0).then_some(levels)
+    }
+
+    fn exceeded(&self, books: usize, bytes: usize) -> bool {
+        (self.max_books > 0 && books > self.max_books) || (self.max_bytes > 0 && bytes > self.max_bytes)
+    }
+}
+
+/// Rough heap + inline footprint of a cached book, good enough for budgeting
+pub fn estimated_size(key: &str, book: &OrderBook) -> usize {
+    let levels: usize = book
+        .bids
+        .iter()
+        .chain(book.asks.iter())
+        .map(|level| size_of::<Vec<f64>>() + level.capacity() * size_of::<f64>())
+        .sum();
+    size_of::<OrderBook>() + key.len() + book.exchange.len() + book.pair.len() + levels
+}
+
+impl SpreadAnalyzer {
+    /// Cut an incoming book to the levels configured for its pair; levels are best first
+    pub fn trim_book_levels(&self, book: &mut OrderBook) {
+        let Some(levels) = self.book_budget.levels_for(&book.pair.replace("WBTC", "BTC")) else { return };
+        let trimmed = book.bids.len().saturating_sub(levels) + book.asks.len().saturating_sub(levels);
+        if trimmed > 0 {
+            book.bids.truncate(levels);
+            book.asks.truncate(levels);
+            self.metrics.book_levels_trimmed.fetch_add(trimmed as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Evict least-recently-updated books until the cache fits the budget again.
+    /// `keep` is the book that was just inserted and is never evicted.
+    pub fn enforce_book_budget(&mut self, keep: &str) {
+        let mut bytes: usize = self.books.iter().map(|(key, book)| estimated_size(key, book)).sum();
+
+        while self.book_budget.exceeded(self.books.len(), bytes) {
+            let victim = self
+                .books
+                .iter()
+                .filter(|(key, book)| {
+                    key.as_str() != keep && !self.book_budget.pinned_pairs.contains(&book.pair.replace("WBTC", "BTC"))
+                })
+                .min_by_key(|(_, book)| book.received_at)
+                .map(|(key, _)| key.clone());
+
+            // Everything left is pinned or just arrived
+            let Some(victim) = victim else { break };
+            if let Some(book) = self.books.remove(&victim) {
+                bytes -= estimated_size(&victim, &book);
+                Metrics::inc(&self.metrics.book_cache_evictions);
+                info!("Evicted orderbook {} to stay within the cache budget", victim);
+            }
+        }
+
+        self.metrics.book_cache_entries.store(self.books.len() as u64, Ordering::Relaxed);
+        self.metrics.book_cache_bytes.store(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    fn book_at(exchange: &str, pair: &str, seconds_ago: i64) -> OrderBook {
+        let mut book = OrderBook::for_test(exchange, pair, vec![vec![1.0, 1.0]], vec![vec![1.1, 1.0]]);
+        book.received_at = Some(Utc::now() - Duration::seconds(seconds_ago));
+        book
+    }
+
+    #[test]
+    fn evicts_least_recently_updated_but_not_pinned() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.book_budget.max_books = 2;
+        analyzer.book_budget.pinned_pairs.insert("BTC/USDT".to_string());
+
+        analyzer.books.insert("binance:WBTC/USDT".to_string(), book_at("binance", "WBTC/USDT", 300));
+        analyzer.books.insert("binance:PEPE/USDT".to_string(), book_at("binance", "PEPE/USDT", 200));
+        analyzer.books.insert("binance:DOGE/USDT".to_string(), book_at("binance", "DOGE/USDT", 100));
+        analyzer.books.insert("binance:SHIB/USDT".to_string(), book_at("binance", "SHIB/USDT", 0));
+        analyzer.enforce_book_budget("binance:SHIB/USDT");
+
+        let mut kept: Vec<&String> = analyzer.books.keys().collect();
+        kept.sort();
+        assert_eq!(kept, ["binance:SHIB/USDT", "binance:WBTC/USDT"]);
+        assert_eq!(analyzer.metrics.book_cache_evictions.load(Ordering::Relaxed), 2);
+        assert_eq!(analyzer.metrics.book_cache_entries.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn books_keep_the_levels_configured_for_their_pair() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.book_budget.max_levels = 2;
+        analyzer.book_budget.levels_by_pair = HashMap::from([("BTC".to_string(), 4), ("BTC/USDC".to_string(), 0)]);
+        assert_eq!(analyzer.book_budget.levels_for("PEPE/USDT"), Some(2));
+        assert_eq!(analyzer.book_budget.levels_for("BTC/USDT"), Some(4));
+        assert_eq!(analyzer.book_budget.levels_for("BTC/USDC"), None);
+
+        let deep = |pair: &str| {
+            let mut book = book_at("binance", pair, 0);
+            book.bids = (0..10).map(|i| vec![1.0 - i as f64 * 0.01, 1.0]).collect();
+            book.asks = (0..3).map(|i| vec![1.1 + i as f64 * 0.01, 1.0]).collect();
+            book
+        };
+        let mut wbtc = deep("WBTC/USDT");
+        analyzer.trim_book_levels(&mut wbtc);
+        assert_eq!((wbtc.bids.len(), wbtc.asks.len()), (4, 3));
+        assert_eq!(wbtc.bids[3], vec![0.97, 1.0]);
+        let mut pepe = deep("PEPE/USDT");
+        analyzer.trim_book_levels(&mut pepe);
+        assert_eq!((pepe.bids.len(), pepe.asks.len()), (2, 2));
+        assert_eq!(analyzer.metrics.book_levels_trimmed.load(Ordering::Relaxed), 6 + 8 + 1);
+    }
+
+    #[test]
+    fn byte_budget_counts_levels() {
+        let shallow = book_at("binance", "BTC/USDT", 0);
+        let mut deep = shallow.clone();
+        deep.bids = vec![vec![1.0, 1.0]; 100];
+        assert!(estimated_size("k", &deep) > estimated_size("k", &shallow) + 100 * 2 * size_of::<f64>());
+    }
+}
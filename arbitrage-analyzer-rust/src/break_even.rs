@@ -0,0 +1,261 @@
+// Per-route spread history and break-even spread calculation.
+// Every update records the top-of-book spread of each route in the updated pair;
+// periodically we price a typical trade on each route with the live fee model and
+// overlay that break-even level on the recorded spread distribution.
+
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::Serialize;
+
+use crate::export::SpreadRow;
+use crate::lifecycle::RouteKey;
+use crate::route_yield::YieldEstimate;
+use crate::{numeric, SpreadAnalyzer};
+
+// Spread samples kept per route
+const HISTORY_PER_ROUTE: usize = 2000;
+// Histogram layout in basis points; anything outside lands in the edge buckets
+const HISTOGRAM_BUCKET_BPS: f64 = 5.0;
+const HISTOGRAM_MIN_BPS: f64 = -50.0;
+const HISTOGRAM_MAX_BPS: f64 = 100.0;
+
+#[derive(Debug, Clone, Copy)]
+struct SpreadSample {
+    spread_bps: f64,
+    executable_size: f64,
+    buy_price: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct SpreadHistory {
+    routes: HashMap<RouteKey, VecDeque<SpreadSample>>,
+}
+
+impl SpreadHistory {
+    fn record(&mut self, route: RouteKey, sample: SpreadSample) {
+        let samples = self.routes.entry(route).or_default();
+        samples.push_back(sample);
+        while samples.len() > HISTORY_PER_ROUTE {
+            samples.pop_front();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistogramBucket {
+    lower_bps: f64,
+    upper_bps: f64,
+    count: usize,
+    // True when the whole bucket clears the break-even spread
+    above_break_even: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakEvenReport {
+    pub route: RouteKey,
+    pub computed_at: DateTime<Utc>,
+    pub typical_size: f64,
+    pub reference_price: f64,
+    pub break_even_bps: f64,
+    pub samples: usize,
+    pub median_spread_bps: f64,
+    pub p90_spread_bps: f64,
+    pub max_spread_bps: f64,
+    // Share of recorded spreads that would have covered costs at the typical size
+    pub profitable_share: f64,
+    pub histogram: Vec<HistogramBucket>,
+    // Set once the route has produced opportunities within the yield window
+    pub yield_estimate: Option<YieldEstimate>,
+}
+
+fn histogram(spreads: &[f64], break_even_bps: f64) -> Vec<HistogramBucket> {
+    let bucket_count = ((HISTOGRAM_MAX_BPS - HISTOGRAM_MIN_BPS) / HISTOGRAM_BUCKET_BPS) as usize;
+    let mut buckets: Vec<HistogramBucket> = (0..bucket_count)
+        .map(|idx| {
+            let lower_bps = HISTOGRAM_MIN_BPS + idx as f64 * HISTOGRAM_BUCKET_BPS;
+            HistogramBucket {
+                lower_bps,
+                upper_bps: lower_bps + HISTOGRAM_BUCKET_BPS,
+                count: 0,
+                above_break_even: lower_bps >= break_even_bps,
+            }
+        })
+        .collect();
+
+    for spread in spreads {
+        let idx = ((spread - HISTOGRAM_MIN_BPS) / HISTOGRAM_BUCKET_BPS).floor().max(0.0) as usize;
+        buckets[idx.min(bucket_count - 1)].count += 1;
+    }
+    buckets
+}
+
+impl SpreadAnalyzer {
+    /// Record the current top-of-book spread for every route in `normalized_pair`
+    pub fn record_spreads(&mut self, normalized_pair: &str) {
+        let books: Vec<_> = self
+            .books
+            .values()
+            .filter(|book| book.pair.replace("WBTC", "BTC") == normalized_pair)
+            .collect();
+
+        let mut samples: Vec<(RouteKey, SpreadSample)> = Vec::new();
+        for buy_book in &books {
+            for sell_book in &books {
+                if buy_book.exchange == sell_book.exchange {
+                    continue;
+                }
+                let (Some((ask_price, ask_size)), Some((bid_price, bid_size))) = (buy_book.best_ask(), sell_book.best_bid()) else {
+                    continue;
+                };
+                let Some(spread_bps) = numeric::safe_div(bid_price - ask_price, ask_price).map(|r| r * 10_000.0) else {
+                    continue;
+                };
+                if !numeric::all_finite(&[ask_size, bid_size]) {
+                    continue;
+                }
+
+                samples.push((
+                    RouteKey::new(normalized_pair, &buy_book.exchange, &sell_book.exchange),
+                    SpreadSample { spread_bps, executable_size: ask_size.min(bid_size), buy_price: ask_price },
+                ));
+            }
+        }
+
+        let now = Utc::now();
+        for (route, sample) in samples {
+            self.exporter.push_spread(SpreadRow {
+                recorded_at: now,
+                route: route.clone(),
+                spread_bps: sample.spread_bps,
+                executable_size: sample.executable_size,
+                buy_price: sample.buy_price,
+            });
+            self.competition.observe_spread(&route, sample.spread_bps, now);
+            self.spread_history.record(route, sample);
+        }
+    }
+
+    /// Recompute break-even spreads for every route with recorded history
+    pub fn refresh_break_even(&mut self) {
+        let now = Utc::now();
+        let mut reports: Vec<BreakEvenReport> = Vec::new();
+
+        for (route, samples) in &self.spread_history.routes {
+            if samples.is_empty() {
+                continue;
+            }
+
+            let mut spreads: Vec<f64> = samples.iter().map(|s| s.spread_bps).collect();
+            let sizes: Vec<f64> = samples.iter().map(|s| s.executable_size).collect();
+            let prices: Vec<f64> = samples.iter().map(|s| s.buy_price).collect();
+
+            // Typical trade: the median displayed size, sized the same way live opportunities are
+            let Some(median_size) = numeric::percentile(&sizes, 50.0) else { continue };
+            let Some(reference_price) = numeric::percentile(&prices, 50.0) else { continue };
+            let typical_size = self.choose_execution_size(
+                median_size,
+                median_size,
+                &route.pair,
+                &route.buy_exchange,
+                &route.sell_exchange,
+                reference_price,
+            );
+            if typical_size <= 0.0 {
+                continue;
+            }
+
+            let fees = self.estimate_fees_and_gas(
+                typical_size,
+                reference_price,
+                reference_price,
+                &route.buy_exchange,
+                &route.sell_exchange,
+                &route.pair,
+            );
+            let Some(break_even_bps) = numeric::safe_div(fees.total, reference_price * typical_size).map(|r| r * 10_000.0) else {
+                continue;
+            };
+
+            spreads.sort_by(|a, b| a.total_cmp(b));
+            let profitable = spreads.iter().filter(|s| **s >= break_even_bps).count();
+
+            reports.push(BreakEvenReport {
+                route: route.clone(),
+                computed_at: now,
+                typical_size,
+                reference_price,
+                break_even_bps,
+                samples: spreads.len(),
+                median_spread_bps: numeric::percentile(&spreads, 50.0).unwrap_or(0.0),
+                p90_spread_bps: numeric::percentile(&spreads, 90.0).unwrap_or(0.0),
+                max_spread_bps: spreads.last().copied().unwrap_or(0.0),
+                profitable_share: numeric::safe_div(profitable as f64, spreads.len() as f64).unwrap_or(0.0),
+                histogram: histogram(&spreads, break_even_bps),
+                yield_estimate: self.route_yields.estimate(route, now),
+            });
+        }
+
+        reports.sort_by(|a, b| a.break_even_bps.total_cmp(&b.break_even_bps));
+        for report in &reports {
+            info!(
+                "Break-even {}: {:.1} bps at size {:.4} (median spread {:.1} bps, {:.1}% of {} samples profitable)",
+                report.route,
+                report.break_even_bps,
+                report.typical_size,
+                report.median_spread_bps,
+                report.profitable_share * 100.0,
+                report.samples
+            );
+            if let Some(estimate) = &report.yield_estimate {
+                info!(
+                    "  Yield {}: {:.1}% annualized ({:.1} triggers/day, ${:.2} avg net on {:.2} capital)",
+                    report.route, estimate.annualized_yield_pct, estimate.triggers_per_day, estimate.avg_net_profit, estimate.avg_capital
+                );
+            }
+        }
+        self.break_even_reports = reports;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OrderBook;
+
+    fn book(exchange: &str, bid: f64, ask: f64) -> OrderBook {
+        OrderBook::for_test(exchange, "BTC/USDT", vec![vec![bid, 1.0]], vec![vec![ask, 1.0]])
+    }
+
+    #[test]
+    fn histogram_clamps_outliers_into_edge_buckets() {
+        let buckets = histogram(&[-500.0, 0.0, 2.0, 500.0], 10.0);
+        assert_eq!(buckets.first().map(|b| b.count), Some(1));
+        assert_eq!(buckets.last().map(|b| b.count), Some(1));
+        assert_eq!(buckets.iter().map(|b| b.count).sum::<usize>(), 4);
+        assert!(buckets.iter().filter(|b| b.above_break_even).all(|b| b.lower_bps >= 10.0));
+    }
+
+    #[test]
+    fn break_even_reported_for_both_directions() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.books.insert("binance:BTC/USDT".to_string(), book("binance", 49990.0, 50000.0));
+        analyzer.books.insert("uniswap-v3-exact:BTC/USDT".to_string(), book("uniswap-v3-exact", 50100.0, 50110.0));
+        analyzer.record_spreads("BTC/USDT");
+        analyzer.refresh_break_even();
+
+        assert_eq!(analyzer.break_even_reports.len(), 2);
+        for report in &analyzer.break_even_reports {
+            assert!(report.break_even_bps > 0.0);
+            assert_eq!(report.samples, 1);
+        }
+        // Buying on binance at 50000 and selling into 50100 is a +20 bps spread
+        let forward = analyzer
+            .break_even_reports
+            .iter()
+            .find(|r| r.route.buy_exchange == "binance")
+            .expect("binance→uniswap route");
+        assert!((forward.median_spread_bps - 20.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,86 @@
+// What is running: the commit and time the binary was built (see build.rs), the
+// cargo features compiled in, and a hash of the configuration it read. Served on
+// `GET /buildinfo` and added as labels to every metric, so a change in behavior
+// on a dashboard can be lined up with the deployment that caused it.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::config;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub build_time: DateTime<Utc>,
+    pub features: Vec<&'static str>,
+    // See `config::config_hash`; taken once the analyzer is configured
+    pub config_hash: String,
+}
+
+/// Optional cargo features this binary was built with
+pub fn enabled_features() -> Vec<&'static str> {
+    [
+        ("simd-json", cfg!(feature = "simd-json")),
+        ("postgres", cfg!(feature = "postgres")),
+        ("parquet", cfg!(feature = "parquet")),
+        ("binance-ws", cfg!(feature = "binance-ws")),
+        ("venue-ws", cfg!(feature = "venue-ws")),
+        ("chaos", cfg!(feature = "chaos")),
+        ("grpc", cfg!(feature = "grpc")),
+        ("venue-plugins", cfg!(feature = "venue-plugins")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        let epoch_secs: i64 = env!("BUILD_EPOCH_SECS").parse().unwrap_or(0);
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("BUILD_GIT_SHA"),
+            build_time: DateTime::from_timestamp(epoch_secs, 0).unwrap_or_default(),
+            features: enabled_features(),
+            config_hash: config::config_hash(),
+        }
+    }
+
+    /// Twelve hex digits tell deployments apart and keep the metric series readable
+    pub fn short_config_hash(&self) -> &str {
+        &self.config_hash[..self.config_hash.len().min(12)]
+    }
+
+    /// Prometheus labels, `{version="..",git_sha="..",...}`
+    pub fn labels(&self) -> String {
+        let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+        format!(
+            "{{version=\"{}\",git_sha=\"{}\",build_time=\"{}\",features=\"{}\",config_hash=\"{}\"}}",
+            escape(self.version),
+            escape(self.git_sha),
+            self.build_time.to_rfc3339(),
+            self.features.join(","),
+            self.short_config_hash()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configuration_changes_the_hash() {
+        let before = BuildInfo::current();
+        assert_eq!(before.git_sha, env!("BUILD_GIT_SHA"));
+        assert_eq!(before.config_hash.len(), 64);
+
+        std::env::set_var("BUILDINFO_TEST_SETTING", "1");
+        let _ = config::env_var("BUILDINFO_TEST_SETTING");
+        let after = BuildInfo::current();
+        assert_ne!(before.config_hash, after.config_hash);
+        assert!(after.labels().starts_with(&format!("{{version=\"{}\",git_sha=\"{}\"", after.version, after.git_sha)));
+        assert!(after.labels().ends_with(&format!("config_hash=\"{}\"}}", after.short_config_hash())));
+    }
+}
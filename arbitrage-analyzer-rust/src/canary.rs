@@ -0,0 +1,264 @@
+// Canary venues. With CANARY_VENUES=true, a venue that hasn't been vetted is put
+// in canary the first time one of its books arrives. Its books are cached and
+// analyzed, and the opportunities it takes part in are logged, scored, recorded
+// and published as usual, but the `canary` pre-trade check blocks every
+// execution request with a leg on it until an operator promotes it:
+//   {"command":"promote_venue","venue":"kraken","actor":"ops"}
+//   {"command":"demote_venue","venue":"kraken","reason":"bad fills","actor":"ops"}
+// Vetted venues are CANARY_TRUSTED_VENUES plus the ones promoted since, less the
+// ones demoted. Promotions and demotions are written to CANARY_STATE_FILE
+// (default `swapsleuth-venues.json`) and read back on startup, so a restart
+// neither re-trusts a demoted venue nor forgets a promoted one. A venue going
+// into canary is logged and raised as a `venue_canary` event.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::Severity;
+use crate::events::{Event, EventClass};
+use crate::{config, ArbitrageOpportunity, SpreadAnalyzer};
+
+pub const DEFAULT_STATE_FILE: &str = "swapsleuth-venues.json";
+
+/// An operator's decision on a venue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Vetting {
+    pub trusted: bool,
+    pub actor: String,
+    pub at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// What a venue in canary has shown so far
+#[derive(Debug, Clone, Serialize)]
+pub struct CanaryVenue {
+    pub since: DateTime<Utc>,
+    pub books: u64,
+    pub opportunities: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_roi_percentage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_opportunity_at: Option<DateTime<Utc>>,
+}
+
+impl CanaryVenue {
+    fn new(since: DateTime<Utc>) -> Self {
+        CanaryVenue { since, books: 0, opportunities: 0, best_roi_percentage: None, last_opportunity_at: None }
+    }
+}
+
+#[derive(Debug)]
+pub struct CanaryVenues {
+    pub enabled: bool,
+    trusted: BTreeSet<String>,
+    // Operator decisions, which win over CANARY_TRUSTED_VENUES
+    vettings: BTreeMap<String, Vetting>,
+    canary: BTreeMap<String, CanaryVenue>,
+    path: Option<PathBuf>,
+}
+
+fn load(path: &Path) -> BTreeMap<String, Vetting> {
+    match fs::read_to_string(path) {
+        Ok(raw) => serde_json::from_str(&raw).unwrap_or_else(|e| {
+            // Only trusted venues trade, so losing the promotions is the safe way to fail
+            error!("Canary state {} is unreadable ({}); only CANARY_TRUSTED_VENUES are vetted", path.display(), e);
+            BTreeMap::new()
+        }),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+impl CanaryVenues {
+    pub fn new(enabled: bool, trusted: impl IntoIterator<Item = String>, path: Option<PathBuf>) -> Self {
+        let vettings = path.as_deref().map(load).unwrap_or_default();
+        CanaryVenues { enabled, trusted: trusted.into_iter().collect(), vettings, canary: BTreeMap::new(), path }
+    }
+
+    pub fn from_env() -> Self {
+        let trusted = config::env_var("CANARY_TRUSTED_VENUES").unwrap_or_default();
+        Self::new(
+            config::env_or("CANARY_VENUES", false),
+            trusted.split(',').map(|venue| venue.trim().to_lowercase()).filter(|venue| !venue.is_empty()),
+            Some(PathBuf::from(config::env_var("CANARY_STATE_FILE").unwrap_or_else(|_| DEFAULT_STATE_FILE.to_string()))),
+        )
+    }
+
+    pub fn is_trusted(&self, venue: &str) -> bool {
+        let venue = venue.to_lowercase();
+        self.vettings.get(&venue).map_or_else(|| self.trusted.contains(&venue), |vetting| vetting.trusted)
+    }
+
+    pub fn in_canary(&self, venue: &str) -> bool {
+        self.enabled && !self.is_trusted(venue)
+    }
+
+    /// Note a book from `venue`; true when that put it in canary
+    pub fn observe_book(&mut self, venue: &str, now: DateTime<Utc>) -> bool {
+        if !self.in_canary(venue) {
+            return false;
+        }
+        let mut added = false;
+        let canary = self.canary.entry(venue.to_lowercase()).or_insert_with(|| {
+            added = true;
+            CanaryVenue::new(now)
+        });
+        canary.books += 1;
+        added
+    }
+
+    /// Note an opportunity with a leg on a venue in canary
+    pub fn observe_opportunity(&mut self, opp: &ArbitrageOpportunity, now: DateTime<Utc>) {
+        for venue in [&opp.buy_exchange, &opp.sell_exchange] {
+            if let Some(canary) = self.canary.get_mut(&venue.to_lowercase()) {
+                canary.opportunities += 1;
+                canary.best_roi_percentage = Some(canary.best_roi_percentage.map_or(opp.roi_percentage, |best| best.max(opp.roi_percentage)));
+                canary.last_opportunity_at = Some(now);
+                info!(
+                    "Canary {}: {} buy {} sell {} at {:.3}% ROI, ${:.2} net (not executed)",
+                    venue, opp.pair, opp.buy_exchange, opp.sell_exchange, opp.roi_percentage, opp.net_profit
+                );
+            }
+        }
+    }
+
+    fn vet(&mut self, venue: &str, vetting: Vetting) -> Result<()> {
+        let venue = venue.trim().to_lowercase();
+        if venue.is_empty() {
+            return Err(anyhow!("no venue given"));
+        }
+        if vetting.trusted {
+            self.canary.remove(&venue);
+        } else if self.enabled {
+            self.canary.entry(venue.clone()).or_insert_with(|| CanaryVenue::new(vetting.at));
+        }
+        self.vettings.insert(venue, vetting);
+        let Some(path) = &self.path else { return Ok(()) };
+        // Write then rename, so a crash mid-write can't leave a half-written file behind
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&self.vettings)?)?;
+        fs::rename(&tmp, path).map_err(|e| anyhow!("venue vetting not persisted to {}: {}", path.display(), e))
+    }
+
+    /// The venues in canary, and the operator decisions on record
+    pub fn report(&self) -> serde_json::Value {
+        serde_json::json!({
+            "enabled": self.enabled,
+            "canary": self.canary,
+            "trusted": self.trusted,
+            "vettings": self.vettings,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.canary.len()
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Put `venue` in canary if this is its first book and it isn't vetted
+    pub(crate) fn observe_canary_book(&mut self, venue: &str, now: DateTime<Utc>) {
+        if !self.canary.observe_book(venue, now) {
+            return;
+        }
+        self.metrics.canary_venues.store(self.canary.len() as u64, Ordering::Relaxed);
+        let message = format!("Venue {} is in canary: its books are analyzed, but nothing is executed on it until it is promoted", venue);
+        warn!("{}", message);
+        self.publish(Event::new(EventClass::VenueCanary, Severity::Warning, message).with_venue(venue));
+    }
+
+    pub(crate) fn promote_venue(&mut self, venue: &str, actor: &str) -> Result<()> {
+        let vetting = Vetting { trusted: true, actor: actor.to_string(), at: Utc::now(), reason: None };
+        let persisted = self.canary.vet(venue, vetting);
+        self.metrics.canary_venues.store(self.canary.len() as u64, Ordering::Relaxed);
+        info!("Venue {} promoted out of canary by {}", venue, actor);
+        persisted
+    }
+
+    pub(crate) fn demote_venue(&mut self, venue: &str, reason: Option<String>, actor: &str) -> Result<()> {
+        let vetting = Vetting { trusted: false, actor: actor.to_string(), at: Utc::now(), reason };
+        let persisted = self.canary.vet(venue, vetting);
+        self.metrics.canary_venues.store(self.canary.len() as u64, Ordering::Relaxed);
+        warn!("Venue {} put back in canary by {}", venue, actor);
+        persisted
+    }
+
+    /// The legs of `opp` on venues in canary
+    pub(crate) fn canary_legs<'a>(&self, opp: &'a ArbitrageOpportunity) -> Vec<&'a str> {
+        [opp.buy_exchange.as_str(), opp.sell_exchange.as_str()].into_iter().filter(|venue| self.canary.in_canary(venue)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::ControlCommand;
+
+    fn temp_venues() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("swapsleuth-venues-{}.json", uuid::Uuid::new_v4()))
+    }
+
+    fn canary_analyzer(path: &std::path::Path) -> SpreadAnalyzer {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.canary = CanaryVenues::new(true, ["binance".to_string()], Some(path.to_path_buf()));
+        analyzer
+    }
+
+    #[test]
+    fn unvetted_venues_are_tracked_in_canary() {
+        let path = temp_venues();
+        let mut analyzer = canary_analyzer(&path);
+        let now = Utc::now();
+        analyzer.observe_canary_book("binance", now);
+        analyzer.observe_canary_book("okx", now);
+        analyzer.observe_canary_book("okx", now);
+        assert_eq!(analyzer.metrics.canary_venues.load(Ordering::Relaxed), 1);
+        assert_eq!(analyzer.canary.canary["okx"].books, 2);
+
+        let opp = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        analyzer.canary.observe_opportunity(&opp, now);
+        assert_eq!(analyzer.canary.canary["okx"].opportunities, 1);
+        assert_eq!(analyzer.canary_legs(&opp), vec!["okx"]);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn canary_legs_are_refused_until_promoted() {
+        let path = temp_venues();
+        let mut analyzer = canary_analyzer(&path);
+        let now = Utc::now();
+        analyzer.observe_canary_book("okx", now);
+        let opp = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        let request = analyzer.execution_request(&opp, None, now);
+        assert_eq!(analyzer.run_pre_trade_checks(&request).map_err(|(check, _)| check), Err("canary"));
+
+        let command = serde_json::from_str::<ControlCommand>(r#"{"command":"promote_venue","venue":"OKX","actor":"ops"}"#).unwrap();
+        analyzer.apply_control_command(command, "control").unwrap();
+        assert!(analyzer.canary_legs(&opp).is_empty());
+        assert!(analyzer.run_pre_trade_checks(&request).is_ok());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn decisions_survive_a_restart() {
+        let path = temp_venues();
+        let mut analyzer = canary_analyzer(&path);
+        analyzer.observe_canary_book("okx", Utc::now());
+        analyzer.promote_venue("okx", "control:ops").unwrap();
+        analyzer.demote_venue("binance", Some("bad fills".to_string()), "control:ops").unwrap();
+
+        // Decisions win over CANARY_TRUSTED_VENUES
+        let restarted = CanaryVenues::new(true, ["binance".to_string()], Some(path.clone()));
+        assert!(restarted.is_trusted("okx"));
+        assert!(restarted.in_canary("binance"));
+        assert!(!CanaryVenues::new(false, [], None).in_canary("kraken"));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,104 @@
+// Capital at risk per route, which ROI and annualized ROI are computed against.
+// Two ways of running a route:
+//  - transfer: buy on one venue, move the base asset, sell on the other. Only the
+//    buy notional is committed, and it is tied up until the transfer lands
+//    (CAPITAL_TRANSFER_SECS).
+//  - pre-funded: both venues already hold inventory (`prefunded` on both accounts
+//    of the account profile), so both legs fill at once. Quote on the buy venue
+//    and base on the sell venue are committed, and stay committed until the
+//    inventory is rebalanced (INVENTORY_REBALANCE_SECS).
+// Capital is in the pair's quote asset, like prices and profit. It is also given
+// in USD when the quote asset has a known USD price (QUOTE_USD_PRICES; USD
+// stablecoins are 1), so routes quoted in different assets can be compared.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config, numeric};
+
+const DEFAULT_TRANSFER_SECS: f64 = 1_800.0;
+const DEFAULT_REBALANCE_SECS: f64 = 86_400.0;
+pub const SECONDS_PER_YEAR: f64 = 365.0 * 86_400.0;
+pub const USD_STABLECOINS: [&str; 5] = ["USD", "USDT", "USDC", "DAI", "BUSD"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CapitalAtRisk {
+    // In the pair's quote asset
+    pub amount: f64,
+    pub amount_usd: Option<f64>,
+    // How long the capital stays committed per trade
+    pub lockup_secs: f64,
+    pub prefunded: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct CapitalConfig {
+    pub transfer_secs: f64,
+    pub rebalance_secs: f64,
+    // USD price per unit of a quote asset
+    pub quote_usd: HashMap<String, f64>,
+}
+
+impl Default for CapitalConfig {
+    fn default() -> Self {
+        CapitalConfig {
+            transfer_secs: DEFAULT_TRANSFER_SECS,
+            rebalance_secs: DEFAULT_REBALANCE_SECS,
+            quote_usd: USD_STABLECOINS.iter().map(|asset| (asset.to_string(), 1.0)).collect(),
+        }
+    }
+}
+
+impl CapitalConfig {
+    pub fn from_env() -> Self {
+        let mut config = CapitalConfig::default();
+        config.transfer_secs = config::env_or("CAPITAL_TRANSFER_SECS", config.transfer_secs);
+        config.rebalance_secs = config::env_or("INVENTORY_REBALANCE_SECS", config.rebalance_secs);
+        config.quote_usd.extend(config::env_map::<f64>("QUOTE_USD_PRICES").into_iter().map(|(asset, price)| (asset.to_uppercase(), price)));
+        config
+    }
+
+    /// How long a trade's capital stays committed
+    pub fn lockup_secs(&self, prefunded: bool) -> f64 {
+        if prefunded { self.rebalance_secs } else { self.transfer_secs }
+    }
+
+    /// Capital a trade of `size` base units on `pair` commits
+    pub fn capital_at_risk(&self, pair: &str, buy_price: f64, sell_price: f64, size: f64, prefunded: bool) -> CapitalAtRisk {
+        let buy_notional = buy_price * size;
+        let amount = if prefunded { buy_notional + sell_price * size } else { buy_notional };
+        let lockup_secs = self.lockup_secs(prefunded);
+        let quote = pair.split('/').nth(1).unwrap_or_default().to_uppercase();
+        CapitalAtRisk { amount, amount_usd: self.quote_usd.get(&quote).map(|price| amount * price), lockup_secs, prefunded }
+    }
+}
+
+/// Simple (non-compounding) annualization of a per-trade ROI earned every `lockup_secs`
+pub fn annualize(roi_percentage: f64, lockup_secs: f64) -> Option<f64> {
+    numeric::safe_div(roi_percentage * SECONDS_PER_YEAR, lockup_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefunded_routes_commit_both_legs_for_longer() {
+        let config = CapitalConfig { transfer_secs: 3_600.0, ..CapitalConfig::default() };
+        let transfer = config.capital_at_risk("BTC/USDT", 50_000.0, 50_500.0, 2.0, false);
+        assert_eq!((transfer.amount, transfer.amount_usd, transfer.lockup_secs), (100_000.0, Some(100_000.0), 3_600.0));
+        let prefunded = config.capital_at_risk("BTC/USDT", 50_000.0, 50_500.0, 2.0, true);
+        assert_eq!((prefunded.amount, prefunded.lockup_secs), (201_000.0, 86_400.0));
+
+        // Quoted in BTC: no USD figure until the quote asset has a price
+        assert_eq!(config.capital_at_risk("ETH/BTC", 0.05, 0.051, 10.0, false).amount_usd, None);
+        let priced = CapitalConfig { quote_usd: HashMap::from([("BTC".to_string(), 60_000.0)]), ..config };
+        let eth_btc = priced.capital_at_risk("ETH/BTC", 0.05, 0.051, 10.0, false);
+        assert!((eth_btc.amount_usd.unwrap() - 30_000.0).abs() < 1e-6);
+
+        // 0.1% per hour-long lockup is 876% a year
+        assert!((annualize(0.1, 3_600.0).unwrap() - 876.0).abs() < 1e-9);
+        assert_eq!(annualize(0.1, 0.0), None);
+    }
+}
@@ -0,0 +1,217 @@
+// Chaos layer for resilience testing, built with `--features chaos`. Incoming
+// messages (pub/sub notifications and venue websocket frames) pass through it
+// before anything parses them, and each one may be dropped, delayed, duplicated
+// or swapped with the next according to CHAOS_*_PROB. Decisions come from a PRNG
+// seeded with CHAOS_SEED, so a run with the same seed and the same input injects
+// the same faults; tests drive `Chaos` directly with their own clock.
+
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use crate::config;
+
+const DEFAULT_DELAY_MS: u64 = 500;
+const DEFAULT_SEED: u64 = 0x5eed;
+// How long the relay task waits for input when nothing is delayed
+const IDLE_WAIT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChaosConfig {
+    pub drop_prob: f64,
+    pub delay_prob: f64,
+    pub duplicate_prob: f64,
+    // A reordered message is held back until the next one has gone through
+    pub reorder_prob: f64,
+    // How long delayed messages are held, and the longest a reordered one waits for a successor
+    pub delay: Duration,
+    pub seed: u64,
+}
+
+impl ChaosConfig {
+    /// None when every probability is zero
+    pub fn from_env() -> Option<Self> {
+        let prob = |name: &str| {
+            let value: f64 = config::env_or(name, 0.0);
+            if !(0.0..=1.0).contains(&value) {
+                warn!("{} must be within 0-1, clamping {}", name, value);
+            }
+            value.clamp(0.0, 1.0)
+        };
+        let config = ChaosConfig {
+            drop_prob: prob("CHAOS_DROP_PROB"),
+            delay_prob: prob("CHAOS_DELAY_PROB"),
+            duplicate_prob: prob("CHAOS_DUPLICATE_PROB"),
+            reorder_prob: prob("CHAOS_REORDER_PROB"),
+            delay: Duration::from_millis(config::env_or("CHAOS_DELAY_MS", DEFAULT_DELAY_MS)),
+            seed: config::env_or("CHAOS_SEED", DEFAULT_SEED),
+        };
+        let enabled = [config.drop_prob, config.delay_prob, config.duplicate_prob, config.reorder_prob].iter().any(|p| *p > 0.0);
+        enabled.then_some(config)
+    }
+}
+
+// SplitMix64: tiny, seedable, and good enough to roll dice with
+#[derive(Debug)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_f64(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[derive(Debug)]
+pub struct Chaos<T> {
+    config: ChaosConfig,
+    rng: SplitMix64,
+    // Delayed messages with their release time, in arrival order
+    delayed: Vec<(Instant, T)>,
+    held: Option<(Instant, T)>,
+}
+
+impl<T: Clone> Chaos<T> {
+    pub fn new(config: ChaosConfig) -> Self {
+        Chaos { config, rng: SplitMix64(config.seed), delayed: Vec::new(), held: None }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        let config = ChaosConfig::from_env()?;
+        info!(
+            "  CHAOS enabled: drop {}, delay {} ({}ms), duplicate {}, reorder {}, seed {}",
+            config.drop_prob,
+            config.delay_prob,
+            config.delay.as_millis(),
+            config.duplicate_prob,
+            config.reorder_prob,
+            config.seed
+        );
+        Some(Chaos::new(config))
+    }
+
+    fn roll(&mut self, prob: f64) -> bool {
+        prob > 0.0 && self.rng.next_f64() < prob
+    }
+
+    /// Messages to deliver now that `item` has arrived, in delivery order
+    pub fn admit(&mut self, item: T, now: Instant) -> Vec<T> {
+        let mut ready = self.release_due(now);
+        if self.roll(self.config.drop_prob) {
+            debug!("chaos: dropped a message");
+            return ready;
+        }
+        if self.roll(self.config.delay_prob) {
+            debug!("chaos: delayed a message by {:?}", self.config.delay);
+            self.delayed.push((now + self.config.delay, item));
+            return ready;
+        }
+        // Only one message is held at a time; its successor goes out first
+        if self.held.is_none() && self.roll(self.config.reorder_prob) {
+            debug!("chaos: holding a message back behind the next one");
+            self.held = Some((now + self.config.delay, item));
+            return ready;
+        }
+        if self.roll(self.config.duplicate_prob) {
+            debug!("chaos: duplicated a message");
+            ready.push(item.clone());
+        }
+        ready.push(item);
+        ready.extend(self.held.take().map(|(_, held)| held));
+        ready
+    }
+
+    /// Delayed messages, and a held one with no successor, whose time has come
+    pub fn release_due(&mut self, now: Instant) -> Vec<T> {
+        let (due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed).into_iter().partition(|(at, _)| *at <= now);
+        self.delayed = waiting;
+        let mut ready: Vec<T> = due.into_iter().map(|(_, item)| item).collect();
+        if self.held.as_ref().is_some_and(|(at, _)| *at <= now) {
+            ready.extend(self.held.take().map(|(_, item)| item));
+        }
+        ready
+    }
+
+    /// When the next held-back message is due, if any
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.delayed.iter().map(|(at, _)| *at).chain(self.held.as_ref().map(|(at, _)| *at)).min()
+    }
+}
+
+/// Pass one received message through `chaos`, or straight through when it is disabled
+#[cfg(any(feature = "binance-ws", feature = "venue-ws"))]
+pub fn pass<T: Clone>(chaos: &mut Option<Chaos<T>>, item: T) -> Vec<T> {
+    match chaos {
+        Some(chaos) => chaos.admit(item, Instant::now()),
+        None => vec![item],
+    }
+}
+
+/// Relay `rx` through a chaos layer configured from the environment, on a task of the
+/// current runtime; `rx` itself when disabled
+pub fn wrap<T: Clone + Send + 'static>(mut rx: UnboundedReceiver<T>) -> UnboundedReceiver<T> {
+    let Some(mut chaos) = Chaos::from_env() else { return rx };
+    let (tx, relayed) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let wait = chaos.next_deadline().map_or(IDLE_WAIT, |at| at.saturating_duration_since(Instant::now()));
+            let ready = match tokio::time::timeout(wait, rx.recv()).await {
+                Ok(Some(item)) => chaos.admit(item, Instant::now()),
+                Err(_) => chaos.release_due(Instant::now()),
+                Ok(None) => return,
+            };
+            for item in ready {
+                if tx.send(item).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    relayed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ChaosConfig {
+        ChaosConfig { drop_prob: 0.0, delay_prob: 0.0, duplicate_prob: 0.0, reorder_prob: 0.0, delay: Duration::from_millis(100), seed: 7 }
+    }
+
+    fn run(config: ChaosConfig, items: impl IntoIterator<Item = u32>) -> Vec<u32> {
+        let mut chaos = Chaos::new(config);
+        let start = Instant::now();
+        let mut out: Vec<u32> = items.into_iter().flat_map(|item| chaos.admit(item, start)).collect();
+        out.extend(chaos.release_due(start + config.delay));
+        out
+    }
+
+    #[test]
+    fn each_fault_does_what_it_says() {
+        assert_eq!(run(config(), 0..4), vec![0, 1, 2, 3]);
+        assert!(run(ChaosConfig { drop_prob: 1.0, ..config() }, 0..4).is_empty());
+        assert_eq!(run(ChaosConfig { duplicate_prob: 1.0, ..config() }, 0..2), vec![0, 0, 1, 1]);
+        assert_eq!(run(ChaosConfig { reorder_prob: 1.0, ..config() }, 0..4), vec![1, 0, 3, 2]);
+
+        let mut chaos = Chaos::new(ChaosConfig { delay_prob: 1.0, ..config() });
+        let start = Instant::now();
+        assert!(chaos.admit(1, start).is_empty());
+        assert_eq!(chaos.next_deadline(), Some(start + Duration::from_millis(100)));
+        assert!(chaos.release_due(start + Duration::from_millis(99)).is_empty());
+        assert_eq!(chaos.release_due(start + Duration::from_millis(100)), vec![1]);
+    }
+
+    #[test]
+    fn same_seed_injects_the_same_faults() {
+        let mixed = ChaosConfig { drop_prob: 0.2, delay_prob: 0.1, duplicate_prob: 0.2, reorder_prob: 0.2, ..config() };
+        let first = run(mixed, 0..200);
+        assert_eq!(first, run(mixed, 0..200));
+        assert_ne!(first, run(ChaosConfig { seed: 8, ..mixed }, 0..200));
+        assert_ne!(first, (0..200).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,191 @@
+// Analysis counters that survive restarts. The number of books applied decides
+// when the next comprehensive pass runs (every COMPREHENSIVE_ANALYSIS_INTERVAL-th),
+// so without it every deploy would restart that cadence from zero. The counters
+// and the time of the last comprehensive pass are written to CHECKPOINT_FILE when
+// they changed, at most every CHECKPOINT_SAVE_SECS, and read back at startup. They
+// are served on `GET /health`. An empty CHECKPOINT_FILE disables the checkpoint.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, SpreadAnalyzer};
+
+pub const DEFAULT_CHECKPOINT_FILE: &str = "swapsleuth-checkpoint.json";
+const DEFAULT_SAVE_SECS: u64 = 10;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnalysisCounters {
+    // Books applied, across restarts
+    pub updates_applied: u64,
+    pub comprehensive_passes: u64,
+    pub last_comprehensive_at: Option<DateTime<Utc>>,
+    // A pass came due while shedding load and has not run yet
+    #[serde(default)]
+    pub comprehensive_pending: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CheckpointFile {
+    saved_at: DateTime<Utc>,
+    #[serde(flatten)]
+    counters: AnalysisCounters,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CheckpointStatus {
+    pub file: Option<PathBuf>,
+    // When the checkpoint restored at startup was written
+    pub restored_from: Option<DateTime<Utc>>,
+    pub last_saved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub struct Checkpoint {
+    // None disables the checkpoint
+    path: Option<PathBuf>,
+    interval: Duration,
+    last_saved: Option<Instant>,
+    // What is on disk, so unchanged counters are not rewritten
+    saved: AnalysisCounters,
+    status: CheckpointStatus,
+}
+
+fn save(path: &Path, counters: &AnalysisCounters, now: DateTime<Utc>) -> Result<()> {
+    // Write then rename, like the kill switch state, so a crash can't leave half a file
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(&CheckpointFile { saved_at: now, counters: counters.clone() })?)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+impl Checkpoint {
+    pub fn new(path: Option<PathBuf>, interval: Duration) -> Self {
+        Checkpoint {
+            status: CheckpointStatus { file: path.clone(), ..Default::default() },
+            path,
+            interval,
+            last_saved: None,
+            saved: AnalysisCounters::default(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let path = config::env_var("CHECKPOINT_FILE").unwrap_or_else(|_| DEFAULT_CHECKPOINT_FILE.to_string());
+        Checkpoint::new(
+            (!path.is_empty()).then(|| PathBuf::from(path)),
+            Duration::from_secs(config::env_or("CHECKPOINT_SAVE_SECS", DEFAULT_SAVE_SECS)),
+        )
+    }
+
+    pub fn status(&self) -> &CheckpointStatus {
+        &self.status
+    }
+
+    /// The counters last checkpointed, if there are any. An unreadable file starts from zero
+    pub fn load(&mut self) -> Option<AnalysisCounters> {
+        let path = self.path.as_ref()?;
+        let raw = fs::read_to_string(path).ok()?;
+        match serde_json::from_str::<CheckpointFile>(&raw) {
+            Ok(file) => {
+                self.saved = file.counters.clone();
+                self.status.restored_from = Some(file.saved_at);
+                Some(file.counters)
+            }
+            Err(e) => {
+                warn!("Ignoring unreadable checkpoint {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Write `counters` if they changed and the last write is at least the save interval old
+    pub fn save_if_due(&mut self, counters: &AnalysisCounters, now: Instant) {
+        let Some(path) = &self.path else { return };
+        if *counters == self.saved || self.last_saved.is_some_and(|last| now.duration_since(last) < self.interval) {
+            return;
+        }
+        self.last_saved = Some(now);
+        let saved_at = Utc::now();
+        match save(path, counters, saved_at) {
+            Ok(()) => {
+                self.saved = counters.clone();
+                self.status.last_saved_at = Some(saved_at);
+            }
+            Err(e) => warn!("Failed to write checkpoint {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Continue the counters of the previous run, if it left a checkpoint
+    pub fn restore_checkpoint(&mut self) {
+        let Some(counters) = self.checkpoint.load() else { return };
+        info!(
+            " Restored analysis counters: update #{}, {} comprehensive passes, last at {}",
+            counters.updates_applied,
+            counters.comprehensive_passes,
+            counters.last_comprehensive_at.map_or("-".to_string(), |at| at.to_rfc3339())
+        );
+        self.counters = counters;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_secs(10);
+
+    fn temp_checkpoint() -> PathBuf {
+        std::env::temp_dir().join(format!("swapsleuth-checkpoint-{}.json", uuid::Uuid::new_v4()))
+    }
+
+    fn counters() -> AnalysisCounters {
+        AnalysisCounters { updates_applied: 37, comprehensive_passes: 3, last_comprehensive_at: Some(Utc::now()), comprehensive_pending: false }
+    }
+
+    #[test]
+    fn counters_survive_a_restart() {
+        let path = temp_checkpoint();
+        let mut checkpoint = Checkpoint::new(Some(path.clone()), INTERVAL);
+        assert_eq!(checkpoint.load(), None);
+        checkpoint.save_if_due(&counters(), Instant::now());
+
+        let mut restarted = Checkpoint::new(Some(path.clone()), INTERVAL);
+        assert_eq!(restarted.load().map(|c| c.updates_applied), Some(37));
+        assert!(restarted.status().restored_from.is_some());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn saves_at_most_once_per_interval() {
+        let path = temp_checkpoint();
+        let start = Instant::now();
+        let mut checkpoint = Checkpoint::new(Some(path.clone()), INTERVAL);
+        let mut counters = counters();
+        checkpoint.save_if_due(&counters, start);
+        // Within the interval nothing is written
+        counters.updates_applied = 38;
+        checkpoint.save_if_due(&counters, start + Duration::from_secs(1));
+        assert_eq!(Checkpoint::new(Some(path.clone()), INTERVAL).load().map(|c| c.updates_applied), Some(37));
+
+        checkpoint.save_if_due(&counters, start + INTERVAL);
+        assert_eq!(Checkpoint::new(Some(path.clone()), INTERVAL).load(), Some(counters));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unreadable_or_disabled_checkpoints_start_fresh() {
+        let path = temp_checkpoint();
+        fs::write(&path, "{not json").unwrap();
+        assert_eq!(Checkpoint::new(Some(path.clone()), INTERVAL).load(), None);
+        fs::remove_file(path).unwrap();
+        assert_eq!(Checkpoint::new(None, INTERVAL).load(), None);
+    }
+}
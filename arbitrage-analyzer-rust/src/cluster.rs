@@ -0,0 +1,95 @@
+// Clustering of opportunities that are one market dislocation seen through
+// several pairs. BTC/USDT and WBTC/USDC bought on one venue and sold on another
+// draw on the same liquidity event, so publishing both would have the executor
+// spend it two or three times. Opportunities of one analysis pass are grouped by
+// canonical asset (WBTC is BTC, USD stablecoins are USD) and venue route. Only
+// the best of each cluster, by net profit, is published and executed; the others
+// are still recorded. Every opportunity in a cluster of more than one carries a
+// `cluster` annotation naming the representative. OPPORTUNITY_CLUSTERING=false
+// turns it off.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::capital::USD_STABLECOINS;
+use crate::ArbitrageOpportunity;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusterAnnotation {
+    // Canonical pair and route, e.g. `BTC/USD:binance>okx`
+    pub key: String,
+    // The member that is published and executed
+    pub representative_id: String,
+    // Pairs of all members, representative first
+    pub pairs: Vec<String>,
+}
+
+fn canonical_asset(asset: &str) -> String {
+    let asset = asset.to_uppercase().replace("WBTC", "BTC");
+    if USD_STABLECOINS.contains(&asset.as_str()) { "USD".to_string() } else { asset }
+}
+
+/// Cluster key of `opp`: canonical base/quote and its venue route
+pub fn cluster_key(opp: &ArbitrageOpportunity) -> String {
+    let mut assets = opp.pair.split('/');
+    let (base, quote) = (assets.next().unwrap_or_default(), assets.next().unwrap_or_default());
+    format!("{}/{}:{}>{}", canonical_asset(base), canonical_asset(quote), opp.buy_exchange, opp.sell_exchange)
+}
+
+/// Annotate the opportunities of one pass that share a cluster with another
+pub fn annotate(opportunities: &mut [ArbitrageOpportunity]) {
+    let mut clusters: HashMap<String, Vec<usize>> = HashMap::new();
+    for (idx, opp) in opportunities.iter().enumerate() {
+        clusters.entry(cluster_key(opp)).or_default().push(idx);
+    }
+
+    for (key, mut members) in clusters.into_iter().filter(|(_, members)| members.len() > 1) {
+        // Best net profit first; quotes are all the same canonical asset, so profits compare
+        members.sort_by(|a, b| opportunities[*b].net_profit.total_cmp(&opportunities[*a].net_profit).then(a.cmp(b)));
+        let annotation = ClusterAnnotation {
+            key,
+            representative_id: opportunities[members[0]].id.clone(),
+            pairs: members.iter().map(|idx| opportunities[*idx].pair.clone()).collect(),
+        };
+        for idx in members {
+            opportunities[idx].cluster = Some(annotation.clone());
+        }
+    }
+}
+
+/// Whether `opp` is published: it leads its cluster or isn't in one
+pub fn leads(opp: &ArbitrageOpportunity) -> bool {
+    opp.cluster.as_ref().is_none_or(|cluster| cluster.representative_id == opp.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpreadAnalyzer;
+
+    #[test]
+    fn correlated_pairs_on_one_route_publish_one_representative() {
+        let analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let price = |pair: &str, buy: &str, sell: &str, sell_price: f64| {
+            analyzer.evaluate_opportunity(buy, sell, pair, 50_000.0, sell_price, 1.0, 1.0).unwrap()
+        };
+        let mut opportunities = vec![
+            price("BTC/USDT", "binance", "okx", 50_800.0),
+            price("BTC/USDC", "binance", "okx", 51_000.0),
+            // Same assets, other route; and a route of its own
+            price("BTC/USDT", "okx", "bybit", 50_900.0),
+            price("BTC/ETH", "binance", "okx", 51_000.0),
+        ];
+        annotate(&mut opportunities);
+
+        let cluster = opportunities[0].cluster.clone().unwrap();
+        assert_eq!(cluster.key, "BTC/USD:binance>okx");
+        assert_eq!(cluster.representative_id, opportunities[1].id);
+        assert_eq!(cluster.pairs, vec!["BTC/USDC", "BTC/USDT"]);
+        assert_eq!(opportunities[1].cluster, Some(cluster));
+        assert!(opportunities[2].cluster.is_none() && opportunities[3].cluster.is_none());
+        assert_eq!(opportunities.iter().filter(|opp| leads(opp)).count(), 3);
+        assert!(!leads(&opportunities[0]));
+    }
+}
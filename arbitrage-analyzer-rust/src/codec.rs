@@ -0,0 +1,23 @@
+// Order book deserialization. serde_json is the default; building with
+// `--features simd-json` switches to simd-json. Which one is faster depends on the
+// CPU and book shape, so measure with the orderbook_decode bench before switching.
+// Kept free of crate types so the benchmark can include it directly.
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+
+/// Name of the active decoder, logged at startup
+pub const DECODER: &str = if cfg!(feature = "simd-json") { "simd-json" } else { "serde_json" };
+
+/// Decode a book from the raw JSON. Takes ownership because simd-json parses in place
+#[cfg(feature = "simd-json")]
+pub fn decode_book<T: DeserializeOwned>(json: String) -> Result<T> {
+    let mut bytes = json.into_bytes();
+    Ok(simd_json::serde::from_slice(&mut bytes)?)
+}
+
+/// Decode a book from the raw JSON. Takes ownership because simd-json parses in place
+#[cfg(not(feature = "simd-json"))]
+pub fn decode_book<T: DeserializeOwned>(json: String) -> Result<T> {
+    Ok(serde_json::from_str(&json)?)
+}
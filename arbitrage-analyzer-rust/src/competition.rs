@@ -0,0 +1,189 @@
+// Competition intensity per route. Two signals feed the estimate:
+//  - how quickly positive top-of-book spreads on the route have closed in the
+//    past (a spread that lasts milliseconds is being taken by someone faster),
+//  - pending swaps a mempool watcher has reported on either venue recently
+//    (`POST /competition/mempool`), i.e. other searchers already in flight.
+// Each signal maps to [0, 1]; the score combines the available ones so that
+// either signal alone can mark a route as contested.
+
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::lifecycle::RouteKey;
+use crate::{config, numeric};
+
+// Spread lifetimes kept per route
+const LIFETIMES_PER_ROUTE: usize = 200;
+const DEFAULT_REFERENCE_CLOSE_SECS: f64 = 5.0;
+const DEFAULT_MEMPOOL_WINDOW_SECS: i64 = 30;
+const DEFAULT_MEMPOOL_SATURATION: f64 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CompetitionEstimate {
+    // 0 = nobody else seems to trade this route, 1 = heavily contested
+    pub score: f64,
+    // Median lifetime of past positive spreads on the route
+    pub median_close_secs: Option<f64>,
+    pub closed_spreads: usize,
+    // Pending swaps seen on either venue within the mempool window
+    pub pending_swaps: u32,
+}
+
+#[derive(Debug)]
+pub struct CompetitionTracker {
+    // A spread lasting this long scores 0.5 on the closing-speed signal
+    reference_close_secs: f64,
+    mempool_window: Duration,
+    // Pending swaps at which the mempool signal saturates at 1
+    mempool_saturation: f64,
+    open_since: HashMap<RouteKey, DateTime<Utc>>,
+    lifetimes: HashMap<RouteKey, VecDeque<f64>>,
+    mempool: HashMap<String, VecDeque<(DateTime<Utc>, u32)>>,
+}
+
+impl CompetitionTracker {
+    pub fn new(reference_close_secs: f64, mempool_window: Duration, mempool_saturation: f64) -> Self {
+        CompetitionTracker {
+            reference_close_secs,
+            mempool_window,
+            mempool_saturation,
+            open_since: HashMap::new(),
+            lifetimes: HashMap::new(),
+            mempool: HashMap::new(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        CompetitionTracker::new(
+            config::env_or("COMPETITION_REFERENCE_CLOSE_SECS", DEFAULT_REFERENCE_CLOSE_SECS),
+            Duration::seconds(config::env_or("COMPETITION_MEMPOOL_WINDOW_SECS", DEFAULT_MEMPOOL_WINDOW_SECS)),
+            config::env_or("COMPETITION_MEMPOOL_SATURATION", DEFAULT_MEMPOOL_SATURATION),
+        )
+    }
+
+    /// Track when the top-of-book spread on `route` opens (positive) and closes again
+    pub fn observe_spread(&mut self, route: &RouteKey, spread_bps: f64, now: DateTime<Utc>) {
+        if spread_bps > 0.0 {
+            self.open_since.entry(route.clone()).or_insert(now);
+            return;
+        }
+        let Some(opened) = self.open_since.remove(route) else { return };
+        let lifetimes = self.lifetimes.entry(route.clone()).or_default();
+        lifetimes.push_back((now - opened).num_milliseconds() as f64 / 1000.0);
+        while lifetimes.len() > LIFETIMES_PER_ROUTE {
+            lifetimes.pop_front();
+        }
+    }
+
+    /// Record `pending_swaps` competing transactions seen by a mempool watcher on `venue`
+    pub fn observe_mempool(&mut self, venue: &str, pending_swaps: u32, now: DateTime<Utc>) {
+        let observations = self.mempool.entry(venue.to_string()).or_default();
+        observations.push_back((now, pending_swaps));
+        while observations.front().is_some_and(|(at, _)| now - *at > self.mempool_window) {
+            observations.pop_front();
+        }
+    }
+
+    fn pending_swaps(&self, venue: &str, now: DateTime<Utc>) -> u32 {
+        self.mempool
+            .get(venue)
+            .map(|observations| {
+                observations.iter().filter(|(at, _)| now - *at <= self.mempool_window).map(|(_, n)| *n).sum()
+            })
+            .unwrap_or(0)
+    }
+
+    /// The `pct` percentile of how long positive spreads on `route` lasted, with the number of them
+    pub fn persistence(&self, route: &RouteKey, pct: f64) -> (Option<f64>, usize) {
+        let lifetimes: Vec<f64> = self.lifetimes.get(route).map(|l| l.iter().copied().collect()).unwrap_or_default();
+        (numeric::percentile(&lifetimes, pct), lifetimes.len())
+    }
+
+    /// None until the route has a closed spread or a mempool observation to go on
+    pub fn estimate(&self, route: &RouteKey, now: DateTime<Utc>) -> Option<CompetitionEstimate> {
+        let (median_close_secs, closed_spreads) = self.persistence(route, 50.0);
+        let pending_swaps = self.pending_swaps(&route.buy_exchange, now) + self.pending_swaps(&route.sell_exchange, now);
+
+        let closing = median_close_secs
+            .and_then(|median| numeric::safe_div(self.reference_close_secs, self.reference_close_secs + median.max(0.0)));
+        let mempool = (pending_swaps > 0)
+            .then(|| numeric::safe_div(pending_swaps as f64, self.mempool_saturation).unwrap_or(1.0).min(1.0));
+
+        let score = match (closing, mempool) {
+            (None, None) => return None,
+            (Some(a), None) | (None, Some(a)) => a,
+            // Either signal alone is enough to call a route contested
+            (Some(a), Some(b)) => 1.0 - (1.0 - a) * (1.0 - b),
+        };
+        Some(CompetitionEstimate { score, median_close_secs, closed_spreads, pending_swaps })
+    }
+
+    /// Estimates for every route we know anything about, most contested first
+    pub fn all(&self, now: DateTime<Utc>) -> Vec<(RouteKey, CompetitionEstimate)> {
+        let mut routes: Vec<&RouteKey> = self.lifetimes.keys().chain(self.open_since.keys()).collect();
+        routes.sort();
+        routes.dedup();
+        let mut estimates: Vec<(RouteKey, CompetitionEstimate)> =
+            routes.into_iter().filter_map(|route| self.estimate(route, now).map(|e| (route.clone(), e))).collect();
+        estimates.sort_by(|a, b| numeric::cmp_desc(a.1.score, b.1.score));
+        estimates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route() -> RouteKey {
+        RouteKey::new("BTC/USDT", "binance", "uniswap-v3-exact")
+    }
+
+    fn tracker() -> CompetitionTracker {
+        CompetitionTracker::new(5.0, Duration::seconds(30), 4.0)
+    }
+
+    #[test]
+    fn fast_closing_spreads_score_high() {
+        let start = Utc::now();
+        let mut fast = tracker();
+        let mut slow = tracker();
+        for i in 0..5 {
+            let t = start + Duration::seconds(i * 100);
+            fast.observe_spread(&route(), 3.0, t);
+            fast.observe_spread(&route(), -1.0, t + Duration::milliseconds(500));
+            slow.observe_spread(&route(), 3.0, t);
+            slow.observe_spread(&route(), -1.0, t + Duration::seconds(60));
+        }
+
+        let fast = fast.estimate(&route(), start).unwrap();
+        let slow = slow.estimate(&route(), start).unwrap();
+        assert_eq!(fast.median_close_secs, Some(0.5));
+        assert_eq!(fast.closed_spreads, 5);
+        assert!(fast.score > 0.9 && slow.score < 0.1, "fast {} slow {}", fast.score, slow.score);
+    }
+
+    #[test]
+    fn unknown_routes_have_no_estimate() {
+        let mut tracker = tracker();
+        let now = Utc::now();
+        // Still open, nothing closed yet
+        tracker.observe_spread(&route(), 3.0, now);
+        assert!(tracker.estimate(&route(), now).is_none());
+        assert!(tracker.all(now).is_empty());
+    }
+
+    #[test]
+    fn mempool_observations_expire_and_saturate() {
+        let mut tracker = tracker();
+        let now = Utc::now();
+        tracker.observe_mempool("uniswap-v3-exact", 2, now - Duration::seconds(60));
+        tracker.observe_mempool("uniswap-v3-exact", 2, now);
+        let estimate = tracker.estimate(&route(), now).unwrap();
+        assert_eq!((estimate.pending_swaps, estimate.score), (2, 0.5));
+
+        tracker.observe_mempool("binance", 10, now);
+        assert_eq!(tracker.estimate(&route(), now).unwrap().score, 1.0);
+    }
+}
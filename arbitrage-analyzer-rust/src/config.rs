@@ -0,0 +1,125 @@
+// Environment-driven settings. Everything is optional and falls back to the
+// defaults baked into the analyzer, same as REDIS_ADDR/REDIS_PASS.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env::VarError;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use log::warn;
+
+// Every value ignored so far, for `doctor`
+static IGNORED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Every setting looked up so far, set or not, for the config hash in `/buildinfo`
+static READ: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+// Contents of the config files read so far, by path, for the same hash
+static FILES: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+fn ignore(name: &str, raw: &str) {
+    IGNORED.lock().unwrap_or_else(|e| e.into_inner()).push(format!("{}={:?}", name, raw));
+}
+
+/// Settings ignored as invalid by `env_or` and `env_map` so far
+pub fn ignored_settings() -> Vec<String> {
+    IGNORED.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// `std::env::var`, remembering that the analyzer reads `name`
+pub fn env_var(name: impl AsRef<str>) -> Result<String, VarError> {
+    let name = name.as_ref();
+    READ.lock().unwrap_or_else(|e| e.into_inner()).insert(name.to_string());
+    std::env::var(name)
+}
+
+/// Remember that the analyzer was configured from `contents` of the file at `path`
+pub fn remember_file(path: &std::path::Path, contents: &str) {
+    FILES.lock().unwrap_or_else(|e| e.into_inner()).insert(path.display().to_string(), contents.to_string());
+}
+
+// Settings carrying credentials: the hash is served unauthenticated and labels every metric
+const SECRET_SUFFIXES: [&str; 6] = ["PASS", "PASSWORD", "TOKEN", "SECRET", "SECRET_ACCESS_KEY", "API_KEY"];
+const SECRET_SETTINGS: [&str; 2] = ["DATABASE_URL", "ALERT_WEBHOOK_URL"];
+
+fn is_secret(name: &str) -> bool {
+    SECRET_SETTINGS.contains(&name) || SECRET_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+// What a set setting contributes to the hash: a secret only counts as set
+fn hashed_setting(name: &str, value: &str) -> String {
+    if is_secret(name) {
+        format!("{}=<secret>\n", name)
+    } else {
+        format!("{}={}\n", name, value)
+    }
+}
+
+/// SHA-256 over the name and value of every setting read so far that is set, and
+/// the contents of every config file read. Two processes that read the same
+/// configuration hash the same. Secrets (passwords, tokens, URLs with credentials)
+/// are hashed as set, never by value
+pub fn config_hash() -> String {
+    let names = READ.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let mut hasher = hmac_sha256::Hash::new();
+    for name in names {
+        if let Ok(value) = std::env::var(&name) {
+            hasher.update(hashed_setting(&name, &value));
+        }
+    }
+    for contents in FILES.lock().unwrap_or_else(|e| e.into_inner()).values() {
+        hasher.update(contents);
+    }
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parse `name` from the environment, falling back to `default` when unset or invalid
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env_var(name) {
+        Ok(raw) => match raw.trim().parse() {
+            Ok(value) => value,
+            Err(_) => {
+                warn!("Ignoring invalid value for {}: {:?}", name, raw);
+                ignore(name, &raw);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+/// Parse a `key:value,key:value` list from the environment. Invalid entries are skipped with a warning
+pub fn env_map<T: FromStr>(name: &str) -> HashMap<String, T> {
+    let mut map = HashMap::new();
+    let Ok(raw) = env_var(name) else {
+        return map;
+    };
+
+    for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.rsplit_once(':').map(|(k, v)| (k.trim(), v.trim().parse::<T>())) {
+            Some((key, Ok(value))) if !key.is_empty() => {
+                map.insert(key.to_string(), value);
+            }
+            _ => {
+                warn!("Ignoring invalid entry in {}: {:?}", name, entry);
+                ignore(name, entry);
+            }
+        }
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_hashed_as_set_not_by_value() {
+        for name in ["REDIS_PASS", "REDIS_SOURCE_CEX_PASS", "SMTP_PASSWORD", "KILL_SWITCH_RESET_TOKEN", "AWS_SECRET_ACCESS_KEY", "DATABASE_URL"] {
+            assert_eq!(hashed_setting(name, "hunter2"), hashed_setting(name, "other"), "{}", name);
+        }
+        assert_ne!(hashed_setting("MIN_PROFIT", "1"), hashed_setting("MIN_PROFIT", "2"));
+        // Paths of secret files are not secret
+        assert_ne!(hashed_setting("REDIS_PASS_FILE", "/a"), hashed_setting("REDIS_PASS_FILE", "/b"));
+    }
+}
@@ -0,0 +1,278 @@
+// Economics from a TOML file (`--config <path>` or SWAPSLEUTH_CONFIG): profit
+// thresholds, sizing caps, fee settings, withdrawal fees per asset and per-exchange
+// fee schedules. A venue listed under `[exchanges.<name>]` replaces the built-in
+// schedule of that name, or is added to the registry, so a new exchange is priced
+// without recompiling. Everything is optional; the file is applied over the
+// built-in defaults and the environment settings of the same values apply over
+// the file. See swapsleuth.example.toml.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::{config, FeeDenomination, SpreadAnalyzer, UnknownExchangePolicy};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    thresholds: ThresholdSettings,
+    #[serde(default)]
+    sizing: SizingSettings,
+    #[serde(default)]
+    fees: FeeSettings,
+    #[serde(default)]
+    exchanges: HashMap<String, ExchangeFees>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThresholdSettings {
+    min_profit: Option<f64>,
+    min_roi_percentage: Option<f64>,
+    cost_multiple: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SizingSettings {
+    max_usd_size: Option<f64>,
+    reference_price: Option<f64>,
+    min_depth_usd: Option<f64>,
+    min_depth_bps: Option<f64>,
+    // In base units, like PAIR_SIZE_CAPS / EXCHANGE_SIZE_CAPS
+    #[serde(default)]
+    pair_caps: HashMap<String, f64>,
+    #[serde(default)]
+    exchange_caps: HashMap<String, f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FeeSettings {
+    use_market_orders: Option<bool>,
+    ethereum_gas_cost: Option<f64>,
+    osmosis_tx_cost: Option<f64>,
+    ibc_transfer_cost: Option<f64>,
+    // `reject` or `default`, and the fee `default` prices unlisted venues with
+    unknown_exchange_policy: Option<String>,
+    unknown_exchange_fee: Option<f64>,
+    // In the base asset, keyed by symbol
+    #[serde(default)]
+    withdrawal_fees: HashMap<String, f64>,
+}
+
+/// Fee schedule of one venue from the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExchangeFees {
+    // Percentages; maker defaults to taker
+    pub taker_fee: f64,
+    pub maker_fee: Option<f64>,
+    // Per-trade cost independent of size (gas, ticket fees), in USD; unset keeps the
+    // built-in venue's, or none
+    pub fixed_cost: Option<f64>,
+    // `quote` or `received`
+    fee_denomination: Option<String>,
+}
+
+impl ExchangeFees {
+    pub fn trading_fee_pct(&self, taker: bool) -> f64 {
+        if taker {
+            self.taker_fee
+        } else {
+            self.maker_fee.unwrap_or(self.taker_fee)
+        }
+    }
+}
+
+fn check_non_negative<'a>(section: &str, values: impl IntoIterator<Item = (&'a str, Option<f64>)>) -> Result<()> {
+    for (name, value) in values {
+        if let Some(value) = value.filter(|v| !v.is_finite() || *v < 0.0) {
+            return Err(anyhow!("{}.{}: invalid value {}", section, name, value));
+        }
+    }
+    Ok(())
+}
+
+impl ConfigFile {
+    /// The file at `path`, or at SWAPSLEUTH_CONFIG when no path is given; None without either
+    pub fn load(path: Option<&Path>) -> Result<Option<(PathBuf, Self)>> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match config::env_var("SWAPSLEUTH_CONFIG") {
+                Ok(path) if !path.trim().is_empty() => PathBuf::from(path),
+                _ => return Ok(None),
+            },
+        };
+        let raw = fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+        config::remember_file(&path, &raw);
+        let file = Self::parse(&raw).with_context(|| format!("loading {}", path.display()))?;
+        Ok(Some((path, file)))
+    }
+
+    fn parse(raw: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(raw)?;
+        let thresholds = &file.thresholds;
+        check_non_negative(
+            "thresholds",
+            [
+                ("min_profit", thresholds.min_profit),
+                ("min_roi_percentage", thresholds.min_roi_percentage),
+                ("cost_multiple", thresholds.cost_multiple),
+            ],
+        )?;
+        let sizing = &file.sizing;
+        check_non_negative(
+            "sizing",
+            [
+                ("max_usd_size", sizing.max_usd_size),
+                ("reference_price", sizing.reference_price),
+                ("min_depth_usd", sizing.min_depth_usd),
+                ("min_depth_bps", sizing.min_depth_bps),
+            ],
+        )?;
+        check_non_negative("sizing.pair_caps", sizing.pair_caps.iter().map(|(k, v)| (k.as_str(), Some(*v))))?;
+        check_non_negative("sizing.exchange_caps", sizing.exchange_caps.iter().map(|(k, v)| (k.as_str(), Some(*v))))?;
+        let fees = &file.fees;
+        check_non_negative(
+            "fees",
+            [
+                ("ethereum_gas_cost", fees.ethereum_gas_cost),
+                ("osmosis_tx_cost", fees.osmosis_tx_cost),
+                ("ibc_transfer_cost", fees.ibc_transfer_cost),
+                ("unknown_exchange_fee", fees.unknown_exchange_fee),
+            ],
+        )?;
+        check_non_negative("fees.withdrawal_fees", fees.withdrawal_fees.iter().map(|(k, v)| (k.as_str(), Some(*v))))?;
+        for (name, exchange) in &file.exchanges {
+            check_non_negative(
+                &format!("exchanges.{}", name),
+                [("taker_fee", Some(exchange.taker_fee)), ("maker_fee", exchange.maker_fee), ("fixed_cost", exchange.fixed_cost)],
+            )?;
+        }
+        Ok(file)
+    }
+
+    /// Apply the file over the analyzer's current settings
+    pub fn apply(&self, analyzer: &mut SpreadAnalyzer) -> Result<()> {
+        analyzer.thresholds = analyzer
+            .thresholds
+            .with(self.thresholds.min_profit, self.thresholds.min_roi_percentage)?
+            .with_cost_multiple(self.thresholds.cost_multiple)?;
+
+        let sizing = &mut analyzer.sizing_config;
+        let settings = &self.sizing;
+        sizing.max_usd_size = settings.max_usd_size.unwrap_or(sizing.max_usd_size);
+        sizing.reference_price = settings.reference_price.unwrap_or(sizing.reference_price);
+        sizing.min_depth = settings.min_depth_usd.unwrap_or(sizing.min_depth);
+        sizing.min_depth_bps = settings.min_depth_bps.unwrap_or(sizing.min_depth_bps);
+        sizing.pair_caps.extend(settings.pair_caps.clone());
+        sizing.exchange_caps.extend(settings.exchange_caps.clone());
+
+        let fees = &mut analyzer.fees_config;
+        let settings = &self.fees;
+        fees.use_market_orders = settings.use_market_orders.unwrap_or(fees.use_market_orders);
+        fees.ethereum_gas_cost = settings.ethereum_gas_cost.unwrap_or(fees.ethereum_gas_cost);
+        fees.osmosis_tx_cost = settings.osmosis_tx_cost.unwrap_or(fees.osmosis_tx_cost);
+        fees.ibc_transfer_cost = settings.ibc_transfer_cost.unwrap_or(fees.ibc_transfer_cost);
+        fees.unknown_exchange_fee = settings.unknown_exchange_fee.unwrap_or(fees.unknown_exchange_fee);
+        if let Some(policy) = &settings.unknown_exchange_policy {
+            fees.unknown_exchange_policy = policy.parse::<UnknownExchangePolicy>().context("fees.unknown_exchange_policy")?;
+        }
+        fees.withdrawal_fees.extend(settings.withdrawal_fees.iter().map(|(asset, fee)| (asset.to_uppercase(), *fee)));
+
+        for (name, exchange) in &self.exchanges {
+            if let Some(denomination) = &exchange.fee_denomination {
+                let denomination = denomination.parse::<FeeDenomination>().with_context(|| format!("exchanges.{}.fee_denomination", name))?;
+                fees.fee_denominations.insert(name.clone(), denomination);
+            }
+            fees.exchange_schedules.insert(name.clone(), exchange.clone());
+        }
+        Ok(())
+    }
+
+    /// Venues the file lists a schedule for, for the startup log
+    pub fn exchanges(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.exchanges.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = r#"
+        [thresholds]
+        min_profit = 25.0
+
+        [sizing]
+        pair_caps = { "BTC/USDT" = 0.5 }
+
+        [fees]
+        unknown_exchange_policy = "reject"
+        withdrawal_fees = { btc = 0.0002 }
+
+        [exchanges.kraken]
+        taker_fee = 0.26
+        maker_fee = 0.16
+        fee_denomination = "quote"
+
+        [exchanges.binance]
+        taker_fee = 0.075
+        "#;
+
+    #[test]
+    fn invalid_files_are_refused() {
+        assert!(ConfigFile::parse("[exchanges.kraken]\ntaker_fee = -1.0\n").is_err());
+        // Keys in the wrong section are unknown there
+        assert!(ConfigFile::parse("[fees]\nmin_profit = 1.0\n").is_err());
+        let bad_policy = ConfigFile::parse("[fees]\nunknown_exchange_policy = \"sometimes\"\n").unwrap();
+        assert!(bad_policy.apply(&mut SpreadAnalyzer::new("127.0.0.1:6379").unwrap()).is_err());
+    }
+
+    #[test]
+    fn applies_over_the_defaults() {
+        let file = ConfigFile::parse(FILE).unwrap();
+        assert_eq!(file.exchanges(), vec!["binance", "kraken"]);
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        file.apply(&mut analyzer).unwrap();
+
+        assert_eq!(analyzer.thresholds.min_profit, 25.0);
+        // Left out of the file: the default stays
+        assert_eq!(analyzer.thresholds.min_roi_percentage, crate::MIN_ROI_PERCENTAGE);
+        assert_eq!(analyzer.fees_config.withdrawal_fees.get("BTC"), Some(&0.0002));
+        let opp = analyzer.evaluate_opportunity("binance", "kraken", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        assert_eq!(opp.max_size, 0.5);
+    }
+
+    #[test]
+    fn exchange_schedules_follow_the_order_type() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        ConfigFile::parse(FILE).unwrap().apply(&mut analyzer).unwrap();
+
+        assert_eq!(analyzer.fees_config.trading_fee_pct("binance"), 0.075);
+        assert_eq!(analyzer.fees_config.trading_fee_pct("kraken"), 0.26);
+        analyzer.fees_config.use_market_orders = false;
+        assert_eq!(analyzer.fees_config.trading_fee_pct("kraken"), 0.16);
+        // No maker fee listed: the taker fee
+        assert_eq!(analyzer.fees_config.trading_fee_pct("binance"), 0.075);
+    }
+
+    #[test]
+    fn listed_exchanges_are_priced_under_the_reject_policy() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        assert!(analyzer.evaluate_opportunity("binance", "kraken", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).is_some());
+        analyzer.fees_config.unknown_exchange_policy = UnknownExchangePolicy::Reject;
+        assert!(analyzer.evaluate_opportunity("binance", "kraken", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).is_none());
+
+        ConfigFile::parse(FILE).unwrap().apply(&mut analyzer).unwrap();
+        assert_eq!(analyzer.fees_config.unknown_exchange_policy, UnknownExchangePolicy::Reject);
+        assert!(analyzer.evaluate_opportunity("binance", "kraken", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).is_some());
+    }
+}
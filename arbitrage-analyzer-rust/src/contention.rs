@@ -0,0 +1,212 @@
+// Balance contention between execution requests. Two opportunities found at the
+// same time may both need the USDT on one venue; without knowing the balance the
+// analyzer would emit both and the executor would fail one of them halfway. With
+// VENUE_BALANCES (`venue.ASSET:amount`, e.g. `binance.USDT:50000,okx.BTC:2`) set,
+// every execution request reserves what its legs spend from those balances:
+// quote on the buy venue and, on pre-funded routes, base on the sell venue. The
+// reservation is held until the request reaches a terminal state or expires.
+//
+// Candidates are served in the order the analysis ranked them (ROI, best
+// first). One that doesn't fit in what is left is downsized to fit, and dropped
+// when the smaller trade no longer clears the profit thresholds. Assets without
+// a configured balance are not constrained.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{config, numeric, overrides, ArbitrageOpportunity, SpreadAnalyzer};
+
+// What one unit of base traded on a route takes from a venue balance
+#[derive(Debug, Clone, PartialEq)]
+pub struct Need {
+    pub venue: String,
+    pub asset: String,
+    pub per_unit: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceUse {
+    pub venue: String,
+    pub asset: String,
+    pub balance: f64,
+    pub reserved: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct BalanceLedger {
+    // By (venue, asset)
+    balances: HashMap<(String, String), f64>,
+    // (venue, asset, amount) held by each in-flight request
+    reservations: HashMap<String, Vec<(String, String, f64)>>,
+}
+
+/// What trading `opp` spends per unit of base
+pub fn needs(opp: &ArbitrageOpportunity) -> Vec<Need> {
+    let mut assets = opp.pair.split('/');
+    let (base, quote) = (assets.next().unwrap_or_default().to_uppercase(), assets.next().unwrap_or_default().to_uppercase());
+    let mut needs = vec![Need { venue: opp.buy_exchange.clone(), asset: quote, per_unit: opp.buy_price }];
+    if opp.capital_at_risk.is_some_and(|capital| capital.prefunded) {
+        needs.push(Need { venue: opp.sell_exchange.clone(), asset: base, per_unit: 1.0 });
+    }
+    needs
+}
+
+impl BalanceLedger {
+    pub fn new(balances: HashMap<(String, String), f64>) -> Self {
+        BalanceLedger { balances, reservations: HashMap::new() }
+    }
+
+    pub fn from_env() -> Self {
+        let mut balances = HashMap::new();
+        for (key, amount) in config::env_map::<f64>("VENUE_BALANCES") {
+            match key.split_once('.') {
+                Some((venue, asset)) => {
+                    balances.insert((venue.to_string(), asset.to_uppercase()), amount);
+                }
+                None => log::warn!("Ignoring VENUE_BALANCES entry {:?}: expected venue.ASSET", key),
+            }
+        }
+        BalanceLedger::new(balances)
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.balances.is_empty()
+    }
+
+    fn reserved(&self, venue: &str, asset: &str) -> f64 {
+        self.reservations.values().flatten().filter(|(v, a, _)| v == venue && a == asset).map(|(_, _, amount)| amount).sum()
+    }
+
+    /// Unreserved balance of `asset` on `venue`; None when it is not constrained
+    pub fn available(&self, venue: &str, asset: &str) -> Option<f64> {
+        let balance = self.balances.get(&(venue.to_string(), asset.to_string()))?;
+        Some((balance - self.reserved(venue, asset)).max(0.0))
+    }
+
+    /// The largest size up to `size` whose needs fit in the available balances
+    pub fn fitting_size(&self, needs: &[Need], size: f64) -> f64 {
+        needs
+            .iter()
+            .filter_map(|need| numeric::safe_div(self.available(&need.venue, &need.asset)?, need.per_unit))
+            .fold(size, f64::min)
+    }
+
+    pub fn reserve(&mut self, request_id: &str, needs: &[Need], size: f64) {
+        let claims: Vec<_> = needs
+            .iter()
+            .filter(|need| self.balances.contains_key(&(need.venue.clone(), need.asset.clone())))
+            .map(|need| (need.venue.clone(), need.asset.clone(), need.per_unit * size))
+            .collect();
+        if !claims.is_empty() {
+            self.reservations.insert(request_id.to_string(), claims);
+        }
+    }
+
+    pub fn release(&mut self, request_id: &str) {
+        self.reservations.remove(request_id);
+    }
+
+    pub fn report(&self) -> Vec<BalanceUse> {
+        let mut report: Vec<BalanceUse> = self
+            .balances
+            .iter()
+            .map(|((venue, asset), balance)| BalanceUse {
+                venue: venue.clone(),
+                asset: asset.clone(),
+                balance: *balance,
+                reserved: self.reserved(venue, asset),
+            })
+            .collect();
+        report.sort_by(|a, b| (&a.venue, &a.asset).cmp(&(&b.venue, &b.asset)));
+        report
+    }
+}
+
+impl SpreadAnalyzer {
+    /// `opp` repriced at another `size`, or None when that no longer clears the thresholds
+    pub fn reprice_at_size(&self, opp: &ArbitrageOpportunity, size: f64) -> Option<ArbitrageOpportunity> {
+        if !size.is_finite() || size <= 0.0 {
+            return None;
+        }
+        let estimate = self.estimate_fees_with(
+            &self.fees_config,
+            size,
+            opp.buy_price,
+            opp.sell_price,
+            &opp.buy_exchange,
+            &opp.sell_exchange,
+            &opp.pair,
+        );
+        let route_override = self.route_overrides.for_route(&opp.pair, &opp.buy_exchange, &opp.sell_exchange);
+        if route_override.is_some_and(|o| o.disabled) {
+            return None;
+        }
+        let estimated_fees = estimate.total + overrides::extra_cost(route_override, size * opp.buy_price);
+        let net_profit = opp.gross_profit_per_unit * size - estimated_fees;
+        let prefunded = opp.capital_at_risk.is_some_and(|capital| capital.prefunded);
+        let mut capital = self.capital_config.capital_at_risk(&opp.pair, opp.buy_price, opp.sell_price, size, prefunded);
+        capital.lockup_secs += self.fees_config.venue_models.settlement_secs(&opp.buy_exchange, &opp.sell_exchange);
+        let roi_percentage = numeric::safe_pct(net_profit, capital.amount)?;
+        let thresholds = self.thresholds;
+        if estimate.sell_size <= 0.0
+            || net_profit < overrides::min_profit(route_override, thresholds.min_profit)
+            || roi_percentage < thresholds.min_roi_percentage
+        {
+            return None;
+        }
+        Some(ArbitrageOpportunity {
+            max_size: size,
+            sell_size: estimate.sell_size,
+            estimated_fees,
+            net_profit,
+            roi_percentage,
+            capital_at_risk: Some(capital),
+            annualized_roi_percentage: crate::capital::annualize(roi_percentage, capital.lockup_secs),
+            ..opp.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // binance holds $75k USDT, which a BTC and an ETH opportunity both want
+    fn contended() -> (SpreadAnalyzer, ArbitrageOpportunity, ArbitrageOpportunity) {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.balances = BalanceLedger::new(HashMap::from([(("binance".to_string(), "USDT".to_string()), 75_000.0)]));
+        let btc = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        let eth = analyzer.evaluate_opportunity("binance", "bybit", "ETH/USDT", 2_500.0, 2_560.0, 20.0, 20.0).unwrap();
+        (analyzer, btc, eth)
+    }
+
+    #[test]
+    fn reservations_hold_a_venue_balance_until_released() {
+        let (mut analyzer, btc, _) = contended();
+        // 0.8 BTC ($40k) fits
+        let btc_needs = needs(&btc);
+        assert_eq!(btc_needs, vec![Need { venue: "binance".to_string(), asset: "USDT".to_string(), per_unit: 50_000.0 }]);
+        assert_eq!(analyzer.balances.fitting_size(&btc_needs, btc.max_size), btc.max_size);
+        analyzer.balances.reserve("r1", &btc_needs, btc.max_size);
+        assert_eq!(analyzer.balances.available("binance", "USDT"), Some(35_000.0));
+
+        analyzer.balances.release("r1");
+        assert_eq!(analyzer.balances.available("binance", "USDT"), Some(75_000.0));
+        assert_eq!(analyzer.balances.available("okx", "BTC"), None);
+    }
+
+    #[test]
+    fn competing_opportunities_are_downsized_to_what_is_left() {
+        let (mut analyzer, btc, eth) = contended();
+        analyzer.balances.reserve("r1", &needs(&btc), btc.max_size);
+        // 16 ETH ($40k) is downsized to the $35k left, repriced, and still worth trading
+        let fitting = analyzer.balances.fitting_size(&needs(&eth), eth.max_size);
+        assert_eq!(fitting, 14.0);
+        let downsized = analyzer.reprice_at_size(&eth, fitting).unwrap();
+        assert_eq!((downsized.id.as_str(), downsized.max_size), (eth.id.as_str(), 14.0));
+        assert!(downsized.net_profit < eth.net_profit);
+        // Too small a remainder is dropped
+        assert!(analyzer.reprice_at_size(&eth, 0.01).is_none());
+    }
+}
@@ -0,0 +1,338 @@
+// Operator commands over Redis pub/sub. A listener thread subscribes to
+// CONTROL_CHANNEL on the first source and hands parsed commands to the analyzer
+// loop, which applies them between updates like API requests. Messages are JSON
+// objects tagged by `command`, e.g.
+//   {"command":"kill_switch_trip","reason":"bad fills","actor":"ops"}
+//   {"command":"kill_switch_reset","token":"...","actor":"ops"}
+//   {"command":"set_pair_priority","pair":"BTC/USDT","priority":5,"actor":"ops"}
+//   {"command":"pause","reason":"venue incident","actor":"ops"}
+//   {"command":"resume","actor":"ops"}
+//   {"command":"set_thresholds","min_profit":5,"min_roi_percentage":0.2,"actor":"ops"}
+//   {"command":"set_thresholds","cost_multiple":1.5,"actor":"ops"}
+//   {"command":"promote_venue","venue":"kraken","actor":"ops"}
+//   {"command":"demote_venue","venue":"kraken","reason":"bad fills","actor":"ops"}
+//   {"command":"confirm_pair","pair":"PEPE/USDT","actor":"ops"}
+//   {"command":"block_pair","pair":"PEPE/USDT","reason":"illiquid","actor":"ops"}
+// A `set_pair_priority` without `priority` goes back to the learned priority.
+// Pausing stops publishing opportunities and execution requests while analysis
+// and recording go on; unlike the kill switch it needs no token to undo and
+// does not survive a restart. `set_thresholds` changes the minimum net profit
+// and ROI every route has to clear, leaving out either keeps it; both go back
+// to MIN_ABSOLUTE_PROFIT and MIN_ROI_PERCENTAGE on restart. A `cost_multiple`
+// replaces the minimum net profit with that multiple of each route's estimated
+// fees, 0 goes back to the fixed minimum. Promoting a venue
+// takes it out of canary, demoting puts it back (see canary.rs). Confirming or
+// blocking a pair settles its onboarding (see onboarding.rs).
+
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use redis::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::backoff::Backoff;
+use crate::metrics::Metrics;
+use crate::{SpreadAnalyzer, MIN_ABSOLUTE_PROFIT, MIN_ROI_PERCENTAGE};
+
+pub const DEFAULT_CONTROL_CHANNEL: &str = "swapsleuth_control";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlCommand {
+    KillSwitchTrip {
+        reason: Option<String>,
+        actor: Option<String>,
+    },
+    KillSwitchReset {
+        token: String,
+        actor: Option<String>,
+    },
+    SetPairPriority {
+        pair: String,
+        priority: Option<i64>,
+        actor: Option<String>,
+    },
+    Pause {
+        reason: Option<String>,
+        actor: Option<String>,
+    },
+    Resume {
+        actor: Option<String>,
+    },
+    SetThresholds {
+        min_profit: Option<f64>,
+        min_roi_percentage: Option<f64>,
+        #[serde(default)]
+        cost_multiple: Option<f64>,
+        actor: Option<String>,
+    },
+    PromoteVenue {
+        venue: String,
+        actor: Option<String>,
+    },
+    DemoteVenue {
+        venue: String,
+        reason: Option<String>,
+        actor: Option<String>,
+    },
+    ConfirmPair {
+        pair: String,
+        actor: Option<String>,
+    },
+    BlockPair {
+        pair: String,
+        reason: Option<String>,
+        actor: Option<String>,
+    },
+}
+
+/// Minimum net profit (USD) and ROI (%) every route has to clear
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Thresholds {
+    pub min_profit: f64,
+    pub min_roi_percentage: f64,
+    // PROFIT_COST_MULTIPLE: net profit must be this many times the route's estimated fees
+    // instead of `min_profit`, so the bar follows gas and fees
+    pub cost_multiple: Option<f64>,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds { min_profit: MIN_ABSOLUTE_PROFIT, min_roi_percentage: MIN_ROI_PERCENTAGE, cost_multiple: None }
+    }
+}
+
+impl Thresholds {
+    /// These thresholds with the given ones replaced; negative or non-finite values are refused
+    pub fn with(self, min_profit: Option<f64>, min_roi_percentage: Option<f64>) -> Result<Self> {
+        for (name, value) in [("min_profit", min_profit), ("min_roi_percentage", min_roi_percentage)] {
+            if value.is_some_and(|v| !v.is_finite() || v < 0.0) {
+                return Err(anyhow!("invalid {}: {}", name, value.unwrap_or_default()));
+            }
+        }
+        Ok(Thresholds {
+            min_profit: min_profit.unwrap_or(self.min_profit),
+            min_roi_percentage: min_roi_percentage.unwrap_or(self.min_roi_percentage),
+            cost_multiple: self.cost_multiple,
+        })
+    }
+
+    /// These thresholds with the cost multiple replaced, if given; 0 goes back to `min_profit`
+    pub fn with_cost_multiple(self, cost_multiple: Option<f64>) -> Result<Self> {
+        match cost_multiple {
+            None => Ok(self),
+            Some(k) if !k.is_finite() || k < 0.0 => Err(anyhow!("invalid cost_multiple: {}", k)),
+            Some(k) => Ok(Thresholds { cost_multiple: (k > 0.0).then_some(k), ..self }),
+        }
+    }
+
+    /// The net profit a route with `estimated_fees` has to clear, before overrides and multipliers
+    pub fn min_net_profit(&self, estimated_fees: f64) -> f64 {
+        match self.cost_multiple {
+            Some(k) => k * estimated_fees,
+            None => self.min_profit,
+        }
+    }
+
+    /// e.g. `$1.00 and 0.10% ROI`, or `1.5× fees and 0.10% ROI`
+    pub fn describe(&self) -> String {
+        match self.cost_multiple {
+            Some(k) => format!("{}× fees and {:.2}% ROI", k, self.min_roi_percentage),
+            None => format!("${:.2} and {:.2}% ROI", self.min_profit, self.min_roi_percentage),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Pause {
+    pub reason: String,
+    pub actor: String,
+    pub since: DateTime<Utc>,
+}
+
+/// What the control commands act on, as `get_state` reports it
+#[derive(Debug, Clone, Serialize)]
+pub struct ControlState {
+    pub mode: String,
+    pub paused: Option<Pause>,
+    pub thresholds: Thresholds,
+    pub kill_switch_tripped: bool,
+    pub idle: bool,
+    pub books: usize,
+    pub live_opportunities: usize,
+}
+
+/// Subscribe to `channel` in a background thread; malformed messages are logged and dropped
+pub fn spawn_listener(client: Client, channel: String) -> Receiver<ControlCommand> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut backoff = Backoff::from_env();
+        loop {
+            match listen(&client, &channel, &tx, &mut backoff) {
+                Ok(()) => return,
+                Err(e) => {
+                    let delay = backoff.next_delay();
+                    warn!("Control channel {} lost its subscription: {}; reconnecting in {}ms", channel, e, delay.as_millis());
+                    thread::sleep(delay);
+                }
+            }
+        }
+    });
+    rx
+}
+
+fn listen(client: &Client, channel: &str, tx: &Sender<ControlCommand>, backoff: &mut Backoff) -> Result<()> {
+    let mut con = client.get_connection()?;
+    let mut pubsub = con.as_pubsub();
+    pubsub.subscribe(channel)?;
+    info!("  Listening for control commands on {}", channel);
+    backoff.reset();
+
+    loop {
+        let payload: String = pubsub.get_message()?.get_payload()?;
+        match serde_json::from_str::<ControlCommand>(&payload) {
+            Ok(command) => {
+                if tx.send(command).is_err() {
+                    return Ok(());
+                }
+            }
+            Err(e) => warn!("Ignoring malformed control message on {}: {}", channel, e),
+        }
+    }
+}
+
+impl SpreadAnalyzer {
+    // Apply every control command received since the last poll
+    pub fn serve_control_commands(&mut self) {
+        let pending: Vec<ControlCommand> = match &self.control_commands {
+            Some(rx) => rx.try_iter().collect(),
+            None => return,
+        };
+
+        for command in pending {
+            if let Err(e) = self.apply_control_command(command, "control") {
+                warn!("Control command failed: {}", e);
+            }
+        }
+    }
+
+    /// Apply `command` received over `via`, e.g. `control` for the Redis channel
+    pub fn apply_control_command(&mut self, command: ControlCommand, via: &str) -> Result<()> {
+        let actor = |actor: Option<String>| format!("{}:{}", via, actor.as_deref().unwrap_or("anonymous"));
+        match command {
+            ControlCommand::KillSwitchTrip { reason, actor: by } => {
+                self.trip_kill_switch(reason.as_deref().unwrap_or(&format!("tripped via {}", via)), &actor(by))
+            }
+            ControlCommand::KillSwitchReset { token, actor: by } => self.reset_kill_switch(&token, &actor(by)),
+            ControlCommand::SetPairPriority { pair, priority, actor: by } => {
+                self.pair_priorities.set(&pair, priority);
+                info!("Priority of {} set to {} by {}", pair, priority.map_or("learned".to_string(), |p| p.to_string()), actor(by));
+                Ok(())
+            }
+            ControlCommand::Pause { reason, actor: by } => {
+                if self.paused.is_none() {
+                    let pause = Pause { reason: reason.unwrap_or_else(|| format!("paused via {}", via)), actor: actor(by), since: Utc::now() };
+                    warn!("Publishing paused by {}: {}", pause.actor, pause.reason);
+                    self.paused = Some(pause);
+                    self.metrics.paused.store(1, Ordering::Relaxed);
+                }
+                Ok(())
+            }
+            ControlCommand::Resume { actor: by } => {
+                if self.paused.take().is_some() {
+                    self.metrics.paused.store(0, Ordering::Relaxed);
+                    info!("Publishing resumed by {}", actor(by));
+                }
+                Ok(())
+            }
+            ControlCommand::SetThresholds { min_profit, min_roi_percentage, cost_multiple, actor: by } => {
+                self.thresholds = self.thresholds.with(min_profit, min_roi_percentage)?.with_cost_multiple(cost_multiple)?;
+                info!("Thresholds set to {} by {}", self.thresholds.describe(), actor(by));
+                Ok(())
+            }
+            ControlCommand::PromoteVenue { venue, actor: by } => self.promote_venue(&venue, &actor(by)),
+            ControlCommand::DemoteVenue { venue, reason, actor: by } => self.demote_venue(&venue, reason, &actor(by)),
+            ControlCommand::ConfirmPair { pair, actor: by } => self.confirm_pair(&pair, &actor(by)),
+            ControlCommand::BlockPair { pair, reason, actor: by } => self.block_pair(&pair, reason, &actor(by)),
+        }
+    }
+
+    /// Count an opportunity that would have been published while paused
+    pub fn publishing_paused(&self) -> bool {
+        let paused = self.paused.is_some();
+        if paused {
+            Metrics::inc(&self.metrics.publishing_paused);
+        }
+        paused
+    }
+
+    pub fn control_state(&self) -> ControlState {
+        ControlState {
+            mode: self.mode.to_string(),
+            paused: self.paused.clone(),
+            thresholds: self.thresholds,
+            kill_switch_tripped: self.kill_switch.is_tripped(),
+            idle: self.idle.is_idle(),
+            books: self.books.len(),
+            live_opportunities: self.live_opportunities.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(raw: &str) -> ControlCommand {
+        serde_json::from_str(raw).unwrap()
+    }
+
+    #[test]
+    fn parses_tagged_commands() {
+        let trip: ControlCommand = serde_json::from_str(r#"{"command":"kill_switch_trip","reason":"bad fills"}"#).unwrap();
+        assert_eq!(trip, ControlCommand::KillSwitchTrip { reason: Some("bad fills".to_string()), actor: None });
+        assert!(serde_json::from_str::<ControlCommand>(r#"{"command":"kill_switch_reset"}"#).is_err());
+        let reset: ControlCommand = serde_json::from_str(r#"{"command":"set_pair_priority","pair":"BTC/USDT"}"#).unwrap();
+        assert_eq!(reset, ControlCommand::SetPairPriority { pair: "BTC/USDT".to_string(), priority: None, actor: None });
+        assert!(serde_json::from_str::<ControlCommand>(r#"{"command":"self_destruct"}"#).is_err());
+    }
+
+    #[test]
+    fn pause_and_resume_apply_at_runtime() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.apply_control_command(command(r#"{"command":"pause","actor":"ops"}"#), "grpc").unwrap();
+        assert_eq!(analyzer.control_state().paused.map(|p| p.actor).as_deref(), Some("grpc:ops"));
+        assert!(analyzer.publishing_paused());
+        analyzer.apply_control_command(command(r#"{"command":"resume"}"#), "grpc").unwrap();
+        assert!(!analyzer.publishing_paused());
+    }
+
+    #[test]
+    fn thresholds_apply_at_runtime() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        assert!(analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).is_some());
+        analyzer.apply_control_command(command(r#"{"command":"set_thresholds","min_profit":100000}"#), "control").unwrap();
+        assert_eq!(analyzer.thresholds, Thresholds { min_profit: 100_000.0, min_roi_percentage: MIN_ROI_PERCENTAGE, cost_multiple: None });
+        assert!(analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).is_none());
+        assert!(analyzer.apply_control_command(command(r#"{"command":"set_thresholds","min_roi_percentage":-1}"#), "control").is_err());
+        assert_eq!(analyzer.thresholds.min_roi_percentage, MIN_ROI_PERCENTAGE);
+    }
+
+    #[test]
+    fn cost_multiple_thresholds_scale_with_the_fees() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        // In multiples of the route's fees, the fixed minimum no longer applies
+        analyzer.apply_control_command(command(r#"{"command":"set_thresholds","cost_multiple":2}"#), "control").unwrap();
+        let opp = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        assert!(opp.net_profit >= 2.0 * opp.estimated_fees);
+        let k = opp.net_profit / opp.estimated_fees + 0.1;
+        analyzer.apply_control_command(command(&format!(r#"{{"command":"set_thresholds","cost_multiple":{}}}"#, k)), "control").unwrap();
+        assert!(analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).is_none());
+        analyzer.apply_control_command(command(r#"{"command":"set_thresholds","cost_multiple":0}"#), "control").unwrap();
+        assert_eq!(analyzer.thresholds.cost_multiple, None);
+    }
+}
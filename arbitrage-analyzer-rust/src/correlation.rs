@@ -0,0 +1,232 @@
+// Return correlations between the assets that active routes trade. Every
+// CORRELATION_SAMPLE_SECS (default 60) the USD price of each base asset with a
+// cached book is sampled (the median mid across its USD-quoted books, see
+// `notional`); the last CORRELATION_WINDOW samples (default 120) are kept. Two
+// assets' correlation is the Pearson correlation of their log returns over the
+// samples both were priced in, once there are CORRELATION_MIN_SAMPLES (default
+// 30) of them; an asset is fully correlated with itself.
+//
+// `GET /reports/correlation` serves the matrix for the base assets of live
+// opportunities and in-flight requests (or `?assets=BTC,ETH`), with the pairs at
+// or over CORRELATION_THRESHOLD (default 0.7) listed as the same bet.
+//
+// MAX_CORRELATED_NOTIONAL_USD (0, the default, disables it) is an aggregate limit
+// of the `risk_limits` pre-trade check: a request's capital at risk, plus that of
+// every in-flight request on an asset correlated at or over the threshold
+// weighted by the correlation, must stay under it. Routes on two assets that move
+// together then share one budget instead of each getting their own.
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::notional::{split_pair, NotionalConverter};
+use crate::{config, ArbitrageOpportunity, SpreadAnalyzer};
+
+const DEFAULT_SAMPLE_SECS: i64 = 60;
+const DEFAULT_WINDOW: usize = 120;
+const DEFAULT_MIN_SAMPLES: usize = 30;
+const DEFAULT_THRESHOLD: f64 = 0.7;
+
+/// Two assets that move together
+#[derive(Debug, Clone, Serialize)]
+pub struct CorrelatedAssets {
+    pub assets: (String, String),
+    pub correlation: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CorrelationMatrix {
+    pub assets: Vec<String>,
+    // Row and column order of `assets`; null where there are too few common samples
+    pub correlations: Vec<Vec<Option<f64>>>,
+    pub samples: usize,
+    pub threshold: f64,
+    // Strongest first
+    pub correlated: Vec<CorrelatedAssets>,
+}
+
+#[derive(Debug)]
+pub struct CorrelationTracker {
+    sample_every: Duration,
+    window: usize,
+    min_samples: usize,
+    pub threshold: f64,
+    // USD price per asset at each sample, oldest first
+    samples: VecDeque<HashMap<String, f64>>,
+    last_sample: Option<DateTime<Utc>>,
+}
+
+impl CorrelationTracker {
+    pub fn new(sample_every: Duration, window: usize, min_samples: usize, threshold: f64) -> Self {
+        CorrelationTracker {
+            sample_every,
+            window: window.max(2),
+            min_samples: min_samples.max(2),
+            threshold,
+            samples: VecDeque::new(),
+            last_sample: None,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(
+            Duration::seconds(config::env_or("CORRELATION_SAMPLE_SECS", DEFAULT_SAMPLE_SECS)),
+            config::env_or("CORRELATION_WINDOW", DEFAULT_WINDOW),
+            config::env_or("CORRELATION_MIN_SAMPLES", DEFAULT_MIN_SAMPLES),
+            config::env_or("CORRELATION_THRESHOLD", DEFAULT_THRESHOLD),
+        )
+    }
+
+    pub fn due(&self, now: DateTime<Utc>) -> bool {
+        self.last_sample.is_none_or(|at| now - at >= self.sample_every)
+    }
+
+    pub fn record(&mut self, prices: HashMap<String, f64>, now: DateTime<Utc>) {
+        self.samples.push_back(prices);
+        // One more sample than returns
+        while self.samples.len() > self.window + 1 {
+            self.samples.pop_front();
+        }
+        self.last_sample = Some(now);
+    }
+
+    // Log return of `asset` between each sample and the previous one
+    fn returns(&self, asset: &str) -> Vec<Option<f64>> {
+        self.samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|(previous, current)| Some((current.get(asset)? / previous.get(asset)?).ln()).filter(|r| r.is_finite()))
+            .collect()
+    }
+
+    /// Pearson correlation of the two assets' returns, None until they share enough samples
+    pub fn correlation(&self, a: &str, b: &str) -> Option<f64> {
+        if a == b {
+            return Some(1.0);
+        }
+        let pairs: Vec<(f64, f64)> = self.returns(a).into_iter().zip(self.returns(b)).filter_map(|(x, y)| Some((x?, y?))).collect();
+        if pairs.len() < self.min_samples {
+            return None;
+        }
+        let n = pairs.len() as f64;
+        let (mean_x, mean_y) = (pairs.iter().map(|p| p.0).sum::<f64>() / n, pairs.iter().map(|p| p.1).sum::<f64>() / n);
+        let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+        for (x, y) in &pairs {
+            cov += (x - mean_x) * (y - mean_y);
+            var_x += (x - mean_x).powi(2);
+            var_y += (y - mean_y).powi(2);
+        }
+        // A flat price has no correlation to speak of
+        let correlation = cov / (var_x * var_y).sqrt();
+        correlation.is_finite().then(|| correlation.clamp(-1.0, 1.0))
+    }
+
+    /// How strongly `asset` counts against exposure on `other`: the correlation, when at or over the threshold
+    pub fn weight(&self, asset: &str, other: &str) -> f64 {
+        self.correlation(asset, other).filter(|c| *c >= self.threshold).unwrap_or(0.0)
+    }
+
+    pub fn matrix(&self, assets: Vec<String>) -> CorrelationMatrix {
+        let correlations: Vec<Vec<Option<f64>>> = assets.iter().map(|a| assets.iter().map(|b| self.correlation(a, b)).collect()).collect();
+        let mut correlated = Vec::new();
+        for (i, a) in assets.iter().enumerate() {
+            for (j, b) in assets.iter().enumerate().skip(i + 1) {
+                if let Some(correlation) = correlations[i][j].filter(|c| *c >= self.threshold) {
+                    correlated.push(CorrelatedAssets { assets: (a.clone(), b.clone()), correlation });
+                }
+            }
+        }
+        correlated.sort_by(|x, y| y.correlation.total_cmp(&x.correlation));
+        CorrelationMatrix { assets, correlations, samples: self.samples.len(), threshold: self.threshold, correlated }
+    }
+}
+
+/// The asset a route on `pair` is a bet on
+pub fn route_asset(pair: &str) -> String {
+    split_pair(pair).0
+}
+
+impl SpreadAnalyzer {
+    /// Sample the USD price of every base asset with a cached book, when due
+    pub(crate) fn sample_correlations(&mut self, now: DateTime<Utc>) {
+        if !self.correlation.due(now) {
+            return;
+        }
+        let converter = NotionalConverter::new(&self.capital_config.quote_usd, &self.books, self.sizing_config.reference_price);
+        let assets: BTreeSet<String> = self.books.values().map(|book| route_asset(&book.pair)).collect();
+        let prices = assets.into_iter().filter_map(|asset| Some((asset.clone(), converter.consensus_price(&asset)?))).collect();
+        self.correlation.record(prices, now);
+    }
+
+    /// Base assets of the live opportunities and in-flight requests
+    pub(crate) fn active_route_assets(&self) -> Vec<String> {
+        let live = self.live_opportunities.routes().map(|route| route_asset(&route.pair));
+        let in_flight = self.lifecycle.in_flight().into_iter().map(|request| route_asset(&request.route.pair));
+        live.chain(in_flight).collect::<BTreeSet<_>>().into_iter().collect()
+    }
+
+    /// Capital at risk in USD of trading `size` of `opp`, when its quote asset has a USD price
+    pub(crate) fn notional_usd(&self, opp: &ArbitrageOpportunity, size: f64) -> Option<f64> {
+        let prefunded = opp.capital_at_risk.is_some_and(|capital| capital.prefunded);
+        self.capital_config.capital_at_risk(&opp.pair, opp.buy_price, opp.sell_price, size, prefunded).amount_usd
+    }
+
+    /// Capital at risk in flight on `asset` or assets correlated with it, weighted by the correlation
+    pub(crate) fn correlated_exposure_usd(&self, asset: &str) -> f64 {
+        self.lifecycle
+            .in_flight()
+            .into_iter()
+            .filter_map(|request| Some(request.notional_usd? * self.correlation.weight(asset, &route_asset(&request.route.pair))))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BTC and ETH move together, DOGE against them, SOL shows up too late to correlate
+    fn tracker(start: DateTime<Utc>) -> CorrelationTracker {
+        let mut tracker = CorrelationTracker::new(Duration::seconds(60), 50, 5, 0.7);
+        for i in 0..20 {
+            let wiggle = if i % 2 == 0 { 1.0 } else { -1.0 } * (1.0 + i as f64 / 10.0);
+            let mut prices = HashMap::from([
+                ("BTC".to_string(), 50_000.0 + 100.0 * wiggle),
+                ("ETH".to_string(), 3_000.0 + 6.1 * wiggle),
+                ("DOGE".to_string(), 0.1 - 0.001 * wiggle),
+            ]);
+            if i > 15 {
+                prices.insert("SOL".to_string(), 100.0 + wiggle);
+            }
+            assert!(tracker.due(start + Duration::seconds(60 * i)));
+            tracker.record(prices, start + Duration::seconds(60 * i));
+        }
+        tracker
+    }
+
+    #[test]
+    fn samples_once_per_interval() {
+        let start = Utc::now();
+        assert!(!tracker(start).due(start + Duration::seconds(60 * 19 + 30)));
+    }
+
+    #[test]
+    fn correlates_returns_over_common_samples() {
+        let tracker = tracker(Utc::now());
+        assert!(tracker.correlation("BTC", "ETH").unwrap() > 0.99);
+        assert!(tracker.correlation("BTC", "DOGE").unwrap() < -0.99);
+        assert_eq!(tracker.correlation("BTC", "SOL"), None);
+        assert_eq!(tracker.weight("BTC", "DOGE"), 0.0);
+    }
+
+    #[test]
+    fn the_matrix_flags_correlated_pairs() {
+        let tracker = tracker(Utc::now());
+        let matrix = tracker.matrix(vec!["BTC".to_string(), "DOGE".to_string(), "ETH".to_string()]);
+        assert_eq!(matrix.correlations[0][0], Some(1.0));
+        assert_eq!(matrix.correlated.len(), 1);
+        assert_eq!(matrix.correlated[0].assets, ("BTC".to_string(), "ETH".to_string()));
+    }
+}
@@ -0,0 +1,345 @@
+// What changed between two comprehensive passes. Each pass keeps a snapshot of
+// the routes it found profitable and of the inputs they were priced from: the
+// top of every cached book, each venue's fixed leg cost (gas, transaction fees)
+// and the venues it could not trade on. The next pass diffs its routes against
+// it and attributes every route that opened or closed to the input that moved
+// the most, e.g. "BTC/USDT binance→uniswap-v3-exact lost profitability: gas +40%".
+// The narrative is printed with the console report and published as a
+// `comprehensive_delta` event when any route opened or closed.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::alerts::Severity;
+use crate::events::{Event, EventClass};
+use crate::lifecycle::RouteKey;
+use crate::{ArbitrageOpportunity, SpreadAnalyzer};
+
+// Routes named in the narrative per direction; the rest are counted
+const NARRATED_ROUTES: usize = 3;
+
+#[derive(Debug, Clone)]
+struct PricedRoute {
+    buy_price: f64,
+    sell_price: f64,
+    size: f64,
+    fees: f64,
+    net_profit: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RunSnapshot {
+    taken_at: Option<DateTime<Utc>>,
+    routes: HashMap<RouteKey, PricedRoute>,
+    // Best bid and ask per (exchange, normalized pair)
+    tops: HashMap<(String, String), (f64, f64)>,
+    fixed_costs: HashMap<String, f64>,
+    unavailable: HashSet<String>,
+}
+
+impl RunSnapshot {
+    fn top(&self, exchange: &str, pair: &str) -> Option<(f64, f64)> {
+        self.tops.get(&(exchange.to_string(), pair.to_string())).copied()
+    }
+
+    fn fixed_cost(&self, route: &RouteKey) -> Option<f64> {
+        Some(self.fixed_costs.get(&route.buy_exchange)? + self.fixed_costs.get(&route.sell_exchange)?)
+    }
+
+    // First leg of `route` this snapshot had no tradable book for
+    fn missing_venue<'a>(&self, route: &'a RouteKey) -> Option<&'a str> {
+        [&route.buy_exchange, &route.sell_exchange]
+            .into_iter()
+            .find(|venue| self.unavailable.contains(*venue) || self.top(venue, &route.pair).is_none())
+            .map(String::as_str)
+    }
+}
+
+/// The input a route's change is attributed to
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "input", rename_all = "snake_case")]
+pub enum Cause {
+    // A leg's book went missing, or its venue went suspect or into quarantine
+    VenueUnavailable { venue: String },
+    // A leg's book (re)appeared, or its venue is tradable again
+    VenueAvailable { venue: String },
+    // Fixed leg costs: gas, transaction and bridge fees
+    Gas { change_pct: f64 },
+    // The spread between the buy ask and the sell bid
+    Spread { from_bps: f64, to_bps: f64 },
+    // Size-dependent trading and withdrawal fees
+    Fees { change_pct: f64 },
+    // Prices and costs did not move enough to explain it: depth, size caps or thresholds
+    Size,
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cause::VenueUnavailable { venue } => write!(f, "{} unavailable", venue),
+            Cause::VenueAvailable { venue } => write!(f, "{} available again", venue),
+            Cause::Gas { change_pct } => write!(f, "gas {:+.0}%", change_pct),
+            Cause::Spread { from_bps, to_bps } => write!(f, "spread {:.1} → {:.1}bps", from_bps, to_bps),
+            Cause::Fees { change_pct } => write!(f, "fees {:+.0}%", change_pct),
+            Cause::Size => f.write_str("depth or size caps"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteChange {
+    pub route: RouteKey,
+    // Of the pass that found the route: the current one for opened routes, the previous one for closed
+    pub net_profit: f64,
+    pub cause: Cause,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunDelta {
+    pub since: DateTime<Utc>,
+    pub at: DateTime<Utc>,
+    pub opened: Vec<RouteChange>,
+    pub closed: Vec<RouteChange>,
+    // Routes profitable in both passes
+    pub still_profitable: usize,
+}
+
+impl RunDelta {
+    pub fn is_empty(&self) -> bool {
+        self.opened.is_empty() && self.closed.is_empty()
+    }
+
+    /// One line, e.g. "3 new routes profitable (...); BTC/USDT binance→okx lost profitability: gas +40%; 2 still profitable"
+    pub fn narrative(&self) -> String {
+        let describe = |changes: &[RouteChange]| {
+            let mut parts: Vec<String> =
+                changes.iter().take(NARRATED_ROUTES).map(|change| format!("{}: {}", change.route, change.cause)).collect();
+            if changes.len() > NARRATED_ROUTES {
+                parts.push(format!("{} more", changes.len() - NARRATED_ROUTES));
+            }
+            parts.join(", ")
+        };
+        let mut sentences = Vec::new();
+        match self.opened.len() {
+            0 => {}
+            1 => sentences.push(format!("{} became profitable: {}", self.opened[0].route, self.opened[0].cause)),
+            n => sentences.push(format!("{} new routes profitable ({})", n, describe(&self.opened))),
+        }
+        match self.closed.len() {
+            0 => {}
+            1 => sentences.push(format!("{} lost profitability: {}", self.closed[0].route, self.closed[0].cause)),
+            n => sentences.push(format!("{} routes lost profitability ({})", n, describe(&self.closed))),
+        }
+        if sentences.is_empty() {
+            sentences.push("no route opened or closed".to_string());
+        }
+        sentences.push(format!("{} still profitable", self.still_profitable));
+        sentences.join("; ")
+    }
+}
+
+fn spread_bps(buy_price: f64, sell_price: f64) -> f64 {
+    if buy_price > 0.0 { (sell_price - buy_price) / buy_price * 10_000.0 } else { 0.0 }
+}
+
+fn change_pct(from: f64, to: f64) -> f64 {
+    if from > 0.0 { (to - from) / from * 100.0 } else { 0.0 }
+}
+
+impl SpreadAnalyzer {
+    // The inputs and profitable routes of the comprehensive pass that found `opportunities`
+    fn run_snapshot(&self, opportunities: &[ArbitrageOpportunity], now: DateTime<Utc>) -> RunSnapshot {
+        let mut snapshot = RunSnapshot { taken_at: Some(now), ..RunSnapshot::default() };
+        for book in self.books.values() {
+            if let (Some((bid, _)), Some((ask, _))) = (book.best_bid(), book.best_ask()) {
+                snapshot.tops.insert((book.exchange.clone(), book.pair.replace("WBTC", "BTC")), (bid, ask));
+            }
+            snapshot.fixed_costs.entry(book.exchange.clone()).or_insert_with(|| self.fees_config.fixed_leg_cost(&book.exchange));
+            if self.watchdog.is_suspect(&book.exchange) || self.quarantined.contains_key(&book.exchange) {
+                snapshot.unavailable.insert(book.exchange.clone());
+            }
+        }
+        for opp in opportunities {
+            let priced = PricedRoute {
+                buy_price: opp.buy_price,
+                sell_price: opp.sell_price,
+                size: opp.max_size,
+                fees: opp.estimated_fees,
+                net_profit: opp.net_profit,
+            };
+            // The best of a route's opportunities stands for it
+            let route = RouteKey::new(&opp.pair, &opp.buy_exchange, &opp.sell_exchange);
+            if snapshot.routes.get(&route).is_none_or(|best| best.net_profit < priced.net_profit) {
+                snapshot.routes.insert(route, priced);
+            }
+        }
+        snapshot
+    }
+
+    // Why `route`, profitable at `was`, is not anymore
+    fn attribute_closed(&self, route: &RouteKey, was: &PricedRoute, previous: &RunSnapshot, current: &RunSnapshot) -> Cause {
+        if let Some(venue) = current.missing_venue(route) {
+            return Cause::VenueUnavailable { venue: venue.to_string() };
+        }
+        let (Some((_, ask)), Some((bid, _))) = (current.top(&route.buy_exchange, &route.pair), current.top(&route.sell_exchange, &route.pair))
+        else {
+            return Cause::Size;
+        };
+        let to_bps = spread_bps(ask, bid);
+        if bid <= ask {
+            return Cause::Spread { from_bps: spread_bps(was.buy_price, was.sell_price), to_bps };
+        }
+        // Reprice the previous size at the current top of book, and compare the loss of gross and the rise in costs
+        let fees = self.estimate_fees_and_gas(was.size, ask, bid, &route.buy_exchange, &route.sell_exchange, &route.pair).total;
+        let gross_drop = ((was.sell_price - was.buy_price) - (bid - ask)) * was.size;
+        let fee_rise = fees - was.fees;
+        let (fixed_was, fixed_now) = (previous.fixed_cost(route).unwrap_or(0.0), current.fixed_cost(route).unwrap_or(0.0));
+        let fixed_rise = fixed_now - fixed_was;
+        if gross_drop <= 0.0 && fee_rise <= 0.0 {
+            Cause::Size
+        } else if gross_drop >= fee_rise {
+            Cause::Spread { from_bps: spread_bps(was.buy_price, was.sell_price), to_bps }
+        } else if fixed_rise > 0.0 && fixed_rise >= fee_rise - fixed_rise && fixed_was > 0.0 {
+            Cause::Gas { change_pct: change_pct(fixed_was, fixed_now) }
+        } else {
+            Cause::Fees { change_pct: change_pct(was.fees, fees) }
+        }
+    }
+
+    // Why `opp`'s route, not profitable in `previous`, is now
+    fn attribute_opened(&self, route: &RouteKey, opp: &PricedRoute, previous: &RunSnapshot, current: &RunSnapshot) -> Cause {
+        if let Some(venue) = previous.missing_venue(route) {
+            return Cause::VenueAvailable { venue: venue.to_string() };
+        }
+        let (Some((_, ask)), Some((bid, _))) = (previous.top(&route.buy_exchange, &route.pair), previous.top(&route.sell_exchange, &route.pair))
+        else {
+            return Cause::Size;
+        };
+        let gross_gain = ((opp.sell_price - opp.buy_price) - (bid - ask)) * opp.size;
+        let (fixed_was, fixed_now) = (previous.fixed_cost(route).unwrap_or(0.0), current.fixed_cost(route).unwrap_or(0.0));
+        let fixed_drop = fixed_was - fixed_now;
+        if fixed_drop > 0.0 && fixed_drop > gross_gain && fixed_was > 0.0 {
+            Cause::Gas { change_pct: change_pct(fixed_was, fixed_now) }
+        } else if gross_gain > 0.0 {
+            Cause::Spread { from_bps: spread_bps(ask, bid), to_bps: spread_bps(opp.buy_price, opp.sell_price) }
+        } else {
+            Cause::Size
+        }
+    }
+
+    /// Diff a comprehensive pass against the previous one, and keep it for the next.
+    /// None for the first pass
+    pub(crate) fn comprehensive_delta(&mut self, opportunities: &[ArbitrageOpportunity], now: DateTime<Utc>) -> Option<RunDelta> {
+        let current = self.run_snapshot(opportunities, now);
+        let previous = std::mem::replace(&mut self.last_comprehensive, current);
+        let since = previous.taken_at?;
+        let current = &self.last_comprehensive;
+
+        let mut opened: Vec<RouteChange> = current
+            .routes
+            .iter()
+            .filter(|(route, _)| !previous.routes.contains_key(*route))
+            .map(|(route, priced)| RouteChange {
+                route: route.clone(),
+                net_profit: priced.net_profit,
+                cause: self.attribute_opened(route, priced, &previous, current),
+            })
+            .collect();
+        let mut closed: Vec<RouteChange> = previous
+            .routes
+            .iter()
+            .filter(|(route, _)| !current.routes.contains_key(*route))
+            .map(|(route, was)| RouteChange {
+                route: route.clone(),
+                net_profit: was.net_profit,
+                cause: self.attribute_closed(route, was, &previous, current),
+            })
+            .collect();
+        // Most valuable first, so those are the ones narrated
+        for changes in [&mut opened, &mut closed] {
+            changes.sort_by(|a, b| b.net_profit.total_cmp(&a.net_profit).then_with(|| a.route.cmp(&b.route)));
+        }
+        let still_profitable = current.routes.len() - opened.len();
+        Some(RunDelta { since, at: now, opened, closed, still_profitable })
+    }
+
+    /// Report what changed since the previous comprehensive pass, and publish it when a route opened or closed
+    pub(crate) fn report_comprehensive_delta(&mut self, opportunities: &[ArbitrageOpportunity], now: DateTime<Utc>) {
+        let Some(delta) = self.comprehensive_delta(opportunities, now) else { return };
+        self.report_delta(&delta);
+        if !delta.is_empty() {
+            self.publish(Event::new(EventClass::ComprehensiveDelta, Severity::Info, format!("Since the last comprehensive analysis: {}", delta.narrative())));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OrderBook;
+
+    fn book(exchange: &str, bid: f64, ask: f64) -> OrderBook {
+        serde_json::from_value(serde_json::json!({
+            "exchange": exchange, "pair": "BTC/USDT", "bids": [[bid, 5.0]], "asks": [[ask, 5.0]], "timestamp": 0
+        }))
+        .unwrap()
+    }
+
+    fn opp(analyzer: &SpreadAnalyzer, buy: &str, sell: &str, ask: f64, bid: f64) -> ArbitrageOpportunity {
+        analyzer.evaluate_opportunity(buy, sell, "BTC/USDT", ask, bid, 1.0, 1.0).unwrap()
+    }
+
+    // Binance → OKX and Binance → Uniswap, both profitable on the first pass at `t0`
+    fn analyzer_after_first_pass(t0: DateTime<Utc>) -> SpreadAnalyzer {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.books.insert("binance:BTC/USDT".to_string(), book("binance", 49_990.0, 50_000.0));
+        analyzer.books.insert("okx:BTC/USDT".to_string(), book("okx", 51_000.0, 51_010.0));
+        analyzer.books.insert("uniswap-v3-exact:BTC/USDT".to_string(), book("uniswap-v3-exact", 50_600.0, 50_610.0));
+        let first = [opp(&analyzer, "binance", "okx", 50_000.0, 51_000.0), opp(&analyzer, "binance", "uniswap-v3-exact", 50_000.0, 50_600.0)];
+        assert!(analyzer.comprehensive_delta(&first, t0).is_none());
+        analyzer
+    }
+
+    #[test]
+    fn closed_routes_are_attributed_to_the_input_that_moved() {
+        let t0 = Utc::now();
+        let mut analyzer = analyzer_after_first_pass(t0);
+        // Gas triples and okx's book goes away: both routes close, for different reasons
+        analyzer.books.remove("okx:BTC/USDT");
+        analyzer.fees_config.ethereum_gas_cost *= 3.0;
+        let delta = analyzer.comprehensive_delta(&[], t0 + chrono::Duration::seconds(30)).unwrap();
+        assert_eq!(delta.closed.len(), 2);
+        assert_eq!(delta.closed[0].cause, Cause::VenueUnavailable { venue: "okx".to_string() });
+        assert!(matches!(delta.closed[1].cause, Cause::Gas { change_pct } if (change_pct - 200.0).abs() < 1e-6));
+        assert!(delta.narrative().starts_with("2 routes lost profitability (BTC/USDT binance→okx: okx unavailable, BTC/USDT binance→uniswap-v3-exact: gas +200%)"));
+    }
+
+    #[test]
+    fn a_venue_coming_back_reopens_its_routes() {
+        let t0 = Utc::now();
+        let mut analyzer = analyzer_after_first_pass(t0);
+        analyzer.books.remove("okx:BTC/USDT");
+        analyzer.comprehensive_delta(&[], t0 + chrono::Duration::seconds(30)).unwrap();
+
+        // okx comes back with a wider spread
+        analyzer.books.insert("okx:BTC/USDT".to_string(), book("okx", 51_500.0, 51_510.0));
+        let reopened = [opp(&analyzer, "binance", "okx", 50_000.0, 51_500.0)];
+        let delta = analyzer.comprehensive_delta(&reopened, t0 + chrono::Duration::seconds(60)).unwrap();
+        assert_eq!(delta.opened[0].cause, Cause::VenueAvailable { venue: "okx".to_string() });
+        assert_eq!(delta.still_profitable, 0);
+    }
+
+    #[test]
+    fn unchanged_passes_have_nothing_to_report() {
+        let t0 = Utc::now();
+        let mut analyzer = analyzer_after_first_pass(t0);
+        let routes = [opp(&analyzer, "binance", "okx", 50_000.0, 51_000.0)];
+        analyzer.comprehensive_delta(&routes, t0 + chrono::Duration::seconds(30)).unwrap();
+        let delta = analyzer.comprehensive_delta(&routes, t0 + chrono::Duration::seconds(60)).unwrap();
+        assert!(delta.is_empty());
+        assert_eq!(delta.narrative(), "no route opened or closed; 1 still profitable");
+    }
+}
@@ -0,0 +1,99 @@
+// Price-level book maintained from exchange depth messages, shared by the direct
+// venue streams. Levels arrive as decimal strings (`["price", "size", ...]`); a
+// zero size removes the level.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+
+// One side of an `OrderBook`: `[price, size]` per level
+pub type Levels = Vec<Vec<f64>>;
+
+// Prices as map keys; parsing rejects NaN, so the total order is the numeric one
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Price(f64);
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+// Venues append extra fields (OKX: liquidated orders, order count); only the first two matter
+fn parse_level(level: &[String]) -> Result<(f64, f64)> {
+    let (Some(price), Some(size)) = (level.first(), level.get(1)) else {
+        return Err(anyhow!("short level {:?}", level));
+    };
+    let (price, size): (f64, f64) = (price.parse()?, size.parse()?);
+    if !price.is_finite() || !size.is_finite() || size < 0.0 {
+        return Err(anyhow!("invalid level {:?}", level));
+    }
+    Ok((price, size))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LevelBook {
+    bids: BTreeMap<Price, f64>,
+    asks: BTreeMap<Price, f64>,
+}
+
+impl LevelBook {
+    fn apply_side<L: AsRef<[String]>>(side: &mut BTreeMap<Price, f64>, levels: &[L]) -> Result<()> {
+        for level in levels {
+            let (price, size) = parse_level(level.as_ref())?;
+            if size == 0.0 {
+                side.remove(&Price(price));
+            } else {
+                side.insert(Price(price), size);
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply level updates. On error the book may be half updated and should be discarded
+    pub fn apply<L: AsRef<[String]>>(&mut self, bids: &[L], asks: &[L]) -> Result<()> {
+        Self::apply_side(&mut self.bids, bids)?;
+        Self::apply_side(&mut self.asks, asks)
+    }
+
+    /// A book holding exactly these levels
+    pub fn from_levels<L: AsRef<[String]>>(bids: &[L], asks: &[L]) -> Result<Self> {
+        let mut book = LevelBook::default();
+        book.apply(bids, asks)?;
+        Ok(book)
+    }
+
+    /// Top `depth` levels per side as `[price, size]`, best first
+    pub fn top(&self, depth: usize) -> (Levels, Levels) {
+        let bids = self.bids.iter().rev().take(depth).map(|(price, size)| vec![price.0, *size]).collect();
+        let asks = self.asks.iter().take(depth).map(|(price, size)| vec![price.0, *size]).collect();
+        (bids, asks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(raw: &[&[&str]]) -> Vec<Vec<String>> {
+        raw.iter().map(|level| level.iter().map(|s| s.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn applies_updates_and_removals() {
+        let mut book = LevelBook::from_levels(&levels(&[&["100", "1"], &["99", "2"]]), &levels(&[&["101", "1", "0", "3"]])).unwrap();
+        book.apply(&levels(&[&["100", "0"], &["99.5", "4"]]), &levels(&[])).unwrap();
+        assert_eq!(book.top(5), (vec![vec![99.5, 4.0], vec![99.0, 2.0]], vec![vec![101.0, 1.0]]));
+        assert!(book.apply(&levels(&[&["NaN", "1"]]), &levels(&[])).is_err());
+        assert!(book.apply(&levels(&[&["100"]]), &levels(&[])).is_err());
+    }
+}
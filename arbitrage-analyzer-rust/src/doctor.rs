@@ -0,0 +1,241 @@
+// `swapsleuth doctor`: checks a deployment before the daemon runs blind on it and
+// prints a pass/fail checklist. It loads the configuration the analyzer would run
+// with (failing on settings that would be ignored as invalid), then for every
+// Redis source:
+//  - connects and PINGs, telling rejected or missing credentials apart from an unreachable server,
+//  - compares the Redis server clock with the local one,
+//  - looks for book keys matching the source's KEY_PATTERN (`orderbook:*` without one),
+//  - fetches a few of them and parses and validates them like ingestion does,
+//  - checks that their timestamps are not ahead of the local clock.
+// Book timestamps that are not wall-clock times (e.g. Binance's lastUpdateId) are
+// left out of the clock check. Exits with an error when any check fails.
+
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use redis::{Commands, Connection};
+
+use crate::sources::RedisSource;
+use crate::{codec, config, configure_from_env, OrderBook, SpreadAnalyzer};
+
+const DEFAULT_KEY_PATTERN: &str = "orderbook:*";
+// Keys scanned to count how many books are present
+const MAX_SCANNED_KEYS: usize = 1_000;
+const DEFAULT_SAMPLE_KEYS: usize = 5;
+const DEFAULT_MAX_CLOCK_SKEW_SECS: i64 = 5;
+
+#[derive(Debug)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Check { name: name.into(), passed: true, detail: detail.into() }
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Check { name: name.into(), passed: false, detail: detail.into() }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}: {}", if self.passed { "PASS" } else { "FAIL" }, self.name, self.detail)
+    }
+}
+
+/// A book timestamp as a wall-clock time, if it looks like Unix seconds or milliseconds
+fn book_time(timestamp: i64) -> Option<DateTime<Utc>> {
+    match timestamp {
+        // 2001 to 2286 in seconds
+        1_000_000_000..=9_999_999_999 => DateTime::from_timestamp(timestamp, 0),
+        // The same range in milliseconds
+        1_000_000_000_000..=9_999_999_999_999 => DateTime::from_timestamp_millis(timestamp),
+        _ => None,
+    }
+}
+
+/// Compare the Redis clock and the sampled book timestamps against `now`
+fn clock_check(name: &str, server_time: Option<DateTime<Utc>>, books: &[(String, i64)], now: DateTime<Utc>, max_skew_secs: i64) -> Check {
+    let mut problems = Vec::new();
+    let mut notes = Vec::new();
+
+    match server_time {
+        Some(server_time) => {
+            let skew = (server_time - now).num_milliseconds() as f64 / 1000.0;
+            if skew.abs() > max_skew_secs as f64 {
+                problems.push(format!("Redis clock is {:+.1}s off the local clock", skew));
+            } else {
+                notes.push(format!("Redis clock within {:.1}s", skew.abs()));
+            }
+        }
+        None => notes.push("Redis clock unknown".to_string()),
+    }
+
+    let timed: Vec<(&String, DateTime<Utc>)> = books.iter().filter_map(|(key, ts)| Some((key, book_time(*ts)?))).collect();
+    for (key, at) in &timed {
+        let ahead = (*at - now).num_seconds();
+        if ahead > max_skew_secs {
+            problems.push(format!("{} is timestamped {}s in the future", key, ahead));
+        }
+    }
+    if let Some(oldest) = timed.iter().map(|(_, at)| (now - *at).num_seconds()).max() {
+        notes.push(format!("oldest sampled book written {}s ago", oldest.max(0)));
+    }
+    if timed.len() < books.len() {
+        notes.push(format!("{} books without a wall-clock timestamp", books.len() - timed.len()));
+    }
+
+    if problems.is_empty() {
+        Check::pass(name, notes.join(", "))
+    } else {
+        Check::fail(name, problems.join("; "))
+    }
+}
+
+fn config_check(config_path: Option<&Path>) -> (Check, Vec<RedisSource>) {
+    let redis_addr = config::env_var("REDIS_ADDR").unwrap_or_else(|_| "127.0.0.1:6379".to_string());
+    let loaded = SpreadAnalyzer::new(&redis_addr).and_then(|mut analyzer| {
+        configure_from_env(&mut analyzer, config_path)?;
+        Ok(analyzer)
+    });
+    match loaded {
+        Err(e) => (Check::fail("config", format!("{:#}", e)), Vec::new()),
+        Ok(analyzer) => {
+            let ignored = config::ignored_settings();
+            let check = if ignored.is_empty() {
+                Check::pass("config", format!("mode {}, {} Redis sources", analyzer.mode, analyzer.sources.len()))
+            } else {
+                Check::fail("config", format!("invalid settings would be ignored: {}", ignored.join(", ")))
+            };
+            (check, analyzer.sources)
+        }
+    }
+}
+
+fn source_checks(source: &RedisSource, sample_keys: usize, max_skew_secs: i64) -> Vec<Check> {
+    let label = |check: &str| format!("{} {}", source.name, check);
+    let mut checks = Vec::new();
+
+    let connected = source
+        .client
+        .get_connection()
+        .and_then(|mut con| redis::cmd("PING").query::<String>(&mut con).map(|_| con));
+    let mut con: Connection = match connected {
+        Ok(con) => {
+            checks.push(Check::pass(label("redis"), format!("connected to {}", source.addr)));
+            con
+        }
+        Err(e) => {
+            match source.auth_problem(&e) {
+                Some(problem) => checks.push(Check::fail(label("auth"), problem)),
+                None => checks.push(Check::fail(label("redis"), format!("cannot reach {}: {}", source.addr, e))),
+            }
+            return checks;
+        }
+    };
+    let server_time = redis::cmd("TIME")
+        .query::<(i64, i64)>(&mut con)
+        .ok()
+        .and_then(|(secs, micros)| DateTime::from_timestamp(secs, (micros * 1_000) as u32));
+
+    let pattern = source.key_pattern.clone().unwrap_or_else(|| DEFAULT_KEY_PATTERN.to_string());
+    let keys: Vec<String> = match con.scan_match::<_, String>(&pattern) {
+        Ok(iter) => iter.take(MAX_SCANNED_KEYS).collect(),
+        Err(e) => {
+            checks.push(Check::fail(label("keys"), format!("SCAN {} failed: {}", pattern, e)));
+            return checks;
+        }
+    };
+    if keys.is_empty() {
+        checks.push(Check::fail(label("keys"), format!("no keys match {}; are the collectors writing to this Redis?", pattern)));
+        return checks;
+    }
+    let count = if keys.len() == MAX_SCANNED_KEYS { format!("{}+", MAX_SCANNED_KEYS) } else { keys.len().to_string() };
+    checks.push(Check::pass(label("keys"), format!("{} keys match {}", count, pattern)));
+
+    let mut sampled = keys;
+    sampled.sort();
+    sampled.truncate(sample_keys);
+    let mut problems = Vec::new();
+    let mut timestamps = Vec::new();
+    for key in &sampled {
+        let book = con
+            .get::<_, String>(key)
+            .map_err(anyhow::Error::from)
+            .and_then(codec::decode_book::<OrderBook>);
+        match book {
+            Ok(book) => {
+                let issues = book.validation_issues();
+                if !issues.is_empty() {
+                    problems.push(format!("{}: {}", key, issues.join(", ")));
+                }
+                timestamps.push((key.clone(), book.timestamp));
+            }
+            Err(e) => problems.push(format!("{}: {:#}", key, e)),
+        }
+    }
+    checks.push(if problems.is_empty() {
+        Check::pass(label("payloads"), format!("{} sampled books parse and validate", sampled.len()))
+    } else {
+        Check::fail(label("payloads"), format!("{} of {} sampled books unusable: {}", problems.len(), sampled.len(), problems.join("; ")))
+    });
+
+    checks.push(clock_check(&label("clock"), server_time, &timestamps, Utc::now(), max_skew_secs));
+    checks
+}
+
+pub fn run(config_path: Option<&Path>) -> Result<()> {
+    let sample_keys = config::env_or("DOCTOR_SAMPLE_KEYS", DEFAULT_SAMPLE_KEYS);
+    let max_skew_secs = config::env_or("DOCTOR_MAX_CLOCK_SKEW_SECS", DEFAULT_MAX_CLOCK_SKEW_SECS);
+
+    let (config, sources) = config_check(config_path);
+    let mut checks = vec![config];
+    for source in &sources {
+        checks.extend(source_checks(source, sample_keys, max_skew_secs));
+    }
+
+    println!("SwapSleuth doctor");
+    for check in &checks {
+        println!("  {}", check);
+    }
+    let failed = checks.iter().filter(|c| !c.passed).count();
+    if failed > 0 {
+        return Err(anyhow!("{} of {} checks failed", failed, checks.len()));
+    }
+    println!("All {} checks passed", checks.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_check_flags_skewed_redis_and_future_books() {
+        let now = Utc::now();
+        let secs = now.timestamp();
+        let books = vec![
+            ("orderbook:uniswap:WBTC/USDT".to_string(), secs - 30),
+            ("orderbook:okx:BTC/USDT".to_string(), now.timestamp_millis() - 500),
+            // A Binance lastUpdateId is not a time
+            ("orderbook:binance:BTC/USDT".to_string(), 72_000_000_000),
+        ];
+        let check = clock_check("primary clock", Some(now), &books, now, 5);
+        assert!(check.passed, "{}", check);
+        assert!(check.detail.contains("oldest sampled book written 30s ago"));
+        assert!(check.detail.contains("1 books without a wall-clock timestamp"));
+
+        let skewed = clock_check("primary clock", Some(now + chrono::Duration::seconds(20)), &books, now, 5);
+        assert!(!skewed.passed);
+        let future = vec![("orderbook:okx:BTC/USDT".to_string(), secs + 60)];
+        let ahead = clock_check("primary clock", Some(now), &future, now, 5);
+        assert!(!ahead.passed && ahead.detail.contains("in the future"));
+        assert!(ahead.to_string().starts_with("[FAIL] primary clock"));
+    }
+}
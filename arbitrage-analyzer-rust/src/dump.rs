@@ -0,0 +1,108 @@
+use std::fs;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::Serialize;
+
+use crate::{OrderBook, SpreadAnalyzer, STALE_BOOK_AGE_MS};
+
+/// One cached orderbook plus what the analyzer currently thinks of it
+#[derive(Debug, Serialize)]
+pub struct BookDumpEntry {
+    key: String,
+    exchange: String,
+    pair: String,
+    normalized_pair: String,
+    // Redis source the book was read from
+    source: Option<String>,
+    best_bid: Option<f64>,
+    best_ask: Option<f64>,
+    bid_levels: usize,
+    ask_levels: usize,
+    timestamp: i64,
+    received_at: Option<DateTime<Utc>>,
+    age_ms: Option<i64>,
+    stale: bool,
+    // Venue stopped sending updates, see the watchdog
+    suspect: bool,
+    valid: bool,
+    validation_issues: Vec<String>,
+    bids: Vec<Vec<f64>>,
+    asks: Vec<Vec<f64>>,
+}
+
+/// Snapshot of the whole `books` cache, used for incident debugging
+#[derive(Debug, Serialize)]
+pub struct BooksDump {
+    generated_at: DateTime<Utc>,
+    stale_after_ms: i64,
+    book_count: usize,
+    stale_count: usize,
+    suspect_count: usize,
+    invalid_count: usize,
+    books: Vec<BookDumpEntry>,
+}
+
+pub fn default_dump_path() -> String {
+    format!("books-dump-{}.json", Utc::now().format("%Y%m%dT%H%M%SZ"))
+}
+
+impl BookDumpEntry {
+    fn from_book(key: &str, book: &OrderBook, suspect: bool, now: DateTime<Utc>) -> Self {
+        let age_ms = book.age_ms(now);
+        let validation_issues = book.validation_issues();
+
+        BookDumpEntry {
+            key: key.to_string(),
+            exchange: book.exchange.clone(),
+            pair: book.pair.clone(),
+            normalized_pair: book.pair.replace("WBTC", "BTC"),
+            source: book.source.clone(),
+            best_bid: book.bids.first().and_then(|level| level.first().copied()),
+            best_ask: book.asks.first().and_then(|level| level.first().copied()),
+            bid_levels: book.bids.len(),
+            ask_levels: book.asks.len(),
+            timestamp: book.timestamp,
+            received_at: book.received_at,
+            age_ms,
+            // A book we never stamped on receipt can't be trusted to be fresh
+            stale: age_ms.is_none_or(|age| age > STALE_BOOK_AGE_MS),
+            suspect,
+            valid: validation_issues.is_empty(),
+            validation_issues,
+            bids: book.bids.clone(),
+            asks: book.asks.clone(),
+        }
+    }
+}
+
+impl SpreadAnalyzer {
+    pub fn dump_books(&self) -> BooksDump {
+        let now = Utc::now();
+        let mut books: Vec<BookDumpEntry> = self
+            .books
+            .iter()
+            .map(|(key, book)| BookDumpEntry::from_book(key, book, self.watchdog.is_suspect(&book.exchange), now))
+            .collect();
+        books.sort_by(|a, b| a.key.cmp(&b.key));
+
+        BooksDump {
+            generated_at: now,
+            stale_after_ms: STALE_BOOK_AGE_MS,
+            book_count: books.len(),
+            stale_count: books.iter().filter(|b| b.stale).count(),
+            suspect_count: books.iter().filter(|b| b.suspect).count(),
+            invalid_count: books.iter().filter(|b| !b.valid).count(),
+            books,
+        }
+    }
+
+    /// Write the current books cache to `path`, returning the number of books written
+    pub fn dump_books_to_file(&self, path: &str) -> Result<usize> {
+        let dump = self.dump_books();
+        fs::write(path, serde_json::to_string_pretty(&dump)?)?;
+        info!("Dumped {} orderbooks to {}", dump.book_count, path);
+        Ok(dump.book_count)
+    }
+}
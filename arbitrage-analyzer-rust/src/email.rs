@@ -0,0 +1,323 @@
+// Email alert sink. Mails opportunities above EMAIL_MIN_PROFIT as they are found,
+// a digest of the top opportunities every EMAIL_DIGEST_SECS, and other events at
+// or above EMAIL_MIN_SEVERITY. Messages are handed to a mailer thread so a slow
+// SMTP server never stalls the analysis loop.
+
+use std::collections::HashSet;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use lettre::message::{Mailbox, Message};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{SmtpTransport, Transport};
+use log::{error, info};
+
+use crate::alerts::{AlertSink, Severity};
+use crate::events::{Event, EventClass};
+use crate::{config, numeric, template, ArbitrageOpportunity};
+
+const DEFAULT_SMTP_PORT: u16 = 587;
+const DEFAULT_DIGEST_SECS: i64 = 3600;
+const DEFAULT_DIGEST_TOP_N: usize = 10;
+
+const DEFAULT_SUBJECT: &str = "[SwapSleuth] {pair} {buy_exchange}→{sell_exchange}: ${net_profit} net";
+const DEFAULT_BODY: &str = "Buy {pair} on {buy_exchange} at {buy_price}, sell on {sell_exchange} at {sell_price}.\n\
+Size: {max_size}\nNet profit: ${net_profit} (ROI {roi_percentage}%)\nFees: ${estimated_fees}\nDetected: {timestamp}\nId: {id}";
+const DEFAULT_DIGEST_SUBJECT: &str = "[SwapSleuth] Top {count} opportunities since {window_start}";
+// Rendered once per opportunity in the digest body
+const DEFAULT_DIGEST_LINE: &str = "{rank}. {pair} {buy_exchange}→{sell_exchange}: ${net_profit} net, ROI {roi_percentage}% ({timestamp})";
+
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    pub from: Mailbox,
+    pub to: Vec<Mailbox>,
+    // Opportunities at or above this net profit are mailed immediately; None disables
+    pub min_profit: Option<f64>,
+    // Digest window; zero disables the digest
+    pub digest_interval: Duration,
+    pub digest_top_n: usize,
+    pub min_severity: Severity,
+    pub subject_template: String,
+    pub body_template: String,
+    pub digest_subject_template: String,
+    pub digest_line_template: String,
+}
+
+#[derive(Debug)]
+struct Digest {
+    window_start: DateTime<Utc>,
+    // Best opportunities of the window, highest net profit first
+    top: Vec<ArbitrageOpportunity>,
+}
+
+#[derive(Debug)]
+pub struct EmailAlertSink {
+    config: EmailConfig,
+    outbox: Sender<Message>,
+    digest: Mutex<Digest>,
+}
+
+fn mailboxes(raw: &str) -> Result<Vec<Mailbox>> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .map(|addr| addr.parse::<Mailbox>().map_err(|e| anyhow!("invalid address {}: {}", addr, e)))
+        .collect()
+}
+
+impl EmailConfig {
+    fn from_env() -> Result<Self> {
+        let from = config::env_var("EMAIL_FROM").map_err(|_| anyhow!("EMAIL_FROM is required"))?;
+        let to = mailboxes(&config::env_var("EMAIL_TO").map_err(|_| anyhow!("EMAIL_TO is required"))?)?;
+        if to.is_empty() {
+            return Err(anyhow!("EMAIL_TO has no addresses"));
+        }
+
+        Ok(EmailConfig {
+            from: from.parse().map_err(|e| anyhow!("invalid EMAIL_FROM {}: {}", from, e))?,
+            to,
+            min_profit: config::env_var("EMAIL_MIN_PROFIT").ok().and_then(|raw| raw.trim().parse().ok()),
+            digest_interval: Duration::seconds(config::env_or("EMAIL_DIGEST_SECS", DEFAULT_DIGEST_SECS)),
+            digest_top_n: config::env_or("EMAIL_DIGEST_TOP_N", DEFAULT_DIGEST_TOP_N),
+            min_severity: config::env_or("EMAIL_MIN_SEVERITY", Severity::Critical),
+            subject_template: template::from_env("EMAIL_SUBJECT_TEMPLATE", DEFAULT_SUBJECT),
+            body_template: template::from_env("EMAIL_BODY_TEMPLATE", DEFAULT_BODY),
+            digest_subject_template: template::from_env("EMAIL_DIGEST_SUBJECT_TEMPLATE", DEFAULT_DIGEST_SUBJECT),
+            digest_line_template: template::from_env("EMAIL_DIGEST_LINE_TEMPLATE", DEFAULT_DIGEST_LINE),
+        })
+    }
+}
+
+fn transport_from_env(host: &str) -> Result<SmtpTransport> {
+    let port: u16 = config::env_or("SMTP_PORT", DEFAULT_SMTP_PORT);
+    // starttls (default), tls for implicit TLS on 465, none for local relays
+    let mut builder = match config::env_var("SMTP_TLS").unwrap_or_default().to_lowercase().as_str() {
+        "" | "starttls" => SmtpTransport::starttls_relay(host)?,
+        "tls" => SmtpTransport::relay(host)?,
+        "none" => SmtpTransport::builder_dangerous(host),
+        other => return Err(anyhow!("unknown SMTP_TLS mode: {}", other)),
+    }
+    .port(port);
+
+    if let (Ok(username), Ok(password)) = (config::env_var("SMTP_USERNAME"), config::env_var("SMTP_PASSWORD")) {
+        builder = builder.credentials(Credentials::new(username, password));
+    }
+    Ok(builder.build())
+}
+
+impl EmailAlertSink {
+    /// Sink configured from SMTP_* / EMAIL_* variables, or None when SMTP_HOST is unset
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(host) = config::env_var("SMTP_HOST") else {
+            return Ok(None);
+        };
+        let config = EmailConfig::from_env()?;
+        let transport = transport_from_env(&host)?;
+
+        let (outbox, rx) = mpsc::channel::<Message>();
+        thread::spawn(move || {
+            for message in rx {
+                if let Err(e) = transport.send(&message) {
+                    error!("Failed to send alert email: {}", e);
+                }
+            }
+        });
+
+        info!("  Email alerts via {} to {} recipient(s)", host, config.to.len());
+        Ok(Some(EmailAlertSink::new(config, outbox, Utc::now())))
+    }
+
+    pub fn new(config: EmailConfig, outbox: Sender<Message>, now: DateTime<Utc>) -> Self {
+        EmailAlertSink { config, outbox, digest: Mutex::new(Digest { window_start: now, top: Vec::new() }) }
+    }
+
+    fn mail(&self, subject: String, body: String) -> Result<()> {
+        let mut builder = Message::builder().from(self.config.from.clone()).subject(subject);
+        for to in &self.config.to {
+            builder = builder.to(to.clone());
+        }
+        let message = builder.body(body)?;
+        self.outbox.send(message).map_err(|_| anyhow!("mailer thread is gone"))
+    }
+
+    // Mailed immediately when profitable enough or routed at a high enough severity
+    fn opportunity(&self, opportunity: &ArbitrageOpportunity, severity: Severity) -> Result<()> {
+        if self.config.digest_interval > Duration::zero() && self.config.digest_top_n > 0 {
+            let mut digest = self.lock_digest();
+            digest.top.push(opportunity.clone());
+            digest.top.sort_by(|a, b| numeric::cmp_desc(a.net_profit, b.net_profit));
+            digest.top.truncate(self.config.digest_top_n);
+        }
+
+        if self.config.min_profit.is_some_and(|min| opportunity.net_profit >= min) || severity >= self.config.min_severity {
+            let vars = template::opportunity_vars(opportunity);
+            self.mail(template::render(&self.config.subject_template, &vars), template::render(&self.config.body_template, &vars))?;
+        }
+        Ok(())
+    }
+
+    fn lock_digest(&self) -> std::sync::MutexGuard<'_, Digest> {
+        self.digest.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl AlertSink for EmailAlertSink {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn default_events(&self) -> HashSet<EventClass> {
+        // Opportunities feed the digest and the immediate mails
+        EventClass::ALERTS.into_iter().chain([EventClass::OpportunityDetected]).collect()
+    }
+
+    fn send(&self, event: &Event) -> Result<()> {
+        if let Some(opportunity) = &event.opportunity {
+            return self.opportunity(opportunity, event.severity);
+        }
+        if event.severity < self.config.min_severity {
+            return Ok(());
+        }
+        let subject = format!("[SwapSleuth {}] {}", event.severity, event.class);
+        let venue = event.venue.as_deref().map(|v| format!("\nVenue: {}", v)).unwrap_or_default();
+        self.mail(subject, format!("{}{}\nRaised: {}", event.message, venue, event.raised_at.to_rfc3339()))
+    }
+
+    fn flush(&self, now: DateTime<Utc>) {
+        if self.config.digest_interval <= Duration::zero() {
+            return;
+        }
+        let (window_start, top) = {
+            let mut digest = self.lock_digest();
+            if now - digest.window_start < self.config.digest_interval {
+                return;
+            }
+            let window_start = std::mem::replace(&mut digest.window_start, now);
+            (window_start, std::mem::take(&mut digest.top))
+        };
+        // Quiet windows don't produce an empty email
+        if top.is_empty() {
+            return;
+        }
+
+        let subject = template::render(
+            &self.config.digest_subject_template,
+            &[("count", top.len().to_string()), ("window_start", window_start.to_rfc3339())],
+        );
+        let body: Vec<String> = top
+            .iter()
+            .enumerate()
+            .map(|(idx, opp)| {
+                let mut vars = template::opportunity_vars(opp);
+                vars.push(("rank", (idx + 1).to_string()));
+                template::render(&self.config.digest_line_template, &vars)
+            })
+            .collect();
+        if let Err(e) = self.mail(subject, body.join("\n")) {
+            error!("Failed to queue digest email: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::Receiver;
+
+    fn opportunity(id: &str, net_profit: f64) -> ArbitrageOpportunity {
+        ArbitrageOpportunity {
+            id: id.to_string(),
+            buy_exchange: "binance".to_string(),
+            sell_exchange: "uniswap-v3-exact".to_string(),
+            pair: "BTC/USDT".to_string(),
+            buy_price: 50000.0,
+            sell_price: 50100.0,
+            max_size: 1.0,
+            sell_size: 1.0,
+            gross_profit_per_unit: 100.0,
+            estimated_fees: 100.0 - net_profit,
+            net_profit,
+            roi_percentage: net_profit / 500.0,
+            capital_at_risk: None,
+            annualized_roi_percentage: None,
+            timestamp: Utc::now(),
+            competition: None,
+            laggard: None,
+            cluster: None,
+            depth: None,
+            book_ages: None,
+            anomaly_score: None,
+            size_ladder: Vec::new(),
+            gas_regime: None,
+            execution_success: None,
+            annotations: Default::default(),
+            tag: Default::default(),
+        }
+    }
+
+    fn sink(min_profit: Option<f64>, start: DateTime<Utc>) -> (EmailAlertSink, Receiver<Message>) {
+        let config = EmailConfig {
+            from: "bot@example.com".parse().unwrap(),
+            to: vec!["desk@example.com".parse().unwrap()],
+            min_profit,
+            digest_interval: Duration::seconds(60),
+            digest_top_n: 2,
+            min_severity: Severity::Critical,
+            subject_template: DEFAULT_SUBJECT.to_string(),
+            body_template: DEFAULT_BODY.to_string(),
+            digest_subject_template: DEFAULT_DIGEST_SUBJECT.to_string(),
+            digest_line_template: DEFAULT_DIGEST_LINE.to_string(),
+        };
+        let (tx, rx) = mpsc::channel();
+        (EmailAlertSink::new(config, tx, start), rx)
+    }
+
+    fn text(message: Message) -> String {
+        String::from_utf8_lossy(&message.formatted()).into_owned()
+    }
+
+    #[test]
+    fn immediate_mail_only_above_threshold() {
+        let (sink, outbox) = sink(Some(10.0), Utc::now());
+        sink.send(&Event::opportunity_detected(&opportunity("small", 5.0))).unwrap();
+        assert!(outbox.try_recv().is_err());
+
+        sink.send(&Event::opportunity_detected(&opportunity("big", 25.0))).unwrap();
+        let mail = text(outbox.try_recv().expect("mail for the big opportunity"));
+        assert!(mail.contains("Id: big"));
+    }
+
+    #[test]
+    fn digest_sends_top_n_once_per_window() {
+        let start = Utc::now();
+        let (sink, outbox) = sink(None, start);
+        for (id, profit) in [("a", 3.0), ("b", 9.0), ("c", 1.0), ("d", 6.0)] {
+            sink.send(&Event::opportunity_detected(&opportunity(id, profit))).unwrap();
+        }
+
+        sink.flush(start + Duration::seconds(30));
+        assert!(outbox.try_recv().is_err());
+
+        sink.flush(start + Duration::seconds(61));
+        let mail = text(outbox.try_recv().expect("digest"));
+        assert!(mail.contains("Top 2 opportunities"));
+        assert!(mail.contains("1. BTC/USDT binance"));
+        assert!(mail.contains("$9.00 net") && mail.contains("$6.00 net") && !mail.contains("$3.00 net"));
+
+        // Nothing new in the next window, so no email
+        sink.flush(start + Duration::seconds(200));
+        assert!(outbox.try_recv().is_err());
+    }
+
+    #[test]
+    fn system_alerts_respect_min_severity() {
+        let (sink, outbox) = sink(None, Utc::now());
+        sink.send(&Event::new(EventClass::VenueStale, Severity::Warning, "quiet".to_string())).unwrap();
+        assert!(outbox.try_recv().is_err());
+        sink.send(&Event::new(EventClass::AllVenuesStale, Severity::Critical, "down".to_string())).unwrap();
+        assert!(text(outbox.try_recv().unwrap()).contains("all_venues_stale"));
+    }
+}
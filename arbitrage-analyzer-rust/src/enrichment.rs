@@ -0,0 +1,184 @@
+// Opportunity enrichment. Detection prices routes and stops there; everything an
+// opportunity is annotated with afterwards runs as an ordered pipeline of
+// `Enricher` stages over the opportunities of each pass, before they are
+// reported, recorded and published:
+//  - competition: how contested the route looks (`competition`, see
+//    competition.rs),
+//  - laggard: whether the spread only exists because one leg's quote trails the
+//    other venue (`laggard`, LAGGARD_POLICY),
+//  - cluster: which pairs show the same dislocation on the same route, so only
+//    the best is published (`cluster`, see cluster.rs),
+//  - gas_regime: the gas regime of the chains its DEX legs settle on
+//    (`gas_regime`, see gas_regime.rs),
+//  - success_rate: how often the route's requests filled at their expected
+//    profit (`execution_success`, see success_rate.rs).
+// ENRICHERS picks and orders the stages that run, e.g. `cluster,competition`; a
+// stage left out costs nothing and leaves its field unset. OPPORTUNITY_CLUSTERING
+// =false drops `cluster` from the default set. Builds with `--features
+// venue-plugins` add the stages of `plugins::enrichers`, which run after the
+// built-in ones unless ENRICHERS names them.
+
+use std::fmt;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+
+use crate::lifecycle::RouteKey;
+use crate::{cluster, config, plugins, ArbitrageOpportunity, SpreadAnalyzer};
+
+pub trait Enricher: fmt::Debug + Send {
+    fn name(&self) -> &'static str;
+    /// Annotate the opportunities of one pass
+    fn enrich(&self, analyzer: &SpreadAnalyzer, opportunities: &mut [ArbitrageOpportunity], now: DateTime<Utc>);
+}
+
+#[derive(Debug)]
+struct Competition;
+
+impl Enricher for Competition {
+    fn name(&self) -> &'static str {
+        "competition"
+    }
+
+    fn enrich(&self, analyzer: &SpreadAnalyzer, opportunities: &mut [ArbitrageOpportunity], now: DateTime<Utc>) {
+        for opp in opportunities {
+            opp.competition = analyzer.competition.estimate(&RouteKey::new(&opp.pair, &opp.buy_exchange, &opp.sell_exchange), now);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Laggard;
+
+impl Enricher for Laggard {
+    fn name(&self) -> &'static str {
+        "laggard"
+    }
+
+    fn enrich(&self, analyzer: &SpreadAnalyzer, opportunities: &mut [ArbitrageOpportunity], now: DateTime<Utc>) {
+        if !analyzer.lag.enabled() {
+            return;
+        }
+        for opp in opportunities {
+            opp.laggard = analyzer.lag.annotate(&opp.pair, &opp.buy_exchange, &opp.sell_exchange, now);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Cluster;
+
+impl Enricher for Cluster {
+    fn name(&self) -> &'static str {
+        "cluster"
+    }
+
+    fn enrich(&self, _: &SpreadAnalyzer, opportunities: &mut [ArbitrageOpportunity], _: DateTime<Utc>) {
+        cluster::annotate(opportunities);
+    }
+}
+
+#[derive(Debug)]
+struct GasRegime;
+
+impl Enricher for GasRegime {
+    fn name(&self) -> &'static str {
+        "gas_regime"
+    }
+
+    fn enrich(&self, analyzer: &SpreadAnalyzer, opportunities: &mut [ArbitrageOpportunity], _: DateTime<Utc>) {
+        for opp in opportunities {
+            opp.gas_regime = analyzer.gas_history.route_regime(&opp.buy_exchange, &opp.sell_exchange);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SuccessRate;
+
+impl Enricher for SuccessRate {
+    fn name(&self) -> &'static str {
+        "success_rate"
+    }
+
+    fn enrich(&self, analyzer: &SpreadAnalyzer, opportunities: &mut [ArbitrageOpportunity], _: DateTime<Utc>) {
+        for opp in opportunities {
+            opp.execution_success = Some(analyzer.success_rates.estimate(&RouteKey::new(&opp.pair, &opp.buy_exchange, &opp.sell_exchange)));
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Enrichers {
+    stages: Vec<Box<dyn Enricher>>,
+}
+
+impl Default for Enrichers {
+    /// The built-in stages
+    fn default() -> Self {
+        Enrichers { stages: vec![Box::new(Competition), Box::new(Laggard), Box::new(Cluster), Box::new(GasRegime), Box::new(SuccessRate)] }
+    }
+}
+
+impl Enrichers {
+    pub fn from_env() -> Result<Self> {
+        let mut available = Self::default().stages;
+        available.extend(plugins::enrichers());
+        let Ok(selected) = config::env_var("ENRICHERS") else {
+            if !config::env_or("OPPORTUNITY_CLUSTERING", true) {
+                available.retain(|stage| stage.name() != "cluster");
+            }
+            return Ok(Enrichers { stages: available });
+        };
+        let mut stages = Vec::new();
+        for name in selected.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match available.iter().position(|stage| stage.name() == name) {
+                Some(index) => stages.push(available.remove(index)),
+                None => bail!("ENRICHERS: unknown or repeated enricher {:?}", name),
+            }
+        }
+        Ok(Enrichers { stages })
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Run the opportunities of a pass through every enricher, in order
+    pub(crate) fn enrich(&self, opportunities: &mut [ArbitrageOpportunity], now: DateTime<Utc>) {
+        for stage in &self.enrichers.stages {
+            stage.enrich(self, opportunities, now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_selected_stages_annotate() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let now = Utc::now();
+        let route = RouteKey::new("BTC/USDT", "binance", "okx");
+        analyzer.competition.observe_mempool("okx", 3, now);
+        let mut opportunities = vec![
+            analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap(),
+            analyzer.evaluate_opportunity("binance", "okx", "BTC/USDC", 50_000.0, 50_900.0, 1.0, 1.0).unwrap(),
+        ];
+        assert_eq!(opportunities[0].competition, None);
+
+        analyzer.enrichers = Enrichers { stages: vec![Box::new(Cluster)] };
+        analyzer.enrich(&mut opportunities, now);
+        assert_eq!(opportunities[1].cluster.as_ref().map(|c| c.representative_id.clone()), Some(opportunities[0].id.clone()));
+        assert_eq!(opportunities[0].competition, None);
+
+        analyzer.enrichers = Enrichers::default();
+        assert_eq!(analyzer.enrichers.names(), vec!["competition", "laggard", "cluster", "gas_regime", "success_rate"]);
+        analyzer.enrich(&mut opportunities, now);
+        assert_eq!(opportunities[0].competition, analyzer.competition.estimate(&route, now));
+        assert!(opportunities[0].competition.is_some());
+    }
+}
@@ -0,0 +1,444 @@
+// Internal event bus. Everything noteworthy the analyzer does is published as an
+// `Event` with a class and a severity: book rejections, venue health, breaker
+// trips, config reloads, detected and expired opportunities. Each sink subscribes
+// to the classes it cares about (<SINK>_EVENTS), ALERT_ROUTES can narrow delivery
+// further, and the most recent events are kept for the API and dashboard.
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::Serialize;
+
+use crate::alert_routing::{self, RouteRule};
+use crate::alerts::{self, AlertSink, Severity};
+use crate::{config, ArbitrageOpportunity};
+
+const DEFAULT_RECENT_EVENTS: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventClass {
+    // A book failed parsing or validation during ingestion
+    BookRejected,
+    VenueStale,
+    VenueRecovered,
+    AllVenuesStale,
+    // A maintenance feed put a venue in or out of quarantine
+    VenueQuarantined,
+    VenueResumed,
+    // A venue that isn't vetted sent its first book and went into canary, see `canary.rs`
+    VenueCanary,
+    // The first book of a pair nobody has confirmed arrived, see `onboarding.rs`
+    PairOnboarding,
+    // The kill switch was tripped or reset
+    BreakerTripped,
+    BreakerReset,
+    // Reserved for config reloads, nothing publishes it yet
+    ConfigReloaded,
+    OpportunityDetected,
+    // A published opportunity stopped qualifying or was not confirmed within its TTL,
+    // or the execution request for one got no terminal update within its TTL
+    OpportunityExpired,
+    // Routes opened or closed since the previous comprehensive analysis, see `delta.rs`
+    ComprehensiveDelta,
+    // A published execution request got no ack within EXECUTION_ACK_TIMEOUT_MS, see `acks.rs`
+    ExecutionUnacknowledged,
+    // A refreshed withdrawal fee moved by more than WITHDRAWAL_FEE_ALERT_PCT, see `withdrawal_fees.rs`
+    WithdrawalFeeChanged,
+}
+
+impl EventClass {
+    pub const ALL: [EventClass; 16] = [
+        EventClass::BookRejected,
+        EventClass::VenueStale,
+        EventClass::VenueRecovered,
+        EventClass::AllVenuesStale,
+        EventClass::VenueQuarantined,
+        EventClass::VenueResumed,
+        EventClass::VenueCanary,
+        EventClass::PairOnboarding,
+        EventClass::BreakerTripped,
+        EventClass::BreakerReset,
+        EventClass::ConfigReloaded,
+        EventClass::OpportunityDetected,
+        EventClass::OpportunityExpired,
+        EventClass::ComprehensiveDelta,
+        EventClass::ExecutionUnacknowledged,
+        EventClass::WithdrawalFeeChanged,
+    ];
+
+    // Operational events every sink receives unless configured otherwise. The
+    // high-volume classes (rejections, opportunities) are opt-in.
+    pub const ALERTS: [EventClass; 12] = [
+        EventClass::VenueStale,
+        EventClass::VenueRecovered,
+        EventClass::AllVenuesStale,
+        EventClass::VenueQuarantined,
+        EventClass::VenueResumed,
+        EventClass::VenueCanary,
+        EventClass::PairOnboarding,
+        EventClass::BreakerTripped,
+        EventClass::BreakerReset,
+        EventClass::ConfigReloaded,
+        EventClass::ExecutionUnacknowledged,
+        EventClass::WithdrawalFeeChanged,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventClass::BookRejected => "book_rejected",
+            EventClass::VenueStale => "venue_stale",
+            EventClass::VenueRecovered => "venue_recovered",
+            EventClass::AllVenuesStale => "all_venues_stale",
+            EventClass::VenueQuarantined => "venue_quarantined",
+            EventClass::VenueResumed => "venue_resumed",
+            EventClass::VenueCanary => "venue_canary",
+            EventClass::PairOnboarding => "pair_onboarding",
+            EventClass::BreakerTripped => "breaker_tripped",
+            EventClass::BreakerReset => "breaker_reset",
+            EventClass::ConfigReloaded => "config_reloaded",
+            EventClass::OpportunityDetected => "opportunity_detected",
+            EventClass::OpportunityExpired => "opportunity_expired",
+            EventClass::ComprehensiveDelta => "comprehensive_delta",
+            EventClass::ExecutionUnacknowledged => "execution_unacknowledged",
+            EventClass::WithdrawalFeeChanged => "withdrawal_fee_changed",
+        }
+    }
+}
+
+impl fmt::Display for EventClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EventClass {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().to_lowercase();
+        EventClass::ALL.into_iter().find(|class| class.as_str() == s).ok_or_else(|| anyhow!("unknown event class: {}", s))
+    }
+}
+
+/// Parse a `<SINK>_EVENTS` value: a comma-separated list of classes, `all`, or `alerts`
+pub fn parse_classes(raw: &str) -> Result<HashSet<EventClass>> {
+    let mut classes = HashSet::new();
+    for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry {
+            "all" => classes.extend(EventClass::ALL),
+            "alerts" => classes.extend(EventClass::ALERTS),
+            class => {
+                classes.insert(class.parse()?);
+            }
+        }
+    }
+    Ok(classes)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    // Serialized as `kind`, which webhook consumers already key on
+    #[serde(rename = "kind")]
+    pub class: EventClass,
+    pub severity: Severity,
+    pub message: String,
+    pub venue: Option<String>,
+    pub pair: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opportunity: Option<ArbitrageOpportunity>,
+    pub raised_at: DateTime<Utc>,
+}
+
+impl Event {
+    pub fn new(class: EventClass, severity: Severity, message: String) -> Self {
+        Event { class, severity, message, venue: None, pair: None, opportunity: None, raised_at: Utc::now() }
+    }
+
+    pub fn with_venue(mut self, venue: &str) -> Self {
+        self.venue = Some(venue.to_string());
+        self
+    }
+
+    pub fn with_pair(mut self, pair: &str) -> Self {
+        self.pair = Some(pair.to_string());
+        self
+    }
+
+    pub fn opportunity_detected(opp: &ArbitrageOpportunity) -> Self {
+        let precision = crate::precision::policy();
+        let price = |venue: &str, price: f64| precision.format_price(venue, &opp.pair, price).unwrap_or_else(|| format!("{:.6}", price));
+        let size = precision.format_size(&opp.pair, &[&opp.buy_exchange, &opp.sell_exchange], opp.max_size);
+        let message = format!(
+            "{} buy {} @ {}, sell {} @ {}: ${:.2} net on {} ({:.3}% ROI)",
+            opp.pair,
+            opp.buy_exchange,
+            price(&opp.buy_exchange, opp.buy_price),
+            opp.sell_exchange,
+            price(&opp.sell_exchange, opp.sell_price),
+            opp.net_profit,
+            size.unwrap_or_else(|| format!("{:.6}", opp.max_size)),
+            opp.roi_percentage
+        );
+        Event {
+            class: EventClass::OpportunityDetected,
+            severity: Severity::Info,
+            message,
+            venue: None,
+            pair: Some(opp.pair.clone()),
+            opportunity: Some(opp.clone()),
+            raised_at: opp.timestamp,
+        }
+    }
+}
+
+/// Filter for the recent-events API
+#[derive(Debug, Default)]
+pub struct EventQuery {
+    pub classes: Option<HashSet<EventClass>>,
+    pub min_severity: Option<Severity>,
+    pub limit: Option<usize>,
+}
+
+impl EventQuery {
+    pub fn from_query(params: &std::collections::HashMap<String, String>) -> Result<Self> {
+        Ok(EventQuery {
+            classes: params.get("kind").map(|raw| parse_classes(raw)).transpose()?,
+            min_severity: params.get("min_severity").map(|raw| raw.parse()).transpose()?,
+            limit: params.get("limit").map(|raw| raw.parse().map_err(|_| anyhow!("invalid limit: {}", raw))).transpose()?,
+        })
+    }
+
+    fn matches(&self, event: &Event) -> bool {
+        self.classes.as_ref().is_none_or(|classes| classes.contains(&event.class))
+            && self.min_severity.is_none_or(|min| event.severity >= min)
+    }
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    sink: Box<dyn AlertSink>,
+    classes: HashSet<EventClass>,
+}
+
+#[derive(Debug)]
+pub struct EventBus {
+    subscribers: Vec<Subscriber>,
+    // None: every subscribed sink gets every event
+    rules: Option<Vec<RouteRule>>,
+    recent: Mutex<VecDeque<Event>>,
+    recent_limit: usize,
+}
+
+impl EventBus {
+    pub fn new(sinks: Vec<(Box<dyn AlertSink>, HashSet<EventClass>)>, rules: Option<Vec<RouteRule>>, recent_limit: usize) -> Result<Self> {
+        for rule in rules.iter().flatten() {
+            if let Some(unknown) = rule.sinks().iter().find(|name| !sinks.iter().any(|(s, _)| s.name() == name.as_str())) {
+                return Err(anyhow!("ALERT_ROUTES names sink {:?}, which is not configured", unknown));
+            }
+        }
+        Ok(EventBus {
+            subscribers: sinks.into_iter().map(|(sink, classes)| Subscriber { sink, classes }).collect(),
+            rules,
+            recent: Mutex::new(VecDeque::new()),
+            recent_limit,
+        })
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let mut sinks = Vec::new();
+        for sink in alerts::sinks_from_env() {
+            let var = format!("{}_EVENTS", sink.name().to_uppercase());
+            let classes = match config::env_var(&var) {
+                Ok(raw) => parse_classes(&raw).map_err(|e| anyhow!("{}: {}", var, e))?,
+                Err(_) => sink.default_events(),
+            };
+            let mut names: Vec<&str> = classes.iter().map(|c| c.as_str()).collect();
+            names.sort();
+            info!("  Sink {} subscribed to: {}", sink.name(), names.join(", "));
+            sinks.push((sink, classes));
+        }
+
+        let rules = match config::env_var("ALERT_ROUTES") {
+            Ok(raw) if !raw.trim().is_empty() => {
+                let rules = alert_routing::parse_rules(&raw)?;
+                info!("  Alert routing: {} rule(s)", rules.len());
+                Some(rules)
+            }
+            _ => None,
+        };
+        EventBus::new(sinks, rules, config::env_or("RECENT_EVENTS", DEFAULT_RECENT_EVENTS))
+    }
+
+    fn deliver(sink: &dyn AlertSink, event: &Event) {
+        if let Err(e) = sink.send(event) {
+            error!("Alert sink {} failed: {}", sink.name(), e);
+        }
+    }
+
+    /// Deliver `event` to its subscribers; a failing sink never blocks the others
+    pub fn publish(&self, event: Event) {
+        let subscribed = self.subscribers.iter().filter(|s| s.classes.contains(&event.class));
+        match &self.rules {
+            None => subscribed.for_each(|s| Self::deliver(s.sink.as_ref(), &event)),
+            Some(rules) => {
+                let rule = rules.iter().find(|r| r.matches(&event));
+                let routed = rule.map(|rule| Event { severity: rule.severity().unwrap_or(event.severity), ..event.clone() });
+                for subscriber in subscribed {
+                    let name = subscriber.sink.name();
+                    match (&routed, rule) {
+                        (Some(routed), Some(rule)) if rule.sinks().iter().any(|s| s == name) => {
+                            Self::deliver(subscriber.sink.as_ref(), routed)
+                        }
+                        // The log sink sees everything it subscribes to, routed or not
+                        _ if name == alerts::LOG_SINK => Self::deliver(subscriber.sink.as_ref(), &event),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let mut recent = self.recent.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        recent.push_back(event);
+        while recent.len() > self.recent_limit {
+            recent.pop_front();
+        }
+    }
+
+    /// Most recent events matching `query`, newest first
+    pub fn recent(&self, query: &EventQuery) -> Vec<Event> {
+        let recent = self.recent.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        recent.iter().rev().filter(|e| query.matches(e)).take(query.limit.unwrap_or(usize::MAX)).cloned().collect()
+    }
+
+    pub fn flush(&self, now: DateTime<Utc>) {
+        for subscriber in &self.subscribers {
+            subscriber.sink.flush(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    type Seen = Arc<Mutex<Vec<(Severity, EventClass)>>>;
+
+    #[derive(Debug)]
+    struct Recorder {
+        name: &'static str,
+        seen: Seen,
+    }
+
+    impl AlertSink for Recorder {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn send(&self, event: &Event) -> Result<()> {
+            self.seen.lock().unwrap().push((event.severity, event.class));
+            Ok(())
+        }
+    }
+
+    fn opportunity(pair: &str, roi_percentage: f64) -> ArbitrageOpportunity {
+        ArbitrageOpportunity {
+            id: "opp".to_string(),
+            buy_exchange: "binance".to_string(),
+            sell_exchange: "uniswap-v3-exact".to_string(),
+            pair: pair.to_string(),
+            buy_price: 100.0,
+            sell_price: 101.0,
+            max_size: 1.0,
+            sell_size: 1.0,
+            gross_profit_per_unit: 1.0,
+            estimated_fees: 0.5,
+            net_profit: 0.5,
+            roi_percentage,
+            capital_at_risk: None,
+            annualized_roi_percentage: None,
+            timestamp: Utc::now(),
+            competition: None,
+            laggard: None,
+            cluster: None,
+            depth: None,
+            book_ages: None,
+            anomaly_score: None,
+            size_ladder: Vec::new(),
+            gas_regime: None,
+            execution_success: None,
+            annotations: Default::default(),
+            tag: Default::default(),
+        }
+    }
+
+    fn bus(email_events: &str, routes: Option<&str>) -> (EventBus, Seen, Seen) {
+        let (pager, channel) = (Seen::default(), Seen::default());
+        let sinks: Vec<(Box<dyn AlertSink>, HashSet<EventClass>)> = vec![
+            (Box::new(Recorder { name: "email", seen: pager.clone() }), parse_classes(email_events).unwrap()),
+            (Box::new(Recorder { name: "webhook", seen: channel.clone() }), parse_classes("all").unwrap()),
+        ];
+        let rules = routes.map(|r| alert_routing::parse_rules(r).unwrap());
+        (EventBus::new(sinks, rules, 3).unwrap(), pager, channel)
+    }
+
+    #[test]
+    fn parses_class_lists() {
+        assert_eq!(parse_classes("all").unwrap().len(), EventClass::ALL.len());
+        let classes = parse_classes("alerts, opportunity_detected").unwrap();
+        assert!(classes.contains(&EventClass::VenueStale) && classes.contains(&EventClass::OpportunityDetected));
+        assert!(!classes.contains(&EventClass::BookRejected));
+        assert!(parse_classes("venue_stale,bogus").is_err());
+    }
+
+    #[test]
+    fn sinks_only_get_subscribed_classes() {
+        let (bus, pager, channel) = bus("venue_stale", None);
+        bus.publish(Event::new(EventClass::VenueStale, Severity::Warning, "quiet".to_string()));
+        bus.publish(Event::new(EventClass::BookRejected, Severity::Info, "bad book".to_string()));
+
+        assert_eq!(*pager.lock().unwrap(), vec![(Severity::Warning, EventClass::VenueStale)]);
+        assert_eq!(channel.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn routes_apply_within_subscriptions() {
+        let (bus, pager, channel) =
+            bus("all", Some("kind=opportunity_detected&pair=BTC/*&min_roi=0.5 -> email:critical; kind=opportunity_* -> webhook"));
+
+        bus.publish(Event::opportunity_detected(&opportunity("BTC/USDT", 0.8)));
+        bus.publish(Event::opportunity_detected(&opportunity("ETH/USDT", 0.8)));
+
+        assert_eq!(*pager.lock().unwrap(), vec![(Severity::Critical, EventClass::OpportunityDetected)]);
+        assert_eq!(*channel.lock().unwrap(), vec![(Severity::Info, EventClass::OpportunityDetected)]);
+    }
+
+    #[test]
+    fn recent_events_are_bounded_and_filtered() {
+        let (bus, _, _) = bus("alerts", None);
+        for class in [EventClass::BookRejected, EventClass::VenueStale, EventClass::BookRejected, EventClass::VenueRecovered] {
+            bus.publish(Event::new(class, Severity::Info, String::new()));
+        }
+
+        let all = bus.recent(&EventQuery::default());
+        assert_eq!(all.iter().map(|e| e.class).collect::<Vec<_>>(), vec![
+            EventClass::VenueRecovered,
+            EventClass::BookRejected,
+            EventClass::VenueStale
+        ]);
+        let query = EventQuery { classes: Some(parse_classes("book_rejected").unwrap()), ..Default::default() };
+        assert_eq!(bus.recent(&query).len(), 1);
+    }
+
+    #[test]
+    fn rules_must_name_configured_sinks() {
+        let sinks: Vec<(Box<dyn AlertSink>, HashSet<EventClass>)> = vec![(Box::new(alerts::LogAlertSink), parse_classes("all").unwrap())];
+        assert!(EventBus::new(sinks, Some(alert_routing::parse_rules("* -> pagerduty").unwrap()), 10).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+// Execution request schema handshake. Every execution request carries the
+// `schema_version` of its layout. With EXECUTOR_SCHEMA_KEY set, the executor
+// advertises what it reads in that Redis key (on the first source), e.g.
+//
+//   {"min_version": 1, "max_version": 1, "capabilities": ["timing", "gas", "atomic"]}
+//
+// It is read at startup and again every EXECUTOR_SCHEMA_REFRESH_SECS (default 60),
+// so either side can be upgraded first. Each request is then shaped for the
+// executor before it goes out:
+//  - a schema version outside the advertised range, or no readable advertisement
+//    at all, publishes nothing;
+//  - advisory sections the executor doesn't list (`timing`, `gas`,
+//    `pre_trade_checks`) are left out of the request;
+//  - a request that needs a section the executor doesn't list (`account_profile`,
+//    `netting`, `atomic`, `slices`) is not published, since executing it without
+//    would trade something other than what was priced.
+// Refused requests are logged and counted in
+// `swapsleuth_execution_requests_incompatible_total`. Without EXECUTOR_SCHEMA_KEY
+// requests go out whole, as before.
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use redis::{Client, Commands};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{config, SpreadAnalyzer};
+
+/// Layout of the execution requests this build publishes
+pub const SCHEMA_VERSION: u32 = 1;
+
+const DEFAULT_REFRESH_SECS: u64 = 60;
+
+// Optional parts of a request, and whether it can go out without them
+const SECTIONS: [(&str, Need); 7] = [
+    ("timing", Need::Advisory),
+    ("gas", Need::Advisory),
+    ("pre_trade_checks", Need::Advisory),
+    ("account_profile", Need::Required),
+    ("netting", Need::Required),
+    ("atomic", Need::Required),
+    ("slices", Need::Required),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Need {
+    Advisory,
+    Required,
+}
+
+/// What the executor advertises under EXECUTOR_SCHEMA_KEY
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecutorSchema {
+    #[serde(default = "first_version")]
+    pub min_version: u32,
+    pub max_version: u32,
+    #[serde(default)]
+    pub capabilities: BTreeSet<String>,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+impl ExecutorSchema {
+    pub fn accepts_version(&self, version: u32) -> bool {
+        (self.min_version..=self.max_version).contains(&version)
+    }
+}
+
+// Latest advertisement read, or why none could be, not yet applied
+type Offered = Arc<Mutex<Option<Result<ExecutorSchema, String>>>>;
+
+#[derive(Debug)]
+pub struct SchemaHandshake {
+    // None: no handshake, requests go out whole
+    key: Option<String>,
+    refresh: Duration,
+    offered: Offered,
+    // What the executor advertised last, or why it can't be used
+    agreed: Result<ExecutorSchema, String>,
+}
+
+impl SchemaHandshake {
+    pub fn new(key: Option<String>, refresh: Duration) -> Self {
+        let agreed = Err(format!("the executor has not advertised a schema at {} yet", key.as_deref().unwrap_or("-")));
+        SchemaHandshake { key, refresh, offered: Offered::default(), agreed }
+    }
+
+    pub fn from_env() -> Self {
+        let key = config::env_var("EXECUTOR_SCHEMA_KEY").ok().filter(|key| !key.trim().is_empty());
+        SchemaHandshake::new(key, Duration::from_secs(config::env_or("EXECUTOR_SCHEMA_REFRESH_SECS", DEFAULT_REFRESH_SECS)))
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Read the advertisement now, then again on a background thread every refresh period
+    pub fn spawn(&self, client: Client) {
+        let Some(key) = self.key.clone() else { return };
+        let offered = self.offered.clone();
+        let offer = move || {
+            let read = read_schema(&client, &key).map_err(|e| e.to_string());
+            *offered.lock().unwrap_or_else(|e| e.into_inner()) = Some(read);
+        };
+        offer();
+        info!("  Negotiating the execution request schema through {} every {}s", self.key.as_deref().unwrap_or_default(), self.refresh.as_secs());
+        let refresh = self.refresh;
+        thread::spawn(move || loop {
+            thread::sleep(refresh);
+            offer();
+        });
+    }
+
+    /// Take up the latest advertisement, logging when it changed
+    pub fn apply_offered(&mut self) {
+        let Some(offered) = self.offered.lock().unwrap_or_else(|e| e.into_inner()).take() else { return };
+        if offered == self.agreed {
+            return;
+        }
+        match &offered {
+            Ok(schema) if schema.accepts_version(SCHEMA_VERSION) => info!(
+                "Executor reads execution request schema {}..={} with {}; publishing schema {}",
+                schema.min_version,
+                schema.max_version,
+                schema.capabilities.iter().cloned().collect::<Vec<_>>().join(", "),
+                SCHEMA_VERSION
+            ),
+            Ok(schema) => warn!(
+                "Executor reads execution request schema {}..={}, not {}; execution requests are held back",
+                schema.min_version, schema.max_version, SCHEMA_VERSION
+            ),
+            Err(e) => warn!("Execution request schema unknown, requests are held back: {}", e),
+        }
+        self.agreed = offered;
+    }
+
+    /// `request` as the executor reads it, or why it can't be sent
+    pub fn adapt(&self, request: Value) -> Result<Value, String> {
+        if !self.enabled() {
+            return Ok(request);
+        }
+        let schema = self.agreed.as_ref().map_err(Clone::clone)?;
+        if !schema.accepts_version(SCHEMA_VERSION) {
+            return Err(format!("the executor reads schema {}..={}, this analyzer publishes {}", schema.min_version, schema.max_version, SCHEMA_VERSION));
+        }
+        let Value::Object(mut fields) = request else { return Ok(request) };
+        for (section, need) in SECTIONS {
+            if !fields.contains_key(section) || schema.capabilities.contains(section) {
+                continue;
+            }
+            match need {
+                Need::Advisory => {
+                    fields.remove(section);
+                }
+                Need::Required => return Err(format!("the request needs `{}`, which the executor does not support", section)),
+            }
+        }
+        Ok(Value::Object(fields))
+    }
+
+    /// For `GET /executor/schema`
+    pub fn report(&self) -> Value {
+        serde_json::json!({
+            "enabled": self.enabled(),
+            "key": self.key,
+            "schema_version": SCHEMA_VERSION,
+            "executor": self.agreed.as_ref().ok(),
+            "error": self.agreed.as_ref().err().filter(|_| self.enabled()),
+        })
+    }
+}
+
+fn read_schema(client: &Client, key: &str) -> Result<ExecutorSchema> {
+    let mut con = client.get_connection()?;
+    let raw: Option<String> = con.get(key)?;
+    let raw = raw.ok_or_else(|| anyhow!("{} is not set; the executor has not advertised a schema", key))?;
+    serde_json::from_str(&raw).map_err(|e| anyhow!("{} is not a schema advertisement: {}", key, e))
+}
+
+impl SpreadAnalyzer {
+    // Pick up the executor's latest advertisement
+    pub(crate) fn sync_executor_schema(&mut self) {
+        self.executor_schema.apply_offered();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn requests_are_shaped_for_the_advertised_schema() {
+        let request = json!({ "id": "r1", "schema_version": SCHEMA_VERSION, "timing": "cross", "gas": [], "atomic": { "executor": "0x1" } });
+        assert_eq!(SchemaHandshake::new(None, Duration::ZERO).adapt(request.clone()), Ok(request.clone()));
+
+        let mut handshake = SchemaHandshake::new(Some("swapsleuth:executor:schema".to_string()), Duration::ZERO);
+        assert!(handshake.adapt(request.clone()).unwrap_err().contains("not advertised"));
+
+        let advertise = |handshake: &mut SchemaHandshake, raw: &str| {
+            *handshake.offered.lock().unwrap() = Some(serde_json::from_str(raw).map_err(|e: serde_json::Error| e.to_string()));
+            handshake.apply_offered();
+        };
+        advertise(&mut handshake, r#"{"max_version": 1, "capabilities": ["atomic", "gas"]}"#);
+        let adapted = handshake.adapt(request.clone()).unwrap();
+        assert!(adapted.get("timing").is_none());
+        assert_eq!(adapted["atomic"], json!({ "executor": "0x1" }));
+
+        advertise(&mut handshake, r#"{"max_version": 1, "capabilities": ["timing"]}"#);
+        assert!(handshake.adapt(request.clone()).unwrap_err().contains("`atomic`"));
+        advertise(&mut handshake, r#"{"min_version": 2, "max_version": 3, "capabilities": ["atomic"]}"#);
+        assert!(handshake.adapt(request).unwrap_err().contains("2..=3"));
+        assert_eq!(handshake.report()["executor"]["min_version"], 2);
+    }
+}
@@ -0,0 +1,555 @@
+// Parameter sweeps over recorded books. `swapsleuth experiment --replay <file>
+// --grid <toml>` backtests the same books once per combination of the values the
+// grid lists, e.g.
+//
+//   name = "thresholds-vs-fees"
+//   [grid]
+//   min_profit = [1.0, 5.0, 10.0]
+//   cost_multiple = [0.0, 1.5]
+//   max_usd_size = [25000.0, 100000.0]
+//   fee_scale = [1.0, 1.25]
+//
+// Thresholds (`min_profit`, `min_roi_percentage`, `cost_multiple`), sizing
+// (`max_usd_size`, `min_depth_usd`) and fee assumptions (`use_market_orders`,
+// `fee_scale`: every trading fee of the built-in and config-file schedules scaled
+// by it) can be varied; a key left out keeps the configured value. Each run starts
+// from the daemon's configuration, like `--replay`, and never publishes.
+//
+// Every run is appended to a results table, so experiments can be compared across
+// invocations: a CSV file, or with `--features sqlite` a SQLite database when the
+// path ends in `.sqlite` or `.db` (table `experiment_runs`). Rows carry the
+// experiment's name and id, the replay file, the parameters and the run's
+// results. At the end the Pareto-best runs are printed: those no other run beats
+// on both simulated net profit and net profit per spread, i.e. the best trade-offs
+// between taking more spreads and taking better ones.
+
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use comfy_table::Cell;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::backtest::{self, RecordedBook};
+use crate::report::{self, OutputFormat, ReportPolicy};
+use crate::{config, FeesConfig, SpreadAnalyzer};
+
+pub const DEFAULT_RESULTS_FILE: &str = "swapsleuth-experiments.csv";
+
+// Runs past this many are most likely a grid typo
+const MAX_RUNS: usize = 10_000;
+
+/// The values each parameter takes; an empty list keeps the configured value
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Grid {
+    #[serde(default)]
+    pub min_profit: Vec<f64>,
+    #[serde(default)]
+    pub min_roi_percentage: Vec<f64>,
+    #[serde(default)]
+    pub cost_multiple: Vec<f64>,
+    #[serde(default)]
+    pub max_usd_size: Vec<f64>,
+    #[serde(default)]
+    pub min_depth_usd: Vec<f64>,
+    #[serde(default)]
+    pub use_market_orders: Vec<bool>,
+    #[serde(default)]
+    pub fee_scale: Vec<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GridFile {
+    name: Option<String>,
+    grid: Grid,
+}
+
+/// One point of the grid; None keeps the configured value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Params {
+    pub min_profit: Option<f64>,
+    pub min_roi_percentage: Option<f64>,
+    pub cost_multiple: Option<f64>,
+    pub max_usd_size: Option<f64>,
+    pub min_depth_usd: Option<f64>,
+    pub use_market_orders: Option<bool>,
+    pub fee_scale: Option<f64>,
+}
+
+// Each of `points` once per value of `values`; unchanged when there are none
+fn vary<T: Copy>(points: Vec<Params>, values: &[T], set: fn(&mut Params, T)) -> Vec<Params> {
+    if values.is_empty() {
+        return points;
+    }
+    points
+        .into_iter()
+        .flat_map(|point| {
+            values.iter().map(move |value| {
+                let mut point = point;
+                set(&mut point, *value);
+                point
+            })
+        })
+        .collect()
+}
+
+impl Grid {
+    /// Every combination, the last parameter varying fastest
+    pub fn points(&self) -> Vec<Params> {
+        let mut points = vec![Params::default()];
+        points = vary(points, &self.min_profit, |p, v| p.min_profit = Some(v));
+        points = vary(points, &self.min_roi_percentage, |p, v| p.min_roi_percentage = Some(v));
+        points = vary(points, &self.cost_multiple, |p, v| p.cost_multiple = Some(v));
+        points = vary(points, &self.max_usd_size, |p, v| p.max_usd_size = Some(v));
+        points = vary(points, &self.min_depth_usd, |p, v| p.min_depth_usd = Some(v));
+        points = vary(points, &self.use_market_orders, |p, v| p.use_market_orders = Some(v));
+        vary(points, &self.fee_scale, |p, v| p.fee_scale = Some(v))
+    }
+}
+
+impl Params {
+    /// e.g. `min_profit=5 fee_scale=1.25`; `configured` when nothing is varied
+    pub fn describe(&self) -> String {
+        let mut out = String::new();
+        let mut add = |name: &str, value: Option<String>| {
+            if let Some(value) = value {
+                let _ = write!(out, "{}{}={}", if out.is_empty() { "" } else { " " }, name, value);
+            }
+        };
+        add("min_profit", self.min_profit.map(|v| v.to_string()));
+        add("min_roi_percentage", self.min_roi_percentage.map(|v| v.to_string()));
+        add("cost_multiple", self.cost_multiple.map(|v| v.to_string()));
+        add("max_usd_size", self.max_usd_size.map(|v| v.to_string()));
+        add("min_depth_usd", self.min_depth_usd.map(|v| v.to_string()));
+        add("use_market_orders", self.use_market_orders.map(|v| v.to_string()));
+        add("fee_scale", self.fee_scale.map(|v| v.to_string()));
+        if out.is_empty() {
+            out.push_str("configured");
+        }
+        out
+    }
+
+    /// Set the varied parameters on an analyzer configured as the daemon would be
+    pub fn apply(&self, analyzer: &mut SpreadAnalyzer) -> Result<()> {
+        analyzer.thresholds = analyzer.thresholds.with(self.min_profit, self.min_roi_percentage)?.with_cost_multiple(self.cost_multiple)?;
+        for (name, value) in [("max_usd_size", self.max_usd_size), ("min_depth_usd", self.min_depth_usd), ("fee_scale", self.fee_scale)] {
+            if value.is_some_and(|v| !v.is_finite() || v < 0.0) {
+                return Err(anyhow!("invalid {}: {}", name, value.unwrap_or_default()));
+            }
+        }
+        if let Some(max_usd_size) = self.max_usd_size {
+            analyzer.sizing_config.max_usd_size = max_usd_size;
+        }
+        if let Some(min_depth) = self.min_depth_usd {
+            analyzer.sizing_config.min_depth = min_depth;
+        }
+        if let Some(use_market_orders) = self.use_market_orders {
+            analyzer.fees_config.use_market_orders = use_market_orders;
+        }
+        if let Some(scale) = self.fee_scale {
+            scale_trading_fees(&mut analyzer.fees_config, scale);
+        }
+        Ok(())
+    }
+}
+
+// Every trading fee percentage of the built-in and config-file schedules, times `scale`.
+// Gas, transfer and withdrawal costs are left alone
+fn scale_trading_fees(fees: &mut FeesConfig, scale: f64) {
+    for fee in [
+        &mut fees.binance_taker_fee,
+        &mut fees.binance_maker_fee,
+        &mut fees.okx_taker_fee,
+        &mut fees.okx_maker_fee,
+        &mut fees.bybit_taker_fee,
+        &mut fees.bybit_maker_fee,
+        &mut fees.uniswap_fee,
+        &mut fees.sushiswap_fee,
+        &mut fees.balancer_fee,
+        &mut fees.raydium_fee,
+        &mut fees.orca_fee,
+        &mut fees.osmosis_fee,
+        &mut fees.unknown_exchange_fee,
+    ] {
+        *fee *= scale;
+    }
+    for schedule in fees.exchange_schedules.values_mut() {
+        schedule.taker_fee *= scale;
+        if let Some(maker_fee) = &mut schedule.maker_fee {
+            *maker_fee *= scale;
+        }
+    }
+}
+
+/// One backtest of the grid, as recorded in the results table
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunResult {
+    pub experiment: String,
+    pub experiment_id: String,
+    pub run: usize,
+    pub started_at: DateTime<Utc>,
+    pub replay_file: String,
+    #[serde(flatten)]
+    pub params: Params,
+    pub books: usize,
+    pub opportunities: usize,
+    pub spreads: usize,
+    pub simulated_net_profit: f64,
+    pub net_profit_per_spread: f64,
+    pub profitable_pairs: usize,
+    // Not beaten on both profit and profit per spread by another run of the experiment
+    pub pareto: bool,
+}
+
+impl RunResult {
+    fn dominates(&self, other: &RunResult) -> bool {
+        self.simulated_net_profit >= other.simulated_net_profit
+            && self.net_profit_per_spread >= other.net_profit_per_spread
+            && (self.simulated_net_profit > other.simulated_net_profit || self.net_profit_per_spread > other.net_profit_per_spread)
+    }
+}
+
+/// Flag the runs no other run dominates
+pub fn mark_pareto(runs: &mut [RunResult]) {
+    let front: Vec<bool> = runs.iter().map(|run| !runs.iter().any(|other| other.dominates(run))).collect();
+    for (run, pareto) in runs.iter_mut().zip(front) {
+        run.pareto = pareto;
+    }
+}
+
+// ---------- Results table ----------
+
+const CSV_COLUMNS: [&str; 20] = [
+    "experiment",
+    "experiment_id",
+    "run",
+    "started_at",
+    "replay_file",
+    "min_profit",
+    "min_roi_percentage",
+    "cost_multiple",
+    "max_usd_size",
+    "min_depth_usd",
+    "use_market_orders",
+    "fee_scale",
+    "books",
+    "opportunities",
+    "spreads",
+    "simulated_net_profit",
+    "net_profit_per_spread",
+    "profitable_pairs",
+    "pareto",
+    "params",
+];
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_row(run: &RunResult) -> String {
+    let opt = |value: Option<f64>| value.map_or(String::new(), |v| v.to_string());
+    let fields = [
+        run.experiment.clone(),
+        run.experiment_id.clone(),
+        run.run.to_string(),
+        run.started_at.to_rfc3339(),
+        run.replay_file.clone(),
+        opt(run.params.min_profit),
+        opt(run.params.min_roi_percentage),
+        opt(run.params.cost_multiple),
+        opt(run.params.max_usd_size),
+        opt(run.params.min_depth_usd),
+        run.params.use_market_orders.map_or(String::new(), |v| v.to_string()),
+        opt(run.params.fee_scale),
+        run.books.to_string(),
+        run.opportunities.to_string(),
+        run.spreads.to_string(),
+        run.simulated_net_profit.to_string(),
+        run.net_profit_per_spread.to_string(),
+        run.profitable_pairs.to_string(),
+        run.pareto.to_string(),
+        run.params.describe(),
+    ];
+    fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(",")
+}
+
+/// Append `runs` to the CSV file at `path`, writing the header when the file is new
+pub fn append_csv(path: &Path, runs: &[RunResult]) -> Result<()> {
+    let new = std::fs::metadata(path).map_or(true, |meta| meta.len() == 0);
+    let mut file = OpenOptions::new().create(true).append(true).open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut out = String::new();
+    if new {
+        out.push_str(&CSV_COLUMNS.join(","));
+        out.push('\n');
+    }
+    for run in runs {
+        out.push_str(&csv_row(run));
+        out.push('\n');
+    }
+    file.write_all(out.as_bytes()).with_context(|| format!("writing {}", path.display()))?;
+    Ok(())
+}
+
+fn is_sqlite(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "sqlite" || ext == "db")
+}
+
+#[cfg(feature = "sqlite")]
+fn append_sqlite(path: &Path, runs: &[RunResult]) -> Result<()> {
+    sqlite::append(path, runs)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn append_sqlite(path: &Path, _runs: &[RunResult]) -> Result<()> {
+    Err(anyhow!("{} needs a build with --features sqlite; use a .csv file instead", path.display()))
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use std::path::Path;
+    use std::str::FromStr;
+
+    use anyhow::Result;
+    use sqlx::sqlite::SqliteConnectOptions;
+    use sqlx::{ConnectOptions, Connection};
+
+    use super::RunResult;
+
+    const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS experiment_runs (
+        experiment TEXT NOT NULL,
+        experiment_id TEXT NOT NULL,
+        run INTEGER NOT NULL,
+        started_at TEXT NOT NULL,
+        replay_file TEXT NOT NULL,
+        min_profit REAL,
+        min_roi_percentage REAL,
+        cost_multiple REAL,
+        max_usd_size REAL,
+        min_depth_usd REAL,
+        use_market_orders INTEGER,
+        fee_scale REAL,
+        books INTEGER NOT NULL,
+        opportunities INTEGER NOT NULL,
+        spreads INTEGER NOT NULL,
+        simulated_net_profit REAL NOT NULL,
+        net_profit_per_spread REAL NOT NULL,
+        profitable_pairs INTEGER NOT NULL,
+        pareto INTEGER NOT NULL,
+        params TEXT NOT NULL,
+        PRIMARY KEY (experiment_id, run)
+    )";
+
+    pub fn append(path: &Path, runs: &[RunResult]) -> Result<()> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        runtime.block_on(async {
+            let options = SqliteConnectOptions::from_str(&format!("sqlite://{}", path.display()))?.create_if_missing(true);
+            let mut con = options.connect().await?;
+            sqlx::query(SCHEMA).execute(&mut con).await?;
+            let mut tx = con.begin().await?;
+            for run in runs {
+                sqlx::query("INSERT INTO experiment_runs VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+                    .bind(&run.experiment)
+                    .bind(&run.experiment_id)
+                    .bind(run.run as i64)
+                    .bind(run.started_at.to_rfc3339())
+                    .bind(&run.replay_file)
+                    .bind(run.params.min_profit)
+                    .bind(run.params.min_roi_percentage)
+                    .bind(run.params.cost_multiple)
+                    .bind(run.params.max_usd_size)
+                    .bind(run.params.min_depth_usd)
+                    .bind(run.params.use_market_orders)
+                    .bind(run.params.fee_scale)
+                    .bind(run.books as i64)
+                    .bind(run.opportunities as i64)
+                    .bind(run.spreads as i64)
+                    .bind(run.simulated_net_profit)
+                    .bind(run.net_profit_per_spread)
+                    .bind(run.profitable_pairs as i64)
+                    .bind(run.pareto)
+                    .bind(run.params.describe())
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await?;
+            Ok(())
+        })
+    }
+}
+
+/// Append `runs` to the results table at `path`: SQLite for `.sqlite` / `.db`, CSV otherwise
+pub fn record(path: &Path, runs: &[RunResult]) -> Result<()> {
+    if is_sqlite(path) {
+        append_sqlite(path, runs)
+    } else {
+        append_csv(path, runs)
+    }
+}
+
+// ---------- Running ----------
+
+/// Backtest `books` once per point of `grid`, on analyzers configured as the daemon would be
+pub fn sweep(
+    name: &str,
+    replay_file: &str,
+    grid: &Grid,
+    books: &[RecordedBook],
+    mut analyzer: impl FnMut() -> Result<SpreadAnalyzer>,
+) -> Result<Vec<RunResult>> {
+    let points = grid.points();
+    if points.len() > MAX_RUNS {
+        return Err(anyhow!("the grid has {} combinations, more than {}", points.len(), MAX_RUNS));
+    }
+    let experiment_id = Uuid::new_v4().to_string();
+    let mut runs = Vec::with_capacity(points.len());
+    for (run, params) in points.into_iter().enumerate() {
+        let mut analyzer = analyzer()?;
+        params.apply(&mut analyzer).with_context(|| format!("run {} ({})", run + 1, params.describe()))?;
+        let started_at = Utc::now();
+        let result = backtest::replay(&mut analyzer, books.to_vec(), 0.0)?;
+        info!("  Run {}: {} -> {} spreads, ${:.2}", run + 1, params.describe(), result.spreads, result.simulated_net_profit);
+        runs.push(RunResult {
+            experiment: name.to_string(),
+            experiment_id: experiment_id.clone(),
+            run: run + 1,
+            started_at,
+            replay_file: replay_file.to_string(),
+            params,
+            books: result.books,
+            opportunities: result.opportunities,
+            spreads: result.spreads,
+            simulated_net_profit: result.simulated_net_profit,
+            net_profit_per_spread: if result.spreads > 0 { result.simulated_net_profit / result.spreads as f64 } else { 0.0 },
+            profitable_pairs: result.pairs.iter().filter(|pair| pair.net_profit > 0.0).count(),
+            pareto: false,
+        });
+    }
+    mark_pareto(&mut runs);
+    Ok(runs)
+}
+
+fn print(runs: &[RunResult], results: &Path, format: OutputFormat) {
+    let mut best: Vec<&RunResult> = runs.iter().filter(|run| run.pareto).collect();
+    best.sort_by(|a, b| b.simulated_net_profit.total_cmp(&a.simulated_net_profit));
+    if format == OutputFormat::Jsonl {
+        for run in runs {
+            report::emit("experiment_run", serde_json::to_value(run).unwrap_or_default());
+        }
+        let pareto: Vec<usize> = best.iter().map(|run| run.run).collect();
+        report::emit("experiment", json!({ "experiment": runs.first().map(|run| &run.experiment), "runs": runs.len(), "pareto": pareto, "results": results }));
+        return;
+    }
+    let Some(first) = runs.first() else { return };
+    println!("Experiment {} ({}): {} runs over {} books, recorded in {}", first.experiment, first.experiment_id, runs.len(), first.books, results.display());
+    let mut table = report::table(&["Run", "Parameters", "Opportunities", "Spreads", "Net profit", "Per spread"], 2..6);
+    for run in &best {
+        table.add_row(vec![
+            Cell::new(run.run),
+            Cell::new(run.params.describe()),
+            Cell::new(run.opportunities),
+            Cell::new(run.spreads),
+            Cell::new(format!("${:.2}", run.simulated_net_profit)),
+            Cell::new(format!("${:.2}", run.net_profit_per_spread)),
+        ]);
+    }
+    println!("Pareto-best configurations (no other run has both more net profit and more per spread):");
+    println!("{}", table);
+}
+
+/// The `experiment` subcommand
+pub fn run(replay: &Path, grid_path: &Path, results: Option<PathBuf>, name: Option<String>, output: OutputFormat, config_path: Option<&Path>) -> Result<()> {
+    let raw = std::fs::read_to_string(grid_path).with_context(|| format!("reading {}", grid_path.display()))?;
+    let file: GridFile = toml::from_str(&raw).map_err(|e| anyhow!("{}: {}", grid_path.display(), e))?;
+    let name = name.or(file.name).unwrap_or_else(|| grid_path.file_stem().map_or("experiment".to_string(), |stem| stem.to_string_lossy().into_owned()));
+    let results = results.or_else(|| config::env_var("EXPERIMENT_RESULTS").ok().map(PathBuf::from)).unwrap_or_else(|| PathBuf::from(DEFAULT_RESULTS_FILE));
+    if is_sqlite(&results) && !cfg!(feature = "sqlite") {
+        return Err(anyhow!("{} needs a build with --features sqlite; use a .csv file instead", results.display()));
+    }
+
+    let (books, skipped) = backtest::read_books(backtest::open(replay)?)?;
+    if skipped > 0 {
+        warn!("Skipped {} lines of {} that were not timestamped books", skipped, replay.display());
+    }
+    info!("  Experiment {}: {} runs over {} books from {}", name, file.grid.points().len(), books.len(), replay.display());
+    let runs = sweep(&name, &replay.display().to_string(), &file.grid, &books, || {
+        let mut analyzer = backtest::observer(output, config_path)?;
+        analyzer.reporter.policy = ReportPolicy::None;
+        Ok(analyzer)
+    })?;
+    record(&results, &runs)?;
+    print(&runs, &results, output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> GridFile {
+        toml::from_str("name = \"t\"\n[grid]\nmin_profit = [1.0, 400.0]\nfee_scale = [1.0, 2.0]\nuse_market_orders = [true]\n").unwrap()
+    }
+
+    fn runs() -> Vec<RunResult> {
+        let recorded = [
+            r#"{"exchange":"binance","pair":"BTC/USDT","bids":[[49990.0,1.2]],"asks":[[50000.0,1.0]],"timestamp":1704067200000}"#,
+            r#"{"exchange":"okx","pair":"BTC/USDT","bids":[[50800.0,1.5]],"asks":[[50810.0,1.1]],"timestamp":1704067201}"#,
+            r#"{"exchange":"binance","pair":"ETH/USDT","bids":[[2999.0,10.0]],"asks":[[3000.0,10.0]],"timestamp":1704067202}"#,
+            r#"{"exchange":"okx","pair":"ETH/USDT","bids":[[3020.0,10.0]],"asks":[[3021.0,10.0]],"timestamp":1704067203}"#,
+        ]
+        .join("\n");
+        let (books, _) = backtest::read_books(recorded.as_bytes()).unwrap();
+        sweep("t", "books.jsonl", &grid().grid, &books, || {
+            let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379")?;
+            analyzer.reporter.policy = ReportPolicy::None;
+            Ok(analyzer)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn the_grid_expands_to_every_combination() {
+        let points = grid().grid.points();
+        assert_eq!(points.len(), 4);
+        assert_eq!(points[1].describe(), "min_profit=1 use_market_orders=true fee_scale=2");
+        assert_eq!(Grid::default().points(), vec![Params::default()]);
+    }
+
+    #[test]
+    fn sweeps_the_grid_and_keeps_the_pareto_front() {
+        let runs = runs();
+        assert_eq!(runs.len(), 4);
+        // The low bar takes both spreads, the high one only BTC's, with more profit per spread
+        assert_eq!((runs[0].spreads, runs[2].spreads), (2, 1));
+        assert!(runs[0].simulated_net_profit > runs[2].simulated_net_profit);
+        assert!(runs[2].net_profit_per_spread > runs[0].net_profit_per_spread);
+        // Doubled fees only cost profit, so those runs are dominated
+        assert!(runs[1].simulated_net_profit < runs[0].simulated_net_profit);
+        assert_eq!(runs.iter().map(|run| run.pareto).collect::<Vec<_>>(), vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn runs_are_appended_under_one_header() {
+        let runs = runs();
+        let results = std::env::temp_dir().join(format!("swapsleuth-experiments-{}.csv", std::process::id()));
+        record(&results, &runs[..2]).unwrap();
+        record(&results, &runs[2..]).unwrap();
+        let written = std::fs::read_to_string(&results).unwrap();
+        std::fs::remove_file(&results).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("experiment,experiment_id,run,"));
+        assert!(lines[3].contains(",400,,,,,true,1,"));
+    }
+}
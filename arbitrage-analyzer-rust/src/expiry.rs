@@ -0,0 +1,230 @@
+// Expiry of published opportunities. A route is live from the first analysis
+// that finds it profitable until one of:
+//  - an analysis that re-evaluates the route (any update on one of its venues,
+//    or a comprehensive pass) no longer finds it, e.g. the spread closed or one
+//    of its venues went suspect or into maintenance,
+//  - no analysis has confirmed it for OPPORTUNITY_TTL_SECS, e.g. its books
+//    stopped updating.
+// Either way an `opportunity_expired` message referencing the id the route was
+// first published under goes out, so consumers can drop it without a timeout of
+// their own. Every re-detection while live still gets a fresh id; the message
+// carries the latest one too.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::capital::CapitalAtRisk;
+use crate::lifecycle::RouteKey;
+use crate::profiles::StrategyTag;
+use crate::ArbitrageOpportunity;
+
+pub const DEFAULT_OPPORTUNITY_TTL_SECS: i64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryReason {
+    // Re-evaluated and no longer profitable or tradable
+    NoLongerQualifies,
+    // Not confirmed by any analysis within the TTL
+    TtlElapsed,
+}
+
+impl ExpiryReason {
+    pub fn describe(self) -> &'static str {
+        match self {
+            ExpiryReason::NoLongerQualifies => "no longer qualifies",
+            ExpiryReason::TtlElapsed => "not confirmed within its TTL",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct LiveOpportunity {
+    first_id: String,
+    latest_id: String,
+    peak_net_profit: f64,
+    capital_at_risk: Option<CapitalAtRisk>,
+    detected_at: DateTime<Utc>,
+    confirmed_at: DateTime<Utc>,
+    tag: StrategyTag,
+}
+
+/// Published when a live opportunity goes away
+#[derive(Debug, Clone, Serialize)]
+pub struct OpportunityExpiry {
+    // Always `opportunity_expired`; lets consumers tell it from the messages it shares a channel with
+    pub kind: &'static str,
+    // Id the route was first published under, and the id of its last detection
+    pub opportunity_id: String,
+    pub latest_opportunity_id: String,
+    pub pair: String,
+    pub buy_exchange: String,
+    pub sell_exchange: String,
+    pub reason: ExpiryReason,
+    // Best net profit any detection offered while the route was live, and the capital of the latest one
+    pub peak_net_profit: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capital_at_risk: Option<CapitalAtRisk>,
+    pub detected_at: DateTime<Utc>,
+    pub expired_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub tag: StrategyTag,
+}
+
+#[derive(Debug)]
+pub struct LiveOpportunities {
+    ttl: Duration,
+    live: HashMap<RouteKey, LiveOpportunity>,
+}
+
+impl LiveOpportunities {
+    pub fn new(ttl: Duration) -> Self {
+        LiveOpportunities { ttl, live: HashMap::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.live.len()
+    }
+
+    pub fn routes(&self) -> impl Iterator<Item = &RouteKey> {
+        self.live.keys()
+    }
+
+    /// Whether `opp` is the detection its route went live with, rather than a repeat
+    pub fn first_detected_by(&self, opp: &ArbitrageOpportunity) -> bool {
+        self.live.get(&RouteKey::new(&opp.pair, &opp.buy_exchange, &opp.sell_exchange)).is_some_and(|live| live.first_id == opp.id)
+    }
+
+    fn expire(&mut self, route: &RouteKey, reason: ExpiryReason, now: DateTime<Utc>) -> Option<OpportunityExpiry> {
+        let live = self.live.remove(route)?;
+        Some(OpportunityExpiry {
+            kind: "opportunity_expired",
+            opportunity_id: live.first_id,
+            latest_opportunity_id: live.latest_id,
+            pair: route.pair.clone(),
+            buy_exchange: route.buy_exchange.clone(),
+            sell_exchange: route.sell_exchange.clone(),
+            reason,
+            peak_net_profit: live.peak_net_profit,
+            capital_at_risk: live.capital_at_risk,
+            detected_at: live.detected_at,
+            expired_at: now,
+            tag: live.tag,
+        })
+    }
+
+    /// Record the outcome of an analysis: `found` is everything it produced and
+    /// `evaluated` says which routes it covered. Live routes it covered without
+    /// finding them again are expired and returned
+    pub fn observe(
+        &mut self,
+        found: &[ArbitrageOpportunity],
+        evaluated: impl Fn(&RouteKey) -> bool,
+        now: DateTime<Utc>,
+    ) -> Vec<OpportunityExpiry> {
+        let found_routes: HashMap<RouteKey, &ArbitrageOpportunity> =
+            found.iter().map(|opp| (RouteKey::new(&opp.pair, &opp.buy_exchange, &opp.sell_exchange), opp)).collect();
+
+        let mut gone: Vec<RouteKey> = self.live.keys().filter(|r| evaluated(r) && !found_routes.contains_key(*r)).cloned().collect();
+        gone.sort();
+        let expired = gone.iter().filter_map(|route| self.expire(route, ExpiryReason::NoLongerQualifies, now)).collect();
+
+        for (route, opp) in found_routes {
+            let live = self.live.entry(route).or_insert_with(|| LiveOpportunity {
+                first_id: opp.id.clone(),
+                latest_id: opp.id.clone(),
+                peak_net_profit: opp.net_profit,
+                capital_at_risk: opp.capital_at_risk,
+                detected_at: now,
+                confirmed_at: now,
+                tag: opp.tag.clone(),
+            });
+            live.latest_id = opp.id.clone();
+            live.peak_net_profit = live.peak_net_profit.max(opp.net_profit);
+            live.capital_at_risk = opp.capital_at_risk;
+            live.confirmed_at = now;
+        }
+        expired
+    }
+
+    /// Expire routes no analysis has confirmed within the TTL
+    pub fn expire_stale(&mut self, now: DateTime<Utc>) -> Vec<OpportunityExpiry> {
+        let mut stale: Vec<RouteKey> = self.live.iter().filter(|(_, l)| now - l.confirmed_at > self.ttl).map(|(r, _)| r.clone()).collect();
+        stale.sort();
+        stale.iter().filter_map(|route| self.expire(route, ExpiryReason::TtlElapsed, now)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opportunity(id: &str, buy: &str, sell: &str) -> ArbitrageOpportunity {
+        ArbitrageOpportunity {
+            id: id.to_string(),
+            buy_exchange: buy.to_string(),
+            sell_exchange: sell.to_string(),
+            pair: "BTC/USDT".to_string(),
+            buy_price: 100.0,
+            sell_price: 101.0,
+            max_size: 1.0,
+            sell_size: 1.0,
+            gross_profit_per_unit: 1.0,
+            estimated_fees: 0.5,
+            net_profit: 0.5,
+            roi_percentage: 0.5,
+            capital_at_risk: None,
+            annualized_roi_percentage: None,
+            timestamp: Utc::now(),
+            competition: None,
+            laggard: None,
+            cluster: None,
+            depth: None,
+            book_ages: None,
+            anomaly_score: None,
+            size_ladder: Vec::new(),
+            gas_regime: None,
+            execution_success: None,
+            annotations: Default::default(),
+            tag: Default::default(),
+        }
+    }
+
+    #[test]
+    fn expires_evaluated_routes_that_were_not_found_again() {
+        let mut live = LiveOpportunities::new(Duration::seconds(30));
+        let start = Utc::now();
+        let found = [opportunity("a1", "binance", "okx"), opportunity("b1", "bybit", "kraken")];
+        assert!(live.observe(&found, |_| true, start).is_empty());
+        assert!(live.observe(&[opportunity("a2", "binance", "okx")], |r| r.buy_exchange == "binance", start).is_empty());
+        assert_eq!(live.len(), 2);
+
+        // An analysis that only covered okx routes says nothing about bybit -> kraken
+        let expired = live.observe(&[], |r| r.sell_exchange == "okx", start + Duration::seconds(1));
+        assert_eq!(expired.len(), 1);
+        assert_eq!((expired[0].opportunity_id.as_str(), expired[0].latest_opportunity_id.as_str()), ("a1", "a2"));
+        assert_eq!(expired[0].reason, ExpiryReason::NoLongerQualifies);
+        assert_eq!(live.len(), 1);
+
+        // Found again later, it is a new opportunity with its own first id
+        live.observe(&[opportunity("a3", "binance", "okx")], |r| r.buy_exchange == "binance", start + Duration::seconds(2));
+        let expired = live.observe(&[], |_| true, start + Duration::seconds(3));
+        assert_eq!(expired.iter().map(|e| e.opportunity_id.as_str()).collect::<Vec<_>>(), vec!["a3", "b1"]);
+    }
+
+    #[test]
+    fn expires_routes_not_confirmed_within_the_ttl() {
+        let mut live = LiveOpportunities::new(Duration::seconds(30));
+        let start = Utc::now();
+        live.observe(&[opportunity("a1", "binance", "okx")], |_| true, start);
+        live.observe(&[opportunity("b1", "bybit", "kraken")], |_| false, start + Duration::seconds(20));
+        assert!(live.expire_stale(start + Duration::seconds(30)).is_empty());
+
+        let expired = live.expire_stale(start + Duration::seconds(31));
+        assert_eq!(expired.len(), 1);
+        assert_eq!((expired[0].opportunity_id.as_str(), expired[0].reason), ("a1", ExpiryReason::TtlElapsed));
+        assert_eq!(live.len(), 1);
+    }
+}
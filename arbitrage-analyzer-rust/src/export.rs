@@ -0,0 +1,261 @@
+// Periodic Parquet export of opportunities and spread time series for research.
+// Enabled by building with `--features parquet` and setting PARQUET_EXPORT_DIR.
+// Rows are buffered in memory and written every PARQUET_EXPORT_SECS on a
+// background thread, hive-partitioned by day:
+//   <dir>/opportunities/date=2024-01-31/opportunities-20240131T120000.000Z.parquet
+//   <dir>/spreads/date=2024-01-31/spreads-20240131T120000.000Z.parquet
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use crate::lifecycle::RouteKey;
+use crate::{config, ArbitrageOpportunity};
+
+const DEFAULT_EXPORT_SECS: u64 = 300;
+
+/// One top-of-book spread observation on a route
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "parquet"), allow(dead_code))]
+pub struct SpreadRow {
+    pub recorded_at: DateTime<Utc>,
+    pub route: RouteKey,
+    pub spread_bps: f64,
+    pub executable_size: f64,
+    pub buy_price: f64,
+}
+
+#[derive(Debug)]
+pub struct ParquetExporter {
+    // None when export is disabled; nothing is buffered then
+    dir: Option<PathBuf>,
+    interval: Duration,
+    last_export: Instant,
+    opportunities: Vec<ArbitrageOpportunity>,
+    spreads: Vec<SpreadRow>,
+}
+
+impl ParquetExporter {
+    pub fn from_env() -> Self {
+        let dir = config::env_var("PARQUET_EXPORT_DIR").ok().map(PathBuf::from);
+        if dir.is_some() && !cfg!(feature = "parquet") {
+            log::warn!("PARQUET_EXPORT_DIR is set but this build has no parquet support; export is disabled");
+        }
+        ParquetExporter {
+            dir: dir.filter(|_| cfg!(feature = "parquet")),
+            interval: Duration::from_secs(config::env_or("PARQUET_EXPORT_SECS", DEFAULT_EXPORT_SECS)),
+            last_export: Instant::now(),
+            opportunities: Vec::new(),
+            spreads: Vec::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    pub fn dir(&self) -> Option<&std::path::Path> {
+        self.dir.as_deref()
+    }
+
+    pub fn push_opportunity(&mut self, opportunity: &ArbitrageOpportunity) {
+        if self.dir.is_some() {
+            self.opportunities.push(opportunity.clone());
+        }
+    }
+
+    pub fn push_spread(&mut self, row: SpreadRow) {
+        if self.dir.is_some() {
+            self.spreads.push(row);
+        }
+    }
+
+    /// Hand the buffered rows to a writer thread once the export interval has passed
+    pub fn flush_if_due(&mut self, now: Instant) {
+        if now.duration_since(self.last_export) < self.interval {
+            return;
+        }
+        self.last_export = now;
+
+        let Some(dir) = self.dir.clone() else { return };
+        if self.opportunities.is_empty() && self.spreads.is_empty() {
+            return;
+        }
+        let opportunities = std::mem::take(&mut self.opportunities);
+        let spreads = std::mem::take(&mut self.spreads);
+        std::thread::spawn(move || write_batch(&dir, opportunities, spreads, Utc::now()));
+    }
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_batch(_dir: &std::path::Path, _opportunities: Vec<ArbitrageOpportunity>, _spreads: Vec<SpreadRow>, _now: DateTime<Utc>) {}
+
+#[cfg(feature = "parquet")]
+use parquet_writer::write_batch;
+
+#[cfg(feature = "parquet")]
+mod parquet_writer {
+    use std::collections::BTreeMap;
+    use std::fs::{self, File};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, TimestampMicrosecondArray};
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use chrono::{DateTime, NaiveDate, Utc};
+    use log::{error, info};
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+    use parquet::file::properties::WriterProperties;
+
+    use super::SpreadRow;
+    use crate::ArbitrageOpportunity;
+
+    pub fn write_batch(dir: &Path, opportunities: Vec<ArbitrageOpportunity>, spreads: Vec<SpreadRow>, now: DateTime<Utc>) {
+        let written = write_dataset(dir, "opportunities", &opportunities, |o| o.timestamp, opportunities_batch, now)
+            .and_then(|files| Ok(files + write_dataset(dir, "spreads", &spreads, |s| s.recorded_at, spreads_batch, now)?));
+        match written {
+            Ok(files) => info!(
+                "Exported {} opportunities and {} spread samples to {} Parquet files",
+                opportunities.len(),
+                spreads.len(),
+                files
+            ),
+            Err(e) => error!("Parquet export to {} failed: {}", dir.display(), e),
+        }
+    }
+
+    /// Write `rows` as one file per day partition, returning the number of files written
+    pub(super) fn write_dataset<T>(
+        dir: &Path,
+        dataset: &str,
+        rows: &[T],
+        timestamp: impl Fn(&T) -> DateTime<Utc>,
+        to_batch: impl Fn(&[&T]) -> Result<RecordBatch>,
+        now: DateTime<Utc>,
+    ) -> Result<usize> {
+        let mut partitions: BTreeMap<NaiveDate, Vec<&T>> = BTreeMap::new();
+        for row in rows {
+            partitions.entry(timestamp(row).date_naive()).or_default().push(row);
+        }
+
+        for (date, rows) in &partitions {
+            let partition: PathBuf = dir.join(dataset).join(format!("date={}", date));
+            fs::create_dir_all(&partition)?;
+            let path = partition.join(format!("{}-{}.parquet", dataset, now.format("%Y%m%dT%H%M%S%.3fZ")));
+
+            let batch = to_batch(rows)?;
+            let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+            let mut writer = ArrowWriter::try_new(File::create(&path)?, batch.schema(), Some(properties))?;
+            writer.write(&batch)?;
+            writer.close()?;
+        }
+        Ok(partitions.len())
+    }
+
+    fn timestamp_field(name: &str) -> Field {
+        Field::new(name, DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), false)
+    }
+
+    fn strings<T>(rows: &[&T], f: impl Fn(&T) -> &str) -> ArrayRef {
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| f(r))))
+    }
+
+    fn floats<T>(rows: &[&T], f: impl Fn(&T) -> f64) -> ArrayRef {
+        Arc::new(Float64Array::from_iter_values(rows.iter().map(|r| f(r))))
+    }
+
+    fn timestamps<T>(rows: &[&T], f: impl Fn(&T) -> DateTime<Utc>) -> ArrayRef {
+        Arc::new(TimestampMicrosecondArray::from_iter_values(rows.iter().map(|r| f(r).timestamp_micros())).with_timezone("UTC"))
+    }
+
+    pub(super) fn opportunities_batch(rows: &[&ArbitrageOpportunity]) -> Result<RecordBatch> {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Utf8, false),
+            timestamp_field("detected_at"),
+            Field::new("pair", DataType::Utf8, false),
+            Field::new("buy_exchange", DataType::Utf8, false),
+            Field::new("sell_exchange", DataType::Utf8, false),
+            Field::new("buy_price", DataType::Float64, false),
+            Field::new("sell_price", DataType::Float64, false),
+            Field::new("max_size", DataType::Float64, false),
+            Field::new("sell_size", DataType::Float64, false),
+            Field::new("gross_profit_per_unit", DataType::Float64, false),
+            Field::new("estimated_fees", DataType::Float64, false),
+            Field::new("net_profit", DataType::Float64, false),
+            Field::new("roi_percentage", DataType::Float64, false),
+        ]);
+        let columns = vec![
+            strings(rows, |o| &o.id),
+            timestamps(rows, |o| o.timestamp),
+            strings(rows, |o| &o.pair),
+            strings(rows, |o| &o.buy_exchange),
+            strings(rows, |o| &o.sell_exchange),
+            floats(rows, |o| o.buy_price),
+            floats(rows, |o| o.sell_price),
+            floats(rows, |o| o.max_size),
+            floats(rows, |o| o.sell_size),
+            floats(rows, |o| o.gross_profit_per_unit),
+            floats(rows, |o| o.estimated_fees),
+            floats(rows, |o| o.net_profit),
+            floats(rows, |o| o.roi_percentage),
+        ];
+        Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+    }
+
+    pub(super) fn spreads_batch(rows: &[&SpreadRow]) -> Result<RecordBatch> {
+        let schema = Schema::new(vec![
+            timestamp_field("recorded_at"),
+            Field::new("pair", DataType::Utf8, false),
+            Field::new("buy_exchange", DataType::Utf8, false),
+            Field::new("sell_exchange", DataType::Utf8, false),
+            Field::new("spread_bps", DataType::Float64, false),
+            Field::new("executable_size", DataType::Float64, false),
+            Field::new("buy_price", DataType::Float64, false),
+        ]);
+        let columns = vec![
+            timestamps(rows, |s| s.recorded_at),
+            strings(rows, |s| &s.route.pair),
+            strings(rows, |s| &s.route.buy_exchange),
+            strings(rows, |s| &s.route.sell_exchange),
+            floats(rows, |s| s.spread_bps),
+            floats(rows, |s| s.executable_size),
+            floats(rows, |s| s.buy_price),
+        ];
+        Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+    }
+}
+
+#[cfg(all(test, feature = "parquet"))]
+mod tests {
+    use super::parquet_writer::{spreads_batch, write_dataset};
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    #[test]
+    fn spreads_are_partitioned_by_day() {
+        let dir = std::env::temp_dir().join(format!("swapsleuth-export-{}", uuid::Uuid::new_v4()));
+        let day_one = DateTime::parse_from_rfc3339("2024-01-31T23:59:00Z").unwrap().with_timezone(&Utc);
+        let row = |recorded_at| SpreadRow {
+            recorded_at,
+            route: RouteKey::new("BTC/USDT", "binance", "uniswap-v3-exact"),
+            spread_bps: 12.5,
+            executable_size: 0.4,
+            buy_price: 50000.0,
+        };
+        let rows = vec![row(day_one), row(day_one), row(day_one + chrono::Duration::minutes(2))];
+
+        let files = write_dataset(&dir, "spreads", &rows, |s| s.recorded_at, spreads_batch, Utc::now()).unwrap();
+        assert_eq!(files, 2);
+
+        let partition = dir.join("spreads").join("date=2024-01-31");
+        let file = std::fs::read_dir(&partition).unwrap().next().unwrap().unwrap().path();
+        let reader = SerializedFileReader::new(std::fs::File::open(file).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        assert!(dir.join("spreads").join("date=2024-02-01").is_dir());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+// Route feasibility: whether a transfer route can actually be carried out right
+// now. Buying on A and selling on B needs the pair trading on both venues, the
+// base asset withdrawable from A and depositable on B. A background thread polls
+// the venues' status endpoints every VENUE_STATUS_REFRESH_SECS and the analyzer
+// checks the cached status before emitting an execution request.
+//
+// Listings come from public endpoints. Deposit/withdrawal status is private on
+// every venue; it is only fetched for Binance, with a read-only key in
+// BINANCE_STATUS_API_KEY / BINANCE_STATUS_API_SECRET. Anything unknown or older
+// than VENUE_STATUS_MAX_AGE_SECS counts as open, so a venue without status data
+// (DEXes, failed polls) is never blocked, only one that reports itself closed.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::config;
+use crate::metrics::Metrics;
+use crate::{ArbitrageOpportunity, SpreadAnalyzer};
+
+const DEFAULT_REFRESH_SECS: u64 = 300;
+const DEFAULT_MAX_AGE_SECS: i64 = 900;
+pub const DEFAULT_BINANCE_REST_URL: &str = "https://api.binance.com";
+pub const DEFAULT_OKX_REST_URL: &str = "https://www.okx.com";
+pub const DEFAULT_BYBIT_REST_URL: &str = "https://api.bybit.com";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+pub(crate) const BINANCE_CAPITAL_CONFIG_PATH: &str = "/sapi/v1/capital/config/getall";
+
+// Same symbol normalization as pair grouping
+fn normalize(symbol: &str) -> String {
+    symbol.to_uppercase().replace("WBTC", "BTC")
+}
+
+fn pair(base: &str, quote: &str) -> String {
+    format!("{}/{}", normalize(base), normalize(quote))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssetStatus {
+    pub deposit: bool,
+    pub withdraw: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VenueStatus {
+    // Pairs currently trading, normalized; None if the listing was not fetched
+    pub trading: Option<HashSet<String>>,
+    pub assets: HashMap<String, AssetStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Infeasible {
+    NotTrading { venue: String, pair: String },
+    WithdrawalsClosed { venue: String, asset: String },
+    DepositsClosed { venue: String, asset: String },
+}
+
+impl fmt::Display for Infeasible {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Infeasible::NotTrading { venue, pair } => write!(f, "{} is not trading on {}", pair, venue),
+            Infeasible::WithdrawalsClosed { venue, asset } => write!(f, "{} withdrawals are closed on {}", asset, venue),
+            Infeasible::DepositsClosed { venue, asset } => write!(f, "{} deposits are closed on {}", asset, venue),
+        }
+    }
+}
+
+/// Latest status per venue, shared between the poller and the analyzer
+#[derive(Debug)]
+pub struct StatusCache {
+    venues: Mutex<HashMap<String, (VenueStatus, DateTime<Utc>)>>,
+    max_age: chrono::Duration,
+}
+
+impl StatusCache {
+    pub fn new(max_age: chrono::Duration) -> Self {
+        StatusCache { venues: Mutex::new(HashMap::new()), max_age }
+    }
+
+    pub fn from_env() -> Self {
+        StatusCache::new(chrono::Duration::seconds(config::env_or("VENUE_STATUS_MAX_AGE_SECS", DEFAULT_MAX_AGE_SECS)))
+    }
+
+    pub fn update(&self, venue: &str, status: VenueStatus, now: DateTime<Utc>) {
+        self.venues.lock().unwrap_or_else(|e| e.into_inner()).insert(venue.to_string(), (status, now));
+    }
+
+    /// Check buying `pair` on `buy_venue`, moving the base over and selling on `sell_venue`
+    pub fn check_route(&self, pair: &str, buy_venue: &str, sell_venue: &str, now: DateTime<Utc>) -> Result<(), Infeasible> {
+        let venues = self.venues.lock().unwrap_or_else(|e| e.into_inner());
+        let fresh = |venue: &str| venues.get(venue).filter(|(_, at)| now - *at <= self.max_age).map(|(status, _)| status);
+        let pair = normalize(pair);
+        let base = pair.split('/').next().unwrap_or(&pair).to_string();
+
+        for venue in [buy_venue, sell_venue] {
+            let listed = fresh(venue).and_then(|s| s.trading.as_ref()).is_none_or(|trading| trading.contains(&pair));
+            if !listed {
+                return Err(Infeasible::NotTrading { venue: venue.to_string(), pair });
+            }
+        }
+        if fresh(buy_venue).and_then(|s| s.assets.get(&base)).is_some_and(|a| !a.withdraw) {
+            return Err(Infeasible::WithdrawalsClosed { venue: buy_venue.to_string(), asset: base });
+        }
+        if fresh(sell_venue).and_then(|s| s.assets.get(&base)).is_some_and(|a| !a.deposit) {
+            return Err(Infeasible::DepositsClosed { venue: sell_venue.to_string(), asset: base });
+        }
+        Ok(())
+    }
+}
+
+// ---------- Status endpoint parsers ----------
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BinanceSymbol {
+    status: String,
+    base_asset: String,
+    quote_asset: String,
+}
+
+#[derive(Deserialize)]
+struct BinanceExchangeInfo {
+    symbols: Vec<BinanceSymbol>,
+}
+
+/// `GET /api/v3/exchangeInfo`
+pub fn parse_binance_exchange_info(raw: &str) -> Result<HashSet<String>> {
+    let info: BinanceExchangeInfo = serde_json::from_str(raw)?;
+    Ok(info.symbols.iter().filter(|s| s.status == "TRADING").map(|s| pair(&s.base_asset, &s.quote_asset)).collect())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BinanceCoin {
+    coin: String,
+    deposit_all_enable: bool,
+    withdraw_all_enable: bool,
+}
+
+/// `GET /sapi/v1/capital/config/getall` (signed)
+pub fn parse_binance_capital_config(raw: &str) -> Result<HashMap<String, AssetStatus>> {
+    let coins: Vec<BinanceCoin> = serde_json::from_str(raw)?;
+    Ok(coins
+        .into_iter()
+        .map(|c| (normalize(&c.coin), AssetStatus { deposit: c.deposit_all_enable, withdraw: c.withdraw_all_enable }))
+        .collect())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OkxInstrument {
+    base_ccy: String,
+    quote_ccy: String,
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct OkxInstruments {
+    data: Vec<OkxInstrument>,
+}
+
+/// `GET /api/v5/public/instruments?instType=SPOT`
+pub fn parse_okx_instruments(raw: &str) -> Result<HashSet<String>> {
+    let instruments: OkxInstruments = serde_json::from_str(raw)?;
+    Ok(instruments.data.iter().filter(|i| i.state == "live").map(|i| pair(&i.base_ccy, &i.quote_ccy)).collect())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BybitInstrument {
+    base_coin: String,
+    quote_coin: String,
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct BybitInstrumentList {
+    list: Vec<BybitInstrument>,
+}
+
+#[derive(Deserialize)]
+struct BybitInstruments {
+    result: BybitInstrumentList,
+}
+
+/// `GET /v5/market/instruments-info?category=spot`
+pub fn parse_bybit_instruments(raw: &str) -> Result<HashSet<String>> {
+    let instruments: BybitInstruments = serde_json::from_str(raw)?;
+    Ok(instruments.result.list.iter().filter(|i| i.status == "Trading").map(|i| pair(&i.base_coin, &i.quote_coin)).collect())
+}
+
+// ---------- Polling ----------
+
+#[derive(Debug, Clone)]
+pub struct StatusConfig {
+    pub venues: Vec<String>,
+    pub refresh: Duration,
+    binance_url: String,
+    okx_url: String,
+    bybit_url: String,
+    binance_key: Option<(String, String)>,
+}
+
+impl StatusConfig {
+    /// None unless VENUE_STATUS_VENUES lists at least one venue
+    pub fn from_env() -> Option<Self> {
+        let venues: Vec<String> = config::env_var("VENUE_STATUS_VENUES")
+            .ok()?
+            .split(',')
+            .map(|v| v.trim().to_lowercase())
+            .filter(|v| !v.is_empty())
+            .collect();
+        if venues.is_empty() {
+            return None;
+        }
+        let env_url = |name: &str, default: &str| config::env_var(name).unwrap_or_else(|_| default.to_string());
+        Some(StatusConfig {
+            venues,
+            refresh: Duration::from_secs(config::env_or("VENUE_STATUS_REFRESH_SECS", DEFAULT_REFRESH_SECS)),
+            binance_url: env_url("BINANCE_REST_URL", DEFAULT_BINANCE_REST_URL),
+            okx_url: env_url("OKX_REST_URL", DEFAULT_OKX_REST_URL),
+            bybit_url: env_url("BYBIT_REST_URL", DEFAULT_BYBIT_REST_URL),
+            binance_key: config::env_var("BINANCE_STATUS_API_KEY").ok().zip(config::env_var("BINANCE_STATUS_API_SECRET").ok()),
+        })
+    }
+
+    fn get(url: &str, api_key: Option<&str>) -> Result<String> {
+        let mut request = ureq::get(url).timeout(REQUEST_TIMEOUT);
+        if let Some(key) = api_key {
+            request = request.set("X-MBX-APIKEY", key);
+        }
+        request.call().map_err(|e| anyhow!("{}: {}", url.split('?').next().unwrap_or(url), e))?.into_string().map_err(Into::into)
+    }
+
+    fn fetch(&self, venue: &str) -> Result<VenueStatus> {
+        match venue {
+            "binance" => {
+                let trading = parse_binance_exchange_info(&Self::get(&format!("{}/api/v3/exchangeInfo", self.binance_url), None)?)?;
+                let assets = match &self.binance_key {
+                    Some(key) => parse_binance_capital_config(&binance_signed_get(&self.binance_url, BINANCE_CAPITAL_CONFIG_PATH, key)?)?,
+                    None => HashMap::new(),
+                };
+                Ok(VenueStatus { trading: Some(trading), assets })
+            }
+            "okx" => {
+                let raw = Self::get(&format!("{}/api/v5/public/instruments?instType=SPOT", self.okx_url), None)?;
+                Ok(VenueStatus { trading: Some(parse_okx_instruments(&raw)?), assets: HashMap::new() })
+            }
+            "bybit" => {
+                let raw = Self::get(&format!("{}/v5/market/instruments-info?category=spot", self.bybit_url), None)?;
+                Ok(VenueStatus { trading: Some(parse_bybit_instruments(&raw)?), assets: HashMap::new() })
+            }
+            other => Err(anyhow!("no status endpoint for {}", other)),
+        }
+    }
+}
+
+/// GET a signed Binance endpoint, `key` being the API key and secret
+pub(crate) fn binance_signed_get(base_url: &str, path: &str, (key, secret): &(String, String)) -> Result<String> {
+    let query = format!("timestamp={}", Utc::now().timestamp_millis());
+    let signature: String = hmac_sha256::HMAC::mac(query.as_bytes(), secret.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+    StatusConfig::get(&format!("{}{}?{}&signature={}", base_url, path, query, signature), Some(key))
+}
+
+/// Poll every configured venue in the background, forever
+pub fn spawn(config: StatusConfig, cache: Arc<StatusCache>) {
+    info!(
+        "  Polling venue status for {} every {}s{}",
+        config.venues.join(", "),
+        config.refresh.as_secs(),
+        if config.binance_key.is_some() { " (with Binance deposit/withdrawal status)" } else { "" }
+    );
+    thread::spawn(move || loop {
+        for venue in &config.venues {
+            match config.fetch(venue) {
+                Ok(status) => cache.update(venue, status, Utc::now()),
+                Err(e) => warn!("Venue status refresh for {} failed: {}", venue, e),
+            }
+        }
+        thread::sleep(config.refresh);
+    });
+}
+
+impl SpreadAnalyzer {
+    /// Whether the route behind `opp` can be executed right now, and pays enough for how slow
+    /// its venues are; suppressed routes are counted, the reason is returned
+    pub(crate) fn route_feasibility(&self, opp: &ArbitrageOpportunity) -> Result<(), String> {
+        let now = Utc::now();
+        if let Err(reason) = self.venue_status.check_route(&opp.pair, &opp.buy_exchange, &opp.sell_exchange, now) {
+            Metrics::inc(&self.metrics.infeasible_routes_suppressed);
+            return Err(reason.to_string());
+        }
+        match self.latency_bar(opp, now) {
+            (Some((venue, round_trip_ms)), required_roi) if opp.roi_percentage < required_roi => {
+                Metrics::inc(&self.metrics.slow_venue_suppressed);
+                Err(format!(
+                    "{:.3}% ROI is under the {:.3}% needed with {} at {:.0}ms",
+                    opp.roi_percentage, required_roi, venue, round_trip_ms
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_status_endpoints() {
+        let binance = parse_binance_exchange_info(include_str!("../fixtures/binance_exchange_info.json")).unwrap();
+        // WBTCUSDT is halted, and maps onto BTC/USDT only through the normalization
+        assert_eq!(binance, HashSet::from(["BTC/USDT".to_string(), "ETH/USDT".to_string()]));
+        let assets = parse_binance_capital_config(include_str!("../fixtures/binance_capital_config.json")).unwrap();
+        assert_eq!(assets["ETH"], AssetStatus { deposit: true, withdraw: false });
+        assert_eq!(parse_okx_instruments(include_str!("../fixtures/okx_instruments.json")).unwrap(), HashSet::from(["BTC/USDT".to_string()]));
+        assert_eq!(
+            parse_bybit_instruments(include_str!("../fixtures/bybit_instruments_info.json")).unwrap(),
+            HashSet::from(["BTC/USDT".to_string()])
+        );
+    }
+
+    #[test]
+    fn closed_venues_make_routes_infeasible() {
+        let now = Utc::now();
+        let cache = StatusCache::new(chrono::Duration::seconds(60));
+        let assets = HashMap::from([("ETH".to_string(), AssetStatus { deposit: true, withdraw: false })]);
+        cache.update("binance", VenueStatus { trading: Some(HashSet::from(["ETH/USDT".to_string()])), assets }, now);
+        cache.update("okx", VenueStatus { trading: Some(HashSet::from(["BTC/USDT".to_string()])), assets: HashMap::new() }, now);
+
+        assert_eq!(
+            cache.check_route("ETH/USDT", "binance", "uniswap-v3-exact", now),
+            Err(Infeasible::WithdrawalsClosed { venue: "binance".to_string(), asset: "ETH".to_string() })
+        );
+        assert!(cache.check_route("ETH/USDT", "uniswap-v3-exact", "binance", now).is_ok());
+        assert!(matches!(cache.check_route("ETH/USDT", "uniswap-v3-exact", "okx", now), Err(Infeasible::NotTrading { .. })));
+        // Stale status no longer blocks anything
+        assert!(cache.check_route("ETH/USDT", "binance", "okx", now + chrono::Duration::seconds(61)).is_ok());
+    }
+}
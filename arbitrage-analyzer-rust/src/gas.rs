@@ -0,0 +1,483 @@
+// Priority-fee strategies for the chains DEX legs settle on, one per chain in
+// GAS_STRATEGIES (`chain:strategy`, e.g. `ethereum:percentile/60,solana:fixed/50000`):
+//  - `fixed/<fee>`: always bid <fee>,
+//  - `percentile/<p>`: bid what recent blocks paid, polled every GAS_POLL_SECS
+//    from the chain's RPC in CHAIN_RPC_URLS. On Ethereum that is `eth_feeHistory`
+//    over the last GAS_FEE_HISTORY_BLOCKS blocks (the p-th percentile of each
+//    block's priority fees, median over the blocks), on Solana the p-th
+//    percentile of `getRecentPrioritizationFees`,
+//  - `escalating/<start>/<step>/<max>`: bid <start> and raise the bid by <step>
+//    on every resubmission, up to <max>.
+// Fees are gwei per gas on Ethereum and lamports per swap on Solana.
+//
+// DEX legs are priced at the initial bid. On Ethereum a swap costs
+// ETHEREUM_GAS_PER_SWAP × (base fee + bid), at the ETH price of the cached books,
+// instead of the flat `ethereum_gas_cost`; on Solana the bid replaces
+// SOLANA_PRIORITY_FEE_LAMPORTS. Every DEX leg of an execution request carries its
+// chain's strategy, initial bid and the most the on-chain executor may pay, so it
+// bids what the route was priced at. Chains without a strategy, and percentile
+// strategies until the first fee data arrives, are priced as before and get no
+// gas plan.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::gas_oracle::{GasOracle, OracleReading};
+use crate::maintenance::{self, Chain};
+use crate::notional::NotionalConverter;
+use crate::solana::BASE_FEE_LAMPORTS_PER_SIGNATURE;
+use crate::{config, numeric, ArbitrageOpportunity, SpreadAnalyzer};
+
+const DEFAULT_POLL_SECS: u64 = 12;
+const DEFAULT_FEE_HISTORY_BLOCKS: u64 = 20;
+const DEFAULT_ETHEREUM_GAS_PER_SWAP: f64 = 150_000.0;
+// Until eth_feeHistory reports one
+const DEFAULT_ETHEREUM_BASE_FEE_GWEI: f64 = 20.0;
+// Until an ETH/USD-stable book arrives
+const DEFAULT_ETH_PRICE_USD: f64 = 3_000.0;
+const DEFAULT_SOLANA_COMPUTE_UNITS: f64 = 200_000.0;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Fee samples this many polls old are stale
+const MAX_AGE_POLLS: u32 = 3;
+const GWEI_PER_ETH: f64 = 1e9;
+const WEI_PER_GWEI: f64 = 1e9;
+const MICRO_LAMPORTS_PER_LAMPORT: f64 = 1e6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriorityFeeStrategy {
+    Fixed(f64),
+    Percentile(f64),
+    Escalating { start: f64, step: f64, max: f64 },
+}
+
+impl FromStr for PriorityFeeStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let number = |raw: &str| -> Result<f64> {
+            raw.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite() && *v >= 0.0)
+                .ok_or_else(|| anyhow!("invalid number {:?} in priority-fee strategy {:?}", raw, s))
+        };
+        let parts: Vec<&str> = s.trim().split('/').collect();
+        match parts.as_slice() {
+            ["fixed", fee] => Ok(PriorityFeeStrategy::Fixed(number(fee)?)),
+            ["percentile", pct] => match number(pct)? {
+                pct if pct <= 100.0 => Ok(PriorityFeeStrategy::Percentile(pct)),
+                _ => Err(anyhow!("percentile above 100 in priority-fee strategy {:?}", s)),
+            },
+            ["escalating", start, step, max] => {
+                let (start, step, max) = (number(start)?, number(step)?, number(max)?);
+                if max < start {
+                    return Err(anyhow!("escalating priority fee {:?} starts above its max", s));
+                }
+                Ok(PriorityFeeStrategy::Escalating { start, step, max })
+            }
+            _ => Err(anyhow!(
+                "unknown priority-fee strategy {:?}, expected fixed/<fee>, percentile/<p> or escalating/<start>/<step>/<max>",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PriorityFeeStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PriorityFeeStrategy::Fixed(fee) => write!(f, "fixed/{}", fee),
+            PriorityFeeStrategy::Percentile(pct) => write!(f, "percentile/{}", pct),
+            PriorityFeeStrategy::Escalating { start, step, max } => write!(f, "escalating/{}/{}/{}", start, step, max),
+        }
+    }
+}
+
+/// What recent blocks paid, in the chain's unit
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FeeSamples {
+    // Ethereum only: the next block's base fee
+    pub base_fee: Option<f64>,
+    pub priority_fees: Vec<f64>,
+}
+
+fn rpc_result(raw: &str) -> Result<Value> {
+    let mut response: Value = serde_json::from_str(raw)?;
+    if let Some(error) = response.get("error") {
+        return Err(anyhow!("RPC error: {}", error));
+    }
+    response.get_mut("result").map(Value::take).ok_or_else(|| anyhow!("no result in RPC response"))
+}
+
+fn gwei_from_hex(value: &Value) -> Option<f64> {
+    let wei = u128::from_str_radix(value.as_str()?.trim_start_matches("0x"), 16).ok()?;
+    Some(wei as f64 / WEI_PER_GWEI)
+}
+
+/// `eth_feeHistory` with one reward percentile: each block's reward at it, and the next base fee
+pub fn parse_fee_history(raw: &str) -> Result<FeeSamples> {
+    let result = rpc_result(raw)?;
+    let base_fee = result.get("baseFeePerGas").and_then(Value::as_array).and_then(|fees| fees.last()).and_then(gwei_from_hex);
+    let priority_fees = result
+        .get("reward")
+        .and_then(Value::as_array)
+        .ok_or_else(|| anyhow!("no rewards in fee history"))?
+        .iter()
+        .filter_map(|block| block.get(0).and_then(gwei_from_hex))
+        .collect();
+    Ok(FeeSamples { base_fee, priority_fees })
+}
+
+/// `getRecentPrioritizationFees`, micro-lamports per compute unit, as lamports per swap
+pub fn parse_prioritization_fees(raw: &str, compute_units: f64) -> Result<FeeSamples> {
+    let result = rpc_result(raw)?;
+    let priority_fees = result
+        .as_array()
+        .ok_or_else(|| anyhow!("unexpected prioritization fees: {}", result))?
+        .iter()
+        .filter_map(|slot| slot.get("prioritizationFee").and_then(Value::as_f64))
+        .map(|micro_lamports| micro_lamports * compute_units / MICRO_LAMPORTS_PER_LAMPORT)
+        .collect();
+    Ok(FeeSamples { base_fee: None, priority_fees })
+}
+
+/// Latest fee samples per chain, written by the poller. Samples older than
+/// `max_age` are not handed out, so a chain whose RPC went away is priced as if
+/// none had arrived
+#[derive(Debug)]
+pub struct GasBoard {
+    chains: Mutex<HashMap<Chain, (FeeSamples, Instant)>>,
+    max_age: Duration,
+}
+
+impl GasBoard {
+    pub fn new(max_age: Duration) -> Self {
+        GasBoard { chains: Mutex::new(HashMap::new()), max_age }
+    }
+
+    pub fn set(&self, chain: Chain, samples: FeeSamples) {
+        self.chains.lock().unwrap_or_else(|e| e.into_inner()).insert(chain, (samples, Instant::now()));
+    }
+
+    pub fn get(&self, chain: Chain) -> Option<FeeSamples> {
+        let chains = self.chains.lock().unwrap_or_else(|e| e.into_inner());
+        chains.get(&chain).filter(|(_, at)| at.elapsed() <= self.max_age).map(|(samples, _)| samples.clone())
+    }
+}
+
+/// The gas plan of one chain's legs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GasQuote {
+    pub chain: &'static str,
+    pub strategy: String,
+    // `gwei` (per gas) or `lamports` (per swap)
+    pub unit: &'static str,
+    // Initial bid; the leg was priced at it
+    pub priority_fee: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub escalation_step: Option<f64>,
+    pub max_priority_fee: f64,
+    pub base_fee: f64,
+    // Most the executor may pay: 2 × base fee + max priority fee per gas on
+    // Ethereum (EIP-1559 maxFeePerGas), base + max priority fee per swap on Solana
+    pub max_fee: f64,
+}
+
+/// The gas plan of one DEX leg of an execution request
+#[derive(Debug, Clone, Serialize)]
+pub struct LegGas {
+    pub venue: String,
+    #[serde(flatten)]
+    pub quote: GasQuote,
+}
+
+#[derive(Debug)]
+pub struct GasModel {
+    strategies: HashMap<Chain, PriorityFeeStrategy>,
+    ethereum_gas_per_swap: f64,
+    ethereum_base_fee_gwei: f64,
+    eth_price_usd: f64,
+    solana_compute_units: f64,
+    history_blocks: u64,
+    interval: Duration,
+    board: Arc<GasBoard>,
+    // Recomputed in housekeeping
+    quotes: HashMap<Chain, GasQuote>,
+    // GAS_ORACLE, for Ethereum when it has no strategy
+    oracle: Option<GasOracle>,
+    // The configured `ethereum_gas_cost`, restored while no live figure is available
+    static_ethereum_gas_cost: Option<f64>,
+}
+
+impl GasModel {
+    pub fn new(strategies: HashMap<Chain, PriorityFeeStrategy>) -> Self {
+        GasModel {
+            strategies,
+            ethereum_gas_per_swap: DEFAULT_ETHEREUM_GAS_PER_SWAP,
+            ethereum_base_fee_gwei: DEFAULT_ETHEREUM_BASE_FEE_GWEI,
+            eth_price_usd: DEFAULT_ETH_PRICE_USD,
+            solana_compute_units: DEFAULT_SOLANA_COMPUTE_UNITS,
+            history_blocks: DEFAULT_FEE_HISTORY_BLOCKS,
+            interval: Duration::from_secs(DEFAULT_POLL_SECS),
+            board: Arc::new(GasBoard::new(Duration::from_secs(DEFAULT_POLL_SECS) * MAX_AGE_POLLS)),
+            quotes: HashMap::new(),
+            oracle: None,
+            static_ethereum_gas_cost: None,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let mut strategies = HashMap::new();
+        for (name, strategy) in config::env_map::<PriorityFeeStrategy>("GAS_STRATEGIES") {
+            match Chain::parse(&name) {
+                Some(Chain::Osmosis) => warn!("Ignoring GAS_STRATEGIES entry for osmosis: its fee is the flat OSMOSIS_TX_COST"),
+                Some(chain) => {
+                    strategies.insert(chain, strategy);
+                }
+                None => warn!("Ignoring GAS_STRATEGIES entry for unknown chain {:?}", name),
+            }
+        }
+        let interval = Duration::from_secs(config::env_or("GAS_POLL_SECS", DEFAULT_POLL_SECS));
+        let history_blocks = config::env_or("GAS_FEE_HISTORY_BLOCKS", DEFAULT_FEE_HISTORY_BLOCKS);
+        GasModel {
+            ethereum_gas_per_swap: config::env_or("ETHEREUM_GAS_PER_SWAP", DEFAULT_ETHEREUM_GAS_PER_SWAP),
+            ethereum_base_fee_gwei: config::env_or("ETHEREUM_BASE_FEE_GWEI", DEFAULT_ETHEREUM_BASE_FEE_GWEI),
+            eth_price_usd: config::env_or("ETH_PRICE_USD", DEFAULT_ETH_PRICE_USD),
+            solana_compute_units: config::env_or("SOLANA_COMPUTE_UNITS_PER_SWAP", DEFAULT_SOLANA_COMPUTE_UNITS),
+            history_blocks,
+            interval,
+            board: Arc::new(GasBoard::new(interval * MAX_AGE_POLLS)),
+            oracle: GasOracle::from_env(interval, history_blocks),
+            ..GasModel::new(strategies)
+        }
+    }
+
+    pub fn ethereum_gas_per_swap(&self) -> f64 {
+        self.ethereum_gas_per_swap
+    }
+
+    pub fn strategies(&self) -> impl Iterator<Item = (&Chain, &PriorityFeeStrategy)> {
+        self.strategies.iter()
+    }
+
+    pub fn quote(&self, chain: Chain) -> Option<&GasQuote> {
+        self.quotes.get(&chain)
+    }
+
+    /// Keep `usd` as the Ethereum swap cost to go back to when no live figure is available
+    pub fn set_static_ethereum_gas_cost(&mut self, usd: f64) {
+        self.static_ethereum_gas_cost = Some(usd);
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// What the chain's recent blocks paid, in its unit: base plus median priority fee on Ethereum
+    /// (from its fee feed, else the oracle), the median priority fee on Solana
+    pub fn market_price(&self, chain: Chain) -> Option<f64> {
+        match self.board.get(chain) {
+            Some(samples) => {
+                let priority_fee = numeric::percentile(&samples.priority_fees, 50.0)?;
+                Some(samples.base_fee.unwrap_or(0.0) + priority_fee)
+            }
+            None if chain == Chain::Ethereum => self.oracle_reading().map(|reading| reading.base_fee_gwei + reading.priority_fee_gwei),
+            None => None,
+        }
+    }
+
+    pub fn oracle_reading(&self) -> Option<OracleReading> {
+        self.oracle.as_ref().and_then(GasOracle::reading)
+    }
+
+    pub fn quotes(&self) -> Vec<&GasQuote> {
+        let mut quotes: Vec<&GasQuote> = self.quotes.values().collect();
+        quotes.sort_by_key(|quote| quote.chain);
+        quotes
+    }
+
+    fn compute_quote(&self, chain: Chain, strategy: PriorityFeeStrategy, samples: Option<&FeeSamples>, solana_base_lamports: f64) -> Option<GasQuote> {
+        let priority_fee = match strategy {
+            PriorityFeeStrategy::Fixed(fee) => fee,
+            // Ethereum samples are already each block's p-th percentile
+            PriorityFeeStrategy::Percentile(pct) => {
+                let pct = if chain == Chain::Ethereum { 50.0 } else { pct };
+                numeric::percentile(&samples?.priority_fees, pct)?
+            }
+            PriorityFeeStrategy::Escalating { start, .. } => start,
+        };
+        let (escalation_step, max_priority_fee) = match strategy {
+            PriorityFeeStrategy::Escalating { step, max, .. } => (Some(step), max),
+            _ => (None, priority_fee),
+        };
+        let (unit, base_fee, max_fee) = match chain {
+            Chain::Ethereum => {
+                let base_fee = samples.and_then(|s| s.base_fee).unwrap_or(self.ethereum_base_fee_gwei);
+                ("gwei", base_fee, 2.0 * base_fee + max_priority_fee)
+            }
+            _ => ("lamports", solana_base_lamports, solana_base_lamports + max_priority_fee),
+        };
+        Some(GasQuote {
+            chain: chain.name(),
+            strategy: strategy.to_string(),
+            unit,
+            priority_fee,
+            escalation_step,
+            max_priority_fee,
+            base_fee,
+            max_fee,
+        })
+    }
+}
+
+fn fetch_samples(chain: Chain, url: &str, pct: f64, history_blocks: u64, compute_units: f64) -> Result<FeeSamples> {
+    let request = match chain {
+        Chain::Ethereum => json!({
+            "jsonrpc": "2.0", "id": 1, "method": "eth_feeHistory",
+            "params": [format!("{:#x}", history_blocks), "latest", [pct]],
+        }),
+        _ => json!({"jsonrpc": "2.0", "id": 1, "method": "getRecentPrioritizationFees", "params": []}),
+    };
+    let raw = ureq::post(url).timeout(REQUEST_TIMEOUT).send_json(request)?.into_string()?;
+    match chain {
+        Chain::Ethereum => parse_fee_history(&raw),
+        _ => parse_prioritization_fees(&raw, compute_units),
+    }
+}
+
+/// Poll fee data for the chains with a percentile strategy, and the gas oracle
+pub fn spawn(model: &GasModel) {
+    if let Some(oracle) = &model.oracle {
+        oracle.spawn();
+    }
+    let urls: HashMap<Chain, String> = maintenance::chain_rpc_urls().into_iter().collect();
+    let mut feeds = Vec::new();
+    for (chain, strategy) in &model.strategies {
+        let PriorityFeeStrategy::Percentile(pct) = strategy else { continue };
+        match urls.get(chain) {
+            Some(url) => feeds.push((*chain, url.clone(), *pct)),
+            None => warn!("{} priority fee is {} but CHAIN_RPC_URLS has no {} RPC to read it from", chain.name(), strategy, chain.name()),
+        }
+    }
+    if feeds.is_empty() {
+        return;
+    }
+    info!(
+        "  Polling priority fees every {}s: {}",
+        model.interval.as_secs(),
+        feeds.iter().map(|(chain, _, _)| chain.name()).collect::<Vec<_>>().join(", ")
+    );
+    let (board, interval, history_blocks, compute_units) = (model.board.clone(), model.interval, model.history_blocks, model.solana_compute_units);
+    thread::spawn(move || loop {
+        for (chain, url, pct) in &feeds {
+            match fetch_samples(*chain, url, *pct, history_blocks, compute_units) {
+                Ok(samples) => board.set(*chain, samples),
+                Err(e) => warn!("Fetching {} priority fees failed: {}", chain.name(), e),
+            }
+        }
+        thread::sleep(interval);
+    });
+}
+
+impl SpreadAnalyzer {
+    /// Recompute each chain's bid from its strategy and price DEX legs at it
+    pub(crate) fn refresh_gas(&mut self) {
+        let solana_base_lamports = (self.fees_config.solana.signatures_per_swap * BASE_FEE_LAMPORTS_PER_SIGNATURE) as f64;
+        let eth_price = NotionalConverter::new(&self.capital_config.quote_usd, &self.books, self.sizing_config.reference_price)
+            .consensus_price("ETH")
+            .or_else(|| self.gas.oracle.as_ref().and_then(GasOracle::eth_usd))
+            .unwrap_or(self.gas.eth_price_usd);
+        let swap_cost = |base_fee: f64, priority_fee: f64| self.gas.ethereum_gas_per_swap * (base_fee + priority_fee) / GWEI_PER_ETH * eth_price;
+        // Without a strategy, the oracle prices Ethereum legs but plans no bids
+        let mut ethereum_cost = match self.gas.strategies.contains_key(&Chain::Ethereum) {
+            false => self.gas.oracle_reading().map(|reading| swap_cost(reading.base_fee_gwei, reading.priority_fee_gwei)),
+            true => None,
+        };
+        let strategies: Vec<(Chain, PriorityFeeStrategy)> = self.gas.strategies.iter().map(|(c, s)| (*c, *s)).collect();
+        for (chain, strategy) in strategies {
+            let samples = self.gas.board.get(chain);
+            let Some(quote) = self.gas.compute_quote(chain, strategy, samples.as_ref(), solana_base_lamports) else {
+                self.gas.quotes.remove(&chain);
+                continue;
+            };
+            match chain {
+                Chain::Ethereum => ethereum_cost = Some(swap_cost(quote.base_fee, quote.priority_fee)),
+                Chain::Solana => self.fees_config.solana.priority_fee_lamports = quote.priority_fee.round() as u64,
+                Chain::Osmosis => {}
+            }
+            self.gas.quotes.insert(chain, quote);
+        }
+        if let Some(usd) = ethereum_cost.or(self.gas.static_ethereum_gas_cost) {
+            self.fees_config.ethereum_gas_cost = usd;
+        }
+    }
+
+    /// Gas plans for the legs of `opp` that trade on a chain with a strategy
+    pub(crate) fn gas_plan(&self, opp: &ArbitrageOpportunity) -> Vec<LegGas> {
+        [&opp.buy_exchange, &opp.sell_exchange]
+            .into_iter()
+            .filter_map(|venue| {
+                let quote = self.gas.quote(Chain::of_venue(venue)?)?;
+                Some(LegGas { venue: venue.clone(), quote: quote.clone() })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_strategies_and_fee_feeds() {
+        assert_eq!("fixed/2".parse::<PriorityFeeStrategy>().unwrap(), PriorityFeeStrategy::Fixed(2.0));
+        let escalating = "escalating/1/0.5/5".parse::<PriorityFeeStrategy>().unwrap();
+        assert_eq!(escalating, PriorityFeeStrategy::Escalating { start: 1.0, step: 0.5, max: 5.0 });
+        assert_eq!(escalating.to_string(), "escalating/1/0.5/5");
+        for invalid in ["percentile/120", "escalating/5/1/2", "fixed/-1", "auction/3"] {
+            assert!(invalid.parse::<PriorityFeeStrategy>().is_err(), "{}", invalid);
+        }
+
+        // 2 and 3 gwei rewards, next base fee 15 gwei
+        let history = r#"{"jsonrpc":"2.0","id":1,"result":{"oldestBlock":"0x1","baseFeePerGas":["0x2540be400","0x37e11d600"],"reward":[["0x77359400"],["0xb2d05e00"]]}}"#;
+        assert_eq!(parse_fee_history(history).unwrap(), FeeSamples { base_fee: Some(15.0), priority_fees: vec![2.0, 3.0] });
+        let solana = r#"{"jsonrpc":"2.0","id":1,"result":[{"slot":1,"prioritizationFee":500},{"slot":2,"prioritizationFee":1000}]}"#;
+        assert_eq!(parse_prioritization_fees(solana, 200_000.0).unwrap().priority_fees, vec![100.0, 200.0]);
+        assert!(parse_fee_history(r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32601,"message":"no"}}"#).is_err());
+    }
+
+    #[test]
+    fn strategies_price_dex_legs_and_fill_the_gas_plan() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.gas = GasModel::new(HashMap::from([
+            (Chain::Ethereum, PriorityFeeStrategy::Percentile(60.0)),
+            (Chain::Solana, PriorityFeeStrategy::Escalating { start: 50_000.0, step: 25_000.0, max: 200_000.0 }),
+        ]));
+        let flat_gas = analyzer.fees_config.ethereum_gas_cost;
+
+        // No fee history yet: Ethereum legs keep the flat cost and get no plan
+        analyzer.refresh_gas();
+        assert_eq!(analyzer.fees_config.ethereum_gas_cost, flat_gas);
+        assert_eq!(analyzer.fees_config.solana.priority_fee_lamports, 50_000);
+        let solana = analyzer.gas.quote(Chain::Solana).unwrap();
+        assert_eq!((solana.max_priority_fee, solana.max_fee, solana.escalation_step), (200_000.0, 205_000.0, Some(25_000.0)));
+
+        analyzer.gas.board.set(Chain::Ethereum, FeeSamples { base_fee: Some(10.0), priority_fees: vec![1.0, 2.0, 9.0] });
+        analyzer.refresh_gas();
+        // 150k gas at (10 + 2) gwei and $3000 ETH
+        assert!((analyzer.fees_config.ethereum_gas_cost - 5.4).abs() < 1e-9);
+        let opp = analyzer.evaluate_opportunity("binance", "uniswap-v3-exact", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        let plan = analyzer.gas_plan(&opp);
+        assert_eq!(plan.len(), 1);
+        assert_eq!((plan[0].venue.as_str(), plan[0].quote.priority_fee, plan[0].quote.max_fee), ("uniswap-v3-exact", 2.0, 22.0));
+    }
+}
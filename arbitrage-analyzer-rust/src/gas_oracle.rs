@@ -0,0 +1,197 @@
+// Live Ethereum gas cost for chains without a GAS_STRATEGIES entry. With
+// GAS_ORACLE=true a background thread reads, every GAS_POLL_SECS:
+//  - the next block's base fee and the GAS_ORACLE_PRIORITY_PERCENTILE (default 50)
+//    of recent priority fees, from `eth_feeHistory` on GAS_ORACLE_RPC_URL (else the
+//    ethereum RPC of CHAIN_RPC_URLS),
+//  - optionally ETH/USD from ETH_PRICE_URL, any JSON endpoint, at the
+//    ETH_PRICE_JSON_POINTER (default `/data/amount`, Coinbase's spot price).
+// A swap then costs ETHEREUM_GAS_PER_SWAP × (base fee + priority fee), valued at
+// the median ETH mid of the cached books, else the feed's price, else
+// ETH_PRICE_USD. Readings older than three polls are ignored: while the RPC is
+// unreachable, Ethereum legs go back to the static `ethereum_gas_cost`.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::gas::{self, FeeSamples};
+use crate::maintenance::{self, Chain};
+use crate::{config, numeric};
+
+const DEFAULT_PRIORITY_PERCENTILE: f64 = 50.0;
+const DEFAULT_PRICE_POINTER: &str = "/data/amount";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Readings this many polls old are stale
+const MAX_AGE_POLLS: u32 = 3;
+
+/// The oracle's latest fresh figures
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct OracleReading {
+    pub base_fee_gwei: f64,
+    pub priority_fee_gwei: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eth_usd: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct Board {
+    // Base and priority fee in gwei
+    fees: Mutex<Option<((f64, f64), Instant)>>,
+    eth_usd: Mutex<Option<(f64, Instant)>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GasOracle {
+    rpc_url: Option<String>,
+    price_url: Option<String>,
+    price_pointer: String,
+    priority_percentile: f64,
+    history_blocks: u64,
+    interval: Duration,
+    board: Arc<Board>,
+}
+
+impl GasOracle {
+    pub fn new(rpc_url: Option<String>, interval: Duration, history_blocks: u64) -> Self {
+        GasOracle {
+            rpc_url,
+            price_url: None,
+            price_pointer: DEFAULT_PRICE_POINTER.to_string(),
+            priority_percentile: DEFAULT_PRIORITY_PERCENTILE,
+            history_blocks,
+            interval,
+            board: Arc::new(Board::default()),
+        }
+    }
+
+    /// None unless GAS_ORACLE is on
+    pub fn from_env(interval: Duration, history_blocks: u64) -> Option<Self> {
+        if !config::env_or("GAS_ORACLE", false) {
+            return None;
+        }
+        let rpc_url = config::env_var("GAS_ORACLE_RPC_URL")
+            .ok()
+            .or_else(|| maintenance::chain_rpc_urls().into_iter().find(|(chain, _)| *chain == Chain::Ethereum).map(|(_, url)| url));
+        Some(GasOracle {
+            price_url: config::env_var("ETH_PRICE_URL").ok(),
+            price_pointer: config::env_var("ETH_PRICE_JSON_POINTER").unwrap_or_else(|_| DEFAULT_PRICE_POINTER.to_string()),
+            priority_percentile: config::env_or("GAS_ORACLE_PRIORITY_PERCENTILE", DEFAULT_PRIORITY_PERCENTILE).clamp(0.0, 100.0),
+            ..Self::new(rpc_url, interval, history_blocks)
+        })
+    }
+
+    fn fresh<T: Copy>(&self, slot: &Mutex<Option<(T, Instant)>>) -> Option<T> {
+        let max_age = self.interval * MAX_AGE_POLLS;
+        slot.lock().unwrap_or_else(|e| e.into_inner()).filter(|(_, at)| at.elapsed() <= max_age).map(|(value, _)| value)
+    }
+
+    /// Base and priority fee, None when the RPC hasn't answered lately
+    pub fn reading(&self) -> Option<OracleReading> {
+        let (base_fee_gwei, priority_fee_gwei) = self.fresh(&self.board.fees)?;
+        Some(OracleReading { base_fee_gwei, priority_fee_gwei, eth_usd: self.eth_usd() })
+    }
+
+    /// ETH/USD from the external feed, when it answered lately
+    pub fn eth_usd(&self) -> Option<f64> {
+        self.fresh(&self.board.eth_usd)
+    }
+
+    pub fn record_fees(&self, samples: &FeeSamples) -> Result<()> {
+        let base_fee = samples.base_fee.ok_or_else(|| anyhow!("no base fee in fee history"))?;
+        // Each sample is already its block's percentile; take the median block
+        let priority_fee = numeric::percentile(&samples.priority_fees, 50.0).unwrap_or(0.0);
+        *self.board.fees.lock().unwrap_or_else(|e| e.into_inner()) = Some(((base_fee, priority_fee), Instant::now()));
+        Ok(())
+    }
+
+    pub fn record_eth_usd(&self, price: f64) {
+        *self.board.eth_usd.lock().unwrap_or_else(|e| e.into_inner()) = Some((price, Instant::now()));
+    }
+
+    fn poll(&self) {
+        if let Some(url) = &self.rpc_url {
+            let request = json!({
+                "jsonrpc": "2.0", "id": 1, "method": "eth_feeHistory",
+                "params": [format!("{:#x}", self.history_blocks), "latest", [self.priority_percentile]],
+            });
+            let fetched = ureq::post(url)
+                .timeout(REQUEST_TIMEOUT)
+                .send_json(request)
+                .map_err(anyhow::Error::from)
+                .and_then(|response| Ok(response.into_string()?))
+                .and_then(|raw| gas::parse_fee_history(&raw))
+                .and_then(|samples| self.record_fees(&samples));
+            if let Err(e) = fetched {
+                warn!("Gas oracle: fetching fee history failed: {}", e);
+            }
+        }
+        if let Some(url) = &self.price_url {
+            let fetched = ureq::get(url)
+                .timeout(REQUEST_TIMEOUT)
+                .call()
+                .map_err(anyhow::Error::from)
+                .and_then(|response| Ok(response.into_string()?))
+                .and_then(|raw| parse_price(&raw, &self.price_pointer));
+            match fetched {
+                Ok(price) => self.record_eth_usd(price),
+                Err(e) => warn!("Gas oracle: fetching the ETH price failed: {}", e),
+            }
+        }
+    }
+
+    /// Poll on a background thread
+    pub fn spawn(&self) {
+        if self.rpc_url.is_none() {
+            warn!("GAS_ORACLE is on but there is no Ethereum RPC: set GAS_ORACLE_RPC_URL or CHAIN_RPC_URLS ethereum=<url>");
+        }
+        info!(
+            "  Gas oracle polling every {}s{}",
+            self.interval.as_secs(),
+            if self.price_url.is_some() { ", with an external ETH price" } else { "" }
+        );
+        let oracle = self.clone();
+        thread::spawn(move || loop {
+            oracle.poll();
+            thread::sleep(oracle.interval);
+        });
+    }
+}
+
+/// The positive number at `pointer` in a JSON price response, as a number or a string
+pub fn parse_price(raw: &str, pointer: &str) -> Result<f64> {
+    let body: Value = serde_json::from_str(raw)?;
+    let value = body.pointer(pointer).ok_or_else(|| anyhow!("no {} in price response", pointer))?;
+    let price = match value {
+        Value::String(s) => s.parse::<f64>().ok(),
+        other => other.as_f64(),
+    };
+    price.filter(|p| p.is_finite() && *p > 0.0).ok_or_else(|| anyhow!("invalid price {} at {}", value, pointer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readings_expire_and_prices_parse() {
+        let oracle = GasOracle::new(None, Duration::from_millis(20), 20);
+        assert_eq!(oracle.reading(), None);
+        oracle.record_fees(&FeeSamples { base_fee: Some(12.0), priority_fees: vec![1.0, 3.0, 2.0] }).unwrap();
+        oracle.record_eth_usd(2_500.0);
+        assert_eq!(oracle.reading(), Some(OracleReading { base_fee_gwei: 12.0, priority_fee_gwei: 2.0, eth_usd: Some(2_500.0) }));
+        assert!(oracle.record_fees(&FeeSamples { base_fee: None, priority_fees: vec![1.0] }).is_err());
+        // Three polls without an answer
+        thread::sleep(Duration::from_millis(70));
+        assert_eq!((oracle.reading(), oracle.eth_usd()), (None, None));
+
+        assert_eq!(parse_price(r#"{"data":{"base":"ETH","currency":"USD","amount":"3012.55"}}"#, "/data/amount").unwrap(), 3012.55);
+        assert_eq!(parse_price(r#"{"ethereum":{"usd":2999.1}}"#, "/ethereum/usd").unwrap(), 2999.1);
+        assert!(parse_price(r#"{"data":{"amount":"0"}}"#, "/data/amount").is_err());
+        assert!(parse_price(r#"{"data":{}}"#, "/data/amount").is_err());
+    }
+}
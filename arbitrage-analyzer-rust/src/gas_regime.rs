@@ -0,0 +1,325 @@
+// Gas price history and regimes. Every GAS_POLL_SECS the market gas price of each
+// chain with fee data is sampled: base fee plus the median priority fee in gwei
+// on Ethereum (from its percentile feed, else the gas oracle), the median
+// priority fee in lamports on Solana. Samples are appended to GAS_HISTORY_FILE
+// (default `swapsleuth-gas-history.jsonl`, empty keeps them in memory only) and
+// read back at startup, so the history survives deploys; the file is rewritten
+// with the samples still in the window once it holds twice as many lines.
+//
+// Once a chain has GAS_REGIME_MIN_SAMPLES (default 30) samples within the last
+// GAS_REGIME_WINDOW_HOURS (default 24), its latest one is labelled against them:
+// `low` at or under the GAS_REGIME_LOW_PERCENTILE (default 25), `spike` at or
+// over the GAS_REGIME_SPIKE_PERCENTILE (default 90), `normal` in between. A route
+// with a DEX leg takes the worst regime of its legs' chains; the `gas_regime`
+// enricher labels its opportunities with it, and GAS_REGIME_THRESHOLD_MULTIPLIERS
+// (`regime:multiplier`, e.g. `low:0.8,spike:2`) scales the profit and ROI
+// thresholds it has to clear. Regimes not listed keep the thresholds as they are.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::maintenance::Chain;
+use crate::{config, numeric, SpreadAnalyzer};
+
+const DEFAULT_HISTORY_FILE: &str = "swapsleuth-gas-history.jsonl";
+const DEFAULT_WINDOW_HOURS: i64 = 24;
+const DEFAULT_MIN_SAMPLES: usize = 30;
+const DEFAULT_LOW_PERCENTILE: f64 = 25.0;
+const DEFAULT_SPIKE_PERCENTILE: f64 = 90.0;
+const CHAINS: [Chain; 3] = [Chain::Ethereum, Chain::Solana, Chain::Osmosis];
+
+/// How the current gas price compares with the chain's recent history; ordered from cheapest to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GasRegime {
+    Low,
+    Normal,
+    Spike,
+}
+
+impl GasRegime {
+    pub fn name(self) -> &'static str {
+        match self {
+            GasRegime::Low => "low",
+            GasRegime::Normal => "normal",
+            GasRegime::Spike => "spike",
+        }
+    }
+}
+
+impl fmt::Display for GasRegime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredSample {
+    at: DateTime<Utc>,
+    chain: String,
+    price: f64,
+}
+
+/// A chain's regime and what it was judged against
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainRegime {
+    pub chain: &'static str,
+    pub regime: GasRegime,
+    pub price: f64,
+    pub low_below: f64,
+    pub spike_above: f64,
+    pub samples: usize,
+}
+
+#[derive(Debug)]
+pub struct GasHistory {
+    // None keeps samples in memory only
+    path: Option<PathBuf>,
+    window: Duration,
+    min_samples: usize,
+    low_percentile: f64,
+    spike_percentile: f64,
+    multipliers: HashMap<String, f64>,
+    interval: Duration,
+    samples: HashMap<Chain, VecDeque<(DateTime<Utc>, f64)>>,
+    regimes: HashMap<Chain, ChainRegime>,
+    last_sample: Option<DateTime<Utc>>,
+    // Opened on the first append
+    file: Option<File>,
+    lines: usize,
+}
+
+impl GasHistory {
+    pub fn new(path: Option<PathBuf>, interval: std::time::Duration) -> Self {
+        GasHistory {
+            path,
+            window: Duration::hours(DEFAULT_WINDOW_HOURS),
+            min_samples: DEFAULT_MIN_SAMPLES,
+            low_percentile: DEFAULT_LOW_PERCENTILE,
+            spike_percentile: DEFAULT_SPIKE_PERCENTILE,
+            multipliers: HashMap::new(),
+            interval: Duration::from_std(interval).unwrap_or(Duration::MAX),
+            samples: HashMap::new(),
+            regimes: HashMap::new(),
+            last_sample: None,
+            file: None,
+            lines: 0,
+        }
+    }
+
+    pub fn from_env(interval: std::time::Duration) -> Self {
+        let path = config::env_var("GAS_HISTORY_FILE").unwrap_or_else(|_| DEFAULT_HISTORY_FILE.to_string());
+        GasHistory {
+            window: Duration::hours(config::env_or("GAS_REGIME_WINDOW_HOURS", DEFAULT_WINDOW_HOURS)),
+            min_samples: config::env_or("GAS_REGIME_MIN_SAMPLES", DEFAULT_MIN_SAMPLES).max(2),
+            low_percentile: config::env_or("GAS_REGIME_LOW_PERCENTILE", DEFAULT_LOW_PERCENTILE).clamp(0.0, 100.0),
+            spike_percentile: config::env_or("GAS_REGIME_SPIKE_PERCENTILE", DEFAULT_SPIKE_PERCENTILE).clamp(0.0, 100.0),
+            multipliers: config::env_map("GAS_REGIME_THRESHOLD_MULTIPLIERS"),
+            ..Self::new((!path.is_empty()).then(|| PathBuf::from(path)), interval)
+        }
+    }
+
+    fn due(&self, now: DateTime<Utc>) -> bool {
+        self.last_sample.is_none_or(|at| now - at >= self.interval)
+    }
+
+    fn trim(&mut self, now: DateTime<Utc>) {
+        for samples in self.samples.values_mut() {
+            while samples.front().is_some_and(|(at, _)| now - *at > self.window) {
+                samples.pop_front();
+            }
+        }
+    }
+
+    fn retained(&self) -> usize {
+        self.samples.values().map(VecDeque::len).sum()
+    }
+
+    fn append(&mut self, sample: &StoredSample) -> Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        if self.file.is_none() {
+            self.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        }
+        if let Some(file) = &mut self.file {
+            writeln!(file, "{}", serde_json::to_string(sample)?)?;
+        }
+        self.lines += 1;
+        Ok(())
+    }
+
+    /// Add the latest price of each chain, relabel the chains and persist the samples
+    pub fn record(&mut self, prices: Vec<(Chain, f64)>, now: DateTime<Utc>) {
+        self.last_sample = Some(now);
+        for (chain, price) in prices.into_iter().filter(|(_, price)| price.is_finite() && *price >= 0.0) {
+            self.samples.entry(chain).or_default().push_back((now, price));
+            if let Err(e) = self.append(&StoredSample { at: now, chain: chain.name().to_string(), price }) {
+                warn!("Failed to persist the {} gas price: {}", chain.name(), e);
+            }
+        }
+        self.trim(now);
+        self.relabel();
+        if self.lines > 2 * self.retained().max(self.min_samples) {
+            self.compact();
+        }
+    }
+
+    fn relabel(&mut self) {
+        self.regimes.clear();
+        for (chain, samples) in &self.samples {
+            let Some(&(_, price)) = samples.back().filter(|_| samples.len() >= self.min_samples) else { continue };
+            let prices: Vec<f64> = samples.iter().map(|(_, price)| *price).collect();
+            let (Some(low_below), Some(spike_above)) =
+                (numeric::percentile(&prices, self.low_percentile), numeric::percentile(&prices, self.spike_percentile))
+            else {
+                continue;
+            };
+            let regime = if price >= spike_above && spike_above > low_below {
+                GasRegime::Spike
+            } else if price <= low_below && spike_above > low_below {
+                GasRegime::Low
+            } else {
+                GasRegime::Normal
+            };
+            self.regimes.insert(*chain, ChainRegime { chain: chain.name(), regime, price, low_below, spike_above, samples: samples.len() });
+        }
+    }
+
+    /// Read back the samples of the file still in the window. A line that doesn't parse is skipped
+    pub fn load(&mut self, now: DateTime<Utc>) {
+        let Some(path) = &self.path else { return };
+        let Ok(raw) = fs::read_to_string(path) else { return };
+        self.samples.clear();
+        self.lines = 0;
+        for line in raw.lines().filter(|line| !line.trim().is_empty()) {
+            self.lines += 1;
+            let Ok(sample) = serde_json::from_str::<StoredSample>(line) else { continue };
+            if let Some(chain) = Chain::parse(&sample.chain) {
+                self.samples.entry(chain).or_default().push_back((sample.at, sample.price));
+            }
+        }
+        for samples in self.samples.values_mut() {
+            samples.make_contiguous().sort_by_key(|(at, _)| *at);
+        }
+        self.trim(now);
+        self.relabel();
+    }
+
+    /// Rewrite the file with the samples in the window
+    pub fn compact(&mut self) {
+        let Some(path) = &self.path else { return };
+        // Write then rename, like the intent log, so a crash can't leave half a file
+        let tmp = path.with_extension("tmp");
+        let write = || -> Result<()> {
+            let mut out = String::new();
+            for (chain, samples) in &self.samples {
+                for (at, price) in samples {
+                    out.push_str(&serde_json::to_string(&StoredSample { at: *at, chain: chain.name().to_string(), price: *price })?);
+                    out.push('\n');
+                }
+            }
+            fs::write(&tmp, out)?;
+            fs::rename(&tmp, path)?;
+            Ok(())
+        };
+        match write() {
+            Ok(()) => {
+                self.file = None;
+                self.lines = self.retained();
+            }
+            Err(e) => warn!("Failed to compact gas history {}: {}", path.display(), e),
+        }
+    }
+
+    pub fn regime(&self, chain: Chain) -> Option<GasRegime> {
+        self.regimes.get(&chain).map(|regime| regime.regime)
+    }
+
+    /// The worst regime of the chains `buy` and `sell` settle on; None for routes between CEXes
+    pub fn route_regime(&self, buy: &str, sell: &str) -> Option<GasRegime> {
+        [buy, sell].into_iter().filter_map(Chain::of_venue).filter_map(|chain| self.regime(chain)).max()
+    }
+
+    /// What the route's profit and ROI thresholds are multiplied by
+    pub fn threshold_multiplier(&self, buy: &str, sell: &str) -> f64 {
+        self.route_regime(buy, sell).and_then(|regime| self.multipliers.get(regime.name()).copied()).unwrap_or(1.0)
+    }
+
+    pub fn report(&self) -> Vec<&ChainRegime> {
+        CHAINS.iter().filter_map(|chain| self.regimes.get(chain)).collect()
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Sample every chain's market gas price, when due
+    pub(crate) fn sample_gas(&mut self, now: DateTime<Utc>) {
+        if !self.gas_history.due(now) {
+            return;
+        }
+        let prices = CHAINS.into_iter().filter_map(|chain| Some((chain, self.gas.market_price(chain)?))).collect();
+        self.gas_history.record(prices, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 20 samples between 20 and 24 gwei, one every 12s from `start`, persisted to `path`
+    fn history(path: &std::path::Path, start: DateTime<Utc>) -> GasHistory {
+        let _ = fs::remove_file(path);
+        let mut history = GasHistory::new(Some(path.to_path_buf()), std::time::Duration::from_secs(12));
+        history.min_samples = 10;
+        history.multipliers = HashMap::from([("spike".to_string(), 2.0)]);
+        for i in 0..20 {
+            history.record(vec![(Chain::Ethereum, 20.0 + ((i + 3) % 5) as f64)], start + Duration::seconds(12 * i));
+        }
+        history
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("swapsleuth-gas-history-{}-{}.jsonl", name, std::process::id()))
+    }
+
+    #[test]
+    fn labels_the_latest_price_against_the_window() {
+        let path = temp_path("labels");
+        let start = Utc::now() - Duration::hours(1);
+        let mut history = history(&path, start);
+        assert_eq!(history.regime(Chain::Ethereum), Some(GasRegime::Normal));
+        history.record(vec![(Chain::Ethereum, 90.0)], start + Duration::seconds(240));
+        assert_eq!(history.regime(Chain::Ethereum), Some(GasRegime::Spike));
+        assert_eq!(history.route_regime("binance", "uniswap-v3-exact"), Some(GasRegime::Spike));
+        assert_eq!(history.route_regime("binance", "raydium"), None);
+        assert_eq!(history.threshold_multiplier("uniswap-v3-exact", "okx"), 2.0);
+        history.record(vec![(Chain::Ethereum, 5.0)], start + Duration::seconds(252));
+        assert_eq!(history.regime(Chain::Ethereum), Some(GasRegime::Low));
+        assert_eq!(history.threshold_multiplier("uniswap-v3-exact", "okx"), 1.0);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_restart_reads_the_history_back() {
+        let path = temp_path("restart");
+        let start = Utc::now() - Duration::hours(1);
+        let mut history = history(&path, start);
+        history.record(vec![(Chain::Ethereum, 5.0)], start + Duration::seconds(240));
+
+        let mut restarted = GasHistory::new(Some(path.clone()), std::time::Duration::from_secs(12));
+        restarted.min_samples = 10;
+        restarted.load(start + Duration::seconds(260));
+        assert_eq!(restarted.samples[&Chain::Ethereum].len(), 21);
+        assert_eq!(restarted.regime(Chain::Ethereum), Some(GasRegime::Low));
+        // Outside the window nothing is left to judge by
+        restarted.load(start + Duration::hours(25));
+        assert_eq!(restarted.regime(Chain::Ethereum), None);
+        let _ = fs::remove_file(&path);
+    }
+}
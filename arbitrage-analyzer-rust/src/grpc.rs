@@ -0,0 +1,329 @@
+// gRPC API for executors that want a typed contract instead of Redis JSON.
+// Build with `--features grpc` and set GRPC_ADDR (e.g. 127.0.0.1:50051) to serve
+// the `swapsleuth.v1.Analyzer` service of proto/swapsleuth.proto:
+//  - SubscribeOpportunities streams every opportunity published on
+//    OPPORTUNITY_CHANNEL, optionally filtered by pair and net profit. Each
+//    subscriber has GRPC_STREAM_BUFFER opportunities of slack; one that falls
+//    further behind skips ahead, counted in swapsleuth_grpc_opportunities_dropped_total;
+//  - Pause, Resume and SetThresholds apply the control commands of the same
+//    names (see control.rs) and GetState reports the result.
+// The server runs on its own thread; control calls are answered by the analyzer
+// loop between updates, like API requests.
+
+use crate::config;
+
+/// GRPC_ADDR, unset or empty when the gRPC API is off
+pub fn addr_from_env() -> Option<String> {
+    config::env_var("GRPC_ADDR").ok().filter(|addr| !addr.trim().is_empty())
+}
+
+#[cfg(feature = "grpc")]
+pub use server::GrpcServer;
+
+/// Without gRPC support in the build there is never a server
+#[cfg(not(feature = "grpc"))]
+#[derive(Debug)]
+pub enum GrpcServer {}
+
+#[cfg(not(feature = "grpc"))]
+impl GrpcServer {
+    pub fn spawn(_addr: &str, _metrics: std::sync::Arc<crate::metrics::Metrics>) -> anyhow::Result<Self> {
+        Err(anyhow::anyhow!("GRPC_ADDR is set but this build has no gRPC support (--features grpc)"))
+    }
+
+    pub fn broadcast(&self, _opp: &crate::ArbitrageOpportunity) {
+        match *self {}
+    }
+
+    pub fn serve(&self, _analyzer: &mut crate::SpreadAnalyzer) {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "grpc")]
+pub mod proto {
+    // The messages of proto/swapsleuth.proto, tag for tag
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequest {
+        #[prost(string, repeated, tag = "1")]
+        pub pairs: Vec<String>,
+        #[prost(double, tag = "2")]
+        pub min_net_profit: f64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Opportunity {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(string, tag = "2")]
+        pub pair: String,
+        #[prost(string, tag = "3")]
+        pub buy_exchange: String,
+        #[prost(string, tag = "4")]
+        pub sell_exchange: String,
+        #[prost(double, tag = "5")]
+        pub buy_price: f64,
+        #[prost(double, tag = "6")]
+        pub sell_price: f64,
+        #[prost(double, tag = "7")]
+        pub max_size: f64,
+        #[prost(double, tag = "8")]
+        pub sell_size: f64,
+        #[prost(double, tag = "9")]
+        pub estimated_fees: f64,
+        #[prost(double, tag = "10")]
+        pub net_profit: f64,
+        #[prost(double, tag = "11")]
+        pub roi_percentage: f64,
+        #[prost(int64, tag = "12")]
+        pub timestamp_ms: i64,
+        #[prost(string, tag = "13")]
+        pub tenant: String,
+        #[prost(string, tag = "14")]
+        pub strategy_id: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PauseRequest {
+        #[prost(string, tag = "1")]
+        pub reason: String,
+        #[prost(string, tag = "2")]
+        pub actor: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ResumeRequest {
+        #[prost(string, tag = "1")]
+        pub actor: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SetThresholdsRequest {
+        #[prost(double, optional, tag = "1")]
+        pub min_profit: Option<f64>,
+        #[prost(double, optional, tag = "2")]
+        pub min_roi_percentage: Option<f64>,
+        #[prost(string, tag = "3")]
+        pub actor: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GetStateRequest {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct State {
+        #[prost(string, tag = "1")]
+        pub mode: String,
+        #[prost(bool, tag = "2")]
+        pub paused: bool,
+        #[prost(string, tag = "3")]
+        pub pause_reason: String,
+        #[prost(string, tag = "4")]
+        pub paused_by: String,
+        #[prost(int64, tag = "5")]
+        pub paused_at_ms: i64,
+        #[prost(double, tag = "6")]
+        pub min_profit: f64,
+        #[prost(double, tag = "7")]
+        pub min_roi_percentage: f64,
+        #[prost(bool, tag = "8")]
+        pub kill_switch_tripped: bool,
+        #[prost(bool, tag = "9")]
+        pub idle: bool,
+        #[prost(uint64, tag = "10")]
+        pub books: u64,
+        #[prost(uint64, tag = "11")]
+        pub live_opportunities: u64,
+    }
+
+    include!(concat!(env!("OUT_DIR"), "/swapsleuth.v1.Analyzer.rs"));
+}
+
+#[cfg(feature = "grpc")]
+mod server {
+    use std::net::SocketAddr;
+    use std::pin::Pin;
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use log::{error, info};
+    use tokio::sync::{broadcast, oneshot};
+    use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+    use tokio_stream::wrappers::{BroadcastStream, TcpListenerStream};
+    use tokio_stream::{Stream, StreamExt};
+    use tonic::{Request, Response, Status};
+
+    use super::proto::analyzer_server::{Analyzer, AnalyzerServer};
+    use super::proto::{GetStateRequest, Opportunity, PauseRequest, ResumeRequest, SetThresholdsRequest, State, SubscribeRequest};
+    use crate::control::{ControlCommand, ControlState};
+    use crate::metrics::Metrics;
+    use crate::{config, ArbitrageOpportunity, SpreadAnalyzer};
+
+    const DEFAULT_STREAM_BUFFER: usize = 1024;
+    // How long a control call waits for the analyzer loop, as for API requests
+    const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+    // A control call handed to the analyzer loop; GetState has no command
+    struct Call {
+        command: Option<ControlCommand>,
+        reply: oneshot::Sender<Result<ControlState, String>>,
+    }
+
+    #[derive(Debug)]
+    pub struct GrpcServer {
+        calls: Receiver<Call>,
+        opportunities: broadcast::Sender<Opportunity>,
+    }
+
+    impl GrpcServer {
+        /// Bind `addr` and serve on a background thread
+        pub fn spawn(addr: &str, metrics: Arc<Metrics>) -> Result<Self> {
+            let addr: SocketAddr = addr.parse()?;
+            let (calls_tx, calls) = mpsc::channel();
+            let (opportunities, _) = broadcast::channel(config::env_or("GRPC_STREAM_BUFFER", DEFAULT_STREAM_BUFFER).max(1));
+            let service = Service { calls: calls_tx, opportunities: opportunities.clone(), metrics };
+            let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(2).enable_all().build()?;
+            // Bound here so a taken port is reported at startup rather than from the thread
+            let listener = runtime.block_on(tokio::net::TcpListener::bind(addr))?;
+            info!("  gRPC API listening on {}", addr);
+            thread::spawn(move || {
+                runtime.block_on(async move {
+                    let served = tonic::transport::Server::builder()
+                        .add_service(AnalyzerServer::new(service))
+                        .serve_with_incoming(TcpListenerStream::new(listener))
+                        .await;
+                    if let Err(e) = served {
+                        error!("gRPC API stopped: {}", e);
+                    }
+                })
+            });
+            Ok(GrpcServer { calls, opportunities })
+        }
+
+        /// Stream `opp` to the subscribers; nobody listening is fine
+        pub fn broadcast(&self, opp: &ArbitrageOpportunity) {
+            let _ = self.opportunities.send(Opportunity {
+                id: opp.id.clone(),
+                pair: opp.pair.clone(),
+                buy_exchange: opp.buy_exchange.clone(),
+                sell_exchange: opp.sell_exchange.clone(),
+                buy_price: opp.buy_price,
+                sell_price: opp.sell_price,
+                max_size: opp.max_size,
+                sell_size: opp.sell_size,
+                estimated_fees: opp.estimated_fees,
+                net_profit: opp.net_profit,
+                roi_percentage: opp.roi_percentage,
+                timestamp_ms: opp.timestamp.timestamp_millis(),
+                tenant: opp.tag.tenant.clone().unwrap_or_default(),
+                strategy_id: opp.tag.strategy_id.clone().unwrap_or_default(),
+            });
+        }
+
+        /// Answer every control call received since the last poll
+        pub fn serve(&self, analyzer: &mut SpreadAnalyzer) {
+            for call in self.calls.try_iter() {
+                let applied = match call.command {
+                    Some(command) => analyzer.apply_control_command(command, "grpc").map_err(|e| e.to_string()),
+                    None => Ok(()),
+                };
+                // The caller may have timed out already; nothing to do then
+                let _ = call.reply.send(applied.map(|()| analyzer.control_state()));
+            }
+        }
+    }
+
+    impl From<ControlState> for State {
+        fn from(state: ControlState) -> Self {
+            let pause = state.paused.as_ref();
+            State {
+                mode: state.mode,
+                paused: pause.is_some(),
+                pause_reason: pause.map(|p| p.reason.clone()).unwrap_or_default(),
+                paused_by: pause.map(|p| p.actor.clone()).unwrap_or_default(),
+                paused_at_ms: pause.map_or(0, |p| p.since.timestamp_millis()),
+                min_profit: state.thresholds.min_profit,
+                min_roi_percentage: state.thresholds.min_roi_percentage,
+                kill_switch_tripped: state.kill_switch_tripped,
+                idle: state.idle,
+                books: state.books as u64,
+                live_opportunities: state.live_opportunities as u64,
+            }
+        }
+    }
+
+    // proto3 strings are empty when unset
+    fn non_empty(raw: String) -> Option<String> {
+        (!raw.is_empty()).then_some(raw)
+    }
+
+    struct Service {
+        calls: Sender<Call>,
+        opportunities: broadcast::Sender<Opportunity>,
+        metrics: Arc<Metrics>,
+    }
+
+    impl Service {
+        async fn call(&self, command: Option<ControlCommand>) -> Result<Response<State>, Status> {
+            let (reply, answer) = oneshot::channel();
+            self.calls.send(Call { command, reply }).map_err(|_| Status::unavailable("analyzer is not running"))?;
+            match tokio::time::timeout(REPLY_TIMEOUT, answer).await {
+                Ok(Ok(Ok(state))) => Ok(Response::new(state.into())),
+                Ok(Ok(Err(e))) => Err(Status::invalid_argument(e)),
+                Ok(Err(_)) => Err(Status::unavailable("analyzer is not running")),
+                Err(_) => Err(Status::deadline_exceeded("analyzer did not answer in time")),
+            }
+        }
+    }
+
+    type OpportunityStream = Pin<Box<dyn Stream<Item = Result<Opportunity, Status>> + Send>>;
+
+    #[tonic::async_trait]
+    impl Analyzer for Service {
+        type SubscribeOpportunitiesStream = OpportunityStream;
+
+        async fn subscribe_opportunities(&self, request: Request<SubscribeRequest>) -> Result<Response<OpportunityStream>, Status> {
+            let filter = request.into_inner();
+            let metrics = self.metrics.clone();
+            let stream = BroadcastStream::new(self.opportunities.subscribe()).filter_map(move |received| match received {
+                Ok(opp) if (filter.pairs.is_empty() || filter.pairs.contains(&opp.pair)) && opp.net_profit >= filter.min_net_profit => {
+                    Some(Ok(opp))
+                }
+                Ok(_) => None,
+                Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                    metrics.grpc_opportunities_dropped.fetch_add(missed, std::sync::atomic::Ordering::Relaxed);
+                    None
+                }
+            });
+            Ok(Response::new(Box::pin(stream)))
+        }
+
+        async fn pause(&self, request: Request<PauseRequest>) -> Result<Response<State>, Status> {
+            let request = request.into_inner();
+            self.call(Some(ControlCommand::Pause { reason: non_empty(request.reason), actor: non_empty(request.actor) })).await
+        }
+
+        async fn resume(&self, request: Request<ResumeRequest>) -> Result<Response<State>, Status> {
+            self.call(Some(ControlCommand::Resume { actor: non_empty(request.into_inner().actor) })).await
+        }
+
+        async fn set_thresholds(&self, request: Request<SetThresholdsRequest>) -> Result<Response<State>, Status> {
+            let request = request.into_inner();
+            self.call(Some(ControlCommand::SetThresholds {
+                min_profit: request.min_profit,
+                min_roi_percentage: request.min_roi_percentage,
+                cost_multiple: None,
+                actor: non_empty(request.actor),
+            }))
+            .await
+        }
+
+        async fn get_state(&self, _request: Request<GetStateRequest>) -> Result<Response<State>, Status> {
+            self.call(None).await
+        }
+    }
+}
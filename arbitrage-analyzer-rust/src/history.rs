@@ -0,0 +1,717 @@
+// Opportunity, execution and market coverage history in Postgres. Enabled by building with
+// `--features postgres` and setting DATABASE_URL; otherwise every record is
+// dropped and the history endpoints answer 503. Writes go through a channel to a
+// background task so the analysis loop never waits on the database. Retention and
+// roll-ups run on the same runtime, see retention.rs.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::lifecycle::{RequestState, RouteKey};
+use crate::market_history::MarketSnapshot;
+use crate::{ArbitrageOpportunity, SpreadAnalyzer};
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+
+#[derive(Debug, Clone, Serialize)]
+pub enum HistoryRecord {
+    Opportunity(Box<ArbitrageOpportunity>),
+    ExecutionRequest {
+        id: String,
+        opportunity_id: String,
+        route: RouteKey,
+        execution_size: f64,
+        created_at: DateTime<Utc>,
+    },
+    Transition {
+        request_id: String,
+        state: RequestState,
+        at: DateTime<Utc>,
+    },
+    // Outcome of a request that reached a terminal state
+    ExecutionResult {
+        request_id: String,
+        state: RequestState,
+        outcome: ExecutionOutcome,
+        recorded_at: DateTime<Utc>,
+    },
+    // Venue coverage every MARKET_HISTORY_SECS, see `market_history.rs`
+    MarketSummary(Box<MarketSnapshot>),
+}
+
+/// What the executor reported alongside a terminal state, all optional
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExecutionOutcome {
+    pub filled_size: Option<f64>,
+    pub realized_pnl: Option<f64>,
+    pub detail: Option<String>,
+    // Average fill prices, and the prices quoted when the orders reached the venues
+    pub buy_fill_price: Option<f64>,
+    pub sell_fill_price: Option<f64>,
+    pub buy_arrival_price: Option<f64>,
+    pub sell_arrival_price: Option<f64>,
+    // Trading and withdrawal fees paid, and gas/transaction/transfer costs, in quote currency
+    pub fees_paid: Option<f64>,
+    pub gas_paid: Option<f64>,
+}
+
+impl ExecutionOutcome {
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Self> {
+        let number = |name: &str| -> Result<Option<f64>> {
+            query
+                .get(name)
+                .map(|raw| raw.parse::<f64>().map_err(|_| anyhow!("invalid {}: {}", name, raw)))
+                .transpose()
+        };
+        Ok(ExecutionOutcome {
+            filled_size: number("filled_size")?,
+            realized_pnl: number("realized_pnl")?,
+            detail: query.get("detail").cloned(),
+            buy_fill_price: number("buy_fill_price")?,
+            sell_fill_price: number("sell_fill_price")?,
+            buy_arrival_price: number("buy_arrival_price")?,
+            sell_arrival_price: number("sell_arrival_price")?,
+            fees_paid: number("fees_paid")?,
+            gas_paid: number("gas_paid")?,
+        })
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Record a lifecycle transition, plus the execution result once the request is finished
+    pub fn record_transition(&mut self, request_id: &str, state: RequestState, at: DateTime<Utc>, outcome: ExecutionOutcome) {
+        self.history.record(HistoryRecord::Transition { request_id: request_id.to_string(), state, at });
+        self.settle_ack(request_id, state);
+        if state == RequestState::Published {
+            if let Some(request) = self.lifecycle.get(request_id) {
+                self.success_rates.published(request_id, request.route.clone());
+            }
+        }
+        if state.is_terminal() {
+            self.intents.resolve(request_id, state, at);
+            self.balances.release(request_id);
+            self.route_locks.release(request_id);
+            self.observe_request_timing(request_id, state);
+            let trade = self.cost_attribution.close(request_id, state == RequestState::Filled, &outcome);
+            if let Some(trade) = trade {
+                log::debug!("Request {} on {}: shortfall {:.2} vs estimate", request_id, trade.route, trade.shortfall);
+            }
+            let profit = trade.map(|trade| (trade.expected_net, trade.realized_net));
+            self.success_rates.close(request_id, state == RequestState::Filled, profit);
+            self.history.record(HistoryRecord::ExecutionResult {
+                request_id: request_id.to_string(),
+                state,
+                outcome,
+                recorded_at: at,
+            });
+        }
+    }
+}
+
+/// Filters for `GET /history/opportunities`, newest first
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpportunityFilter {
+    pub pair: Option<String>,
+    pub buy_exchange: Option<String>,
+    pub sell_exchange: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl OpportunityFilter {
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Self> {
+        let time = |name: &str| -> Result<Option<DateTime<Utc>>> {
+            query
+                .get(name)
+                .map(|raw| {
+                    DateTime::parse_from_rfc3339(raw)
+                        .map(|t| t.with_timezone(&Utc))
+                        .map_err(|e| anyhow!("invalid {} (expected RFC 3339): {}", name, e))
+                })
+                .transpose()
+        };
+        let number = |name: &str, default: i64| -> Result<i64> {
+            match query.get(name) {
+                Some(raw) => raw.parse::<i64>().map_err(|_| anyhow!("invalid {}: {}", name, raw)),
+                None => Ok(default),
+            }
+        };
+
+        Ok(OpportunityFilter {
+            pair: query.get("pair").cloned(),
+            buy_exchange: query.get("buy_exchange").cloned(),
+            sell_exchange: query.get("sell_exchange").cloned(),
+            from: time("from")?,
+            to: time("to")?,
+            limit: number("limit", DEFAULT_PAGE_SIZE)?.clamp(1, MAX_PAGE_SIZE),
+            offset: number("offset", 0)?.max(0),
+        })
+    }
+}
+
+#[cfg(feature = "postgres")]
+pub use pg::HistoryStore;
+
+#[cfg(not(feature = "postgres"))]
+#[derive(Debug)]
+pub struct HistoryStore;
+
+#[cfg(not(feature = "postgres"))]
+impl HistoryStore {
+    pub fn from_env() -> Result<Self> {
+        if crate::config::env_var("DATABASE_URL").is_ok() {
+            log::warn!("DATABASE_URL is set but this build has no postgres support; history is disabled");
+        }
+        Ok(HistoryStore)
+    }
+
+    pub fn enabled(&self) -> bool {
+        false
+    }
+
+    pub fn record(&self, _record: HistoryRecord) {}
+
+    pub fn query_opportunities(&self, _filter: &OpportunityFilter) -> Result<serde_json::Value> {
+        Err(anyhow!("history is not enabled (build with --features postgres and set DATABASE_URL)"))
+    }
+
+    pub fn seasonality(&self, _filter: &OpportunityFilter) -> Result<Vec<crate::seasonality::SeasonalityCell>> {
+        Err(anyhow!("history is not enabled (build with --features postgres and set DATABASE_URL)"))
+    }
+
+    pub fn market_summaries(&self, _filter: &crate::market_history::MarketHistoryFilter) -> Result<Vec<MarketSnapshot>> {
+        Err(anyhow!("history is not enabled (build with --features postgres and set DATABASE_URL)"))
+    }
+
+    pub fn spawn_maintenance(&self, _config: crate::retention::RetentionConfig, _metrics: std::sync::Arc<crate::metrics::Metrics>) {}
+}
+
+#[cfg(feature = "postgres")]
+mod pg {
+    use std::collections::HashMap;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use anyhow::{anyhow, Result};
+    use chrono::{DateTime, Utc};
+    use log::{info, warn};
+    use serde_json::json;
+    use sqlx::postgres::{PgPool, PgPoolOptions};
+    use sqlx::{Postgres, QueryBuilder, Row};
+    use tokio::runtime::Runtime;
+    use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+    use super::{HistoryRecord, OpportunityFilter};
+    use crate::lifecycle::RouteKey;
+    use crate::market_history::{MarketHistoryFilter, MarketSnapshot, VenueCoverage};
+    use crate::metrics::Metrics;
+    use crate::retention::RetentionConfig;
+    use crate::seasonality::SeasonalityCell;
+
+    const SCHEMA: &str = include_str!("../migrations/001_history.sql");
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+    // History queries run on the analyzer loop, so they must stay short
+    const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+    // Seasonality reports without a `from` cover this many days
+    const DEFAULT_SEASONALITY_DAYS: i64 = 30;
+    // Tables the store size covers
+    const TABLES: &[&str] = &[
+        "routes",
+        "opportunities",
+        "opportunity_hourly",
+        "execution_requests",
+        "lifecycle_transitions",
+        "execution_results",
+        "market_summaries",
+        "venue_coverage",
+    ];
+
+    struct Connected {
+        runtime: Runtime,
+        pool: PgPool,
+        writer: UnboundedSender<HistoryRecord>,
+    }
+
+    pub struct HistoryStore {
+        inner: Option<Connected>,
+    }
+
+    impl std::fmt::Debug for HistoryStore {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("HistoryStore").field("enabled", &self.inner.is_some()).finish()
+        }
+    }
+
+    impl HistoryStore {
+        pub fn from_env() -> Result<Self> {
+            let Ok(url) = crate::config::env_var("DATABASE_URL") else {
+                return Ok(HistoryStore { inner: None });
+            };
+
+            // One worker is plenty: it only drains the write channel and serves the odd query
+            let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(1).enable_all().build()?;
+            let pool = runtime.block_on(async {
+                let pool = PgPoolOptions::new().max_connections(4).acquire_timeout(CONNECT_TIMEOUT).connect(&url).await?;
+                sqlx::raw_sql(SCHEMA).execute(&pool).await?;
+                Ok::<_, sqlx::Error>(pool)
+            })?;
+
+            let (writer, rx) = mpsc::unbounded_channel();
+            runtime.spawn(write_records(pool.clone(), rx));
+            info!("  Recording opportunity history to Postgres");
+
+            Ok(HistoryStore { inner: Some(Connected { runtime, pool, writer }) })
+        }
+
+        pub fn enabled(&self) -> bool {
+            self.inner.is_some()
+        }
+
+        pub fn record(&self, record: HistoryRecord) {
+            if let Some(connected) = &self.inner {
+                let _ = connected.writer.send(record);
+            }
+        }
+
+        fn connected(&self) -> Result<&Connected> {
+            self.inner.as_ref().ok_or_else(|| anyhow!("history is not enabled (set DATABASE_URL)"))
+        }
+
+        pub fn query_opportunities(&self, filter: &OpportunityFilter) -> Result<serde_json::Value> {
+            let connected = self.connected()?;
+            connected.runtime.block_on(async {
+                tokio::time::timeout(QUERY_TIMEOUT, query_opportunities(&connected.pool, filter))
+                    .await
+                    .map_err(|_| anyhow!("history query timed out"))?
+            })
+        }
+
+        pub fn seasonality(&self, filter: &OpportunityFilter) -> Result<Vec<SeasonalityCell>> {
+            let connected = self.connected()?;
+            connected.runtime.block_on(async {
+                tokio::time::timeout(QUERY_TIMEOUT, query_seasonality(&connected.pool, filter))
+                    .await
+                    .map_err(|_| anyhow!("seasonality query timed out"))?
+            })
+        }
+
+        pub fn market_summaries(&self, filter: &MarketHistoryFilter) -> Result<Vec<MarketSnapshot>> {
+            let connected = self.connected()?;
+            connected.runtime.block_on(async {
+                tokio::time::timeout(QUERY_TIMEOUT, query_market_summaries(&connected.pool, filter))
+                    .await
+                    .map_err(|_| anyhow!("market summary query timed out"))?
+            })
+        }
+
+        /// Measure, roll up and prune the history every `config.interval`
+        pub fn spawn_maintenance(&self, config: RetentionConfig, metrics: Arc<Metrics>) {
+            let Some(connected) = &self.inner else { return };
+            info!(
+                "  History retention: raw {}, hourly roll-ups {}",
+                config.raw_days.map_or("kept".to_string(), |days| format!("{} days", days)),
+                config.rollup_days.map_or("kept".to_string(), |days| format!("{} days", days))
+            );
+            let pool = connected.pool.clone();
+            connected.runtime.spawn(async move {
+                let mut ticks = tokio::time::interval(config.interval);
+                loop {
+                    ticks.tick().await;
+                    if let Err(e) = maintain(&pool, &config, &metrics).await {
+                        warn!("History maintenance failed: {}", e);
+                    }
+                }
+            });
+        }
+    }
+
+    async fn maintain(pool: &PgPool, config: &RetentionConfig, metrics: &Metrics) -> Result<()> {
+        let now = Utc::now();
+        let mut pruned: u64 = 0;
+        if let Some(cutoff) = config.raw_cutoff(now) {
+            // One statement, so rows are never deleted without their aggregate
+            let rolled_up: i64 = sqlx::query_scalar(
+                "WITH expired AS (
+                     DELETE FROM opportunities o
+                     WHERE o.detected_at < $1
+                       AND NOT EXISTS (SELECT 1 FROM execution_requests e WHERE e.opportunity_id = o.id)
+                     RETURNING o.route_id, o.detected_at, o.net_profit, o.roi_percentage
+                 ), rolled AS (
+                     INSERT INTO opportunity_hourly (route_id, hour, opportunities, total_net_profit, max_net_profit, total_roi_percentage)
+                     SELECT route_id, date_trunc('hour', detected_at AT TIME ZONE 'UTC') AT TIME ZONE 'UTC',
+                            COUNT(*), SUM(net_profit), MAX(net_profit), SUM(roi_percentage)
+                     FROM expired GROUP BY 1, 2
+                     ON CONFLICT (route_id, hour) DO UPDATE SET
+                         opportunities = opportunity_hourly.opportunities + EXCLUDED.opportunities,
+                         total_net_profit = opportunity_hourly.total_net_profit + EXCLUDED.total_net_profit,
+                         max_net_profit = GREATEST(opportunity_hourly.max_net_profit, EXCLUDED.max_net_profit),
+                         total_roi_percentage = opportunity_hourly.total_roi_percentage + EXCLUDED.total_roi_percentage
+                 )
+                 SELECT COUNT(*) FROM expired",
+            )
+            .bind(cutoff)
+            .fetch_one(pool)
+            .await?;
+            let summaries = sqlx::query("DELETE FROM market_summaries WHERE recorded_at < $1").bind(cutoff).execute(pool).await?;
+            if rolled_up > 0 || summaries.rows_affected() > 0 {
+                info!(
+                    "Rolled up {} opportunities and removed {} market summaries recorded before {}",
+                    rolled_up,
+                    summaries.rows_affected(),
+                    cutoff.to_rfc3339()
+                );
+            }
+            pruned += rolled_up as u64 + summaries.rows_affected();
+        }
+        if let Some(cutoff) = config.rollup_cutoff(now) {
+            pruned += sqlx::query("DELETE FROM opportunity_hourly WHERE hour < $1").bind(cutoff).execute(pool).await?.rows_affected();
+        }
+        if pruned > 0 {
+            metrics.history_rows_pruned.fetch_add(pruned, Ordering::Relaxed);
+            if config.vacuum {
+                sqlx::raw_sql("VACUUM (ANALYZE) opportunities, opportunity_hourly, market_summaries, venue_coverage").execute(pool).await?;
+            }
+        }
+
+        let tables: Vec<String> = TABLES.iter().map(|table| table.to_string()).collect();
+        let bytes: i64 = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(pg_total_relation_size(c.oid)), 0)::int8 FROM pg_class c
+             WHERE c.relkind = 'r' AND c.relname = ANY($1) AND pg_table_is_visible(c.oid)",
+        )
+        .bind(&tables)
+        .fetch_one(pool)
+        .await?;
+        // The planner's estimate; counting would scan the table
+        let rows: i64 = sqlx::query_scalar(
+            "SELECT COALESCE(MAX(c.reltuples), 0)::int8 FROM pg_class c WHERE c.relname = 'opportunities' AND pg_table_is_visible(c.oid)",
+        )
+        .fetch_one(pool)
+        .await?;
+        metrics.history_store_bytes.store(bytes.max(0) as u64, Ordering::Relaxed);
+        metrics.history_opportunity_rows.store(rows.max(0) as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn write_records(pool: PgPool, mut rx: UnboundedReceiver<HistoryRecord>) {
+        let mut route_ids: HashMap<RouteKey, i32> = HashMap::new();
+        while let Some(record) = rx.recv().await {
+            if let Err(e) = write_record(&pool, &mut route_ids, &record).await {
+                warn!("Failed to write history record: {}", e);
+            }
+        }
+    }
+
+    async fn route_id(pool: &PgPool, route_ids: &mut HashMap<RouteKey, i32>, route: &RouteKey) -> Result<i32> {
+        if let Some(id) = route_ids.get(route) {
+            return Ok(*id);
+        }
+        let id: i32 = sqlx::query_scalar(
+            "INSERT INTO routes (pair, buy_exchange, sell_exchange) VALUES ($1, $2, $3)
+             ON CONFLICT (pair, buy_exchange, sell_exchange) DO UPDATE SET pair = EXCLUDED.pair
+             RETURNING id",
+        )
+        .bind(&route.pair)
+        .bind(&route.buy_exchange)
+        .bind(&route.sell_exchange)
+        .fetch_one(pool)
+        .await?;
+        route_ids.insert(route.clone(), id);
+        Ok(id)
+    }
+
+    async fn write_record(pool: &PgPool, route_ids: &mut HashMap<RouteKey, i32>, record: &HistoryRecord) -> Result<()> {
+        match record {
+            HistoryRecord::Opportunity(opp) => {
+                let route = RouteKey::new(&opp.pair, &opp.buy_exchange, &opp.sell_exchange);
+                let route_id = route_id(pool, route_ids, &route).await?;
+                sqlx::query(
+                    "INSERT INTO opportunities (id, route_id, detected_at, buy_price, sell_price, max_size, sell_size,
+                         gross_profit_per_unit, estimated_fees, net_profit, roi_percentage)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                     ON CONFLICT (id) DO NOTHING",
+                )
+                .bind(&opp.id)
+                .bind(route_id)
+                .bind(opp.timestamp)
+                .bind(opp.buy_price)
+                .bind(opp.sell_price)
+                .bind(opp.max_size)
+                .bind(opp.sell_size)
+                .bind(opp.gross_profit_per_unit)
+                .bind(opp.estimated_fees)
+                .bind(opp.net_profit)
+                .bind(opp.roi_percentage)
+                .execute(pool)
+                .await?;
+            }
+            HistoryRecord::ExecutionRequest { id, opportunity_id, route, execution_size, created_at } => {
+                let route_id = route_id(pool, route_ids, route).await?;
+                sqlx::query(
+                    "INSERT INTO execution_requests (id, opportunity_id, route_id, execution_size, created_at)
+                     VALUES ($1, $2, $3, $4, $5)
+                     ON CONFLICT (id) DO NOTHING",
+                )
+                .bind(id)
+                .bind(opportunity_id)
+                .bind(route_id)
+                .bind(execution_size)
+                .bind(created_at)
+                .execute(pool)
+                .await?;
+            }
+            HistoryRecord::Transition { request_id, state, at } => {
+                sqlx::query("INSERT INTO lifecycle_transitions (request_id, state, at) VALUES ($1, $2, $3)")
+                    .bind(request_id)
+                    .bind(state.to_string().to_lowercase())
+                    .bind(at)
+                    .execute(pool)
+                    .await?;
+            }
+            HistoryRecord::ExecutionResult { request_id, state, outcome, recorded_at } => {
+                sqlx::query(
+                    "INSERT INTO execution_results (request_id, state, filled_size, realized_pnl, detail, recorded_at,
+                         buy_fill_price, sell_fill_price, buy_arrival_price, sell_arrival_price, fees_paid, gas_paid)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                     ON CONFLICT (request_id) DO UPDATE SET state = EXCLUDED.state, filled_size = EXCLUDED.filled_size,
+                         realized_pnl = EXCLUDED.realized_pnl, detail = EXCLUDED.detail, recorded_at = EXCLUDED.recorded_at,
+                         buy_fill_price = EXCLUDED.buy_fill_price, sell_fill_price = EXCLUDED.sell_fill_price,
+                         buy_arrival_price = EXCLUDED.buy_arrival_price, sell_arrival_price = EXCLUDED.sell_arrival_price,
+                         fees_paid = EXCLUDED.fees_paid, gas_paid = EXCLUDED.gas_paid",
+                )
+                .bind(request_id)
+                .bind(state.to_string().to_lowercase())
+                .bind(outcome.filled_size)
+                .bind(outcome.realized_pnl)
+                .bind(&outcome.detail)
+                .bind(recorded_at)
+                .bind(outcome.buy_fill_price)
+                .bind(outcome.sell_fill_price)
+                .bind(outcome.buy_arrival_price)
+                .bind(outcome.sell_arrival_price)
+                .bind(outcome.fees_paid)
+                .bind(outcome.gas_paid)
+                .execute(pool)
+                .await?;
+            }
+            HistoryRecord::MarketSummary(summary) => {
+                let mut tx = pool.begin().await?;
+                let id: i64 = sqlx::query_scalar(
+                    "INSERT INTO market_summaries (recorded_at, books, pairs, cross_listed_pairs) VALUES ($1, $2, $3, $4) RETURNING id",
+                )
+                .bind(summary.recorded_at)
+                .bind(summary.books as i64)
+                .bind(summary.pairs as i64)
+                .bind(summary.cross_listed_pairs as i64)
+                .fetch_one(&mut *tx)
+                .await?;
+                for venue in &summary.venues {
+                    sqlx::query(
+                        "INSERT INTO venue_coverage (summary_id, exchange, books, pairs, stale_books, updates_per_minute,
+                             last_update_age_ms, rejection_rate)
+                         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                    )
+                    .bind(id)
+                    .bind(&venue.exchange)
+                    .bind(venue.books as i64)
+                    .bind(venue.pairs as i64)
+                    .bind(venue.stale_books as i64)
+                    .bind(venue.updates_per_minute)
+                    .bind(venue.last_update_age_ms)
+                    .bind(venue.rejection_rate)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                tx.commit().await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Route and time-range conditions shared by the opportunity queries
+    fn push_filter<'a>(query: &mut QueryBuilder<'a, Postgres>, filter: &'a OpportunityFilter) {
+        if let Some(pair) = &filter.pair {
+            query.push(" AND r.pair = ").push_bind(pair);
+        }
+        if let Some(exchange) = &filter.buy_exchange {
+            query.push(" AND r.buy_exchange = ").push_bind(exchange);
+        }
+        if let Some(exchange) = &filter.sell_exchange {
+            query.push(" AND r.sell_exchange = ").push_bind(exchange);
+        }
+        if let Some(from) = filter.from {
+            query.push(" AND o.detected_at >= ").push_bind(from);
+        }
+        if let Some(to) = filter.to {
+            query.push(" AND o.detected_at < ").push_bind(to);
+        }
+    }
+
+    async fn query_opportunities(pool: &PgPool, filter: &OpportunityFilter) -> Result<serde_json::Value> {
+        let mut query: QueryBuilder<Postgres> = QueryBuilder::new(
+            "SELECT o.id, r.pair, r.buy_exchange, r.sell_exchange, o.detected_at, o.buy_price, o.sell_price,
+                    o.max_size, o.sell_size, o.gross_profit_per_unit, o.estimated_fees, o.net_profit, o.roi_percentage
+             FROM opportunities o JOIN routes r ON r.id = o.route_id WHERE TRUE",
+        );
+        push_filter(&mut query, filter);
+        query.push(" ORDER BY o.detected_at DESC, o.id DESC LIMIT ").push_bind(filter.limit);
+        query.push(" OFFSET ").push_bind(filter.offset);
+
+        let rows = query.build().fetch_all(pool).await?;
+        let mut opportunities = Vec::with_capacity(rows.len());
+        for row in &rows {
+            opportunities.push(json!({
+                "id": row.try_get::<String, _>("id")?,
+                "pair": row.try_get::<String, _>("pair")?,
+                "buy_exchange": row.try_get::<String, _>("buy_exchange")?,
+                "sell_exchange": row.try_get::<String, _>("sell_exchange")?,
+                "detected_at": row.try_get::<DateTime<Utc>, _>("detected_at")?,
+                "buy_price": row.try_get::<f64, _>("buy_price")?,
+                "sell_price": row.try_get::<f64, _>("sell_price")?,
+                "max_size": row.try_get::<f64, _>("max_size")?,
+                "sell_size": row.try_get::<f64, _>("sell_size")?,
+                "gross_profit_per_unit": row.try_get::<f64, _>("gross_profit_per_unit")?,
+                "estimated_fees": row.try_get::<f64, _>("estimated_fees")?,
+                "net_profit": row.try_get::<f64, _>("net_profit")?,
+                "roi_percentage": row.try_get::<f64, _>("roi_percentage")?,
+            }));
+        }
+
+        // A full page means there may be more
+        let next_offset = (opportunities.len() as i64 == filter.limit).then_some(filter.offset + filter.limit);
+        Ok(json!({ "opportunities": opportunities, "next_offset": next_offset }))
+    }
+
+    async fn query_market_summaries(pool: &PgPool, filter: &MarketHistoryFilter) -> Result<Vec<MarketSnapshot>> {
+        let mut query: QueryBuilder<Postgres> =
+            QueryBuilder::new("SELECT id, recorded_at, books, pairs, cross_listed_pairs FROM market_summaries WHERE TRUE");
+        if let Some(from) = filter.from {
+            query.push(" AND recorded_at >= ").push_bind(from);
+        }
+        if let Some(to) = filter.to {
+            query.push(" AND recorded_at < ").push_bind(to);
+        }
+        query.push(" ORDER BY recorded_at DESC, id DESC LIMIT ").push_bind(filter.limit);
+        query.push(" OFFSET ").push_bind(filter.offset);
+        let rows = query.build().fetch_all(pool).await?;
+        let ids: Vec<i64> = rows.iter().map(|row| row.try_get("id")).collect::<Result<_, _>>()?;
+
+        let mut venues: QueryBuilder<Postgres> = QueryBuilder::new(
+            "SELECT summary_id, exchange, books, pairs, stale_books, updates_per_minute, last_update_age_ms, rejection_rate
+             FROM venue_coverage WHERE summary_id = ANY(",
+        );
+        venues.push_bind(&ids).push(")");
+        if let Some(exchange) = &filter.exchange {
+            venues.push(" AND exchange = ").push_bind(exchange);
+        }
+        venues.push(" ORDER BY exchange");
+        let mut coverage: HashMap<i64, Vec<VenueCoverage>> = HashMap::new();
+        for row in venues.build().fetch_all(pool).await? {
+            coverage.entry(row.try_get("summary_id")?).or_default().push(VenueCoverage {
+                exchange: row.try_get("exchange")?,
+                books: row.try_get::<i64, _>("books")? as usize,
+                pairs: row.try_get::<i64, _>("pairs")? as usize,
+                stale_books: row.try_get::<i64, _>("stale_books")? as usize,
+                updates_per_minute: row.try_get("updates_per_minute")?,
+                last_update_age_ms: row.try_get("last_update_age_ms")?,
+                rejection_rate: row.try_get("rejection_rate")?,
+            });
+        }
+
+        let mut summaries = Vec::with_capacity(rows.len());
+        for (row, id) in rows.iter().zip(&ids) {
+            summaries.push(MarketSnapshot {
+                recorded_at: row.try_get("recorded_at")?,
+                books: row.try_get::<i64, _>("books")? as usize,
+                pairs: row.try_get::<i64, _>("pairs")? as usize,
+                cross_listed_pairs: row.try_get::<i64, _>("cross_listed_pairs")? as usize,
+                venues: coverage.remove(id).unwrap_or_default(),
+            });
+        }
+        Ok(summaries)
+    }
+
+    async fn query_seasonality(pool: &PgPool, filter: &OpportunityFilter) -> Result<Vec<SeasonalityCell>> {
+        let filter = OpportunityFilter {
+            from: filter.from.or_else(|| Some(Utc::now() - chrono::Duration::days(DEFAULT_SEASONALITY_DAYS))),
+            ..filter.clone()
+        };
+        let mut query: QueryBuilder<Postgres> = QueryBuilder::new(
+            "SELECT r.pair, r.buy_exchange, r.sell_exchange,
+                    EXTRACT(ISODOW FROM o.detected_at AT TIME ZONE 'UTC')::int4 AS weekday,
+                    EXTRACT(HOUR FROM o.detected_at AT TIME ZONE 'UTC')::int4 AS hour,
+                    SUM(o.opportunities)::int8 AS opportunities, SUM(o.total_net_profit) AS total_net_profit,
+                    MAX(o.max_net_profit) AS max_net_profit, SUM(o.total_roi_percentage) AS total_roi_percentage
+             FROM (
+                 SELECT route_id, detected_at, 1::int8 AS opportunities, net_profit AS total_net_profit,
+                        net_profit AS max_net_profit, roi_percentage AS total_roi_percentage
+                 FROM opportunities
+                 -- Rolled-up hours count the same as the rows they replaced
+                 UNION ALL
+                 SELECT route_id, hour, opportunities, total_net_profit, max_net_profit, total_roi_percentage
+                 FROM opportunity_hourly
+             ) o JOIN routes r ON r.id = o.route_id WHERE TRUE",
+        );
+        push_filter(&mut query, &filter);
+        query.push(" GROUP BY r.pair, r.buy_exchange, r.sell_exchange, weekday, hour");
+
+        let rows = query.build().fetch_all(pool).await?;
+        let mut cells = Vec::with_capacity(rows.len());
+        for row in &rows {
+            cells.push(SeasonalityCell {
+                route: RouteKey::new(
+                    &row.try_get::<String, _>("pair")?,
+                    &row.try_get::<String, _>("buy_exchange")?,
+                    &row.try_get::<String, _>("sell_exchange")?,
+                ),
+                weekday: row.try_get::<i32, _>("weekday")? as u32,
+                hour: row.try_get::<i32, _>("hour")? as u32,
+                opportunities: row.try_get("opportunities")?,
+                total_net_profit: row.try_get("total_net_profit")?,
+                max_net_profit: row.try_get("max_net_profit")?,
+                total_roi_percentage: row.try_get("total_roi_percentage")?,
+            });
+        }
+        Ok(cells)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn filter_defaults_and_clamps_paging() {
+        let filter = OpportunityFilter::from_query(&HashMap::new()).unwrap();
+        assert_eq!((filter.limit, filter.offset), (DEFAULT_PAGE_SIZE, 0));
+
+        let filter = OpportunityFilter::from_query(&query(&[("limit", "50000"), ("offset", "-3")])).unwrap();
+        assert_eq!((filter.limit, filter.offset), (MAX_PAGE_SIZE, 0));
+    }
+
+    #[test]
+    fn filter_parses_route_and_time_range() {
+        let filter = OpportunityFilter::from_query(&query(&[
+            ("pair", "BTC/USDT"),
+            ("buy_exchange", "binance"),
+            ("from", "2024-01-01T00:00:00Z"),
+        ]))
+        .unwrap();
+        assert_eq!(filter.pair.as_deref(), Some("BTC/USDT"));
+        assert_eq!(filter.buy_exchange.as_deref(), Some("binance"));
+        assert_eq!(filter.from.map(|t| t.timestamp()), Some(1_704_067_200));
+
+        assert!(OpportunityFilter::from_query(&query(&[("to", "yesterday")])).is_err());
+        assert!(OpportunityFilter::from_query(&query(&[("limit", "ten")])).is_err());
+    }
+}
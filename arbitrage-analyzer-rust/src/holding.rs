@@ -0,0 +1,233 @@
+// Holding costs of the legs of a route. A route is not closed the moment both
+// legs fill: the base asset is in transit until the transfer lands, or pre-funded
+// inventory waits for the next rebalance, and a basis or stat-arb leg on a
+// perpetual or a margin account stays open all that time. What each leg costs (or
+// earns) while it is held is charged with the fees in `estimate_fees_and_gas`:
+//  - funding: the rate perpetual longs pay shorts every 8 hours, in bps of the
+//    leg's notional. The buy leg is long and pays it, the sell leg is short and
+//    receives it; a negative rate works the other way round.
+//  - borrow: what financing the leg costs a year, in percent of its notional,
+//    e.g. quote borrowed on margin to buy, or base borrowed to sell short.
+// Venues trade `spot` unless HOLDING_INSTRUMENTS says otherwise, e.g.
+// `binance-perp:perpetual,kraken:margin`. Rates are keyed by venue and instrument
+// type (`binance-perp.perpetual:1.2`), or by instrument type alone for every venue
+// (`margin:8`), in HOLDING_FUNDING_BPS and HOLDING_BORROW_APR. The holding period
+// is the capital's lockup (see `capital`), or HOLDING_PERIOD_SECS when set.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use log::warn;
+
+use crate::capital::SECONDS_PER_YEAR;
+use crate::config;
+
+// Perpetual funding is settled every 8 hours on the major venues
+const FUNDING_INTERVAL_SECS: f64 = 8.0 * 3_600.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum InstrumentType {
+    #[default]
+    Spot,
+    Perpetual,
+    Margin,
+}
+
+impl FromStr for InstrumentType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "spot" => Ok(InstrumentType::Spot),
+            "perpetual" | "perp" | "swap" => Ok(InstrumentType::Perpetual),
+            "margin" => Ok(InstrumentType::Margin),
+            other => Err(anyhow!("unknown instrument type: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for InstrumentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InstrumentType::Spot => "spot",
+            InstrumentType::Perpetual => "perpetual",
+            InstrumentType::Margin => "margin",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct HoldingRate {
+    // bps of notional per 8h funding interval, paid by longs when positive
+    pub funding_bps: f64,
+    // Percent of notional per year
+    pub borrow_apr_pct: f64,
+}
+
+// Where a rate applies: one venue's instrument, or the instrument on every venue
+type RateKey = (Option<String>, InstrumentType);
+
+#[derive(Debug, Clone, Default)]
+pub struct HoldingCosts {
+    // Venues not listed trade spot
+    instruments: HashMap<String, InstrumentType>,
+    rates: HashMap<RateKey, HoldingRate>,
+    // None: the route's capital lockup
+    pub period_secs: Option<f64>,
+}
+
+// `venue.instrument` or `instrument`
+fn rate_key(setting: &str, key: &str) -> Option<RateKey> {
+    let (venue, instrument) = match key.rsplit_once('.') {
+        Some((venue, instrument)) => (Some(venue.to_string()), instrument),
+        None => (None, key),
+    };
+    match instrument.parse() {
+        Ok(instrument) => Some((venue, instrument)),
+        Err(e) => {
+            warn!("Ignoring {} entry {:?}: {}", setting, key, e);
+            None
+        }
+    }
+}
+
+impl HoldingCosts {
+    pub fn from_env() -> Self {
+        let mut rates: HashMap<RateKey, HoldingRate> = HashMap::new();
+        for (key, bps) in config::env_map::<f64>("HOLDING_FUNDING_BPS") {
+            if let Some(key) = rate_key("HOLDING_FUNDING_BPS", &key) {
+                rates.entry(key).or_default().funding_bps = bps;
+            }
+        }
+        for (key, apr) in config::env_map::<f64>("HOLDING_BORROW_APR") {
+            if let Some(key) = rate_key("HOLDING_BORROW_APR", &key) {
+                rates.entry(key).or_default().borrow_apr_pct = apr;
+            }
+        }
+        let mut costs = HoldingCosts::default();
+        for ((venue, instrument), rate) in rates {
+            costs.set_rate(venue.as_deref(), instrument, rate);
+        }
+        for (venue, instrument) in config::env_map::<InstrumentType>("HOLDING_INSTRUMENTS") {
+            costs.set_instrument(&venue, instrument);
+        }
+        costs.period_secs = Some(config::env_or("HOLDING_PERIOD_SECS", -1.0)).filter(|secs| *secs >= 0.0);
+        costs
+    }
+
+    pub fn set_instrument(&mut self, venue: &str, instrument: InstrumentType) {
+        self.instruments.insert(venue.to_string(), instrument);
+    }
+
+    /// Rates for `instrument` on `venue`, or on every venue when `venue` is None
+    pub fn set_rate(&mut self, venue: Option<&str>, instrument: InstrumentType, rate: HoldingRate) {
+        self.rates.insert((venue.map(str::to_string), instrument), rate);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty()
+    }
+
+    pub fn instrument(&self, venue: &str) -> InstrumentType {
+        self.instruments.get(venue).copied().unwrap_or_default()
+    }
+
+    /// The venue's own rate for its instrument, else the instrument's
+    pub fn rate(&self, venue: &str) -> HoldingRate {
+        let instrument = self.instrument(venue);
+        self.rates
+            .get(&(Some(venue.to_string()), instrument))
+            .or_else(|| self.rates.get(&(None, instrument)))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // One leg held for `secs`; `long` pays funding, the short side receives it
+    fn leg_cost(&self, venue: &str, notional: f64, long: bool, secs: f64) -> f64 {
+        let rate = self.rate(venue);
+        let side = if long { 1.0 } else { -1.0 };
+        let funding = side * notional * rate.funding_bps / 10_000.0 * secs / FUNDING_INTERVAL_SECS;
+        let borrow = notional * rate.borrow_apr_pct / 100.0 * secs / SECONDS_PER_YEAR;
+        funding + borrow
+    }
+
+    /// Quote-valued cost of holding both legs for the holding period (or `lockup_secs`).
+    /// Negative when funding received outweighs what is paid
+    pub fn route_cost(&self, buy_exchange: &str, buy_notional: f64, sell_exchange: &str, sell_notional: f64, lockup_secs: f64) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let secs = self.period_secs.unwrap_or(lockup_secs).max(0.0);
+        self.leg_cost(buy_exchange, buy_notional, true, secs) + self.leg_cost(sell_exchange, sell_notional, false, secs)
+    }
+
+    /// For the startup log, e.g. `binance-perp perpetual (funding 1bps/8h, borrow 0%/y)`
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .rates
+            .iter()
+            .map(|((venue, instrument), rate)| {
+                format!("{} {} (funding {}bps/8h, borrow {}%/y)", venue.as_deref().unwrap_or("any venue"), instrument, rate.funding_bps, rate.borrow_apr_pct)
+            })
+            .collect();
+        lines.sort();
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn perp_costs() -> HoldingCosts {
+        let mut costs = HoldingCosts::default();
+        costs.set_instrument("binance-perp", InstrumentType::Perpetual);
+        costs.set_instrument("kraken", InstrumentType::Margin);
+        costs.set_rate(None, InstrumentType::Perpetual, HoldingRate { funding_bps: 1.0, borrow_apr_pct: 0.0 });
+        costs.set_rate(Some("binance-perp"), InstrumentType::Perpetual, HoldingRate { funding_bps: 2.0, borrow_apr_pct: 0.0 });
+        costs.set_rate(None, InstrumentType::Margin, HoldingRate { funding_bps: 0.0, borrow_apr_pct: 10.0 });
+        costs
+    }
+
+    #[test]
+    fn venue_rates_win_over_instrument_rates() {
+        let mut costs = perp_costs();
+        assert_eq!(costs.rate("binance-perp").funding_bps, 2.0);
+        costs.set_instrument("okx-perp", InstrumentType::Perpetual);
+        assert_eq!(costs.rate("okx-perp").funding_bps, 1.0);
+        // Spot venues hold nothing without a spot rate
+        assert_eq!(costs.rate("binance"), HoldingRate::default());
+        assert_eq!("swap".parse::<InstrumentType>().unwrap(), InstrumentType::Perpetual);
+        assert!("future".parse::<InstrumentType>().is_err());
+    }
+
+    #[test]
+    fn longs_pay_funding_and_shorts_receive_it() {
+        let costs = perp_costs();
+        let eight_hours = FUNDING_INTERVAL_SECS;
+        // Long the perpetual: 2bps of 100k for one interval
+        assert!((costs.route_cost("binance-perp", 100_000.0, "binance", 100_000.0, eight_hours) - 20.0).abs() < 1e-9);
+        // Short it: the funding is received
+        assert!((costs.route_cost("binance", 100_000.0, "binance-perp", 100_000.0, eight_hours) + 20.0).abs() < 1e-9);
+        // Borrowing on margin for a year at 10%, on either leg
+        let year = costs.route_cost("binance", 50_000.0, "kraken", 50_000.0, SECONDS_PER_YEAR);
+        assert!((year - 5_000.0).abs() < 1e-6);
+        // A fixed holding period wins over the lockup
+        let fixed = HoldingCosts { period_secs: Some(eight_hours * 3.0), ..costs };
+        assert!((fixed.route_cost("binance-perp", 100_000.0, "binance", 100_000.0, 1.0) - 60.0).abs() < 1e-9);
+        assert_eq!(HoldingCosts::default().route_cost("binance-perp", 100_000.0, "binance", 100_000.0, eight_hours), 0.0);
+    }
+
+    #[test]
+    fn holding_costs_are_charged_with_the_fees() {
+        let mut analyzer = crate::SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let without = analyzer.estimate_fees_and_gas(1.0, 50_000.0, 50_500.0, "binance", "binance-perp", "BTC/USDT").total;
+        analyzer.fees_config.holding = perp_costs();
+        let with = analyzer.estimate_fees_and_gas(1.0, 50_000.0, 50_500.0, "binance", "binance-perp", "BTC/USDT");
+        // Short what reaches the perpetual over the transfer lockup: the funding received lowers the cost
+        let received = with.sell_size * 50_500.0 * 2.0 / 10_000.0 * analyzer.capital_config.lockup_secs(false) / FUNDING_INTERVAL_SECS;
+        assert!((without - with.total - received).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,220 @@
+// Low-activity mode. When no venue has sent a book for IDLE_AFTER_SECS, or the
+// clock is outside ACTIVE_HOURS, the analyzer goes idle:
+//  - the loop waits IDLE_POLL_MS instead of 250ms between housekeeping passes.
+//    A book still wakes it at once, since the queue wakes the waiting loop;
+//  - logging drops to IDLE_LOG_LEVEL;
+//  - comprehensive sweeps are put off; one that comes due runs once the
+//    analyzer is active again, like one deferred while shedding load.
+// A book ends quiet idling immediately. Off-hours idling lasts until ACTIVE_HOURS
+// start again; books that arrive meanwhile are still analyzed one by one.
+
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveTime, Utc};
+use log::{info, LevelFilter};
+use serde::Serialize;
+
+use crate::metrics::Metrics;
+use crate::{config, SpreadAnalyzer};
+
+const DEFAULT_IDLE_AFTER_SECS: i64 = 300;
+const DEFAULT_IDLE_POLL_MS: u64 = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdleReason {
+    // No venue has updated for IDLE_AFTER_SECS
+    Quiet,
+    // Outside ACTIVE_HOURS
+    OffHours,
+}
+
+impl fmt::Display for IdleReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdleReason::Quiet => f.write_str("no venue updates"),
+            IdleReason::OffHours => f.write_str("outside active hours"),
+        }
+    }
+}
+
+/// `HH:MM-HH:MM` in UTC; a window that ends before it starts runs past midnight
+pub fn parse_active_hours(raw: &str) -> Result<(NaiveTime, NaiveTime)> {
+    let (start, end) = raw.split_once('-').ok_or_else(|| anyhow!("expected HH:MM-HH:MM, got {}", raw))?;
+    let time = |value: &str| NaiveTime::parse_from_str(value.trim(), "%H:%M").map_err(|e| anyhow!("invalid time {}: {}", value.trim(), e));
+    Ok((time(start)?, time(end)?))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IdleStatus {
+    pub idle: Option<IdleReason>,
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub struct IdleMode {
+    // None: never idle for lack of updates
+    after: Option<chrono::Duration>,
+    active_hours: Option<(NaiveTime, NaiveTime)>,
+    poll_interval: Duration,
+    log_level: LevelFilter,
+    // Last book applied, or when the analyzer started waiting for one
+    last_activity: Option<DateTime<Utc>>,
+    status: IdleStatus,
+    // The log level to go back to
+    active_log_level: LevelFilter,
+}
+
+impl IdleMode {
+    pub fn new(after: Option<chrono::Duration>, active_hours: Option<(NaiveTime, NaiveTime)>) -> Self {
+        IdleMode {
+            after,
+            active_hours,
+            poll_interval: Duration::from_millis(DEFAULT_IDLE_POLL_MS),
+            log_level: LevelFilter::Warn,
+            last_activity: None,
+            status: IdleStatus { idle: None, since: None },
+            active_log_level: LevelFilter::Info,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let after_secs: i64 = config::env_or("IDLE_AFTER_SECS", DEFAULT_IDLE_AFTER_SECS);
+        let active_hours = config::env_var("ACTIVE_HOURS").ok().filter(|raw| !raw.trim().is_empty()).and_then(|raw| {
+            parse_active_hours(&raw).map_err(|e| log::warn!("Ignoring ACTIVE_HOURS: {}", e)).ok()
+        });
+        IdleMode {
+            poll_interval: Duration::from_millis(config::env_or("IDLE_POLL_MS", DEFAULT_IDLE_POLL_MS)),
+            log_level: config::env_or("IDLE_LOG_LEVEL", LevelFilter::Warn),
+            ..IdleMode::new((after_secs > 0).then(|| chrono::Duration::seconds(after_secs)), active_hours)
+        }
+    }
+
+    pub fn status(&self) -> &IdleStatus {
+        &self.status
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.status.idle.is_some()
+    }
+
+    /// How long the loop waits for a book before its next housekeeping pass
+    pub fn poll_interval(&self, active: Duration) -> Duration {
+        if self.is_idle() { self.poll_interval } else { active }
+    }
+
+    fn in_active_hours(&self, now: DateTime<Utc>) -> bool {
+        let Some((start, end)) = self.active_hours else { return true };
+        let time = now.time();
+        if start <= end { start <= time && time < end } else { time >= start || time < end }
+    }
+
+    fn reason(&self, now: DateTime<Utc>) -> Option<IdleReason> {
+        if !self.in_active_hours(now) {
+            return Some(IdleReason::OffHours);
+        }
+        match (self.after, self.last_activity) {
+            (Some(after), Some(last)) if now - last >= after => Some(IdleReason::Quiet),
+            _ => None,
+        }
+    }
+
+    /// Go idle or back to active as `now` requires. Returns the new state when it changed
+    pub fn update(&mut self, now: DateTime<Utc>) -> Option<Option<IdleReason>> {
+        self.last_activity.get_or_insert(now);
+        let reason = self.reason(now);
+        if reason == self.status.idle {
+            return None;
+        }
+        self.status = IdleStatus { idle: reason, since: reason.map(|_| now) };
+        Some(reason)
+    }
+
+    fn quiet_logging(&mut self) {
+        self.active_log_level = log::max_level();
+        log::set_max_level(self.log_level.min(self.active_log_level));
+    }
+
+    fn restore_logging(&self) {
+        log::set_max_level(self.active_log_level);
+    }
+
+    /// A book arrived; ends quiet idling at once
+    pub fn record_activity(&mut self, now: DateTime<Utc>) -> Option<Option<IdleReason>> {
+        self.last_activity = Some(now);
+        self.update(now)
+    }
+}
+
+impl SpreadAnalyzer {
+    // Announce a change of the idle state while logging is at the active level, then adjust it
+    fn apply_idle_change(&mut self, was_idle: bool, change: Option<Option<IdleReason>>, now: DateTime<Utc>) {
+        let Some(state) = change else { return };
+        self.metrics.idle.store(state.is_some() as u64, std::sync::atomic::Ordering::Relaxed);
+        match state {
+            Some(reason) => {
+                if !was_idle {
+                    Metrics::inc(&self.metrics.idle_periods);
+                }
+                info!(" Going idle ({}): polling every {}ms, comprehensive sweeps paused", reason, self.idle.poll_interval.as_millis());
+                if !was_idle {
+                    self.idle.quiet_logging();
+                }
+            }
+            None => {
+                self.idle.restore_logging();
+                info!(" Active again at {}", now.format("%H:%M:%S UTC"));
+            }
+        }
+    }
+
+    /// Called from housekeeping: go idle once the venues fall quiet or active hours end
+    pub(crate) fn update_idle(&mut self, now: DateTime<Utc>) {
+        let was_idle = self.idle.is_idle();
+        let change = self.idle.update(now);
+        self.apply_idle_change(was_idle, change, now);
+    }
+
+    /// Called for every book: wakes the analyzer from quiet idling
+    pub(crate) fn record_idle_activity(&mut self, now: DateTime<Utc>) {
+        let was_idle = self.idle.is_idle();
+        let change = self.idle.record_activity(now);
+        self.apply_idle_change(was_idle, change, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(h: u32, m: u32, s: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 31, h, m, s).unwrap()
+    }
+
+    #[test]
+    fn idles_when_quiet_until_books_return() {
+        let mut idle = IdleMode::new(Some(chrono::Duration::seconds(300)), None);
+        assert_eq!(idle.update(at(12, 0, 0)), None);
+        assert_eq!(idle.update(at(12, 4, 59)), None);
+        assert_eq!(idle.update(at(12, 5, 0)), Some(Some(IdleReason::Quiet)));
+        assert_eq!(idle.poll_interval(Duration::from_millis(250)), Duration::from_millis(DEFAULT_IDLE_POLL_MS));
+        assert_eq!(idle.record_activity(at(12, 6, 0)), Some(None));
+        assert!(!idle.is_idle());
+    }
+
+    #[test]
+    fn idles_outside_the_active_hours() {
+        // 22:00 to 06:00 active, across midnight
+        let hours = parse_active_hours("22:00-06:00").unwrap();
+        let mut idle = IdleMode::new(None, Some(hours));
+        assert_eq!(idle.update(at(23, 0, 0)), None);
+        assert_eq!(idle.update(at(6, 0, 0)), Some(Some(IdleReason::OffHours)));
+        // Books don't end off-hours idling
+        assert_eq!(idle.record_activity(at(12, 0, 0)), None);
+        assert_eq!(idle.update(at(22, 0, 0)), Some(None));
+        assert!(parse_active_hours("9-17").is_err());
+    }
+}
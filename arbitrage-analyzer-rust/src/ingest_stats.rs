@@ -0,0 +1,126 @@
+// Per-exchange feed health: how often books arrive, how deep they are, and how
+// many we had to throw away at ingest.
+
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::numeric;
+
+// Rolling window used for rates, gaps and depth
+const WINDOW_SAMPLES: usize = 500;
+
+#[derive(Debug, Default)]
+struct ExchangeIngest {
+    update_times: VecDeque<DateTime<Utc>>,
+    depths: VecDeque<usize>,
+    accepted: u64,
+    rejected: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExchangeIngestSummary {
+    pub exchange: String,
+    pub updates_per_minute: f64,
+    pub median_gap_ms: Option<f64>,
+    pub average_depth: f64,
+    pub last_update_age_ms: Option<i64>,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub rejection_rate: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct IngestStats {
+    exchanges: HashMap<String, ExchangeIngest>,
+}
+
+/// Exchange name from a Redis book key (`orderbook:binance:WBTC/USDT` or `binance:WBTC/USDT`)
+pub fn exchange_from_key(key: &str) -> Option<&str> {
+    let parts: Vec<&str> = key.split(':').collect();
+    match parts.as_slice() {
+        ["orderbook", exchange, _pair] => Some(exchange),
+        [exchange, _pair] => Some(exchange),
+        _ => None,
+    }
+}
+
+impl IngestStats {
+    pub fn record_accepted(&mut self, exchange: &str, depth: usize, now: DateTime<Utc>) {
+        let entry = self.exchanges.entry(exchange.to_string()).or_default();
+        entry.accepted += 1;
+        entry.update_times.push_back(now);
+        entry.depths.push_back(depth);
+        while entry.update_times.len() > WINDOW_SAMPLES {
+            entry.update_times.pop_front();
+        }
+        while entry.depths.len() > WINDOW_SAMPLES {
+            entry.depths.pop_front();
+        }
+    }
+
+    pub fn record_rejected(&mut self, exchange: &str) {
+        self.exchanges.entry(exchange.to_string()).or_default().rejected += 1;
+    }
+
+    pub fn summaries(&self, now: DateTime<Utc>) -> Vec<ExchangeIngestSummary> {
+        let mut summaries: Vec<ExchangeIngestSummary> = self
+            .exchanges
+            .iter()
+            .map(|(exchange, ingest)| {
+                let one_minute_ago = now - Duration::minutes(1);
+                let gaps: Vec<f64> = ingest
+                    .update_times
+                    .iter()
+                    .zip(ingest.update_times.iter().skip(1))
+                    .map(|(a, b)| (*b - *a).num_milliseconds() as f64)
+                    .collect();
+                let depth_total: usize = ingest.depths.iter().sum();
+                let total = ingest.accepted + ingest.rejected;
+
+                ExchangeIngestSummary {
+                    exchange: exchange.clone(),
+                    updates_per_minute: ingest.update_times.iter().filter(|t| **t > one_minute_ago).count() as f64,
+                    median_gap_ms: numeric::percentile(&gaps, 50.0),
+                    average_depth: numeric::safe_div(depth_total as f64, ingest.depths.len() as f64).unwrap_or(0.0),
+                    last_update_age_ms: ingest.update_times.back().map(|t| (now - *t).num_milliseconds()),
+                    accepted: ingest.accepted,
+                    rejected: ingest.rejected,
+                    rejection_rate: numeric::safe_div(ingest.rejected as f64, total as f64).unwrap_or(0.0),
+                }
+            })
+            .collect();
+        summaries.sort_by(|a, b| a.exchange.cmp(&b.exchange));
+        summaries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exchange_is_parsed_from_both_key_formats() {
+        assert_eq!(exchange_from_key("orderbook:binance:WBTC/USDT"), Some("binance"));
+        assert_eq!(exchange_from_key("uniswap-v3-exact:WBTC/USDT"), Some("uniswap-v3-exact"));
+        assert_eq!(exchange_from_key("garbage"), None);
+    }
+
+    #[test]
+    fn summary_reports_rates_gaps_and_rejections() {
+        let mut stats = IngestStats::default();
+        let start = Utc::now() - Duration::seconds(30);
+        for i in 0..4 {
+            stats.record_accepted("binance", 10 + i, start + Duration::seconds(i as i64 * 2));
+        }
+        stats.record_rejected("binance");
+
+        let summary = &stats.summaries(start + Duration::seconds(30))[0];
+        assert_eq!(summary.updates_per_minute, 4.0);
+        assert_eq!(summary.median_gap_ms, Some(2000.0));
+        assert_eq!(summary.average_depth, 11.5);
+        assert_eq!(summary.last_update_age_ms, Some(24_000));
+        assert_eq!(summary.rejection_rate, 0.2);
+    }
+}
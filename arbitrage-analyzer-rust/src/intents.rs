@@ -0,0 +1,291 @@
+// Write-ahead log of execution requests, so a crash between handing a request to
+// the executor and hearing back about it does not lose track of the request.
+// Before a request is published, an intent (request id, opportunity id, route,
+// size and the request as published) is appended to INTENT_LOG_FILE and synced
+// to disk. When the request reaches a terminal state, from a result posted to
+// `POST /executions/<id>` or its TTL running out, a resolution is appended.
+//
+// At startup the log is replayed, and every intent without a resolution is
+//  - re-published unchanged, under the same id so executors can drop duplicates,
+//    if it is younger than EXECUTION_REQUEST_TTL_SECS, INTENT_REPUBLISH is on
+//    and execution requests may go out. It is tracked again, so its result is
+//    accepted and its TTL runs from when it was first created;
+//  - expired otherwise, as it would have been had the analyzer kept running.
+// The outcome only depends on the log and the time of the restart. Resolved
+// entries are dropped when the log is compacted: at startup, and whenever it
+// holds INTENT_COMPACT_LINES more lines than open intents. An empty
+// INTENT_LOG_FILE disables the log.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::history::ExecutionOutcome;
+use crate::lifecycle::{RequestState, RouteKey};
+use crate::metrics::Metrics;
+use crate::{config, contention, ArbitrageOpportunity, SpreadAnalyzer};
+
+pub const DEFAULT_INTENT_LOG_FILE: &str = "swapsleuth-intents.jsonl";
+const DEFAULT_COMPACT_LINES: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Intent {
+    pub id: String,
+    pub opportunity_id: String,
+    pub route: RouteKey,
+    pub size: f64,
+    pub created_at: DateTime<Utc>,
+    // The request exactly as it was published
+    pub request: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Entry {
+    Intent(Intent),
+    Resolved { id: String, state: RequestState, at: DateTime<Utc> },
+}
+
+/// What startup does with an intent the previous run left unresolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    Republish,
+    Expire,
+}
+
+/// Re-publish intents that could still be acted on, expire the rest
+pub fn recovery(intent: &Intent, now: DateTime<Utc>, ttl: Duration, may_publish: bool) -> Recovery {
+    if may_publish && now - intent.created_at <= ttl {
+        Recovery::Republish
+    } else {
+        Recovery::Expire
+    }
+}
+
+#[derive(Debug)]
+pub struct IntentLog {
+    // None disables the log
+    path: Option<PathBuf>,
+    republish: bool,
+    compact_lines: usize,
+    // Opened on the first append
+    file: Option<File>,
+    lines: usize,
+    // Keyed by request id, so replay and recovery run in the same order every time
+    open: BTreeMap<String, Intent>,
+}
+
+impl IntentLog {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        IntentLog { path, republish: true, compact_lines: DEFAULT_COMPACT_LINES, file: None, lines: 0, open: BTreeMap::new() }
+    }
+
+    pub fn from_env() -> Self {
+        let path = config::env_var("INTENT_LOG_FILE").unwrap_or_else(|_| DEFAULT_INTENT_LOG_FILE.to_string());
+        IntentLog {
+            republish: config::env_or("INTENT_REPUBLISH", true),
+            compact_lines: config::env_or("INTENT_COMPACT_LINES", DEFAULT_COMPACT_LINES),
+            ..IntentLog::new((!path.is_empty()).then(|| PathBuf::from(path)))
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn open_intents(&self) -> impl Iterator<Item = &Intent> {
+        self.open.values()
+    }
+
+    fn append(&mut self, entry: &Entry) -> Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        if self.file.is_none() {
+            self.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        }
+        if let Some(file) = &mut self.file {
+            writeln!(file, "{}", serde_json::to_string(entry)?)?;
+            file.sync_data()?;
+        }
+        self.lines += 1;
+        Ok(())
+    }
+
+    /// Persist `intent`; the request must not be published unless this succeeded
+    pub fn record(&mut self, intent: Intent) -> Result<()> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        self.append(&Entry::Intent(intent.clone()))?;
+        self.open.insert(intent.id.clone(), intent);
+        Ok(())
+    }
+
+    /// Mark the intent of request `id` done, if the log holds one
+    pub fn resolve(&mut self, id: &str, state: RequestState, at: DateTime<Utc>) {
+        if self.open.remove(id).is_none() {
+            return;
+        }
+        if let Err(e) = self.append(&Entry::Resolved { id: id.to_string(), state, at }) {
+            error!("Failed to log the resolution of request {}: {}", id, e);
+        }
+        if self.lines > self.open.len() + self.compact_lines {
+            self.compact();
+        }
+    }
+
+    /// Replay the log: the intents without a resolution, oldest first. A line that
+    /// doesn't parse, e.g. one torn by the crash, is skipped
+    pub fn load(&mut self) -> Vec<Intent> {
+        let Some(path) = &self.path else { return Vec::new() };
+        let Ok(raw) = fs::read_to_string(path) else { return Vec::new() };
+        self.open.clear();
+        self.lines = 0;
+        for (number, line) in raw.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            self.lines += 1;
+            match serde_json::from_str::<Entry>(line) {
+                Ok(Entry::Intent(intent)) => {
+                    self.open.insert(intent.id.clone(), intent);
+                }
+                Ok(Entry::Resolved { id, .. }) => {
+                    self.open.remove(&id);
+                }
+                Err(e) => warn!("Skipping line {} of intent log {}: {}", number + 1, path.display(), e),
+            }
+        }
+        let mut intents: Vec<Intent> = self.open.values().cloned().collect();
+        intents.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        intents
+    }
+
+    /// Rewrite the log with only the open intents
+    pub fn compact(&mut self) {
+        let Some(path) = &self.path else { return };
+        // Write then rename, like the checkpoint, so a crash can't leave half a log
+        let tmp = path.with_extension("tmp");
+        let write = || -> Result<()> {
+            let mut out = String::new();
+            for intent in self.open.values() {
+                out.push_str(&serde_json::to_string(&Entry::Intent(intent.clone()))?);
+                out.push('\n');
+            }
+            fs::write(&tmp, out)?;
+            File::open(&tmp)?.sync_all()?;
+            fs::rename(&tmp, path)?;
+            Ok(())
+        };
+        match write() {
+            Ok(()) => {
+                // Later appends go to the new file
+                self.file = None;
+                self.lines = self.open.len();
+            }
+            Err(e) => warn!("Failed to compact intent log {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Settle the execution requests the previous run published but never heard the end of
+    pub(crate) fn recover_intents(&mut self, now: DateTime<Utc>) {
+        let intents = self.intents.load();
+        if intents.is_empty() {
+            self.intents.compact();
+            return;
+        }
+        let may_publish = self.intents.republish && self.mode.emits_execution_requests() && !self.halted_by_kill_switch();
+        let ttl = self.lifecycle.ttl();
+        for intent in intents {
+            // The oldest intent on a route takes it; later ones expire, as they would never have been emitted
+            let republish = recovery(&intent, now, ttl, may_publish) == Recovery::Republish
+                && self.lifecycle.open(&intent.id, intent.route.clone(), intent.created_at).is_ok();
+            if republish {
+                if let Ok(opp) = serde_json::from_value::<ArbitrageOpportunity>(intent.request["opportunity"].clone()) {
+                    self.balances.reserve(&intent.id, &contention::needs(&opp), intent.size);
+                    self.lifecycle.set_notional_usd(&intent.id, self.notional_usd(&opp, intent.size));
+                }
+                Metrics::inc(&self.metrics.intents_republished);
+                info!("Re-publishing execution request {} on {} left unresolved by the previous run", intent.id, intent.route);
+                self.publish_to(&self.execution_channel, &intent.request);
+                if self.lifecycle.transition(&intent.id, RequestState::Published, now).is_ok() {
+                    self.record_transition(&intent.id, RequestState::Published, now, ExecutionOutcome::default());
+                }
+            } else {
+                Metrics::inc(&self.metrics.intents_expired);
+                info!("Expiring execution request {} on {} left unresolved by the previous run", intent.id, intent.route);
+                self.record_transition(&intent.id, RequestState::Expired, now, ExecutionOutcome::default());
+            }
+        }
+        self.intents.compact();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intent(id: &str, created_at: DateTime<Utc>) -> Intent {
+        Intent {
+            id: id.to_string(),
+            opportunity_id: format!("opp-{}", id),
+            route: RouteKey::new("BTC/USDT", "binance", "okx"),
+            size: 0.5,
+            created_at,
+            request: serde_json::json!({ "id": id }),
+        }
+    }
+
+    // `a` and `c` open, `b` filled, and a torn last line from a crash mid-write
+    fn write_log(path: &std::path::Path, start: DateTime<Utc>) {
+        let mut log = IntentLog::new(Some(path.to_path_buf()));
+        log.record(intent("a", start)).unwrap();
+        log.record(intent("b", start + Duration::seconds(1))).unwrap();
+        log.record(intent("c", start + Duration::seconds(2))).unwrap();
+        log.resolve("b", RequestState::Filled, start + Duration::seconds(3));
+        fs::write(path, fs::read_to_string(path).unwrap() + "{\"op\":\"resol").unwrap();
+    }
+
+    fn temp_log() -> PathBuf {
+        std::env::temp_dir().join(format!("swapsleuth-intents-{}.jsonl", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn unresolved_intents_survive_a_restart() {
+        let path = temp_log();
+        let start = Utc::now();
+        write_log(&path, start);
+        let open = IntentLog::new(Some(path.clone())).load();
+        assert_eq!(open.iter().map(|i| i.id.as_str()).collect::<Vec<_>>(), vec!["a", "c"]);
+        assert_eq!(open[0], intent("a", start));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compaction_keeps_only_open_intents() {
+        let path = temp_log();
+        write_log(&path, Utc::now());
+        let mut restarted = IntentLog::new(Some(path.clone()));
+        let open = restarted.load();
+        restarted.compact();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+        assert_eq!(IntentLog::new(Some(path.clone())).load(), open);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn young_intents_are_republished_and_the_rest_expire() {
+        let start = Utc::now();
+        let open = intent("a", start);
+        let ttl = Duration::seconds(30);
+        // Young intents go out again while execution may continue
+        assert_eq!(recovery(&open, start + Duration::seconds(10), ttl, true), Recovery::Republish);
+        assert_eq!(recovery(&open, start + Duration::seconds(10), ttl, false), Recovery::Expire);
+        assert_eq!(recovery(&open, start + Duration::seconds(31), ttl, true), Recovery::Expire);
+    }
+}
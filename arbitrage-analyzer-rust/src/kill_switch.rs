@@ -0,0 +1,228 @@
+// Break-glass kill switch. Once tripped (CLI, API or control channel) no further
+// execution requests are emitted; books are still ingested and analyzed, so the
+// analyzer carries on in observe-only mode. The state is written to
+// KILL_SWITCH_STATE_FILE on every change and read back on startup, so a restart
+// never silently re-arms execution. Resetting needs KILL_SWITCH_RESET_TOKEN;
+// without a configured token the switch can only be cleared by removing the file.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::Severity;
+use crate::events::{Event, EventClass};
+use crate::metrics::Metrics;
+use crate::SpreadAnalyzer;
+
+pub const DEFAULT_STATE_FILE: &str = "swapsleuth-kill-switch.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KillSwitchState {
+    pub tripped: bool,
+    pub reason: Option<String>,
+    // Who tripped it: CLI user, API caller, control channel sender
+    pub tripped_by: Option<String>,
+    pub tripped_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub struct KillSwitch {
+    state: KillSwitchState,
+    path: PathBuf,
+    reset_token: Option<String>,
+}
+
+// Compare without an early exit so the token can't be guessed byte by byte from timing
+fn tokens_match(expected: &str, given: &str) -> bool {
+    let (expected, given) = (expected.as_bytes(), given.as_bytes());
+    expected.len() == given.len() && expected.iter().zip(given).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+pub fn state_file_from_env() -> PathBuf {
+    PathBuf::from(crate::config::env_var("KILL_SWITCH_STATE_FILE").unwrap_or_else(|_| DEFAULT_STATE_FILE.to_string()))
+}
+
+fn load(path: &Path) -> KillSwitchState {
+    match fs::read_to_string(path) {
+        Ok(raw) => serde_json::from_str(&raw).unwrap_or_else(|e| {
+            // Fail safe: an unreadable state file must not re-enable execution
+            error!("Kill switch state {} is unreadable ({}); treating the switch as tripped", path.display(), e);
+            KillSwitchState {
+                tripped: true,
+                reason: Some(format!("unreadable state file: {}", e)),
+                tripped_by: None,
+                tripped_at: None,
+            }
+        }),
+        Err(_) => KillSwitchState::default(),
+    }
+}
+
+fn save(path: &Path, state: &KillSwitchState) -> Result<()> {
+    // Write then rename, so a crash mid-write can't leave a half-written file behind
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(state)?)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Trip the switch by writing the state file directly, for when no analyzer is running
+pub fn trip_offline(path: &Path, reason: &str, actor: &str) -> Result<()> {
+    save(
+        path,
+        &KillSwitchState {
+            tripped: true,
+            reason: Some(reason.to_string()),
+            tripped_by: Some(actor.to_string()),
+            tripped_at: Some(Utc::now()),
+        },
+    )
+}
+
+impl KillSwitch {
+    pub fn new(path: PathBuf, reset_token: Option<String>) -> Self {
+        KillSwitch { state: load(&path), path, reset_token: reset_token.filter(|t| !t.is_empty()) }
+    }
+
+    pub fn from_env() -> Self {
+        let switch = KillSwitch::new(state_file_from_env(), crate::config::env_var("KILL_SWITCH_RESET_TOKEN").ok());
+        if switch.is_tripped() {
+            warn!(
+                "  KILL SWITCH IS TRIPPED ({}); running observe-only, no execution requests will be emitted",
+                switch.state.reason.as_deref().unwrap_or("no reason given")
+            );
+        }
+        if switch.reset_token.is_none() {
+            info!("  KILL_SWITCH_RESET_TOKEN is not set; a tripped kill switch can only be cleared by removing {}", switch.path.display());
+        }
+        switch
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.state.tripped
+    }
+
+    pub fn state(&self) -> &KillSwitchState {
+        &self.state
+    }
+
+    /// Trip the switch. Tripping an already tripped switch keeps the original reason
+    pub fn trip(&mut self, reason: &str, actor: &str, now: DateTime<Utc>) -> Result<bool> {
+        if self.state.tripped {
+            return Ok(false);
+        }
+        self.state = KillSwitchState {
+            tripped: true,
+            reason: Some(reason.to_string()),
+            tripped_by: Some(actor.to_string()),
+            tripped_at: Some(now),
+        };
+        // The in-memory switch stays tripped even if it could not be persisted
+        save(&self.path, &self.state).map_err(|e| anyhow!("kill switch tripped but not persisted to {}: {}", self.path.display(), e))?;
+        Ok(true)
+    }
+
+    pub fn reset(&mut self, token: &str) -> Result<()> {
+        let Some(expected) = &self.reset_token else {
+            return Err(anyhow!("reset is disabled: KILL_SWITCH_RESET_TOKEN is not configured"));
+        };
+        if !tokens_match(expected, token) {
+            return Err(anyhow!("invalid reset token"));
+        }
+        let cleared = KillSwitchState::default();
+        save(&self.path, &cleared)?;
+        self.state = cleared;
+        Ok(())
+    }
+}
+
+impl SpreadAnalyzer {
+    pub fn trip_kill_switch(&mut self, reason: &str, actor: &str) -> Result<()> {
+        let tripped = self.kill_switch.trip(reason, actor, Utc::now());
+        self.metrics.kill_switch_tripped.store(1, std::sync::atomic::Ordering::Relaxed);
+        if matches!(tripped, Ok(true) | Err(_)) {
+            self.publish(Event::new(
+                EventClass::BreakerTripped,
+                Severity::Critical,
+                format!("Kill switch tripped by {}: {}; execution requests halted", actor, reason),
+            ));
+        }
+        tripped.map(|_| ())
+    }
+
+    pub fn reset_kill_switch(&mut self, token: &str, actor: &str) -> Result<()> {
+        if let Err(e) = self.kill_switch.reset(token) {
+            warn!("Rejected kill switch reset from {}: {}", actor, e);
+            return Err(e);
+        }
+        self.metrics.kill_switch_tripped.store(0, std::sync::atomic::Ordering::Relaxed);
+        self.publish(Event::new(
+            EventClass::BreakerReset,
+            Severity::Warning,
+            format!("Kill switch reset by {}; execution requests resume", actor),
+        ));
+        Ok(())
+    }
+
+    /// Count an opportunity that would have become an execution request
+    pub fn halted_by_kill_switch(&self) -> bool {
+        let halted = self.kill_switch.is_tripped();
+        if halted {
+            Metrics::inc(&self.metrics.execution_requests_halted);
+        }
+        halted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_path() -> PathBuf {
+        std::env::temp_dir().join(format!("swapsleuth-kill-switch-{}.json", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn tripped_state_survives_restart() {
+        let path = state_path();
+        let mut switch = KillSwitch::new(path.clone(), Some("s3cret".to_string()));
+        assert!(!switch.is_tripped());
+        assert!(switch.trip("bad fills", "cli:alice", Utc::now()).unwrap());
+        assert!(!switch.trip("again", "api", Utc::now()).unwrap());
+
+        let restarted = KillSwitch::new(path.clone(), None);
+        assert!(restarted.is_tripped());
+        assert_eq!(restarted.state().reason.as_deref(), Some("bad fills"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reset_requires_the_configured_token() {
+        let path = state_path();
+        let mut switch = KillSwitch::new(path.clone(), Some("s3cret".to_string()));
+        switch.trip("test", "api", Utc::now()).unwrap();
+
+        assert!(switch.reset("guess").is_err());
+        assert!(switch.is_tripped());
+        switch.reset("s3cret").unwrap();
+        assert!(!switch.is_tripped());
+        assert!(!KillSwitch::new(path.clone(), None).is_tripped());
+
+        let mut untokened = KillSwitch::new(path.clone(), None);
+        untokened.trip("test", "api", Utc::now()).unwrap();
+        assert!(untokened.reset("").is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unreadable_state_counts_as_tripped() {
+        let path = state_path();
+        fs::write(&path, "{ not json").unwrap();
+        assert!(KillSwitch::new(path.clone(), None).is_tripped());
+        fs::remove_file(path).unwrap();
+    }
+}
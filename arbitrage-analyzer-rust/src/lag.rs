@@ -0,0 +1,281 @@
+// Lead-lag detection between venues quoting the same pair, enabled with
+// LAGGARD_POLICY. Each venue's mid price is tracked per pair; a change of at
+// least LAG_MOVE_BPS from the last move is a move. When a venue moves in the
+// same direction another venue moved within LAG_WINDOW_MS, the gap is a lag
+// sample for that (leader, follower). A follower whose samples are consistent
+// (enough of them, rarely the other way round, and a typical lag of at least
+// LAG_MIN_MS) is a laggard of that leader.
+//
+// An opportunity with a laggard on one leg, whose leader has just moved in the
+// direction that opened the spread while the laggard has not followed yet, only
+// exists because the laggard's quote is stale. It is annotated as such; those
+// usually evaporate by the time orders arrive. `flag` trades them like any
+// other, `ignore` never turns them into execution requests.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+const DEFAULT_MOVE_BPS: f64 = 5.0;
+const DEFAULT_WINDOW_MS: i64 = 2_000;
+const DEFAULT_MIN_SAMPLES: u32 = 5;
+const DEFAULT_MIN_LAG_MS: f64 = 50.0;
+// Weight of the newest sample in the typical lag
+const LAG_EWMA_ALPHA: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaggardPolicy {
+    // No tracking, no annotations
+    Off,
+    // Annotate laggard opportunities and trade them as usual
+    Flag,
+    // Annotate them and emit no execution requests for them
+    Ignore,
+}
+
+impl FromStr for LaggardPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(LaggardPolicy::Off),
+            "flag" => Ok(LaggardPolicy::Flag),
+            "ignore" => Ok(LaggardPolicy::Ignore),
+            other => Err(anyhow!("unknown laggard policy: {} (expected off, flag or ignore)", other)),
+        }
+    }
+}
+
+impl fmt::Display for LaggardPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LaggardPolicy::Off => "off",
+            LaggardPolicy::Flag => "flag",
+            LaggardPolicy::Ignore => "ignore",
+        })
+    }
+}
+
+/// Attached to opportunities that only exist because one leg's quote is stale
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LaggardAnnotation {
+    pub laggard: String,
+    pub leader: String,
+    // How long the laggard usually takes to follow the leader
+    pub typical_lag_ms: f64,
+    // Since the leader's move that opened the spread
+    pub leader_moved_ms_ago: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LagStats {
+    pub samples: u32,
+    pub typical_lag_ms: f64,
+}
+
+#[derive(Debug)]
+struct Move {
+    at: DateTime<Utc>,
+    up: bool,
+    // Venues that already moved the same way after this one
+    followed_by: HashSet<String>,
+}
+
+#[derive(Debug)]
+struct VenueMid {
+    // Mid price at the last move
+    anchor: f64,
+    last_move: Option<Move>,
+}
+
+#[derive(Debug)]
+pub struct LagTracker {
+    pub policy: LaggardPolicy,
+    move_bps: f64,
+    window: Duration,
+    min_samples: u32,
+    min_lag_ms: f64,
+    // pair -> venue -> mid
+    mids: HashMap<String, HashMap<String, VenueMid>>,
+    // (pair, leader, follower) -> lag samples
+    stats: HashMap<(String, String, String), LagStats>,
+}
+
+impl LagTracker {
+    pub fn new(policy: LaggardPolicy, move_bps: f64, window: Duration, min_samples: u32, min_lag_ms: f64) -> Self {
+        LagTracker { policy, move_bps, window, min_samples, min_lag_ms, mids: HashMap::new(), stats: HashMap::new() }
+    }
+
+    pub fn from_env() -> Self {
+        LagTracker::new(
+            config::env_or("LAGGARD_POLICY", LaggardPolicy::Off),
+            config::env_or("LAG_MOVE_BPS", DEFAULT_MOVE_BPS),
+            Duration::milliseconds(config::env_or("LAG_WINDOW_MS", DEFAULT_WINDOW_MS)),
+            config::env_or("LAG_MIN_SAMPLES", DEFAULT_MIN_SAMPLES),
+            config::env_or("LAG_MIN_MS", DEFAULT_MIN_LAG_MS),
+        )
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.policy != LaggardPolicy::Off
+    }
+
+    /// Feed the mid price of a venue's book on a normalized pair
+    pub fn observe(&mut self, pair: &str, venue: &str, mid: f64, now: DateTime<Utc>) {
+        if !mid.is_finite() || mid <= 0.0 {
+            return;
+        }
+        let venues = self.mids.entry(pair.to_string()).or_default();
+        let Some(state) = venues.get(venue) else {
+            venues.insert(venue.to_string(), VenueMid { anchor: mid, last_move: None });
+            return;
+        };
+        let change_bps = (mid - state.anchor) / state.anchor * 10_000.0;
+        if change_bps.abs() < self.move_bps {
+            return;
+        }
+        let up = change_bps > 0.0;
+
+        // Every venue that made the same move shortly before leads this one
+        let mut samples = Vec::new();
+        for (leader, other) in venues.iter_mut().filter(|(name, _)| name.as_str() != venue) {
+            let Some(leading) = &mut other.last_move else { continue };
+            let lag = now - leading.at;
+            if leading.up == up && lag > Duration::zero() && lag <= self.window && leading.followed_by.insert(venue.to_string()) {
+                samples.push((leader.clone(), lag.num_milliseconds() as f64));
+            }
+        }
+        venues.insert(venue.to_string(), VenueMid { anchor: mid, last_move: Some(Move { at: now, up, followed_by: HashSet::new() }) });
+
+        for (leader, lag_ms) in samples {
+            let stats = self.stats.entry((pair.to_string(), leader, venue.to_string())).or_default();
+            stats.typical_lag_ms =
+                if stats.samples == 0 { lag_ms } else { LAG_EWMA_ALPHA * lag_ms + (1.0 - LAG_EWMA_ALPHA) * stats.typical_lag_ms };
+            stats.samples += 1;
+        }
+    }
+
+    /// Stats of `follower` trailing `leader`, if it does so consistently
+    fn laggard_stats(&self, pair: &str, leader: &str, follower: &str) -> Option<&LagStats> {
+        let key = |a: &str, b: &str| (pair.to_string(), a.to_string(), b.to_string());
+        let stats = self.stats.get(&key(leader, follower))?;
+        let reverse = self.stats.get(&key(follower, leader)).map_or(0, |s| s.samples);
+        (stats.samples >= self.min_samples && reverse * 2 <= stats.samples && stats.typical_lag_ms >= self.min_lag_ms).then_some(stats)
+    }
+
+    /// Annotation for an opportunity buying on `buy` and selling on `sell`, if
+    /// one leg is a laggard whose leader just opened the spread
+    pub fn annotate(&self, pair: &str, buy: &str, sell: &str, now: DateTime<Utc>) -> Option<LaggardAnnotation> {
+        let venues = self.mids.get(pair)?;
+        // A stale bid on the sell venue after the leader fell, or a stale ask on the buy venue after it rose
+        [(sell, buy, false), (buy, sell, true)].into_iter().find_map(|(laggard, leader, leader_up)| {
+            let stats = self.laggard_stats(pair, leader, laggard)?;
+            let moved = venues.get(leader)?.last_move.as_ref()?;
+            let since = now - moved.at;
+            let pending = moved.up == leader_up && since <= self.window && !moved.followed_by.contains(laggard);
+            pending.then(|| LaggardAnnotation {
+                laggard: laggard.to_string(),
+                leader: leader.to_string(),
+                typical_lag_ms: stats.typical_lag_ms,
+                leader_moved_ms_ago: since.num_milliseconds(),
+            })
+        })
+    }
+
+    /// Every measured (pair, leader, follower) with whether the follower counts as a laggard
+    pub fn report(&self) -> Vec<serde_json::Value> {
+        let mut keys: Vec<_> = self.stats.keys().collect();
+        keys.sort();
+        keys.into_iter()
+            .map(|key @ (pair, leader, follower)| {
+                serde_json::json!({
+                    "pair": pair,
+                    "leader": leader,
+                    "follower": follower,
+                    "stats": self.stats[key],
+                    "laggard": self.laggard_stats(pair, leader, follower).is_some(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> LagTracker {
+        LagTracker::new(LaggardPolicy::Flag, 5.0, Duration::milliseconds(2_000), 3, 50.0)
+    }
+
+    // Binance moves first, OKX follows `lag_ms` later, alternating up and down
+    fn feed(tracker: &mut LagTracker, start: DateTime<Utc>, moves: usize, lag_ms: i64) -> DateTime<Utc> {
+        let mut at = start;
+        for n in 0..moves {
+            let mid = if n % 2 == 0 { 100.2 } else { 100.0 };
+            tracker.observe("BTC/USDT", "binance", mid, at);
+            tracker.observe("BTC/USDT", "okx", mid, at + Duration::milliseconds(lag_ms));
+            at += Duration::seconds(5);
+        }
+        at
+    }
+
+    #[test]
+    fn flags_opportunities_on_a_stale_laggard_quote() {
+        let mut tracker = tracker();
+        let start = Utc::now();
+        tracker.observe("BTC/USDT", "binance", 100.0, start);
+        tracker.observe("BTC/USDT", "okx", 100.0, start);
+        let at = feed(&mut tracker, start + Duration::seconds(1), 4, 300);
+        let report = tracker.report();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0]["follower"], "okx");
+        assert_eq!(report[0]["laggard"], true);
+
+        // The last move was down: Binance rises, OKX's ask is still low, so buying OKX looks profitable
+        tracker.observe("BTC/USDT", "binance", 100.2, at);
+        let annotation = tracker.annotate("BTC/USDT", "okx", "binance", at + Duration::milliseconds(100)).unwrap();
+        assert_eq!((annotation.laggard.as_str(), annotation.leader.as_str()), ("okx", "binance"));
+        assert!((annotation.typical_lag_ms - 300.0).abs() < 1e-9);
+        assert_eq!(annotation.leader_moved_ms_ago, 100);
+        // The other direction is not explained by the lag
+        assert!(tracker.annotate("BTC/USDT", "binance", "okx", at + Duration::milliseconds(100)).is_none());
+
+        // Once OKX catches up the spread is real again
+        tracker.observe("BTC/USDT", "okx", 100.2, at + Duration::milliseconds(300));
+        assert!(tracker.annotate("BTC/USDT", "okx", "binance", at + Duration::milliseconds(400)).is_none());
+    }
+
+    #[test]
+    fn inconsistent_or_short_lags_are_not_laggards() {
+        let start = Utc::now();
+        // Too few samples
+        let mut few = tracker();
+        feed(&mut few, start, 2, 300);
+        assert!(few.laggard_stats("BTC/USDT", "binance", "okx").is_none());
+
+        // Below LAG_MIN_MS
+        let mut quick = tracker();
+        feed(&mut quick, start, 6, 20);
+        assert!(quick.laggard_stats("BTC/USDT", "binance", "okx").is_none());
+
+        // Leading as often as following
+        let mut mixed = tracker();
+        let at = feed(&mut mixed, start, 4, 300);
+        let mut at = at;
+        for n in 0..4 {
+            let mid = if n % 2 == 0 { 100.2 } else { 100.0 };
+            mixed.observe("BTC/USDT", "okx", mid, at);
+            mixed.observe("BTC/USDT", "binance", mid, at + Duration::milliseconds(300));
+            at += Duration::seconds(5);
+        }
+        assert!(mixed.laggard_stats("BTC/USDT", "binance", "okx").is_none());
+        assert!(mixed.laggard_stats("BTC/USDT", "okx", "binance").is_none());
+    }
+}
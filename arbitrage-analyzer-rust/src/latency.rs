@@ -0,0 +1,271 @@
+// Venue latency probes. With LATENCY_PROBE_VENUES set, a background thread times
+// a cheap request to every listed venue every LATENCY_PROBE_SECS:
+//  - binance / okx / bybit: their REST server-time endpoint (BINANCE_REST_URL,
+//    OKX_REST_URL, BYBIT_REST_URL),
+//  - DEX venues: the RPC node of their chain in CHAIN_RPC_URLS (`eth_blockNumber`,
+//    `getSlot`, Tendermint `/health`), once per chain.
+// A probe that fails counts as taking the whole probe timeout: a venue we cannot
+// reach is as slow as it gets.
+//
+// A venue's round trip is the median of its last LATENCY_SAMPLES probes; none
+// within three probe intervals means unknown. An execution request has to wait
+// for its slower leg, and the spread can close meanwhile, so every 100ms that
+// leg takes beyond LATENCY_BASELINE_MS adds LATENCY_ROI_PER_100MS percentage
+// points to the minimum ROI the opportunity needs. Below that bar the request is
+// suppressed like an infeasible route. Unknown latency adds nothing.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::feasibility::{DEFAULT_BINANCE_REST_URL, DEFAULT_BYBIT_REST_URL, DEFAULT_OKX_REST_URL};
+use crate::maintenance::{self, Chain};
+use crate::{config, numeric, ArbitrageOpportunity, SpreadAnalyzer};
+
+const DEFAULT_PROBE_SECS: u64 = 30;
+const DEFAULT_SAMPLES: usize = 10;
+const DEFAULT_BASELINE_MS: f64 = 250.0;
+const DEFAULT_ROI_PER_100MS: f64 = 0.02;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What timing a venue means
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Probe {
+    Get(String),
+    JsonRpc { url: String, method: &'static str },
+}
+
+/// The probe for `venue`, None when there is nothing to time it against
+pub fn probe_for(venue: &str, rest_url: impl Fn(&str, &str) -> String, rpc_urls: &HashMap<Chain, String>) -> Option<Probe> {
+    match venue {
+        "binance" => Some(Probe::Get(format!("{}/api/v3/ping", rest_url("BINANCE_REST_URL", DEFAULT_BINANCE_REST_URL)))),
+        "okx" => Some(Probe::Get(format!("{}/api/v5/public/time", rest_url("OKX_REST_URL", DEFAULT_OKX_REST_URL)))),
+        "bybit" => Some(Probe::Get(format!("{}/v5/market/time", rest_url("BYBIT_REST_URL", DEFAULT_BYBIT_REST_URL)))),
+        _ => {
+            let chain = Chain::of_venue(venue)?;
+            let url = rpc_urls.get(&chain)?.clone();
+            Some(match chain {
+                Chain::Ethereum => Probe::JsonRpc { url, method: "eth_blockNumber" },
+                Chain::Solana => Probe::JsonRpc { url, method: "getSlot" },
+                Chain::Osmosis => Probe::Get(format!("{}/health", url.trim_end_matches('/'))),
+            })
+        }
+    }
+}
+
+impl Probe {
+    // Round trip of one request, in ms
+    fn run(&self) -> Result<f64> {
+        let started = Instant::now();
+        match self {
+            Probe::Get(url) => ureq::get(url).timeout(PROBE_TIMEOUT).call().map_err(|e| anyhow!("{}: {}", url, e))?,
+            Probe::JsonRpc { url, method } => ureq::post(url)
+                .timeout(PROBE_TIMEOUT)
+                .send_json(json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": []}))
+                .map_err(|e| anyhow!("{}: {}", url, e))?,
+        };
+        Ok(started.elapsed().as_secs_f64() * 1000.0)
+    }
+}
+
+// Round trips in ms, oldest first
+type Samples = VecDeque<(f64, DateTime<Utc>)>;
+
+/// Recent round trips per venue, written by the prober
+#[derive(Debug)]
+pub struct LatencyBoard {
+    samples: usize,
+    max_age: chrono::Duration,
+    venues: Mutex<HashMap<String, Samples>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VenueLatency {
+    pub venue: String,
+    pub round_trip_ms: Option<f64>,
+    pub samples: usize,
+    pub last_probe_at: Option<DateTime<Utc>>,
+}
+
+impl LatencyBoard {
+    pub fn new(samples: usize, max_age: chrono::Duration) -> Self {
+        LatencyBoard { samples: samples.max(1), max_age, venues: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn record(&self, venue: &str, round_trip_ms: f64, at: DateTime<Utc>) {
+        let mut venues = self.venues.lock().unwrap_or_else(|e| e.into_inner());
+        let samples = venues.entry(venue.to_string()).or_default();
+        samples.push_back((round_trip_ms, at));
+        while samples.len() > self.samples {
+            samples.pop_front();
+        }
+    }
+
+    fn median(&self, samples: &Samples, now: DateTime<Utc>) -> Option<f64> {
+        let recent: Vec<f64> = samples.iter().filter(|(_, at)| now - *at <= self.max_age).map(|(ms, _)| *ms).collect();
+        numeric::percentile(&recent, 50.0)
+    }
+
+    /// Median round trip of `venue` over its recent probes; None when it has none
+    pub fn round_trip_ms(&self, venue: &str, now: DateTime<Utc>) -> Option<f64> {
+        let venues = self.venues.lock().unwrap_or_else(|e| e.into_inner());
+        self.median(venues.get(venue)?, now)
+    }
+
+    pub fn report(&self, now: DateTime<Utc>) -> Vec<VenueLatency> {
+        let venues = self.venues.lock().unwrap_or_else(|e| e.into_inner());
+        let mut report: Vec<VenueLatency> = venues
+            .iter()
+            .map(|(venue, samples)| VenueLatency {
+                venue: venue.clone(),
+                round_trip_ms: self.median(samples, now),
+                samples: samples.len(),
+                last_probe_at: samples.back().map(|(_, at)| *at),
+            })
+            .collect();
+        report.sort_by(|a, b| a.venue.cmp(&b.venue));
+        report
+    }
+}
+
+#[derive(Debug)]
+pub struct LatencyModel {
+    pub board: Arc<LatencyBoard>,
+    probes: Vec<(Probe, Vec<String>)>,
+    interval: Duration,
+    baseline_ms: f64,
+    roi_per_100ms: f64,
+}
+
+impl LatencyModel {
+    pub fn new(interval: Duration, samples: usize, baseline_ms: f64, roi_per_100ms: f64) -> Self {
+        // Samples go stale after three missed probes
+        let max_age = chrono::Duration::from_std(interval * 3).unwrap_or(chrono::Duration::MAX);
+        LatencyModel { board: Arc::new(LatencyBoard::new(samples, max_age)), probes: Vec::new(), interval, baseline_ms, roi_per_100ms }
+    }
+
+    pub fn from_env() -> Self {
+        let mut model = LatencyModel::new(
+            Duration::from_secs(config::env_or("LATENCY_PROBE_SECS", DEFAULT_PROBE_SECS)),
+            config::env_or("LATENCY_SAMPLES", DEFAULT_SAMPLES),
+            config::env_or("LATENCY_BASELINE_MS", DEFAULT_BASELINE_MS),
+            config::env_or("LATENCY_ROI_PER_100MS", DEFAULT_ROI_PER_100MS),
+        );
+        let rest_url = |name: &str, default: &str| config::env_var(name).unwrap_or_else(|_| default.to_string());
+        let rpc_urls: HashMap<Chain, String> = maintenance::chain_rpc_urls().into_iter().collect();
+        let venues = config::env_var("LATENCY_PROBE_VENUES").unwrap_or_default();
+        for venue in venues.split(',').map(|v| v.trim().to_lowercase()).filter(|v| !v.is_empty()) {
+            match probe_for(&venue, rest_url, &rpc_urls) {
+                // Venues on one chain share its RPC node, probed once for all of them
+                Some(probe) => match model.probes.iter_mut().find(|(p, _)| *p == probe) {
+                    Some((_, venues)) => venues.push(venue),
+                    None => model.probes.push((probe, vec![venue])),
+                },
+                None => warn!("Not probing {}: no REST endpoint, and no CHAIN_RPC_URLS entry for its chain", venue),
+            }
+        }
+        model
+    }
+
+    /// Minimum ROI percentage for a request whose slowest leg takes `round_trip_ms`,
+    /// `min_roi` being the bar at baseline latency
+    pub fn required_roi(&self, min_roi: f64, round_trip_ms: Option<f64>) -> f64 {
+        let excess_ms = round_trip_ms.map_or(0.0, |ms| (ms - self.baseline_ms).max(0.0));
+        min_roi + self.roi_per_100ms * excess_ms / 100.0
+    }
+}
+
+/// Probe every configured venue in the background, forever
+pub fn spawn(model: &LatencyModel) {
+    if model.probes.is_empty() {
+        return;
+    }
+    info!(
+        "  Probing venue latency every {}s: {}",
+        model.interval.as_secs(),
+        model.probes.iter().map(|(_, venues)| venues.join("/")).collect::<Vec<_>>().join(", ")
+    );
+    let (probes, board, interval) = (model.probes.clone(), model.board.clone(), model.interval);
+    thread::spawn(move || loop {
+        for (probe, venues) in &probes {
+            let round_trip_ms = probe.run().unwrap_or_else(|e| {
+                warn!("Latency probe for {} failed: {}", venues.join("/"), e);
+                PROBE_TIMEOUT.as_secs_f64() * 1000.0
+            });
+            for venue in venues {
+                board.record(venue, round_trip_ms, Utc::now());
+            }
+        }
+        thread::sleep(interval);
+    });
+}
+
+impl SpreadAnalyzer {
+    /// The slower of `opp`'s legs with its round trip, and the ROI an execution request on it needs
+    pub(crate) fn latency_bar<'a>(&self, opp: &'a ArbitrageOpportunity, now: DateTime<Utc>) -> (Option<(&'a str, f64)>, f64) {
+        let board = &self.latency.board;
+        let slowest = [opp.buy_exchange.as_str(), opp.sell_exchange.as_str()]
+            .into_iter()
+            .filter_map(|venue| {
+                let declared = || self.fees_config.venue_models.get(venue)?.latency_ms();
+                Some((venue, board.round_trip_ms(venue, now).or_else(declared)?))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        (slowest, self.latency.required_roi(self.thresholds.min_roi_percentage, slowest.map(|(_, ms)| ms)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MIN_ROI_PERCENTAGE;
+
+    fn latency_analyzer() -> (SpreadAnalyzer, ArbitrageOpportunity) {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.latency = LatencyModel::new(Duration::from_secs(30), 3, 250.0, 0.02);
+        let opp = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        (analyzer, opp)
+    }
+
+    #[test]
+    fn probes_each_venue_by_its_kind() {
+        let rpc_urls = HashMap::from([(Chain::Ethereum, "http://eth:8545".to_string())]);
+        let rest_url = |_: &str, default: &str| default.to_string();
+        assert_eq!(probe_for("binance", rest_url, &rpc_urls), Some(Probe::Get("https://api.binance.com/api/v3/ping".to_string())));
+        assert_eq!(probe_for("sushiswap", rest_url, &rpc_urls), Some(Probe::JsonRpc { url: "http://eth:8545".to_string(), method: "eth_blockNumber" }));
+        assert_eq!(probe_for("raydium", rest_url, &rpc_urls), None);
+    }
+
+    #[test]
+    fn unprobed_venues_keep_the_default_bar() {
+        let (analyzer, opp) = latency_analyzer();
+        assert_eq!(analyzer.latency_bar(&opp, Utc::now()), (None, MIN_ROI_PERCENTAGE));
+    }
+
+    #[test]
+    fn slow_venues_raise_the_profit_bar() {
+        let (analyzer, opp) = latency_analyzer();
+        let now = Utc::now();
+        // The median of the last 3 probes: one outlier doesn't count, stale ones are forgotten
+        let board = analyzer.latency.board.clone();
+        board.record("okx", 5_000.0, now - chrono::Duration::seconds(120));
+        for ms in [80.0, 1_250.0, 90.0, 70.0] {
+            board.record("binance", ms, now);
+        }
+        for ms in [750.0, 800.0, 5_000.0] {
+            board.record("okx", ms, now);
+        }
+        assert_eq!(board.round_trip_ms("binance", now), Some(90.0));
+        let (slowest, required) = analyzer.latency_bar(&opp, now);
+        assert_eq!(slowest, Some(("okx", 800.0)));
+        assert!((required - (MIN_ROI_PERCENTAGE + 0.02 * 5.5)).abs() < 1e-12);
+        assert_eq!(board.round_trip_ms("okx", now + chrono::Duration::seconds(91)), None);
+    }
+}
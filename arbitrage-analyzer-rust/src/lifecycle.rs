@@ -0,0 +1,249 @@
+// Lifecycle tracking for execution requests. Every request we hand to the
+// executor moves through these states; a route may only have one request
+// that has not reached a terminal state.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+// Terminal requests kept around for the API
+const HISTORY_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestState {
+    // Built by the analyzer, not yet handed to the executor
+    Pending,
+    Published,
+    Acknowledged,
+    Filled,
+    Failed,
+    // No terminal update arrived within the TTL
+    Expired,
+}
+
+impl RequestState {
+    pub fn is_terminal(self) -> bool {
+        matches!(self, RequestState::Filled | RequestState::Failed | RequestState::Expired)
+    }
+
+    fn can_transition_to(self, next: RequestState) -> bool {
+        use RequestState::*;
+        matches!(
+            (self, next),
+            (Pending, Published)
+                | (Published, Acknowledged)
+                | (Pending | Published | Acknowledged, Filled | Failed | Expired)
+        )
+    }
+}
+
+impl std::str::FromStr for RequestState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(RequestState::Pending),
+            "published" => Ok(RequestState::Published),
+            "acknowledged" => Ok(RequestState::Acknowledged),
+            "filled" => Ok(RequestState::Filled),
+            "failed" => Ok(RequestState::Failed),
+            "expired" => Ok(RequestState::Expired),
+            other => Err(anyhow!("unknown request state: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for RequestState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// A route is one direction of a spread: buy on one venue, sell on another
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct RouteKey {
+    pub pair: String,
+    pub buy_exchange: String,
+    pub sell_exchange: String,
+}
+
+impl RouteKey {
+    pub fn new(pair: &str, buy_exchange: &str, sell_exchange: &str) -> Self {
+        RouteKey {
+            pair: pair.to_string(),
+            buy_exchange: buy_exchange.to_string(),
+            sell_exchange: sell_exchange.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RouteKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}→{}", self.pair, self.buy_exchange, self.sell_exchange)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackedRequest {
+    pub id: String,
+    pub route: RouteKey,
+    pub state: RequestState,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    // Capital at risk, when it has a USD price; see `correlation`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notional_usd: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct LifecycleTracker {
+    ttl: Duration,
+    active: HashMap<String, TrackedRequest>,
+    in_flight_by_route: HashMap<RouteKey, String>,
+    history: VecDeque<TrackedRequest>,
+}
+
+impl LifecycleTracker {
+    pub fn new(ttl: Duration) -> Self {
+        LifecycleTracker {
+            ttl,
+            active: HashMap::new(),
+            in_flight_by_route: HashMap::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Start tracking a new request. Fails with the id of the request already in flight on the same route.
+    pub fn open(&mut self, id: &str, route: RouteKey, now: DateTime<Utc>) -> Result<(), String> {
+        if let Some(existing) = self.in_flight_by_route.get(&route) {
+            return Err(existing.clone());
+        }
+
+        self.in_flight_by_route.insert(route.clone(), id.to_string());
+        self.active.insert(
+            id.to_string(),
+            TrackedRequest { id: id.to_string(), route, state: RequestState::Pending, created_at: now, updated_at: now, notional_usd: None },
+        );
+        Ok(())
+    }
+
+    /// Stop tracking a request that never went out, leaving no history
+    pub fn discard(&mut self, id: &str) {
+        if let Some(request) = self.active.remove(id) {
+            self.in_flight_by_route.remove(&request.route);
+        }
+    }
+
+    pub fn set_notional_usd(&mut self, id: &str, usd: Option<f64>) {
+        if let Some(request) = self.active.get_mut(id) {
+            request.notional_usd = usd;
+        }
+    }
+
+    pub fn transition(&mut self, id: &str, next: RequestState, now: DateTime<Utc>) -> Result<()> {
+        let request = self.active.get_mut(id).ok_or_else(|| anyhow!("no in-flight request with id {}", id))?;
+        if !request.state.can_transition_to(next) {
+            return Err(anyhow!("invalid transition for {}: {} -> {}", id, request.state, next));
+        }
+
+        request.state = next;
+        request.updated_at = now;
+
+        if next.is_terminal() {
+            if let Some(done) = self.active.remove(id) {
+                self.in_flight_by_route.remove(&done.route);
+                self.history.push_back(done);
+                while self.history.len() > HISTORY_LIMIT {
+                    self.history.pop_front();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Expire requests that have been in flight longer than the TTL, returning their ids
+    pub fn expire_stale(&mut self, now: DateTime<Utc>) -> Vec<String> {
+        let stale: Vec<String> = self
+            .active
+            .values()
+            .filter(|r| now - r.created_at > self.ttl)
+            .map(|r| r.id.clone())
+            .collect();
+
+        for id in &stale {
+            // Every non-terminal state can expire
+            let _ = self.transition(id, RequestState::Expired, now);
+        }
+        stale
+    }
+
+    pub fn in_flight(&self) -> Vec<&TrackedRequest> {
+        let mut requests: Vec<&TrackedRequest> = self.active.values().collect();
+        requests.sort_by_key(|r| r.created_at);
+        requests
+    }
+
+    /// A request that has not reached a terminal state
+    pub fn get(&self, id: &str) -> Option<&TrackedRequest> {
+        self.active.get(id)
+    }
+
+    pub fn recent(&self) -> impl Iterator<Item = &TrackedRequest> {
+        self.history.iter().rev()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route() -> RouteKey {
+        RouteKey::new("BTC/USDT", "binance", "uniswap-v3-exact")
+    }
+
+    #[test]
+    fn one_in_flight_request_per_route() {
+        let mut tracker = LifecycleTracker::new(Duration::seconds(30));
+        let now = Utc::now();
+
+        assert!(tracker.open("a", route(), now).is_ok());
+        assert_eq!(tracker.open("b", route(), now), Err("a".to_string()));
+        // The reverse direction is a different route
+        assert!(tracker.open("c", RouteKey::new("BTC/USDT", "uniswap-v3-exact", "binance"), now).is_ok());
+
+        tracker.transition("a", RequestState::Published, now).unwrap();
+        tracker.transition("a", RequestState::Filled, now).unwrap();
+        assert!(tracker.open("b", route(), now).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_transitions() {
+        let mut tracker = LifecycleTracker::new(Duration::seconds(30));
+        let now = Utc::now();
+        tracker.open("a", route(), now).unwrap();
+
+        assert!(tracker.transition("a", RequestState::Acknowledged, now).is_err());
+        tracker.transition("a", RequestState::Failed, now).unwrap();
+        assert!(tracker.transition("a", RequestState::Filled, now).is_err());
+    }
+
+    #[test]
+    fn stale_requests_expire_and_free_the_route() {
+        let mut tracker = LifecycleTracker::new(Duration::seconds(30));
+        let start = Utc::now();
+        tracker.open("a", route(), start).unwrap();
+
+        assert!(tracker.expire_stale(start + Duration::seconds(10)).is_empty());
+        assert_eq!(tracker.expire_stale(start + Duration::seconds(31)), vec!["a".to_string()]);
+        assert_eq!(tracker.recent().next().map(|r| r.state), Some(RequestState::Expired));
+        assert!(tracker.open("b", route(), start + Duration::seconds(31)).is_ok());
+    }
+}
@@ -1,17 +1,310 @@
-use redis::{Client, Commands, ConnectionInfo, ConnectionAddr, RedisConnectionInfo};
+mod admin;
+mod allocation;
+mod anomaly;
+mod alert_routing;
+mod alerts;
+mod attribution;
+mod binance_ws;
+mod api;
+mod archive;
+mod acks;
+mod atomic;
+mod backtest;
+mod backoff;
+mod book_archive;
+mod book_cache;
+mod break_even;
+mod buildinfo;
+mod canary;
+mod capital;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod checkpoint;
+mod cluster;
+mod codec;
+mod competition;
+mod config;
+mod config_file;
+mod contention;
+mod delta;
+mod depth;
+mod doctor;
+mod control;
+mod correlation;
+mod dump;
+mod email;
+mod enrichment;
+mod events;
+mod executor_schema;
+mod experiment;
+mod expiry;
+mod export;
+mod feasibility;
+mod gas;
+mod gas_oracle;
+mod gas_regime;
+mod grpc;
+mod history;
+mod holding;
+mod idle;
+mod ingest_stats;
+mod intents;
+mod kill_switch;
+mod lag;
+mod latency;
+mod lifecycle;
+mod maintenance;
+mod market_history;
+mod metrics;
+#[cfg(test)]
+mod mini_redis;
+mod mode;
+mod netting;
+mod notional;
+mod numeric;
+mod onboarding;
+mod overrides;
+mod pipeline;
+mod precision;
+mod plugins;
+mod pretrade;
+mod priority;
+mod profiles;
+mod profit_rollup;
+mod pruning;
+mod publisher;
+mod report;
+#[cfg(test)]
+mod replay;
+mod retention;
+mod route_locks;
+mod route_yield;
+mod seasonality;
+mod shedding;
+mod shadow;
+mod shutdown;
+mod shutdown_report;
+mod slicing;
+mod snapshot;
+mod solana;
+mod stage_timing;
+mod staleness;
+mod sources;
+mod subscription;
+mod stream_replay;
+mod streams;
+mod success_rate;
+mod sweep;
+mod template;
+mod throttle;
+mod timing;
+mod triangular;
+mod venue_costs;
+mod venues;
+mod watchdog;
+mod withdrawal_fees;
+
 use dotenvy::dotenv;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use anyhow::{Result, anyhow};
 use log::{info, warn, error, debug};
 use env_logger::Env;
+use clap::{Parser, Subcommand};
+
+use alerts::Severity;
+use allocation::{AllocationConfig, AllocationPlan};
+use events::{Event, EventBus, EventClass};
+use api::ApiRequest;
+use expiry::{LiveOpportunities, OpportunityExpiry};
+use export::ParquetExporter;
+use feasibility::StatusCache;
+use history::{ExecutionOutcome, HistoryRecord, HistoryStore};
+use ingest_stats::IngestStats;
+use book_cache::BookBudget;
+use break_even::{BreakEvenReport, SpreadHistory};
+use capital::{CapitalAtRisk, CapitalConfig};
+use holding::HoldingCosts;
+use attribution::{CostAttribution, CostEstimate};
+use competition::{CompetitionEstimate, CompetitionTracker};
+use control::ControlCommand;
+use kill_switch::KillSwitch;
+use lag::{LagTracker, LaggardAnnotation, LaggardPolicy};
+use lifecycle::{LifecycleTracker, RequestState, RouteKey};
+use maintenance::MaintenanceBoard;
+use latency::LatencyModel;
+use metrics::Metrics;
+use mode::Mode;
+use pipeline::{BookQueue, IngestEvent, Ingestor, OverflowPolicy, Pop};
+use priority::PairPriorities;
+use profiles::{AccountProfile, StrategyTag};
+use publisher::Publisher;
+use route_yield::YieldTracker;
+use checkpoint::{AnalysisCounters, Checkpoint};
+use cluster::ClusterAnnotation;
+use archive::OpportunityArchive;
+use report::{OutputFormat, Reporter};
+use buildinfo::BuildInfo;
+use contention::BalanceLedger;
+use gas::{GasModel, LegGas};
+use netting::{NettedBatch, Netting};
+use notional::NotionalConverter;
+use shadow::ShadowFees;
+use snapshot::StateSnapshotter;
+use shedding::LoadShedder;
+use stage_timing::Stage;
+use solana::{SlotClock, SolanaFees, TokenMap};
+use sources::RedisSource;
+use throttle::LogThrottle;
+use timing::{TimingAdvice, TimingAdvisor};
+use venue_costs::VenueCostModels;
+use streams::{EntryType, PublishCooldown, StreamConfig};
+use config_file::{ConfigFile, ExchangeFees};
+use watchdog::VenueWatchdog;
 
 
 // Rust analyzer config constants
 const MIN_ABSOLUTE_PROFIT: f64 = 1.0; // Minimum absolute profit in USDT
 const MIN_ROI_PERCENTAGE: f64 = 0.1; // Minimum ROI percentage
+const STALE_BOOK_AGE_MS: i64 = 30_000; // Same as the 30s TTL the go collector sets on book keys
+
+const DEFAULT_API_ADDR: &str = "127.0.0.1:9898";
+// In-flight execution requests that get no terminal update within this window are expired
+const DEFAULT_EXECUTION_REQUEST_TTL_SECS: i64 = 30;
+// How often per-route break-even spreads are recomputed
+const DEFAULT_BREAK_EVEN_REFRESH_SECS: u64 = 60;
+// A venue with no book update for this long is marked suspect
+const DEFAULT_VENUE_MAX_SILENCE_SECS: i64 = 60;
+// Repeated warnings for the same key are folded into one line per interval
+const DEFAULT_LOG_THROTTLE_SECS: u64 = 30;
+// Every this many applied books, all pairs are re-analyzed rather than just the updated one
+const COMPREHENSIVE_ANALYSIS_INTERVAL: u64 = 10;
+// How long the update loop blocks before servicing API requests
+const PUBSUB_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Parser, Debug)]
+#[command(name = "swapsleuth", about = "Cross-exchange arbitrage spread analyzer")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// What the analyzer prints to stdout: the human report, or one JSON object per line
+    #[arg(long, value_enum, default_value = "human")]
+    output: OutputFormat,
+    /// TOML file with fees, thresholds and the exchange registry; defaults to SWAPSLEUTH_CONFIG
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Backtest against recorded books (JSON lines, optionally gzipped) instead of Redis; defaults to REPLAY_FILE
+    #[arg(long)]
+    replay: Option<PathBuf>,
+    /// Replay speed against the recorded time, 0 for as fast as possible; defaults to REPLAY_SPEED or 0
+    #[arg(long)]
+    replay_speed: Option<f64>,
+    /// Print where each update's time goes, stage by stage, every PROFILE_PIPELINE_SECS and at shutdown
+    #[arg(long)]
+    profile_pipeline: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the analyzer (default when no command is given)
+    Run,
+    /// Dump the in-memory books of a running analyzer to a JSON file
+    DumpBooks {
+        /// Output file; defaults to books-dump-<timestamp>.json
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// Analyzer API address; defaults to API_ADDR or 127.0.0.1:9898
+        #[arg(long)]
+        api: Option<String>,
+    },
+    /// Export the hour-of-day / weekday opportunity seasonality report of a running analyzer
+    Seasonality {
+        /// `json` or `csv`
+        #[arg(long, default_value = "json")]
+        format: String,
+        /// Output file; defaults to stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// Only this normalized pair, e.g. BTC/USDT
+        #[arg(long)]
+        pair: Option<String>,
+        /// Start of the window (RFC 3339); defaults to the last 30 days
+        #[arg(long)]
+        from: Option<String>,
+        /// Analyzer API address; defaults to API_ADDR or 127.0.0.1:9898
+        #[arg(long)]
+        api: Option<String>,
+    },
+    /// Inspect, trip or reset the execution kill switch of a running analyzer
+    KillSwitch {
+        #[command(subcommand)]
+        action: KillSwitchAction,
+        /// Analyzer API address; defaults to API_ADDR or 127.0.0.1:9898
+        #[arg(long, global = true)]
+        api: Option<String>,
+    },
+    /// Check Redis, book keys and payloads, clocks and configuration before running
+    Doctor,
+    /// Re-run a range of the orderbook stream in observe mode, at the recorded times
+    Replay {
+        /// First stream id, e.g. 1704067200000-0 or 1704067200000; `-` for the oldest entry
+        #[arg(long)]
+        from: String,
+        /// Last stream id; `+` for the newest entry
+        #[arg(long)]
+        to: String,
+        /// Stream the collectors append books to; defaults to ORDERBOOK_STREAM
+        #[arg(long)]
+        stream: Option<String>,
+    },
+    /// Open the admin console of a running analyzer, or run one console command
+    Admin {
+        /// Console socket; defaults to ADMIN_SOCKET
+        #[arg(long)]
+        socket: Option<PathBuf>,
+        /// e.g. `books BTC/USDT`; reads commands from stdin when empty
+        command: Vec<String>,
+    },
+    /// Backtest recorded books over a grid of parameters, record every run and print the Pareto-best ones
+    Experiment {
+        /// Recorded books, as for `--replay`; defaults to REPLAY_FILE
+        #[arg(long)]
+        replay: Option<PathBuf>,
+        /// TOML file with the `[grid]` of parameter values to try
+        #[arg(long)]
+        grid: PathBuf,
+        /// Results table the runs are appended to: `.csv`, or `.sqlite` / `.db` with `--features sqlite`;
+        /// defaults to EXPERIMENT_RESULTS or swapsleuth-experiments.csv
+        #[arg(long)]
+        results: Option<PathBuf>,
+        /// Experiment name recorded with each run; defaults to the grid's `name`, then its file name
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum KillSwitchAction {
+    /// Show whether the kill switch is tripped
+    Status,
+    /// Halt all execution requests. Writes the state file directly if no analyzer answers
+    Trip {
+        #[arg(long, default_value = "tripped via CLI")]
+        reason: String,
+    },
+    /// Re-enable execution requests; needs the KILL_SWITCH_RESET_TOKEN of the analyzer
+    Reset {
+        /// Defaults to KILL_SWITCH_RESET_TOKEN
+        #[arg(long)]
+        token: Option<String>,
+    },
+}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct OrderBook {
@@ -25,6 +318,153 @@ struct OrderBook {
     asks: Vec<Vec<f64>>, // [[price, size], [price,size]] matching our go codebase
     #[serde(rename = "timestamp")]
     timestamp: i64,
+    // Slot the book was read at; only Solana collectors set it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    slot: Option<u64>,
+    // Local receive time, stamped at ingest. Not part of the wire format
+    #[serde(skip)]
+    received_at: Option<DateTime<Utc>>,
+    // Name of the Redis source the book was read from
+    #[serde(skip)]
+    source: Option<String>,
+    // Highest anomaly score its scorers gave it at ingest, see anomaly.rs
+    #[serde(skip)]
+    anomaly_score: Option<f64>,
+}
+
+impl OrderBook {
+    fn age_ms(&self, now: DateTime<Utc>) -> Option<i64> {
+        self.received_at.map(|received| (now - received).num_milliseconds())
+    }
+
+    fn has_non_finite_values(&self) -> bool {
+        self.bids.iter().chain(self.asks.iter()).any(|level| !numeric::all_finite(level))
+    }
+
+    // Price and size of the level at `idx`, if it is well formed
+    fn level(levels: &[Vec<f64>], idx: usize) -> Option<(f64, f64)> {
+        let level = levels.get(idx)?;
+        Some((*level.first()?, *level.get(1)?))
+    }
+
+    fn best_bid(&self) -> Option<(f64, f64)> {
+        Self::level(&self.bids, 0)
+    }
+
+    fn best_ask(&self) -> Option<(f64, f64)> {
+        Self::level(&self.asks, 0)
+    }
+
+    // Quote notional resting on `levels` within `bps` of `best`; bids pass `sign` -1, asks +1
+    fn notional_within(levels: &[Vec<f64>], best: f64, bps: f64, sign: f64) -> f64 {
+        let limit = best * (1.0 + sign * bps / 10_000.0);
+        (0..levels.len())
+            .filter_map(|idx| Self::level(levels, idx))
+            .filter(|(price, size)| *size > 0.0 && (price - limit) * sign <= 0.0)
+            .map(|(price, size)| price * size)
+            .sum()
+    }
+
+    /// Notional a sell could fill within `bps` below the best bid
+    fn bid_depth_within(&self, bps: f64) -> f64 {
+        self.best_bid().map_or(0.0, |(best, _)| Self::notional_within(&self.bids, best, bps, -1.0))
+    }
+
+    /// Notional a buy could fill within `bps` above the best ask
+    fn ask_depth_within(&self, bps: f64) -> f64 {
+        self.best_ask().map_or(0.0, |(best, _)| Self::notional_within(&self.asks, best, bps, 1.0))
+    }
+
+    // Best bid at or above best ask. No venue publishes that, so the book is corrupt
+    fn is_crossed(&self) -> bool {
+        matches!((self.best_bid(), self.best_ask()), (Some((bid, _)), Some((ask, _))) if bid >= ask)
+    }
+
+    // Sanity checks on the book contents. An empty list means the book is usable
+    fn validation_issues(&self) -> Vec<String> {
+        let mut issues: Vec<String> = Vec::new();
+
+        if self.has_non_finite_values() {
+            issues.push("contains NaN or infinite values".to_string());
+        }
+
+        if self.bids.is_empty() {
+            issues.push("no bids".to_string());
+        }
+        if self.asks.is_empty() {
+            issues.push("no asks".to_string());
+        }
+
+        for (side, levels) in [("bid", &self.bids), ("ask", &self.asks)] {
+            for (idx, level) in levels.iter().enumerate() {
+                if level.len() < 2 {
+                    issues.push(format!("{} level {} is malformed", side, idx));
+                } else if level[0] <= 0.0 || level[1] <= 0.0 {
+                    issues.push(format!("{} level {} has non-positive price or size", side, idx));
+                }
+            }
+        }
+
+        if let (Some(bid), Some(ask)) = (self.bids.first(), self.asks.first()) {
+            if let (Some(bid_price), Some(ask_price)) = (bid.first(), ask.first()) {
+                if bid_price >= ask_price {
+                    issues.push(format!("crossed book: best bid {} >= best ask {}", bid_price, ask_price));
+                }
+            }
+        }
+
+        issues
+    }
+}
+
+#[cfg(test)]
+impl SpreadAnalyzer {
+    // Top of book only: one level a side, already adjusted for wrapped tokens
+    #[allow(clippy::too_many_arguments)]
+    fn evaluate_opportunity(
+        &self,
+        buy_exchange: &str,
+        sell_exchange: &str,
+        pair: &str,
+        buy_price: f64,
+        sell_price: f64,
+        buy_size: f64,
+        sell_size: f64,
+    ) -> Option<ArbitrageOpportunity> {
+        self.evaluate_opportunity_depth(buy_exchange, sell_exchange, pair, &[vec![buy_price, buy_size]], &[vec![sell_price, sell_size]], 1.0)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn price_route(
+        &self,
+        fees: &FeesConfig,
+        buy_exchange: &str,
+        sell_exchange: &str,
+        pair: &str,
+        buy_price: f64,
+        sell_price: f64,
+        buy_size: f64,
+        sell_size: f64,
+    ) -> Option<ArbitrageOpportunity> {
+        self.price_route_depth(fees, buy_exchange, sell_exchange, pair, &[vec![buy_price, buy_size]], &[vec![sell_price, sell_size]], 1.0)
+    }
+}
+
+#[cfg(test)]
+impl OrderBook {
+    fn for_test(exchange: &str, pair: &str, bids: Vec<Vec<f64>>, asks: Vec<Vec<f64>>) -> Self {
+        OrderBook {
+            exchange: exchange.to_string(),
+            pair: pair.to_string(),
+            bids,
+            asks,
+            timestamp: 0,
+            slot: None,
+            received_at: Some(Utc::now()),
+            source: None,
+            anomaly_score: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -36,27 +476,225 @@ struct ArbitrageOpportunity {
     buy_price: f64,
     sell_price: f64,
     max_size: f64,
+    // Base quantity left for the sell leg after in-kind fees and transfer costs
+    sell_size: f64,
     gross_profit_per_unit: f64,
     estimated_fees: f64,
     net_profit: f64,
+    // Net profit over the capital at risk
     roi_percentage: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    capital_at_risk: Option<CapitalAtRisk>,
+    // `roi_percentage` earned once per capital lockup, over a year
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    annualized_roi_percentage: Option<f64>,
     timestamp: DateTime<Utc>,
+    // How contested the route looks; lets the executor favour routes we can realistically win
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    competition: Option<CompetitionEstimate>,
+    // Set when the spread only exists because one leg's quote trails the other venue (LAGGARD_POLICY)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    laggard: Option<LaggardAnnotation>,
+    // Set when other pairs show the same dislocation on the same route; only the representative is published
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cluster: Option<ClusterAnnotation>,
+    // Set when `max_size` sweeps past the top level of either book; `buy_price` and
+    // `sell_price` are then the VWAPs it fills at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    depth: Option<sweep::DepthFill>,
+    // How old each leg's book was when the spread was found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    book_ages: Option<staleness::BookAges>,
+    // The higher anomaly score of the two legs' books, when scorers are configured (ANOMALY_SCORERS)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    anomaly_score: Option<f64>,
+    // Smaller sizes the executor may take instead of `max_size`, smallest first (SIZE_LADDER_STEPS)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    size_ladder: Vec<sweep::LadderPoint>,
+    // How the gas price of its DEX legs' chains compares with their recent history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gas_regime: Option<gas_regime::GasRegime>,
+    // How often requests on its route have filled at their expected profit so far
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    execution_success: Option<success_rate::SuccessEstimate>,
+    // Set by enrichers of your own (`plugins::enrichers`), by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    annotations: BTreeMap<String, serde_json::Value>,
+    // `tenant` / `strategy_id` of the deployment that found it
+    #[serde(flatten)]
+    tag: StrategyTag,
 }
 
 
 #[derive(Debug, Clone, Serialize)]
 struct ExecutionRequest {
     id: String,
+    // Layout of the request, see `executor_schema`
+    schema_version: u32,
     opportunity: ArbitrageOpportunity,
     execution_size: f64,
     created_at: DateTime<Utc>,
+    // Account profile the route was priced under; the executor must trade from the same accounts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account_profile: Option<String>,
+    // Whether to cross the spread or post, from how long the route's spreads last vs our fill latency
+    timing: TimingAdvice,
+    // Priority-fee strategy, initial bid and max fee of each leg on a chain with a GAS_STRATEGIES entry
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    gas: Vec<LegGas>,
+    // Set when the request nets several small opportunities on its route (NETTING_WINDOW_MS)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    netting: Option<NettedBatch>,
+    // Repeated from the opportunity, so a shared executor can route orders without unpacking it
+    #[serde(flatten)]
+    tag: StrategyTag,
+    // The pre-trade checks it passed, in the order they ran
+    pre_trade_checks: Vec<&'static str>,
+    // Both swaps in one transaction, on routes between two Ethereum DEXes (ATOMIC_EXECUTOR_ADDRESS)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    atomic: Option<atomic::AtomicPlan>,
+    // Child orders to work it in, when it is too large for one clip (SLICE_PLANNER)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slices: Option<slicing::SlicePlan>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 struct SpreadAnalyzer {
     books: HashMap<String, OrderBook>,
-    redis_client: Client,
+    book_budget: BookBudget,
+    sources: Vec<RedisSource>,
     fees_config: FeesConfig,
+    // GAS_STRATEGIES; refreshes the DEX leg costs in `fees_config`
+    gas: GasModel,
+    // Candidate fee model evaluated alongside `fees_config` without acting on it
+    shadow_fees: Option<ShadowFees>,
+    // From the account profile or TENANT / STRATEGY_ID; stamped on everything published
+    strategy_tag: StrategyTag,
+    sizing_config: SizingConfig,
+    capital_config: CapitalConfig,
+    api_requests: Option<Receiver<ApiRequest>>,
+    // HEALTHZ_MAX_BOOK_AGE_MS; `/healthz` fails once the newest book is older
+    healthz_max_book_age_ms: i64,
+    control_commands: Option<Receiver<ControlCommand>>,
+    // GRPC_ADDR, with `--features grpc`
+    grpc: Option<grpc::GrpcServer>,
+    // ADMIN_SOCKET, or the socket systemd passed in
+    admin: Option<admin::AdminConsole>,
+    // `--profile-pipeline`: how often the stage table is printed, and when it last was
+    pipeline_profile: Option<(Duration, Instant)>,
+    // Set by `pause` / `set_thresholds` control commands, see `control.rs`
+    paused: Option<control::Pause>,
+    thresholds: control::Thresholds,
+    log_throttle: Arc<LogThrottle>,
+    metrics: Arc<Metrics>,
+    // Labels every metric; retaken once configuration is complete
+    build_info: BuildInfo,
+    lifecycle: LifecycleTracker,
+    spread_history: SpreadHistory,
+    competition: CompetitionTracker,
+    timing: TimingAdvisor,
+    lag: LagTracker,
+    break_even_reports: Vec<BreakEvenReport>,
+    break_even_refresh: Duration,
+    last_break_even_refresh: Instant,
+    events: EventBus,
+    mode: Mode,
+    // Set in `run()`; without it nothing is written to Redis (tests)
+    publisher: Option<Publisher>,
+    // Runs the Redis I/O: source listeners, book fetches, the publisher and the signal watcher
+    io_runtime: Option<tokio::runtime::Runtime>,
+    opportunity_channel: String,
+    execution_channel: String,
+    // Redis Streams copies of the channels, and what keeps repeats off both
+    streams: StreamConfig,
+    publish_cooldown: PublishCooldown,
+    kill_switch: KillSwitch,
+    watchdog: VenueWatchdog,
+    ingest_stats: IngestStats,
+    queue_capacity: usize,
+    overflow_policy: OverflowPolicy,
+    history: HistoryStore,
+    exporter: ParquetExporter,
+    solana_tokens: TokenMap,
+    slot_clock: SlotClock,
+    // Refreshed by the venue status poller started in `run()`
+    venue_status: Arc<StatusCache>,
+    // Fetched withdrawal fee tables, applied to `fees_config` in housekeeping
+    withdrawal_fee_refresh: withdrawal_fees::FeeRefresh,
+    // EXECUTOR_SCHEMA_KEY: what the executor reads, and how requests are shaped for it
+    executor_schema: executor_schema::SchemaHandshake,
+    // ROUTE_LOCK_TTL_MS: routes reserved in Redis against other deployments
+    route_locks: route_locks::RouteLocks,
+    // Unvetted venues, analyzed but not executed on
+    canary: canary::CanaryVenues,
+    // Pairs seen for the first time, held to stricter thresholds until confirmed or blocked
+    onboarding: onboarding::PairOnboarding,
+    // For the shutdown report
+    started_at: DateTime<Utc>,
+    // Written by the maintenance poller; `quarantined` is the view analysis uses, synced in housekeeping
+    maintenance: Arc<MaintenanceBoard>,
+    // Venue round trips from LATENCY_PROBE_VENUES; raise the profit bar of slow routes
+    latency: LatencyModel,
+    quarantined: HashMap<String, String>,
+    cost_attribution: CostAttribution,
+    // Published requests per route and how many filled at their expected profit
+    success_rates: success_rate::SuccessTracker,
+    // Routes with a published opportunity that has not expired yet
+    live_opportunities: LiveOpportunities,
+    // REPORT_POLICY: what each analysis pass prints
+    reporter: Reporter,
+    // Routes and inputs of the last comprehensive pass, see `delta.rs`
+    last_comprehensive: delta::RunSnapshot,
+    // Profit the last comprehensive pass found, by quote asset and route family
+    profit_rollup: profit_rollup::ProfitRollup,
+    // MARKET_HISTORY_SECS: when the market summary is next recorded
+    market_history: market_history::MarketHistory,
+    // ROUTE_OVERRIDES: pinned adjustments that win over the fee and profit model
+    route_overrides: overrides::RouteOverrides,
+    // Run on every execution request before it goes out
+    pre_trade: pretrade::PreTradeChecks,
+    // ATOMIC_EXECUTOR_ADDRESS: how DEX-to-DEX routes are bundled into one transaction
+    atomic_routes: atomic::AtomicRoutes,
+    // Settings until the pipeline starts the archiver
+    book_archive_config: Option<book_archive::BookArchiveConfig>,
+    book_archiver: Option<book_archive::BookArchiver>,
+    // Fed with every expired opportunity; shown in the break-even report
+    route_yields: YieldTracker,
+    allocation: AllocationConfig,
+    // Recomputed with the break-even report when ALLOCATION_TOTAL_CAPITAL is set
+    allocation_plan: Option<AllocationPlan>,
+    // Every COMPREHENSIVE_ANALYSIS_INTERVAL-th book applied triggers a full pass; a
+    // pass deferred while shedding load runs once caught up. Restored from the checkpoint
+    counters: AnalysisCounters,
+    checkpoint: Checkpoint,
+    intents: intents::IntentLog,
+    idle: idle::IdleMode,
+    // Shared with the pipeline queue, which serves higher-priority pairs first
+    pair_priorities: Arc<PairPriorities>,
+    shedder: LoadShedder,
+    snapshotter: StateSnapshotter,
+    // OPPORTUNITY_ARCHIVE: recorded opportunities kept in Redis under TTL tiers
+    archive: OpportunityArchive,
+    staleness: staleness::StalenessPolicy,
+    // ANOMALY_SCORERS and the plugged-in scorers, with what their scores gate
+    anomaly: anomaly::AnomalyScoring,
+    // MULTI_LEG_ARBITRAGE: cycles across several books, see triangular.rs
+    multi_leg: triangular::MultiLegConfig,
+    // Routes whose spreads close faster than we can execute (EXECUTION_LATENCY_MS)
+    route_pruning: pruning::LatencyBudget,
+    // Asset return correlations, for the correlated exposure limit
+    correlation: correlation::CorrelationTracker,
+    // Sampled gas prices per chain and the regime they put each chain in (GAS_HISTORY_FILE)
+    gas_history: gas_regime::GasHistory,
+    acks: acks::AckTracker,
+    // Competition, laggard and cluster annotations, and the plugged-in stages (ENRICHERS)
+    enrichers: enrichment::Enrichers,
+    // SLICE_PLANNER; cuts requests too large for one clip into timed slices
+    slicing: slicing::Slicing,
+    // VENUE_BALANCES and what in-flight requests hold of them
+    balances: BalanceLedger,
+    // Small opportunities held per route until their netting window closes
+    netting: Netting,
 }
 
 #[derive(Debug, Clone)]
@@ -64,13 +702,185 @@ struct FeesConfig {
     // Trading fees as percentage (e.g., 0.1 for 0.1%)
     binance_taker_fee: f64,
     binance_maker_fee: f64,
+    okx_taker_fee: f64,
+    okx_maker_fee: f64,
+    bybit_taker_fee: f64,
+    bybit_maker_fee: f64,
     uniswap_fee: f64,
+    sushiswap_fee: f64,
+    balancer_fee: f64, // varies per pool, set to the pool the collector quotes
+    raydium_fee: f64,
+    orca_fee: f64,
+    osmosis_fee: f64, // varies per pool, like balancer_fee
     // Gas costs in USD
     ethereum_gas_cost: f64,
+    // Transaction costs on Solana venues, in lamports
+    solana: SolanaFees,
+    osmosis_tx_cost: f64,
+    // Relayer/transaction cost of moving the asset over IBC when only one leg is on Osmosis
+    ibc_transfer_cost: f64,
     // Withdrawal fees
     withdrawal_fees: HashMap<String, f64>,
     // execution strategy
     use_market_orders: bool, // true = taker fees, false = maker fees
+    // What to do with venues that have no fee schedule above
+    unknown_exchange_policy: UnknownExchangePolicy,
+    unknown_exchange_fee: f64, // percentage, only used with UnknownExchangePolicy::DefaultFee
+    // Which asset each venue charges trading fees in. Venues not listed pay in quote
+    fee_denominations: HashMap<String, FeeDenomination>,
+    // Per-account overrides selected with ACCOUNT_PROFILE
+    profile: Option<AccountProfile>,
+    // Cost models plugged in for venues without a schedule here (see venue_costs.rs)
+    venue_models: VenueCostModels,
+    // Schedules from the config file, over the built-in ones (see config_file.rs)
+    exchange_schedules: HashMap<String, ExchangeFees>,
+    // Funding and borrow costs of the legs while they are held (see holding.rs)
+    holding: HoldingCosts,
+}
+
+// Venues with an explicit fee schedule in `estimate_fees_and_gas`
+const REGISTERED_EXCHANGES: [&str; 9] =
+    ["binance", "okx", "bybit", "uniswap-v3-exact", "sushiswap", "balancer", "raydium", "orca", "osmosis"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnknownExchangePolicy {
+    // Drop any opportunity that involves an unregistered venue
+    Reject,
+    // Price unregistered venues with `unknown_exchange_fee` and warn about it
+    DefaultFee,
+}
+
+impl std::str::FromStr for UnknownExchangePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(UnknownExchangePolicy::Reject),
+            "default" | "default_fee" => Ok(UnknownExchangePolicy::DefaultFee),
+            other => Err(anyhow!("unknown exchange policy: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FeeDenomination {
+    // Fee is paid in quote currency on top of the trade
+    Quote,
+    // Fee is deducted from the asset received (e.g. Binance without BNB discounts)
+    ReceivedAsset,
+}
+
+impl std::str::FromStr for FeeDenomination {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "quote" => Ok(FeeDenomination::Quote),
+            "received" | "received_asset" => Ok(FeeDenomination::ReceivedAsset),
+            other => Err(anyhow!("unknown fee denomination: {}", other)),
+        }
+    }
+}
+
+// Quote-valued cost of a route and the base quantity that actually reaches the sell leg
+#[derive(Debug, Clone, Copy)]
+struct FeeEstimate {
+    total: f64,
+    sell_size: f64,
+}
+
+impl FeesConfig {
+    fn is_registered_exchange(&self, exchange: &str) -> bool {
+        REGISTERED_EXCHANGES.contains(&exchange)
+            || self.exchange_schedules.contains_key(exchange)
+            || self.venue_models.get(exchange).is_some()
+    }
+
+    // Trading fee percentage charged by `exchange` for one leg
+    fn trading_fee_pct(&self, exchange: &str) -> f64 {
+        let scheduled = self.exchange_schedules.get(exchange).map(|schedule| schedule.trading_fee_pct(self.use_market_orders));
+        let default = scheduled.unwrap_or_else(|| match exchange {
+            "binance" if self.use_market_orders => self.binance_taker_fee,
+            "binance" => self.binance_maker_fee,
+            "okx" if self.use_market_orders => self.okx_taker_fee,
+            "okx" => self.okx_maker_fee,
+            "bybit" if self.use_market_orders => self.bybit_taker_fee,
+            "bybit" => self.bybit_maker_fee,
+            "uniswap-v3-exact" => self.uniswap_fee,
+            "sushiswap" => self.sushiswap_fee,
+            "balancer" => self.balancer_fee,
+            "raydium" => self.raydium_fee,
+            "orca" => self.orca_fee,
+            "osmosis" => self.osmosis_fee,
+            _ => self.venue_models.get(exchange).map_or(self.unknown_exchange_fee, |model| model.trading_fee_pct(self.use_market_orders)),
+        });
+        match &self.profile {
+            Some(profile) => profile.trading_fee_pct(exchange, self.use_market_orders, default),
+            None => default,
+        }
+    }
+
+    // Whether the account on `exchange` may withdraw `asset`
+    fn can_withdraw(&self, exchange: &str, asset: &str) -> bool {
+        self.profile.as_ref().is_none_or(|profile| profile.can_withdraw(exchange, asset))
+    }
+
+    // Per-trade costs independent of size, in USD
+    fn fixed_leg_cost(&self, exchange: &str) -> f64 {
+        if let Some(cost) = self.exchange_schedules.get(exchange).and_then(|schedule| schedule.fixed_cost) {
+            return cost;
+        }
+        match exchange {
+            "uniswap-v3-exact" | "sushiswap" | "balancer" => self.ethereum_gas_cost,
+            "raydium" | "orca" => self.solana.leg_cost_usd(),
+            "osmosis" => self.osmosis_tx_cost,
+            _ => self.venue_models.get(exchange).map_or(0.0, |model| model.fixed_cost_usd()),
+        }
+    }
+
+    // Cost of bridging between chains that a route pays on top of the withdrawal fee, in USD
+    fn transfer_cost(&self, buy_exchange: &str, sell_exchange: &str) -> f64 {
+        let ibc = if (buy_exchange == "osmosis") != (sell_exchange == "osmosis") { self.ibc_transfer_cost } else { 0.0 };
+        ibc + self.venue_models.transfer_cost_usd(buy_exchange, sell_exchange)
+    }
+
+    fn fee_denomination(&self, exchange: &str) -> FeeDenomination {
+        self.fee_denominations.get(exchange).copied().unwrap_or(FeeDenomination::Quote)
+    }
+
+    // Override one numeric setting by field name, e.g. `okx_taker_fee` or `withdrawal_fee.BTC`.
+    // `use_market_orders` takes 1 or 0
+    fn set_field(&mut self, field: &str, value: f64) -> Result<()> {
+        if let Some(asset) = field.strip_prefix("withdrawal_fee.") {
+            self.withdrawal_fees.insert(asset.to_uppercase(), value);
+            return Ok(());
+        }
+        let target = match field {
+            "binance_taker_fee" => &mut self.binance_taker_fee,
+            "binance_maker_fee" => &mut self.binance_maker_fee,
+            "okx_taker_fee" => &mut self.okx_taker_fee,
+            "okx_maker_fee" => &mut self.okx_maker_fee,
+            "bybit_taker_fee" => &mut self.bybit_taker_fee,
+            "bybit_maker_fee" => &mut self.bybit_maker_fee,
+            "uniswap_fee" => &mut self.uniswap_fee,
+            "sushiswap_fee" => &mut self.sushiswap_fee,
+            "balancer_fee" => &mut self.balancer_fee,
+            "raydium_fee" => &mut self.raydium_fee,
+            "orca_fee" => &mut self.orca_fee,
+            "osmosis_fee" => &mut self.osmosis_fee,
+            "ethereum_gas_cost" => &mut self.ethereum_gas_cost,
+            "osmosis_tx_cost" => &mut self.osmosis_tx_cost,
+            "ibc_transfer_cost" => &mut self.ibc_transfer_cost,
+            "unknown_exchange_fee" => &mut self.unknown_exchange_fee,
+            "use_market_orders" => {
+                self.use_market_orders = value != 0.0;
+                return Ok(());
+            }
+            other => return Err(anyhow!("unknown fee setting: {}", other)),
+        };
+        *target = value;
+        Ok(())
+    }
 }
 
 impl Default for FeesConfig {
@@ -81,56 +891,387 @@ impl Default for FeesConfig {
         withdrawal_fees.insert("ETH".to_string(), 0.005);
         withdrawal_fees.insert("USDT".to_string(), 10.0);
 
+        let mut fee_denominations: HashMap<String, FeeDenomination> = HashMap::new();
+        fee_denominations.insert("binance".to_string(), FeeDenomination::ReceivedAsset);
+        // Both charge spot fees in the asset received
+        fee_denominations.insert("okx".to_string(), FeeDenomination::ReceivedAsset);
+        fee_denominations.insert("bybit".to_string(), FeeDenomination::ReceivedAsset);
+
         // This can change. VARIABLE
         FeesConfig {
             binance_taker_fee: 0.1, // 0.1%
             binance_maker_fee: 0.1,
+            // Regular (lowest) tier spot fees
+            okx_taker_fee: 0.1,
+            okx_maker_fee: 0.08,
+            bybit_taker_fee: 0.1,
+            bybit_maker_fee: 0.1,
             uniswap_fee: 0.3, // 0.3%
+            sushiswap_fee: 0.3,
+            balancer_fee: 0.3,
+            raydium_fee: 0.25, // standard AMM pools
+            orca_fee: 0.3,     // most liquid whirlpool tier
+            osmosis_fee: 0.2,  // most common pool fee
             ethereum_gas_cost: 50.0, // $50 average gas cost
+            solana: SolanaFees::default(),
+            osmosis_tx_cost: 0.01,
+            ibc_transfer_cost: 0.05,
             withdrawal_fees,
             use_market_orders: true, // Default to use taker fees for speed of execution.
+            unknown_exchange_policy: UnknownExchangePolicy::DefaultFee,
+            unknown_exchange_fee: 0.15, // 0.15%
+            fee_denominations,
+            profile: None,
+            venue_models: VenueCostModels::default(),
+            exchange_schedules: HashMap::new(),
+            holding: HoldingCosts::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SizingConfig {
+    // Generic notional cap, converted to base units at the pair's USD price (see
+    // `notional`); `reference_price` only prices assets nothing else can
+    max_usd_size: f64,
+    reference_price: f64,
+    // Hard caps on max_size in base units, keyed by normalized pair (e.g. BTC/USDT)
+    pair_caps: HashMap<String, f64>,
+    // Hard caps on max_size in base units for any route touching the venue
+    exchange_caps: HashMap<String, f64>,
+    // Quote notional both legs must offer within `min_depth_bps` of their top of book; 0 disables
+    min_depth: f64,
+    min_depth_bps: f64,
+    // Per-route overrides of `min_depth`, keyed `PAIR:buy>sell` or just `PAIR`
+    route_min_depth: HashMap<String, f64>,
+    // Even fractions of max_size priced for the size ladder besides the depth breakpoints; 0 disables the ladder
+    ladder_steps: usize,
+}
+
+impl SizingConfig {
+    fn min_depth_for(&self, pair: &str, buy_exchange: &str, sell_exchange: &str) -> f64 {
+        self.route_min_depth
+            .get(&format!("{}:{}>{}", pair, buy_exchange, sell_exchange))
+            .or_else(|| self.route_min_depth.get(pair))
+            .copied()
+            .unwrap_or(self.min_depth)
+    }
+}
+
+impl Default for SizingConfig {
+    fn default() -> Self {
+        SizingConfig {
+            max_usd_size: 100000.0,
+            reference_price: 50000.0, // BTC-ish price the notional cap was tuned for
+            pair_caps: HashMap::new(),
+            exchange_caps: HashMap::new(),
+            min_depth: 0.0,
+            min_depth_bps: 10.0,
+            route_min_depth: HashMap::new(),
+            ladder_steps: 4,
         }
     }
 }
 
 impl SpreadAnalyzer {
     fn new(_redis_url: &str) -> Result<Self> {
-        let addr = std::env::var("REDIS_ADDR").unwrap_or_else(|_| "127.0.0.1:6379".to_string());
-        let mut parts = addr.split(':');
-        let host = parts.next().unwrap_or("127.0.0.1").to_string();
-        let port: u16 = parts.next().and_then(|p| p.parse().ok()).unwrap_or(6379);
-        let password = std::env::var("REDIS_PASS").ok();
-        let info = ConnectionInfo {
-            addr: ConnectionAddr::Tcp(host, port),
-            redis: RedisConnectionInfo {
-                db: 0,
-                username: None,         // or Some(user) if you have REDIS_USER
-                password,
-                ..Default::default()
-            },
-        };
-        let client = Client::open(info)?;
+        let throttle_secs: u64 = config::env_or("LOG_THROTTLE_SECS", DEFAULT_LOG_THROTTLE_SECS);
+        let kill_switch = KillSwitch::from_env();
+        let metrics = Metrics::default();
+        metrics.kill_switch_tripped.store(kill_switch.is_tripped() as u64, std::sync::atomic::Ordering::Relaxed);
+        let pair_priorities = Arc::new(PairPriorities::from_env());
+        let profile = AccountProfile::from_env()?;
         Ok(SpreadAnalyzer {
             books: HashMap::new(),
-            redis_client: client,
-            fees_config: FeesConfig::default(),
+            book_budget: BookBudget::from_env(),
+            sources: sources::sources_from_env()?,
+            strategy_tag: StrategyTag::resolve(profile.as_ref()),
+            fees_config: FeesConfig { profile, ..FeesConfig::default() },
+            gas: GasModel::new(HashMap::new()),
+            shadow_fees: None,
+            sizing_config: SizingConfig::default(),
+            capital_config: CapitalConfig::from_env(),
+            api_requests: None,
+            healthz_max_book_age_ms: config::env_or("HEALTHZ_MAX_BOOK_AGE_MS", api::DEFAULT_HEALTHZ_MAX_BOOK_AGE_MS),
+            control_commands: None,
+            grpc: None,
+            admin: None,
+            pipeline_profile: None,
+            paused: None,
+            thresholds: control::Thresholds::default(),
+            log_throttle: Arc::new(LogThrottle::new(Duration::from_secs(throttle_secs))),
+            metrics: Arc::new(metrics),
+            build_info: BuildInfo::current(),
+            lifecycle: LifecycleTracker::new(chrono::Duration::seconds(config::env_or(
+                "EXECUTION_REQUEST_TTL_SECS",
+                DEFAULT_EXECUTION_REQUEST_TTL_SECS,
+            ))),
+            spread_history: SpreadHistory::default(),
+            competition: CompetitionTracker::from_env(),
+            timing: TimingAdvisor::from_env(),
+            lag: LagTracker::from_env(),
+            break_even_reports: Vec::new(),
+            break_even_refresh: Duration::from_secs(config::env_or("BREAK_EVEN_REFRESH_SECS", DEFAULT_BREAK_EVEN_REFRESH_SECS)),
+            last_break_even_refresh: Instant::now(),
+            events: EventBus::from_env()?,
+            mode: config::env_or("ANALYZER_MODE", Mode::Observe),
+            publisher: None,
+            io_runtime: None,
+            opportunity_channel: config::env_var("OPPORTUNITY_CHANNEL").unwrap_or_else(|_| mode::DEFAULT_OPPORTUNITY_CHANNEL.to_string()),
+            execution_channel: config::env_var("EXECUTION_CHANNEL").unwrap_or_else(|_| mode::DEFAULT_EXECUTION_CHANNEL.to_string()),
+            streams: StreamConfig::from_env(),
+            publish_cooldown: PublishCooldown::from_env(),
+            kill_switch,
+            watchdog: VenueWatchdog::new(chrono::Duration::seconds(config::env_or(
+                "VENUE_MAX_SILENCE_SECS",
+                DEFAULT_VENUE_MAX_SILENCE_SECS,
+            ))),
+            ingest_stats: IngestStats::default(),
+            queue_capacity: config::env_or("PIPELINE_QUEUE_CAPACITY", pipeline::DEFAULT_QUEUE_CAPACITY),
+            overflow_policy: config::env_or("PIPELINE_OVERFLOW_POLICY", OverflowPolicy::DropOldest),
+            history: HistoryStore::from_env()?,
+            exporter: ParquetExporter::from_env(),
+            solana_tokens: TokenMap::from_env(),
+            slot_clock: SlotClock::from_env(),
+            venue_status: Arc::new(StatusCache::from_env()),
+            withdrawal_fee_refresh: withdrawal_fees::FeeRefresh::new(None, 0.0),
+            executor_schema: executor_schema::SchemaHandshake::new(None, Duration::from_secs(60)),
+            route_locks: route_locks::RouteLocks::new(0, route_locks::DEFAULT_PREFIX, &route_locks::instance_from_env()),
+            canary: canary::CanaryVenues::new(false, [], None),
+            onboarding: onboarding::PairOnboarding::new(false, [], 1.0, None),
+            started_at: Utc::now(),
+            maintenance: Arc::new(MaintenanceBoard::default()),
+            latency: LatencyModel::from_env(),
+            quarantined: HashMap::new(),
+            cost_attribution: CostAttribution::default(),
+            success_rates: success_rate::SuccessTracker::from_env(),
+            reporter: Reporter::from_env(),
+            last_comprehensive: delta::RunSnapshot::default(),
+            profit_rollup: profit_rollup::ProfitRollup::default(),
+            route_overrides: overrides::RouteOverrides::default(),
+            pre_trade: pretrade::PreTradeChecks::default(),
+            atomic_routes: atomic::AtomicRoutes::default(),
+            book_archive_config: None,
+            book_archiver: None,
+            market_history: market_history::MarketHistory::from_env(),
+            live_opportunities: LiveOpportunities::new(chrono::Duration::seconds(config::env_or(
+                "OPPORTUNITY_TTL_SECS",
+                expiry::DEFAULT_OPPORTUNITY_TTL_SECS,
+            ))),
+            route_yields: YieldTracker::from_env(),
+            allocation: AllocationConfig::from_env(),
+            allocation_plan: None,
+            counters: AnalysisCounters::default(),
+            checkpoint: Checkpoint::from_env(),
+            intents: intents::IntentLog::from_env(),
+            idle: idle::IdleMode::from_env(),
+            pair_priorities: pair_priorities.clone(),
+            shedder: LoadShedder::from_env(pair_priorities),
+            snapshotter: StateSnapshotter::from_env(),
+            enrichers: enrichment::Enrichers::default(),
+            slicing: slicing::Slicing::default(),
+            balances: BalanceLedger::from_env(),
+            netting: Netting::from_env(),
+            archive: OpportunityArchive::from_env(),
+            staleness: staleness::StalenessPolicy::from_env(),
+            anomaly: anomaly::AnomalyScoring::default(),
+            multi_leg: triangular::MultiLegConfig::from_env(),
+            route_pruning: pruning::LatencyBudget::from_env(),
+            correlation: correlation::CorrelationTracker::from_env(),
+            gas_history: gas_regime::GasHistory::new(None, Duration::from_secs(12)),
+            acks: acks::AckTracker::from_env(),
         })
     }
 
-    fn parse_key_from_payload(&self, payload: &str) -> Result<String> {
-        if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(payload) {
-            if let Some(key) = json_value.get("key").and_then(|k| k.as_str()) {
-                return Ok(key.to_string());
+    fn publish(&self, event: Event) {
+        self.events.publish(event);
+    }
+
+    // Queue a message on a Redis channel; a no-op when nothing is set up to publish
+    fn publish_to<T: Serialize>(&self, channel: &str, message: &T) {
+        let Some(publisher) = &self.publisher else { return };
+        match serde_json::to_string(message) {
+            Ok(payload) => publisher.publish(channel, payload),
+            Err(e) => error!("Failed to serialize message for {}: {}", channel, e),
+        }
+    }
+
+    // Queue a Redis key write, expiring after `ttl_secs` if given; a no-op when
+    // nothing is set up to publish
+    fn publish_key<T: Serialize>(&self, key: &str, value: &T, ttl_secs: Option<usize>) {
+        let Some(publisher) = &self.publisher else { return };
+        match serde_json::to_string(value) {
+            Ok(payload) => publisher.set(key, payload, ttl_secs),
+            Err(e) => error!("Failed to serialize value for {}: {}", key, e),
+        }
+    }
+
+    // Close out opportunities that went away: count them towards their route's
+    // yield and tell subscribers. Executors only hear about routes they hold an
+    // in-flight request for
+    fn expire_opportunities(&mut self, expiries: Vec<OpportunityExpiry>) {
+        self.metrics.live_opportunities.store(self.live_opportunities.len() as u64, std::sync::atomic::Ordering::Relaxed);
+        for expiry in expiries {
+            self.route_yields.record(&expiry);
+            self.pair_priorities.learn(&expiry.pair, expiry.peak_net_profit, expiry.expired_at);
+            Metrics::inc(&self.metrics.opportunities_expired);
+            let route = RouteKey::new(&expiry.pair, &expiry.buy_exchange, &expiry.sell_exchange);
+            info!("Opportunity {} on {} expired: {}", expiry.opportunity_id, route, expiry.reason.describe());
+            self.publish(
+                Event::new(
+                    EventClass::OpportunityExpired,
+                    Severity::Info,
+                    format!("Opportunity {} on {} {}", expiry.opportunity_id, route, expiry.reason.describe()),
+                )
+                .with_pair(&expiry.pair),
+            );
+            let (id, at) = (&expiry.opportunity_id, expiry.expired_at);
+            if self.mode.publishes_opportunities() {
+                self.publish_to(&self.opportunity_channel, &expiry);
+                self.stream_to(self.streams.opportunities.as_deref(), EntryType::OpportunityExpired, id, &route, at, &expiry);
+            }
+            if self.mode.emits_execution_requests() && self.lifecycle.in_flight().iter().any(|r| r.route == route) {
+                self.publish_to(&self.execution_channel, &expiry);
+                self.stream_to(self.streams.executions.as_deref(), EntryType::OpportunityExpired, id, &route, at, &expiry);
+            }
+        }
+    }
+
+    // Periodic upkeep, run on every loop iteration whether or not an update arrived
+    fn housekeeping(&mut self) {
+        self.update_idle(Utc::now());
+        self.sync_maintenance();
+        self.refresh_gas();
+        self.sample_gas(Utc::now());
+        self.check_venue_silence(Utc::now());
+        let expired = self.live_opportunities.expire_stale(Utc::now());
+        self.expire_opportunities(expired);
+        self.evict_stale_books(Utc::now());
+        self.refresh_route_pruning(Utc::now());
+        self.check_acks(Utc::now());
+        self.sample_correlations(Utc::now());
+        self.apply_withdrawal_fees(Utc::now());
+        self.sync_executor_schema();
+
+        let now = Utc::now();
+        for id in self.lifecycle.expire_stale(now) {
+            info!("Execution request {} expired without a terminal update", id);
+            self.record_transition(&id, RequestState::Expired, now, ExecutionOutcome::default());
+            if let Some(route) = self.lifecycle.recent().find(|r| r.id == id).map(|r| r.route.clone()) {
+                self.publish(
+                    Event::new(
+                        EventClass::OpportunityExpired,
+                        Severity::Warning,
+                        format!("Execution request {} on {} got no terminal update within its TTL", id, route),
+                    )
+                    .with_pair(&route.pair),
+                );
             }
         }
-        Ok(payload.to_string())
+
+        self.flush_netting(now);
+        self.exporter.flush_if_due(Instant::now());
+        self.events.flush(Utc::now());
+        self.report_summary_if_due(Utc::now());
+        self.report_pipeline_profile_if_due();
+        self.record_market_history(Utc::now());
+
+        if self.last_break_even_refresh.elapsed() >= self.break_even_refresh {
+            self.refresh_break_even();
+            self.refresh_allocation_plan();
+            self.last_break_even_refresh = Instant::now();
+        }
+        self.write_state_snapshot_if_due();
+        self.checkpoint.save_if_due(&self.counters, Instant::now());
+        self.compact_archive_if_due(Instant::now());
+    }
+
+    // Mark venues that went quiet as suspect, publishing an event for each
+    fn check_venue_silence(&mut self, now: DateTime<Utc>) {
+        let newly_suspect = self.watchdog.check(now);
+        if !newly_suspect.is_empty() && self.watchdog.all_suspect() {
+            self.publish(Event::new(
+                EventClass::AllVenuesStale,
+                Severity::Critical,
+                "No venue is sending orderbook updates; check the collectors and Redis".to_string(),
+            ));
+        }
+        for (venue, silence) in newly_suspect {
+            self.publish(
+                Event::new(
+                    EventClass::VenueStale,
+                    Severity::Warning,
+                    format!(
+                        "No orderbook update from {} for {}s (limit {}s); its books are suspect",
+                        venue,
+                        silence.num_seconds(),
+                        self.watchdog.max_silence().num_seconds()
+                    ),
+                )
+                .with_venue(&venue),
+            );
+        }
+    }
+
+    // Answer every gRPC control call queued since the last poll
+    fn serve_grpc_calls(&mut self) {
+        if let Some(grpc) = self.grpc.take() {
+            grpc.serve(self);
+            self.grpc = Some(grpc);
+        }
+    }
+
+    // Answer every admin console command queued since the last poll
+    fn serve_admin_commands(&mut self) {
+        if let Some(mut admin) = self.admin.take() {
+            admin.serve(self);
+            self.admin = Some(admin);
+        }
+    }
+
+    // Answer every API request queued since the last poll
+    fn serve_api_requests(&mut self) {
+        let pending: Vec<ApiRequest> = match &self.api_requests {
+            Some(rx) => rx.try_iter().collect(),
+            None => return,
+        };
+
+        for request in pending {
+            let response = api::handle(self, &request);
+            request.respond(response);
+        }
     }
 
-    fn estimate_fees_and_gas(&self, size: f64, buy_exchange: &str, sell_exchange: &str, pair: &str) -> f64 {
+    fn estimate_fees_and_gas(
+        &self,
+        size: f64,
+        buy_price: f64,
+        sell_price: f64,
+        buy_exchange: &str,
+        sell_exchange: &str,
+        pair: &str,
+    ) -> FeeEstimate {
+        self.estimate_fees_with(&self.fees_config, size, buy_price, sell_price, buy_exchange, sell_exchange, pair)
+    }
+
+    // `estimate_fees_and_gas` under any fee model, e.g. a shadow candidate
+    #[allow(clippy::too_many_arguments)]
+    fn estimate_fees_with(
+        &self,
+        fees: &FeesConfig,
+        size: f64,
+        buy_price: f64,
+        sell_price: f64,
+        buy_exchange: &str,
+        sell_exchange: &str,
+        pair: &str,
+    ) -> FeeEstimate {
         /*
             In Arbitrage Context:
             - Taker fees apply when you use market orders (immediate execution)
             - Maker fees apply when you use limit orders (add liquidity to orderbook)
+            - Venues charging fees in the received asset shrink the quantity we hold,
+              so the sell leg only gets what is left after the buy leg fee and the transfer
          */
         let mut total_fees: f64 = 0.0;
 
@@ -146,31 +1287,39 @@ impl SpreadAnalyzer {
             pair.chars().take(4).collect()
         };
 
-        // matching the buy exchanging
-        match buy_exchange {
-            "binance" => { total_fees += size * self.fees_config.binance_taker_fee / 100.0; }
-            "uniswap-v3-exact" => {
-                total_fees += size * self.fees_config.uniswap_fee / 100.0;
-                total_fees += self.fees_config.ethereum_gas_cost;
-            }
-            _ => { total_fees += size * 0.15 / 100.0; }
-        };
-
-        match sell_exchange {
-            "binance" => { total_fees += size * self.fees_config.binance_taker_fee / 100.0; }
-            "uniswap-v3-exact" => {
-                total_fees += size * self.fees_config.uniswap_fee / 100.0;
-                total_fees += self.fees_config.ethereum_gas_cost;
-            }
-            _ => { total_fees += size * 0.15 / 100.0; }
+        // Buy leg: quote fees are paid on top, in-kind fees come out of the base we receive
+        let buy_fee_pct = fees.trading_fee_pct(buy_exchange);
+        let mut held_size = size;
+        match fees.fee_denomination(buy_exchange) {
+            FeeDenomination::Quote => { total_fees += size * buy_price * buy_fee_pct / 100.0; }
+            FeeDenomination::ReceivedAsset => { held_size -= size * buy_fee_pct / 100.0; }
         }
+        total_fees += fees.fixed_leg_cost(buy_exchange);
 
-        // Withdrawal/transfer fees - normalize WBTC to BTC for fee lookup
+        // Withdrawal/transfer fees are charged in the base asset being moved - normalize WBTC to BTC for fee lookup
         let fee_lookup_currency = base_currency.replace("WBTC", "BTC");
-        if let Some(withdrawal_fee) = self.fees_config.withdrawal_fees.get(&fee_lookup_currency) {
-            total_fees += withdrawal_fee * size; // Assuming withdrawal fee is per unit
+        if let Some(withdrawal_fee) = fees.withdrawal_fees.get(&fee_lookup_currency) {
+            held_size -= withdrawal_fee;
+        }
+        let held_size = held_size.max(0.0);
+        total_fees += fees.transfer_cost(buy_exchange, sell_exchange);
+
+        // Sell leg: we receive quote, so the fee is quote-denominated either way
+        let sell_fee_pct = fees.trading_fee_pct(sell_exchange);
+        total_fees += held_size * sell_price * sell_fee_pct / 100.0;
+        total_fees += fees.fixed_leg_cost(sell_exchange);
+
+        // Base lost to in-kind fees is valued at the price we would have sold it for
+        total_fees += (size - held_size) * sell_price;
+
+        // Funding and borrowing over the time the legs are held
+        if !fees.holding.is_empty() {
+            let prefunded = fees.profile.as_ref().is_some_and(|p| p.is_prefunded(buy_exchange, sell_exchange));
+            let lockup_secs = self.capital_config.lockup_secs(prefunded) + fees.venue_models.settlement_secs(buy_exchange, sell_exchange);
+            total_fees += fees.holding.route_cost(buy_exchange, size * buy_price, sell_exchange, held_size * sell_price, lockup_secs);
         }
-        total_fees
+
+        FeeEstimate { total: total_fees, sell_size: held_size }
     }
 
     fn normalize_pair_symbols(&self, pair1: &str, pair2: &str) -> (String, String, f64) {
@@ -189,18 +1338,39 @@ impl SpreadAnalyzer {
         (normalized_pair1, normalized_pair2, price_adjustment)
     }
 
-    fn choose_execution_size(&self, ask_size: f64, bid_size: f64) -> f64 {
+    fn choose_execution_size(
+        &self,
+        ask_size: f64,
+        bid_size: f64,
+        pair: &str,
+        buy_exchange: &str,
+        sell_exchange: &str,
+        route_mid: f64,
+    ) -> f64 {
         // Take the minimum to ensure we can execute both sides
         let max_possible: f64 = ask_size.min(bid_size);
 
         // Apply conservative sizing (80% of max possible)
         let conservative_size: f64 = max_possible * 0.8;
 
-        // Cap at reasonable maximum (e.g., $100K possible)
-        let max_usd_size: f64 = 100000.0;
-        let reasonable_max: f64 = max_usd_size / 50000.0;
+        // Cap at reasonable maximum (e.g., $100K possible), valued at this pair's price
+        let converter = NotionalConverter::new(&self.capital_config.quote_usd, &self.books, self.sizing_config.reference_price);
+        let (reasonable_max, price_source) = converter.base_size_for(self.sizing_config.max_usd_size, pair, route_mid);
+        if price_source == notional::PriceSource::Reference {
+            debug!("No USD price for {}; capping its size at the reference price", pair);
+        }
 
-        conservative_size.min(reasonable_max)
+        // Hard caps from config, in base units: per pair, then per venue on either leg
+        let hard_cap = [
+            self.sizing_config.pair_caps.get(pair),
+            self.sizing_config.exchange_caps.get(buy_exchange),
+            self.sizing_config.exchange_caps.get(sell_exchange),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(f64::INFINITY, |cap, limit| cap.min(*limit));
+
+        conservative_size.min(reasonable_max).min(hard_cap)
     }
 
     // Group orderbooks by normalized trading pair for cross-exchange comparison
@@ -210,24 +1380,34 @@ impl SpreadAnalyzer {
         for (key, book) in &self.books {
             // Normalize the pair (e.g., WBTC/USDT -> BTC/USDT)
             let normalized_pair = book.pair.replace("WBTC", "BTC");
-            group.entry(normalized_pair).or_insert_with(Vec::new).push((key, book));
+            group.entry(normalized_pair).or_default().push((key, book));
         }
 
         group
     }
 
     fn analyze_all_spreads(&self) -> Result<Vec<ArbitrageOpportunity>> {
+        self.analyze_spreads(None, Utc::now())
+    }
+
+    // Every route of every pair, or of `only_pair` when given, with books aged as of `now`
+    fn analyze_spreads(&self, only_pair: Option<&str>, now: DateTime<Utc>) -> Result<Vec<ArbitrageOpportunity>> {
         debug!("Analyzing all spreads...");
         let mut all_opportunities: Vec<ArbitrageOpportunity> = Vec::new();
 
         // Group orderbooks by normalized trading pair
-        let grouped_books = self.group_books_by_pair();
+        let mut grouped_books = self.group_books_by_pair();
+        if let Some(pair) = only_pair {
+            grouped_books.retain(|normalized_pair, _| normalized_pair == pair);
+        }
 
         debug!("Grouped {} orderbooks by trading pair", grouped_books.len());
 
         // Analyze each trading pair across all exchanges
-        for (normalized_pair, books) in grouped_books {
-            if books.len() < 1 {
+        for (normalized_pair, mut books) in grouped_books {
+            // Keep the order of routes (and of their log lines) stable across runs
+            books.sort_by_key(|(key, _)| *key);
+            if books.len() < 2 {
                 // need atleast 2 exchanges to compare
                 debug!("Skipping {} with less than 2 exchanges", normalized_pair);
                 continue;
@@ -246,45 +1426,129 @@ impl SpreadAnalyzer {
                         continue;
                     }
 
+                    // Books from a venue that stopped updating produce phantom spreads
+                    if let Some(suspect) = [&book1.exchange, &book2.exchange].into_iter().find(|e| self.watchdog.is_suspect(e)) {
+                        self.log_throttle.warn(
+                            &format!("suspect_venue:{}", suspect),
+                            format_args!("Skipping {} routes: venue {} is suspect (no recent updates)", normalized_pair, suspect),
+                        );
+                        continue;
+                    }
+
+                    // Venues under maintenance cannot fill either leg
+                    if let Some(quarantined) = [&book1.exchange, &book2.exchange].into_iter().find(|e| self.quarantined.contains_key(*e)) {
+                        self.log_throttle.warn(
+                            &format!("quarantined_venue:{}", quarantined),
+                            format_args!("Skipping {} routes: venue {} is quarantined ({})", normalized_pair, quarantined, self.quarantined[quarantined]),
+                        );
+                        continue;
+                    }
+
+                    // Solana books are aged by slot rather than by wall clock
+                    if let Some(lagging) = [book1, book2].into_iter().find(|b| self.lags_slot_tip(b)) {
+                        self.log_throttle.warn(
+                            &format!("slot_lag:{}", lagging.exchange),
+                            format_args!("Skipping {} routes: {} book is too far behind the slot tip", normalized_pair, lagging.exchange),
+                        );
+                        continue;
+                    }
+
+                    // A quote that stopped updating would be compared against fresh ones
+                    if !self.fresh_enough(&normalized_pair, book1, book2, now) {
+                        continue;
+                    }
+
                     // Ensure both books have valid data
                     if book1.bids.is_empty() || book1.asks.is_empty() || book2.bids.is_empty() || book2.asks.is_empty() {
-                        warn!("Empty orderbook found: {} or {}" , key1, key2);
+                        self.log_throttle.warn(
+                            &format!("empty_book:{}:{}", key1, key2),
+                            format_args!("Empty orderbook found: {} or {}", key1, key2),
+                        );
                         continue;
                     }
 
-                    // calculate price adjustments for wrapped tokens
-                    let (_, _, price_adjustment) = self.normalize_pair_symbols(&book1.pair, &book2.pair);
-
-                    // Scenario 1: Buy from book1, sell to book2
-                    let buy_price1 = book1.asks[0][0] * price_adjustment;
-                    let buy_size1 = book1.asks[0][1];
-
-                    let sell_price2 = book2.bids[0][0];
-                    let sell_size2 = book2.bids[0][1];
-
-                    if let Some(opp) = self.evaluate_opportunity(
-                        &book1.exchange,
-                        &book2.exchange,
-                        &normalized_pair,
-                        buy_price1,
-                        sell_price2,
-                        buy_size1,
-                        sell_size2
-                    ) {
-                        all_opportunities.push(opp);
+                    // Both directions: either venue can be the cheap one
+                    for (buy_key, buy_book, sell_key, sell_book) in [(key1, book1, key2, book2), (key2, book2, key1, book1)] {
+                        // Malformed top levels are skipped rather than indexed into
+                        let (Some(_), Some(_)) = (buy_book.best_ask(), sell_book.best_bid()) else {
+                            self.log_throttle.warn(
+                                &format!("malformed_top:{}:{}", buy_key, sell_key),
+                                format_args!("Malformed top of book found: {} or {}", buy_key, sell_key),
+                            );
+                            continue;
+                        };
+
+                        // Spreads on this route close before our orders could land
+                        if !self.route_pruning.is_empty() && self.route_pruning.prunes(&RouteKey::new(&normalized_pair, &buy_book.exchange, &sell_book.exchange)) {
+                            Metrics::inc(&self.metrics.pruned_route_evaluations);
+                            continue;
+                        }
+
+                        // calculate price adjustments for wrapped tokens
+                        let (_, _, price_adjustment) = self.normalize_pair_symbols(&buy_book.pair, &sell_book.pair);
+
+                        // Buy from one book, sell to the other, as deep into both books as pays
+                        let opportunity = self
+                            .evaluate_opportunity_depth(&buy_book.exchange, &sell_book.exchange, &normalized_pair, &buy_book.asks, &sell_book.bids, price_adjustment)
+                            .filter(|opp| self.has_min_depth(buy_book, sell_book, opp));
+
+                        // Shadow evaluation is the first thing to go when behind
+                        if let Some(shadow) = self.shadow_fees.as_ref().filter(|_| !self.shedder.shedding()) {
+                            let candidate = shadow
+                                .admits_venues(&buy_book.exchange, &sell_book.exchange)
+                                .then(|| {
+                                    self.price_route_depth(&shadow.candidate, &buy_book.exchange, &sell_book.exchange, &normalized_pair, &buy_book.asks, &sell_book.bids, price_adjustment)
+                                })
+                                .flatten()
+                                .filter(|opp| self.depth_shortfall(buy_book, sell_book, opp).is_none());
+                            let route = RouteKey::new(&normalized_pair, &buy_book.exchange, &sell_book.exchange);
+                            if shadow.compare(route, opportunity.as_ref(), candidate.as_ref(), Utc::now()) {
+                                Metrics::inc(&self.metrics.shadow_fee_divergences);
+                            }
+                        }
+
+                        if let Some(mut opp) = opportunity {
+                            opp.book_ages = staleness::StalenessPolicy::ages(buy_book, sell_book, now);
+                            opp.anomaly_score = anomaly::combined(buy_book.anomaly_score, sell_book.anomaly_score);
+                            all_opportunities.push(opp);
+                        }
                     }
                 }
             }
         }
 
         // Sort all opportunities by ROI in descending order
-        all_opportunities.sort_by(|a, b| b.roi_percentage.partial_cmp(&a.roi_percentage).unwrap_or(std::cmp::Ordering::Equal));
+        all_opportunities.sort_by(|a, b| numeric::cmp_desc(a.roi_percentage, b.roi_percentage));
         
         Ok(all_opportunities)
     }
 
-    fn analyze_spread(&mut self, updated_key: &str) -> Result<Vec<ArbitrageOpportunity>> {
-        let mut all_opportunities: Vec<ArbitrageOpportunity> = self.analyze_all_spreads()?;
+    // Profitable on paper but only for dust: too little resting near the top of either leg
+    fn has_min_depth(&self, buy_book: &OrderBook, sell_book: &OrderBook, opp: &ArbitrageOpportunity) -> bool {
+        let Some((required, buy_depth, sell_depth)) = self.depth_shortfall(buy_book, sell_book, opp) else {
+            return true;
+        };
+        Metrics::inc(&self.metrics.shallow_routes_skipped);
+        debug!(
+            "Skipping {} {}→{}: depth within {}bps is {:.2} / {:.2}, below {:.2}",
+            opp.pair, opp.buy_exchange, opp.sell_exchange, self.sizing_config.min_depth_bps, buy_depth, sell_depth, required
+        );
+        false
+    }
+
+    // (required, buy depth, sell depth) when either leg is too thin for `opp`'s route
+    fn depth_shortfall(&self, buy_book: &OrderBook, sell_book: &OrderBook, opp: &ArbitrageOpportunity) -> Option<(f64, f64, f64)> {
+        let required = self.sizing_config.min_depth_for(&opp.pair, &opp.buy_exchange, &opp.sell_exchange);
+        if required <= 0.0 {
+            return None;
+        }
+        let bps = self.sizing_config.min_depth_bps;
+        let (buy_depth, sell_depth) = (buy_book.ask_depth_within(bps), sell_book.bid_depth_within(bps));
+        (buy_depth < required || sell_depth < required).then_some((required, buy_depth, sell_depth))
+    }
+
+    fn analyze_spread(&mut self, updated_key: &str, now: DateTime<Utc>) -> Result<Vec<ArbitrageOpportunity>> {
+        let all_opportunities: Vec<ArbitrageOpportunity> = self.analyze_spreads(None, now)?;
 
         // Filter for opportunities involving the updated exchange/pair
         let updated_book = self.books.get(updated_key).ok_or_else(|| anyhow!("Orderbook not found for key: {}", updated_key))?;
@@ -296,34 +1560,161 @@ impl SpreadAnalyzer {
         Ok(filtered_opportunities)        
     }
 
-    fn evaluate_opportunity(
+    /// Price the route buying from `asks` (their prices × `price_adjustment`, for wrapped
+    /// tokens) and selling into `bids`, at the most profitable size the levels allow
+    #[allow(clippy::too_many_arguments)]
+    fn evaluate_opportunity_depth(
         &self,
         buy_exchange: &str,
         sell_exchange: &str,
         pair: &str,
-        buy_price: f64,
-        sell_price: f64,
-        buy_size: f64,
-        sell_size: f64,
+        asks: &[Vec<f64>],
+        bids: &[Vec<f64>],
+        price_adjustment: f64,
     ) -> Option<ArbitrageOpportunity> {
+        // Poisoned inputs never produce an opportunity
+        let (Some((ask_price, buy_size)), Some((sell_price, sell_size))) = (OrderBook::level(asks, 0), OrderBook::level(bids, 0)) else {
+            return None;
+        };
+        let buy_price = ask_price * price_adjustment;
+        if !numeric::all_finite(&[buy_price, sell_price, buy_size, sell_size]) || buy_price <= 0.0 {
+            return None;
+        }
+
+        // Venues without a fee schedule are either dropped or priced with the default fee
+        for exchange in [buy_exchange, sell_exchange] {
+            if self.fees_config.is_registered_exchange(exchange) {
+                continue;
+            }
+            match self.fees_config.unknown_exchange_policy {
+                UnknownExchangePolicy::Reject => {
+                    Metrics::inc(&self.metrics.unknown_exchange_rejections);
+                    return None;
+                }
+                UnknownExchangePolicy::DefaultFee => {
+                    Metrics::inc(&self.metrics.unknown_exchange_evaluations);
+                    self.log_throttle.warn(
+                        &format!("unknown_exchange:{}", exchange),
+                        format_args!(
+                            "UNREGISTERED EXCHANGE '{}': estimating fees with default {:.3}%, results may be wrong",
+                            exchange, self.fees_config.unknown_exchange_fee
+                        ),
+                    );
+                }
+            }
+        }
+
+        // Counted under the active model only, not again for the shadow one
+        self.route_overrides.record_applied(pair, buy_exchange, sell_exchange, Utc::now());
+        self.price_route_depth(&self.fees_config, buy_exchange, sell_exchange, pair, asks, bids, price_adjustment)
+    }
+
+    // The fee-dependent part of `evaluate_opportunity_depth`: everything after input and
+    // venue checks, under the given fee model. Every size where a level of either book
+    // runs out is priced at the VWAPs it fills at, and the most profitable one wins
+    #[allow(clippy::too_many_arguments)]
+    fn price_route_depth(
+        &self,
+        fees: &FeesConfig,
+        buy_exchange: &str,
+        sell_exchange: &str,
+        pair: &str,
+        asks: &[Vec<f64>],
+        bids: &[Vec<f64>],
+        price_adjustment: f64,
+    ) -> Option<ArbitrageOpportunity> {
+        let (Some((ask_price, _)), Some((top_sell_price, _))) = (OrderBook::level(asks, 0), OrderBook::level(bids, 0)) else {
+            return None;
+        };
+        let top_buy_price = ask_price * price_adjustment;
         // Check for positive spread
-        if sell_price <= buy_price {
+        if top_sell_price <= top_buy_price {
+            return None;
+        }
+
+        let route_override = self.route_overrides.for_route(pair, buy_exchange, sell_exchange);
+        if route_override.is_some_and(|o| o.disabled) {
+            return None;
+        }
+
+        // The bought asset has to leave the buy venue
+        let base_asset = pair.split('/').next().unwrap_or(pair);
+        if !fees.can_withdraw(buy_exchange, base_asset) {
+            debug!("Skipping {} via {}: {} is not on the withdrawal whitelist", pair, buy_exchange, base_asset);
             return None;
         }
 
-        let max_size: f64 = self.choose_execution_size(buy_size, sell_size);
-        if max_size <= 0.0 {
+        // Sizes where a level runs out, up to where the next ask costs what the next bid pays
+        let mut sizes = sweep::breakpoints(asks, bids, price_adjustment);
+        let walkable = sizes.last().copied().unwrap_or(0.0);
+        let max_size: f64 =
+            self.choose_execution_size(walkable, walkable, pair, buy_exchange, sell_exchange, (top_buy_price + top_sell_price) / 2.0);
+        if !max_size.is_finite() || max_size <= 0.0 {
             return None;
         }
+        sizes.retain(|size| *size < max_size);
+        let ladder_steps = self.sizing_config.ladder_steps;
+        sizes.extend(sweep::ladder_fractions(max_size, ladder_steps));
+        sizes.push(max_size);
+        sizes.sort_by(f64::total_cmp);
+        sizes.dedup_by(|a, b| (*a - *b).abs() <= *b * 1e-9);
+
+        let priced: Vec<ArbitrageOpportunity> = sizes
+            .into_iter()
+            .filter_map(|size| {
+                let fill = sweep::Fill::at(asks, bids, price_adjustment, size)?;
+                let mut opp = self.price_at_size(fees, buy_exchange, sell_exchange, pair, fill.buy_price, fill.sell_price, size)?;
+                opp.depth = fill.annotation(top_buy_price, top_sell_price);
+                Some(opp)
+            })
+            .collect();
+        // Every priced size that clears the thresholds, up to the best one
+        let mut ladder: Vec<sweep::LadderPoint> = priced.iter().map(sweep::LadderPoint::of).collect();
+        let mut best = priced.into_iter().max_by(|a, b| a.net_profit.total_cmp(&b.net_profit))?;
+        ladder.retain(|point| point.size <= best.max_size);
+        if ladder_steps > 0 && ladder.len() > 1 {
+            best.size_ladder = ladder;
+        }
+        Some(best)
+    }
 
+    // `size` of the route bought at `buy_price` and sold at `sell_price`, if that clears the thresholds
+    #[allow(clippy::too_many_arguments)]
+    fn price_at_size(
+        &self,
+        fees: &FeesConfig,
+        buy_exchange: &str,
+        sell_exchange: &str,
+        pair: &str,
+        buy_price: f64,
+        sell_price: f64,
+        max_size: f64,
+    ) -> Option<ArbitrageOpportunity> {
+        let route_override = self.route_overrides.for_route(pair, buy_exchange, sell_exchange);
         let gross_profit_per_unit: f64 = sell_price - buy_price;
-        let estimated_fees: f64 = self.estimate_fees_and_gas(max_size, buy_exchange, sell_exchange, pair);
+        let fee_estimate = self.estimate_fees_with(fees, max_size, buy_price, sell_price, buy_exchange, sell_exchange, pair);
+        if fee_estimate.sell_size <= 0.0 {
+            return None;
+        }
+        let estimated_fees: f64 = fee_estimate.total + overrides::extra_cost(route_override, max_size * buy_price);
         let gross_profit: f64 = gross_profit_per_unit * max_size;
         let net_profit: f64 = gross_profit - estimated_fees;
-        let roi_percentage: f64 = (net_profit / (buy_price * max_size)) * 100.0;
+        let prefunded = fees.profile.as_ref().is_some_and(|p| p.is_prefunded(buy_exchange, sell_exchange));
+        let mut capital = self.capital_config.capital_at_risk(pair, buy_price, sell_price, max_size, prefunded);
+        capital.lockup_secs += fees.venue_models.settlement_secs(buy_exchange, sell_exchange);
+        let roi_percentage: f64 = numeric::safe_pct(net_profit, capital.amount)?;
 
-        // Check profitability thresholds
-        if net_profit < MIN_ABSOLUTE_PROFIT || roi_percentage < MIN_ROI_PERCENTAGE {
+        if !numeric::all_finite(&[gross_profit_per_unit, estimated_fees, net_profit, fee_estimate.sell_size]) {
+            return None;
+        }
+
+        // Check profitability thresholds, scaled by the gas regime on DEX legs and for pairs still onboarding
+        let thresholds = self.thresholds;
+        let multiplier = self.gas_history.threshold_multiplier(buy_exchange, sell_exchange)
+            * self.onboarding.threshold_multiplier(&pair.replace("WBTC", "BTC"));
+        if net_profit < overrides::min_profit(route_override, thresholds.min_net_profit(estimated_fees)) * multiplier
+            || roi_percentage < thresholds.min_roi_percentage * multiplier
+        {
             return None;
         }
 
@@ -332,82 +1723,35 @@ impl SpreadAnalyzer {
             buy_exchange: buy_exchange.to_string(), 
             sell_exchange: sell_exchange.to_string(), 
             pair: pair.to_string(), 
-            buy_price: buy_price, 
-            sell_price: sell_price, 
-            max_size: max_size, 
-            gross_profit_per_unit: gross_profit_per_unit, 
-            estimated_fees: estimated_fees, 
-            net_profit: net_profit, 
-            roi_percentage: roi_percentage, 
+            buy_price,
+            sell_price,
+            max_size,
+            sell_size: fee_estimate.sell_size,
+            gross_profit_per_unit,
+            estimated_fees,
+            net_profit,
+            roi_percentage,
+            capital_at_risk: Some(capital),
+            annualized_roi_percentage: capital::annualize(roi_percentage, capital.lockup_secs),
             timestamp: Utc::now(),
+            // Left to the enrichers, for the opportunities that come out of detection
+            competition: None,
+            laggard: None,
+            cluster: None,
+            depth: None,
+            book_ages: None,
+            anomaly_score: None,
+            size_ladder: Vec::new(),
+            gas_regime: None,
+            execution_success: None,
+            annotations: BTreeMap::new(),
+            tag: self.strategy_tag.clone(),
         })
 
     }
 
-    fn print_exchange_stats(&self) {
-        let grouped = self.group_books_by_pair();
-        let exchanges: std::collections::HashSet<String> = self.books.values()
-            .map(|book| book.exchange.clone())
-            .collect();
-        
-        println!("\n MARKET DATA SUMMARY");
-        println!("───────────────────────────────────");
-        println!("  Active Exchanges: {}", exchanges.len());
-        println!("  Trading Pairs: {}", grouped.len());
-        println!("  Total Orderbooks: {}", self.books.len());
-        
-        for exchange in &exchanges {
-            let count = self.books.values().filter(|book| book.exchange == *exchange).count();
-            println!("  - {}: {} pairs", exchange, count);
-        }
-        
-        for (pair, books) in &grouped {
-            if books.len() > 1 {
-                println!("   {}: {} exchanges", pair, books.len());
-            }
-        }
-    }
-
-    fn print_analysis_results(&self, opportunities: &[ArbitrageOpportunity]) {
-        // Print exchange statistics first
-        self.print_exchange_stats();
-        if opportunities.is_empty() {
-            println!(" SPREAD ANALYSIS: No profitable opportunities found");
-            return;
-        }
-
-        println!("\n ARBITRAGE OPPORTUNITIES DETECTED ");
-        println!("═══════════════════════════════════════════");
-
-        for (idx, opp) in opportunities.iter().enumerate() {
-            println!("\n\n Opportunity #{}", idx + 1);
-            println!("  ID: {}", opp.id);
-            println!("  Strategy: Buy {} → Sell {}", opp.buy_exchange, opp.sell_exchange);
-            println!("  Pair: {}", opp.pair);
-            println!("  Buy Price: ${:.4}", opp.buy_price);
-            println!("  Sell Price: ${:.4}", opp.sell_price);
-            println!("  Spread: ${:.4} ({:.3}%)", 
-                     opp.gross_profit_per_unit, 
-                     (opp.gross_profit_per_unit / opp.buy_price) * 100.0);
-            println!("  Max Execution Size: {:.6}", opp.max_size);
-            println!("  Gross Profit: ${:.2}", opp.gross_profit_per_unit * opp.max_size);
-            println!("  Estimated Fees: ${:.2}", opp.estimated_fees);
-            println!("  NET PROFIT: ${:.2}", opp.net_profit);
-            println!("  ROI: {:.2}%", opp.roi_percentage);
-            println!("  Timestamp: {}", opp.timestamp.format("%Y-%m-%d %H:%M:%S UTC"));
-            
-            // Risk assessment
-            if opp.roi_percentage > 2.0 {
-                println!("  Risk Level: HIGH PROFIT");
-            } else if opp.roi_percentage > 1.0 {
-                println!("  Risk Level: MODERATE");
-            } else {
-                println!("  Risk Level: LOW MARGIN");
-            }
-        }
-    }
-
     /// Add method for periodic comprehensive analysis (useful for debugging/monitoring)
+    #[allow(dead_code)]
     fn run_comprehensive_analysis(&self) -> Result<()> {
         info!("🔍 Running comprehensive cross-exchange analysis...");
         
@@ -425,98 +1769,555 @@ impl SpreadAnalyzer {
 
     fn run(&mut self) -> Result<(), anyhow::Error> {
         info!(" Starting Spread Analysis...");
+        let queue = self.start_pipeline()?;
+        if let Some(runtime) = &self.io_runtime {
+            shutdown::install(runtime.handle());
+        }
+        // Before anything new goes out, settle what the previous run left in flight
+        self.recover_intents(Utc::now());
 
-        let mut con = self.redis_client.get_connection()?;
-        let mut pubsub = con.as_pubsub();
+        // To keep checking for the updates from the channel from redis
+        loop {
+            if shutdown::requested() {
+                self.shut_down();
+                return Ok(());
+            }
+            self.serve_api_requests();
+            self.serve_control_commands();
+            self.serve_grpc_calls();
+            self.serve_admin_commands();
+            self.housekeeping();
+
+            // Wake up regularly so API requests are served even when no updates arrive; less often when idle
+            let event = match queue.pop_timeout(self.idle.poll_interval(PUBSUB_POLL_INTERVAL)) {
+                Pop::Event(event) => event,
+                Pop::Timeout => continue,
+                Pop::Closed => return Err(anyhow!("All source listeners stopped")),
+            };
+            self.record_idle_activity(Utc::now());
+            let started = Instant::now();
+            // One bad update must not stop an unattended analyzer
+            if let Err(e) = self.process_event(event, Utc::now()) {
+                Metrics::inc(&self.metrics.analysis_errors);
+                self.log_throttle.error("process_event", format_args!("Failed to analyze update: {:#}", e));
+            }
+            self.metrics.observe_analysis(started.elapsed().as_secs_f64());
+            if let Some(level) = self.shedder.finish_cycle(started.elapsed(), queue.len(), &self.metrics) {
+                match level {
+                    shedding::ShedLevel::Normal => info!("Caught up with the update backlog, analysis back to normal"),
+                    level => warn!("Analysis behind by {} updates, shedding load: {}", queue.len(), level),
+                }
+            }
+        }
+    }
 
-        pubsub.subscribe("orderbook_updates")?;
-        info!("Subscribed to orderbook_updates channel");
+    /// Flush what is still pending on the way out: event digests, the shutdown report, queued publishes
+    /// and the book archive chunk
+    fn shut_down(&mut self) {
+        info!(" Shutting down");
+        self.events.flush(Utc::now());
+        self.emit_shutdown_report(Utc::now());
+        if self.pipeline_profile.is_some() {
+            self.report_pipeline_profile();
+        }
+        let timeout = Duration::from_secs(config::env_or("SHUTDOWN_FLUSH_SECS", shutdown::DEFAULT_FLUSH_SECS));
+        if let Some(publisher) = self.publisher.take() {
+            publisher.close(timeout);
+        }
+        if let Some(archiver) = self.book_archiver.take() {
+            archiver.close(timeout);
+        }
+        if let Some(admin) = self.admin.take() {
+            admin.close();
+        }
+        // Listeners and fetches have nothing left to deliver to
+        if let Some(runtime) = self.io_runtime.take() {
+            runtime.shutdown_timeout(Duration::from_secs(1));
+        }
+    }
 
-        // Counter for periodic comprehensive analysis
-        let mut update_counter = 0;
-        const COMPREHENSIVE_ANALYSIS_INTERVAL: u32 = 10;
+    /// Start the I/O runtime, the control listener, the publisher and every ingestion and status task.
+    /// Returns the queue books arrive on
+    fn start_pipeline(&mut self) -> Result<Arc<BookQueue>> {
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(2).thread_name("redis-io").enable_all().build()?;
+        for source in &self.sources {
+            match &source.user {
+                Some(user) => info!("Reading source {} at {} as {}", source.name, source.addr, user),
+                None => info!("Reading source {} at {}", source.name, source.addr),
+            }
+        }
+        // Control commands arrive on, and signals and reports go out through, the first source's Redis.
+        // What gets published is up to the mode, see `process_event`
+        if let Some(source) = self.sources.first() {
+            let channel = config::env_var("CONTROL_CHANNEL").unwrap_or_else(|_| control::DEFAULT_CONTROL_CHANNEL.to_string());
+            self.control_commands = Some(control::spawn_listener(source.client.clone(), channel));
+            self.executor_schema.spawn(source.client.clone());
+            self.route_locks.connect(source.client.clone());
+            let publisher = Publisher::spawn(runtime.handle(), source.client.clone(), self.metrics.clone());
+            if let Some(group) = &self.streams.group {
+                for stream in self.streams.streams() {
+                    publisher.create_group(stream, group);
+                }
+            }
+            self.publisher = Some(publisher);
+        }
+        // Ingestion runs on the I/O runtime; this loop only applies books and analyzes
+        let queue = Arc::new(
+            BookQueue::new(self.queue_capacity, self.overflow_policy, self.metrics.clone()).with_priorities(self.pair_priorities.clone()),
+        );
+        self.metrics.redis_sources_configured.store(self.sources.len() as u64, std::sync::atomic::Ordering::Relaxed);
+        Ingestor::new(std::mem::take(&mut self.sources), self.log_throttle.clone(), self.metrics.clone()).spawn(runtime.handle(), queue.clone());
+        self.io_runtime = Some(runtime);
+        if let Some(config) = binance_ws::BinanceWsConfig::from_env() {
+            binance_ws::spawn(config, queue.clone(), self.metrics.clone());
+        }
+        for config in venues::VenueWsConfig::all_from_env() {
+            venues::spawn(config, queue.clone());
+        }
+        if let Some(config) = feasibility::StatusConfig::from_env() {
+            feasibility::spawn(config, self.venue_status.clone());
+        }
+        self.withdrawal_fee_refresh.spawn();
+        if let Some(config) = maintenance::MaintenanceConfig::from_env() {
+            maintenance::spawn(config, self.maintenance.clone());
+        }
+        gas::spawn(&self.gas);
+        latency::spawn(&self.latency);
+        if let Some(config) = self.book_archive_config.take() {
+            self.book_archiver = Some(book_archive::BookArchiver::spawn(config, self.metrics.clone()));
+        }
+        let retention = retention::RetentionConfig::from_env();
+        self.history.spawn_maintenance(retention.clone(), self.metrics.clone());
+        if let Some(dir) = self.exporter.dir() {
+            retention::spawn_parquet(retention, dir.to_path_buf(), self.metrics.clone());
+        }
+        Ok(queue)
+    }
 
+    /// Apply one event from the ingestion stage and act on the opportunities it reveals.
+    /// Time comes from `now`, so recorded scenarios replay the same way every run
+    fn process_event(&mut self, event: IngestEvent, now: DateTime<Utc>) -> Result<Vec<ArbitrageOpportunity>> {
+        let mut orderbook = match event {
+            IngestEvent::Book { key, book } => {
+                debug!("Applying {}", key);
+                book
+            }
+            IngestEvent::Rejected { key, exchange, reason } => {
+                self.ingest_stats.record_rejected(&exchange);
+                self.report_rejection(&key, &exchange, &reason, now);
+                self.publish(Event::new(EventClass::BookRejected, Severity::Info, format!("Rejected {}: {}", key, reason)).with_venue(&exchange));
+                return Ok(Vec::new());
+            }
+        };
 
-        // To keep checking for the updates from the channel from redis
-        loop {
-            let msg = pubsub.get_message()?;
-            let payload: String = msg.get_payload()?;
+        if let Some(reason) = self.admit_solana_book(&mut orderbook) {
+            self.reject_solana_book(&orderbook, &reason);
+            self.report_rejection(&format!("{}:{}", orderbook.exchange, orderbook.pair), &orderbook.exchange, &reason, now);
+            return Ok(Vec::new());
+        }
+        if !self.score_anomaly(&mut orderbook, now) {
+            return Ok(Vec::new());
+        }
 
-            debug!("Received message: {}", payload);
+        self.ingest_stats.record_accepted(&orderbook.exchange, orderbook.bids.len() + orderbook.asks.len(), now);
+        // Archived at full depth
+        if let Some(archiver) = &mut self.book_archiver {
+            archiver.archive(&orderbook, now);
+        }
+        self.trim_book_levels(&mut orderbook);
 
-            // Parsing the key from the payload
-            let key = match self.parse_key_from_payload(&payload) {
-                Ok(key) => key,
-                Err(e) => {
-                    error!("Failed to parse key from payload: {}", e);
+        if self.watchdog.heartbeat(&orderbook.exchange, now) {
+            self.publish(
+                Event::new(EventClass::VenueRecovered, Severity::Info, format!("{} is sending orderbook updates again", orderbook.exchange))
+                    .with_venue(&orderbook.exchange),
+            );
+        }
+        self.observe_canary_book(&orderbook.exchange, now);
+        if !self.observe_pair_book(&orderbook, now) {
+            debug!("Dropping {}:{}: pair blocked", orderbook.exchange, orderbook.pair);
+            return Ok(Vec::new());
+        }
+
+        // Store locally in the format as our go codebase: order:exchange:pair
+        let book_key = format!("{}:{}", orderbook.exchange, orderbook.pair);
+        self.books.insert(book_key.clone(), orderbook.clone());
+        self.enforce_book_budget(&book_key);
+
+        info!(
+            "Updated orderbook: {} from {} (bids: {}, asks: {})",
+            book_key,
+            orderbook.source.as_deref().unwrap_or(sources::DEFAULT_SOURCE),
+            orderbook.bids.len(),
+            orderbook.asks.len()
+        );
+
+        let normalized_pair = orderbook.pair.replace("WBTC", "BTC");
+        // Behind on a backlog: keep the cache current but leave low-priority pairs for later
+        if !self.shedder.analyzes(&normalized_pair) {
+            Metrics::inc(&self.metrics.shed_updates_skipped);
+            return Ok(Vec::new());
+        }
+        let shedding = self.shedder.shedding();
+        if !shedding {
+            self.record_spreads(&normalized_pair);
+        }
+        if let (true, Some((bid, _)), Some((ask, _))) = (self.lag.enabled(), orderbook.best_bid(), orderbook.best_ask()) {
+            self.lag.observe(&normalized_pair, &orderbook.exchange, (bid + ask) / 2.0, now);
+        }
+
+        self.counters.updates_applied += 1;
+        Metrics::inc(&self.metrics.books_processed);
+        if self.counters.updates_applied.is_multiple_of(COMPREHENSIVE_ANALYSIS_INTERVAL) {
+            if shedding || self.idle.is_idle() {
+                Metrics::inc(&self.metrics.comprehensive_passes_deferred);
+            }
+            self.counters.comprehensive_pending = true;
+        }
+        let comprehensive = self.counters.comprehensive_pending && !shedding && !self.idle.is_idle();
+
+        let analysis_started = Instant::now();
+        let mut opportunities = if comprehensive {
+            self.counters.comprehensive_pending = false;
+            self.counters.comprehensive_passes += 1;
+            self.counters.last_comprehensive_at = Some(now);
+            info!(" Running comprehensive analysis (update #{})...", self.counters.updates_applied);
+            self.analyze_spreads(None, now)?
+        } else if shedding {
+            Metrics::inc(&self.metrics.shed_analyses);
+            self.analyze_spreads(Some(&normalized_pair), now)?
+                .into_iter()
+                .filter(|opp| opp.buy_exchange == orderbook.exchange || opp.sell_exchange == orderbook.exchange)
+                .collect()
+        } else {
+            // Targeted analysis for the updated pair
+            self.analyze_spread(&book_key, now)?
+        };
+        self.metrics.stages.record(Stage::Analyze, analysis_started.elapsed());
+        let enrichment_started = Instant::now();
+        self.enrich(&mut opportunities, now);
+        self.metrics.stages.record(Stage::Enrich, enrichment_started.elapsed());
+
+        // A targeted pass only re-evaluated routes on the updated venue, a shed one only those on its pair too.
+        // Only published opportunities are live; a cluster member that stops leading expires
+        let evaluated_venue = (!comprehensive).then_some(orderbook.exchange.as_str());
+        let published: Vec<ArbitrageOpportunity> = opportunities.iter().filter(|opp| cluster::leads(opp)).cloned().collect();
+        let expired = self.live_opportunities.observe(
+            &published,
+            |route| {
+                evaluated_venue.is_none_or(|venue| route.buy_exchange == venue || route.sell_exchange == venue)
+                    && (!shedding || route.pair == normalized_pair)
+            },
+            now,
+        );
+        self.expire_opportunities(expired);
+        if comprehensive {
+            self.profit_rollup = profit_rollup::ProfitRollup::of_run(&opportunities, now);
+        }
+        self.report_pass(&opportunities, comprehensive, now);
+        if comprehensive {
+            self.report_comprehensive_delta(&opportunities, now);
+        }
+
+        let publishing_started = Instant::now();
+        if !opportunities.is_empty() {
+            // Process execution requests
+            for opp in &opportunities {
+                Metrics::inc(&self.metrics.opportunities_found);
+                self.metrics.observe_roi(opp.roi_percentage);
+                self.history.record(HistoryRecord::Opportunity(Box::new(opp.clone())));
+                self.exporter.push_opportunity(opp);
+                self.snapshotter.record(opp);
+                self.archive_opportunity(opp);
+                self.canary.observe_opportunity(opp, now);
+                // The same dislocation is published once, through the cluster's representative
+                if !cluster::leads(opp) {
+                    Metrics::inc(&self.metrics.clustered_opportunities_suppressed);
                     continue;
                 }
-            };
+                // Prices and sizes as the venues quote them, for everything that leaves the analyzer
+                let published = precision::policy().round_opportunity(opp);
+                self.publish(Event::opportunity_detected(&published));
 
-            // Fetching the most updated orderbook from redis
-            let mut redis_con = self.redis_client.get_connection()?;
-            
-            let json_data: String = match redis_con.get(&key) {
-                Ok(data) => data,
-                Err(e) => {
-                    error!("Failed to fetch orderbook : {}", e);
+                // Paused: keep analyzing and recording, publish nothing
+                if self.publishing_paused() {
                     continue;
                 }
-            };
-
-            // parse the orderbook
-            let orderbook: OrderBook = match serde_json::from_str(&json_data) {
-                Ok(ob) => ob,
-                Err(e) => {
-                    error!("Failed to parse orderbook JSON for {}: {}", key, e);
+                if self.mode.publishes_opportunities() {
+                    if self.publish_cooldown.admit(opp, now) {
+                        self.publish_to(&self.opportunity_channel, &published);
+                        Metrics::inc(&self.metrics.opportunities_published);
+                        let route = RouteKey::new(&opp.pair, &opp.buy_exchange, &opp.sell_exchange);
+                        self.stream_to(self.streams.opportunities.as_deref(), EntryType::Opportunity, &opp.id, &route, opp.timestamp, &published);
+                        if let Some(grpc) = &self.grpc {
+                            grpc.broadcast(&published);
+                        }
+                        if let Some(admin) = &mut self.admin {
+                            admin.broadcast(&published);
+                        }
+                    } else {
+                        Metrics::inc(&self.metrics.opportunities_deduplicated);
+                    }
+                }
+                // The kill switch, venue status and limits are checked on the request itself
+                if !self.mode.emits_execution_requests() {
+                    continue;
+                }
+                // Spreads that only exist on a stale quote, when the operator chose not to chase them
+                if let (LaggardPolicy::Ignore, Some(laggard)) = (self.lag.policy, &opp.laggard) {
+                    Metrics::inc(&self.metrics.laggard_opportunities_ignored);
+                    debug!("Skipping {} on {}: {} trails {}", opp.id, opp.pair, laggard.laggard, laggard.leader);
+                    continue;
+                }
+                // Likely bad data on one leg: seen, but not traded
+                if let Some(score) = opp.anomaly_score.filter(|score| self.anomaly.flags(*score)) {
+                    Metrics::inc(&self.metrics.anomalous_opportunities_ignored);
+                    debug!("Skipping {} on {}: anomaly score {:.2}", opp.id, opp.pair, score);
                     continue;
                 }
-            };
 
-            // Store locally in the format as our go codebase: order:exchange:pair
-            let book_key = format!("{}:{}", orderbook.exchange, orderbook.pair);
-            self.books.insert(book_key.clone(), orderbook.clone());
+                // Small ones wait for others on their route, to pay the fixed costs once
+                if self.netting.enabled() && self.nets(opp) {
+                    self.netting.hold(opp, now);
+                    Metrics::inc(&self.metrics.opportunities_netted);
+                    continue;
+                }
+                self.request_execution(opp, None, now);
+            }
+        }
+        self.metrics.stages.record(Stage::Publish, publishing_started.elapsed());
+        // Cycles through the updated book, or through every book on a comprehensive pass
+        if self.multi_leg.enabled && !shedding {
+            self.analyze_multi_leg((!comprehensive).then_some(book_key.as_str()), now);
+        }
+        Ok(opportunities)
+    }
 
-            info!("Updated orderbook: {} (bids: {}, asks: {})", book_key, orderbook.bids.len(), orderbook.asks.len());
+    fn execution_request(&self, opp: &ArbitrageOpportunity, netted: Option<NettedBatch>, now: DateTime<Utc>) -> ExecutionRequest {
+        // Sized and priced as the venues accept, down to the atomic plan's amounts
+        let opp = &precision::policy().round_opportunity(opp);
+        let atomic = self.atomic_plan(opp, now);
+        ExecutionRequest {
+            id: Uuid::new_v4().to_string(),
+            schema_version: executor_schema::SCHEMA_VERSION,
+            opportunity: opp.clone(),
+            execution_size: opp.max_size,
+            created_at: now,
+            account_profile: self.fees_config.profile.as_ref().map(|p| p.name.clone()),
+            timing: self.timing_advice(&RouteKey::new(&opp.pair, &opp.buy_exchange, &opp.sell_exchange)),
+            gas: self.gas_plan(opp),
+            netting: netted,
+            tag: opp.tag.clone(),
+            pre_trade_checks: Vec::new(),
+            slices: self.slice_plan(opp, opp.max_size, atomic.is_some(), now),
+            atomic,
+        }
+    }
 
-            update_counter += 1;
+    // Turn `opp` into an execution request, within the venue balances left and one request per route
+    fn request_execution(&mut self, opp: &ArbitrageOpportunity, netted: Option<NettedBatch>, now: DateTime<Utc>) {
+        // Balance held by requests in flight, including better-ranked ones from this pass
+        let needs = contention::needs(opp);
+        let fitting = self.balances.fitting_size(&needs, opp.max_size);
+        let downsized;
+        let opp = if fitting < opp.max_size {
+            match self.reprice_at_size(opp, fitting) {
+                Some(smaller) => {
+                    Metrics::inc(&self.metrics.contention_downsized);
+                    info!("Downsizing {} on {} from {} to {} to fit venue balances", opp.id, opp.pair, opp.max_size, fitting);
+                    downsized = smaller;
+                    &downsized
+                }
+                None => {
+                    Metrics::inc(&self.metrics.contention_dropped);
+                    info!("Dropping {} on {}: venue balances only fit {} of {}", opp.id, opp.pair, fitting, opp.max_size);
+                    return;
+                }
+            }
+        } else {
+            opp
+        };
 
-            let opportunities = if update_counter % COMPREHENSIVE_ANALYSIS_INTERVAL == 0 {
-                info!(" Running comprehensive analysis (update #{})...", update_counter);
-                self.analyze_all_spreads()?
-            } else {
-                // Targeted analysis for the updated pair
-                self.analyze_spread(&book_key)?
-            };
+        let mut exec_request = self.execution_request(opp, netted, now);
+        let route = RouteKey::new(&opp.pair, &opp.buy_exchange, &opp.sell_exchange);
+        // Kill switch, venue status, balances and limits; a tripped switch keeps analysis and recording going
+        match self.run_pre_trade_checks(&exec_request) {
+            Ok(passed) => exec_request.pre_trade_checks = passed,
+            Err((check, reason)) => {
+                Metrics::inc(&self.metrics.pre_trade_rejections);
+                self.log_throttle.warn(
+                    &format!("pre_trade:{}:{}", check, route),
+                    format_args!("Blocking {} on {}: {} check failed: {}", opp.id, route, check, reason),
+                );
+                return;
+            }
+        }
 
+        // Shaped for what the executor reads, or held back when it can't read it
+        let payload = match self.executor_schema.adapt(serde_json::to_value(&exec_request).unwrap_or_default()) {
+            Ok(payload) => payload,
+            Err(reason) => {
+                Metrics::inc(&self.metrics.execution_requests_incompatible);
+                self.log_throttle.error(
+                    &format!("executor_schema:{}", route),
+                    format_args!("Not publishing execution request for {} on {}: {}", opp.id, route, reason),
+                );
+                return;
+            }
+        };
 
-            if !opportunities.is_empty() {
-                self.print_analysis_results(&opportunities);
-                
-                // Process execution requests
-                for opp in opportunities {
-                    let exec_request = ExecutionRequest {
-                        id: Uuid::new_v4().to_string(),
-                        opportunity: opp.clone(),
-                        execution_size: opp.max_size,
-                        created_at: Utc::now(),
-                    };
-                    
-                    info!("⚡ Would execute: {} (Net: ${:.2}, ROI: {:.2}%)", exec_request.id, opp.net_profit, opp.roi_percentage);
-                    
-                    // TODO: Publish to execution stream and test this.
-                    
-                    // let exec_json = serde_json::to_string(&exec_request)?;
-                    // redis_con.publish("execution_requests", exec_json)?;
-                }
-            } else if update_counter % COMPREHENSIVE_ANALYSIS_INTERVAL == 0 {
-                // Only show "no opportunities" for comprehensive analysis
-                println!("\n Comprehensive analysis complete - no profitable opportunities found");
+        // Only one request per route may be in flight, otherwise they all chase the same liquidity
+        if let Err(in_flight_id) = self.lifecycle.open(&exec_request.id, route.clone(), exec_request.created_at) {
+            Metrics::inc(&self.metrics.execution_requests_suppressed);
+            debug!("Skipping {}: request {} still in flight", route, in_flight_id);
+            return;
+        }
+        // Nor may another deployment's. Asked only after `open`, so this deployment's own lock never reads as a conflict
+        match self.route_locks.acquire(&route, &exec_request.id) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.lifecycle.discard(&exec_request.id);
+                Metrics::inc(&self.metrics.route_lock_conflicts);
+                debug!("Skipping {}: route reserved by another deployment", route);
+                return;
+            }
+            Err(e) => {
+                self.lifecycle.discard(&exec_request.id);
+                Metrics::inc(&self.metrics.redis_errors);
+                self.log_throttle.error("route_lock", format_args!("Not publishing execution request for {} on {}: failed to reserve the route: {}", opp.id, route, e));
+                return;
             }
         }
+        self.lifecycle.set_notional_usd(&exec_request.id, self.notional_usd(&exec_request.opportunity, exec_request.execution_size));
+        // Nothing goes out that a restart could lose track of
+        let intent = intents::Intent {
+            id: exec_request.id.clone(),
+            opportunity_id: opp.id.clone(),
+            route: route.clone(),
+            size: exec_request.execution_size,
+            created_at: exec_request.created_at,
+            request: payload.clone(),
+        };
+        if let Err(e) = self.intents.record(intent) {
+            error!("Not publishing execution request {} on {}: failed to log its intent: {}", exec_request.id, route, e);
+            let _ = self.lifecycle.transition(&exec_request.id, RequestState::Failed, now);
+            self.route_locks.release(&exec_request.id);
+            return;
+        }
+        self.balances.reserve(&exec_request.id, &needs, exec_request.execution_size);
+        self.history.record(HistoryRecord::ExecutionRequest {
+            id: exec_request.id.clone(),
+            opportunity_id: opp.id.clone(),
+            route: route.clone(),
+            execution_size: exec_request.execution_size,
+            created_at: exec_request.created_at,
+        });
+        let fixed_costs = self.fees_config.fixed_leg_cost(&opp.buy_exchange)
+            + self.fees_config.fixed_leg_cost(&opp.sell_exchange)
+            + self.fees_config.transfer_cost(&opp.buy_exchange, &opp.sell_exchange);
+        self.cost_attribution.open(
+            &exec_request.id,
+            CostEstimate {
+                route: route.clone(),
+                size: exec_request.execution_size,
+                buy_price: opp.buy_price,
+                sell_price: opp.sell_price,
+                variable_fees: opp.estimated_fees - fixed_costs,
+                fixed_costs,
+                net_profit: opp.net_profit,
+            },
+        );
+        self.record_transition(&exec_request.id, RequestState::Pending, exec_request.created_at, ExecutionOutcome::default());
+        self.publish_to(&self.execution_channel, &payload);
+        let stream = self.streams.executions.as_deref();
+        self.stream_to(stream, EntryType::ExecutionRequest, &exec_request.id, &route, exec_request.created_at, &payload);
+        // From here on the executor owes us an ack, see `acks`
+        if self.lifecycle.transition(&exec_request.id, RequestState::Published, now).is_ok() {
+            self.record_transition(&exec_request.id, RequestState::Published, now, ExecutionOutcome::default());
+        }
+        if let Some(plan) = &exec_request.slices {
+            Metrics::inc(&self.metrics.execution_requests_sliced);
+            debug!("Execution request {} goes out in {} {} slices", exec_request.id, plan.slices.len(), plan.planner);
+        }
+        info!("⚡ Execution request {} emitted (Net: ${:.2}, ROI: {:.2}%)", exec_request.id, opp.net_profit, opp.roi_percentage);
+    }
+}
+
+
+fn dump_books(out: Option<PathBuf>, api: Option<String>) -> Result<()> {
+    let api_addr = api
+        .or_else(|| config::env_var("API_ADDR").ok())
+        .unwrap_or_else(|| DEFAULT_API_ADDR.to_string());
+    let out = out.unwrap_or_else(|| PathBuf::from(dump::default_dump_path()));
+
+    let dump = api::fetch(&api_addr, "/books")?;
+    std::fs::write(&out, serde_json::to_string_pretty(&dump)?)?;
+
+    let count = dump.get("book_count").and_then(|c| c.as_u64()).unwrap_or(0);
+    info!("Wrote {} orderbooks from {} to {}", count, api_addr, out.display());
+    Ok(())
+}
+
+fn seasonality_report(format: &str, out: Option<PathBuf>, pair: Option<String>, from: Option<String>, api: Option<String>) -> Result<()> {
+    let api_addr = api
+        .or_else(|| config::env_var("API_ADDR").ok())
+        .unwrap_or_else(|| DEFAULT_API_ADDR.to_string());
+
+    let mut path = String::from("/history/seasonality?format=json");
+    for (name, value) in [("pair", pair), ("from", from)] {
+        if let Some(value) = value {
+            path.push_str(&format!("&{}={}", name, api::percent_encode(&value)));
+        }
+    }
+    let report: seasonality::SeasonalityReport = serde_json::from_value(api::fetch(&api_addr, &path)?)?;
+    let body = match format {
+        "json" => serde_json::to_string_pretty(&report)?,
+        "csv" => report.to_csv(),
+        other => return Err(anyhow!("unknown format: {} (expected json or csv)", other)),
+    };
+
+    match out {
+        Some(out) => {
+            std::fs::write(&out, body)?;
+            info!("Wrote seasonality report for {} routes/hours to {}", report.by_hour.len(), out.display());
+        }
+        None => println!("{}", body),
     }
+    Ok(())
 }
 
+fn kill_switch_command(action: KillSwitchAction, api: Option<String>) -> Result<()> {
+    let api_addr = api
+        .or_else(|| config::env_var("API_ADDR").ok())
+        .unwrap_or_else(|| DEFAULT_API_ADDR.to_string());
+    let user = format!("cli:{}", config::env_var("USER").unwrap_or_else(|_| "unknown".to_string()));
+    let actor = api::percent_encode(&user);
+
+    let state = match action {
+        KillSwitchAction::Status => api::fetch(&api_addr, "/kill-switch")?,
+        KillSwitchAction::Trip { reason } => {
+            let path = format!("/kill-switch/trip?reason={}&actor={}", api::percent_encode(&reason), actor);
+            match api::post(&api_addr, &path, None) {
+                Ok(state) => state,
+                Err(e) => {
+                    // Break glass: the next analyzer start picks the tripped state up
+                    let file = kill_switch::state_file_from_env();
+                    warn!("{}; writing the tripped state to {} instead", e, file.display());
+                    kill_switch::trip_offline(&file, &reason, &user)?;
+                    info!("Kill switch tripped in {}; a running analyzer only picks it up on restart", file.display());
+                    return Ok(());
+                }
+            }
+        }
+        KillSwitchAction::Reset { token } => {
+            let token = token
+                .or_else(|| config::env_var("KILL_SWITCH_RESET_TOKEN").ok())
+                .ok_or_else(|| anyhow!("a reset token is required (--token or KILL_SWITCH_RESET_TOKEN)"))?;
+            api::post(&api_addr, &format!("/kill-switch/reset?actor={}", actor), Some(&token))?
+        }
+    };
+    println!("{}", serde_json::to_string_pretty(&state)?);
+    Ok(())
+}
 
 fn main() -> Result<()> {
     // Load environment variables from .env if present
@@ -525,23 +2326,132 @@ fn main() -> Result<()> {
     // env_logger::init();
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
+    let cli = Cli::parse();
+    let replay = cli.replay.or_else(|| config::env_var("REPLAY_FILE").ok().map(PathBuf::from));
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => match replay {
+            Some(path) => backtest::run(&path, cli.replay_speed, cli.output, cli.config.as_deref()),
+            None => run_analyzer(cli.output, cli.config.as_deref(), cli.profile_pipeline),
+        },
+        Command::DumpBooks { out, api } => dump_books(out, api),
+        Command::Seasonality { format, out, pair, from, api } => seasonality_report(&format, out, pair, from, api),
+        Command::KillSwitch { action, api } => kill_switch_command(action, api),
+        Command::Doctor => doctor::run(cli.config.as_deref()),
+        Command::Replay { from, to, stream } => stream_replay::run(&from, &to, stream, cli.output, cli.config.as_deref()),
+        Command::Admin { socket, command } => {
+            let socket = socket.or_else(admin::socket_from_env).ok_or_else(|| anyhow!("Pass --socket or set ADMIN_SOCKET"))?;
+            admin::run_client(&socket, &command)
+        }
+        Command::Experiment { replay: books, grid, results, name } => {
+            let books = books.or(replay).ok_or_else(|| anyhow!("Pass --replay or set REPLAY_FILE"))?;
+            experiment::run(&books, &grid, results, name, cli.output, cli.config.as_deref())
+        }
+    }
+}
+
+// Fee and sizing settings on top of what SpreadAnalyzer::new reads. Shared with
+// `doctor`, which validates the same configuration the daemon would run with
+fn configure_from_env(analyzer: &mut SpreadAnalyzer, config_path: Option<&Path>) -> Result<()> {
+    // The config file first, so the environment can override what it sets
+    if let Some((path, file)) = ConfigFile::load(config_path)? {
+        file.apply(analyzer)?;
+        info!("  Loaded {} ({} exchange schedules: {})", path.display(), file.exchanges().len(), file.exchanges().join(", "));
+    }
+    let cost_multiple = config::env_or("PROFIT_COST_MULTIPLE", analyzer.thresholds.cost_multiple.unwrap_or(0.0));
+    analyzer.thresholds = analyzer.thresholds.with_cost_multiple(Some(cost_multiple))?;
+    analyzer.fees_config.balancer_fee = config::env_or("BALANCER_SWAP_FEE", analyzer.fees_config.balancer_fee);
+    analyzer.fees_config.solana = SolanaFees::from_env();
+    analyzer.fees_config.osmosis_fee = config::env_or("OSMOSIS_SWAP_FEE", analyzer.fees_config.osmosis_fee);
+    analyzer.fees_config.osmosis_tx_cost = config::env_or("OSMOSIS_TX_COST", analyzer.fees_config.osmosis_tx_cost);
+    analyzer.fees_config.ibc_transfer_cost = config::env_or("IBC_TRANSFER_COST", analyzer.fees_config.ibc_transfer_cost);
+    analyzer.fees_config.unknown_exchange_policy = config::env_or("UNKNOWN_EXCHANGE_POLICY", analyzer.fees_config.unknown_exchange_policy);
+    analyzer.fees_config.unknown_exchange_fee = config::env_or("UNKNOWN_EXCHANGE_FEE", analyzer.fees_config.unknown_exchange_fee);
+    analyzer.fees_config.fee_denominations.extend(config::env_map::<FeeDenomination>("FEE_DENOMINATIONS"));
+    analyzer.fees_config.venue_models = VenueCostModels::from_plugins();
+    analyzer.fees_config.holding = HoldingCosts::from_env();
+    precision::install(precision::PrecisionPolicy::from_env());
+    analyzer.sizing_config.max_usd_size = config::env_or("MAX_USD_SIZE", analyzer.sizing_config.max_usd_size);
+    analyzer.sizing_config.reference_price = config::env_or("SIZING_REFERENCE_PRICE", analyzer.sizing_config.reference_price);
+    analyzer.sizing_config.pair_caps.extend(config::env_map("PAIR_SIZE_CAPS"));
+    analyzer.sizing_config.exchange_caps.extend(config::env_map("EXCHANGE_SIZE_CAPS"));
+    analyzer.sizing_config.min_depth = config::env_or("MIN_DEPTH_USD", analyzer.sizing_config.min_depth);
+    analyzer.sizing_config.min_depth_bps = config::env_or("MIN_DEPTH_BPS", analyzer.sizing_config.min_depth_bps);
+    analyzer.sizing_config.ladder_steps = config::env_or("SIZE_LADDER_STEPS", analyzer.sizing_config.ladder_steps);
+    analyzer.sizing_config.route_min_depth = config::env_map("ROUTE_MIN_DEPTH_USD");
+    // Priority-fee strategies override the gas settings above for the chains they name
+    analyzer.gas = GasModel::from_env();
+    analyzer.gas.set_static_ethereum_gas_cost(analyzer.fees_config.ethereum_gas_cost);
+    analyzer.refresh_gas();
+    analyzer.gas_history = gas_regime::GasHistory::from_env(analyzer.gas.interval());
+    analyzer.gas_history.load(Utc::now());
+    // Derived from the active model once it is fully configured
+    analyzer.shadow_fees = ShadowFees::from_env(&analyzer.fees_config)?;
+    analyzer.withdrawal_fee_refresh = withdrawal_fees::FeeRefresh::from_env()?;
+    analyzer.executor_schema = executor_schema::SchemaHandshake::from_env();
+    analyzer.route_locks = route_locks::RouteLocks::from_env();
+    analyzer.canary = canary::CanaryVenues::from_env();
+    analyzer.onboarding = onboarding::PairOnboarding::from_env();
+    analyzer.route_overrides = overrides::RouteOverrides::from_env()?;
+    analyzer.pre_trade = pretrade::PreTradeChecks::from_env()?;
+    analyzer.enrichers = enrichment::Enrichers::from_env()?;
+    analyzer.atomic_routes = atomic::AtomicRoutes::from_env()?;
+    analyzer.slicing = slicing::Slicing::from_env()?;
+    analyzer.book_archive_config = book_archive::BookArchiveConfig::from_env()?;
+    analyzer.anomaly = anomaly::AnomalyScoring::from_env()?;
+    Ok(())
+}
+
+fn run_analyzer(output: OutputFormat, config_path: Option<&Path>, profile_pipeline: bool) -> Result<()> {
     
     info!("  Starting Arbitrage Spread Analyzer");
     info!("  Monitoring Redis for orderbook updates...");
     
     // Configuration: log the address we will actually use
-    let redis_addr = std::env::var("REDIS_ADDR").unwrap_or_else(|_| "127.0.0.1:6379".to_string());
+    let redis_addr = config::env_var("REDIS_ADDR").unwrap_or_else(|_| "127.0.0.1:6379".to_string());
     info!("  Connecting to Redis at: {}", redis_addr);
     
     // Create and configure the analyzer
     let mut analyzer = SpreadAnalyzer::new(&redis_addr)?;
     
-    // Optional: Customize fee configuration
-    analyzer.fees_config.use_market_orders = true; // Use taker fees for speed
-    analyzer.fees_config.binance_taker_fee = 0.1; // 0.1% for regular users
-    analyzer.fees_config.ethereum_gas_cost = 50.0; // Adjust based on current gas prices
+    configure_from_env(&mut analyzer, config_path)?;
+    // Credentials Redis rejects would only have the listeners retry forever
+    for source in &analyzer.sources {
+        source.check_auth()?;
+    }
+    analyzer.build_info = BuildInfo::current();
+    analyzer.reporter.format = output;
+    if profile_pipeline {
+        analyzer.pipeline_profile = Some((Duration::from_secs(config::env_or("PROFILE_PIPELINE_SECS", stage_timing::DEFAULT_PROFILE_SECS)), Instant::now()));
+    }
     
     info!("   Configuration:");
+    info!(
+        "   - Build: {} ({}), built {}, features [{}], config {}",
+        analyzer.build_info.version,
+        analyzer.build_info.git_sha,
+        analyzer.build_info.build_time,
+        analyzer.build_info.features.join(", "),
+        analyzer.build_info.short_config_hash()
+    );
+    if let Some(profile) = &analyzer.fees_config.profile {
+        info!("   - Account Profile: {} ({} venues)", profile.name, profile.venues.len());
+        for (exchange, account) in &profile.venues {
+            info!(
+                "     - {}: VIP {}, key {}, {:.0}% fee discount",
+                exchange,
+                account.vip_tier.map_or("-".to_string(), |tier| tier.to_string()),
+                account.api_key_env.as_deref().unwrap_or("-"),
+                account.fee_discount_pct
+            );
+        }
+    }
+    if !analyzer.strategy_tag.is_empty() {
+        info!(
+            "   - Tenant / Strategy: {} / {}",
+            analyzer.strategy_tag.tenant.as_deref().unwrap_or("-"),
+            analyzer.strategy_tag.strategy_id.as_deref().unwrap_or("-")
+        );
+    }
     info!("   - Execution Strategy: {}", if analyzer.fees_config.use_market_orders { "Market Orders (Taker)" } else { "Limit Orders (Maker)" });
     info!("   - Binance Fee: {:.3}%", 
           if analyzer.fees_config.use_market_orders { 
@@ -549,24 +2459,498 @@ fn main() -> Result<()> {
           } else { 
               analyzer.fees_config.binance_maker_fee 
           });
+    info!("   - OKX Fee: {:.3}%", analyzer.fees_config.trading_fee_pct("okx"));
+    info!("   - Bybit Fee: {:.3}%", analyzer.fees_config.trading_fee_pct("bybit"));
     info!("   - Uniswap Fee: {:.1}%", analyzer.fees_config.uniswap_fee);
-    info!("   - Min Profit: ${:.2}", MIN_ABSOLUTE_PROFIT);
-    info!("   - Min ROI: {:.1}%", MIN_ROI_PERCENTAGE);
-    
-    // Test Redis connection
-    match analyzer.redis_client.get_connection() {
-        Ok(_) => info!(" Redis connection successful"),
-        Err(e) => {
-            error!(" Failed to connect to Redis: {}", e);
-            error!(" Make sure Redis is running: redis-server");
-            return Err(e.into());
+    info!("   - SushiSwap Fee: {:.1}%", analyzer.fees_config.sushiswap_fee);
+    info!("   - Balancer Fee: {:.2}%", analyzer.fees_config.balancer_fee);
+    info!("   - Raydium / Orca Fee: {:.2}% / {:.2}%", analyzer.fees_config.raydium_fee, analyzer.fees_config.orca_fee);
+    analyzer.log_solana_config();
+    analyzer.anomaly.log_config();
+    if analyzer.multi_leg.enabled {
+        info!(
+            "   - Multi-leg Arbitrage: up to {} legs from {}{}, published on {}",
+            analyzer.multi_leg.max_legs,
+            analyzer.multi_leg.start_assets.join(", "),
+            if analyzer.multi_leg.cross_exchange { ", across venues" } else { "" },
+            analyzer.multi_leg.channel
+        );
+    }
+    analyzer.log_archive_config();
+    info!(
+        "   - Osmosis Fee: {:.2}% + ${:.2} per swap, ${:.2} per IBC transfer",
+        analyzer.fees_config.osmosis_fee, analyzer.fees_config.osmosis_tx_cost, analyzer.fees_config.ibc_transfer_cost
+    );
+    let plugged_in = analyzer.fees_config.venue_models.venues();
+    if !plugged_in.is_empty() {
+        info!("   - Plugged-in Venue Cost Models: {}", plugged_in.join(", "));
+    }
+    for holding in analyzer.fees_config.holding.describe() {
+        info!("   - Holding Cost: {}", holding);
+    }
+    match analyzer.fees_config.unknown_exchange_policy {
+        UnknownExchangePolicy::Reject => info!("   - Unknown Exchanges: rejected"),
+        UnknownExchangePolicy::DefaultFee => info!("   - Unknown Exchanges: {:.3}% default fee", analyzer.fees_config.unknown_exchange_fee),
+    }
+    if let Some(shadow) = &analyzer.shadow_fees {
+        info!("   - Shadow Fee Model: {} (evaluated, never acted on)", shadow.description);
+    }
+    for (pair, cap) in &analyzer.sizing_config.pair_caps {
+        info!("   - Size Cap {}: {}", pair, cap);
+    }
+    for (exchange, cap) in &analyzer.sizing_config.exchange_caps {
+        info!("   - Size Cap {}: {}", exchange, cap);
+    }
+    if analyzer.sizing_config.min_depth > 0.0 || !analyzer.sizing_config.route_min_depth.is_empty() {
+        info!(
+            "   - Min Depth: ${:.0} within {}bps ({} route overrides)",
+            analyzer.sizing_config.min_depth,
+            analyzer.sizing_config.min_depth_bps,
+            analyzer.sizing_config.route_min_depth.len()
+        );
+    }
+    for (chain, strategy) in analyzer.gas.strategies() {
+        info!("   - Priority fee {}: {}", chain.name(), strategy);
+    }
+    if analyzer.balances.enabled() {
+        for balance in analyzer.balances.report() {
+            info!("   - Balance {} {}: {}", balance.venue, balance.asset, balance.balance);
+        }
+    }
+    info!("   - Mode: {}", analyzer.mode);
+    info!(
+        "   - Console report: {}{}",
+        analyzer.reporter.policy,
+        if analyzer.reporter.format == OutputFormat::Jsonl { ", as JSON lines" } else { "" }
+    );
+    if analyzer.mode.publishes_opportunities() {
+        info!("   - Opportunities published on: {}", analyzer.opportunity_channel);
+        if let Some(stream) = &analyzer.streams.opportunities {
+            info!("   - Opportunities streamed to: {}", stream);
+        }
+        if let Some(window) = analyzer.publish_cooldown.window() {
+            info!("   - Repeats of a route's spread held back for {}ms", window.num_milliseconds());
+        }
+    }
+    info!("   - Enrichers: {}", analyzer.enrichers.names().join(", "));
+    if analyzer.mode.emits_execution_requests() {
+        info!("   - Execution requests published on: {}", analyzer.execution_channel);
+        if let Some(stream) = &analyzer.streams.executions {
+            info!("   - Execution requests streamed to: {}", stream);
         }
+        info!("   - Pre-trade checks: {}", analyzer.pre_trade.names().join(", "));
+        if let Some(executor) = analyzer.atomic_routes.executor() {
+            info!("   - Atomic DEX routes through executor {}", executor);
+        }
+        if let Some(planner) = analyzer.slicing.planner_name() {
+            info!("   - Large requests sliced by: {}", planner);
+        }
+    }
+    if analyzer.lag.enabled() {
+        info!("   - Laggard Opportunities: {}", analyzer.lag.policy);
+    }
+    info!("   - Book Decoder: {}", codec::DECODER);
+    match analyzer.thresholds.cost_multiple {
+        Some(k) => info!("   - Min Profit: {}× estimated fees", k),
+        None => info!("   - Min Profit: ${:.2}", analyzer.thresholds.min_profit),
+    }
+    info!("   - Min ROI: {:.1}%", analyzer.thresholds.min_roi_percentage);
+    for route_override in analyzer.route_overrides.entries() {
+        info!("   - Override {}: {}", route_override.key, route_override.describe());
     }
     
+    // Carry on the comprehensive-analysis cadence of the previous run
+    analyzer.restore_checkpoint();
+
+    // The API is a debugging aid; the analyzer keeps running without it
+    let api_addr = config::env_var("API_ADDR").unwrap_or_else(|_| DEFAULT_API_ADDR.to_string());
+    match api::spawn(&api_addr) {
+        Ok(rx) => analyzer.api_requests = Some(rx),
+        Err(e) => warn!(" API disabled: {}", e),
+    }
+    if let Some(grpc_addr) = grpc::addr_from_env() {
+        match grpc::GrpcServer::spawn(&grpc_addr, analyzer.metrics.clone()) {
+            Ok(server) => analyzer.grpc = Some(server),
+            Err(e) => warn!(" gRPC API disabled: {}", e),
+        }
+    }
+    match admin::AdminConsole::spawn(admin::socket_from_env().as_deref()) {
+        Ok(console) => analyzer.admin = console,
+        Err(e) => warn!(" Admin console disabled: {}", e),
+    }
+
     info!(" Analyzer ready! Waiting for orderbook updates...");
-    info!(" Supported exchanges: binance, uniswap-v3-exact");
+    info!(" Supported exchanges: {}", REGISTERED_EXCHANGES.join(", "));
     info!(" Press Ctrl+C to stop");
     
     // Run the main analysis loop
     analyzer.run()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyzer() -> SpreadAnalyzer {
+        SpreadAnalyzer::new("127.0.0.1:6379").expect("client construction does not connect")
+    }
+
+    fn book(exchange: &str, pair: &str, bids: Vec<Vec<f64>>, asks: Vec<Vec<f64>>) -> OrderBook {
+        OrderBook::for_test(exchange, pair, bids, asks)
+    }
+
+    fn assert_finite(opp: &ArbitrageOpportunity) {
+        assert!(numeric::all_finite(&[
+            opp.buy_price,
+            opp.sell_price,
+            opp.max_size,
+            opp.gross_profit_per_unit,
+            opp.estimated_fees,
+            opp.net_profit,
+            opp.roi_percentage,
+        ]));
+    }
+
+    // An execute-mode analyzer reserving routes on `server` as `instance`
+    fn locking_analyzer(server: &mini_redis::MiniRedis, instance: &str) -> SpreadAnalyzer {
+        let mut analyzer = analyzer();
+        analyzer.mode = Mode::Execute;
+        analyzer.route_locks = route_locks::RouteLocks::new(60_000, route_locks::DEFAULT_PREFIX, instance);
+        analyzer.route_locks.connect(server.client());
+        analyzer
+    }
+
+    #[test]
+    fn a_route_busy_with_our_own_request_is_not_a_lock_conflict() {
+        let server = mini_redis::MiniRedis::start();
+        let mut analyzer = locking_analyzer(&server, "a");
+        let opp = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0).unwrap();
+        analyzer.request_execution(&opp, None, Utc::now());
+        analyzer.request_execution(&opp, None, Utc::now());
+        assert_eq!(analyzer.lifecycle.in_flight().len(), 1);
+        assert_eq!(analyzer.metrics.execution_requests_suppressed.load(std::sync::atomic::Ordering::Relaxed), 1);
+        assert_eq!(analyzer.metrics.route_lock_conflicts.load(std::sync::atomic::Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn a_route_reserved_elsewhere_leaves_nothing_in_flight() {
+        let server = mini_redis::MiniRedis::start();
+        let mut other = route_locks::RouteLocks::new(60_000, route_locks::DEFAULT_PREFIX, "b");
+        other.connect(server.client());
+        assert!(other.acquire(&RouteKey::new("BTC/USDT", "binance", "okx"), "theirs").unwrap());
+
+        let mut analyzer = locking_analyzer(&server, "a");
+        let opp = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0).unwrap();
+        analyzer.request_execution(&opp, None, Utc::now());
+        assert_eq!(analyzer.metrics.route_lock_conflicts.load(std::sync::atomic::Ordering::Relaxed), 1);
+        assert!(analyzer.lifecycle.in_flight().is_empty());
+        assert_eq!(analyzer.lifecycle.recent().count(), 0);
+    }
+
+    #[test]
+    fn evaluate_opportunity_rejects_poisoned_inputs() {
+        let analyzer = analyzer();
+        let poison = [f64::NAN, f64::INFINITY, f64::NEG_INFINITY];
+
+        for p in poison {
+            assert!(analyzer.evaluate_opportunity("binance", "kraken", "BTC/USDT", p, 51000.0, 1.0, 1.0).is_none());
+            assert!(analyzer.evaluate_opportunity("binance", "kraken", "BTC/USDT", 50000.0, p, 1.0, 1.0).is_none());
+            assert!(analyzer.evaluate_opportunity("binance", "kraken", "BTC/USDT", 50000.0, 51000.0, p, 1.0).is_none());
+            assert!(analyzer.evaluate_opportunity("binance", "kraken", "BTC/USDT", 50000.0, 51000.0, 1.0, p).is_none());
+        }
+    }
+
+    #[test]
+    fn evaluate_opportunity_rejects_zero_buy_price() {
+        let analyzer = analyzer();
+        assert!(analyzer.evaluate_opportunity("binance", "kraken", "BTC/USDT", 0.0, 51000.0, 1.0, 1.0).is_none());
+    }
+
+    #[test]
+    fn evaluate_opportunity_still_finds_clean_spreads() {
+        let analyzer = analyzer();
+        let opp = analyzer
+            .evaluate_opportunity("binance", "kraken", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0)
+            .expect("2% spread is profitable");
+        assert_finite(&opp);
+    }
+
+    #[test]
+    fn unknown_exchange_policy_controls_unregistered_venues() {
+        let mut analyzer = analyzer();
+        assert!(analyzer.evaluate_opportunity("binance", "kraken", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0).is_some());
+        assert_eq!(analyzer.metrics.unknown_exchange_evaluations.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        analyzer.fees_config.unknown_exchange_policy = UnknownExchangePolicy::Reject;
+        assert!(analyzer.evaluate_opportunity("binance", "kraken", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0).is_none());
+        assert!(analyzer.evaluate_opportunity("binance", "uniswap-v3-exact", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0).is_some());
+        assert_eq!(analyzer.metrics.unknown_exchange_rejections.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn okx_and_bybit_have_fee_schedules() {
+        let mut analyzer = analyzer();
+        analyzer.fees_config.unknown_exchange_policy = UnknownExchangePolicy::Reject;
+        assert!(analyzer.evaluate_opportunity("okx", "bybit", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0).is_some());
+        assert_eq!(analyzer.fees_config.trading_fee_pct("okx"), 0.1);
+
+        analyzer.fees_config.use_market_orders = false;
+        assert_eq!(analyzer.fees_config.trading_fee_pct("okx"), 0.08);
+        assert_eq!(analyzer.fees_config.fee_denomination("bybit"), FeeDenomination::ReceivedAsset);
+    }
+
+    #[test]
+    fn amm_venues_pay_swap_fee_and_gas() {
+        let mut analyzer = analyzer();
+        analyzer.fees_config.unknown_exchange_policy = UnknownExchangePolicy::Reject;
+        analyzer.fees_config.balancer_fee = 0.25;
+        assert!(analyzer.evaluate_opportunity("sushiswap", "balancer", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0).is_some());
+        assert_eq!(analyzer.fees_config.trading_fee_pct("sushiswap"), 0.3);
+        assert_eq!(analyzer.fees_config.trading_fee_pct("balancer"), 0.25);
+        assert_eq!(analyzer.fees_config.fixed_leg_cost("balancer"), analyzer.fees_config.ethereum_gas_cost);
+    }
+
+    #[test]
+    fn ibc_transfer_is_charged_on_cross_chain_routes_only() {
+        let mut analyzer = analyzer();
+        analyzer.fees_config.withdrawal_fees.clear();
+        analyzer.fees_config.fee_denominations.clear();
+        analyzer.fees_config.ibc_transfer_cost = 1.0;
+        let cross_chain = analyzer.estimate_fees_and_gas(10.0, 10.0, 11.0, "osmosis", "binance", "ATOM/USDT");
+        // 0.2% of 100 on osmosis, its tx cost, the IBC hop, 0.1% of 110 on binance
+        assert!((cross_chain.total - (0.2 + 0.01 + 1.0 + 0.11)).abs() < 1e-9);
+        assert_eq!(analyzer.fees_config.transfer_cost("binance", "okx"), 0.0);
+        assert_eq!(analyzer.fees_config.transfer_cost("osmosis", "osmosis"), 0.0);
+    }
+
+    #[test]
+    fn account_profile_drives_fees_and_withdrawals() {
+        let mut analyzer = analyzer();
+        let mut venues = HashMap::new();
+        venues.insert(
+            "binance".to_string(),
+            profiles::VenueAccount { taker_fee: Some(0.05), withdrawal_whitelist: Some(vec!["USDT".to_string()]), ..Default::default() },
+        );
+        analyzer.fees_config.profile = Some(AccountProfile { name: "vip".to_string(), venues, tag: StrategyTag::default() });
+        assert_eq!(analyzer.fees_config.trading_fee_pct("binance"), 0.05);
+        assert_eq!(analyzer.fees_config.trading_fee_pct("okx"), 0.1);
+        // BTC cannot leave binance under this profile, so binance can only be the sell leg
+        assert!(analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0).is_none());
+        assert!(analyzer.evaluate_opportunity("okx", "binance", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0).is_some());
+    }
+
+    #[test]
+    fn opportunities_carry_the_strategy_tag() {
+        let mut analyzer = analyzer();
+        let untagged = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0).unwrap();
+        assert!(serde_json::to_value(&untagged).unwrap().get("tenant").is_none());
+
+        analyzer.strategy_tag = StrategyTag { tenant: Some("desk-a".to_string()), strategy_id: None };
+        let tagged = serde_json::to_value(analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0).unwrap()).unwrap();
+        assert_eq!(tagged["tenant"], "desk-a");
+        assert!(tagged.get("strategy_id").is_none());
+        let parsed: ArbitrageOpportunity = serde_json::from_value(tagged).unwrap();
+        assert_eq!(parsed.tag.tenant.as_deref(), Some("desk-a"));
+    }
+
+    #[test]
+    fn roi_is_measured_against_capital_at_risk() {
+        let mut analyzer = analyzer();
+        let transfer = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0).unwrap();
+        let capital = transfer.capital_at_risk.unwrap();
+        assert!(!capital.prefunded);
+        assert!((transfer.roi_percentage - transfer.net_profit / capital.amount * 100.0).abs() < 1e-9);
+
+        // Pre-funded on both venues: both legs are committed, so the same trade earns half the ROI
+        let prefunded = profiles::VenueAccount { prefunded: true, ..Default::default() };
+        let venues = HashMap::from([("binance".to_string(), prefunded.clone()), ("okx".to_string(), prefunded)]);
+        analyzer.fees_config.profile = Some(AccountProfile { name: "inventory".to_string(), venues, tag: StrategyTag::default() });
+        let funded = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50000.0, 51000.0, 1.0, 1.0).unwrap();
+        let funded_capital = funded.capital_at_risk.unwrap();
+        assert!(funded_capital.prefunded && funded_capital.amount > capital.amount * 2.0);
+        assert!(funded.roi_percentage < transfer.roi_percentage / 2.0);
+        assert!(funded.annualized_roi_percentage.unwrap() < transfer.annualized_roi_percentage.unwrap());
+    }
+
+    #[test]
+    fn in_kind_buy_fees_shrink_the_sell_leg() {
+        let mut analyzer = analyzer();
+        analyzer.fees_config.withdrawal_fees.clear();
+
+        // Binance takes its 0.1% out of the BTC we buy
+        let in_kind = analyzer.estimate_fees_and_gas(1.0, 50000.0, 51000.0, "binance", "uniswap-v3-exact", "BTC/USDT");
+        assert!((in_kind.sell_size - 0.999).abs() < 1e-12);
+
+        // Paid in quote, the full size reaches the sell leg and the fee is charged on buy notional
+        analyzer.fees_config.fee_denominations.insert("binance".to_string(), FeeDenomination::Quote);
+        let quote = analyzer.estimate_fees_and_gas(1.0, 50000.0, 51000.0, "binance", "uniswap-v3-exact", "BTC/USDT");
+        assert_eq!(quote.sell_size, 1.0);
+
+        let gas = analyzer.fees_config.ethereum_gas_cost;
+        assert!((quote.total - (50.0 + 51000.0 * 0.003 + gas)).abs() < 1e-6);
+        assert!((in_kind.total - (51.0 + 0.999 * 51000.0 * 0.003 + gas)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn withdrawal_fee_is_deducted_in_base() {
+        let analyzer = analyzer();
+        let estimate = analyzer.estimate_fees_and_gas(0.8, 50000.0, 51000.0, "uniswap-v3-exact", "kraken", "BTC/USDT");
+        assert!((estimate.sell_size - (0.8 - 0.0005)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn size_caps_apply_per_pair_and_per_venue() {
+        let mut analyzer = analyzer();
+        // Uncapped: 80% of top-of-book, limited by the $100k notional cap (2 BTC at $50k)
+        assert_eq!(analyzer.choose_execution_size(10.0, 10.0, "BTC/USDT", "binance", "kraken", 50_000.0), 2.0);
+        // The notional cap follows the pair's own price: $100k is 40 ETH at $2,500, so depth limits instead
+        assert_eq!(analyzer.choose_execution_size(10.0, 10.0, "ETH/USDT", "binance", "kraken", 2_500.0), 8.0);
+        assert_eq!(analyzer.choose_execution_size(100.0, 100.0, "ETH/USDT", "binance", "kraken", 2_500.0), 40.0);
+
+        analyzer.sizing_config.pair_caps.insert("BTC/USDT".to_string(), 0.5);
+        analyzer.sizing_config.exchange_caps.insert("kraken".to_string(), 0.25);
+        assert_eq!(analyzer.choose_execution_size(10.0, 10.0, "BTC/USDT", "binance", "uniswap-v3-exact", 50_000.0), 0.5);
+        assert_eq!(analyzer.choose_execution_size(10.0, 10.0, "BTC/USDT", "kraken", "binance", 50_000.0), 0.25);
+        assert_eq!(analyzer.choose_execution_size(100.0, 100.0, "ETH/USDT", "binance", "uniswap-v3-exact", 2_500.0), 40.0);
+    }
+
+    #[test]
+    fn min_depth_filters_routes_that_only_fit_dust() {
+        let mut analyzer = analyzer();
+        // 1 BTC at the top of each leg, plus 5 more on the sell side 20bps below
+        analyzer.books.insert("binance:BTC/USDT".to_string(), book("binance", "BTC/USDT", vec![vec![49990.0, 1.0]], vec![vec![50000.0, 1.0]]));
+        analyzer.books.insert(
+            "okx:BTC/USDT".to_string(),
+            book("okx", "BTC/USDT", vec![vec![50800.0, 1.0], vec![50700.0, 5.0]], vec![vec![50810.0, 1.0]]),
+        );
+        assert_eq!(analyzer.analyze_all_spreads().unwrap().len(), 1);
+
+        analyzer.sizing_config.min_depth = 40_000.0;
+        assert_eq!(analyzer.analyze_all_spreads().unwrap().len(), 1);
+        analyzer.sizing_config.min_depth = 60_000.0;
+        assert!(analyzer.analyze_all_spreads().unwrap().is_empty());
+
+        // Route overrides win over pair overrides, which win over the default
+        analyzer.sizing_config.route_min_depth.insert("BTC/USDT".to_string(), 100.0);
+        assert_eq!(analyzer.analyze_all_spreads().unwrap().len(), 1);
+        analyzer.sizing_config.route_min_depth.insert("BTC/USDT:binance>okx".to_string(), 60_000.0);
+        assert!(analyzer.analyze_all_spreads().unwrap().is_empty());
+        // Widening the band to 30bps brings the deeper okx bid in; binance still only has 1 BTC
+        analyzer.sizing_config.min_depth_bps = 30.0;
+        assert!(analyzer.analyze_all_spreads().unwrap().is_empty());
+        assert!(analyzer.books["okx:BTC/USDT"].bid_depth_within(30.0) > 300_000.0);
+    }
+
+    #[test]
+    fn suspect_venues_are_excluded_from_analysis() {
+        let mut analyzer = analyzer();
+        analyzer.books.insert(
+            "binance:BTC/USDT".to_string(),
+            book("binance", "BTC/USDT", vec![vec![49990.0, 1.0]], vec![vec![50000.0, 1.0]]),
+        );
+        analyzer.books.insert(
+            "uniswap-v3-exact:BTC/USDT".to_string(),
+            book("uniswap-v3-exact", "BTC/USDT", vec![vec![52000.0, 1.0]], vec![vec![52010.0, 1.0]]),
+        );
+        // Only one direction is evaluated per book pair, so make both directions profitable-looking
+        analyzer.books.insert(
+            "binance2:BTC/USDT".to_string(),
+            book("binance", "BTC/USDT", vec![vec![53000.0, 1.0]], vec![vec![49000.0, 1.0]]),
+        );
+
+        let start = Utc::now() - chrono::Duration::seconds(120);
+        analyzer.watchdog.heartbeat("binance", start);
+        analyzer.watchdog.heartbeat("uniswap-v3-exact", Utc::now());
+        assert!(!analyzer.analyze_all_spreads().unwrap().is_empty());
+
+        analyzer.watchdog.check(Utc::now());
+        assert!(analyzer.analyze_all_spreads().unwrap().is_empty());
+    }
+
+    #[test]
+    fn analyze_all_spreads_survives_poisoned_books() {
+        let mut analyzer = analyzer();
+        let poison = [f64::NAN, f64::INFINITY, f64::NEG_INFINITY];
+
+        for (idx, p) in poison.iter().enumerate() {
+            analyzer.books.insert(
+                format!("poison{}:BTC/USDT", idx),
+                book(&format!("poison{}", idx), "BTC/USDT", vec![vec![*p, 1.0]], vec![vec![*p, *p]]),
+            );
+        }
+        // Malformed levels must not be indexed into
+        analyzer.books.insert("short:BTC/USDT".to_string(), book("short", "BTC/USDT", vec![vec![50000.0]], vec![vec![]]));
+        analyzer.books.insert(
+            "binance:BTC/USDT".to_string(),
+            book("binance", "BTC/USDT", vec![vec![49990.0, 1.0]], vec![vec![50000.0, 1.0]]),
+        );
+        analyzer.books.insert(
+            "kraken:BTC/USDT".to_string(),
+            book("kraken", "BTC/USDT", vec![vec![51000.0, 1.0]], vec![vec![51010.0, 1.0]]),
+        );
+
+        let opportunities = analyzer.analyze_all_spreads().expect("analysis never fails on bad data");
+        assert!(!opportunities.is_empty());
+        for opp in &opportunities {
+            assert_finite(opp);
+            assert!(!opp.buy_exchange.starts_with("poison") && !opp.sell_exchange.starts_with("poison"));
+        }
+    }
+
+    #[test]
+    fn non_finite_books_fail_validation() {
+        let poisoned = book("binance", "BTC/USDT", vec![vec![f64::NAN, 1.0]], vec![vec![50000.0, 1.0]]);
+        assert!(poisoned.has_non_finite_values());
+        assert!(!poisoned.validation_issues().is_empty());
+
+        let clean = book("binance", "BTC/USDT", vec![vec![49990.0, 1.0]], vec![vec![50000.0, 1.0]]);
+        assert!(!clean.has_non_finite_values());
+        assert!(clean.validation_issues().is_empty());
+    }
+
+    #[test]
+    fn books_flow_from_redis_to_published_opportunities() {
+        use redis::Commands;
+        use serde_json::json;
+
+        let redis = mini_redis::MiniRedis::start();
+        let mut analyzer = analyzer();
+        analyzer.sources = vec![RedisSource {
+            name: sources::DEFAULT_SOURCE.to_string(),
+            addr: redis.addr.clone(),
+            client: redis.client(),
+            user: None,
+            env_prefix: "REDIS_".to_string(),
+            subscription: subscription::SubscriptionConfig::default(),
+            key_pattern: None,
+        }];
+        analyzer.mode = Mode::Signal;
+
+        let mut listener = redis.client().get_connection().unwrap();
+        let mut signals = listener.as_pubsub();
+        signals.subscribe(&analyzer.opportunity_channel).unwrap();
+        signals.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let queue = analyzer.start_pipeline().unwrap();
+        redis.wait_for_subscribers(subscription::DEFAULT_CHANNEL, 1, Duration::from_secs(5)).unwrap();
+        // The ingestor owns the sources now; /healthz still counts them
+        assert!(analyzer.sources.is_empty());
+        assert_eq!(analyzer.metrics.redis_sources_configured.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        // What the Go collector does: write the book with a TTL, then announce its key
+        let mut collector = redis.client().get_connection().unwrap();
+        for (exchange, bid, ask) in [("binance", 49990.0, 50000.0), ("okx", 50800.0, 50810.0)] {
+            let key = format!("orderbook:{}:BTC/USDT", exchange);
+            let book = json!({ "exchange": exchange, "pair": "BTC/USDT", "bids": [[bid, 1.0]], "asks": [[ask, 1.0]], "timestamp": 1 });
+            let _: () = collector.set_ex(&key, book.to_string(), 30).unwrap();
+            let _: i64 = collector.publish(subscription::DEFAULT_CHANNEL, json!({ "key": key }).to_string()).unwrap();
+
+            let Pop::Event(event) = queue.pop_timeout(Duration::from_secs(5)) else { panic!("no update for {}", key) };
+            analyzer.process_event(event, Utc::now()).unwrap();
+        }
+
+        let published: ArbitrageOpportunity = serde_json::from_str(&signals.get_message().unwrap().get_payload::<String>().unwrap()).unwrap();
+        assert_eq!((published.buy_exchange.as_str(), published.sell_exchange.as_str()), ("binance", "okx"));
+        assert_eq!(published.pair, "BTC/USDT");
+    }
+}
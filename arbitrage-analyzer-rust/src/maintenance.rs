@@ -0,0 +1,273 @@
+// Venue maintenance: exchange system-status feeds and the health of the chains
+// DEX venues settle on, polled every MAINTENANCE_POLL_SECS. A venue under
+// maintenance, or on a chain whose RPC is unhealthy, is quarantined: routes that
+// touch it are skipped until the status clears. The poller only records status;
+// the analysis loop turns changes into venue_quarantined / venue_resumed events.
+//
+// Feeds:
+//  - MAINTENANCE_BINANCE_STATUS=true: Binance `/sapi/v1/system/status`,
+//  - CHAIN_RPC_URLS=ethereum=<url>,solana=<url>,osmosis=<url>: `eth_syncing`,
+//    `getHealth` and the Tendermint `/status` catching-up flag respectively.
+// A failed exchange status request leaves the venue as it was, since books still
+// flow; an unreachable RPC quarantines its venues, since nothing can settle there.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use serde_json::{json, Value};
+
+use crate::alerts::Severity;
+use crate::config;
+use crate::events::{Event, EventClass};
+use crate::SpreadAnalyzer;
+
+const DEFAULT_POLL_SECS: u64 = 60;
+const DEFAULT_BINANCE_REST_URL: &str = "https://api.binance.com";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Chain {
+    Ethereum,
+    Solana,
+    Osmosis,
+}
+
+impl Chain {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "ethereum" => Some(Chain::Ethereum),
+            "solana" => Some(Chain::Solana),
+            "osmosis" => Some(Chain::Osmosis),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Chain::Ethereum => "ethereum",
+            Chain::Solana => "solana",
+            Chain::Osmosis => "osmosis",
+        }
+    }
+
+    /// Venues that settle on this chain
+    pub fn venues(self) -> &'static [&'static str] {
+        match self {
+            Chain::Ethereum => &["uniswap-v3-exact", "sushiswap", "balancer"],
+            Chain::Solana => &["raydium", "orca"],
+            Chain::Osmosis => &["osmosis"],
+        }
+    }
+
+    /// The chain `venue` settles on; None for centralized exchanges
+    pub fn of_venue(venue: &str) -> Option<Self> {
+        [Chain::Ethereum, Chain::Solana, Chain::Osmosis].into_iter().find(|chain| chain.venues().contains(&venue))
+    }
+}
+
+/// CHAIN_RPC_URLS, `chain=url` entries since URLs contain ':'
+pub fn chain_rpc_urls() -> Vec<(Chain, String)> {
+    let mut chains = Vec::new();
+    for entry in config::env_var("CHAIN_RPC_URLS").unwrap_or_default().split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.split_once('=').and_then(|(chain, url)| Some((Chain::parse(chain)?, url.trim().to_string()))) {
+            Some(parsed) => chains.push(parsed),
+            None => warn!("Ignoring invalid entry in CHAIN_RPC_URLS: {:?}", entry),
+        }
+    }
+    chains
+}
+
+// ---------- Feed parsers. Ok(None) is healthy, Ok(Some(reason)) is under maintenance ----------
+
+/// Binance `GET /sapi/v1/system/status`: `{"status": 0, "msg": "normal"}`, 1 is maintenance
+pub fn parse_binance_system_status(raw: &str) -> Result<Option<String>> {
+    let status: Value = serde_json::from_str(raw)?;
+    match status.get("status").and_then(Value::as_i64) {
+        Some(0) => Ok(None),
+        Some(_) => Ok(Some(format!("binance system status: {}", status.get("msg").and_then(Value::as_str).unwrap_or("maintenance")))),
+        None => Err(anyhow!("unexpected system status response: {}", raw)),
+    }
+}
+
+/// JSON-RPC health response for `chain`: `eth_syncing` / `getHealth`, or a Tendermint `/status`
+pub fn parse_chain_health(chain: Chain, raw: &str) -> Result<Option<String>> {
+    let response: Value = serde_json::from_str(raw)?;
+    if let Some(error) = response.get("error") {
+        let message = error.get("message").and_then(Value::as_str).map_or_else(|| error.to_string(), str::to_string);
+        return Ok(Some(format!("{:?} RPC unhealthy: {}", chain, message)));
+    }
+    let result = response.get("result").ok_or_else(|| anyhow!("no result in RPC response"))?;
+    let healthy = match chain {
+        // `false` when synced, a progress object while syncing
+        Chain::Ethereum => result == &Value::Bool(false),
+        Chain::Solana => result.as_str() == Some("ok"),
+        Chain::Osmosis => result.pointer("/sync_info/catching_up") == Some(&Value::Bool(false)),
+    };
+    Ok((!healthy).then(|| format!("{:?} RPC node is not synced", chain)))
+}
+
+// ---------- Polling ----------
+
+#[derive(Debug, Clone)]
+pub struct MaintenanceConfig {
+    binance_url: Option<String>,
+    chains: Vec<(Chain, String)>,
+    interval: Duration,
+}
+
+impl MaintenanceConfig {
+    /// None when no feed is configured
+    pub fn from_env() -> Option<Self> {
+        let binance_url = config::env_or("MAINTENANCE_BINANCE_STATUS", false)
+            .then(|| config::env_var("BINANCE_REST_URL").unwrap_or_else(|_| DEFAULT_BINANCE_REST_URL.to_string()));
+        let chains = chain_rpc_urls();
+        if binance_url.is_none() && chains.is_empty() {
+            return None;
+        }
+        Some(MaintenanceConfig {
+            binance_url,
+            chains,
+            interval: Duration::from_secs(config::env_or("MAINTENANCE_POLL_SECS", DEFAULT_POLL_SECS)),
+        })
+    }
+
+    fn check_chain(chain: Chain, url: &str) -> Result<Option<String>> {
+        let response = match chain {
+            Chain::Ethereum => ureq::post(url)
+                .timeout(REQUEST_TIMEOUT)
+                .send_json(json!({"jsonrpc": "2.0", "id": 1, "method": "eth_syncing", "params": []})),
+            // getHealth answers with a JSON-RPC error while the node is behind, under a non-2xx status
+            Chain::Solana => {
+                match ureq::post(url).timeout(REQUEST_TIMEOUT).send_json(json!({"jsonrpc": "2.0", "id": 1, "method": "getHealth"})) {
+                    Err(ureq::Error::Status(_, response)) => Ok(response),
+                    other => other,
+                }
+            }
+            Chain::Osmosis => ureq::get(&format!("{}/status", url.trim_end_matches('/'))).timeout(REQUEST_TIMEOUT).call(),
+        };
+        match response {
+            Ok(response) => parse_chain_health(chain, &response.into_string()?),
+            Err(e) => Ok(Some(format!("{:?} RPC unreachable: {}", chain, e))),
+        }
+    }
+}
+
+/// Current maintenance reason per venue, written by the poller
+#[derive(Debug, Default)]
+pub struct MaintenanceBoard {
+    venues: Mutex<HashMap<String, String>>,
+}
+
+impl MaintenanceBoard {
+    pub fn set(&self, venue: &str, reason: Option<String>) {
+        let mut venues = self.venues.lock().unwrap_or_else(|e| e.into_inner());
+        match reason {
+            Some(reason) => venues.insert(venue.to_string(), reason),
+            None => venues.remove(venue),
+        };
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, String> {
+        self.venues.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+pub fn spawn(config: MaintenanceConfig, board: Arc<MaintenanceBoard>) {
+    info!(
+        "  Watching maintenance feeds every {}s: {}",
+        config.interval.as_secs(),
+        config
+            .binance_url
+            .iter()
+            .map(|_| "binance status".to_string())
+            .chain(config.chains.iter().map(|(chain, _)| format!("{:?} RPC", chain)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    thread::spawn(move || loop {
+        if let Some(url) = &config.binance_url {
+            let status = ureq::get(&format!("{}/sapi/v1/system/status", url))
+                .timeout(REQUEST_TIMEOUT)
+                .call()
+                .map_err(anyhow::Error::from)
+                .and_then(|response| parse_binance_system_status(&response.into_string()?));
+            match status {
+                Ok(reason) => board.set("binance", reason),
+                Err(e) => warn!("Binance system status check failed: {}", e),
+            }
+        }
+        for (chain, url) in &config.chains {
+            match MaintenanceConfig::check_chain(*chain, url) {
+                Ok(reason) => {
+                    for venue in chain.venues() {
+                        board.set(venue, reason.clone());
+                    }
+                }
+                Err(e) => warn!("{:?} RPC health check failed: {}", chain, e),
+            }
+        }
+        thread::sleep(config.interval);
+    });
+}
+
+impl SpreadAnalyzer {
+    /// Apply the poller's latest view, publishing an event for every venue entering or leaving quarantine
+    pub(crate) fn sync_maintenance(&mut self) {
+        let current = self.maintenance.snapshot();
+        for (venue, reason) in &current {
+            if !self.quarantined.contains_key(venue) {
+                warn!("Quarantining {}: {}", venue, reason);
+                self.publish(
+                    Event::new(EventClass::VenueQuarantined, Severity::Warning, format!("{} quarantined: {}", venue, reason)).with_venue(venue),
+                );
+            }
+        }
+        for venue in self.quarantined.keys() {
+            if !current.contains_key(venue) {
+                info!("{} is out of maintenance", venue);
+                self.publish(
+                    Event::new(EventClass::VenueResumed, Severity::Info, format!("{} is out of maintenance, resuming", venue)).with_venue(venue),
+                );
+            }
+        }
+        self.quarantined = current;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventQuery;
+
+    #[test]
+    fn parses_status_feeds() {
+        assert_eq!(parse_binance_system_status(r#"{"status": 0, "msg": "normal"}"#).unwrap(), None);
+        assert!(parse_binance_system_status(r#"{"status": 1, "msg": "system_maintenance"}"#).unwrap().unwrap().contains("system_maintenance"));
+        assert!(parse_binance_system_status(r#"{"code": -1}"#).is_err());
+
+        assert_eq!(parse_chain_health(Chain::Ethereum, r#"{"jsonrpc":"2.0","id":1,"result":false}"#).unwrap(), None);
+        assert!(parse_chain_health(Chain::Ethereum, r#"{"jsonrpc":"2.0","id":1,"result":{"currentBlock":"0x1"}}"#).unwrap().is_some());
+        assert_eq!(parse_chain_health(Chain::Solana, r#"{"jsonrpc":"2.0","id":1,"result":"ok"}"#).unwrap(), None);
+        let behind = r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32005,"message":"Node is behind by 42 slots"}}"#;
+        assert!(parse_chain_health(Chain::Solana, behind).unwrap().unwrap().contains("behind by 42 slots"));
+        assert!(parse_chain_health(Chain::Osmosis, r#"{"result":{"sync_info":{"catching_up":true}}}"#).unwrap().is_some());
+    }
+
+    #[test]
+    fn quarantine_follows_the_board() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.maintenance.set("binance", Some("system_maintenance".to_string()));
+        analyzer.sync_maintenance();
+        assert!(analyzer.quarantined.contains_key("binance"));
+        assert_eq!(analyzer.events.recent(&EventQuery::default()).first().map(|e| e.class), Some(EventClass::VenueQuarantined));
+
+        analyzer.maintenance.set("binance", None);
+        analyzer.sync_maintenance();
+        assert!(analyzer.quarantined.is_empty());
+        assert_eq!(analyzer.events.recent(&EventQuery::default()).first().map(|e| e.class), Some(EventClass::VenueResumed));
+    }
+}
@@ -0,0 +1,200 @@
+// Market summary history. Every MARKET_HISTORY_SECS the coverage the market
+// summary prints (venues, the books and pairs each one has, how many of them are
+// stale, feed rate and staleness) is recorded with the opportunity history, so a
+// venue that silently drops out shows in `GET /history/market-summaries` long
+// after the summary that would have shown it scrolled past. Needs the Postgres
+// history (`--features postgres`, DATABASE_URL); MARKET_HISTORY_SECS=0 turns it off.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::history::{HistoryRecord, OpportunityFilter};
+use crate::{config, SpreadAnalyzer, STALE_BOOK_AGE_MS};
+
+const DEFAULT_MARKET_HISTORY_SECS: i64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VenueCoverage {
+    pub exchange: String,
+    pub books: usize,
+    pub pairs: usize,
+    // Books older than STALE_BOOK_AGE_MS, or never stamped on receipt
+    pub stale_books: usize,
+    pub updates_per_minute: f64,
+    pub last_update_age_ms: Option<i64>,
+    pub rejection_rate: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MarketSnapshot {
+    pub recorded_at: DateTime<Utc>,
+    pub books: usize,
+    pub pairs: usize,
+    pub cross_listed_pairs: usize,
+    // By exchange; venues that only have feed stats left are listed with no books
+    pub venues: Vec<VenueCoverage>,
+}
+
+/// Filters for `GET /history/market-summaries`, newest first
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketHistoryFilter {
+    // Only this venue's coverage in each summary
+    pub exchange: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl MarketHistoryFilter {
+    /// Time range and paging as for the opportunity history
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Self> {
+        let base = OpportunityFilter::from_query(query)?;
+        Ok(MarketHistoryFilter {
+            exchange: query.get("exchange").cloned(),
+            from: base.from,
+            to: base.to,
+            limit: base.limit,
+            offset: base.offset,
+        })
+    }
+}
+
+/// Venues with books in the summary before each one (in time) and none in it,
+/// keyed by the summary's time. `summaries` can come in any order
+pub fn lost_venues(summaries: &[MarketSnapshot]) -> BTreeMap<DateTime<Utc>, Vec<String>> {
+    let mut ordered: Vec<&MarketSnapshot> = summaries.iter().collect();
+    ordered.sort_by_key(|summary| summary.recorded_at);
+    let covered = |summary: &MarketSnapshot| -> BTreeSet<String> {
+        summary.venues.iter().filter(|venue| venue.books > 0).map(|venue| venue.exchange.clone()).collect()
+    };
+    let mut lost = BTreeMap::new();
+    for pair in ordered.windows(2) {
+        let now_covered = covered(pair[1]);
+        let gone: Vec<String> = covered(pair[0]).difference(&now_covered).cloned().collect();
+        if !gone.is_empty() {
+            lost.insert(pair[1].recorded_at, gone);
+        }
+    }
+    lost
+}
+
+#[derive(Debug)]
+pub struct MarketHistory {
+    // None: not recorded
+    every: Option<Duration>,
+    last_recorded: Option<DateTime<Utc>>,
+}
+
+impl MarketHistory {
+    pub fn from_env() -> Self {
+        let secs: i64 = config::env_or("MARKET_HISTORY_SECS", DEFAULT_MARKET_HISTORY_SECS);
+        MarketHistory { every: (secs > 0).then(|| Duration::seconds(secs)), last_recorded: None }
+    }
+
+    fn take_due(&mut self, now: DateTime<Utc>) -> bool {
+        let Some(every) = self.every else { return false };
+        if self.last_recorded.is_some_and(|last| now - last < every) {
+            return false;
+        }
+        self.last_recorded = Some(now);
+        true
+    }
+}
+
+impl SpreadAnalyzer {
+    /// The coverage the market summary shows, at `now`
+    pub fn market_snapshot(&self, now: DateTime<Utc>) -> MarketSnapshot {
+        let grouped = self.group_books_by_pair();
+        let mut venues: BTreeMap<String, (usize, BTreeSet<&str>, usize)> = BTreeMap::new();
+        for book in self.books.values() {
+            let (books, pairs, stale) = venues.entry(book.exchange.clone()).or_default();
+            *books += 1;
+            pairs.insert(book.pair.as_str());
+            if book.age_ms(now).is_none_or(|age| age > STALE_BOOK_AGE_MS) {
+                *stale += 1;
+            }
+        }
+        let feeds = self.ingest_stats.summaries(now);
+        for feed in &feeds {
+            venues.entry(feed.exchange.clone()).or_default();
+        }
+        MarketSnapshot {
+            recorded_at: now,
+            books: self.books.len(),
+            pairs: grouped.len(),
+            cross_listed_pairs: grouped.values().filter(|books| books.len() > 1).count(),
+            venues: venues
+                .into_iter()
+                .map(|(exchange, (books, pairs, stale_books))| {
+                    let feed = feeds.iter().find(|feed| feed.exchange == exchange);
+                    VenueCoverage {
+                        books,
+                        pairs: pairs.len(),
+                        stale_books,
+                        updates_per_minute: feed.map_or(0.0, |f| f.updates_per_minute),
+                        last_update_age_ms: feed.and_then(|f| f.last_update_age_ms),
+                        rejection_rate: feed.map_or(0.0, |f| f.rejection_rate),
+                        exchange,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Called from housekeeping: record the market summary every MARKET_HISTORY_SECS
+    pub(crate) fn record_market_history(&mut self, now: DateTime<Utc>) {
+        if !self.history.enabled() || !self.market_history.take_due(now) {
+            return;
+        }
+        self.history.record(HistoryRecord::MarketSummary(Box::new(self.market_snapshot(now))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn snapshot(minute: u32, venues: &[(&str, usize)]) -> MarketSnapshot {
+        MarketSnapshot {
+            recorded_at: Utc.with_ymd_and_hms(2024, 1, 31, 12, minute, 0).unwrap(),
+            books: venues.iter().map(|(_, books)| books).sum(),
+            pairs: 0,
+            cross_listed_pairs: 0,
+            venues: venues
+                .iter()
+                .map(|(exchange, books)| VenueCoverage {
+                    exchange: exchange.to_string(),
+                    books: *books,
+                    pairs: *books,
+                    stale_books: 0,
+                    updates_per_minute: 0.0,
+                    last_update_age_ms: None,
+                    rejection_rate: 0.0,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn venues_that_drop_out_are_flagged() {
+        // Newest first, as the API pages them
+        let summaries = [
+            snapshot(3, &[("binance", 12), ("okx", 4)]),
+            snapshot(2, &[("binance", 12), ("okx", 0)]),
+            snapshot(1, &[("binance", 12), ("okx", 4), ("kraken", 2)]),
+        ];
+        let lost = lost_venues(&summaries);
+        assert_eq!(lost.len(), 1);
+        assert_eq!(lost.get(&summaries[1].recorded_at), Some(&vec!["kraken".to_string(), "okx".to_string()]));
+
+        let mut history = MarketHistory { every: Some(Duration::seconds(60)), last_recorded: None };
+        assert!(history.take_due(summaries[2].recorded_at));
+        assert!(!history.take_due(summaries[2].recorded_at + Duration::seconds(59)));
+        assert!(history.take_due(summaries[1].recorded_at));
+    }
+}
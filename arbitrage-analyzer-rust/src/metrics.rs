@@ -0,0 +1,441 @@
+// Process-wide counters, gauges and histograms, rendered in Prometheus text format
+// on `GET /metrics`. Every sample carries the build labels of `GET /buildinfo`.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::stage_timing::{Stage, StageTimings};
+
+// Upper bounds of the histogram buckets; at most MAX_BUCKETS each
+const ANALYSIS_SECONDS_BUCKETS: [f64; 10] = [0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1];
+const ROI_PERCENT_BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.0, 3.0, 5.0, 10.0, 25.0, 50.0];
+const MAX_BUCKETS: usize = 12;
+
+/// Observations per bucket, the last one past every bound
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; MAX_BUCKETS + 1],
+    count: AtomicU64,
+    // f64 bits
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, bounds: &[f64], value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        let bucket = bounds.iter().position(|bound| value <= *bound).unwrap_or(bounds.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let _ = self.sum.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| Some((f64::from_bits(sum) + value).to_bits()));
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str, bounds: &[f64], labels: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let mut cumulative = 0;
+        let les = bounds.iter().map(|bound| bound.to_string()).chain(["+Inf".to_string()]);
+        for (bucket, le) in self.buckets.iter().zip(les) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let labels = match labels.strip_suffix('}') {
+                Some(open) => format!("{},le=\"{}\"}}", open, le),
+                None => format!("{{le=\"{}\"}}", le),
+            };
+            let _ = writeln!(out, "{}_bucket{} {}", name, labels, cumulative);
+        }
+        let _ = writeln!(out, "{}_sum{} {}", name, labels, f64::from_bits(self.sum.load(Ordering::Relaxed)));
+        let _ = writeln!(out, "{}_count{} {}", name, labels, self.count.load(Ordering::Relaxed));
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub unknown_exchange_evaluations: AtomicU64,
+    pub unknown_exchange_rejections: AtomicU64,
+    pub execution_requests_suppressed: AtomicU64,
+    pub stale_book_refetches: AtomicU64,
+    pub stale_book_rejections: AtomicU64,
+    pub embedded_book_updates: AtomicU64,
+    pub book_cache_evictions: AtomicU64,
+    pub pipeline_coalesced: AtomicU64,
+    pub pipeline_dropped: AtomicU64,
+    pub pipeline_blocked: AtomicU64,
+    pub execution_requests_halted: AtomicU64,
+    pub binance_resyncs: AtomicU64,
+    pub infeasible_routes_suppressed: AtomicU64,
+    pub opportunities_expired: AtomicU64,
+    pub laggard_opportunities_ignored: AtomicU64,
+    pub shallow_routes_skipped: AtomicU64,
+    pub shadow_fee_divergences: AtomicU64,
+    pub cycles_over_budget: AtomicU64,
+    pub shed_analyses: AtomicU64,
+    pub shed_updates_skipped: AtomicU64,
+    pub comprehensive_passes_deferred: AtomicU64,
+    pub clustered_opportunities_suppressed: AtomicU64,
+    pub contention_downsized: AtomicU64,
+    pub contention_dropped: AtomicU64,
+    pub opportunities_netted: AtomicU64,
+    pub netted_requests: AtomicU64,
+    pub slow_venue_suppressed: AtomicU64,
+    pub intents_republished: AtomicU64,
+    pub intents_expired: AtomicU64,
+    pub idle_periods: AtomicU64,
+    pub book_levels_trimmed: AtomicU64,
+    pub publishing_paused: AtomicU64,
+    pub grpc_opportunities_dropped: AtomicU64,
+    pub pre_trade_rejections: AtomicU64,
+    pub opportunities_deduplicated: AtomicU64,
+    pub redis_reconnects: AtomicU64,
+    pub analysis_errors: AtomicU64,
+    pub history_rows_pruned: AtomicU64,
+    pub book_archive_chunks: AtomicU64,
+    pub book_archive_upload_failures: AtomicU64,
+    pub book_archive_dropped: AtomicU64,
+    pub stale_routes_skipped: AtomicU64,
+    pub leg_skew_rejections: AtomicU64,
+    pub stale_books_evicted: AtomicU64,
+    pub anomalous_books_rejected: AtomicU64,
+    pub anomalous_opportunities_ignored: AtomicU64,
+    pub multi_leg_opportunities: AtomicU64,
+    pub pruned_route_evaluations: AtomicU64,
+    pub execution_requests_unacked: AtomicU64,
+    pub late_execution_acks: AtomicU64,
+    pub withdrawal_fee_changes: AtomicU64,
+    pub execution_requests_incompatible: AtomicU64,
+    pub route_lock_conflicts: AtomicU64,
+    pub execution_requests_sliced: AtomicU64,
+    pub books_processed: AtomicU64,
+    pub opportunities_found: AtomicU64,
+    pub opportunities_published: AtomicU64,
+    pub redis_errors: AtomicU64,
+    // Gauges, overwritten rather than incremented
+    pub book_cache_entries: AtomicU64,
+    pub book_cache_bytes: AtomicU64,
+    pub pipeline_queue_depth: AtomicU64,
+    pub kill_switch_tripped: AtomicU64,
+    pub live_opportunities: AtomicU64,
+    pub shed_level: AtomicU64,
+    pub idle: AtomicU64,
+    pub paused: AtomicU64,
+    pub history_store_bytes: AtomicU64,
+    pub history_opportunity_rows: AtomicU64,
+    pub parquet_export_bytes: AtomicU64,
+    pub pruned_routes: AtomicU64,
+    pub unacked_execution_requests: AtomicU64,
+    pub redis_sources_connected: AtomicU64,
+    // Sources handed to the ingestor, which takes them off the analyzer
+    pub redis_sources_configured: AtomicU64,
+    pub canary_venues: AtomicU64,
+    pub pending_pairs: AtomicU64,
+    pub analysis_seconds: Histogram,
+    pub opportunity_roi_percent: Histogram,
+    // Per-message time of each pipeline stage, see `stage_timing`
+    pub stages: StageTimings,
+}
+
+impl Metrics {
+    pub fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Time the analysis stage spent on one update
+    pub fn observe_analysis(&self, seconds: f64) {
+        self.analysis_seconds.observe(&ANALYSIS_SECONDS_BUCKETS, seconds);
+    }
+
+    /// Run `work` as one message's `stage`
+    pub fn time<T>(&self, stage: Stage, work: impl FnOnce() -> T) -> T {
+        self.stages.time(stage, work)
+    }
+
+    /// Await `work` as one message's `stage`
+    pub async fn time_async<T>(&self, stage: Stage, work: impl std::future::Future<Output = T>) -> T {
+        self.stages.time_async(stage, work).await
+    }
+
+    pub fn observe_roi(&self, roi_percentage: f64) {
+        self.opportunity_roi_percent.observe(&ROI_PERCENT_BUCKETS, roi_percentage);
+    }
+
+    /// Prometheus text, with `labels` (`{name="value",...}`) on every sample
+    pub fn render(&self, labels: &str) -> String {
+        let mut out = String::new();
+        let counters: [(&str, &str, &AtomicU64); 58] = [
+            (
+                "swapsleuth_unknown_exchange_evaluations_total",
+                "Opportunity evaluations that priced an unregistered venue with the default fee",
+                &self.unknown_exchange_evaluations,
+            ),
+            (
+                "swapsleuth_unknown_exchange_rejections_total",
+                "Opportunity evaluations rejected because a venue is not registered",
+                &self.unknown_exchange_rejections,
+            ),
+            (
+                "swapsleuth_execution_requests_suppressed_total",
+                "Execution requests not emitted because their route already had one in flight",
+                &self.execution_requests_suppressed,
+            ),
+            (
+                "swapsleuth_stale_book_refetches_total",
+                "Fetched books older than the notified version that were read again",
+                &self.stale_book_refetches,
+            ),
+            (
+                "swapsleuth_stale_book_rejections_total",
+                "Updates dropped because the book was still older than the notified version after a re-fetch",
+                &self.stale_book_rejections,
+            ),
+            (
+                "swapsleuth_embedded_book_updates_total",
+                "Updates whose book arrived inside the pub/sub message instead of being fetched",
+                &self.embedded_book_updates,
+            ),
+            (
+                "swapsleuth_book_cache_evictions_total",
+                "Books evicted from the in-memory cache to stay within its budget",
+                &self.book_cache_evictions,
+            ),
+            (
+                "swapsleuth_pipeline_coalesced_total",
+                "Queued books replaced by a newer version of the same book before analysis",
+                &self.pipeline_coalesced,
+            ),
+            (
+                "swapsleuth_pipeline_dropped_total",
+                "Ingest events dropped because the analysis queue was full (drop_oldest policy)",
+                &self.pipeline_dropped,
+            ),
+            (
+                "swapsleuth_pipeline_blocked_total",
+                "Times ingestion waited on a full analysis queue (block policy)",
+                &self.pipeline_blocked,
+            ),
+            (
+                "swapsleuth_execution_requests_halted_total",
+                "Execution requests not emitted because the kill switch is tripped",
+                &self.execution_requests_halted,
+            ),
+            (
+                "swapsleuth_binance_resyncs_total",
+                "Binance websocket books rebuilt from a REST snapshot (initial sync, gaps, malformed diffs)",
+                &self.binance_resyncs,
+            ),
+            (
+                "swapsleuth_infeasible_routes_suppressed_total",
+                "Execution requests not emitted because a venue reported the route closed (not trading, deposits or withdrawals off)",
+                &self.infeasible_routes_suppressed,
+            ),
+            (
+                "swapsleuth_opportunities_expired_total",
+                "Published opportunities expired because they stopped qualifying or were not confirmed within their TTL",
+                &self.opportunities_expired,
+            ),
+            (
+                "swapsleuth_laggard_opportunities_ignored_total",
+                "Execution requests not emitted because the spread only exists on a lagging venue's stale quote (LAGGARD_POLICY=ignore)",
+                &self.laggard_opportunities_ignored,
+            ),
+            (
+                "swapsleuth_shallow_routes_skipped_total",
+                "Profitable route evaluations dropped because a leg lacked the minimum depth near its top of book",
+                &self.shallow_routes_skipped,
+            ),
+            (
+                "swapsleuth_shadow_fee_divergences_total",
+                "Route evaluations where the shadow fee model decided differently from the active one",
+                &self.shadow_fee_divergences,
+            ),
+            (
+                "swapsleuth_cycles_over_budget_total",
+                "Analysis cycles that took longer than CYCLE_BUDGET_MS",
+                &self.cycles_over_budget,
+            ),
+            (
+                "swapsleuth_shed_analyses_total",
+                "Updates analyzed at a reduced level (own pair only, no spread recording) to catch up with a backlog",
+                &self.shed_analyses,
+            ),
+            (
+                "swapsleuth_shed_updates_skipped_total",
+                "Updates on non-priority pairs applied to the cache without analysis to catch up with a backlog",
+                &self.shed_updates_skipped,
+            ),
+            (
+                "swapsleuth_comprehensive_passes_deferred_total",
+                "Comprehensive analysis passes postponed while shedding load or idle",
+                &self.comprehensive_passes_deferred,
+            ),
+            (
+                "swapsleuth_clustered_opportunities_suppressed_total",
+                "Opportunities not published because a correlated pair on the same route offered more",
+                &self.clustered_opportunities_suppressed,
+            ),
+            (
+                "swapsleuth_contention_downsized_total",
+                "Execution requests downsized to fit venue balances other requests hold",
+                &self.contention_downsized,
+            ),
+            (
+                "swapsleuth_contention_dropped_total",
+                "Execution requests dropped because what was left of a venue balance made them unprofitable",
+                &self.contention_dropped,
+            ),
+            (
+                "swapsleuth_opportunities_netted_total",
+                "Small opportunities held for netting with others on their route",
+                &self.opportunities_netted,
+            ),
+            (
+                "swapsleuth_netted_requests_total",
+                "Execution requests emitted for a netted batch of opportunities",
+                &self.netted_requests,
+            ),
+            (
+                "swapsleuth_slow_venue_suppressed_total",
+                "Execution requests not emitted because their ROI is under the bar raised for a slow venue",
+                &self.slow_venue_suppressed,
+            ),
+            (
+                "swapsleuth_intents_republished_total",
+                "Execution requests left unresolved by the previous run and published again at startup",
+                &self.intents_republished,
+            ),
+            (
+                "swapsleuth_intents_expired_total",
+                "Execution requests left unresolved by the previous run and expired at startup",
+                &self.intents_expired,
+            ),
+            ("swapsleuth_idle_periods_total", "Times the analyzer went idle for lack of updates or outside active hours", &self.idle_periods),
+            (
+                "swapsleuth_book_levels_trimmed_total",
+                "Book levels dropped at ingest beyond BOOK_MAX_LEVELS / BOOK_LEVELS_BY_PAIR",
+                &self.book_levels_trimmed,
+            ),
+            ("swapsleuth_publishing_paused_total", "Opportunities not published because publishing was paused", &self.publishing_paused),
+            (
+                "swapsleuth_grpc_opportunities_dropped_total",
+                "Opportunities skipped by gRPC subscribers that fell more than GRPC_STREAM_BUFFER behind",
+                &self.grpc_opportunities_dropped,
+            ),
+            ("swapsleuth_pre_trade_rejections_total", "Execution requests blocked by a pre-trade check", &self.pre_trade_rejections),
+            (
+                "swapsleuth_opportunities_deduplicated_total",
+                "Opportunities not re-published because their route went out within PUBLISH_COOLDOWN_MS at the same spread",
+                &self.opportunities_deduplicated,
+            ),
+            ("swapsleuth_redis_reconnects_total", "Times a source listener lost its Redis subscription and reconnected", &self.redis_reconnects),
+            ("swapsleuth_analysis_errors_total", "Updates whose analysis failed and was skipped", &self.analysis_errors),
+            (
+                "swapsleuth_history_rows_pruned_total",
+                "Opportunity, market summary and hourly roll-up rows removed by history retention",
+                &self.history_rows_pruned,
+            ),
+            ("swapsleuth_book_archive_chunks_total", "Book snapshot chunks uploaded to object storage", &self.book_archive_chunks),
+            (
+                "swapsleuth_book_archive_upload_failures_total",
+                "Book snapshot chunks dropped after every upload attempt failed",
+                &self.book_archive_upload_failures,
+            ),
+            (
+                "swapsleuth_book_archive_dropped_total",
+                "Books not archived because BOOK_ARCHIVE_QUEUE_BOOKS were already waiting for upload",
+                &self.book_archive_dropped,
+            ),
+            ("swapsleuth_stale_routes_skipped_total", "Routes not analyzed because a leg's book was older than MAX_BOOK_AGE_MS", &self.stale_routes_skipped),
+            (
+                "swapsleuth_leg_skew_rejections_total",
+                "Routes not analyzed because their legs' books were more than MAX_LEG_SKEW_MS apart",
+                &self.leg_skew_rejections,
+            ),
+            ("swapsleuth_stale_books_evicted_total", "Books dropped from the cache after BOOK_EVICT_AGE_MS without an update", &self.stale_books_evicted),
+            ("swapsleuth_anomalous_books_rejected_total", "Books rejected for an anomaly score of ANOMALY_REJECT_SCORE or more", &self.anomalous_books_rejected),
+            (
+                "swapsleuth_anomalous_opportunities_ignored_total",
+                "Opportunities given no execution request for a leg scoring ANOMALY_FLAG_SCORE or more",
+                &self.anomalous_opportunities_ignored,
+            ),
+            ("swapsleuth_multi_leg_opportunities_total", "Profitable triangular and multi-hop cycles found", &self.multi_leg_opportunities),
+            ("swapsleuth_pruned_route_evaluations_total", "Route evaluations skipped because the route is latency-pruned", &self.pruned_route_evaluations),
+            (
+                "swapsleuth_execution_requests_unacked_total",
+                "Execution requests not acknowledged within EXECUTION_ACK_TIMEOUT_MS",
+                &self.execution_requests_unacked,
+            ),
+            ("swapsleuth_late_execution_acks_total", "Executor answers to requests already alerted on as unacknowledged", &self.late_execution_acks),
+            ("swapsleuth_withdrawal_fee_changes_total", "Withdrawal fees changed by a refresh", &self.withdrawal_fee_changes),
+            (
+                "swapsleuth_execution_requests_incompatible_total",
+                "Execution requests not published because the executor's advertised schema can't carry them",
+                &self.execution_requests_incompatible,
+            ),
+            ("swapsleuth_route_lock_conflicts_total", "Execution requests not published because another deployment reserved the route", &self.route_lock_conflicts),
+            ("swapsleuth_execution_requests_sliced_total", "Execution requests published with a slice plan", &self.execution_requests_sliced),
+            ("swapsleuth_books_processed_total", "Orderbook updates applied to the cache and analyzed", &self.books_processed),
+            ("swapsleuth_opportunities_found_total", "Opportunities found by the analysis", &self.opportunities_found),
+            ("swapsleuth_opportunities_published_total", "Opportunities published on the opportunity channel", &self.opportunities_published),
+            ("swapsleuth_redis_errors_total", "Failed Redis connects, reads and writes, and lost subscriptions", &self.redis_errors),
+        ];
+        let gauges: [(&str, &str, &AtomicU64); 16] = [
+            ("swapsleuth_book_cache_entries", "Books currently held in memory", &self.book_cache_entries),
+            ("swapsleuth_book_cache_bytes", "Estimated memory used by cached books", &self.book_cache_bytes),
+            ("swapsleuth_pipeline_queue_depth", "Events waiting for the analysis stage", &self.pipeline_queue_depth),
+            ("swapsleuth_kill_switch_tripped", "1 while the kill switch halts execution requests", &self.kill_switch_tripped),
+            ("swapsleuth_live_opportunities", "Routes with a detected opportunity that has not expired", &self.live_opportunities),
+            ("swapsleuth_shed_level", "Load shedding level: 0 normal, 1 top of book, 2 priority pairs only", &self.shed_level),
+            ("swapsleuth_idle", "1 while idle: polling slowly, logging less and without comprehensive sweeps", &self.idle),
+            ("swapsleuth_paused", "1 while publishing is paused by a control command", &self.paused),
+            ("swapsleuth_history_store_bytes", "Size of the Postgres history tables, indexes included", &self.history_store_bytes),
+            ("swapsleuth_history_opportunity_rows", "Estimated raw opportunity rows in the Postgres history", &self.history_opportunity_rows),
+            ("swapsleuth_parquet_export_bytes", "Size of the Parquet export directory", &self.parquet_export_bytes),
+            ("swapsleuth_pruned_routes", "Routes currently pruned for spreads shorter than the execution latency", &self.pruned_routes),
+            ("swapsleuth_unacked_execution_requests", "Published execution requests currently waiting past EXECUTION_ACK_TIMEOUT_MS", &self.unacked_execution_requests),
+            ("swapsleuth_redis_sources_connected", "Redis sources whose pub/sub subscription is up", &self.redis_sources_connected),
+            ("swapsleuth_canary_venues", "Venues in canary: analyzed, but not executed on until promoted", &self.canary_venues),
+            ("swapsleuth_pending_pairs", "New pairs waiting for an operator to confirm or block them", &self.pending_pairs),
+        ];
+
+        for (name, help, counter) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{}{} {}", name, labels, counter.load(Ordering::Relaxed));
+        }
+        for (name, help, gauge) in gauges {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            let _ = writeln!(out, "{}{} {}", name, labels, gauge.load(Ordering::Relaxed));
+        }
+        self.analysis_seconds.render(
+            &mut out,
+            "swapsleuth_analysis_seconds",
+            "Time the analysis stage spent on one update",
+            &ANALYSIS_SECONDS_BUCKETS,
+            labels,
+        );
+        self.opportunity_roi_percent.render(&mut out, "swapsleuth_opportunity_roi_percent", "ROI of the opportunities found", &ROI_PERCENT_BUCKETS, labels);
+        self.stages.render(&mut out, labels);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_render_cumulative_buckets_with_the_build_labels() {
+        let metrics = Metrics::default();
+        for roi in [0.05, 0.3, 0.3, 80.0, f64::NAN] {
+            metrics.observe_roi(roi);
+        }
+        let out = metrics.render(r#"{version="1.0"}"#);
+        assert!(out.contains("# TYPE swapsleuth_opportunity_roi_percent histogram"));
+        assert!(out.contains(r#"swapsleuth_opportunity_roi_percent_bucket{version="1.0",le="0.1"} 1"#));
+        assert!(out.contains(r#"swapsleuth_opportunity_roi_percent_bucket{version="1.0",le="0.5"} 3"#));
+        assert!(out.contains(r#"swapsleuth_opportunity_roi_percent_bucket{version="1.0",le="+Inf"} 4"#));
+        assert!(out.contains(r#"swapsleuth_opportunity_roi_percent_count{version="1.0"} 4"#));
+        assert!(out.contains(r#"swapsleuth_opportunity_roi_percent_sum{version="1.0"} 80.65"#));
+        assert!(metrics.render("").contains(r#"swapsleuth_analysis_seconds_bucket{le="+Inf"} 0"#));
+    }
+}
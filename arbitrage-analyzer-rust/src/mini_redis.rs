@@ -0,0 +1,337 @@
+// In-process Redis stand-in for tests. It speaks enough RESP2 for the commands
+// the analyzer and the Go collector use (GET/SET, with NX, /SETEX/DEL, PUBLISH, SUBSCRIBE,
+// PSUBSCRIBE and their unsubscribe counterparts, and XADD/XLEN), so the real `redis` client code
+// paths run end to end under `cargo test` without an external server. Keys never
+// expire and there is one database; anything else answers with an error.
+// `require_auth` turns on one ACL user, which every connection then has to AUTH as,
+// and `disconnect_all` hangs up on every client as a Redis restart would.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+
+use crate::sources;
+
+#[derive(Debug, Default)]
+struct Subscriptions {
+    channels: HashSet<String>,
+    patterns: HashSet<String>,
+}
+
+impl Subscriptions {
+    fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+}
+
+#[derive(Debug)]
+struct Client {
+    writer: Arc<Mutex<TcpStream>>,
+    subscriptions: Subscriptions,
+    authenticated: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    keys: HashMap<String, Vec<u8>>,
+    // Entries of each stream, oldest first; MAXLEN is not enforced
+    streams: HashMap<String, Vec<Vec<Vec<u8>>>>,
+    clients: HashMap<u64, Client>,
+    next_client: u64,
+    // User and password connections must AUTH with; None accepts everyone
+    acl: Option<(String, String)>,
+}
+
+/// A running server on an ephemeral localhost port; it lives until the test process exits
+#[derive(Debug, Clone)]
+pub struct MiniRedis {
+    pub addr: String,
+    state: Arc<Mutex<State>>,
+}
+
+// ---------- RESP encoding ----------
+
+fn bulk(out: &mut Vec<u8>, value: Option<&[u8]>) {
+    match value {
+        Some(value) => {
+            out.extend_from_slice(format!("${}\r\n", value.len()).as_bytes());
+            out.extend_from_slice(value);
+            out.extend_from_slice(b"\r\n");
+        }
+        None => out.extend_from_slice(b"$-1\r\n"),
+    }
+}
+
+fn integer(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(format!(":{}\r\n", value).as_bytes());
+}
+
+// `[kind, name, count]` as sent for (un)subscribe confirmations
+fn subscription_reply(kind: &str, name: Option<&str>, count: usize) -> Vec<u8> {
+    let mut out = b"*3\r\n".to_vec();
+    bulk(&mut out, Some(kind.as_bytes()));
+    bulk(&mut out, name.map(str::as_bytes));
+    integer(&mut out, count);
+    out
+}
+
+fn read_line(reader: &mut impl BufRead) -> Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// One command as an array of bulk strings; None once the client hung up
+fn read_command(reader: &mut impl BufRead) -> Result<Option<Vec<Vec<u8>>>> {
+    let Some(header) = read_line(reader)? else { return Ok(None) };
+    let count: usize = header.strip_prefix('*').and_then(|n| n.parse().ok()).ok_or_else(|| anyhow!("expected an array, got {:?}", header))?;
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        let line = read_line(reader)?.ok_or_else(|| anyhow!("connection closed mid-command"))?;
+        let len: usize = line.strip_prefix('$').and_then(|n| n.parse().ok()).ok_or_else(|| anyhow!("expected a bulk string, got {:?}", line))?;
+        let mut arg = vec![0; len + 2];
+        reader.read_exact(&mut arg)?;
+        arg.truncate(len);
+        args.push(arg);
+    }
+    Ok(Some(args))
+}
+
+impl MiniRedis {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind a localhost port");
+        let server = MiniRedis { addr: listener.local_addr().unwrap().to_string(), state: Arc::default() };
+        let accepting = server.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let connection = accepting.clone();
+                thread::spawn(move || connection.serve(stream));
+            }
+        });
+        server
+    }
+
+    /// Refuse every command until the connection authenticates as `user`
+    pub fn require_auth(&self, user: &str, password: &str) {
+        self.lock().acl = Some((user.to_string(), password.to_string()));
+    }
+
+    pub fn client(&self) -> redis::Client {
+        redis::Client::open(format!("redis://{}/", self.addr)).expect("valid address")
+    }
+
+    /// Close every client connection; the server keeps accepting new ones
+    pub fn disconnect_all(&self) {
+        // Forgotten at once, so their subscriptions no longer count
+        for (_, client) in self.lock().clients.drain() {
+            let _ = client.writer.lock().unwrap_or_else(|e| e.into_inner()).shutdown(std::net::Shutdown::Both);
+        }
+    }
+
+    /// Wait until `n` clients are subscribed to `channel`, so a test's PUBLISH isn't lost
+    pub fn wait_for_subscribers(&self, channel: &str, n: usize, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let subscribed = self.lock().clients.values().filter(|c| c.subscriptions.channels.contains(channel)).count();
+            if subscribed >= n {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(anyhow!("{} of {} subscribers on {} after {:?}", subscribed, n, channel, timeout));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn serve(&self, stream: TcpStream) {
+        let Ok(writer) = stream.try_clone() else { return };
+        let writer = Arc::new(Mutex::new(writer));
+        let id = {
+            let mut state = self.lock();
+            state.next_client += 1;
+            let id = state.next_client;
+            state.clients.insert(id, Client { writer: writer.clone(), subscriptions: Subscriptions::default(), authenticated: false });
+            id
+        };
+
+        let mut reader = BufReader::new(stream);
+        while let Ok(Some(args)) = read_command(&mut reader) {
+            let reply = self.execute(id, &args);
+            if writer.lock().unwrap_or_else(|e| e.into_inner()).write_all(&reply).is_err() {
+                break;
+            }
+        }
+        self.lock().clients.remove(&id);
+    }
+
+    fn execute(&self, id: u64, args: &[Vec<u8>]) -> Vec<u8> {
+        let Some((name, args)) = args.split_first() else {
+            return b"-ERR empty command\r\n".to_vec();
+        };
+        let text = |arg: &Vec<u8>| String::from_utf8_lossy(arg).into_owned();
+        let mut out = Vec::new();
+        let command = String::from_utf8_lossy(name).to_uppercase();
+        {
+            let mut state = self.lock();
+            if let Some((user, password)) = state.acl.clone() {
+                if command == "AUTH" {
+                    let (given_user, given_password) = match args {
+                        [password] => ("default".to_string(), text(password)),
+                        [user, password] => (text(user), text(password)),
+                        _ => return b"-ERR wrong number of arguments for 'auth' command\r\n".to_vec(),
+                    };
+                    if (given_user, given_password) != (user, password) {
+                        return b"-WRONGPASS invalid username-password pair or user is disabled.\r\n".to_vec();
+                    }
+                    if let Some(client) = state.clients.get_mut(&id) {
+                        client.authenticated = true;
+                    }
+                } else if state.clients.get(&id).is_none_or(|client| !client.authenticated) {
+                    return b"-NOAUTH Authentication required.\r\n".to_vec();
+                }
+            }
+        }
+        match (command.as_str(), args) {
+            ("PING", _) => out.extend_from_slice(b"+PONG\r\n"),
+            // Connection setup some client versions send
+            ("SELECT" | "CLIENT" | "AUTH", _) => out.extend_from_slice(b"+OK\r\n"),
+            ("GET", [key]) => bulk(&mut out, self.lock().keys.get(&text(key)).map(Vec::as_slice)),
+            // Expiry options (EX/PX) are accepted and ignored; NX only sets a missing key
+            ("SET", [key, value, options @ ..]) => {
+                let mut state = self.lock();
+                if options.iter().any(|option| option.eq_ignore_ascii_case(b"NX")) && state.keys.contains_key(&text(key)) {
+                    bulk(&mut out, None);
+                } else {
+                    state.keys.insert(text(key), value.clone());
+                    out.extend_from_slice(b"+OK\r\n");
+                }
+            }
+            ("SETEX", [key, _seconds, value]) => {
+                self.lock().keys.insert(text(key), value.clone());
+                out.extend_from_slice(b"+OK\r\n");
+            }
+            ("DEL", keys) => {
+                let mut state = self.lock();
+                integer(&mut out, keys.iter().filter(|key| state.keys.remove(&text(key)).is_some()).count());
+            }
+            // Only auto-generated ids (`*`), after any trimming options
+            ("XADD", [key, rest @ ..]) => match rest.iter().position(|arg| arg.as_slice() == b"*") {
+                Some(at) if at + 1 < rest.len() && (rest.len() - at - 1) % 2 == 0 => {
+                    let mut state = self.lock();
+                    let entries = state.streams.entry(text(key)).or_default();
+                    entries.push(rest[at + 1..].to_vec());
+                    bulk(&mut out, Some(format!("{}-0", entries.len()).as_bytes()));
+                }
+                _ => out.extend_from_slice(b"-ERR unsupported XADD arguments\r\n"),
+            },
+            ("XLEN", [key]) => integer(&mut out, self.lock().streams.get(&text(key)).map_or(0, Vec::len)),
+            ("PUBLISH", [channel, payload]) => integer(&mut out, self.publish(&text(channel), payload)),
+            ("SUBSCRIBE" | "PSUBSCRIBE", names) if !names.is_empty() => {
+                let pattern = name.eq_ignore_ascii_case(b"PSUBSCRIBE");
+                let mut state = self.lock();
+                let Some(client) = state.clients.get_mut(&id) else { return out };
+                for name in names {
+                    let set = if pattern { &mut client.subscriptions.patterns } else { &mut client.subscriptions.channels };
+                    set.insert(text(name));
+                    let kind = if pattern { "psubscribe" } else { "subscribe" };
+                    out.extend(subscription_reply(kind, Some(&text(name)), client.subscriptions.count()));
+                }
+                // Confirmed before the state is unlocked, so no PUBLISH can overtake the confirmation
+                let _ = client.writer.lock().unwrap_or_else(|e| e.into_inner()).write_all(&out);
+                out.clear();
+            }
+            ("UNSUBSCRIBE" | "PUNSUBSCRIBE", names) => {
+                let pattern = name.eq_ignore_ascii_case(b"PUNSUBSCRIBE");
+                let kind = if pattern { "punsubscribe" } else { "unsubscribe" };
+                let mut state = self.lock();
+                let Some(client) = state.clients.get_mut(&id) else { return out };
+                let set = if pattern { &mut client.subscriptions.patterns } else { &mut client.subscriptions.channels };
+                // No names means all of them
+                let removed: Vec<String> = if names.is_empty() { set.drain().collect() } else { names.iter().map(text).filter(|n| set.remove(n)).collect() };
+                if removed.is_empty() {
+                    out.extend(subscription_reply(kind, None, client.subscriptions.count()));
+                }
+                for (idx, name) in removed.iter().enumerate() {
+                    out.extend(subscription_reply(kind, Some(name), client.subscriptions.count() + removed.len() - idx - 1));
+                }
+            }
+            (other, _) => out.extend_from_slice(format!("-ERR unsupported command '{}'\r\n", other).as_bytes()),
+        }
+        out
+    }
+
+    /// Deliver to every matching subscription; returns how many received it
+    fn publish(&self, channel: &str, payload: &[u8]) -> usize {
+        let state = self.lock();
+        let mut delivered = 0;
+        for client in state.clients.values() {
+            let mut messages = Vec::new();
+            if client.subscriptions.channels.contains(channel) {
+                let mut message = b"*3\r\n".to_vec();
+                bulk(&mut message, Some(b"message"));
+                bulk(&mut message, Some(channel.as_bytes()));
+                bulk(&mut message, Some(payload));
+                messages.push(message);
+            }
+            for pattern in client.subscriptions.patterns.iter().filter(|p| sources::glob_match(p, channel)) {
+                let mut message = b"*4\r\n".to_vec();
+                bulk(&mut message, Some(b"pmessage"));
+                bulk(&mut message, Some(pattern.as_bytes()));
+                bulk(&mut message, Some(channel.as_bytes()));
+                bulk(&mut message, Some(payload));
+                messages.push(message);
+            }
+            let mut writer = client.writer.lock().unwrap_or_else(|e| e.into_inner());
+            for message in messages {
+                if writer.write_all(&message).is_ok() {
+                    delivered += 1;
+                }
+            }
+        }
+        delivered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use redis::Commands;
+
+    use super::*;
+
+    #[test]
+    fn serves_keys_and_pub_sub_to_the_redis_client() {
+        let server = MiniRedis::start();
+        let mut con = server.client().get_connection().unwrap();
+        let _: () = con.set_ex("orderbook:binance:BTC/USDT", "{}", 30).unwrap();
+        assert_eq!(con.get::<_, Option<String>>("orderbook:binance:BTC/USDT").unwrap().as_deref(), Some("{}"));
+        assert_eq!(con.get::<_, Option<String>>("missing").unwrap(), None);
+
+        let mut sub_con = server.client().get_connection().unwrap();
+        let mut pubsub = sub_con.as_pubsub();
+        pubsub.subscribe("orderbook_updates").unwrap();
+        pubsub.psubscribe("orderbook:*").unwrap();
+        pubsub.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        assert_eq!(con.publish::<_, _, i64>("orderbook_updates", "k1").unwrap(), 1);
+        assert_eq!(con.publish::<_, _, i64>("orderbook:okx", "k2").unwrap(), 1);
+        let first = pubsub.get_message().unwrap();
+        assert_eq!((first.get_channel_name(), first.get_payload::<String>().unwrap().as_str()), ("orderbook_updates", "k1"));
+        let second = pubsub.get_message().unwrap();
+        assert_eq!(second.get_pattern::<String>().unwrap(), "orderbook:*");
+        assert_eq!(second.get_payload::<String>().unwrap(), "k2");
+        // Dropping the PubSub unsubscribes from everything and waits for the confirmations
+        drop(pubsub);
+        assert_eq!(sub_con.get::<_, Option<String>>("missing").unwrap(), None);
+    }
+}
@@ -0,0 +1,70 @@
+// What the analyzer does with the opportunities it finds, set with ANALYZER_MODE:
+//  - observe: log and record them, publish nothing,
+//  - signal:  also publish each opportunity on OPPORTUNITY_CHANNEL,
+//  - execute: also emit an execution request per opportunity on EXECUTION_CHANNEL.
+// Each mode includes everything the previous one does. The kill switch overrides
+// `execute`: while it is tripped no execution request is emitted.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+pub const DEFAULT_OPPORTUNITY_CHANNEL: &str = "arbitrage_opportunities";
+pub const DEFAULT_EXECUTION_CHANNEL: &str = "execution_requests";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Mode {
+    Observe,
+    Signal,
+    Execute,
+}
+
+impl Mode {
+    pub fn publishes_opportunities(self) -> bool {
+        self >= Mode::Signal
+    }
+
+    pub fn emits_execution_requests(self) -> bool {
+        self == Mode::Execute
+    }
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "observe" => Ok(Mode::Observe),
+            "signal" => Ok(Mode::Signal),
+            "execute" => Ok(Mode::Execute),
+            other => Err(anyhow!("unknown mode: {} (expected observe, signal or execute)", other)),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Observe => "observe",
+            Mode::Signal => "signal",
+            Mode::Execute => "execute",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_are_cumulative() {
+        let observe: Mode = "observe".parse().unwrap();
+        let signal: Mode = "Signal".parse().unwrap();
+        let execute: Mode = "EXECUTE".parse().unwrap();
+        assert!(!observe.publishes_opportunities() && !observe.emits_execution_requests());
+        assert!(signal.publishes_opportunities() && !signal.emits_execution_requests());
+        assert!(execute.publishes_opportunities() && execute.emits_execution_requests());
+        assert!("dry-run".parse::<Mode>().is_err());
+    }
+}
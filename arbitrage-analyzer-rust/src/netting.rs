@@ -0,0 +1,209 @@
+// Netting of small opportunities on one route, enabled with NETTING_WINDOW_MS.
+// Gas, transaction and transfer costs are paid per trade, so a route that keeps
+// reopening for a small size pays them every time. With netting on, an
+// opportunity worth less than NETTING_MAX_NOTIONAL_USD is held instead of turned
+// into an execution request, and later ones on its route join it. When the
+// window from the first one closes, the batch becomes a single request:
+//  - sized at the sum of its members' sizes, but no more than both legs' current
+//    books hold within NETTING_DEPTH_BPS of the top, nor than the size caps,
+//  - priced at the latest member's prices with the fixed costs counted once,
+//    and dropped if that no longer clears the profit thresholds.
+// The kill switch, mode and route feasibility are checked again at that point.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, info};
+use serde::Serialize;
+
+use crate::lifecycle::RouteKey;
+use crate::metrics::Metrics;
+use crate::notional::NotionalConverter;
+use crate::{config, ArbitrageOpportunity, OrderBook, SpreadAnalyzer};
+
+const DEFAULT_MAX_NOTIONAL_USD: f64 = 5_000.0;
+const DEFAULT_DEPTH_BPS: f64 = 10.0;
+
+/// What a netted execution request is made of
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NettedBatch {
+    pub opportunity_ids: Vec<String>,
+    // Sum of the members' sizes, before depth and caps
+    pub requested_size: f64,
+    // Fixed costs the members would have paid as separate trades, minus the one paid
+    pub fixed_costs_saved: f64,
+}
+
+#[derive(Debug)]
+struct Batch {
+    opened_at: DateTime<Utc>,
+    latest: ArbitrageOpportunity,
+    opportunity_ids: Vec<String>,
+    total_size: f64,
+}
+
+#[derive(Debug)]
+pub struct Netting {
+    // None disables netting
+    window: Option<Duration>,
+    max_notional_usd: f64,
+    depth_bps: f64,
+    batches: HashMap<RouteKey, Batch>,
+}
+
+impl Netting {
+    pub fn new(window: Option<Duration>, max_notional_usd: f64, depth_bps: f64) -> Self {
+        Netting { window, max_notional_usd, depth_bps, batches: HashMap::new() }
+    }
+
+    pub fn from_env() -> Self {
+        let window_ms: i64 = config::env_or("NETTING_WINDOW_MS", 0);
+        Netting::new(
+            (window_ms > 0).then(|| Duration::milliseconds(window_ms)),
+            config::env_or("NETTING_MAX_NOTIONAL_USD", DEFAULT_MAX_NOTIONAL_USD),
+            config::env_or("NETTING_DEPTH_BPS", DEFAULT_DEPTH_BPS),
+        )
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.window.is_some()
+    }
+
+    /// Add `opp` to its route's batch, opening one if there is none
+    pub fn hold(&mut self, opp: &ArbitrageOpportunity, now: DateTime<Utc>) {
+        let route = RouteKey::new(&opp.pair, &opp.buy_exchange, &opp.sell_exchange);
+        let batch = self.batches.entry(route).or_insert_with(|| Batch {
+            opened_at: now,
+            latest: opp.clone(),
+            opportunity_ids: Vec::new(),
+            total_size: 0.0,
+        });
+        batch.latest = opp.clone();
+        batch.opportunity_ids.push(opp.id.clone());
+        batch.total_size += opp.max_size;
+    }
+
+    /// Take the batches whose window has closed
+    fn take_due(&mut self, now: DateTime<Utc>) -> Vec<Batch> {
+        let Some(window) = self.window else { return Vec::new() };
+        let due: Vec<RouteKey> =
+            self.batches.iter().filter(|(_, batch)| now - batch.opened_at >= window).map(|(route, _)| route.clone()).collect();
+        due.into_iter().filter_map(|route| self.batches.remove(&route)).collect()
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Whether `opp` is small enough to be held for netting
+    pub(crate) fn nets(&self, opp: &ArbitrageOpportunity) -> bool {
+        let converter = NotionalConverter::new(&self.capital_config.quote_usd, &self.books, self.sizing_config.reference_price);
+        let (price, _) = converter.base_usd_price(&opp.pair, (opp.buy_price + opp.sell_price) / 2.0);
+        opp.max_size * price < self.netting.max_notional_usd
+    }
+
+    // The cached books of `opp`'s buy and sell leg
+    fn route_books(&self, opp: &ArbitrageOpportunity) -> Option<(&OrderBook, &OrderBook)> {
+        let find = |exchange: &str| {
+            self.books.values().find(|book| book.exchange == exchange && book.pair.replace("WBTC", "BTC") == opp.pair)
+        };
+        Some((find(&opp.buy_exchange)?, find(&opp.sell_exchange)?))
+    }
+
+    // Largest size a netted request on `opp`'s route may have: current depth near the top, and the size caps
+    fn netting_limit(&self, opp: &ArbitrageOpportunity) -> f64 {
+        let caps = self.choose_execution_size(f64::INFINITY, f64::INFINITY, &opp.pair, &opp.buy_exchange, &opp.sell_exchange, (opp.buy_price + opp.sell_price) / 2.0);
+        let depth = match self.route_books(opp) {
+            Some((buy_book, sell_book)) => {
+                let bps = self.netting.depth_bps;
+                (buy_book.ask_depth_within(bps) / opp.buy_price).min(sell_book.bid_depth_within(bps) / opp.sell_price)
+            }
+            // Books evicted meanwhile: no more than the latest member was sized at
+            None => opp.max_size,
+        };
+        caps.min(depth)
+    }
+
+    /// Turn every batch whose window has closed into one execution request
+    pub(crate) fn flush_netting(&mut self, now: DateTime<Utc>) {
+        for batch in self.netting.take_due(now) {
+            let opp = &batch.latest;
+            if !self.mode.emits_execution_requests() || self.publishing_paused() {
+                debug!("Discarding netted batch of {} on {}: no longer executable", batch.opportunity_ids.len(), opp.pair);
+                continue;
+            }
+            let size = batch.total_size.min(self.netting_limit(opp));
+            let Some(netted) = self.reprice_at_size(opp, size) else {
+                info!("Dropping netted batch of {} on {}: {} is no longer profitable", batch.opportunity_ids.len(), opp.pair, size);
+                continue;
+            };
+            let fixed_costs = self.fees_config.fixed_leg_cost(&opp.buy_exchange)
+                + self.fees_config.fixed_leg_cost(&opp.sell_exchange)
+                + self.fees_config.transfer_cost(&opp.buy_exchange, &opp.sell_exchange);
+            let members = batch.opportunity_ids.len();
+            info!("Netting {} opportunities on {} into one request of {} (asked {})", members, opp.pair, size, batch.total_size);
+            Metrics::inc(&self.metrics.netted_requests);
+            let summary = NettedBatch {
+                opportunity_ids: batch.opportunity_ids,
+                requested_size: batch.total_size,
+                fixed_costs_saved: fixed_costs * (members - 1) as f64,
+            };
+            self.request_execution(&netted, Some(summary), now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode::Mode;
+
+    // An analyzer netting for 2s, and a 0.05 BTC opportunity on its books
+    fn analyzer_and_small() -> (SpreadAnalyzer, ArbitrageOpportunity) {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.mode = Mode::Execute;
+        analyzer.netting = Netting::new(Some(Duration::seconds(2)), 5_000.0, 10.0);
+        // 0.05 BTC at each top, more within 10bps
+        analyzer.books.insert(
+            "binance:BTC/USDT".to_string(),
+            OrderBook::for_test("binance", "BTC/USDT", vec![vec![49_990.0, 1.0]], vec![vec![50_000.0, 0.05], vec![50_040.0, 0.15]]),
+        );
+        analyzer.books.insert(
+            "okx:BTC/USDT".to_string(),
+            OrderBook::for_test("okx", "BTC/USDT", vec![vec![51_000.0, 0.05], vec![50_980.0, 0.2]], vec![vec![51_010.0, 1.0]]),
+        );
+        let small = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 0.05, 0.05).unwrap();
+        (analyzer, small)
+    }
+
+    #[test]
+    fn only_small_opportunities_are_netted() {
+        let (analyzer, small) = analyzer_and_small();
+        assert!(analyzer.nets(&small));
+        let large = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        assert!(!analyzer.nets(&large));
+    }
+
+    #[test]
+    fn small_opportunities_on_a_route_become_one_request() {
+        let (mut analyzer, small) = analyzer_and_small();
+        let start = Utc::now();
+        for i in 0..4 {
+            let mut opp = small.clone();
+            opp.id = format!("opp-{}", i);
+            analyzer.netting.hold(&opp, start + Duration::milliseconds(i * 100));
+        }
+        analyzer.flush_netting(start + Duration::seconds(1));
+        assert!(analyzer.lifecycle.in_flight().is_empty());
+
+        analyzer.flush_netting(start + Duration::seconds(2));
+        assert_eq!(analyzer.lifecycle.in_flight().len(), 1);
+        assert!(analyzer.netting.batches.is_empty());
+    }
+
+    #[test]
+    fn netted_size_is_capped_by_the_depth_near_the_top() {
+        let (analyzer, small) = analyzer_and_small();
+        // Binance asks within 10bps hold ~0.2 BTC, the okx bids 0.25
+        let depth = (50_000.0 * 0.05 + 50_040.0 * 0.15) / 50_000.0;
+        assert!((analyzer.netting_limit(&small) - depth).abs() < 1e-12);
+    }
+}
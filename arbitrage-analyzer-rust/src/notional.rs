@@ -0,0 +1,113 @@
+// USD value of base quantities, so caps given in USD (MAX_USD_SIZE) mean the
+// same for every asset. A pair's base asset is priced, in order of preference:
+//  - live: the mid of the route being sized, when the pair's quote asset has a
+//    USD price (stablecoins, QUOTE_USD_PRICES; see `capital`),
+//  - consensus: the median mid across every cached book quoting the same base
+//    in a USD-priced asset, e.g. ETH/USDT and ETH/USDC books for ETH/BTC,
+//  - reference: SIZING_REFERENCE_PRICE, the fixed price the cap used to be
+//    converted with, so an asset that can't be priced at all is still capped.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::{numeric, OrderBook};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceSource {
+    Live,
+    Consensus,
+    Reference,
+}
+
+impl fmt::Display for PriceSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PriceSource::Live => "live",
+            PriceSource::Consensus => "consensus",
+            PriceSource::Reference => "reference",
+        })
+    }
+}
+
+pub fn split_pair(pair: &str) -> (String, String) {
+    let normalized = pair.to_uppercase().replace("WBTC", "BTC");
+    let mut assets = normalized.split('/');
+    (assets.next().unwrap_or_default().to_string(), assets.next().unwrap_or_default().to_string())
+}
+
+pub struct NotionalConverter<'a> {
+    // USD price per unit of a quote asset
+    quote_usd: &'a HashMap<String, f64>,
+    books: &'a HashMap<String, OrderBook>,
+    reference_price: f64,
+}
+
+impl<'a> NotionalConverter<'a> {
+    pub fn new(quote_usd: &'a HashMap<String, f64>, books: &'a HashMap<String, OrderBook>, reference_price: f64) -> Self {
+        NotionalConverter { quote_usd, books, reference_price }
+    }
+
+    fn usd(&self, quote: &str, price: f64) -> Option<f64> {
+        let usd = price * self.quote_usd.get(quote)?;
+        (usd.is_finite() && usd > 0.0).then_some(usd)
+    }
+
+    /// Median USD mid of `base` across the cached books that quote it in a USD-priced asset
+    pub fn consensus_price(&self, base: &str) -> Option<f64> {
+        let mids: Vec<f64> = self
+            .books
+            .values()
+            .filter_map(|book| {
+                let (book_base, quote) = split_pair(&book.pair);
+                if book_base != base {
+                    return None;
+                }
+                let ((bid, _), (ask, _)) = (book.best_bid()?, book.best_ask()?);
+                self.usd(&quote, (bid + ask) / 2.0)
+            })
+            .collect();
+        numeric::percentile(&mids, 50.0)
+    }
+
+    /// USD price of one unit of `pair`'s base, given the mid of the route being sized
+    pub fn base_usd_price(&self, pair: &str, route_mid: f64) -> (f64, PriceSource) {
+        let (base, quote) = split_pair(pair);
+        if let Some(price) = self.usd(&quote, route_mid) {
+            return (price, PriceSource::Live);
+        }
+        match self.consensus_price(&base) {
+            Some(price) => (price, PriceSource::Consensus),
+            None => (self.reference_price, PriceSource::Reference),
+        }
+    }
+
+    /// Base quantity of `pair` worth `usd`
+    pub fn base_size_for(&self, usd: f64, pair: &str, route_mid: f64) -> (f64, PriceSource) {
+        let (price, source) = self.base_usd_price(pair, route_mid);
+        (numeric::safe_div(usd, price).unwrap_or(0.0), source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prices_the_base_live_then_by_consensus_then_by_reference() {
+        let quote_usd = HashMap::from([("USDT".to_string(), 1.0), ("USDC".to_string(), 1.0)]);
+        let mut books = HashMap::new();
+        for (key, pair, bid, ask) in [
+            ("binance:ETH/USDT", "ETH/USDT", 2_990.0, 3_010.0),
+            ("okx:ETH/USDC", "ETH/USDC", 2_995.0, 3_005.0),
+            ("uniswap:ETH/USDT", "ETH/USDT", 3_100.0, 3_120.0),
+        ] {
+            books.insert(key.to_string(), OrderBook::for_test("x", pair, vec![vec![bid, 1.0]], vec![vec![ask, 1.0]]));
+        }
+        let converter = NotionalConverter::new(&quote_usd, &books, 50_000.0);
+
+        assert_eq!(converter.base_size_for(100_000.0, "SOL/USDT", 200.0), (500.0, PriceSource::Live));
+        // BTC isn't USD-priced, so ETH/BTC falls back to the median ETH mid
+        assert_eq!(converter.base_usd_price("ETH/BTC", 0.05), (3_000.0, PriceSource::Consensus));
+        assert_eq!(converter.base_usd_price("PEPE/BTC", 1e-10), (50_000.0, PriceSource::Reference));
+    }
+}
@@ -0,0 +1,80 @@
+// Numeric hygiene helpers. Everything that divides or orders floats in the
+// analysis path goes through here so a NaN/inf can never leak into an opportunity.
+
+use std::cmp::Ordering;
+
+/// `numerator / denominator`, or `None` when the result would not be a finite number
+pub fn safe_div(numerator: f64, denominator: f64) -> Option<f64> {
+    if denominator == 0.0 || !numerator.is_finite() || !denominator.is_finite() {
+        return None;
+    }
+    let result = numerator / denominator;
+    result.is_finite().then_some(result)
+}
+
+/// Percentage `part / whole * 100`, or `None` when not computable
+pub fn safe_pct(part: f64, whole: f64) -> Option<f64> {
+    safe_div(part, whole).map(|ratio| ratio * 100.0).filter(|pct| pct.is_finite())
+}
+
+pub fn all_finite(values: &[f64]) -> bool {
+    values.iter().all(|v| v.is_finite())
+}
+
+/// Nearest-rank percentile (`pct` in 0..=100) of the finite values, or `None` if there are none
+pub fn percentile(values: &[f64], pct: f64) -> Option<f64> {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let rank = ((pct.clamp(0.0, 100.0) / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted.get(rank).copied()
+}
+
+/// Descending order that is total even if a NaN slipped through (NaNs sort last)
+pub fn cmp_desc(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => b.total_cmp(&a),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safe_div_rejects_zero_and_non_finite() {
+        assert_eq!(safe_div(1.0, 2.0), Some(0.5));
+        assert_eq!(safe_div(1.0, 0.0), None);
+        assert_eq!(safe_div(1.0, -0.0), None);
+        assert_eq!(safe_div(f64::NAN, 1.0), None);
+        assert_eq!(safe_div(1.0, f64::INFINITY), None);
+        assert_eq!(safe_div(f64::MAX, f64::MIN_POSITIVE), None);
+    }
+
+    #[test]
+    fn safe_pct_scales_ratio() {
+        assert_eq!(safe_pct(1.0, 4.0), Some(25.0));
+        assert_eq!(safe_pct(1.0, 0.0), None);
+    }
+
+    #[test]
+    fn percentile_ignores_non_finite() {
+        let values = [5.0, 1.0, f64::NAN, 3.0, 2.0, 4.0, f64::INFINITY];
+        assert_eq!(percentile(&values, 50.0), Some(3.0));
+        assert_eq!(percentile(&values, 100.0), Some(5.0));
+        assert_eq!(percentile(&[f64::NAN], 50.0), None);
+    }
+
+    #[test]
+    fn cmp_desc_puts_nan_last() {
+        let mut values = [1.0, f64::NAN, 3.0, f64::NEG_INFINITY, 2.0];
+        values.sort_by(|a, b| cmp_desc(*a, *b));
+        assert_eq!(&values[..4], &[3.0, 2.0, 1.0, f64::NEG_INFINITY]);
+        assert!(values[4].is_nan());
+    }
+}
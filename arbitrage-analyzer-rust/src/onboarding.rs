@@ -0,0 +1,340 @@
+// Pair onboarding. With PAIR_ONBOARDING=true, the first book of a normalized
+// pair that isn't known puts it on the pending list: default instrument metadata
+// is created for it (base and quote asset, the venue it came from, the price
+// decimals its first quotes use), it is raised as a `pair_onboarding` event, and
+// its routes have to clear the profit and ROI thresholds times
+// ONBOARDING_THRESHOLD_MULTIPLIER (default 2) until an operator decides:
+//   {"command":"confirm_pair","pair":"PEPE/USDT","actor":"ops"}
+//   {"command":"block_pair","pair":"PEPE/USDT","reason":"illiquid","actor":"ops"}
+// A confirmed pair is analyzed like any other; the books of a blocked one are
+// dropped on arrival. Known pairs are ONBOARDING_KNOWN_PAIRS plus every pair on
+// record in ONBOARDING_STATE_FILE (default `swapsleuth-pairs.json`), which holds
+// the pending pairs and the operator's decisions so a restart keeps both.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::Severity;
+use crate::events::{Event, EventClass};
+use crate::{config, OrderBook, SpreadAnalyzer};
+
+pub const DEFAULT_STATE_FILE: &str = "swapsleuth-pairs.json";
+const DEFAULT_THRESHOLD_MULTIPLIER: f64 = 2.0;
+// Past this, a price's shortest representation is float noise rather than a tick size
+const MAX_PRICE_DECIMALS: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PairStatus {
+    Pending,
+    Confirmed,
+    Blocked,
+}
+
+/// The defaults a pair starts with when its first book arrives
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstrumentMetadata {
+    pub base: String,
+    pub quote: String,
+    pub first_venue: String,
+    // Decimals of the first book's top quotes, the finest of the two
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_decimals: Option<u32>,
+}
+
+impl InstrumentMetadata {
+    fn for_pair(pair: &str) -> Self {
+        let (base, quote) = pair.split_once('/').unwrap_or((pair, ""));
+        InstrumentMetadata { base: base.to_string(), quote: quote.to_string(), first_venue: String::new(), price_decimals: None }
+    }
+
+    fn from_book(pair: &str, book: &OrderBook) -> Self {
+        let price_decimals = [book.best_bid(), book.best_ask()].into_iter().flatten().filter_map(|(price, _)| decimals(price)).max();
+        InstrumentMetadata { first_venue: book.exchange.to_lowercase(), price_decimals, ..Self::for_pair(pair) }
+    }
+}
+
+fn decimals(price: f64) -> Option<u32> {
+    if !price.is_finite() {
+        return None;
+    }
+    let digits = price.to_string().split_once('.').map_or(0, |(_, fraction)| fraction.len());
+    (digits <= MAX_PRICE_DECIMALS).then_some(digits as u32)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnboardedPair {
+    pub status: PairStatus,
+    pub metadata: InstrumentMetadata,
+    pub first_seen: DateTime<Utc>,
+    pub venues: BTreeSet<String>,
+    // Since this run started
+    #[serde(skip)]
+    pub books: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decided_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decided_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// What to do with a book, by the status of its pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Analyze,
+    // First book of an unknown pair; it is pending from now on
+    Onboarded,
+    Drop,
+}
+
+#[derive(Debug)]
+pub struct PairOnboarding {
+    pub enabled: bool,
+    known: BTreeSet<String>,
+    threshold_multiplier: f64,
+    pairs: BTreeMap<String, OnboardedPair>,
+    path: Option<PathBuf>,
+}
+
+fn load(path: &Path) -> BTreeMap<String, OnboardedPair> {
+    match fs::read_to_string(path) {
+        Ok(raw) => serde_json::from_str(&raw).unwrap_or_else(|e| {
+            error!("Pair onboarding state {} is unreadable ({}); only ONBOARDING_KNOWN_PAIRS are known", path.display(), e);
+            BTreeMap::new()
+        }),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+impl PairOnboarding {
+    pub fn new(enabled: bool, known: impl IntoIterator<Item = String>, threshold_multiplier: f64, path: Option<PathBuf>) -> Self {
+        let pairs = path.as_deref().map(load).unwrap_or_default();
+        PairOnboarding { enabled, known: known.into_iter().collect(), threshold_multiplier: threshold_multiplier.max(1.0), pairs, path }
+    }
+
+    pub fn from_env() -> Self {
+        let known = config::env_var("ONBOARDING_KNOWN_PAIRS").unwrap_or_default();
+        Self::new(
+            config::env_or("PAIR_ONBOARDING", false),
+            known.split(',').map(|pair| pair.trim().to_uppercase().replace("WBTC", "BTC")).filter(|pair| !pair.is_empty()),
+            config::env_or("ONBOARDING_THRESHOLD_MULTIPLIER", DEFAULT_THRESHOLD_MULTIPLIER),
+            Some(PathBuf::from(config::env_var("ONBOARDING_STATE_FILE").unwrap_or_else(|_| DEFAULT_STATE_FILE.to_string()))),
+        )
+    }
+
+    /// The status of normalized `pair`; None for a known pair nobody has decided on
+    pub fn status(&self, pair: &str) -> Option<PairStatus> {
+        self.pairs.get(pair).map(|record| record.status)
+    }
+
+    /// Note a book of normalized `pair`
+    pub fn observe_book(&mut self, pair: &str, book: &OrderBook, now: DateTime<Utc>) -> Admission {
+        if !self.enabled {
+            return Admission::Analyze;
+        }
+        if let Some(record) = self.pairs.get_mut(pair) {
+            record.books += 1;
+            let new_venue = record.venues.insert(book.exchange.to_lowercase());
+            let status = record.status;
+            if new_venue && status == PairStatus::Pending {
+                self.persist();
+            }
+            return if status == PairStatus::Blocked { Admission::Drop } else { Admission::Analyze };
+        }
+        if self.known.contains(pair) {
+            return Admission::Analyze;
+        }
+        self.pairs.insert(
+            pair.to_string(),
+            OnboardedPair {
+                status: PairStatus::Pending,
+                metadata: InstrumentMetadata::from_book(pair, book),
+                first_seen: now,
+                venues: BTreeSet::from([book.exchange.to_lowercase()]),
+                books: 1,
+                decided_by: None,
+                decided_at: None,
+                reason: None,
+            },
+        );
+        self.persist();
+        Admission::Onboarded
+    }
+
+    /// What the thresholds of routes on normalized `pair` are multiplied by
+    pub fn threshold_multiplier(&self, pair: &str) -> f64 {
+        if self.enabled && self.status(pair) == Some(PairStatus::Pending) {
+            self.threshold_multiplier
+        } else {
+            1.0
+        }
+    }
+
+    fn decide(&mut self, pair: &str, status: PairStatus, actor: &str, reason: Option<String>, now: DateTime<Utc>) -> Result<String> {
+        let pair = pair.trim().to_uppercase().replace("WBTC", "BTC");
+        if !pair.contains('/') {
+            return Err(anyhow!("expected a pair like BTC/USDT, got {:?}", pair));
+        }
+        // A pair can be decided on before its first book
+        let record = self.pairs.entry(pair.clone()).or_insert_with(|| OnboardedPair {
+            status,
+            metadata: InstrumentMetadata::for_pair(&pair),
+            first_seen: now,
+            venues: BTreeSet::new(),
+            books: 0,
+            decided_by: None,
+            decided_at: None,
+            reason: None,
+        });
+        record.status = status;
+        record.decided_by = Some(actor.to_string());
+        record.decided_at = Some(now);
+        record.reason = reason;
+        self.save()?;
+        Ok(pair)
+    }
+
+    fn persist(&self) {
+        if let Err(e) = self.save() {
+            warn!("{}", e);
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        // Write then rename, so a crash mid-write can't leave a half-written file behind
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&self.pairs)?)?;
+        fs::rename(&tmp, path).map_err(|e| anyhow!("pair onboarding state not persisted to {}: {}", path.display(), e))
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pairs.values().filter(|record| record.status == PairStatus::Pending).count()
+    }
+
+    /// Pending pairs first, then the decided ones
+    pub fn report(&self) -> serde_json::Value {
+        let by_status = |status: PairStatus| -> BTreeMap<&String, &OnboardedPair> {
+            self.pairs.iter().filter(|(_, record)| record.status == status).collect()
+        };
+        serde_json::json!({
+            "enabled": self.enabled,
+            "threshold_multiplier": self.threshold_multiplier,
+            "pending": by_status(PairStatus::Pending),
+            "confirmed": by_status(PairStatus::Confirmed),
+            "blocked": by_status(PairStatus::Blocked),
+            "known": self.known,
+        })
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Onboard the pair of `book` if it is new; false when its books are to be dropped
+    pub(crate) fn observe_pair_book(&mut self, book: &OrderBook, now: DateTime<Utc>) -> bool {
+        let pair = book.pair.replace("WBTC", "BTC");
+        match self.onboarding.observe_book(&pair, book, now) {
+            Admission::Analyze => true,
+            Admission::Drop => false,
+            Admission::Onboarded => {
+                self.metrics.pending_pairs.store(self.onboarding.pending() as u64, Ordering::Relaxed);
+                let message = format!(
+                    "New pair {} from {}: analyzed with thresholds x{} until it is confirmed or blocked",
+                    pair, book.exchange, self.onboarding.threshold_multiplier
+                );
+                warn!("{}", message);
+                self.publish(Event::new(EventClass::PairOnboarding, Severity::Warning, message).with_venue(&book.exchange).with_pair(&pair));
+                true
+            }
+        }
+    }
+
+    pub(crate) fn confirm_pair(&mut self, pair: &str, actor: &str) -> Result<()> {
+        let pair = self.onboarding.decide(pair, PairStatus::Confirmed, actor, None, Utc::now())?;
+        self.metrics.pending_pairs.store(self.onboarding.pending() as u64, Ordering::Relaxed);
+        info!("Pair {} confirmed by {}", pair, actor);
+        Ok(())
+    }
+
+    /// Block `pair` and drop its cached books
+    pub(crate) fn block_pair(&mut self, pair: &str, reason: Option<String>, actor: &str) -> Result<()> {
+        let pair = self.onboarding.decide(pair, PairStatus::Blocked, actor, reason, Utc::now())?;
+        self.metrics.pending_pairs.store(self.onboarding.pending() as u64, Ordering::Relaxed);
+        self.books.retain(|_, book| book.pair.replace("WBTC", "BTC") != pair);
+        self.enforce_book_budget("");
+        warn!("Pair {} blocked by {}", pair, actor);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::ControlCommand;
+
+    fn temp_pairs() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("swapsleuth-pairs-{}.json", uuid::Uuid::new_v4()))
+    }
+
+    fn onboarding_analyzer(path: &std::path::Path) -> SpreadAnalyzer {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.onboarding = PairOnboarding::new(true, ["BTC/USDT".to_string()], 2.0, Some(path.to_path_buf()));
+        analyzer
+    }
+
+    fn pepe() -> OrderBook {
+        OrderBook::for_test("binance", "PEPE/USDT", vec![vec![0.00001234, 1e9]], vec![vec![0.0000124, 1e9]])
+    }
+
+    #[test]
+    fn new_pairs_are_held_to_a_higher_threshold() {
+        let path = temp_pairs();
+        let mut analyzer = onboarding_analyzer(&path);
+        let now = Utc::now();
+        assert!(analyzer.observe_pair_book(&OrderBook::for_test("okx", "WBTC/USDT", vec![vec![1.0, 1.0]], vec![vec![1.1, 1.0]]), now));
+        assert!(analyzer.observe_pair_book(&pepe(), now));
+        assert_eq!(analyzer.metrics.pending_pairs.load(Ordering::Relaxed), 1);
+        let record = &analyzer.onboarding.pairs["PEPE/USDT"];
+        assert_eq!((record.metadata.quote.as_str(), record.metadata.price_decimals), ("USDT", Some(8)));
+        assert_eq!(analyzer.onboarding.threshold_multiplier("PEPE/USDT"), 2.0);
+        assert_eq!(analyzer.onboarding.threshold_multiplier("BTC/USDT"), 1.0);
+
+        // Pending pairs survive a restart
+        let restarted = PairOnboarding::new(true, [], 2.0, Some(path.clone()));
+        assert_eq!(restarted.status("PEPE/USDT"), Some(PairStatus::Pending));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn confirmed_pairs_trade_at_the_normal_threshold() {
+        let path = temp_pairs();
+        let mut analyzer = onboarding_analyzer(&path);
+        analyzer.observe_pair_book(&pepe(), Utc::now());
+        let command = serde_json::from_str::<ControlCommand>(r#"{"command":"confirm_pair","pair":"pepe/usdt","actor":"ops"}"#).unwrap();
+        analyzer.apply_control_command(command, "control").unwrap();
+        assert_eq!(analyzer.onboarding.threshold_multiplier("PEPE/USDT"), 1.0);
+        assert_eq!(analyzer.metrics.pending_pairs.load(Ordering::Relaxed), 0);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn blocked_pairs_are_dropped_with_their_books() {
+        let path = temp_pairs();
+        let mut analyzer = onboarding_analyzer(&path);
+        let pepe = pepe();
+        analyzer.observe_pair_book(&pepe, Utc::now());
+        analyzer.books.insert("binance:PEPE/USDT".to_string(), pepe.clone());
+        analyzer.block_pair("PEPE/USDT", Some("illiquid".to_string()), "control:ops").unwrap();
+        assert!(analyzer.books.is_empty());
+        assert!(!analyzer.observe_pair_book(&pepe, Utc::now()));
+        assert!(!PairOnboarding::new(false, [], 2.0, Some(path.clone())).enabled);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+// Route overrides: adjustments operators pin per route that win over the
+// automatic model. ROUTE_OVERRIDES is a `;`-separated list of
+// `<PAIR>[:<buy>><sell>] <setting>...`, a route entry winning over its pair's:
+//   extra_bps=<bps>     extra assumed cost, in bps of the bought notional, on top of the fees
+//   min_profit=<usd>    minimum net profit instead of the global one
+//   disabled            never report the route
+//   since=<YYYY-MM-DD>  when it was set, so its age shows
+// e.g. `BTC/USDT:binance>uniswap-v3-exact extra_bps=5 min_profit=25; PEPE/USDT disabled`.
+// Every override is listed with the market summary, with how often it applied and
+// when it last did, so one that is stale or no longer matches anything stands out.
+
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+use crate::config;
+
+#[derive(Debug, Default)]
+pub struct RouteOverride {
+    // `PAIR` or `PAIR:buy>sell`
+    pub key: String,
+    pub extra_bps: f64,
+    pub min_profit: Option<f64>,
+    pub disabled: bool,
+    pub since: Option<NaiveDate>,
+    // Evaluations of a matching route, and the time of the last one in ms
+    applied: AtomicU64,
+    last_applied_ms: AtomicI64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OverrideReport {
+    pub key: String,
+    pub extra_bps: f64,
+    pub min_profit: Option<f64>,
+    pub disabled: bool,
+    pub since: Option<NaiveDate>,
+    pub applied: u64,
+    pub last_applied: Option<DateTime<Utc>>,
+}
+
+impl RouteOverride {
+    fn parse(entry: &str) -> Result<Self> {
+        let mut parts = entry.split_whitespace();
+        let key = parts.next().ok_or_else(|| anyhow!("empty override"))?;
+        let mut route_override = RouteOverride { key: key.to_string(), ..RouteOverride::default() };
+        for setting in parts {
+            let number = |value: &str| value.parse::<f64>().ok().filter(|v| v.is_finite()).ok_or_else(|| anyhow!("invalid number in {:?}", setting));
+            match setting.split_once('=') {
+                Some(("extra_bps", value)) => route_override.extra_bps = number(value)?,
+                Some(("min_profit", value)) => route_override.min_profit = Some(number(value)?),
+                Some(("since", value)) => {
+                    route_override.since = Some(NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| anyhow!("invalid date in {:?}: {}", setting, e))?)
+                }
+                None if setting == "disabled" => route_override.disabled = true,
+                _ => return Err(anyhow!("unknown setting {:?} for {}", setting, key)),
+            }
+        }
+        Ok(route_override)
+    }
+
+    fn mark_applied(&self, now: DateTime<Utc>) {
+        self.applied.fetch_add(1, Ordering::Relaxed);
+        self.last_applied_ms.store(now.timestamp_millis(), Ordering::Relaxed);
+    }
+
+    /// The settings, e.g. `+5bps, min $25`
+    pub fn describe(&self) -> String {
+        let mut settings = Vec::new();
+        if self.disabled {
+            settings.push("disabled".to_string());
+        }
+        if self.extra_bps != 0.0 {
+            settings.push(format!("{:+}bps", self.extra_bps));
+        }
+        if let Some(min_profit) = self.min_profit {
+            settings.push(format!("min ${}", min_profit));
+        }
+        if settings.is_empty() { "no-op".to_string() } else { settings.join(", ") }
+    }
+
+    pub fn report(&self) -> OverrideReport {
+        let last_applied_ms = self.last_applied_ms.load(Ordering::Relaxed);
+        OverrideReport {
+            key: self.key.clone(),
+            extra_bps: self.extra_bps,
+            min_profit: self.min_profit,
+            disabled: self.disabled,
+            since: self.since,
+            applied: self.applied.load(Ordering::Relaxed),
+            last_applied: (last_applied_ms > 0).then(|| DateTime::from_timestamp_millis(last_applied_ms)).flatten(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RouteOverrides {
+    // In configuration order
+    entries: Vec<RouteOverride>,
+}
+
+impl RouteOverrides {
+    pub fn parse(raw: &str) -> Result<Self> {
+        let entries = raw
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| RouteOverride::parse(entry).map_err(|e| anyhow!("ROUTE_OVERRIDES: {}", e)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(RouteOverrides { entries })
+    }
+
+    /// A malformed entry fails startup rather than leave a route on the automatic model unnoticed
+    pub fn from_env() -> Result<Self> {
+        config::env_var("ROUTE_OVERRIDES").map_or_else(|_| Ok(RouteOverrides::default()), |raw| RouteOverrides::parse(&raw))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> &[RouteOverride] {
+        &self.entries
+    }
+
+    /// The override for a route: its own entry, else its pair's
+    pub fn for_route(&self, pair: &str, buy_exchange: &str, sell_exchange: &str) -> Option<&RouteOverride> {
+        let route = format!("{}:{}>{}", pair, buy_exchange, sell_exchange);
+        self.entries.iter().find(|entry| entry.key == route).or_else(|| self.entries.iter().find(|entry| entry.key == pair))
+    }
+
+    /// Count an evaluation of the route against its override, if it has one
+    pub fn record_applied(&self, pair: &str, buy_exchange: &str, sell_exchange: &str, now: DateTime<Utc>) {
+        if let Some(route_override) = self.for_route(pair, buy_exchange, sell_exchange) {
+            route_override.mark_applied(now);
+        }
+    }
+}
+
+/// Net profit a route has to clear, `global` unless its override pins one
+pub fn min_profit(route_override: Option<&RouteOverride>, global: f64) -> f64 {
+    route_override.and_then(|o| o.min_profit).unwrap_or(global)
+}
+
+/// Assumed extra cost of trading `notional` on a route with `route_override`
+pub fn extra_cost(route_override: Option<&RouteOverride>, notional: f64) -> f64 {
+    route_override.map_or(0.0, |o| notional * o.extra_bps / 10_000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpreadAnalyzer;
+
+    fn overrides() -> RouteOverrides {
+        RouteOverrides::parse("BTC/USDT:binance>okx extra_bps=30 since=2024-05-01; BTC/USDT min_profit=1000; PEPE/USDT disabled").unwrap()
+    }
+
+    #[test]
+    fn routes_match_the_most_specific_override() {
+        let overrides = overrides();
+        assert_eq!(overrides.for_route("BTC/USDT", "binance", "okx").map(|o| o.describe()).as_deref(), Some("+30bps"));
+        assert_eq!(overrides.for_route("BTC/USDT", "okx", "binance").map(|o| o.key.as_str()), Some("BTC/USDT"));
+        assert!(overrides.for_route("ETH/USDT", "binance", "okx").is_none());
+    }
+
+    #[test]
+    fn unknown_settings_and_bad_dates_are_refused() {
+        assert!(RouteOverrides::parse("BTC/USDT extra_bsp=5").is_err());
+        assert!(RouteOverrides::parse("BTC/USDT since=May").is_err());
+    }
+
+    #[test]
+    fn overrides_win_over_the_model() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let baseline = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        analyzer.route_overrides = overrides();
+        let padded = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        assert!((padded.estimated_fees - baseline.estimated_fees - padded.max_size * 50_000.0 * 0.003).abs() < 1e-6);
+        // $1000 minimum on the reverse route, and PEPE is off
+        assert!(analyzer.evaluate_opportunity("okx", "binance", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).is_none());
+        assert!(analyzer.evaluate_opportunity("binance", "okx", "PEPE/USDT", 0.00001, 0.000011, 1e9, 1e9).is_none());
+
+        let reports: Vec<OverrideReport> = analyzer.route_overrides.entries().iter().map(RouteOverride::report).collect();
+        assert_eq!(reports.iter().map(|r| r.applied).collect::<Vec<_>>(), vec![1, 1, 1]);
+        assert!(reports.iter().all(|r| r.last_applied.is_some()));
+    }
+}
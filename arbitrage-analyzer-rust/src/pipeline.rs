@@ -0,0 +1,514 @@
+// Two-stage update pipeline. The ingestion stage (a listener task per source plus
+// one fetch task, on the analyzer's tokio I/O runtime) turns pub/sub messages into
+// validated books; the analysis stage applies them to the cache and looks for
+// spreads on the thread that called `run`. They are connected by a bounded queue
+// so a slow comprehensive analysis never stalls the pub/sub readers. With pair
+// priorities attached, the queue hands out books of the highest-priority pair
+// first (oldest first among equals) instead of strictly in arrival order. The
+// fetch task GETs books over one multiplexed connection per source and only
+// reconnects after one fails.
+
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::debug;
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use tokio::runtime::Handle;
+
+use crate::ingest_stats;
+use crate::metrics::Metrics;
+use crate::priority::PairPriorities;
+use crate::sources::{self, RedisSource};
+use crate::stage_timing::Stage;
+use crate::subscription::{Notification, PayloadHandler};
+use crate::throttle::LogThrottle;
+use crate::{codec, OrderBook};
+
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    // Discard the oldest queued event of the lowest priority to make room; ingestion never waits
+    DropOldest,
+    // Ingestion waits for the analysis stage to catch up
+    Block,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "drop_oldest" => Ok(OverflowPolicy::DropOldest),
+            "block" => Ok(OverflowPolicy::Block),
+            other => Err(anyhow!("unknown overflow policy: {}", other)),
+        }
+    }
+}
+
+/// What the ingestion stage hands to the analysis stage
+#[derive(Debug)]
+pub enum IngestEvent {
+    Book { key: String, book: OrderBook },
+    // A book for `exchange` arrived but failed validation
+    Rejected { key: String, exchange: String, reason: String },
+}
+
+pub enum Pop {
+    Event(IngestEvent),
+    Timeout,
+    // The ingestion stage is gone and the queue is drained
+    Closed,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    events: VecDeque<IngestEvent>,
+    closed: bool,
+}
+
+/// Bounded queue between the stages. A book whose key is already queued replaces
+/// the queued one in place, so only the newest version of a book ever waits.
+#[derive(Debug)]
+pub struct BookQueue {
+    capacity: usize,
+    policy: OverflowPolicy,
+    state: Mutex<QueueState>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    metrics: Arc<Metrics>,
+    priorities: Option<Arc<PairPriorities>>,
+}
+
+impl IngestEvent {
+    // Rejections carry no book; they are cheap and feed venue stats, so they go first
+    fn priority(&self, priorities: &PairPriorities, now: DateTime<Utc>) -> i64 {
+        match self {
+            IngestEvent::Book { book, .. } => priorities.priority(&book.pair, now),
+            IngestEvent::Rejected { .. } => i64::MAX,
+        }
+    }
+}
+
+impl BookQueue {
+    pub fn new(capacity: usize, policy: OverflowPolicy, metrics: Arc<Metrics>) -> Self {
+        BookQueue {
+            capacity: capacity.max(1),
+            policy,
+            state: Mutex::new(QueueState::default()),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            metrics,
+            priorities: None,
+        }
+    }
+
+    pub fn with_priorities(mut self, priorities: Arc<PairPriorities>) -> Self {
+        self.priorities = Some(priorities);
+        self
+    }
+
+    // Index of the first event with the highest (or lowest) priority; plain FIFO without priorities
+    fn pick(&self, events: &VecDeque<IngestEvent>, highest: bool) -> usize {
+        let Some(priorities) = &self.priorities else { return 0 };
+        let now = Utc::now();
+        let mut best: Option<(usize, i64)> = None;
+        for (idx, event) in events.iter().enumerate() {
+            let priority = event.priority(priorities, now);
+            if best.is_none_or(|(_, p)| if highest { priority > p } else { priority < p }) {
+                best = Some((idx, priority));
+            }
+        }
+        best.map_or(0, |(idx, _)| idx)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn push(&self, event: IngestEvent) {
+        let mut state = self.lock();
+
+        if let IngestEvent::Book { key, .. } = &event {
+            let queued = state.events.iter_mut().find(|e| matches!(e, IngestEvent::Book { key: k, .. } if k == key));
+            if let Some(slot) = queued {
+                *slot = event;
+                Metrics::inc(&self.metrics.pipeline_coalesced);
+                return;
+            }
+        }
+
+        while state.events.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    let idx = self.pick(&state.events, false);
+                    state.events.remove(idx);
+                    Metrics::inc(&self.metrics.pipeline_dropped);
+                }
+                OverflowPolicy::Block => {
+                    Metrics::inc(&self.metrics.pipeline_blocked);
+                    state = self.not_full.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+                }
+            }
+        }
+
+        state.events.push_back(event);
+        self.metrics.pipeline_queue_depth.store(state.events.len() as u64, Ordering::Relaxed);
+        self.not_empty.notify_one();
+    }
+
+    pub fn pop_timeout(&self, timeout: Duration) -> Pop {
+        let mut state = self.lock();
+        if state.events.is_empty() && !state.closed {
+            state = self
+                .not_empty
+                .wait_timeout(state, timeout)
+                .map(|(guard, _)| guard)
+                .unwrap_or_else(|poisoned| poisoned.into_inner().0);
+        }
+
+        let idx = self.pick(&state.events, true);
+        match state.events.remove(idx) {
+            Some(event) => {
+                self.metrics.pipeline_queue_depth.store(state.events.len() as u64, Ordering::Relaxed);
+                self.not_full.notify_one();
+                Pop::Event(event)
+            }
+            None if state.closed => Pop::Closed,
+            None => Pop::Timeout,
+        }
+    }
+
+    /// Events currently waiting for the analysis stage
+    pub fn len(&self) -> usize {
+        self.lock().events.len()
+    }
+
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.not_empty.notify_all();
+    }
+}
+
+/// Ingestion stage: reads notifications from every source, fetches and validates books
+pub struct Ingestor {
+    sources: Vec<RedisSource>,
+    // Multiplexed GET connection per source, opened on first use and dropped when a command fails
+    connections: Vec<Option<MultiplexedConnection>>,
+    log_throttle: Arc<LogThrottle>,
+    metrics: Arc<Metrics>,
+}
+
+impl Ingestor {
+    pub fn new(sources: Vec<RedisSource>, log_throttle: Arc<LogThrottle>, metrics: Arc<Metrics>) -> Self {
+        let connections = sources.iter().map(|_| None).collect();
+        Ingestor { sources, connections, log_throttle, metrics }
+    }
+
+    /// Run the stage as tasks on `runtime`, feeding `queue` until every source listener is gone
+    pub fn spawn(mut self, runtime: &Handle, queue: Arc<BookQueue>) {
+        runtime.spawn(async move {
+            let updates = sources::spawn_listeners(&self.sources, self.metrics.clone());
+            #[cfg(feature = "chaos")]
+            let updates = crate::chaos::wrap(updates);
+            let mut updates = updates;
+            while let Some(msg) = updates.recv().await {
+                if let Some(event) = self.ingest(msg).await {
+                    // A full queue under the block policy waits for analysis; the listeners keep running meanwhile
+                    tokio::task::block_in_place(|| queue.push(event));
+                }
+            }
+            queue.close();
+        });
+    }
+
+    async fn ingest(&mut self, msg: sources::SourceMessage) -> Option<IngestEvent> {
+        let source = &self.sources[msg.source];
+        let channel = msg.channel;
+        let payload = msg.payload;
+
+        debug!("Received message from {} on {}: {}", source.name, channel, payload);
+
+        // Parsing the key from the payload
+        let handler = source.subscription.handler_for(&channel, msg.pattern.as_deref());
+        let notification = match self.metrics.time(Stage::Parse, || parse_notification(&payload, handler)) {
+            Ok(notification) => notification,
+            Err(e) => {
+                self.log_throttle.error(
+                    &format!("parse_key:{}", channel),
+                    format_args!("Failed to parse key from payload on {}: {}", channel, e),
+                );
+                return None;
+            }
+        };
+        let key = notification.key;
+        if !source.accepts_key(&key) {
+            debug!("Ignoring {} from {}: outside its key pattern", key, source.name);
+            return None;
+        }
+
+        let orderbook = match notification.book {
+            // The collector embedded the book, no need for a second round trip
+            Some(book) => {
+                Metrics::inc(&self.metrics.embedded_book_updates);
+                book
+            }
+            None => match self.fetch_book(msg.source, &key, notification.version).await {
+                Ok(book) => book,
+                Err(rejected) => {
+                    return rejected.map(|exchange| IngestEvent::Rejected { key, exchange, reason: "unparseable JSON".to_string() })
+                }
+            },
+        };
+
+        let event = self.metrics.time(Stage::Validate, || admit_book(key, orderbook, &self.sources[msg.source].name, Utc::now()));
+        if let IngestEvent::Rejected { key, reason, .. } = &event {
+            self.log_throttle.error(&format!("rejected:{}", key), format_args!("Rejected orderbook {}: {}", key, reason));
+        }
+        Some(event)
+    }
+
+    /// GET and parse the book behind `key` from the source it was announced on.
+    /// On failure the reason has been logged; the error names the exchange to count a rejection against, if any.
+    async fn fetch_book(&mut self, source: usize, key: &str, version: Option<i64>) -> Result<OrderBook, Option<String>> {
+        let name = &self.sources[source].name;
+        let redis_con = match &mut self.connections[source] {
+            Some(con) => con,
+            slot => match self.sources[source].client.get_multiplexed_tokio_connection().await {
+                Ok(con) => slot.insert(con),
+                Err(e) => {
+                    Metrics::inc(&self.metrics.redis_errors);
+                    self.log_throttle.error(&format!("connect:{}", name), format_args!("Failed to connect to source {}: {}", name, e));
+                    return Err(None);
+                }
+            },
+        };
+
+        let mut refetched = false;
+        loop {
+            let json_data: String = match self.metrics.time_async(Stage::Fetch, redis_con.get(key)).await {
+                Ok(data) => data,
+                Err(e) => {
+                    self.log_throttle.error(&format!("fetch:{}", key), format_args!("Failed to fetch orderbook {}: {}", key, e));
+                    // A missing key is not the connection's fault
+                    if e.is_io_error() || e.is_connection_dropped() || e.is_timeout() {
+                        Metrics::inc(&self.metrics.redis_errors);
+                        self.connections[source] = None;
+                    }
+                    return Err(None);
+                }
+            };
+
+            // parse the orderbook
+            let orderbook: OrderBook = match self.metrics.time(Stage::Parse, || codec::decode_book(json_data)) {
+                Ok(ob) => ob,
+                Err(e) => {
+                    self.log_throttle.error(
+                        &format!("parse_book:{}", key),
+                        format_args!("Failed to parse orderbook JSON for {}: {}", key, e),
+                    );
+                    return Err(ingest_stats::exchange_from_key(key).map(str::to_string));
+                }
+            };
+
+            // The key can be read mid-overwrite; an older book than announced gets one more GET
+            match version {
+                Some(version) if orderbook.timestamp < version && !refetched => {
+                    Metrics::inc(&self.metrics.stale_book_refetches);
+                    refetched = true;
+                }
+                Some(version) if orderbook.timestamp < version => {
+                    Metrics::inc(&self.metrics.stale_book_rejections);
+                    self.log_throttle.warn(
+                        &format!("stale_read:{}", key),
+                        format_args!(
+                            "Dropping update for {}: fetched timestamp {} is older than notified version {}",
+                            key, orderbook.timestamp, version
+                        ),
+                    );
+                    return Err(None);
+                }
+                _ => return Ok(orderbook),
+            }
+        }
+    }
+}
+
+/// Validate a decoded book and stamp it with its source and arrival time
+pub fn admit_book(key: String, mut orderbook: OrderBook, source: &str, now: DateTime<Utc>) -> IngestEvent {
+    // JSON can't carry NaN, but a collector can still send overflowing numbers
+    if orderbook.has_non_finite_values() {
+        return IngestEvent::Rejected { key, exchange: orderbook.exchange, reason: "NaN or infinite values".to_string() };
+    }
+    // A crossed book would pair its own bid with other venues' asks as a phantom spread
+    if orderbook.is_crossed() {
+        return IngestEvent::Rejected { key, exchange: orderbook.exchange, reason: "crossed book".to_string() };
+    }
+    orderbook.received_at = Some(now);
+    orderbook.source = Some(source.to_string());
+    IngestEvent::Book { key, book: orderbook }
+}
+
+pub fn parse_notification(payload: &str, handler: &PayloadHandler) -> Result<Notification> {
+    let json_value = serde_json::from_str::<serde_json::Value>(payload).ok();
+
+    // Collectors in embedded mode publish the book itself, either bare or under "book"
+    let embedded = json_value.as_ref().and_then(|v| {
+        let candidate = v.get("book").unwrap_or(v);
+        candidate.get("bids")?;
+        serde_json::from_value::<OrderBook>(candidate.clone()).ok()
+    });
+    if let Some(book) = embedded {
+        let key = json_value
+            .as_ref()
+            .and_then(|v| v.get("key"))
+            .and_then(|k| k.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("orderbook:{}:{}", book.exchange, book.pair));
+        return Ok(Notification { key, version: Some(book.timestamp), book: Some(book) });
+    }
+
+    // Collectors may announce the book timestamp they just wrote so we can detect reading an older one
+    let version = json_value.as_ref().and_then(|v| v.get("version")).and_then(|v| v.as_i64());
+
+    let key = match handler {
+        PayloadHandler::Key => json_value
+            .as_ref()
+            .and_then(|v| v.get("key"))
+            .and_then(|k| k.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| payload.to_string()),
+        PayloadHandler::JsonField(field) => json_value
+            .ok_or_else(|| anyhow!("payload is not JSON"))?
+            .get(field)
+            .and_then(|k| k.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("payload has no string field '{}'", field))?,
+    };
+    Ok(Notification { key, version, book: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn book_event(key: &str, timestamp: i64) -> IngestEvent {
+        let mut book = OrderBook::for_test("binance", "BTC/USDT", vec![vec![1.0, 1.0]], vec![vec![1.1, 1.0]]);
+        book.timestamp = timestamp;
+        IngestEvent::Book { key: key.to_string(), book }
+    }
+
+    fn popped_timestamp(queue: &BookQueue) -> Option<i64> {
+        match queue.pop_timeout(Duration::ZERO) {
+            Pop::Event(IngestEvent::Book { book, .. }) => Some(book.timestamp),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn queued_books_coalesce_by_key() {
+        let queue = BookQueue::new(8, OverflowPolicy::Block, Arc::new(Metrics::default()));
+        queue.push(book_event("a", 1));
+        queue.push(book_event("b", 2));
+        queue.push(book_event("a", 3));
+
+        assert_eq!(popped_timestamp(&queue), Some(3));
+        assert_eq!(popped_timestamp(&queue), Some(2));
+        assert!(matches!(queue.pop_timeout(Duration::ZERO), Pop::Timeout));
+    }
+
+    #[test]
+    fn drop_oldest_makes_room_and_close_drains() {
+        let metrics = Arc::new(Metrics::default());
+        let queue = BookQueue::new(2, OverflowPolicy::DropOldest, metrics.clone());
+        queue.push(book_event("a", 1));
+        queue.push(book_event("b", 2));
+        queue.push(book_event("c", 3));
+        queue.close();
+
+        assert_eq!(metrics.pipeline_dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(popped_timestamp(&queue), Some(2));
+        assert_eq!(popped_timestamp(&queue), Some(3));
+        assert!(matches!(queue.pop_timeout(Duration::ZERO), Pop::Closed));
+    }
+
+    #[test]
+    fn higher_priority_pairs_are_served_first_and_dropped_last() {
+        let priorities = Arc::new(PairPriorities::new(std::collections::HashMap::from([("ETH/USDT".to_string(), 2)]), chrono::Duration::hours(24)));
+        let queue = BookQueue::new(3, OverflowPolicy::DropOldest, Arc::new(Metrics::default())).with_priorities(priorities.clone());
+        let pair_event = |key: &str, pair: &str, timestamp: i64| {
+            let mut book = OrderBook::for_test("binance", pair, vec![vec![1.0, 1.0]], vec![vec![1.1, 1.0]]);
+            book.timestamp = timestamp;
+            IngestEvent::Book { key: key.to_string(), book }
+        };
+        queue.push(pair_event("a", "BTC/USDT", 1));
+        queue.push(pair_event("b", "ETH/USDT", 2));
+        queue.push(pair_event("c", "BTC/USDT", 3));
+        // Full: the oldest BTC book makes room, not the older ETH one
+        queue.push(pair_event("d", "ETH/USDT", 4));
+
+        assert_eq!(popped_timestamp(&queue), Some(2));
+        // Adjusted at runtime: BTC now outranks the queued ETH book
+        priorities.set("BTC/USDT", Some(3));
+        assert_eq!(popped_timestamp(&queue), Some(3));
+        assert_eq!(popped_timestamp(&queue), Some(4));
+        assert!(matches!(queue.pop_timeout(Duration::ZERO), Pop::Timeout));
+    }
+
+    #[test]
+    fn block_waits_for_the_analysis_stage() {
+        let queue = Arc::new(BookQueue::new(1, OverflowPolicy::Block, Arc::new(Metrics::default())));
+        queue.push(book_event("a", 1));
+
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.push(book_event("b", 2)))
+        };
+        assert_eq!(popped_timestamp(&queue), Some(1));
+        producer.join().unwrap();
+        assert_eq!(popped_timestamp(&queue), Some(2));
+    }
+
+    #[test]
+    fn notifications_carry_key_and_optional_version() {
+        let plain = parse_notification("orderbook:binance:BTC/USDT", &PayloadHandler::Key).unwrap();
+        assert_eq!(plain.key, "orderbook:binance:BTC/USDT");
+        assert_eq!(plain.version, None);
+        assert!(plain.book.is_none());
+
+        let versioned = parse_notification(r#"{"key":"orderbook:binance:BTC/USDT","version":42}"#, &PayloadHandler::Key)
+            .unwrap();
+        assert_eq!(versioned.version, Some(42));
+
+        let field = PayloadHandler::JsonField("book_key".to_string());
+        assert!(parse_notification("orderbook:binance:BTC/USDT", &field).is_err());
+        assert_eq!(parse_notification(r#"{"book_key":"k"}"#, &field).unwrap().key, "k");
+    }
+
+    #[test]
+    fn embedded_books_skip_the_fetch() {
+        let book_json = r#"{"exchange":"binance","pair":"BTC/USDT","bids":[[49990.0,1.0]],"asks":[[50000.0,1.0]],"timestamp":7}"#;
+
+        let bare = parse_notification(book_json, &PayloadHandler::Key).unwrap();
+        assert_eq!(bare.key, "orderbook:binance:BTC/USDT");
+        assert_eq!(bare.version, Some(7));
+        assert_eq!(bare.book.map(|b| b.bids.len()), Some(1));
+
+        let wrapped = parse_notification(&format!(r#"{{"key":"custom","book":{}}}"#, book_json), &PayloadHandler::Key)
+            .unwrap();
+        assert_eq!(wrapped.key, "custom");
+        assert!(wrapped.book.is_some());
+
+        // A payload that merely mentions bids but isn't a book falls back to the key path
+        let broken = parse_notification(r#"{"key":"k","bids":"nope"}"#, &PayloadHandler::Key).unwrap();
+        assert_eq!(broken.key, "k");
+        assert!(broken.book.is_none());
+    }
+}
@@ -0,0 +1,169 @@
+// Venue cost, anomaly scoring and enrichment plugins, compiled in with `--features
+// venue-plugins`. To price a venue the fee model has no schedule for, implement
+// `VenueCostModel` for it below and add it to `venue_cost_models`; see
+// venue_costs.rs for how each cost is used. To score books with a model of your
+// own (an ONNX model loaded through `tract`, a rule set), implement
+// `AnomalyScorer` and add it to `anomaly_scorers`; see anomaly.rs for how scores
+// are used. To annotate opportunities, implement `Enricher` and add it to
+// `enrichers`; see enrichment.rs for when it runs. To work large requests in
+// child orders your own way, implement `SlicePlanner`, add it to
+// `slice_planners` and name it in SLICE_PLANNER; see slicing.rs. The shipped
+// plugins are examples: their venue name matches no collector, so they price,
+// score, annotate and slice nothing until renamed.
+
+use std::sync::Arc;
+
+use crate::anomaly::AnomalyScorer;
+use crate::enrichment::Enricher;
+use crate::slicing::SlicePlanner;
+use crate::venue_costs::VenueCostModel;
+
+#[cfg(feature = "venue-plugins")]
+pub fn venue_cost_models() -> Vec<Arc<dyn VenueCostModel>> {
+    vec![Arc::new(example::RegionalExchange)]
+}
+
+/// Without plugins in the build every venue is priced by the fee model
+#[cfg(not(feature = "venue-plugins"))]
+pub fn venue_cost_models() -> Vec<Arc<dyn VenueCostModel>> {
+    Vec::new()
+}
+
+#[cfg(feature = "venue-plugins")]
+pub fn anomaly_scorers() -> Vec<Arc<dyn AnomalyScorer>> {
+    vec![Arc::new(example::RegionalBookCheck)]
+}
+
+/// Without plugins in the build only the ANOMALY_SCORERS named score books
+#[cfg(not(feature = "venue-plugins"))]
+pub fn anomaly_scorers() -> Vec<Arc<dyn AnomalyScorer>> {
+    Vec::new()
+}
+
+#[cfg(feature = "venue-plugins")]
+pub fn enrichers() -> Vec<Box<dyn Enricher>> {
+    vec![Box::new(example::RegionalSettlement)]
+}
+
+/// Without plugins in the build only the built-in enrichers run
+#[cfg(not(feature = "venue-plugins"))]
+pub fn enrichers() -> Vec<Box<dyn Enricher>> {
+    Vec::new()
+}
+
+#[cfg(feature = "venue-plugins")]
+pub fn slice_planners() -> Vec<Arc<dyn SlicePlanner>> {
+    vec![Arc::new(example::RegionalClips)]
+}
+
+/// Without plugins in the build only the built-in `twap` planner can be picked
+#[cfg(not(feature = "venue-plugins"))]
+pub fn slice_planners() -> Vec<Arc<dyn SlicePlanner>> {
+    Vec::new()
+}
+
+#[cfg(feature = "venue-plugins")]
+mod example {
+    use chrono::{DateTime, Duration, Utc};
+    use serde_json::json;
+
+    use crate::anomaly::AnomalyScorer;
+    use crate::enrichment::Enricher;
+    use crate::slicing::{SliceInput, SlicePlan, SlicePlanner};
+    use crate::venue_costs::VenueCostModel;
+    use crate::{ArbitrageOpportunity, OrderBook, SpreadAnalyzer};
+
+    // A regional spot exchange: flat ticket fee, a bank wire to reach any other
+    // venue, and T+1 settlement of fills
+    #[derive(Debug)]
+    pub struct RegionalExchange;
+
+    impl VenueCostModel for RegionalExchange {
+        fn venue(&self) -> &str {
+            "example-regional"
+        }
+
+        fn trading_fee_pct(&self, taker: bool) -> f64 {
+            if taker {
+                0.25
+            } else {
+                0.15
+            }
+        }
+
+        fn fixed_cost_usd(&self) -> f64 {
+            2.0
+        }
+
+        fn transfer_cost_usd(&self, _counterparty: &str) -> f64 {
+            25.0
+        }
+
+        fn latency_ms(&self) -> Option<f64> {
+            Some(350.0)
+        }
+
+        fn settlement_secs(&self) -> f64 {
+            86_400.0
+        }
+    }
+
+    // The regional venue quotes in round lots: a book with an odd-sized top level,
+    // or none at all, is likely a partial snapshot
+    #[derive(Debug)]
+    pub struct RegionalBookCheck;
+
+    impl AnomalyScorer for RegionalBookCheck {
+        fn name(&self) -> &str {
+            "example-regional-lots"
+        }
+
+        fn score(&self, book: &OrderBook, _previous: Option<&OrderBook>) -> Option<f64> {
+            if book.exchange != "example-regional" {
+                return None;
+            }
+            let round_lot = |level: Option<(f64, f64)>| level.is_some_and(|(_, size)| size > 0.0 && size.fract() == 0.0);
+            Some(if round_lot(book.best_bid()) && round_lot(book.best_ask()) { 0.0 } else { 0.8 })
+        }
+    }
+
+    // Routes through the regional venue settle T+1; tell the executor which leg waits
+    #[derive(Debug)]
+    pub struct RegionalSettlement;
+
+    impl Enricher for RegionalSettlement {
+        fn name(&self) -> &'static str {
+            "example-regional-settlement"
+        }
+
+        fn enrich(&self, _: &SpreadAnalyzer, opportunities: &mut [ArbitrageOpportunity], _: DateTime<Utc>) {
+            for opp in opportunities {
+                for (leg, venue) in [("buy", &opp.buy_exchange), ("sell", &opp.sell_exchange)] {
+                    if venue == "example-regional" {
+                        opp.annotations.insert("settlement".to_string(), json!({ "leg": leg, "days": 1 }));
+                    }
+                }
+            }
+        }
+    }
+
+    // The regional venue caps orders at one unit: routes through it go out one unit
+    // a minute, at the priced VWAPs
+    #[derive(Debug)]
+    pub struct RegionalClips;
+
+    impl SlicePlanner for RegionalClips {
+        fn name(&self) -> &str {
+            "example-regional-clips"
+        }
+
+        fn plan(&self, input: &SliceInput) -> Option<SlicePlan> {
+            let opp = input.opportunity;
+            if opp.buy_exchange != "example-regional" && opp.sell_exchange != "example-regional" {
+                return None;
+            }
+            let count = (input.size.ceil() as usize).min(60);
+            (count > 1).then(|| SlicePlan::even(self.name(), input, count, Duration::minutes(1), 0.0, 1))
+        }
+    }
+}
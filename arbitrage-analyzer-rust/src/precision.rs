@@ -0,0 +1,224 @@
+// Precision policy: how many decimals each asset's amounts and each pair's prices
+// are quoted in, and which way values are rounded to get there. Prices and sizes
+// are rounded once, where an opportunity leaves the analyzer (published
+// opportunities, their events and alerts, execution requests and their plans), so
+// what an executor receives is what the venue accepts; fees and profit stay as
+// estimated on the unrounded values. Reports print with the same decimals.
+//
+//  - ASSET_PRECISION: decimals by asset, or by `venue/ASSET` for a venue that
+//    differs, e.g. `BTC:6,ETH:4,USDT:2,kraken/BTC:5`. A size uses its base asset's
+//    decimals on the coarser of its two venues.
+//  - PRICE_PRECISION: decimals by pair, or by `venue/PAIR`, e.g. `BTC/USDT:2,DOGE/USDT:5`.
+//  - SIZE_ROUNDING / PRICE_ROUNDING: `conservative` (default: sizes and sell
+//    prices down, buy prices up, so no leg asks for more than the quote gave),
+//    `down`, `up` or `nearest`.
+// Anything not listed keeps full precision and is formatted as before.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use anyhow::anyhow;
+
+use crate::{config, ArbitrageOpportunity};
+
+static POLICY: OnceLock<PrecisionPolicy> = OnceLock::new();
+
+/// The policy installed at startup, or one that rounds nothing
+pub fn policy() -> &'static PrecisionPolicy {
+    static NONE: OnceLock<PrecisionPolicy> = OnceLock::new();
+    POLICY.get().unwrap_or_else(|| NONE.get_or_init(PrecisionPolicy::default))
+}
+
+/// Make `policy` the one every output uses; only the first call takes effect
+pub fn install(policy: PrecisionPolicy) {
+    let _ = POLICY.set(policy);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoundingMode {
+    #[default]
+    Conservative,
+    Down,
+    Up,
+    Nearest,
+}
+
+impl FromStr for RoundingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "conservative" => Ok(RoundingMode::Conservative),
+            "down" => Ok(RoundingMode::Down),
+            "up" => Ok(RoundingMode::Up),
+            "nearest" => Ok(RoundingMode::Nearest),
+            other => Err(anyhow!("unknown rounding mode: {}", other)),
+        }
+    }
+}
+
+/// `value` to `decimals` places; `up` picks the direction for the modes that need one
+fn round(value: f64, decimals: u32, mode: RoundingMode, up: bool) -> f64 {
+    if !value.is_finite() {
+        return value;
+    }
+    let scale = 10f64.powi(decimals as i32);
+    let scaled = value * scale;
+    // Values already on a step, give or take float noise, stay where they are
+    let rounded = match mode {
+        _ if (scaled - scaled.round()).abs() < 1e-9 => scaled.round(),
+        RoundingMode::Nearest => scaled.round(),
+        RoundingMode::Up => scaled.ceil(),
+        RoundingMode::Down => scaled.floor(),
+        RoundingMode::Conservative if up => scaled.ceil(),
+        RoundingMode::Conservative => scaled.floor(),
+    };
+    rounded / scale
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PrecisionPolicy {
+    // By asset or `venue/ASSET`
+    asset_decimals: HashMap<String, u32>,
+    // By pair or `venue/PAIR`
+    price_decimals: HashMap<String, u32>,
+    size_rounding: RoundingMode,
+    price_rounding: RoundingMode,
+}
+
+impl PrecisionPolicy {
+    pub fn from_env() -> Self {
+        PrecisionPolicy {
+            asset_decimals: config::env_map::<u32>("ASSET_PRECISION").into_iter().map(|(key, decimals)| (upper_asset(&key), decimals)).collect(),
+            price_decimals: config::env_map("PRICE_PRECISION"),
+            size_rounding: config::env_or("SIZE_ROUNDING", RoundingMode::Conservative),
+            price_rounding: config::env_or("PRICE_ROUNDING", RoundingMode::Conservative),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.asset_decimals.is_empty() && self.price_decimals.is_empty()
+    }
+
+    fn lookup(map: &HashMap<String, u32>, venue: &str, key: &str) -> Option<u32> {
+        map.get(&format!("{}/{}", venue, key)).or_else(|| map.get(key)).copied()
+    }
+
+    /// Decimals of `asset` amounts on `venue`
+    pub fn asset_decimals(&self, venue: &str, asset: &str) -> Option<u32> {
+        Self::lookup(&self.asset_decimals, venue, &asset.to_uppercase())
+    }
+
+    /// Decimals a size of `pair` takes on every one of `venues`: the coarsest of them
+    pub fn size_decimals(&self, pair: &str, venues: &[&str]) -> Option<u32> {
+        let base = pair.split('/').next().unwrap_or_default();
+        venues.iter().filter_map(|venue| self.asset_decimals(venue, base)).min()
+    }
+
+    pub fn price_decimals(&self, venue: &str, pair: &str) -> Option<u32> {
+        Self::lookup(&self.price_decimals, venue, pair)
+    }
+
+    pub fn round_size(&self, pair: &str, venues: &[&str], size: f64) -> f64 {
+        self.size_decimals(pair, venues).map_or(size, |decimals| round(size, decimals, self.size_rounding, false))
+    }
+
+    /// A limit price on `venue`; conservative rounding moves it away from the spread
+    pub fn round_price(&self, venue: &str, pair: &str, price: f64, buying: bool) -> f64 {
+        self.price_decimals(venue, pair).map_or(price, |decimals| round(price, decimals, self.price_rounding, buying))
+    }
+
+    /// `opp` with prices and sizes its venues accept
+    pub fn round_opportunity(&self, opp: &ArbitrageOpportunity) -> ArbitrageOpportunity {
+        let mut rounded = opp.clone();
+        if self.is_empty() {
+            return rounded;
+        }
+        rounded.buy_price = self.round_price(&opp.buy_exchange, &opp.pair, opp.buy_price, true);
+        rounded.sell_price = self.round_price(&opp.sell_exchange, &opp.pair, opp.sell_price, false);
+        rounded.max_size = self.round_size(&opp.pair, &[&opp.buy_exchange, &opp.sell_exchange], opp.max_size);
+        // What reaches the sell leg shrinks with the size bought
+        let arriving = if opp.max_size > 0.0 { opp.sell_size * rounded.max_size / opp.max_size } else { 0.0 };
+        rounded.sell_size = self.round_size(&opp.pair, &[&opp.sell_exchange], arriving).min(arriving);
+        for point in &mut rounded.size_ladder {
+            point.size = self.round_size(&opp.pair, &[&opp.buy_exchange, &opp.sell_exchange], point.size);
+            point.buy_price = self.round_price(&opp.buy_exchange, &opp.pair, point.buy_price, true);
+            point.sell_price = self.round_price(&opp.sell_exchange, &opp.pair, point.sell_price, false);
+        }
+        rounded
+    }
+
+    pub fn format_price(&self, venue: &str, pair: &str, price: f64) -> Option<String> {
+        self.price_decimals(venue, pair).map(|decimals| format!("{:.*}", decimals as usize, price))
+    }
+
+    pub fn format_size(&self, pair: &str, venues: &[&str], size: f64) -> Option<String> {
+        self.size_decimals(pair, venues).map(|decimals| format!("{:.*}", decimals as usize, size))
+    }
+}
+
+// `venue/asset` keys keep the venue as written
+fn upper_asset(key: &str) -> String {
+    match key.rsplit_once('/') {
+        Some((venue, asset)) => format!("{}/{}", venue, asset.to_uppercase()),
+        None => key.to_uppercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpreadAnalyzer;
+
+    fn policy() -> PrecisionPolicy {
+        PrecisionPolicy {
+            asset_decimals: HashMap::from([("BTC".to_string(), 5), ("okx/BTC".to_string(), 3)]),
+            price_decimals: HashMap::from([("BTC/USDT".to_string(), 1)]),
+            ..PrecisionPolicy::default()
+        }
+    }
+
+    fn opportunity() -> ArbitrageOpportunity {
+        let analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let mut opp = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.04, 51_000.06, 1.23456, 1.3).unwrap();
+        opp.max_size = 0.98765;
+        opp
+    }
+
+    #[test]
+    fn sizes_use_the_coarsest_venue_step() {
+        let policy = policy();
+        assert_eq!(policy.size_decimals("BTC/USDT", &["binance", "okx"]), Some(3));
+        assert_eq!(policy.size_decimals("ETH/USDT", &["binance"]), None);
+    }
+
+    #[test]
+    fn rounds_opportunities_against_the_trader() {
+        let opp = opportunity();
+        let rounded = policy().round_opportunity(&opp);
+        // Buy up, sell down, size down
+        assert_eq!(rounded.buy_price, 50_000.1);
+        assert_eq!(rounded.sell_price, 51_000.0);
+        assert_eq!(rounded.max_size, 0.987);
+        assert!(rounded.sell_size <= opp.sell_size * 0.987 / 0.98765);
+        assert_eq!((rounded.net_profit, rounded.estimated_fees), (opp.net_profit, opp.estimated_fees));
+        assert_eq!(PrecisionPolicy::default().round_opportunity(&opp).buy_price, opp.buy_price);
+    }
+
+    #[test]
+    fn formats_to_the_venue_precision() {
+        let policy = policy();
+        assert_eq!(policy.format_price("binance", "BTC/USDT", 50_000.1).as_deref(), Some("50000.1"));
+        assert_eq!(policy.format_size("BTC/USDT", &["binance"], 0.5).as_deref(), Some("0.50000"));
+    }
+
+    #[test]
+    fn rounding_modes_and_float_noise() {
+        let policy = policy();
+        let nearest = PrecisionPolicy { size_rounding: RoundingMode::Nearest, ..policy.clone() };
+        assert_eq!(nearest.round_size("BTC/USDT", &["okx"], 0.9876), 0.988);
+        // Already on a step despite float noise
+        assert_eq!(policy.round_size("BTC/USDT", &["okx"], 0.1 + 0.2), 0.3);
+    }
+}
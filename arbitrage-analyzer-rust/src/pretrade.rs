@@ -0,0 +1,343 @@
+// Pre-trade checks. Every execution request, direct or netted, goes through the
+// same pipeline before it is opened, logged and published; the first check that
+// fails blocks it, and the names of the ones passed are attached to the request
+// (`pre_trade_checks`) so the executor and the audit trail see what was verified.
+// In order:
+//  - breaker: the kill switch is not tripped,
+//  - canary: no leg is on a venue in canary (CANARY_VENUES, see canary.rs),
+//  - route_feasibility: the venues allow the trade and transfer, and the ROI
+//    clears the bar of their latency (VENUE_STATUS_*, LATENCY_PROBE_VENUES),
+//  - balance: the venue balances left cover the size (VENUE_BALANCES),
+//  - risk_limits: capital at risk per request (MAX_REQUEST_NOTIONAL_USD),
+//    requests in flight (MAX_IN_FLIGHT_REQUESTS), capital in flight on
+//    correlated assets (MAX_CORRELATED_NOTIONAL_USD, see correlation.rs) and
+//    the route's learned success rate (MIN_SUCCESS_PROBABILITY, see
+//    success_rate.rs),
+//  - instrument_rules: each leg's size, once rounded to its venues' precision
+//    (see precision.rs), is not zero and meets its venue's minimums
+//    (INSTRUMENT_MIN_SIZE, INSTRUMENT_MIN_NOTIONAL, both `venue:value` lists),
+//  - gas_guard: no DEX leg may pay more than its chain's cap, in the chain's
+//    unit (GAS_GUARD_MAX_FEE, e.g. `ethereum:80,solana:200000`).
+// Limits left unset don't block. PRE_TRADE_CHECKS picks and orders the checks
+// that run, e.g. `breaker,balance,gas_guard`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::{bail, Result};
+
+use crate::correlation::route_asset;
+use crate::lifecycle::RouteKey;
+use crate::{config, contention, ExecutionRequest, SpreadAnalyzer};
+
+pub trait PreTradeCheck: fmt::Debug + Send {
+    fn name(&self) -> &'static str;
+    /// Why `request` must not go out, if it must not
+    fn check(&self, analyzer: &SpreadAnalyzer, request: &ExecutionRequest) -> Result<(), String>;
+}
+
+#[derive(Debug)]
+struct Breaker;
+
+impl PreTradeCheck for Breaker {
+    fn name(&self) -> &'static str {
+        "breaker"
+    }
+
+    fn check(&self, analyzer: &SpreadAnalyzer, _: &ExecutionRequest) -> Result<(), String> {
+        if analyzer.halted_by_kill_switch() {
+            return Err("kill switch tripped".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Canary;
+
+impl PreTradeCheck for Canary {
+    fn name(&self) -> &'static str {
+        "canary"
+    }
+
+    fn check(&self, analyzer: &SpreadAnalyzer, request: &ExecutionRequest) -> Result<(), String> {
+        let legs = analyzer.canary_legs(&request.opportunity);
+        if !legs.is_empty() {
+            return Err(format!("{} in canary until promoted", legs.join(" and ")));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct RouteFeasibility;
+
+impl PreTradeCheck for RouteFeasibility {
+    fn name(&self) -> &'static str {
+        "route_feasibility"
+    }
+
+    fn check(&self, analyzer: &SpreadAnalyzer, request: &ExecutionRequest) -> Result<(), String> {
+        analyzer.route_feasibility(&request.opportunity)
+    }
+}
+
+#[derive(Debug)]
+struct Balance;
+
+impl PreTradeCheck for Balance {
+    fn name(&self) -> &'static str {
+        "balance"
+    }
+
+    fn check(&self, analyzer: &SpreadAnalyzer, request: &ExecutionRequest) -> Result<(), String> {
+        let fitting = analyzer.balances.fitting_size(&contention::needs(&request.opportunity), request.execution_size);
+        if fitting < request.execution_size {
+            return Err(format!("venue balances only fit {} of {}", fitting, request.execution_size));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct RiskLimits {
+    // 0: no limit
+    max_request_usd: f64,
+    max_in_flight: usize,
+    max_correlated_usd: f64,
+    min_success_probability: f64,
+}
+
+impl PreTradeCheck for RiskLimits {
+    fn name(&self) -> &'static str {
+        "risk_limits"
+    }
+
+    fn check(&self, analyzer: &SpreadAnalyzer, request: &ExecutionRequest) -> Result<(), String> {
+        if self.max_in_flight > 0 && analyzer.lifecycle.in_flight().len() >= self.max_in_flight {
+            return Err(format!("{} requests already in flight", self.max_in_flight));
+        }
+        if self.min_success_probability > 0.0 {
+            let opp = &request.opportunity;
+            let success = analyzer.success_rates.estimate(&RouteKey::new(&opp.pair, &opp.buy_exchange, &opp.sell_exchange));
+            if success.probability < self.min_success_probability {
+                return Err(format!(
+                    "success probability {:.2} ({} of {} filled at expected profit) is under the {:.2} minimum",
+                    success.probability, success.succeeded, success.published, self.min_success_probability
+                ));
+            }
+        }
+        if self.max_request_usd <= 0.0 && self.max_correlated_usd <= 0.0 {
+            return Ok(());
+        }
+        let opp = &request.opportunity;
+        let Some(usd) = analyzer.notional_usd(opp, request.execution_size) else {
+            return Err(format!("capital at risk on {} has no USD price to hold against the limit", opp.pair));
+        };
+        if self.max_request_usd > 0.0 && usd > self.max_request_usd {
+            return Err(format!("${:.2} at risk is over the ${:.2} limit", usd, self.max_request_usd));
+        }
+        if self.max_correlated_usd > 0.0 {
+            let asset = route_asset(&opp.pair);
+            let exposure = usd + analyzer.correlated_exposure_usd(&asset);
+            if exposure > self.max_correlated_usd {
+                return Err(format!(
+                    "${:.2} at risk on {} and correlated assets is over the ${:.2} limit",
+                    exposure, asset, self.max_correlated_usd
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct InstrumentRules {
+    // By venue; size in base units, notional in the quote asset
+    min_size: HashMap<String, f64>,
+    min_notional: HashMap<String, f64>,
+}
+
+impl PreTradeCheck for InstrumentRules {
+    fn name(&self) -> &'static str {
+        "instrument_rules"
+    }
+
+    fn check(&self, _: &SpreadAnalyzer, request: &ExecutionRequest) -> Result<(), String> {
+        let opp = &request.opportunity;
+        if request.execution_size <= 0.0 || opp.sell_size <= 0.0 {
+            return Err(format!("{} rounds to nothing at the venues' precision", opp.max_size));
+        }
+        // The sell leg is the base that arrives, net of transfer fees
+        let sell_size = request.execution_size * opp.sell_size / opp.max_size;
+        for (venue, size, price) in [(&opp.buy_exchange, request.execution_size, opp.buy_price), (&opp.sell_exchange, sell_size, opp.sell_price)] {
+            if let Some(min) = self.min_size.get(venue).filter(|&&min| size < min) {
+                return Err(format!("{} on {} is under its minimum size of {}", size, venue, min));
+            }
+            if let Some(min) = self.min_notional.get(venue).filter(|&&min| size * price < min) {
+                return Err(format!("{:.2} notional on {} is under its minimum of {}", size * price, venue, min));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct GasGuard {
+    // By chain name, in the chain's unit (gwei per gas, lamports per swap)
+    max_fee: HashMap<String, f64>,
+}
+
+impl PreTradeCheck for GasGuard {
+    fn name(&self) -> &'static str {
+        "gas_guard"
+    }
+
+    fn check(&self, _: &SpreadAnalyzer, request: &ExecutionRequest) -> Result<(), String> {
+        for leg in &request.gas {
+            if let Some(cap) = self.max_fee.get(leg.quote.chain).filter(|&&cap| leg.quote.max_fee > cap) {
+                return Err(format!(
+                    "{} leg may pay up to {} {}, over the {} cap of {}",
+                    leg.venue, leg.quote.max_fee, leg.quote.unit, leg.quote.chain, cap
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct PreTradeChecks {
+    checks: Vec<Box<dyn PreTradeCheck>>,
+}
+
+impl Default for PreTradeChecks {
+    /// Every check, with no limits
+    fn default() -> Self {
+        PreTradeChecks {
+            checks: vec![
+                Box::new(Breaker),
+                Box::new(Canary),
+                Box::new(RouteFeasibility),
+                Box::new(Balance),
+                Box::new(RiskLimits::default()),
+                Box::new(InstrumentRules::default()),
+                Box::new(GasGuard::default()),
+            ],
+        }
+    }
+}
+
+impl PreTradeChecks {
+    pub fn from_env() -> Result<Self> {
+        let mut available: Vec<Box<dyn PreTradeCheck>> = vec![
+            Box::new(Breaker),
+            Box::new(Canary),
+            Box::new(RouteFeasibility),
+            Box::new(Balance),
+            Box::new(RiskLimits {
+                max_request_usd: config::env_or("MAX_REQUEST_NOTIONAL_USD", 0.0),
+                max_in_flight: config::env_or("MAX_IN_FLIGHT_REQUESTS", 0),
+                max_correlated_usd: config::env_or("MAX_CORRELATED_NOTIONAL_USD", 0.0),
+                min_success_probability: config::env_or("MIN_SUCCESS_PROBABILITY", 0.0),
+            }),
+            Box::new(InstrumentRules { min_size: config::env_map("INSTRUMENT_MIN_SIZE"), min_notional: config::env_map("INSTRUMENT_MIN_NOTIONAL") }),
+            Box::new(GasGuard { max_fee: config::env_map("GAS_GUARD_MAX_FEE") }),
+        ];
+        let Ok(selected) = config::env_var("PRE_TRADE_CHECKS") else {
+            return Ok(PreTradeChecks { checks: available });
+        };
+        let mut checks = Vec::new();
+        for name in selected.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match available.iter().position(|check| check.name() == name) {
+                Some(index) => checks.push(available.remove(index)),
+                None => bail!("PRE_TRADE_CHECKS: unknown or repeated check {:?}", name),
+            }
+        }
+        Ok(PreTradeChecks { checks })
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.checks.iter().map(|check| check.name()).collect()
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Run `request` through every pre-trade check: the names of the checks it passed, or
+    /// the first one that blocked it and why
+    pub(crate) fn run_pre_trade_checks(&self, request: &ExecutionRequest) -> Result<Vec<&'static str>, (&'static str, String)> {
+        let mut passed = Vec::with_capacity(self.pre_trade.checks.len());
+        for check in &self.pre_trade.checks {
+            check.check(self, request).map_err(|reason| (check.name(), reason))?;
+            passed.push(check.name());
+        }
+        Ok(passed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gas::{GasQuote, LegGas};
+    use chrono::Utc;
+
+    fn analyzer_and_request() -> (SpreadAnalyzer, ExecutionRequest) {
+        let analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let opp = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        let request = analyzer.execution_request(&opp, None, Utc::now());
+        (analyzer, request)
+    }
+
+    #[test]
+    fn every_default_check_passes_a_plain_request() {
+        let (analyzer, request) = analyzer_and_request();
+        assert_eq!(
+            analyzer.run_pre_trade_checks(&request),
+            Ok(vec!["breaker", "canary", "route_feasibility", "balance", "risk_limits", "instrument_rules", "gas_guard"])
+        );
+    }
+
+    #[test]
+    fn first_failing_check_blocks_the_request() {
+        let (mut analyzer, mut request) = analyzer_and_request();
+        analyzer.pre_trade = PreTradeChecks {
+            checks: vec![
+                Box::new(RiskLimits { max_request_usd: 1_000_000.0, max_in_flight: 0, max_correlated_usd: 0.0, min_success_probability: 0.0 }),
+                Box::new(InstrumentRules { min_size: HashMap::new(), min_notional: HashMap::from([("okx".to_string(), 10.0)]) }),
+                Box::new(GasGuard { max_fee: HashMap::from([("ethereum".to_string(), 80.0)]) }),
+            ],
+        };
+        assert_eq!(analyzer.run_pre_trade_checks(&request), Ok(vec!["risk_limits", "instrument_rules", "gas_guard"]));
+
+        request.gas.push(LegGas {
+            venue: "uniswap".to_string(),
+            quote: GasQuote {
+                chain: "ethereum",
+                strategy: "fixed/2".to_string(),
+                unit: "gwei",
+                priority_fee: 2.0,
+                escalation_step: None,
+                max_priority_fee: 2.0,
+                base_fee: 60.0,
+                max_fee: 122.0,
+            },
+        });
+        assert_eq!(analyzer.run_pre_trade_checks(&request).map_err(|(check, _)| check), Err("gas_guard"));
+
+        request.execution_size = 0.0001;
+        assert_eq!(analyzer.run_pre_trade_checks(&request).map_err(|(check, _)| check), Err("instrument_rules"));
+        request.execution_size = 100.0;
+        assert_eq!(analyzer.run_pre_trade_checks(&request).map_err(|(check, _)| check), Err("risk_limits"));
+    }
+
+    #[test]
+    fn routes_need_the_minimum_success_probability() {
+        let (mut analyzer, request) = analyzer_and_request();
+        // A route with no record sits at the prior's 0.5 until a fill lifts it
+        analyzer.pre_trade = PreTradeChecks { checks: vec![Box::new(RiskLimits { min_success_probability: 0.6, ..RiskLimits::default() })] };
+        assert_eq!(analyzer.run_pre_trade_checks(&request).map_err(|(check, _)| check), Err("risk_limits"));
+        analyzer.success_rates.published("filled", RouteKey::new("BTC/USDT", "binance", "okx"));
+        analyzer.success_rates.close("filled", true, None);
+        assert_eq!(analyzer.run_pre_trade_checks(&request), Ok(vec!["risk_limits"]));
+    }
+}
@@ -0,0 +1,170 @@
+// Per-pair analysis priorities. When a backlog forms, the queue between ingestion
+// and analysis hands out books of higher-priority pairs first, and drops
+// lower-priority ones first when it overflows. Under load shedding only pairs with
+// a priority above zero are analyzed (see `shedding`).
+//
+// A pair's priority is configured (PAIR_PRIORITIES, or at runtime over the control
+// channel) or else learned from its history: every expired opportunity adds its
+// peak net profit to a score that halves every PRIORITY_HALF_LIFE_HOURS, and the
+// learned priority is the order of magnitude of that score in USD (about $10 of
+// recent profit is 1, $100 is 2, nothing is 0).
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::config;
+
+const DEFAULT_HALF_LIFE_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, Copy)]
+struct Learned {
+    score: f64,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    configured: HashMap<String, i64>,
+    learned: HashMap<String, Learned>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PairPriority {
+    pub pair: String,
+    pub priority: i64,
+    pub configured: Option<i64>,
+    // Decayed profit score behind the learned priority
+    pub learned_score: f64,
+}
+
+#[derive(Debug)]
+pub struct PairPriorities {
+    half_life: Duration,
+    state: Mutex<State>,
+}
+
+fn normalize(pair: &str) -> String {
+    pair.to_uppercase().replace("WBTC", "BTC")
+}
+
+impl PairPriorities {
+    pub fn new(configured: HashMap<String, i64>, half_life: Duration) -> Self {
+        let configured = configured.into_iter().map(|(pair, priority)| (normalize(&pair), priority)).collect();
+        PairPriorities { half_life, state: Mutex::new(State { configured, learned: HashMap::new() }) }
+    }
+
+    pub fn from_env() -> Self {
+        PairPriorities::new(
+            config::env_map("PAIR_PRIORITIES"),
+            Duration::hours(config::env_or("PRIORITY_HALF_LIFE_HOURS", DEFAULT_HALF_LIFE_HOURS)),
+        )
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn decayed(&self, learned: &Learned, now: DateTime<Utc>) -> f64 {
+        let half_lives = (now - learned.updated_at).num_milliseconds() as f64 / self.half_life.num_milliseconds().max(1) as f64;
+        learned.score * 0.5_f64.powf(half_lives.max(0.0))
+    }
+
+    fn resolve(&self, state: &State, pair: &str, now: DateTime<Utc>) -> i64 {
+        if let Some(priority) = state.configured.get(pair) {
+            return *priority;
+        }
+        let score = state.learned.get(pair).map_or(0.0, |l| self.decayed(l, now));
+        if score < 10.0 { 0 } else { score.log10().floor() as i64 }
+    }
+
+    pub fn priority(&self, pair: &str, now: DateTime<Utc>) -> i64 {
+        self.resolve(&self.lock(), &normalize(pair), now)
+    }
+
+    /// Whether any pair currently has a priority above zero
+    pub fn any_prioritized(&self, now: DateTime<Utc>) -> bool {
+        let state = self.lock();
+        state.configured.values().any(|p| *p > 0) || state.learned.keys().any(|pair| self.resolve(&state, pair, now) > 0)
+    }
+
+    /// Override the priority of `pair`, or go back to the learned one with None
+    pub fn set(&self, pair: &str, priority: Option<i64>) {
+        let mut state = self.lock();
+        match priority {
+            Some(priority) => state.configured.insert(normalize(pair), priority),
+            None => state.configured.remove(&normalize(pair)),
+        };
+    }
+
+    /// Credit `pair` with the profit of one opportunity
+    pub fn learn(&self, pair: &str, net_profit: f64, now: DateTime<Utc>) {
+        if !net_profit.is_finite() || net_profit <= 0.0 {
+            return;
+        }
+        let mut state = self.lock();
+        let previous = state.learned.get(&normalize(pair)).map_or(0.0, |l| self.decayed(l, now));
+        state.learned.insert(normalize(pair), Learned { score: previous + net_profit, updated_at: now });
+    }
+
+    /// Every pair with a configured or learned priority, highest first
+    pub fn report(&self, now: DateTime<Utc>) -> Vec<PairPriority> {
+        let state = self.lock();
+        let pairs: BTreeSet<&String> = state.configured.keys().chain(state.learned.keys()).collect();
+        let mut report: Vec<PairPriority> = pairs
+            .into_iter()
+            .map(|pair| PairPriority {
+                pair: pair.clone(),
+                priority: self.resolve(&state, pair, now),
+                configured: state.configured.get(pair).copied(),
+                learned_score: state.learned.get(pair).map_or(0.0, |l| self.decayed(l, now)),
+            })
+            .collect();
+        report.sort_by_key(|p| std::cmp::Reverse(p.priority));
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn priorities() -> PairPriorities {
+        PairPriorities::new(HashMap::from([("wbtc/usdt".to_string(), 5)]), Duration::hours(24))
+    }
+
+    #[test]
+    fn configured_priorities_apply_to_the_canonical_pair() {
+        let start = Utc::now();
+        let priorities = priorities();
+        assert_eq!(priorities.priority("BTC/USDT", start), 5);
+        assert_eq!(priorities.priority("ETH/USDT", start), 0);
+        assert!(priorities.any_prioritized(start));
+    }
+
+    #[test]
+    fn learned_priorities_decay() {
+        let start = Utc::now();
+        let priorities = priorities();
+        priorities.learn("ETH/USDT", 150.0, start);
+        priorities.learn("ETH/USDT", 150.0, start);
+        assert_eq!(priorities.priority("ETH/USDT", start), 2);
+        // $300 halves to $75 over two days
+        assert_eq!(priorities.priority("ETH/USDT", start + Duration::hours(48)), 1);
+    }
+
+    #[test]
+    fn set_priorities_override_learned_ones() {
+        let start = Utc::now();
+        let priorities = priorities();
+        priorities.learn("ETH/USDT", 300.0, start);
+        priorities.set("ETH/USDT", Some(-1));
+        priorities.set("BTC/USDT", None);
+        assert_eq!(priorities.priority("ETH/USDT", start), -1);
+        assert_eq!(priorities.priority("BTC/USDT", start), 0);
+        assert!(!priorities.any_prioritized(start));
+        assert_eq!(priorities.report(start).len(), 1);
+    }
+}
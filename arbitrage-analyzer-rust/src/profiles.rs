@@ -0,0 +1,189 @@
+// Account profiles: what one set of exchange accounts looks like to the fee model
+// (API key, VIP tier, fee overrides and discounts, withdrawal whitelist), loaded
+// from the JSON file at ACCOUNT_PROFILES_FILE. ACCOUNT_PROFILE picks the profile
+// routes are evaluated under, so the same analyzer can be run against different
+// account assumptions. Its name is attached to every execution request so the
+// executor trades from the accounts the route was priced for.
+//
+// A profile can also carry a tenant and strategy id (under `tags` in the same
+// file, or TENANT / STRATEGY_ID for the whole deployment). Both are attached to
+// every opportunity, execution request and expiry, so an executor shared by
+// several profiles or deployments can attribute and segregate the orders.
+//
+// API keys are referenced by the name of the environment variable holding them;
+// the analyzer never reads the secret itself.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_PROFILES_FILE: &str = "account-profiles.json";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VenueAccount {
+    pub api_key_env: Option<String>,
+    pub vip_tier: Option<u32>,
+    // Percentages like the rest of FeesConfig; unset keeps the analyzer default
+    pub taker_fee: Option<f64>,
+    pub maker_fee: Option<f64>,
+    // Applied on top, e.g. 25 for paying fees in BNB
+    #[serde(default)]
+    pub fee_discount_pct: f64,
+    // Assets this account may withdraw; unset means no restriction
+    pub withdrawal_whitelist: Option<Vec<String>>,
+    // Holds inventory of the traded assets, so legs on it don't wait for a transfer
+    #[serde(default)]
+    pub prefunded: bool,
+}
+
+// Who an opportunity is for. Flattened into the messages, and left out when unset
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct StrategyTag {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy_id: Option<String>,
+}
+
+impl StrategyTag {
+    /// The tag of `profile`, with TENANT / STRATEGY_ID filling in what it leaves unset
+    pub fn resolve(profile: Option<&AccountProfile>) -> Self {
+        let tag = profile.map(|p| p.tag.clone()).unwrap_or_default();
+        StrategyTag {
+            tenant: tag.tenant.or_else(|| crate::config::env_var("TENANT").ok()),
+            strategy_id: tag.strategy_id.or_else(|| crate::config::env_var("STRATEGY_ID").ok()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tenant.is_none() && self.strategy_id.is_none()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountProfile {
+    pub name: String,
+    pub venues: HashMap<String, VenueAccount>,
+    pub tag: StrategyTag,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfilesFile {
+    profiles: HashMap<String, HashMap<String, VenueAccount>>,
+    // Keyed by profile name
+    #[serde(default)]
+    tags: HashMap<String, StrategyTag>,
+}
+
+impl AccountProfile {
+    /// Trading fee percentage on `exchange` under this profile, given the analyzer default
+    pub fn trading_fee_pct(&self, exchange: &str, use_market_orders: bool, default: f64) -> f64 {
+        let Some(account) = self.venues.get(exchange) else {
+            return default;
+        };
+        let listed = if use_market_orders { account.taker_fee } else { account.maker_fee };
+        listed.unwrap_or(default) * (1.0 - account.fee_discount_pct / 100.0)
+    }
+
+    /// Whether `asset` may be withdrawn from `exchange`. WBTC and BTC are interchangeable, like in fee lookups
+    pub fn can_withdraw(&self, exchange: &str, asset: &str) -> bool {
+        let Some(whitelist) = self.venues.get(exchange).and_then(|a| a.withdrawal_whitelist.as_ref()) else {
+            return true;
+        };
+        let normalize = |s: &str| s.to_uppercase().replace("WBTC", "BTC");
+        whitelist.iter().any(|allowed| normalize(allowed) == normalize(asset))
+    }
+
+    /// Whether both venues of a route hold inventory, so it trades without moving funds
+    pub fn is_prefunded(&self, buy_exchange: &str, sell_exchange: &str) -> bool {
+        [buy_exchange, sell_exchange].iter().all(|exchange| self.venues.get(*exchange).is_some_and(|a| a.prefunded))
+    }
+
+    /// Load profile `name` from `path`
+    pub fn load(path: &Path, name: &str) -> Result<Self> {
+        let raw = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let mut file: ProfilesFile = serde_json::from_str(&raw).with_context(|| format!("parsing {}", path.display()))?;
+        let venues = file
+            .profiles
+            .remove(name)
+            .ok_or_else(|| anyhow!("no profile {:?} in {}", name, path.display()))?;
+
+        for (exchange, account) in &venues {
+            let fees = [account.taker_fee, account.maker_fee];
+            if fees.iter().flatten().any(|fee| !fee.is_finite() || *fee < 0.0) {
+                return Err(anyhow!("profile {}: invalid fee for {}", name, exchange));
+            }
+            if !(0.0..=100.0).contains(&account.fee_discount_pct) {
+                return Err(anyhow!("profile {}: fee_discount_pct for {} must be within 0-100", name, exchange));
+            }
+        }
+        let tag = file.tags.remove(name).unwrap_or_default();
+        Ok(AccountProfile { name: name.to_string(), venues, tag })
+    }
+
+    /// The profile named by ACCOUNT_PROFILE, if set. A named profile that cannot be loaded is an error
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(name) = crate::config::env_var("ACCOUNT_PROFILE") else {
+            return Ok(None);
+        };
+        Self::load_named(&name).map(Some)
+    }
+
+    /// Load profile `name` from ACCOUNT_PROFILES_FILE
+    pub fn load_named(name: &str) -> Result<Self> {
+        let path = crate::config::env_var("ACCOUNT_PROFILES_FILE").unwrap_or_else(|_| DEFAULT_PROFILES_FILE.to_string());
+        Self::load(Path::new(&path), name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profiles_file(contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("swapsleuth-profiles-{}.json", uuid::Uuid::new_v4()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn resolves_fees_and_withdrawals_per_profile() {
+        let path = profiles_file(
+            r#"{"profiles": {
+                "vip": {"binance": {"api_key_env": "BINANCE_VIP_KEY", "vip_tier": 2, "taker_fee": 0.08,
+                                    "fee_discount_pct": 25, "withdrawal_whitelist": ["WBTC", "USDT"]}},
+                "retail": {}
+            },
+            "tags": {"vip": {"tenant": "desk-a", "strategy_id": "cex-spread"}}}"#,
+        );
+        let vip = AccountProfile::load(&path, "vip").unwrap();
+        assert!((vip.trading_fee_pct("binance", true, 0.1) - 0.06).abs() < 1e-12);
+        // No maker override: the default is still discounted
+        assert!((vip.trading_fee_pct("binance", false, 0.1) - 0.075).abs() < 1e-12);
+        assert_eq!(vip.trading_fee_pct("okx", true, 0.1), 0.1);
+        assert!(vip.can_withdraw("binance", "BTC") && !vip.can_withdraw("binance", "ETH"));
+        assert!(vip.can_withdraw("okx", "ETH"));
+        assert!(!vip.is_prefunded("binance", "okx"));
+        assert_eq!(vip.tag.tenant.as_deref(), Some("desk-a"));
+        assert_eq!(StrategyTag::resolve(Some(&vip)).strategy_id.as_deref(), Some("cex-spread"));
+
+        let retail = AccountProfile::load(&path, "retail").unwrap();
+        assert!(retail.venues.is_empty() && retail.tag.is_empty());
+        assert!(AccountProfile::load(&path, "missing").is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_profiles() {
+        let path = profiles_file(r#"{"profiles": {"bad": {"binance": {"fee_discount_pct": 150}}}}"#);
+        assert!(AccountProfile::load(&path, "bad").is_err());
+        fs::write(&path, r#"{"profiles": {"typo": {"binance": {"taker_fees": 0.1}}}}"#).unwrap();
+        assert!(AccountProfile::load(&path, "typo").is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+// What the market is worth, not just how much of it is covered: each comprehensive
+// run totals the net profit of the opportunities it found, by quote asset and by
+// route family (both legs on centralized exchanges, one on a DEX, both on DEXes).
+// Amounts in different quote assets are never added together. The rollup of the
+// last run is shown with the market summary, on `GET /stats/profit` and as the
+// `swapsleuth_theoretical_net_profit` / `swapsleuth_theoretical_opportunities`
+// gauges. It is theoretical: the profit the books showed, not what was executed.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::maintenance::Chain;
+use crate::report::quote_asset;
+use crate::ArbitrageOpportunity;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteFamily {
+    CexCex,
+    CexDex,
+    DexDex,
+}
+
+impl RouteFamily {
+    /// A venue is a DEX when it settles on a chain; buying on the DEX side or selling on it is the same family
+    pub fn of(buy_exchange: &str, sell_exchange: &str) -> Self {
+        match (Chain::of_venue(buy_exchange).is_some(), Chain::of_venue(sell_exchange).is_some()) {
+            (false, false) => RouteFamily::CexCex,
+            (true, true) => RouteFamily::DexDex,
+            _ => RouteFamily::CexDex,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RouteFamily::CexCex => "cex_cex",
+            RouteFamily::CexDex => "cex_dex",
+            RouteFamily::DexDex => "dex_dex",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ProfitTotal {
+    pub opportunities: usize,
+    pub net_profit: f64,
+}
+
+impl ProfitTotal {
+    fn add(&mut self, net_profit: f64) {
+        self.opportunities += 1;
+        self.net_profit += net_profit;
+    }
+}
+
+// Name, help and value of one rollup gauge
+type Gauge = (&'static str, &'static str, fn(&ProfitTotal) -> f64);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ProfitRollup {
+    // The comprehensive run it covers; None before the first one
+    pub at: Option<DateTime<Utc>>,
+    pub opportunities: usize,
+    pub by_quote: BTreeMap<String, ProfitTotal>,
+    // By family, then quote asset
+    pub by_family: BTreeMap<RouteFamily, BTreeMap<String, ProfitTotal>>,
+}
+
+impl ProfitRollup {
+    /// Total what one comprehensive run found
+    pub fn of_run(opportunities: &[ArbitrageOpportunity], at: DateTime<Utc>) -> Self {
+        let mut rollup = ProfitRollup { at: Some(at), opportunities: opportunities.len(), ..ProfitRollup::default() };
+        for opp in opportunities {
+            let quote = quote_asset(&opp.pair).to_string();
+            rollup.by_quote.entry(quote.clone()).or_default().add(opp.net_profit);
+            rollup.by_family.entry(RouteFamily::of(&opp.buy_exchange, &opp.sell_exchange)).or_default().entry(quote).or_default().add(opp.net_profit);
+        }
+        rollup
+    }
+
+    /// Gauges per family and quote asset, with `labels` (`{name="value",...}`) on every sample
+    pub fn render_metrics(&self, labels: &str) -> String {
+        let series: Vec<(String, &ProfitTotal)> = self
+            .by_family
+            .iter()
+            .flat_map(|(family, quotes)| {
+                quotes.iter().map(move |(quote, total)| {
+                    let extra = format!("family=\"{}\",quote=\"{}\"", family.as_str(), quote.replace('\\', "\\\\").replace('"', "\\\""));
+                    let labels = match labels.strip_suffix('}') {
+                        Some(open) => format!("{},{}}}", open, extra),
+                        None => format!("{{{}}}", extra),
+                    };
+                    (labels, total)
+                })
+            })
+            .collect();
+        let mut out = String::new();
+        let gauges: [Gauge; 2] = [
+            ("swapsleuth_theoretical_net_profit", "Net profit of the opportunities the last comprehensive run found, in the quote asset", |total| {
+                total.net_profit
+            }),
+            ("swapsleuth_theoretical_opportunities", "Opportunities the last comprehensive run found", |total| total.opportunities as f64),
+        ];
+        for (name, help, value) in gauges {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            for (labels, total) in &series {
+                let _ = writeln!(out, "{}{} {}", name, labels, value(total));
+            }
+        }
+        out
+    }
+
+    /// e.g. `120.5 USDT (cex_cex 100, cex_dex 20.5)`, one entry per quote asset
+    pub fn describe(&self) -> Vec<String> {
+        self.by_quote
+            .iter()
+            .map(|(quote, total)| {
+                let families: Vec<String> = self
+                    .by_family
+                    .iter()
+                    .filter_map(|(family, quotes)| quotes.get(quote).map(|part| format!("{} {:.2}", family.as_str(), part.net_profit)))
+                    .collect();
+                format!("{:.2} {} ({})", total.net_profit, quote, families.join(", "))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpreadAnalyzer;
+
+    // Two USDT routes, CEX-CEX and CEX-DEX, and one quoted in BTC
+    fn opportunities() -> Vec<ArbitrageOpportunity> {
+        let analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let opp = |buy: &str, sell: &str, pair: &str, ask: f64, bid: f64| analyzer.evaluate_opportunity(buy, sell, pair, ask, bid, 1.0, 1.0).unwrap();
+        let mut quoted_in_btc = opp("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0);
+        quoted_in_btc.pair = "ETH/BTC".to_string();
+        vec![opp("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0), opp("uniswap-v3-exact", "binance", "ETH/USDT", 3_000.0, 3_100.0), quoted_in_btc]
+    }
+
+    #[test]
+    fn profit_is_totalled_per_quote() {
+        let opportunities = opportunities();
+        let rollup = ProfitRollup::of_run(&opportunities, Utc::now());
+        assert_eq!(rollup.opportunities, 3);
+        assert_eq!(rollup.by_quote.keys().collect::<Vec<_>>(), vec!["BTC", "USDT"]);
+        let usdt = rollup.by_quote["USDT"];
+        assert_eq!(usdt.opportunities, 2);
+        assert!((usdt.net_profit - (opportunities[0].net_profit + opportunities[1].net_profit)).abs() < 1e-9);
+        assert_eq!(rollup.describe().len(), 2);
+    }
+
+    #[test]
+    fn profit_is_totalled_per_route_family() {
+        let rollup = ProfitRollup::of_run(&opportunities(), Utc::now());
+        assert_eq!(RouteFamily::of("raydium", "orca"), RouteFamily::DexDex);
+        assert_eq!(rollup.by_family[&RouteFamily::CexDex]["USDT"].opportunities, 1);
+        assert_eq!(rollup.by_family[&RouteFamily::CexCex].len(), 2);
+    }
+
+    #[test]
+    fn renders_gauges_even_when_empty() {
+        let metrics = ProfitRollup::of_run(&opportunities(), Utc::now()).render_metrics("{version=\"1\"}");
+        assert!(metrics.contains("swapsleuth_theoretical_opportunities{version=\"1\",family=\"cex_dex\",quote=\"USDT\"} 1"));
+        assert!(ProfitRollup::default().render_metrics("").contains("# TYPE swapsleuth_theoretical_net_profit gauge"));
+    }
+}
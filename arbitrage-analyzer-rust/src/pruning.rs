@@ -0,0 +1,184 @@
+// Latency-budget route pruning. EXECUTION_LATENCY_MS declares how long we take,
+// end to end, to get an order filled on each venue (`binance=120,uniswap-v3-exact=4000`);
+// venues not listed take EXECUTION_LATENCY_DEFAULT_MS, and are left alone when
+// that is 0 (the default). Both legs go out at once, so a route takes as long as
+// its slower leg.
+//
+// A route's spread half-life is the median lifetime of its past positive spreads
+// (see `competition`). Once ROUTE_PRUNE_MIN_SPREADS of them have closed, a route
+// whose half-life is shorter than its latency is pruned: most of its spreads are
+// gone before our orders could land, so it is not evaluated at all. The set is
+// rebuilt every ROUTE_PRUNE_REFRESH_SECS; spreads keep being recorded for pruned
+// routes, so one whose spreads start lasting longer comes back. The pruned routes
+// are logged every ROUTE_PRUNE_REPORT_SECS and served on `GET /routes/pruned`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::Serialize;
+
+use crate::lifecycle::RouteKey;
+use crate::{config, SpreadAnalyzer};
+
+const DEFAULT_MIN_SPREADS: usize = 10;
+const DEFAULT_REFRESH_SECS: u64 = 60;
+const DEFAULT_REPORT_SECS: u64 = 300;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrunedRoute {
+    pub route: RouteKey,
+    pub half_life_secs: f64,
+    pub latency_ms: f64,
+    pub closed_spreads: usize,
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct LatencyBudget {
+    venue_ms: HashMap<String, f64>,
+    // None leaves venues without a declared latency unpruned
+    default_ms: Option<f64>,
+    min_spreads: usize,
+    refresh: Duration,
+    report_every: Duration,
+    pruned: BTreeMap<RouteKey, PrunedRoute>,
+    last_refresh: Option<Instant>,
+    last_report: Instant,
+}
+
+impl LatencyBudget {
+    pub fn new(venue_ms: HashMap<String, f64>, default_ms: Option<f64>, min_spreads: usize) -> Self {
+        LatencyBudget {
+            venue_ms,
+            default_ms,
+            min_spreads: min_spreads.max(1),
+            refresh: Duration::from_secs(DEFAULT_REFRESH_SECS),
+            report_every: Duration::from_secs(DEFAULT_REPORT_SECS),
+            pruned: BTreeMap::new(),
+            last_refresh: None,
+            last_report: Instant::now(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let mut budget = Self::new(
+            config::env_map("EXECUTION_LATENCY_MS"),
+            Some(config::env_or("EXECUTION_LATENCY_DEFAULT_MS", 0.0)).filter(|ms| *ms > 0.0),
+            config::env_or("ROUTE_PRUNE_MIN_SPREADS", DEFAULT_MIN_SPREADS),
+        );
+        budget.refresh = Duration::from_secs(config::env_or("ROUTE_PRUNE_REFRESH_SECS", DEFAULT_REFRESH_SECS));
+        budget.report_every = Duration::from_secs(config::env_or("ROUTE_PRUNE_REPORT_SECS", DEFAULT_REPORT_SECS));
+        budget
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.venue_ms.is_empty() || self.default_ms.is_some()
+    }
+
+    fn venue_latency_ms(&self, venue: &str) -> Option<f64> {
+        self.venue_ms.get(venue).copied().or(self.default_ms)
+    }
+
+    /// How long an execution on `route` takes: its slower leg, when either is declared
+    pub fn route_latency_ms(&self, route: &RouteKey) -> Option<f64> {
+        match (self.venue_latency_ms(&route.buy_exchange), self.venue_latency_ms(&route.sell_exchange)) {
+            (Some(buy), Some(sell)) => Some(buy.max(sell)),
+            (buy, sell) => buy.or(sell),
+        }
+    }
+
+    /// The pruning of `route`, given its spreads' half-life and how many closed
+    pub fn judge(&self, route: &RouteKey, half_life_secs: Option<f64>, closed_spreads: usize, now: DateTime<Utc>) -> Option<PrunedRoute> {
+        let half_life_secs = half_life_secs.filter(|_| closed_spreads >= self.min_spreads)?;
+        let latency_ms = self.route_latency_ms(route)?;
+        (half_life_secs * 1000.0 < latency_ms).then(|| {
+            let since = self.pruned.get(route).map_or(now, |pruned| pruned.since);
+            PrunedRoute { route: route.clone(), half_life_secs, latency_ms, closed_spreads, since }
+        })
+    }
+
+    pub fn prunes(&self, route: &RouteKey) -> bool {
+        self.pruned.contains_key(route)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pruned.is_empty()
+    }
+
+    pub fn report(&self) -> Vec<&PrunedRoute> {
+        self.pruned.values().collect()
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Rebuild the pruned set from the routes' spread history when due, and log it when due
+    pub(crate) fn refresh_route_pruning(&mut self, now: DateTime<Utc>) {
+        let budget = &self.route_pruning;
+        if !budget.enabled() || budget.last_refresh.is_some_and(|at| at.elapsed() < budget.refresh) {
+            return;
+        }
+        let pruned: BTreeMap<RouteKey, PrunedRoute> = self
+            .competition
+            .all(now)
+            .into_iter()
+            .filter_map(|(route, estimate)| budget.judge(&route, estimate.median_close_secs, estimate.closed_spreads, now))
+            .map(|pruned| (pruned.route.clone(), pruned))
+            .collect();
+        for (route, entry) in &pruned {
+            if !budget.prunes(route) {
+                info!(
+                    "Pruning {}: spreads last {:.2}s (median of {}), execution takes {:.0}ms",
+                    route, entry.half_life_secs, entry.closed_spreads, entry.latency_ms
+                );
+            }
+        }
+        for route in budget.pruned.keys().filter(|route| !pruned.contains_key(*route)) {
+            info!("Evaluating {} again: its spreads now outlast the execution latency", route);
+        }
+
+        let budget = &mut self.route_pruning;
+        budget.pruned = pruned;
+        budget.last_refresh = Some(Instant::now());
+        self.metrics.pruned_routes.store(budget.pruned.len() as u64, Ordering::Relaxed);
+        if budget.last_report.elapsed() >= budget.report_every {
+            budget.last_report = Instant::now();
+            info!("Latency-pruned routes: {}", budget.pruned.len());
+            for entry in budget.pruned.values() {
+                info!(
+                    "  - {}: half-life {:.2}s < {:.0}ms latency, pruned since {}",
+                    entry.route,
+                    entry.half_life_secs,
+                    entry.latency_ms,
+                    entry.since.format("%Y-%m-%d %H:%M:%S")
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prunes_routes_whose_spreads_close_before_the_slower_leg_fills() {
+        let budget = LatencyBudget::new(HashMap::from([("binance".to_string(), 100.0), ("uniswap-v3-exact".to_string(), 4_000.0)]), None, 5);
+        let now = Utc::now();
+        let dex = RouteKey::new("ETH/USDT", "binance", "uniswap-v3-exact");
+        let cex = RouteKey::new("ETH/USDT", "binance", "okx");
+
+        assert_eq!(budget.route_latency_ms(&dex), Some(4_000.0));
+        // okx has no declared latency and there is no default: binance's alone counts
+        assert_eq!(budget.route_latency_ms(&cex), Some(100.0));
+        assert_eq!(budget.judge(&dex, Some(2.5), 20, now).map(|pruned| pruned.latency_ms), Some(4_000.0));
+        assert!(budget.judge(&dex, Some(5.0), 20, now).is_none());
+        // Too few closed spreads to judge by
+        assert!(budget.judge(&dex, Some(2.5), 4, now).is_none());
+        assert!(budget.judge(&cex, Some(0.5), 20, now).is_none());
+        assert!(budget.judge(&RouteKey::new("ETH/USDT", "okx", "bybit"), Some(0.01), 20, now).is_none());
+        assert!(!LatencyBudget::new(HashMap::new(), None, 5).enabled());
+    }
+}
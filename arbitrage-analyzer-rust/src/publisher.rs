@@ -0,0 +1,264 @@
+// Outgoing Redis writes: publications and stream entries (opportunities, execution requests) and
+// keys other services read (the allocation plan, the state snapshot, the
+// opportunity archive). The analysis loop only queues
+// them; a task on the I/O runtime owns a multiplexed connection, so a slow or
+// unreachable Redis never stalls analysis. Writes that fail are logged and dropped rather than
+// retried, since a late signal is a stale one and keys are rewritten periodically.
+// After a failed connect, writes are dropped until the backoff allows the next
+// attempt. Execution stream entries are the exception: an executor that reads the
+// stream would never see a request lost there, so they are held (up to
+// MAX_HELD_ENTRIES, oldest dropped first) and written, in order, once Redis is back.
+// On shutdown the queue is drained before the process exits.
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncCommands, Client, RedisResult};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use crate::backoff::Backoff;
+use crate::metrics::Metrics;
+
+const MAX_HELD_ENTRIES: usize = 10_000;
+
+#[derive(Debug)]
+enum Outgoing {
+    Publish { channel: String, payload: String },
+    // Keys with a TTL expire unless rewritten in time
+    Set { key: String, payload: String, ttl_secs: Option<usize> },
+    Hash { key: String, fields: Vec<(String, String)>, ttl_secs: usize },
+    // Sorted set member, scored by time
+    Index { key: String, member: String, score: i64 },
+    // Drop members scored at or below `max_score`, then all but the `keep` highest
+    Trim { key: String, max_score: i64, keep: usize },
+    // Stream entry, the stream capped near `maxlen`; held across outages with `hold`
+    Stream { key: String, fields: Vec<(String, String)>, maxlen: usize, hold: bool },
+    // Consumer group reading the stream from its first entry; one that exists is kept
+    Group { key: String, group: String },
+}
+
+#[derive(Debug)]
+pub struct Publisher {
+    outbox: UnboundedSender<Outgoing>,
+    // Signalled once the task has written everything queued before `close`
+    drained: Receiver<()>,
+}
+
+impl Outgoing {
+    // The channel or key written, for logs
+    fn target(&self) -> &str {
+        match self {
+            Outgoing::Publish { channel, .. } => channel,
+            Outgoing::Set { key, .. }
+            | Outgoing::Hash { key, .. }
+            | Outgoing::Index { key, .. }
+            | Outgoing::Trim { key, .. }
+            | Outgoing::Stream { key, .. }
+            | Outgoing::Group { key, .. } => key,
+        }
+    }
+
+    // Whether to keep it for after a reconnect rather than drop it
+    fn held(&self) -> bool {
+        matches!(self, Outgoing::Stream { hold: true, .. })
+    }
+
+    async fn write(&self, con: &mut MultiplexedConnection) -> RedisResult<()> {
+        match self {
+            Outgoing::Publish { channel, payload } => con.publish::<_, _, i64>(channel, payload).await.map(|_| ()),
+            Outgoing::Set { key, payload, ttl_secs: None } => con.set::<_, _, ()>(key, payload).await,
+            Outgoing::Set { key, payload, ttl_secs: Some(ttl) } => con.set_ex::<_, _, ()>(key, payload, *ttl).await,
+            Outgoing::Hash { key, fields, ttl_secs } => {
+                redis::pipe().atomic().hset_multiple(key, fields).ignore().expire(key, *ttl_secs).ignore().query_async::<_, ()>(con).await
+            }
+            Outgoing::Index { key, member, score } => con.zadd::<_, _, _, ()>(key, member, *score).await,
+            Outgoing::Trim { key, max_score, keep } => {
+                redis::pipe()
+                    .zrembyscore(key, "-inf", *max_score)
+                    .ignore()
+                    .zremrangebyrank(key, 0, -(*keep as isize) - 1)
+                    .ignore()
+                    .query_async::<_, ()>(con)
+                    .await
+            }
+            Outgoing::Stream { key, fields, maxlen, .. } => redis::cmd("XADD")
+                .arg(key)
+                .arg("MAXLEN")
+                .arg("~")
+                .arg(*maxlen)
+                .arg("*")
+                .arg(fields)
+                .query_async::<_, String>(con)
+                .await
+                .map(|_| ()),
+            Outgoing::Group { key, group } => {
+                match redis::cmd("XGROUP").arg("CREATE").arg(key).arg(group).arg("0").arg("MKSTREAM").query_async::<_, ()>(con).await {
+                    Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+                    result => result,
+                }
+            }
+        }
+    }
+}
+
+impl Publisher {
+    /// Start the writer task on `runtime`
+    pub fn spawn(runtime: &Handle, client: Client, metrics: Arc<Metrics>) -> Self {
+        let (outbox, mut rx) = unbounded_channel::<Outgoing>();
+        let (drained_tx, drained) = mpsc::channel();
+        runtime.spawn(async move {
+            let mut connection: Option<MultiplexedConnection> = None;
+            let mut backoff = Backoff::from_env();
+            let mut retry_at = Instant::now();
+            // Held entries not yet written, oldest first
+            let mut held: VecDeque<Outgoing> = VecDeque::new();
+            let mut open = true;
+            while open || !held.is_empty() {
+                let received = if !open {
+                    tokio::time::sleep_until(retry_at.into()).await;
+                    None
+                } else if held.is_empty() {
+                    Some(rx.recv().await)
+                } else {
+                    // Held entries need a reconnect attempt even when nothing new is queued
+                    tokio::time::timeout_at(retry_at.into(), rx.recv()).await.ok()
+                };
+                if let Some(None) = received {
+                    open = false;
+                }
+                if connection.is_none() && Instant::now() >= retry_at {
+                    match client.get_multiplexed_tokio_connection().await {
+                        Ok(con) => {
+                            connection = Some(con);
+                            backoff.reset();
+                        }
+                        Err(e) => {
+                            Metrics::inc(&metrics.redis_errors);
+                            let delay = backoff.next_delay();
+                            warn!("Publisher cannot reach Redis: {}; retrying in {}ms", e, delay.as_millis());
+                            retry_at = Instant::now() + delay;
+                        }
+                    }
+                }
+                // Held entries go out ahead of anything newer
+                let pending = held.drain(..).chain(received.flatten()).collect::<Vec<_>>();
+                for outgoing in pending {
+                    let target = outgoing.target().to_string();
+                    let failure = match connection.as_mut() {
+                        Some(con) => match outgoing.write(con).await {
+                            Ok(()) => continue,
+                            Err(e) => {
+                                Metrics::inc(&metrics.redis_errors);
+                                // Reconnect on the next message
+                                connection = None;
+                                // Rewriting what Redis itself refused would only fail again
+                                if !(e.is_io_error() || e.is_connection_dropped()) {
+                                    warn!("Failed to write {}: {}", target, e);
+                                    continue;
+                                }
+                                e.to_string()
+                            }
+                        },
+                        None => "no Redis connection".to_string(),
+                    };
+                    if !outgoing.held() {
+                        warn!("Dropped write to {}: {}", target, failure);
+                        continue;
+                    }
+                    if held.len() == MAX_HELD_ENTRIES {
+                        held.pop_front();
+                        warn!("Dropped the oldest entry held for {}: over {} held", target, MAX_HELD_ENTRIES);
+                    }
+                    warn!("Holding entry for {} until Redis is back: {}", target, failure);
+                    held.push_back(outgoing);
+                }
+            }
+            let _ = drained_tx.send(());
+        });
+        Publisher { outbox, drained }
+    }
+
+    /// Stop taking writes and wait up to `timeout` for the queued ones to go out
+    pub fn close(self, timeout: Duration) {
+        let Publisher { outbox, drained } = self;
+        drop(outbox);
+        match drained.recv_timeout(timeout) {
+            Ok(()) => info!("Publisher flushed"),
+            Err(_) => warn!("Publisher still had writes queued after {}s, dropping them", timeout.as_secs()),
+        }
+    }
+
+    pub fn publish(&self, channel: &str, payload: String) {
+        // The task only stops when the analyzer is gone
+        let _ = self.outbox.send(Outgoing::Publish { channel: channel.to_string(), payload });
+    }
+
+    pub fn set(&self, key: &str, payload: String, ttl_secs: Option<usize>) {
+        let _ = self.outbox.send(Outgoing::Set { key: key.to_string(), payload, ttl_secs });
+    }
+
+    pub fn hash(&self, key: &str, fields: Vec<(String, String)>, ttl_secs: usize) {
+        let _ = self.outbox.send(Outgoing::Hash { key: key.to_string(), fields, ttl_secs });
+    }
+
+    pub fn index(&self, key: &str, member: &str, score: i64) {
+        let _ = self.outbox.send(Outgoing::Index { key: key.to_string(), member: member.to_string(), score });
+    }
+
+    pub fn trim(&self, key: &str, max_score: i64, keep: usize) {
+        let _ = self.outbox.send(Outgoing::Trim { key: key.to_string(), max_score, keep });
+    }
+
+    /// Append an entry to `key`; with `hold` it survives a Redis outage
+    pub fn stream(&self, key: &str, fields: Vec<(String, String)>, maxlen: usize, hold: bool) {
+        let _ = self.outbox.send(Outgoing::Stream { key: key.to_string(), fields, maxlen, hold });
+    }
+
+    pub fn create_group(&self, key: &str, group: &str) {
+        let _ = self.outbox.send(Outgoing::Group { key: key.to_string(), group: group.to_string() });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::Commands;
+
+    #[test]
+    fn close_flushes_queued_writes() {
+        let server = crate::mini_redis::MiniRedis::start();
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(1).enable_all().build().unwrap();
+        let publisher = Publisher::spawn(runtime.handle(), server.client(), Arc::new(Metrics::default()));
+        for i in 0..100 {
+            publisher.set(&format!("key:{}", i), i.to_string(), None);
+        }
+        publisher.close(Duration::from_secs(5));
+        let mut con = server.client().get_connection().unwrap();
+        assert_eq!(con.get::<_, Option<String>>("key:99").unwrap().as_deref(), Some("99"));
+    }
+
+    #[test]
+    fn execution_entries_wait_out_a_lost_connection() {
+        let server = crate::mini_redis::MiniRedis::start();
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(1).enable_all().build().unwrap();
+        let publisher = Publisher::spawn(runtime.handle(), server.client(), Arc::new(Metrics::default()));
+        let mut con = server.client().get_connection().unwrap();
+        let entry = || vec![("id".to_string(), "req-1".to_string())];
+        publisher.stream("arb:execution_requests", entry(), 100, true);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while con.xlen::<_, usize>("arb:execution_requests").unwrap() == 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        server.disconnect_all();
+        publisher.stream("arb:execution_requests", entry(), 100, true);
+        publisher.close(Duration::from_secs(5));
+        let mut con = server.client().get_connection().unwrap();
+        assert_eq!(con.xlen::<_, usize>("arb:execution_requests").unwrap(), 2);
+    }
+}
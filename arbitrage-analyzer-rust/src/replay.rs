@@ -0,0 +1,163 @@
+// Replay of the recorded market scenarios in `scenarios/`. Each scenario is a
+// sequence of book payloads as collectors write them to Redis, plus the clock
+// ticks and config changes between them. Steps go through the same decoding and
+// validation as the ingestion stage and the same `process_event` as the live
+// loop, and the opportunities, rejections and events they produce are checked
+// against what the scenario expects. A behaviour change that moves any of them
+// fails here, and the scenario file says what used to happen.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::events::{EventClass, EventQuery};
+use crate::mode::Mode;
+use crate::pipeline::{self, IngestEvent};
+use crate::{codec, ingest_stats, SpreadAnalyzer};
+
+const SCENARIO_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios");
+// Scenario clocks start at 2024-01-01T00:00:00Z, where the books' timestamps count from
+const SCENARIO_START_SECS: i64 = 1_704_067_200;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Scenario {
+    description: String,
+    #[serde(default)]
+    mode: Option<String>,
+    steps: Vec<Step>,
+    #[serde(default)]
+    expect: Expectations,
+}
+
+#[derive(Debug, Deserialize)]
+struct Step {
+    // Seconds since the start of the scenario
+    at_secs: i64,
+    #[serde(flatten)]
+    action: Action,
+    // Routes the step must produce opportunities on, and no others
+    #[serde(default)]
+    opportunities: Vec<Route>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum Action {
+    // A book as stored under `key`: a JSON object, or the raw `payload` when it is not valid JSON
+    Book { key: String, book: Option<Value>, payload: Option<String> },
+    // Periodic upkeep at this time, i.e. the venue watchdog and opportunity expiry
+    Tick,
+    // Gas price moves, in USD per Ethereum leg
+    GasCost { usd: f64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Route {
+    pair: String,
+    buy: String,
+    sell: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Expectations {
+    // Venues whose books were rejected at ingest
+    #[serde(default)]
+    rejected: BTreeSet<String>,
+    // Event classes that must have been published
+    #[serde(default)]
+    events: Vec<String>,
+    #[serde(default)]
+    execution_requests: usize,
+}
+
+fn ingest(key: String, book: Option<Value>, payload: Option<String>, now: DateTime<Utc>) -> Result<IngestEvent> {
+    let payload = match (book, payload) {
+        (Some(book), None) => book.to_string(),
+        (None, Some(payload)) => payload,
+        _ => return Err(anyhow!("{}: a book step needs exactly one of `book` and `payload`", key)),
+    };
+    Ok(match codec::decode_book(payload) {
+        Ok(book) => pipeline::admit_book(key, book, "replay", now),
+        Err(_) => IngestEvent::Rejected {
+            exchange: ingest_stats::exchange_from_key(&key).unwrap_or_default().to_string(),
+            key,
+            reason: "unparseable JSON".to_string(),
+        },
+    })
+}
+
+fn replay(path: &Path) -> Result<()> {
+    let scenario: Scenario = serde_json::from_str(&fs::read_to_string(path)?).context("parsing scenario")?;
+    let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379")?;
+    analyzer.mode = scenario.mode.as_deref().map_or(Ok(Mode::Observe), str::parse)?;
+    let start = DateTime::from_timestamp(SCENARIO_START_SECS, 0).ok_or_else(|| anyhow!("bad scenario start"))?;
+
+    for (idx, step) in scenario.steps.into_iter().enumerate() {
+        let now = start + Duration::seconds(step.at_secs);
+        let opportunities = match step.action {
+            Action::Book { key, book, payload } => analyzer.process_event(ingest(key, book, payload, now)?, now)?,
+            Action::Tick => {
+                analyzer.check_venue_silence(now);
+                let expired = analyzer.live_opportunities.expire_stale(now);
+                analyzer.expire_opportunities(expired);
+                Vec::new()
+            }
+            Action::GasCost { usd } => {
+                analyzer.fees_config.ethereum_gas_cost = usd;
+                Vec::new()
+            }
+        };
+        let found: BTreeSet<Route> = opportunities
+            .iter()
+            .map(|opp| Route { pair: opp.pair.clone(), buy: opp.buy_exchange.clone(), sell: opp.sell_exchange.clone() })
+            .collect();
+        let expected: BTreeSet<Route> = step.opportunities.into_iter().collect();
+        if found != expected {
+            return Err(anyhow!("step {}: expected opportunities on {:?}, got {:?}", idx, expected, found));
+        }
+    }
+
+    let events = analyzer.events.recent(&EventQuery::default());
+    let rejected: BTreeSet<String> =
+        events.iter().filter(|e| e.class == EventClass::BookRejected).filter_map(|e| e.venue.clone()).collect();
+    if rejected != scenario.expect.rejected {
+        return Err(anyhow!("expected rejected books from {:?}, got {:?}", scenario.expect.rejected, rejected));
+    }
+    for class in &scenario.expect.events {
+        let class: EventClass = class.parse()?;
+        if !events.iter().any(|e| e.class == class) {
+            return Err(anyhow!("expected a {} event", class));
+        }
+    }
+    let requests = analyzer.lifecycle.recent().count() + analyzer.lifecycle.in_flight().len();
+    if requests != scenario.expect.execution_requests {
+        return Err(anyhow!("expected {} execution requests, got {}", scenario.expect.execution_requests, requests));
+    }
+    log::debug!("Scenario {} passed: {}", path.display(), scenario.description);
+    Ok(())
+}
+
+#[test]
+fn recorded_scenarios_replay_unchanged() {
+    let mut paths: Vec<_> = fs::read_dir(SCENARIO_DIR)
+        .expect("scenarios directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no scenarios in {}", SCENARIO_DIR);
+
+    let failures: Vec<String> = paths
+        .iter()
+        .filter_map(|path| replay(path).err().map(|e| format!("{}: {:#}", path.display(), e)))
+        .collect();
+    assert!(failures.is_empty(), "scenario regressions:\n{}", failures.join("\n"));
+}
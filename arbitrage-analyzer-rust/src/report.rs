@@ -0,0 +1,522 @@
+// What the analyzer prints to stdout after each analysis pass, chosen with
+// REPORT_POLICY. At high detection rates the full report of every pass buries
+// everything else in the logs:
+//  - full (default): the market summary and every opportunity found, each pass,
+//  - top: only the REPORT_TOP_N best opportunities of a pass,
+//  - new: only opportunities on routes that just went live (see `expiry`), not
+//    the repeats while a route stays open,
+//  - summary: nothing per pass; every REPORT_SUMMARY_SECS the market summary and
+//    a digest of what was found since the last one,
+//  - none: nothing; opportunities reach consumers through Redis, the API and the
+//    configured sinks only.
+// Log lines are not affected; RUST_LOG controls those, and they go to stderr.
+//
+// The human report is a set of tables. Prices and sizes are shown to six
+// significant figures, so a 0.00001234 quote reads as well as a 50,000 one, and
+// amounts carry the asset they are in. ROI is colored by tier (high profit,
+// moderate, low margin) when stdout is a terminal and NO_COLOR is unset.
+//
+// `--output jsonl` keeps the policy but replaces the human report with one JSON
+// object per line, told apart by `type`: `market_summary`, `opportunity`,
+// `rejection` (a book dropped at ingest) and `summary` (the periodic digest).
+// Stdout then carries nothing else, so it can be piped into jq or a collector.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, Color, ContentArrangement, Table};
+use serde_json::{json, Value};
+
+use crate::delta::RunDelta;
+use crate::lifecycle::RouteKey;
+use crate::overrides::RouteOverride;
+use crate::{config, numeric, ArbitrageOpportunity, SpreadAnalyzer};
+
+const DEFAULT_TOP_N: usize = 5;
+// Significant figures of prices and sizes in the human report
+const PRICE_FIGURES: u32 = 6;
+// Of profits, fees and capital, never fewer than cents
+const AMOUNT_FIGURES: u32 = 4;
+const DEFAULT_SUMMARY_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportPolicy {
+    Full,
+    Top,
+    New,
+    Summary,
+    None,
+}
+
+impl FromStr for ReportPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "full" => Ok(ReportPolicy::Full),
+            "top" => Ok(ReportPolicy::Top),
+            "new" => Ok(ReportPolicy::New),
+            "summary" => Ok(ReportPolicy::Summary),
+            "none" => Ok(ReportPolicy::None),
+            other => Err(anyhow!("unknown report policy: {} (expected full, top, new, summary or none)", other)),
+        }
+    }
+}
+
+impl fmt::Display for ReportPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReportPolicy::Full => "full",
+            ReportPolicy::Top => "top",
+            ReportPolicy::New => "new",
+            ReportPolicy::Summary => "summary",
+            ReportPolicy::None => "none",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    // The report for people reading a terminal
+    Human,
+    // One JSON object per line
+    Jsonl,
+}
+
+// What the passes since the last summary found
+#[derive(Debug)]
+struct Window {
+    started_at: DateTime<Utc>,
+    passes: u64,
+    found: u64,
+    routes: HashSet<RouteKey>,
+    // Per quote asset, since profits in different assets don't add up
+    total_net_profit: BTreeMap<String, f64>,
+    best: Option<ArbitrageOpportunity>,
+}
+
+impl Window {
+    fn new(started_at: DateTime<Utc>) -> Self {
+        Window { started_at, passes: 0, found: 0, routes: HashSet::new(), total_net_profit: BTreeMap::new(), best: None }
+    }
+}
+
+#[derive(Debug)]
+pub struct Reporter {
+    pub policy: ReportPolicy,
+    pub format: OutputFormat,
+    top_n: usize,
+    summary_interval: Duration,
+    window: Window,
+}
+
+impl Reporter {
+    pub fn new(policy: ReportPolicy, top_n: usize, summary_interval: Duration) -> Self {
+        Reporter { policy, format: OutputFormat::Human, top_n, summary_interval, window: Window::new(Utc::now()) }
+    }
+
+    pub fn from_env() -> Self {
+        Reporter::new(
+            config::env_or("REPORT_POLICY", ReportPolicy::Full),
+            config::env_or("REPORT_TOP_N", DEFAULT_TOP_N),
+            Duration::seconds(config::env_or("REPORT_SUMMARY_SECS", DEFAULT_SUMMARY_SECS)),
+        )
+    }
+
+    fn observe(&mut self, opportunities: &[ArbitrageOpportunity]) {
+        let window = &mut self.window;
+        window.passes += 1;
+        for opp in opportunities {
+            window.found += 1;
+            window.routes.insert(RouteKey::new(&opp.pair, &opp.buy_exchange, &opp.sell_exchange));
+            *window.total_net_profit.entry(quote_asset(&opp.pair).to_string()).or_default() += opp.net_profit;
+            if window.best.as_ref().is_none_or(|best| opp.net_profit > best.net_profit) {
+                window.best = Some(opp.clone());
+            }
+        }
+    }
+
+    // The window to summarize, when one is due
+    fn take_due_window(&mut self, now: DateTime<Utc>) -> Option<Window> {
+        if self.policy != ReportPolicy::Summary || now - self.window.started_at < self.summary_interval {
+            return None;
+        }
+        Some(std::mem::replace(&mut self.window, Window::new(now)))
+    }
+}
+
+/// `value` to `figures` significant figures but at least `min_decimals` decimals, in plain notation
+pub fn sig_figs(value: f64, figures: u32, min_decimals: usize) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    let magnitude = if value == 0.0 { 0 } else { value.abs().log10().floor() as i64 };
+    let decimals = (figures as i64 - 1 - magnitude).max(min_decimals as i64) as usize;
+    format!("{:.*}", decimals, value)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoiTier {
+    HighProfit,
+    Moderate,
+    LowMargin,
+}
+
+impl RoiTier {
+    pub fn of(roi_percentage: f64) -> Self {
+        if roi_percentage > 2.0 {
+            RoiTier::HighProfit
+        } else if roi_percentage > 1.0 {
+            RoiTier::Moderate
+        } else {
+            RoiTier::LowMargin
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            RoiTier::HighProfit => "HIGH PROFIT",
+            RoiTier::Moderate => "MODERATE",
+            RoiTier::LowMargin => "LOW MARGIN",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            RoiTier::HighProfit => Color::Green,
+            RoiTier::Moderate => Color::Yellow,
+            RoiTier::LowMargin => Color::Red,
+        }
+    }
+}
+
+// Numbers are right-aligned, in the `right_aligned` columns
+pub(crate) fn table(header: &[&str], right_aligned: std::ops::Range<usize>) -> Table {
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL_CONDENSED).set_content_arrangement(ContentArrangement::Dynamic).set_header(header.to_vec());
+    if config::env_var("NO_COLOR").is_ok() {
+        table.force_no_tty();
+    }
+    for column in table.column_iter_mut().filter(|column| right_aligned.contains(&column.index)) {
+        column.set_cell_alignment(CellAlignment::Right);
+    }
+    table
+}
+
+// One line of `--output jsonl`
+pub(crate) fn emit(kind: &str, body: Value) {
+    let mut line = json!({ "type": kind });
+    if let (Some(line), Value::Object(body)) = (line.as_object_mut(), body) {
+        line.extend(body);
+    }
+    println!("{}", line);
+}
+
+impl SpreadAnalyzer {
+    /// Print what one analysis pass found, as far as REPORT_POLICY wants it printed
+    pub(crate) fn report_pass(&mut self, opportunities: &[ArbitrageOpportunity], comprehensive: bool, now: DateTime<Utc>) {
+        let human = self.reporter.format == OutputFormat::Human;
+        match self.reporter.policy {
+            ReportPolicy::Full if !opportunities.is_empty() => {
+                self.report_market_summary(now);
+                self.report_opportunities(opportunities, opportunities.len());
+            }
+            // Only show "no opportunities" for comprehensive analysis
+            ReportPolicy::Full if comprehensive && human => println!("\n Comprehensive analysis complete - no profitable opportunities found"),
+            ReportPolicy::Top if !opportunities.is_empty() => {
+                let shown = opportunities.len().min(self.reporter.top_n);
+                self.report_opportunities(&opportunities[..shown], opportunities.len());
+            }
+            ReportPolicy::New => {
+                let fresh: Vec<ArbitrageOpportunity> =
+                    opportunities.iter().filter(|opp| self.live_opportunities.first_detected_by(opp)).cloned().collect();
+                if !fresh.is_empty() {
+                    self.report_opportunities(&fresh, fresh.len());
+                }
+            }
+            ReportPolicy::Summary => self.reporter.observe(opportunities),
+            _ => {}
+        }
+    }
+
+    // `shown` out of the `found` opportunities of a pass
+    fn report_opportunities(&self, shown: &[ArbitrageOpportunity], found: usize) {
+        match self.reporter.format {
+            OutputFormat::Human => {
+                print_opportunities(shown);
+                if shown.len() < found {
+                    println!("  ... and {} more", found - shown.len());
+                }
+            }
+            OutputFormat::Jsonl => {
+                for opp in shown {
+                    emit("opportunity", serde_json::to_value(opp).unwrap_or_default());
+                }
+            }
+        }
+    }
+
+    fn report_market_summary(&self, now: DateTime<Utc>) {
+        let exchanges = self.books_per_exchange();
+        let grouped = self.group_books_by_pair();
+        let mut cross_listed: Vec<(&String, usize)> =
+            grouped.iter().filter(|(_, books)| books.len() > 1).map(|(pair, books)| (pair, books.len())).collect();
+        cross_listed.sort();
+        if self.reporter.format == OutputFormat::Human {
+            self.print_market_summary(&exchanges, grouped.len(), &cross_listed, now);
+            return;
+        }
+        let cross_listed: serde_json::Map<String, Value> = cross_listed.into_iter().map(|(pair, count)| (pair.clone(), json!(count))).collect();
+        emit(
+            "market_summary",
+            json!({
+                "at": now,
+                "books": self.books.len(),
+                "pairs": grouped.len(),
+                "exchanges": exchanges.iter().map(|(exchange, books)| json!({ "exchange": exchange, "books": books })).collect::<Vec<_>>(),
+                "cross_listed": cross_listed,
+                "profit": self.profit_rollup,
+                "feed_health": self.ingest_stats.summaries(now),
+                "route_overrides": self.route_overrides.entries().iter().map(RouteOverride::report).collect::<Vec<_>>(),
+            }),
+        );
+    }
+
+    fn books_per_exchange(&self) -> Vec<(String, usize)> {
+        let mut exchanges: Vec<(String, usize)> = Vec::new();
+        for book in self.books.values() {
+            match exchanges.iter_mut().find(|(exchange, _)| *exchange == book.exchange) {
+                Some((_, books)) => *books += 1,
+                None => exchanges.push((book.exchange.clone(), 1)),
+            }
+        }
+        exchanges.sort();
+        exchanges
+    }
+
+    fn print_market_summary(&self, exchanges: &[(String, usize)], pairs: usize, cross_listed: &[(&String, usize)], now: DateTime<Utc>) {
+        println!("\n MARKET DATA SUMMARY: {} exchanges, {} pairs, {} books", exchanges.len(), pairs, self.books.len());
+        let mut table = table(&["Exchange", "Books", "Upd/min", "Median gap", "Avg depth", "Last update", "Rejected"], 1..7);
+        let feeds = self.ingest_stats.summaries(now);
+        // Venues whose every book was rejected have feed stats but nothing cached
+        let silent = feeds.iter().filter(|feed| !exchanges.iter().any(|(exchange, _)| *exchange == feed.exchange)).map(|feed| (feed.exchange.clone(), 0));
+        for (exchange, books) in exchanges.iter().cloned().chain(silent) {
+            let feed = feeds.iter().find(|feed| feed.exchange == exchange);
+            table.add_row(vec![
+                exchange,
+                books.to_string(),
+                feed.map_or("-".to_string(), |f| format!("{:.0}", f.updates_per_minute)),
+                feed.and_then(|f| f.median_gap_ms).map_or("-".to_string(), |gap| format!("{:.0}ms", gap)),
+                feed.map_or("-".to_string(), |f| format!("{:.1}", f.average_depth)),
+                feed.and_then(|f| f.last_update_age_ms).map_or("never".to_string(), |age| format!("{:.1}s ago", age as f64 / 1000.0)),
+                feed.map_or("-".to_string(), |f| format!("{:.1}% ({}/{})", f.rejection_rate * 100.0, f.rejected, f.accepted + f.rejected)),
+            ]);
+        }
+        println!("{}", table);
+        if !cross_listed.is_empty() {
+            let pairs: Vec<String> = cross_listed.iter().map(|(pair, count)| format!("{} ({})", pair, count)).collect();
+            println!("  Cross-listed: {}", pairs.join(", "));
+        }
+        if self.profit_rollup.opportunities > 0 {
+            println!("  Theoretical profit of the last comprehensive run: {}", self.profit_rollup.describe().join(", "));
+        }
+        self.print_route_overrides(now);
+    }
+
+    // Every pinned override, so one that outlived its reason or matches nothing gets noticed
+    fn print_route_overrides(&self, now: DateTime<Utc>) {
+        if self.route_overrides.is_empty() {
+            return;
+        }
+        println!("  Route overrides (in place of the automatic model):");
+        let mut table = table(&["Route", "Override", "Since", "Applied", "Last applied"], 3..5);
+        for route_override in self.route_overrides.entries() {
+            let report = route_override.report();
+            let since = report.since.map_or("-".to_string(), |since| format!("{} ({}d)", since, (now.date_naive() - since).num_days()));
+            let last_applied = report.last_applied.map_or("never".to_string(), |at| format!("{:.1}s ago", (now - at).num_milliseconds() as f64 / 1000.0));
+            table.add_row(vec![report.key, route_override.describe(), since, report.applied.to_string(), last_applied]);
+        }
+        println!("{}", table);
+    }
+
+    /// The market summary and every opportunity of a pass, as the `full` policy prints them
+    pub(crate) fn print_analysis_results(&self, opportunities: &[ArbitrageOpportunity]) {
+        self.report_market_summary(Utc::now());
+        if opportunities.is_empty() {
+            println!(" SPREAD ANALYSIS: No profitable opportunities found");
+            return;
+        }
+        self.report_opportunities(opportunities, opportunities.len());
+    }
+
+    /// A book dropped at ingest. The human report leaves these to the logs
+    pub(crate) fn report_rejection(&self, key: &str, exchange: &str, reason: &str, now: DateTime<Utc>) {
+        if self.reporter.format == OutputFormat::Jsonl && self.reporter.policy != ReportPolicy::None {
+            emit("rejection", json!({ "at": now, "key": key, "exchange": exchange, "reason": reason }));
+        }
+    }
+
+    /// What changed since the previous comprehensive pass; printed under the per-pass policies
+    pub(crate) fn report_delta(&self, delta: &RunDelta) {
+        if !matches!(self.reporter.policy, ReportPolicy::Full | ReportPolicy::Top | ReportPolicy::New) {
+            return;
+        }
+        match self.reporter.format {
+            OutputFormat::Jsonl => emit("delta", json!(delta)),
+            OutputFormat::Human => println!("\n WHAT CHANGED since {}: {}", delta.since.format("%H:%M:%S"), delta.narrative()),
+        }
+    }
+
+    /// Under the summary policy, print the digest of the last REPORT_SUMMARY_SECS when it is due
+    pub(crate) fn report_summary_if_due(&mut self, now: DateTime<Utc>) {
+        let Some(window) = self.reporter.take_due_window(now) else { return };
+        self.report_market_summary(now);
+        if self.reporter.format == OutputFormat::Jsonl {
+            emit(
+                "summary",
+                json!({
+                    "since": window.started_at,
+                    "at": now,
+                    "passes": window.passes,
+                    "opportunities": window.found,
+                    "routes": window.routes.len(),
+                    "total_net_profit": window.total_net_profit,
+                    "live": self.live_opportunities.len(),
+                    "best": window.best,
+                }),
+            );
+            return;
+        }
+        println!(
+            "\n SPREAD ANALYSIS SINCE {}: {} passes, {} opportunities on {} routes, {} live now",
+            window.started_at.format("%H:%M:%S UTC"),
+            window.passes,
+            window.found,
+            window.routes.len(),
+            self.live_opportunities.len()
+        );
+        if !window.total_net_profit.is_empty() {
+            let totals: Vec<String> = window.total_net_profit.iter().map(|(asset, total)| amount(*total, asset)).collect();
+            println!("  Net profit in total: {}", totals.join(", "));
+        }
+        if let Some(best) = &window.best {
+            println!("  Best of the window:");
+            print_opportunities(std::slice::from_ref(best));
+        }
+    }
+}
+
+pub(crate) fn quote_asset(pair: &str) -> &str {
+    pair.split('/').nth(1).unwrap_or_default()
+}
+
+fn amount(value: f64, asset: &str) -> String {
+    format!("{} {}", sig_figs(value, AMOUNT_FIGURES, 2), asset).trim_end().to_string()
+}
+
+fn print_opportunities(opportunities: &[ArbitrageOpportunity]) {
+    println!("\n ARBITRAGE OPPORTUNITIES DETECTED");
+    let mut table =
+        table(&["#", "ID", "Pair", "Buy → Sell", "Buy", "Sell", "Spread", "Size", "Fees", "Net profit", "Capital", "ROI", "Annualized", "Tier"], 4..13);
+    let mut notes = Vec::new();
+    for (idx, opp) in opportunities.iter().enumerate() {
+        let (base, quote) = (opp.pair.split('/').next().unwrap_or_default(), quote_asset(&opp.pair));
+        let tier = RoiTier::of(opp.roi_percentage);
+        let precision = crate::precision::policy();
+        let price = |venue: &str, price: f64| precision.format_price(venue, &opp.pair, price).unwrap_or_else(|| sig_figs(price, PRICE_FIGURES, 2));
+        let size = precision.format_size(&opp.pair, &[&opp.buy_exchange, &opp.sell_exchange], opp.max_size);
+        table.add_row(vec![
+            Cell::new(idx + 1),
+            Cell::new(opp.id.get(..8).unwrap_or(&opp.id)),
+            Cell::new(&opp.pair),
+            Cell::new(format!("{} → {}", opp.buy_exchange, opp.sell_exchange)),
+            Cell::new(price(&opp.buy_exchange, opp.buy_price)),
+            Cell::new(price(&opp.sell_exchange, opp.sell_price)),
+            Cell::new(format!("{:.3}%", numeric::safe_pct(opp.gross_profit_per_unit, opp.buy_price).unwrap_or(0.0))),
+            Cell::new(format!("{} {}", size.unwrap_or_else(|| sig_figs(opp.max_size, PRICE_FIGURES, 0)), base)),
+            Cell::new(amount(opp.estimated_fees, quote)),
+            Cell::new(amount(opp.net_profit, quote)).fg(tier.color()),
+            Cell::new(opp.capital_at_risk.map_or("-".to_string(), |capital| amount(capital.amount, quote))),
+            Cell::new(format!("{:.2}%", opp.roi_percentage)).fg(tier.color()),
+            Cell::new(opp.annualized_roi_percentage.map_or("-".to_string(), |roi| format!("{:.0}%", roi))),
+            Cell::new(tier.label()).fg(tier.color()),
+        ]);
+        if let Some(competition) = &opp.competition {
+            notes.push(format!(
+                "  #{} competition {:.2}: median spread life {}, {} pending swaps",
+                idx + 1,
+                competition.score,
+                competition.median_close_secs.map(|s| format!("{:.1}s", s)).unwrap_or_else(|| "n/a".to_string()),
+                competition.pending_swaps
+            ));
+        }
+        if let Some(laggard) = &opp.laggard {
+            notes.push(format!(
+                "  #{} laggard: {} trails {} by ~{:.0}ms; {} moved {}ms ago, likely gone at execution",
+                idx + 1,
+                laggard.laggard,
+                laggard.leader,
+                laggard.typical_lag_ms,
+                laggard.leader,
+                laggard.leader_moved_ms_ago
+            ));
+        }
+    }
+    println!("{}", table);
+    for note in notes {
+        println!("{}", note);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn figures_follow_the_magnitude_of_each_asset() {
+        assert_eq!(sig_figs(50_123.456, 6, 2), "50123.46");
+        assert_eq!(sig_figs(2512.3456, 6, 2), "2512.35");
+        assert_eq!(sig_figs(0.000012345678, 6, 2), "0.0000123457");
+        assert_eq!(sig_figs(0.8, 6, 0), "0.800000");
+        assert_eq!(sig_figs(14.0, 6, 0), "14.0000");
+        assert_eq!(sig_figs(0.0, 6, 2), "0.00000");
+        assert_eq!(amount(1_234.5, "USDT"), "1234.50 USDT");
+        assert_eq!(amount(-0.00012346, "BTC"), "-0.0001235 BTC");
+        assert_eq!((RoiTier::of(2.5), RoiTier::of(1.5), RoiTier::of(1.0)), (RoiTier::HighProfit, RoiTier::Moderate, RoiTier::LowMargin));
+    }
+
+    #[test]
+    fn summaries_cover_the_passes_since_the_last_one() {
+        assert_eq!("Summary".parse::<ReportPolicy>().unwrap(), ReportPolicy::Summary);
+        assert!("verbose".parse::<ReportPolicy>().is_err());
+
+        let analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let btc = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        let eth = analyzer.evaluate_opportunity("binance", "bybit", "ETH/USDT", 2_500.0, 2_560.0, 20.0, 20.0).unwrap();
+
+        let start = Utc::now();
+        let mut reporter = Reporter::new(ReportPolicy::Summary, 5, Duration::seconds(60));
+        reporter.window = Window::new(start);
+        reporter.observe(&[btc.clone(), eth.clone()]);
+        reporter.observe(&[]);
+        reporter.observe(std::slice::from_ref(&btc));
+        assert!(reporter.take_due_window(start + Duration::seconds(59)).is_none());
+
+        let window = reporter.take_due_window(start + Duration::seconds(60)).unwrap();
+        assert_eq!((window.passes, window.found, window.routes.len()), (3, 3, 2));
+        assert_eq!(window.total_net_profit.keys().collect::<Vec<_>>(), vec!["USDT"]);
+        assert!((window.total_net_profit["USDT"] - (btc.net_profit * 2.0 + eth.net_profit)).abs() < 1e-9);
+        assert_eq!(window.best.map(|best| best.id), Some(if btc.net_profit > eth.net_profit { btc.id } else { eth.id }));
+        // A fresh window starts with the summary
+        assert_eq!(reporter.window.passes, 0);
+        assert!(reporter.take_due_window(start + Duration::seconds(61)).is_none());
+
+        // Other policies never summarize
+        reporter.policy = ReportPolicy::Top;
+        assert!(reporter.take_due_window(start + Duration::seconds(600)).is_none());
+    }
+}
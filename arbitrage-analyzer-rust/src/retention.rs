@@ -0,0 +1,164 @@
+// Retention for the long-term opportunity stores, so a deployment left running
+// doesn't grow without bound. Every HISTORY_MAINTENANCE_SECS a background job
+// measures each configured store and, when retention is set, prunes it:
+//  - Postgres (DATABASE_URL): opportunities older than HISTORY_RETENTION_DAYS are
+//    rolled up into `opportunity_hourly` (count, total and max net profit, total
+//    ROI per route and UTC hour) and deleted, along with older market summaries.
+//    Opportunities an execution request points at are kept. Hourly rows older
+//    than HISTORY_ROLLUP_RETENTION_DAYS are deleted. A pass that removed rows ends
+//    with VACUUM (ANALYZE) so the space is reused, unless HISTORY_VACUUM=false.
+//  - Parquet export (PARQUET_EXPORT_DIR): `date=` partitions older than
+//    PARQUET_RETENTION_DAYS are removed.
+// A retention of 0 (the default) keeps everything. The Redis archive has its own
+// compaction, see archive.rs.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, DurationRound, NaiveDate, Utc};
+use log::{info, warn};
+
+use crate::config;
+use crate::metrics::Metrics;
+
+const DEFAULT_MAINTENANCE_SECS: u64 = 3600;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+pub struct RetentionConfig {
+    pub interval: Duration,
+    // Days of each tier to keep; None keeps everything
+    pub raw_days: Option<i64>,
+    pub rollup_days: Option<i64>,
+    pub parquet_days: Option<i64>,
+    pub vacuum: bool,
+}
+
+fn days(var: &str) -> Option<i64> {
+    Some(config::env_or::<i64>(var, 0)).filter(|days| *days > 0)
+}
+
+impl RetentionConfig {
+    pub fn from_env() -> Self {
+        RetentionConfig {
+            interval: Duration::from_secs(config::env_or("HISTORY_MAINTENANCE_SECS", DEFAULT_MAINTENANCE_SECS).max(1)),
+            raw_days: days("HISTORY_RETENTION_DAYS"),
+            rollup_days: days("HISTORY_ROLLUP_RETENTION_DAYS"),
+            parquet_days: days("PARQUET_RETENTION_DAYS"),
+            vacuum: config::env_or("HISTORY_VACUUM", true),
+        }
+    }
+
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    /// Rows detected before this are rolled up. On an hour boundary, so no hour is
+    /// split between raw rows and its aggregate
+    pub fn raw_cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.raw_days.map(|days| {
+            let cutoff = now - chrono::Duration::days(days);
+            cutoff.duration_trunc(chrono::Duration::hours(1)).unwrap_or(cutoff)
+        })
+    }
+
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    pub fn rollup_cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.rollup_days.map(|days| now - chrono::Duration::days(days))
+    }
+
+    /// The oldest export day kept
+    pub fn parquet_cutoff(&self, now: DateTime<Utc>) -> Option<NaiveDate> {
+        self.parquet_days.map(|days| (now - chrono::Duration::days(days)).date_naive())
+    }
+}
+
+/// Remove the `date=` partitions of every dataset under `root` from before `keep_from`.
+/// Returns the partitions removed and the bytes left in the export
+pub fn prune_partitions(root: &Path, keep_from: Option<NaiveDate>) -> Result<(usize, u64)> {
+    let mut removed = 0;
+    let mut bytes = 0;
+    for dataset in fs::read_dir(root)? {
+        let dataset = dataset?.path();
+        if !dataset.is_dir() {
+            continue;
+        }
+        for partition in fs::read_dir(&dataset)? {
+            let partition = partition?.path();
+            let date = partition
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("date="))
+                .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok());
+            match (date, keep_from) {
+                (Some(date), Some(keep_from)) if date < keep_from => {
+                    fs::remove_dir_all(&partition)?;
+                    removed += 1;
+                }
+                _ => bytes += dir_size(&partition),
+            }
+        }
+    }
+    Ok((removed, bytes))
+}
+
+fn dir_size(path: &Path) -> u64 {
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_dir() => {
+            fs::read_dir(path).map(|entries| entries.flatten().map(|entry| dir_size(&entry.path())).sum()).unwrap_or(0)
+        }
+        Ok(meta) => meta.len(),
+        Err(_) => 0,
+    }
+}
+
+/// Measure and prune the Parquet export on a background thread
+pub fn spawn_parquet(config: RetentionConfig, root: PathBuf, metrics: Arc<Metrics>) {
+    info!(
+        "  Parquet export retention: {}",
+        config.parquet_days.map_or("keep everything".to_string(), |days| format!("{} days", days))
+    );
+    thread::spawn(move || loop {
+        // Nothing has been exported yet
+        if root.is_dir() {
+            match prune_partitions(&root, config.parquet_cutoff(Utc::now())) {
+                Ok((removed, bytes)) => {
+                    if removed > 0 {
+                        info!("Removed {} Parquet partitions past retention", removed);
+                    }
+                    metrics.parquet_export_bytes.store(bytes, Ordering::Relaxed);
+                }
+                Err(e) => warn!("Parquet retention pass failed: {}", e),
+            }
+        }
+        thread::sleep(config.interval);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn cutoffs_and_partition_pruning() {
+        let config = RetentionConfig { interval: Duration::from_secs(60), raw_days: Some(7), rollup_days: None, parquet_days: Some(2), vacuum: true };
+        let now = Utc.with_ymd_and_hms(2024, 3, 10, 15, 42, 7).unwrap();
+        assert_eq!(config.raw_cutoff(now), Some(Utc.with_ymd_and_hms(2024, 3, 3, 15, 0, 0).unwrap()));
+        assert_eq!(config.rollup_cutoff(now), None);
+        assert_eq!(config.parquet_cutoff(now), NaiveDate::from_ymd_opt(2024, 3, 8));
+
+        let root = std::env::temp_dir().join(format!("swapsleuth-retention-{}", uuid::Uuid::new_v4()));
+        for partition in ["opportunities/date=2024-03-07", "opportunities/date=2024-03-08", "spreads/date=2024-03-01", "spreads/notes"] {
+            fs::create_dir_all(root.join(partition)).unwrap();
+            fs::write(root.join(partition).join("part.parquet"), [0u8; 10]).unwrap();
+        }
+        assert_eq!(prune_partitions(&root, config.parquet_cutoff(now)).unwrap(), (2, 20));
+        assert!(root.join("opportunities/date=2024-03-08").exists() && !root.join("spreads/date=2024-03-01").exists());
+        // Without retention nothing goes, and everything is measured
+        assert_eq!(prune_partitions(&root, None).unwrap(), (0, 20));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
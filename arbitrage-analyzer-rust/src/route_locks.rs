@@ -0,0 +1,150 @@
+// Route reservations shared between deployments. Analyzers watching the same
+// venues find the same opportunities, and the one-request-per-route rule of each
+// (see `lifecycle`) only covers its own requests. With ROUTE_LOCK_TTL_MS set, an
+// execution request is only published once its route is reserved on the first
+// Redis source:
+//
+//   SET <ROUTE_LOCK_PREFIX><pair>|<buy exchange>|<sell exchange> <instance>/<request id> NX PX <ttl>
+//
+// A route another deployment holds is skipped, logged and counted in
+// `swapsleuth_route_lock_conflicts_total`. When Redis can't be asked, nothing is
+// published. The reservation expires on its own after the TTL, and is deleted as
+// soon as the request reaches a terminal state, but only while it still names that
+// request. ROUTE_LOCK_INSTANCE names this deployment in the lock (default: HOSTNAME
+// and the process id).
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use log::debug;
+use redis::{Client, Connection, Script};
+
+use crate::config;
+use crate::lifecycle::RouteKey;
+
+pub const DEFAULT_PREFIX: &str = "swapsleuth:route-lock:";
+
+// Delete the lock only while it still names the request that took it
+const RELEASE_SCRIPT: &str = r#"if redis.call("GET", KEYS[1]) == ARGV[1] then return redis.call("DEL", KEYS[1]) else return 0 end"#;
+
+pub struct RouteLocks {
+    // 0: no reservations
+    ttl_ms: u64,
+    prefix: String,
+    instance: String,
+    client: Option<Client>,
+    // Opened on first use and dropped when a command fails
+    connection: Option<Connection>,
+    // Lock key and value, by request id
+    held: HashMap<String, (String, String)>,
+}
+
+impl std::fmt::Debug for RouteLocks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RouteLocks")
+            .field("ttl_ms", &self.ttl_ms)
+            .field("prefix", &self.prefix)
+            .field("instance", &self.instance)
+            .field("held", &self.held)
+            .finish_non_exhaustive()
+    }
+}
+
+/// ROUTE_LOCK_INSTANCE, or HOSTNAME and the process id
+pub fn instance_from_env() -> String {
+    config::env_var("ROUTE_LOCK_INSTANCE").unwrap_or_else(|_| {
+        format!("{}-{}", config::env_var("HOSTNAME").unwrap_or_else(|_| "swapsleuth".to_string()), std::process::id())
+    })
+}
+
+impl RouteLocks {
+    pub fn new(ttl_ms: u64, prefix: &str, instance: &str) -> Self {
+        RouteLocks { ttl_ms, prefix: prefix.to_string(), instance: instance.to_string(), client: None, connection: None, held: HashMap::new() }
+    }
+
+    pub fn from_env() -> Self {
+        let prefix = config::env_var("ROUTE_LOCK_PREFIX").unwrap_or_else(|_| DEFAULT_PREFIX.to_string());
+        RouteLocks::new(config::env_or("ROUTE_LOCK_TTL_MS", 0), &prefix, &instance_from_env())
+    }
+
+    /// Reserve routes on `client`'s Redis
+    pub fn connect(&mut self, client: Client) {
+        if self.ttl_ms > 0 {
+            self.client = Some(client);
+        }
+    }
+
+    fn key(&self, route: &RouteKey) -> String {
+        format!("{}{}|{}|{}", self.prefix, route.pair, route.buy_exchange, route.sell_exchange)
+    }
+
+    fn connection(&mut self) -> Result<Option<&mut Connection>> {
+        let Some(client) = &self.client else { return Ok(None) };
+        if self.connection.is_none() {
+            self.connection = Some(client.get_connection()?);
+        }
+        Ok(self.connection.as_mut())
+    }
+
+    /// Reserve `route` for `request_id`. False when another deployment holds it; always true
+    /// without reservations
+    pub fn acquire(&mut self, route: &RouteKey, request_id: &str) -> Result<bool> {
+        let key = self.key(route);
+        let value = format!("{}/{}", self.instance, request_id);
+        let ttl_ms = self.ttl_ms;
+        let Some(con) = self.connection()? else { return Ok(true) };
+        let reply: redis::RedisResult<Option<String>> = redis::cmd("SET").arg(&key).arg(&value).arg("NX").arg("PX").arg(ttl_ms).query(con);
+        match reply {
+            Ok(Some(_)) => {
+                self.held.insert(request_id.to_string(), (key, value));
+                Ok(true)
+            }
+            Ok(None) => Ok(false),
+            Err(e) => {
+                self.connection = None;
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Give up the reservation of `request_id`, if it took one. Best effort: the lock expires anyway
+    pub fn release(&mut self, request_id: &str) {
+        let Some((key, value)) = self.held.remove(request_id) else { return };
+        let released = match self.connection() {
+            Ok(Some(con)) => Script::new(RELEASE_SCRIPT).key(&key).arg(&value).invoke::<i64>(con).map_err(anyhow::Error::from),
+            Ok(None) => return,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = released {
+            self.connection = None;
+            debug!("Route lock {} left to expire: {}", key, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mini_redis::MiniRedis;
+
+    #[test]
+    fn a_route_reserved_elsewhere_is_refused() {
+        let route = RouteKey::new("BTC/USDT", "binance", "okx");
+        let mut unlocked = RouteLocks::new(0, DEFAULT_PREFIX, "a");
+        unlocked.connect(MiniRedis::start().client());
+        assert!(unlocked.acquire(&route, "r0").unwrap());
+
+        let server = MiniRedis::start();
+        let mut first = RouteLocks::new(2_000, DEFAULT_PREFIX, "a");
+        let mut second = RouteLocks::new(2_000, DEFAULT_PREFIX, "b");
+        first.connect(server.client());
+        second.connect(server.client());
+        assert!(first.acquire(&route, "r1").unwrap());
+        assert!(!second.acquire(&route, "r2").unwrap());
+        assert!(second.acquire(&RouteKey::new("BTC/USDT", "okx", "binance"), "r3").unwrap());
+        assert_eq!(first.held["r1"], ("swapsleuth:route-lock:BTC/USDT|binance|okx".to_string(), "a/r1".to_string()));
+        // Nothing held under this id: nothing to release
+        second.release("r2");
+        assert!(!second.acquire(&route, "r4").unwrap());
+    }
+}
@@ -0,0 +1,179 @@
+// Annualized yield per route, for allocating capital to routes that trigger
+// repeatedly rather than to the best single trade. Every expired opportunity is
+// one episode of its route, worth its peak net profit on the capital it needed.
+// Over the episodes of the last YIELD_WINDOW_HOURS:
+//
+//   yield = trades per year × average net profit / average capital required
+//
+// where trades per year is the observed episode rate, but never more than the
+// capital can turn over: one trade per lockup (see `capital`).
+
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::capital::SECONDS_PER_YEAR;
+use crate::expiry::OpportunityExpiry;
+use crate::lifecycle::RouteKey;
+use crate::{config, numeric};
+
+const DEFAULT_WINDOW_HOURS: i64 = 168;
+
+#[derive(Debug, Clone, Copy)]
+struct Episode {
+    ended_at: DateTime<Utc>,
+    net_profit: f64,
+    capital: f64,
+    lockup_secs: f64,
+    // USD value of the trade itself, i.e. of one leg
+    trade_notional_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct YieldEstimate {
+    pub episodes: usize,
+    // Over the part of the window the analyzer has been watching
+    pub observed_hours: f64,
+    pub triggers_per_day: f64,
+    pub avg_net_profit: f64,
+    // Quote asset, like the capital at risk of each opportunity
+    pub avg_capital: f64,
+    // Of one leg, when the quote asset has a USD price; what pre-funding one trade per venue takes
+    pub avg_trade_notional_usd: Option<f64>,
+    // Capped at one trade per capital lockup
+    pub trades_per_year: f64,
+    pub annualized_yield_pct: f64,
+}
+
+#[derive(Debug)]
+pub struct YieldTracker {
+    window: Duration,
+    started_at: DateTime<Utc>,
+    routes: HashMap<RouteKey, VecDeque<Episode>>,
+}
+
+impl YieldTracker {
+    pub fn new(window: Duration, started_at: DateTime<Utc>) -> Self {
+        YieldTracker { window, started_at, routes: HashMap::new() }
+    }
+
+    pub fn from_env() -> Self {
+        YieldTracker::new(Duration::hours(config::env_or("YIELD_WINDOW_HOURS", DEFAULT_WINDOW_HOURS)), Utc::now())
+    }
+
+    /// Count an expired opportunity as one episode of its route. Opportunities
+    /// without a capital figure can't contribute a yield and are skipped
+    pub fn record(&mut self, expiry: &OpportunityExpiry) {
+        let Some(capital) = expiry.capital_at_risk else { return };
+        if !numeric::all_finite(&[expiry.peak_net_profit, capital.amount]) || capital.amount <= 0.0 {
+            return;
+        }
+        let route = RouteKey::new(&expiry.pair, &expiry.buy_exchange, &expiry.sell_exchange);
+        self.routes.entry(route).or_default().push_back(Episode {
+            ended_at: expiry.expired_at,
+            net_profit: expiry.peak_net_profit,
+            capital: capital.amount,
+            lockup_secs: capital.lockup_secs,
+            // Pre-funded capital counts both legs
+            trade_notional_usd: capital.amount_usd.map(|usd| if capital.prefunded { usd / 2.0 } else { usd }),
+        });
+    }
+
+    pub fn estimate(&mut self, route: &RouteKey, now: DateTime<Utc>) -> Option<YieldEstimate> {
+        let episodes = self.routes.get_mut(route)?;
+        while episodes.front().is_some_and(|e| now - e.ended_at > self.window) {
+            episodes.pop_front();
+        }
+        if episodes.is_empty() {
+            return None;
+        }
+
+        let observed = (now - self.started_at).min(self.window);
+        let observed_secs = (observed.num_milliseconds() as f64 / 1000.0).max(1.0);
+        let count = episodes.len() as f64;
+        let avg = |f: fn(&Episode) -> f64| episodes.iter().map(f).sum::<f64>() / count;
+        let (avg_net_profit, avg_capital, avg_lockup) = (avg(|e| e.net_profit), avg(|e| e.capital), avg(|e| e.lockup_secs));
+
+        let observed_rate = count / observed_secs * SECONDS_PER_YEAR;
+        let trades_per_year = numeric::safe_div(SECONDS_PER_YEAR, avg_lockup).map_or(observed_rate, |cap| observed_rate.min(cap));
+        Some(YieldEstimate {
+            episodes: episodes.len(),
+            observed_hours: observed_secs / 3600.0,
+            triggers_per_day: count / observed_secs * 86_400.0,
+            avg_net_profit,
+            avg_capital,
+            avg_trade_notional_usd: episodes
+                .iter()
+                .map(|e| e.trade_notional_usd)
+                .sum::<Option<f64>>()
+                .map(|total| total / count),
+            trades_per_year,
+            annualized_yield_pct: numeric::safe_pct(trades_per_year * avg_net_profit, avg_capital)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capital::CapitalAtRisk;
+    use crate::expiry::ExpiryReason;
+
+    fn expiry(net_profit: f64, capital: f64, lockup_secs: f64, at: DateTime<Utc>) -> OpportunityExpiry {
+        OpportunityExpiry {
+            kind: "opportunity_expired",
+            opportunity_id: "o".to_string(),
+            latest_opportunity_id: "o".to_string(),
+            pair: "BTC/USDT".to_string(),
+            buy_exchange: "binance".to_string(),
+            sell_exchange: "okx".to_string(),
+            reason: ExpiryReason::NoLongerQualifies,
+            peak_net_profit: net_profit,
+            capital_at_risk: Some(CapitalAtRisk { amount: capital, amount_usd: Some(capital), lockup_secs, prefunded: false }),
+            detected_at: at,
+            expired_at: at,
+            tag: Default::default(),
+        }
+    }
+
+    fn route() -> RouteKey {
+        RouteKey::new("BTC/USDT", "binance", "okx")
+    }
+
+    // Four $50 episodes on $100k over a day, with an hour-long lockup
+    fn daily(start: DateTime<Utc>) -> YieldTracker {
+        let mut tracker = YieldTracker::new(Duration::hours(24), start);
+        for hour in [1, 7, 13, 19] {
+            tracker.record(&expiry(50.0, 100_000.0, 3_600.0, start + Duration::hours(hour)));
+        }
+        tracker
+    }
+
+    #[test]
+    fn yield_scales_with_frequency() {
+        let start = Utc::now();
+        let estimate = daily(start).estimate(&route(), start + Duration::hours(24)).unwrap();
+        assert!((estimate.triggers_per_day - 4.0).abs() < 1e-9);
+        // 1460 trades a year × $50 / $100k
+        assert!((estimate.annualized_yield_pct - 73.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn trades_are_capped_by_capital_turnover() {
+        let start = Utc::now();
+        // Triggering every minute can't be traded faster than the capital comes back
+        let mut busy = YieldTracker::new(Duration::hours(1), start);
+        for minute in 0..60 {
+            busy.record(&expiry(50.0, 100_000.0, 3_600.0, start + Duration::minutes(minute)));
+        }
+        let estimate = busy.estimate(&route(), start + Duration::hours(1)).unwrap();
+        assert!((estimate.trades_per_year - 8_760.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn episodes_age_out_of_the_window() {
+        let start = Utc::now();
+        assert!(daily(start).estimate(&route(), start + Duration::hours(48)).is_none());
+    }
+}
@@ -0,0 +1,177 @@
+// Time-of-day and day-of-week seasonality of recorded opportunities. The history
+// store aggregates opportunities per route, ISO weekday and UTC hour; this module
+// rolls those cells up into per-route hour-of-day and weekday views and renders
+// the report as JSON or CSV.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use serde::{Deserialize, Serialize};
+
+use crate::lifecycle::RouteKey;
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Opportunities on one route in one (weekday, hour) slot, as aggregated by the database
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeasonalityCell {
+    pub route: RouteKey,
+    // ISO weekday, 1 = Monday
+    pub weekday: u32,
+    // Hour of day, UTC
+    pub hour: u32,
+    pub opportunities: i64,
+    pub total_net_profit: f64,
+    pub max_net_profit: f64,
+    pub total_roi_percentage: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeasonalityRow {
+    pub pair: String,
+    pub buy_exchange: String,
+    pub sell_exchange: String,
+    pub weekday: Option<String>,
+    pub hour: Option<u32>,
+    pub opportunities: i64,
+    pub total_net_profit: f64,
+    pub avg_net_profit: f64,
+    pub max_net_profit: f64,
+    pub avg_roi_percentage: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SeasonalityReport {
+    pub by_hour: Vec<SeasonalityRow>,
+    pub by_weekday: Vec<SeasonalityRow>,
+    pub by_weekday_hour: Vec<SeasonalityRow>,
+}
+
+#[derive(Debug, Default)]
+struct Totals {
+    opportunities: i64,
+    net_profit: f64,
+    max_net_profit: Option<f64>,
+    roi_percentage: f64,
+}
+
+impl Totals {
+    fn add(&mut self, cell: &SeasonalityCell) {
+        self.opportunities += cell.opportunities;
+        self.net_profit += cell.total_net_profit;
+        self.roi_percentage += cell.total_roi_percentage;
+        self.max_net_profit = Some(self.max_net_profit.map_or(cell.max_net_profit, |max| max.max(cell.max_net_profit)));
+    }
+
+    fn row(&self, route: &RouteKey, weekday: Option<u32>, hour: Option<u32>) -> SeasonalityRow {
+        let count = self.opportunities.max(1) as f64;
+        SeasonalityRow {
+            pair: route.pair.clone(),
+            buy_exchange: route.buy_exchange.clone(),
+            sell_exchange: route.sell_exchange.clone(),
+            weekday: weekday.map(weekday_name),
+            hour,
+            opportunities: self.opportunities,
+            total_net_profit: self.net_profit,
+            avg_net_profit: self.net_profit / count,
+            max_net_profit: self.max_net_profit.unwrap_or(0.0),
+            avg_roi_percentage: self.roi_percentage / count,
+        }
+    }
+}
+
+fn weekday_name(iso: u32) -> String {
+    WEEKDAYS.get(iso.wrapping_sub(1) as usize).copied().unwrap_or("?").to_string()
+}
+
+impl SeasonalityReport {
+    pub fn from_cells(cells: &[SeasonalityCell]) -> Self {
+        let mut by_hour: BTreeMap<(RouteKey, u32), Totals> = BTreeMap::new();
+        let mut by_weekday: BTreeMap<(RouteKey, u32), Totals> = BTreeMap::new();
+        let mut by_weekday_hour: BTreeMap<(RouteKey, u32, u32), Totals> = BTreeMap::new();
+
+        for cell in cells {
+            by_hour.entry((cell.route.clone(), cell.hour)).or_default().add(cell);
+            by_weekday.entry((cell.route.clone(), cell.weekday)).or_default().add(cell);
+            by_weekday_hour.entry((cell.route.clone(), cell.weekday, cell.hour)).or_default().add(cell);
+        }
+
+        SeasonalityReport {
+            by_hour: by_hour.iter().map(|((route, hour), totals)| totals.row(route, None, Some(*hour))).collect(),
+            by_weekday: by_weekday.iter().map(|((route, weekday), totals)| totals.row(route, Some(*weekday), None)).collect(),
+            by_weekday_hour: by_weekday_hour
+                .iter()
+                .map(|((route, weekday, hour), totals)| totals.row(route, Some(*weekday), Some(*hour)))
+                .collect(),
+        }
+    }
+
+    /// All three views in one table; `grouping` tells them apart
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "grouping,pair,buy_exchange,sell_exchange,weekday,hour,opportunities,total_net_profit,avg_net_profit,max_net_profit,avg_roi_percentage\n",
+        );
+        let groups = [("hour", &self.by_hour), ("weekday", &self.by_weekday), ("weekday_hour", &self.by_weekday_hour)];
+        for (grouping, rows) in groups {
+            for row in rows {
+                let _ = writeln!(
+                    csv,
+                    "{},{},{},{},{},{},{},{:.6},{:.6},{:.6},{:.6}",
+                    grouping,
+                    row.pair,
+                    row.buy_exchange,
+                    row.sell_exchange,
+                    row.weekday.as_deref().unwrap_or(""),
+                    row.hour.map(|h| h.to_string()).unwrap_or_default(),
+                    row.opportunities,
+                    row.total_net_profit,
+                    row.avg_net_profit,
+                    row.max_net_profit,
+                    row.avg_roi_percentage
+                );
+            }
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(weekday: u32, hour: u32, opportunities: i64, total_net_profit: f64, max_net_profit: f64) -> SeasonalityCell {
+        SeasonalityCell {
+            route: RouteKey::new("BTC/USDT", "binance", "uniswap-v3-exact"),
+            weekday,
+            hour,
+            opportunities,
+            total_net_profit,
+            max_net_profit,
+            total_roi_percentage: opportunities as f64 * 0.5,
+        }
+    }
+
+    #[test]
+    fn rolls_cells_up_by_hour_and_weekday() {
+        let report = SeasonalityReport::from_cells(&[cell(1, 14, 2, 30.0, 20.0), cell(3, 14, 1, 6.0, 6.0), cell(3, 9, 4, 8.0, 3.0)]);
+
+        assert_eq!(report.by_weekday_hour.len(), 3);
+        assert_eq!(report.by_hour.iter().map(|r| (r.hour, r.opportunities)).collect::<Vec<_>>(), vec![(Some(9), 4), (Some(14), 3)]);
+        let afternoon = &report.by_hour[1];
+        assert_eq!((afternoon.total_net_profit, afternoon.avg_net_profit, afternoon.max_net_profit), (36.0, 12.0, 20.0));
+        assert_eq!(afternoon.avg_roi_percentage, 0.5);
+
+        let weekdays: Vec<_> = report.by_weekday.iter().map(|r| (r.weekday.as_deref(), r.opportunities)).collect();
+        assert_eq!(weekdays, vec![(Some("Mon"), 2), (Some("Wed"), 5)]);
+    }
+
+    #[test]
+    fn csv_has_one_line_per_row() {
+        let report = SeasonalityReport::from_cells(&[cell(7, 0, 1, 5.0, 5.0)]);
+        let csv = report.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1], "hour,BTC/USDT,binance,uniswap-v3-exact,,0,1,5.000000,5.000000,5.000000,0.500000");
+        assert!(lines[3].starts_with("weekday_hour,BTC/USDT,binance,uniswap-v3-exact,Sun,0,1,"));
+    }
+}
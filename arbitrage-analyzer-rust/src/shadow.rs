@@ -0,0 +1,210 @@
+// Shadow evaluation of a candidate fee model on live traffic, so a fee change can
+// be validated before it is switched on. The candidate is the active FeesConfig
+// with SHADOW_FEES applied (`field:value` overrides, e.g.
+// `binance_taker_fee:0.075,withdrawal_fee.BTC:0.0002`) and, with
+// SHADOW_ACCOUNT_PROFILE, evaluated under that profile from ACCOUNT_PROFILES_FILE
+// instead of ACCOUNT_PROFILE.
+//
+// Every route the active model evaluates is priced again under the candidate.
+// The candidate never changes what is published or executed. Routes where the
+// two accept/reject decisions differ are logged (throttled per route), counted
+// per route and served with the most recent ones on `GET /shadow/fees`.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::lifecycle::RouteKey;
+use crate::profiles::AccountProfile;
+use crate::throttle::LogThrottle;
+use crate::{config, ArbitrageOpportunity, FeesConfig, UnknownExchangePolicy};
+
+const RECENT_DIVERGENCES: usize = 100;
+const LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Decision {
+    pub accepted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub net_profit: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roi_percentage: Option<f64>,
+}
+
+impl Decision {
+    fn of(opportunity: Option<&ArbitrageOpportunity>) -> Self {
+        Decision {
+            accepted: opportunity.is_some(),
+            net_profit: opportunity.map(|opp| opp.net_profit),
+            roi_percentage: opportunity.map(|opp| opp.roi_percentage),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Divergence {
+    pub route: RouteKey,
+    pub at: DateTime<Utc>,
+    pub active: Decision,
+    pub candidate: Decision,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ShadowCounts {
+    pub evaluated: u64,
+    pub both_accepted: u64,
+    // Accepted by the active model only, i.e. the candidate would stop trading it
+    pub active_only: u64,
+    // Accepted by the candidate only, i.e. switching would start trading it
+    pub candidate_only: u64,
+}
+
+#[derive(Debug, Default)]
+struct ShadowState {
+    totals: ShadowCounts,
+    routes: BTreeMap<RouteKey, ShadowCounts>,
+    recent: VecDeque<Divergence>,
+}
+
+#[derive(Debug)]
+pub struct ShadowFees {
+    pub candidate: FeesConfig,
+    // What differs from the active model, for logs and the report
+    pub description: String,
+    state: Mutex<ShadowState>,
+    log_throttle: LogThrottle,
+}
+
+impl ShadowFees {
+    pub fn new(candidate: FeesConfig, description: String) -> Self {
+        ShadowFees { candidate, description, state: Mutex::default(), log_throttle: LogThrottle::new(LOG_INTERVAL) }
+    }
+
+    /// The candidate derived from `active`, or None when no shadow settings are configured
+    pub fn from_env(active: &FeesConfig) -> Result<Option<Self>> {
+        let overrides = config::env_map::<f64>("SHADOW_FEES");
+        let profile = config::env_var("SHADOW_ACCOUNT_PROFILE").ok();
+        if overrides.is_empty() && profile.is_none() {
+            return Ok(None);
+        }
+
+        let mut candidate = active.clone();
+        let mut changes: Vec<String> = Vec::new();
+        let mut fields: Vec<_> = overrides.into_iter().collect();
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        for (field, value) in fields {
+            candidate.set_field(&field, value).context("SHADOW_FEES")?;
+            changes.push(format!("{}={}", field, value));
+        }
+        if let Some(name) = profile {
+            candidate.profile = Some(AccountProfile::load_named(&name).context("SHADOW_ACCOUNT_PROFILE")?);
+            changes.push(format!("profile={}", name));
+        }
+        Ok(Some(ShadowFees::new(candidate, changes.join(", "))))
+    }
+
+    /// Whether the candidate prices a route between these venues at all
+    pub fn admits_venues(&self, buy_exchange: &str, sell_exchange: &str) -> bool {
+        self.candidate.unknown_exchange_policy != UnknownExchangePolicy::Reject
+            || [buy_exchange, sell_exchange].iter().all(|e| self.candidate.is_registered_exchange(e))
+    }
+
+    /// Record both models' outcome on `route`; true when their decisions diverge
+    pub fn compare(
+        &self,
+        route: RouteKey,
+        active: Option<&ArbitrageOpportunity>,
+        candidate: Option<&ArbitrageOpportunity>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let (active, candidate) = (Decision::of(active), Decision::of(candidate));
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let ShadowState { totals, routes, recent } = &mut *state;
+        let route_counts = routes.entry(route.clone()).or_default();
+        for counts in [totals, route_counts] {
+            counts.evaluated += 1;
+            match (active.accepted, candidate.accepted) {
+                (true, true) => counts.both_accepted += 1,
+                (true, false) => counts.active_only += 1,
+                (false, true) => counts.candidate_only += 1,
+                (false, false) => {}
+            }
+        }
+        if active.accepted == candidate.accepted {
+            return false;
+        }
+
+        let (kept, verdict) = if active.accepted { ("active", "rejects") } else { ("candidate", "accepts") };
+        self.log_throttle.log(
+            log::Level::Info,
+            &format!("shadow:{}:{}", route, kept),
+            format_args!(
+                "Shadow fees ({}) {} {} (active net ${:.2}, candidate net ${:.2})",
+                self.description,
+                verdict,
+                route,
+                active.net_profit.unwrap_or(0.0),
+                candidate.net_profit.unwrap_or(0.0)
+            ),
+        );
+        if recent.len() == RECENT_DIVERGENCES {
+            recent.pop_front();
+        }
+        recent.push_back(Divergence { route, at: now, active, candidate });
+        true
+    }
+
+    pub fn report(&self) -> serde_json::Value {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let diverging: Vec<_> = state
+            .routes
+            .iter()
+            .filter(|(_, counts)| counts.active_only + counts.candidate_only > 0)
+            .map(|(route, counts)| serde_json::json!({ "route": route, "counts": counts }))
+            .collect();
+        serde_json::json!({
+            "candidate": self.description,
+            "totals": state.totals,
+            "routes": diverging,
+            "recent": state.recent.iter().rev().collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpreadAnalyzer;
+
+    #[test]
+    fn counts_routes_where_the_candidate_decides_differently() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let mut candidate = analyzer.fees_config.clone();
+        candidate.set_field("okx_taker_fee", 0.8).unwrap();
+        assert!(candidate.set_field("okx_fee", 0.8).is_err());
+        analyzer.shadow_fees = Some(ShadowFees::new(candidate, "okx_taker_fee=0.8".to_string()));
+
+        let shadow = analyzer.shadow_fees.as_ref().unwrap();
+        let price = |fees: &FeesConfig, buy: &str, sell: &str| {
+            analyzer.price_route(fees, buy, sell, "BTC/USDT", 50_000.0, 50_400.0, 1.0, 1.0)
+        };
+        // 0.8% spread: both models accept binance -> bybit, only the active one clears okx's higher fee
+        for (buy, sell) in [("binance", "bybit"), ("okx", "bybit")] {
+            let route = RouteKey::new("BTC/USDT", buy, sell);
+            let (active, candidate) = (price(&analyzer.fees_config, buy, sell), price(&shadow.candidate, buy, sell));
+            shadow.compare(route, active.as_ref(), candidate.as_ref(), Utc::now());
+        }
+
+        let report = shadow.report();
+        assert_eq!(report["totals"]["evaluated"], 2);
+        assert_eq!(report["totals"]["both_accepted"], 1);
+        assert_eq!(report["totals"]["active_only"], 1);
+        assert_eq!(report["routes"].as_array().unwrap().len(), 1);
+        assert_eq!(report["recent"][0]["route"]["buy_exchange"], "okx");
+        assert_eq!(report["recent"][0]["candidate"]["accepted"], false);
+    }
+}
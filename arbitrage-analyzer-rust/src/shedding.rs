@@ -0,0 +1,149 @@
+// Graceful degradation under extreme update rates. Every cycle of the analysis
+// loop (apply one book, analyze it) is timed against CYCLE_BUDGET_MS. A cycle
+// that runs over budget while books are still queued moves the analyzer one
+// level down:
+//  - top_of_book: an update only re-evaluates routes of its own pair, spread
+//    recording and shadow fee evaluation are skipped, and comprehensive passes
+//    are deferred until the backlog is gone,
+//  - priority_only: on top of that, updates for pairs without a priority above
+//    zero (see `priority`) are applied to the cache but not analyzed. Only
+//    reachable while some pair has one.
+// Each cycle that finishes with the queue empty moves back up one level.
+// Everything shed is counted in metrics rather than silently falling behind.
+
+use std::fmt;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::config;
+use crate::metrics::Metrics;
+use crate::priority::PairPriorities;
+
+const DEFAULT_CYCLE_BUDGET_MS: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ShedLevel {
+    Normal,
+    TopOfBook,
+    PriorityOnly,
+}
+
+impl fmt::Display for ShedLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ShedLevel::Normal => "normal",
+            ShedLevel::TopOfBook => "top_of_book",
+            ShedLevel::PriorityOnly => "priority_only",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct LoadShedder {
+    // Zero disables shedding
+    budget: Duration,
+    priorities: Arc<PairPriorities>,
+    level: ShedLevel,
+}
+
+impl LoadShedder {
+    pub fn new(budget: Duration, priorities: Arc<PairPriorities>) -> Self {
+        LoadShedder { budget, priorities, level: ShedLevel::Normal }
+    }
+
+    pub fn from_env(priorities: Arc<PairPriorities>) -> Self {
+        LoadShedder::new(Duration::from_millis(config::env_or("CYCLE_BUDGET_MS", DEFAULT_CYCLE_BUDGET_MS)), priorities)
+    }
+
+    pub fn level(&self) -> ShedLevel {
+        self.level
+    }
+
+    pub fn shedding(&self) -> bool {
+        self.level > ShedLevel::Normal
+    }
+
+    /// Whether an update on `normalized_pair` is analyzed at the current level
+    pub fn analyzes(&self, normalized_pair: &str) -> bool {
+        self.level < ShedLevel::PriorityOnly || self.priorities.priority(normalized_pair, Utc::now()) > 0
+    }
+
+    fn deepest(&self) -> ShedLevel {
+        if self.priorities.any_prioritized(Utc::now()) { ShedLevel::PriorityOnly } else { ShedLevel::TopOfBook }
+    }
+
+    /// Account for a finished cycle that took `elapsed` and left `backlog` books
+    /// queued. Returns the new level when it changed
+    pub fn finish_cycle(&mut self, elapsed: Duration, backlog: usize, metrics: &Metrics) -> Option<ShedLevel> {
+        if self.budget.is_zero() {
+            return None;
+        }
+        let over_budget = elapsed > self.budget;
+        if over_budget {
+            Metrics::inc(&metrics.cycles_over_budget);
+        }
+        let next = if over_budget && backlog > 0 {
+            if self.level == ShedLevel::Normal { ShedLevel::TopOfBook } else { self.deepest() }
+        } else if backlog == 0 {
+            if self.level == ShedLevel::PriorityOnly { ShedLevel::TopOfBook } else { ShedLevel::Normal }
+        } else {
+            self.level
+        };
+        metrics.shed_level.store(next as u64, Ordering::Relaxed);
+        (next != self.level).then(|| {
+            self.level = next;
+            next
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SLOW: Duration = Duration::from_millis(150);
+    const FAST: Duration = Duration::from_millis(10);
+
+    fn shedder(priority_pairs: &[&str]) -> LoadShedder {
+        let priorities = priority_pairs.iter().map(|pair| (pair.to_string(), 1)).collect();
+        LoadShedder::new(Duration::from_millis(100), Arc::new(PairPriorities::new(priorities, chrono::Duration::hours(24))))
+    }
+
+    #[test]
+    fn sheds_further_while_behind() {
+        let metrics = Metrics::default();
+        let mut shedder = shedder(&["BTC/USDT"]);
+        // Over budget with nothing queued is not a backlog
+        assert_eq!(shedder.finish_cycle(SLOW, 0, &metrics), None);
+        assert_eq!(shedder.finish_cycle(SLOW, 50, &metrics), Some(ShedLevel::TopOfBook));
+        assert!(shedder.analyzes("ETH/USDT"));
+        assert_eq!(shedder.finish_cycle(SLOW, 40, &metrics), Some(ShedLevel::PriorityOnly));
+        assert!(shedder.analyzes("BTC/USDT") && !shedder.analyzes("ETH/USDT"));
+        assert_eq!(shedder.finish_cycle(SLOW, 30, &metrics), None);
+        assert_eq!(metrics.cycles_over_budget.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn recovers_once_the_queue_is_drained() {
+        let metrics = Metrics::default();
+        let mut shedder = shedder(&["BTC/USDT"]);
+        shedder.finish_cycle(SLOW, 50, &metrics);
+        shedder.finish_cycle(SLOW, 40, &metrics);
+        // Fast cycles keep the level until the queue is drained
+        assert_eq!(shedder.finish_cycle(FAST, 5, &metrics), None);
+        assert_eq!(shedder.finish_cycle(FAST, 0, &metrics), Some(ShedLevel::TopOfBook));
+        assert_eq!(shedder.finish_cycle(FAST, 0, &metrics), Some(ShedLevel::Normal));
+    }
+
+    #[test]
+    fn without_priority_pairs_stops_at_top_of_book() {
+        let metrics = Metrics::default();
+        let mut plain = shedder(&[]);
+        plain.finish_cycle(SLOW, 50, &metrics);
+        assert_eq!(plain.finish_cycle(SLOW, 50, &metrics), None);
+        assert_eq!(plain.level, ShedLevel::TopOfBook);
+    }
+}
@@ -0,0 +1,50 @@
+// Graceful shutdown. A task on the I/O runtime watches for Ctrl+C (or SIGTERM) and
+// asks the analysis loop to stop after the update in hand; the loop then flushes
+// pending events and gives the publisher task up to SHUTDOWN_FLUSH_SECS to write
+// out what it has queued before the runtime is shut down. A second signal exits
+// at once.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{error, warn};
+use tokio::runtime::Handle;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub const DEFAULT_FLUSH_SECS: u64 = 5;
+
+/// Whether a shutdown signal has arrived
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Watch for Ctrl+C and SIGTERM on a task of `runtime`
+pub fn install(runtime: &Handle) {
+    runtime.spawn(async {
+        loop {
+            if let Err(e) = signal().await {
+                return error!("Cannot watch for shutdown signals: {}", e);
+            }
+            if REQUESTED.swap(true, Ordering::Relaxed) {
+                warn!("Second shutdown signal, exiting without flushing");
+                std::process::exit(130);
+            }
+            warn!("Shutdown requested, finishing the current update and flushing (again to exit now)");
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+async fn signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
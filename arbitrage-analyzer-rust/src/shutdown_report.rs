@@ -0,0 +1,195 @@
+// Shutdown report. On a graceful shutdown the analyzer leaves one structured
+// summary of the run behind for ops review: uptime, books applied and rejected
+// per venue, opportunities found and published, execution requests and what
+// blocked them, how full the book cache and pipeline got, and error counts.
+// It is logged as one JSON line (and printed as a `shutdown` line under
+// `--output jsonl`), and with SHUTDOWN_REPORT_KEY set also written to that Redis
+// key before the publisher drains, expiring after SHUTDOWN_REPORT_TTL_SECS if
+// set. Counts cover this run only; `updates_applied` is the checkpointed total.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::Serialize;
+
+use crate::buildinfo::BuildInfo;
+use crate::report::{self, OutputFormat};
+use crate::{config, SpreadAnalyzer};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VenueRun {
+    pub venue: String,
+    pub books_applied: u64,
+    pub books_rejected: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OpportunityCounts {
+    pub found: u64,
+    pub published: u64,
+    pub clustered: u64,
+    pub expired: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionCounts {
+    pub in_flight: usize,
+    pub halted_by_kill_switch: u64,
+    pub blocked_by_pre_trade_checks: u64,
+    pub suppressed_in_flight: u64,
+    pub unacknowledged: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BookCacheBudget {
+    pub books: u64,
+    pub bytes: u64,
+    // 0: unlimited
+    pub max_books: usize,
+    pub max_bytes: usize,
+    pub evictions: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineBudget {
+    pub capacity: usize,
+    pub depth: u64,
+    pub dropped: u64,
+    pub coalesced: u64,
+    pub blocked: u64,
+    pub cycles_over_budget: u64,
+    pub shed_updates_skipped: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Budgets {
+    pub book_cache: BookCacheBudget,
+    pub pipeline: PipelineBudget,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorCounts {
+    pub analysis: u64,
+    pub redis: u64,
+    pub books_rejected: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShutdownReport {
+    pub started_at: DateTime<Utc>,
+    pub stopped_at: DateTime<Utc>,
+    pub uptime_secs: i64,
+    pub mode: String,
+    pub build: BuildInfo,
+    pub books_processed: u64,
+    pub updates_applied: u64,
+    // Busiest first
+    pub venues: Vec<VenueRun>,
+    pub opportunities: OpportunityCounts,
+    pub execution_requests: ExecutionCounts,
+    pub budgets: Budgets,
+    pub errors: ErrorCounts,
+}
+
+fn load(counter: &AtomicU64) -> u64 {
+    counter.load(Ordering::Relaxed)
+}
+
+impl SpreadAnalyzer {
+    pub(crate) fn shutdown_report(&self, now: DateTime<Utc>) -> ShutdownReport {
+        let metrics = &self.metrics;
+        let mut venues: Vec<VenueRun> = self
+            .ingest_stats
+            .summaries(now)
+            .into_iter()
+            .map(|summary| VenueRun { venue: summary.exchange, books_applied: summary.accepted, books_rejected: summary.rejected })
+            .collect();
+        venues.sort_by(|a, b| b.books_applied.cmp(&a.books_applied).then_with(|| a.venue.cmp(&b.venue)));
+        let books_rejected = venues.iter().map(|venue| venue.books_rejected).sum();
+        ShutdownReport {
+            started_at: self.started_at,
+            stopped_at: now,
+            uptime_secs: (now - self.started_at).num_seconds(),
+            mode: self.mode.to_string(),
+            build: self.build_info.clone(),
+            books_processed: load(&metrics.books_processed),
+            updates_applied: self.counters.updates_applied,
+            venues,
+            opportunities: OpportunityCounts {
+                found: load(&metrics.opportunities_found),
+                published: load(&metrics.opportunities_published),
+                clustered: load(&metrics.clustered_opportunities_suppressed),
+                expired: load(&metrics.opportunities_expired),
+            },
+            execution_requests: ExecutionCounts {
+                in_flight: self.lifecycle.in_flight().len(),
+                halted_by_kill_switch: load(&metrics.execution_requests_halted),
+                blocked_by_pre_trade_checks: load(&metrics.pre_trade_rejections),
+                suppressed_in_flight: load(&metrics.execution_requests_suppressed),
+                unacknowledged: load(&metrics.execution_requests_unacked),
+            },
+            budgets: Budgets {
+                book_cache: BookCacheBudget {
+                    books: load(&metrics.book_cache_entries),
+                    bytes: load(&metrics.book_cache_bytes),
+                    max_books: self.book_budget.max_books,
+                    max_bytes: self.book_budget.max_bytes,
+                    evictions: load(&metrics.book_cache_evictions),
+                },
+                pipeline: PipelineBudget {
+                    capacity: self.queue_capacity,
+                    depth: load(&metrics.pipeline_queue_depth),
+                    dropped: load(&metrics.pipeline_dropped),
+                    coalesced: load(&metrics.pipeline_coalesced),
+                    blocked: load(&metrics.pipeline_blocked),
+                    cycles_over_budget: load(&metrics.cycles_over_budget),
+                    shed_updates_skipped: load(&metrics.shed_updates_skipped),
+                },
+            },
+            errors: ErrorCounts { analysis: load(&metrics.analysis_errors), redis: load(&metrics.redis_errors), books_rejected },
+        }
+    }
+
+    /// Log the shutdown report, and queue it on SHUTDOWN_REPORT_KEY when set
+    pub(crate) fn emit_shutdown_report(&self, now: DateTime<Utc>) {
+        let report = self.shutdown_report(now);
+        let body = serde_json::to_value(&report).unwrap_or_default();
+        info!("Shutdown report: {}", body);
+        if self.reporter.format == OutputFormat::Jsonl {
+            report::emit("shutdown", body);
+        }
+        if let Ok(key) = config::env_var("SHUTDOWN_REPORT_KEY") {
+            let ttl_secs = config::env_or("SHUTDOWN_REPORT_TTL_SECS", 0usize);
+            self.publish_key(&key, &report, (ttl_secs > 0).then_some(ttl_secs));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use chrono::Duration;
+
+    #[test]
+    fn summarizes_the_run() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let now = Utc::now();
+        analyzer.started_at = now - Duration::seconds(90);
+        for _ in 0..3 {
+            analyzer.ingest_stats.record_accepted("okx", 10, now);
+        }
+        analyzer.ingest_stats.record_accepted("binance", 10, now);
+        analyzer.ingest_stats.record_rejected("binance");
+        Metrics::inc(&analyzer.metrics.opportunities_found);
+        Metrics::inc(&analyzer.metrics.redis_errors);
+
+        let report = analyzer.shutdown_report(now);
+        assert_eq!(report.uptime_secs, 90);
+        assert_eq!(report.venues.iter().map(|v| (v.venue.as_str(), v.books_applied)).collect::<Vec<_>>(), vec![("okx", 3), ("binance", 1)]);
+        assert_eq!((report.opportunities.found, report.errors.redis, report.errors.books_rejected), (1, 1, 1));
+        let body = serde_json::to_value(&report).unwrap();
+        assert_eq!(body["budgets"]["pipeline"]["capacity"], analyzer.queue_capacity);
+    }
+}
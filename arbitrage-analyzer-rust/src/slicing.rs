@@ -0,0 +1,313 @@
+// Slice planning of large execution requests. A route can show more size than one
+// order should take from the books at once: the first clip moves the price, and
+// the rest of the request fills at whatever is left. With SLICE_PLANNER set, a
+// request too large for one clip carries `slices`, the child orders the executor
+// works it in:
+//  - each slice's size, the window it may be sent in (`not_before` / `not_after`),
+//    and limit prices on both legs;
+//  - `abort` conditions for the rest of the plan: the spread falling below what
+//    the fees per unit need, too many slices failing, or the plan's deadline.
+// The built-in `twap` planner splits a request whose buy leg is worth more than
+// SLICE_MAX_CLIP_USD into equal slices, at most SLICE_MAX_SLICES, one every
+// SLICE_INTERVAL_MS. Limits sit SLICE_LIMIT_SLACK_BPS away from the priced
+// VWAPs, never so far that a slice filled at both limits loses money. Costs paid
+// once per order (ticket fees, transfers, gas) are paid by every slice, so a
+// request is cut into fewer slices, or none, when more of them would eat its
+// profit. Slice sizes and limits are rounded to the precision policy (see
+// `precision`); no slice is smaller than a lot, and the last one takes what the
+// rounding left. Builds with `--features venue-plugins` can pick a planner of their own
+// from `plugins::slice_planners` by its name. Requests with an atomic plan are
+// never sliced: the bundle is one transaction by design.
+
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::precision::{self, PrecisionPolicy};
+use crate::{config, plugins, ArbitrageOpportunity, SpreadAnalyzer};
+
+const DEFAULT_MAX_CLIP_USD: f64 = 10_000.0;
+const DEFAULT_MAX_SLICES: usize = 10;
+const DEFAULT_INTERVAL_MS: i64 = 2_000;
+const DEFAULT_LIMIT_SLACK_BPS: f64 = 5.0;
+const DEFAULT_MAX_FAILED_SLICES: usize = 2;
+
+/// What a planner is asked to split
+#[derive(Debug, Clone, Copy)]
+pub struct SliceInput<'a> {
+    pub opportunity: &'a ArbitrageOpportunity,
+    pub size: f64,
+    // Of the buy leg at `size`; None without a USD price for the quote asset
+    pub notional_usd: Option<f64>,
+    // Paid by every slice rather than per unit
+    pub fixed_costs: f64,
+    pub now: DateTime<Utc>,
+}
+
+pub trait SlicePlanner: fmt::Debug + Send + Sync {
+    /// Picked by SLICE_PLANNER, and named in the plan
+    fn name(&self) -> &str;
+    /// The child slices of a request; None sends it in one clip
+    fn plan(&self, input: &SliceInput) -> Option<SlicePlan>;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Slice {
+    pub index: usize,
+    pub size: f64,
+    pub not_before: DateTime<Utc>,
+    // A slice not sent by then is skipped
+    pub not_after: DateTime<Utc>,
+    // Pay at most this on the buy leg, take at least this on the sell leg
+    pub buy_limit: f64,
+    pub sell_limit: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AbortConditions {
+    // Stop once the sell venue's bid is less than this over the buy venue's ask: the fees per unit need it
+    pub min_spread_bps: f64,
+    // Stop after this many slices failed or filled short
+    pub max_failed_slices: usize,
+    // Drop whatever is left after this
+    pub deadline: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SlicePlan {
+    pub planner: String,
+    pub slices: Vec<Slice>,
+    pub abort: AbortConditions,
+    // The request's net profit less the fixed costs the extra slices pay
+    pub expected_net_profit: f64,
+}
+
+impl SlicePlan {
+    /// Equal slices of `size`, one every `interval` from `now`. Sizes and limits are rounded to the
+    /// precision policy, the last slice taking whatever the rounding left
+    pub fn even(planner: &str, input: &SliceInput, count: usize, interval: Duration, slack_bps: f64, max_failed_slices: usize) -> Self {
+        Self::even_with(precision::policy(), planner, input, count, interval, slack_bps, max_failed_slices)
+    }
+
+    fn even_with(
+        policy: &PrecisionPolicy,
+        planner: &str,
+        input: &SliceInput,
+        count: usize,
+        interval: Duration,
+        slack_bps: f64,
+        max_failed_slices: usize,
+    ) -> Self {
+        let opp = input.opportunity;
+        let venues = [opp.buy_exchange.as_str(), opp.sell_exchange.as_str()];
+        let fees_per_unit = opp.estimated_fees / input.size;
+        // A slice filled at both limits still covers its fees
+        let headroom = ((opp.sell_price - opp.buy_price - fees_per_unit) / 2.0).max(0.0);
+        let buy_limit = opp.buy_price + (opp.buy_price * slack_bps / 10_000.0).min(headroom);
+        let sell_limit = opp.sell_price - (opp.sell_price * slack_bps / 10_000.0).min(headroom);
+        let buy_limit = policy.round_price(&opp.buy_exchange, &opp.pair, buy_limit, true);
+        let sell_limit = policy.round_price(&opp.sell_exchange, &opp.pair, sell_limit, false);
+        // No more slices than the size has lots
+        let count = match policy.size_decimals(&opp.pair, &venues) {
+            Some(decimals) => count.min((input.size * 10f64.powi(decimals as i32) + 1e-9) as usize).max(1),
+            None => count,
+        };
+        let clip = policy.round_size(&opp.pair, &venues, input.size / count as f64);
+        let slices: Vec<Slice> = (0..count)
+            .map(|index| {
+                let not_before = input.now + interval * index as i32;
+                Slice {
+                    index,
+                    size: if index + 1 == count { policy.round_size(&opp.pair, &venues, input.size - clip * index as f64) } else { clip },
+                    not_before,
+                    not_after: not_before + interval,
+                    buy_limit,
+                    sell_limit,
+                }
+            })
+            .collect();
+        SlicePlan {
+            planner: planner.to_string(),
+            abort: AbortConditions {
+                min_spread_bps: fees_per_unit / opp.buy_price * 10_000.0,
+                max_failed_slices,
+                deadline: slices.last().map_or(input.now, |slice| slice.not_after),
+            },
+            slices,
+            expected_net_profit: opp.net_profit - input.fixed_costs * (count - 1) as f64,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Twap {
+    pub max_clip_usd: f64,
+    pub max_slices: usize,
+    pub interval: Duration,
+    pub limit_slack_bps: f64,
+    pub max_failed_slices: usize,
+}
+
+impl Twap {
+    pub fn from_env() -> Self {
+        Twap {
+            max_clip_usd: config::env_or("SLICE_MAX_CLIP_USD", DEFAULT_MAX_CLIP_USD),
+            max_slices: config::env_or("SLICE_MAX_SLICES", DEFAULT_MAX_SLICES).max(2),
+            interval: Duration::milliseconds(config::env_or("SLICE_INTERVAL_MS", DEFAULT_INTERVAL_MS)),
+            limit_slack_bps: config::env_or("SLICE_LIMIT_SLACK_BPS", DEFAULT_LIMIT_SLACK_BPS),
+            max_failed_slices: config::env_or("SLICE_MAX_FAILED", DEFAULT_MAX_FAILED_SLICES),
+        }
+    }
+}
+
+impl SlicePlanner for Twap {
+    fn name(&self) -> &str {
+        "twap"
+    }
+
+    fn plan(&self, input: &SliceInput) -> Option<SlicePlan> {
+        let notional = input.notional_usd?;
+        if self.max_clip_usd <= 0.0 || notional <= self.max_clip_usd {
+            return None;
+        }
+        let wanted = ((notional / self.max_clip_usd).ceil() as usize).min(self.max_slices);
+        // Fewer slices when each extra one's fixed costs would leave no profit
+        let affordable = (1..=wanted).rev().find(|count| input.opportunity.net_profit - input.fixed_costs * (*count - 1) as f64 > 0.0)?;
+        (affordable > 1).then(|| SlicePlan::even(self.name(), input, affordable, self.interval, self.limit_slack_bps, self.max_failed_slices))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Slicing {
+    // None: requests go out in one clip
+    planner: Option<Arc<dyn SlicePlanner>>,
+}
+
+impl Slicing {
+    pub fn new(planner: Option<Arc<dyn SlicePlanner>>) -> Self {
+        Slicing { planner }
+    }
+
+    /// SLICE_PLANNER: `twap`, or the name of a planner this build was compiled with
+    pub fn from_env() -> Result<Self> {
+        let name = config::env_var("SLICE_PLANNER").unwrap_or_default();
+        let planner: Arc<dyn SlicePlanner> = match name.trim() {
+            "" | "off" => return Ok(Slicing::default()),
+            "twap" => Arc::new(Twap::from_env()),
+            other => plugins::slice_planners()
+                .into_iter()
+                .find(|planner| planner.name() == other)
+                .ok_or_else(|| anyhow!("unknown slice planner {:?}; expected twap or one of plugins::slice_planners", other))?,
+        };
+        Ok(Slicing::new(Some(planner)))
+    }
+
+    pub fn planner_name(&self) -> Option<&str> {
+        self.planner.as_deref().map(|planner| planner.name())
+    }
+
+    pub fn plan(&self, input: &SliceInput) -> Option<SlicePlan> {
+        let plan = self.planner.as_ref()?.plan(input)?;
+        // A planner that loses size, sends an empty slice or nothing leaves the request whole
+        let planned: f64 = plan.slices.iter().map(|slice| slice.size).sum();
+        let filled = plan.slices.iter().all(|slice| slice.size > 0.0);
+        (plan.slices.len() > 1 && filled && (planned - input.size).abs() <= input.size * 1e-9).then_some(plan)
+    }
+}
+
+impl SpreadAnalyzer {
+    /// The slices `opp` goes out in at `size`, when the planner thinks one clip is too much
+    pub(crate) fn slice_plan(&self, opp: &ArbitrageOpportunity, size: f64, atomic: bool, now: DateTime<Utc>) -> Option<SlicePlan> {
+        if atomic || size <= 0.0 {
+            return None;
+        }
+        let fixed_costs = self.fees_config.fixed_leg_cost(&opp.buy_exchange)
+            + self.fees_config.fixed_leg_cost(&opp.sell_exchange)
+            + self.fees_config.transfer_cost(&opp.buy_exchange, &opp.sell_exchange);
+        self.slicing.plan(&SliceInput { opportunity: opp, size, notional_usd: self.notional_usd(opp, size), fixed_costs, now })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn twap() -> Twap {
+        Twap { max_clip_usd: 15_000.0, max_slices: 10, interval: Duration::seconds(2), limit_slack_bps: 5.0, max_failed_slices: 2 }
+    }
+
+    fn opportunity() -> ArbitrageOpportunity {
+        let analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 50_500.0, 1.0, 1.0).unwrap()
+    }
+
+    fn input(opp: &ArbitrageOpportunity) -> SliceInput<'_> {
+        SliceInput { opportunity: opp, size: 1.0, notional_usd: Some(50_000.0), fixed_costs: 0.0, now: Utc::now() }
+    }
+
+    #[test]
+    fn large_requests_are_cut_into_timed_slices() {
+        let opp = opportunity();
+        let input = input(&opp);
+        let plan = Slicing::new(Some(Arc::new(twap()))).plan(&input).unwrap();
+        assert_eq!(plan.planner, "twap");
+        assert_eq!(plan.slices.len(), 4);
+        assert!((plan.slices.iter().map(|slice| slice.size).sum::<f64>() - 1.0).abs() < 1e-12);
+        assert_eq!(plan.slices[3].not_before, input.now + Duration::seconds(6));
+        assert_eq!(plan.abort.deadline, input.now + Duration::seconds(8));
+    }
+
+    #[test]
+    fn slice_limits_still_cover_the_fees() {
+        let opp = opportunity();
+        let plan = twap().plan(&input(&opp)).unwrap();
+        let slice = &plan.slices[0];
+        assert!(slice.buy_limit > opp.buy_price && slice.sell_limit < opp.sell_price);
+        // Filled at both limits, a slice still covers its share of the fees
+        assert!(slice.sell_limit - slice.buy_limit >= opp.estimated_fees - 1e-9);
+        assert!((plan.abort.min_spread_bps - opp.estimated_fees / opp.buy_price * 10_000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn small_or_unpriced_requests_are_not_sliced() {
+        let opp = opportunity();
+        let input = input(&opp);
+        // One clip is enough, or unknown in USD
+        assert!(twap().plan(&SliceInput { notional_usd: Some(10_000.0), ..input }).is_none());
+        assert!(twap().plan(&SliceInput { notional_usd: None, ..input }).is_none());
+    }
+
+    #[test]
+    fn fixed_costs_leave_fewer_slices() {
+        let opp = opportunity();
+        let input = input(&opp);
+        // Fixed costs that would eat the profit of extra slices leave fewer of them
+        let fixed_costs = opp.net_profit / 2.5;
+        let plan = twap().plan(&SliceInput { fixed_costs, ..input }).unwrap();
+        assert_eq!(plan.slices.len(), 3);
+        assert!((plan.expected_net_profit - (opp.net_profit - 2.0 * fixed_costs)).abs() < 1e-9);
+        assert!(twap().plan(&SliceInput { fixed_costs: opp.net_profit, ..input }).is_none());
+    }
+
+    #[test]
+    fn slices_and_limits_follow_the_precision_policy() {
+        std::env::set_var("ASSET_PRECISION", "BTC:2");
+        std::env::set_var("PRICE_PRECISION", "BTC/USDT:0");
+        let policy = PrecisionPolicy::from_env();
+        std::env::remove_var("ASSET_PRECISION");
+        std::env::remove_var("PRICE_PRECISION");
+        let opp = opportunity();
+        let input = input(&opp);
+        let plan = SlicePlan::even_with(&policy, "twap", &input, 3, Duration::seconds(2), 3.3, 2);
+        let sizes: Vec<f64> = plan.slices.iter().map(|slice| slice.size).collect();
+        assert_eq!(sizes, vec![0.33, 0.33, 0.34]);
+        // Buy limit up, sell limit down, to whole dollars
+        assert_eq!((plan.slices[0].buy_limit, plan.slices[0].sell_limit), (50_017.0, 50_483.0));
+        // Two lots make two slices at most
+        let two_lots = SlicePlan::even_with(&policy, "twap", &SliceInput { size: 0.02, ..input }, 3, Duration::seconds(2), 3.3, 2);
+        assert_eq!(two_lots.slices.iter().map(|slice| slice.size).collect::<Vec<_>>(), vec![0.01, 0.01]);
+    }
+}
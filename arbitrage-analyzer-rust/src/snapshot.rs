@@ -0,0 +1,195 @@
+// Compact analyzer state written to Redis every STATE_SNAPSHOT_SECS under
+// STATE_SNAPSHOT_KEY (default `analyzer:state`), so other services and dashboards
+// can see what the analyzer is doing without the HTTP API: cached books and their
+// ages, breakers (kill switch, suspect and quarantined venues), how much of each
+// budget is used, and the last STATE_SNAPSHOT_OPPORTUNITIES opportunities. The key
+// expires after three intervals, so a snapshot that is gone means the analyzer is.
+
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::kill_switch::KillSwitchState;
+use crate::{config, ArbitrageOpportunity, SpreadAnalyzer};
+
+pub const DEFAULT_STATE_KEY: &str = "analyzer:state";
+const DEFAULT_INTERVAL_SECS: u64 = 10;
+const DEFAULT_RECENT_OPPORTUNITIES: usize = 20;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentOpportunity {
+    pub id: String,
+    pub pair: String,
+    pub buy_exchange: String,
+    pub sell_exchange: String,
+    pub net_profit: f64,
+    pub roi_percentage: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BookAge {
+    pub key: String,
+    pub age_ms: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Breakers {
+    pub kill_switch: KillSwitchState,
+    pub suspect_venues: Vec<String>,
+    // Venue -> maintenance reason
+    pub quarantined_venues: Vec<(String, String)>,
+}
+
+// Used against limit; a limit of 0 is unlimited
+#[derive(Debug, Serialize)]
+pub struct BudgetUse {
+    pub used: u64,
+    pub limit: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Budgets {
+    pub book_cache_books: BudgetUse,
+    pub book_cache_bytes: BudgetUse,
+    pub pipeline_queue: BudgetUse,
+    pub shed_level: String,
+    pub cycles_over_budget: u64,
+    pub in_flight_requests: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StateSnapshot {
+    pub generated_at: DateTime<Utc>,
+    pub mode: String,
+    pub books: Vec<BookAge>,
+    pub breakers: Breakers,
+    pub budgets: Budgets,
+    pub live_opportunities: usize,
+    // Newest first
+    pub recent_opportunities: Vec<RecentOpportunity>,
+}
+
+#[derive(Debug)]
+pub struct StateSnapshotter {
+    key: String,
+    // Zero disables snapshots
+    interval: Duration,
+    last_written: Option<Instant>,
+    recent: VecDeque<RecentOpportunity>,
+    capacity: usize,
+}
+
+impl StateSnapshotter {
+    pub fn from_env() -> Self {
+        StateSnapshotter {
+            key: config::env_var("STATE_SNAPSHOT_KEY").unwrap_or_else(|_| DEFAULT_STATE_KEY.to_string()),
+            interval: Duration::from_secs(config::env_or("STATE_SNAPSHOT_SECS", DEFAULT_INTERVAL_SECS)),
+            last_written: None,
+            recent: VecDeque::new(),
+            capacity: config::env_or("STATE_SNAPSHOT_OPPORTUNITIES", DEFAULT_RECENT_OPPORTUNITIES),
+        }
+    }
+
+    pub fn record(&mut self, opp: &ArbitrageOpportunity) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.recent.len() == self.capacity {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(RecentOpportunity {
+            id: opp.id.clone(),
+            pair: opp.pair.clone(),
+            buy_exchange: opp.buy_exchange.clone(),
+            sell_exchange: opp.sell_exchange.clone(),
+            net_profit: opp.net_profit,
+            roi_percentage: opp.roi_percentage,
+            timestamp: opp.timestamp,
+        });
+    }
+
+    fn due(&self, now: Instant) -> bool {
+        !self.interval.is_zero() && self.last_written.is_none_or(|last| now.duration_since(last) >= self.interval)
+    }
+}
+
+impl SpreadAnalyzer {
+    pub fn state_snapshot(&self, now: DateTime<Utc>) -> StateSnapshot {
+        let mut books: Vec<BookAge> = self.books.iter().map(|(key, book)| BookAge { key: key.clone(), age_ms: book.age_ms(now) }).collect();
+        books.sort_by(|a, b| a.key.cmp(&b.key));
+        let mut quarantined_venues: Vec<(String, String)> = self.quarantined.iter().map(|(v, r)| (v.clone(), r.clone())).collect();
+        quarantined_venues.sort();
+
+        StateSnapshot {
+            generated_at: now,
+            mode: self.mode.to_string(),
+            books,
+            breakers: Breakers {
+                kill_switch: self.kill_switch.state().clone(),
+                suspect_venues: self.watchdog.suspect_venues(),
+                quarantined_venues,
+            },
+            budgets: Budgets {
+                book_cache_books: BudgetUse { used: self.books.len() as u64, limit: self.book_budget.max_books as u64 },
+                book_cache_bytes: BudgetUse {
+                    used: self.metrics.book_cache_bytes.load(Ordering::Relaxed),
+                    limit: self.book_budget.max_bytes as u64,
+                },
+                pipeline_queue: BudgetUse {
+                    used: self.metrics.pipeline_queue_depth.load(Ordering::Relaxed),
+                    limit: self.queue_capacity as u64,
+                },
+                shed_level: self.shedder.level().to_string(),
+                cycles_over_budget: self.metrics.cycles_over_budget.load(Ordering::Relaxed),
+                in_flight_requests: self.lifecycle.in_flight().len(),
+            },
+            live_opportunities: self.live_opportunities.len(),
+            recent_opportunities: self.snapshotter.recent.iter().rev().cloned().collect(),
+        }
+    }
+
+    /// Write the state snapshot to Redis when one is due
+    pub fn write_state_snapshot_if_due(&mut self) {
+        let now = Instant::now();
+        if !self.snapshotter.due(now) {
+            return;
+        }
+        self.snapshotter.last_written = Some(now);
+        let ttl_secs = self.snapshotter.interval.as_secs().max(1) as usize * 3;
+        self.publish_key(&self.snapshotter.key, &self.state_snapshot(Utc::now()), Some(ttl_secs));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OrderBook;
+
+    #[test]
+    fn snapshot_lists_books_breakers_and_recent_opportunities() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.snapshotter.capacity = 2;
+        for (exchange, bid, ask) in [("binance", 50_000.0, 50_010.0), ("okx", 50_500.0, 50_510.0)] {
+            let book = OrderBook::for_test(exchange, "BTC/USDT", vec![vec![bid, 1.0]], vec![vec![ask, 1.0]]);
+            analyzer.books.insert(format!("{}:BTC/USDT", exchange), book);
+        }
+        let opportunities = analyzer.analyze_all_spreads().unwrap();
+        assert_eq!(opportunities.len(), 1);
+        for _ in 0..3 {
+            analyzer.snapshotter.record(&opportunities[0]);
+        }
+        analyzer.quarantined.insert("okx".to_string(), "wallet maintenance".to_string());
+
+        let snapshot = serde_json::to_value(analyzer.state_snapshot(Utc::now())).unwrap();
+        assert_eq!(snapshot["books"].as_array().unwrap().len(), 2);
+        assert_eq!(snapshot["books"][0]["key"], "binance:BTC/USDT");
+        assert_eq!(snapshot["breakers"]["quarantined_venues"][0][0], "okx");
+        assert_eq!(snapshot["budgets"]["book_cache_books"]["used"], 2);
+        assert_eq!(snapshot["budgets"]["shed_level"], "normal");
+        assert_eq!(snapshot["recent_opportunities"].as_array().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,228 @@
+// Solana AMM venues (Raydium, Orca). They differ from the EVM venues in three ways:
+//  - transactions pay a fixed 5000 lamports per signature plus an optional priority
+//    fee, valued in USD at the latest SOL price the analyzer has seen,
+//  - freshness is measured in slots: collectors stamp each book with the slot it was
+//    read at, and a book more than SOLANA_MAX_SLOT_LAG slots behind the newest slot
+//    seen on any Solana venue is stale,
+//  - pools are keyed by mint address, so pairs are mapped to symbols at ingest.
+
+use std::collections::HashMap;
+
+use log::info;
+
+use crate::alerts::Severity;
+use crate::config;
+use crate::events::{Event, EventClass};
+use crate::{OrderBook, SpreadAnalyzer};
+
+pub const VENUES: [&str; 2] = ["raydium", "orca"];
+
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+pub const BASE_FEE_LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+// ~30s at 400ms slots, in line with STALE_BOOK_AGE_MS
+const DEFAULT_MAX_SLOT_LAG: u64 = 75;
+
+// Mints every deployment needs; the rest come from SOLANA_TOKEN_MINTS
+const WRAPPED_SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+pub fn is_solana_venue(exchange: &str) -> bool {
+    VENUES.contains(&exchange)
+}
+
+#[derive(Debug, Clone)]
+pub struct SolanaFees {
+    pub signatures_per_swap: u64,
+    pub priority_fee_lamports: u64,
+    // Fallback until a SOL/USDC or SOL/USDT book arrives
+    pub sol_price_usd: f64,
+}
+
+impl Default for SolanaFees {
+    fn default() -> Self {
+        SolanaFees { signatures_per_swap: 1, priority_fee_lamports: 100_000, sol_price_usd: 150.0 }
+    }
+}
+
+impl SolanaFees {
+    pub fn from_env() -> Self {
+        let defaults = SolanaFees::default();
+        SolanaFees {
+            signatures_per_swap: config::env_or("SOLANA_SIGNATURES_PER_SWAP", defaults.signatures_per_swap),
+            priority_fee_lamports: config::env_or("SOLANA_PRIORITY_FEE_LAMPORTS", defaults.priority_fee_lamports),
+            sol_price_usd: config::env_or("SOL_PRICE_USD", defaults.sol_price_usd),
+        }
+    }
+
+    pub fn lamports_per_swap(&self) -> u64 {
+        self.signatures_per_swap * BASE_FEE_LAMPORTS_PER_SIGNATURE + self.priority_fee_lamports
+    }
+
+    /// Transaction cost of one swap in USD
+    pub fn leg_cost_usd(&self) -> f64 {
+        self.lamports_per_swap() as f64 / LAMPORTS_PER_SOL * self.sol_price_usd
+    }
+}
+
+/// Mint address -> symbol
+#[derive(Debug, Clone)]
+pub struct TokenMap(HashMap<String, String>);
+
+impl Default for TokenMap {
+    fn default() -> Self {
+        TokenMap(HashMap::from([
+            (WRAPPED_SOL_MINT.to_string(), "SOL".to_string()),
+            (USDC_MINT.to_string(), "USDC".to_string()),
+        ]))
+    }
+}
+
+impl TokenMap {
+    /// Defaults plus SOLANA_TOKEN_MINTS, e.g. `<mint>:BONK,<mint>:USDT`
+    pub fn from_env() -> Self {
+        let mut map = TokenMap::default();
+        map.0.extend(config::env_map::<String>("SOLANA_TOKEN_MINTS"));
+        map
+    }
+
+    /// Replace mint addresses in a `BASE/QUOTE` pair with their symbols; unknown sides are kept
+    pub fn map_pair(&self, pair: &str) -> String {
+        pair.split('/').map(|side| self.0.get(side).map_or(side, String::as_str)).collect::<Vec<_>>().join("/")
+    }
+}
+
+/// Newest slot seen across Solana venues
+#[derive(Debug, Clone)]
+pub struct SlotClock {
+    tip: Option<u64>,
+    max_lag: u64,
+}
+
+impl SlotClock {
+    pub fn new(max_lag: u64) -> Self {
+        SlotClock { tip: None, max_lag }
+    }
+
+    pub fn from_env() -> Self {
+        SlotClock::new(config::env_or("SOLANA_MAX_SLOT_LAG", DEFAULT_MAX_SLOT_LAG))
+    }
+
+    pub fn observe(&mut self, slot: u64) {
+        self.tip = Some(self.tip.map_or(slot, |tip| tip.max(slot)));
+    }
+
+    /// Slots `slot` is behind the tip
+    pub fn lag(&self, slot: u64) -> u64 {
+        self.tip.map_or(0, |tip| tip.saturating_sub(slot))
+    }
+
+    pub fn is_stale(&self, slot: u64) -> bool {
+        self.lag(slot) > self.max_lag
+    }
+
+    pub fn max_lag(&self) -> u64 {
+        self.max_lag
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Prepare a freshly received book: map Solana mints to symbols, advance the slot clock
+    /// and track the SOL price used for lamport fees. Returns why the book must be dropped, if it must.
+    pub(crate) fn admit_solana_book(&mut self, book: &mut OrderBook) -> Option<String> {
+        if is_solana_venue(&book.exchange) {
+            book.pair = self.solana_tokens.map_pair(&book.pair);
+            // Collectors that do not stamp slots fall back to the venue watchdog
+            if let Some(slot) = book.slot {
+                self.slot_clock.observe(slot);
+                if self.slot_clock.is_stale(slot) {
+                    return Some(format!("slot {} is {} slots behind the tip", slot, self.slot_clock.lag(slot)));
+                }
+            }
+        }
+        if let Some(price) = sol_usd_mid(book) {
+            self.fees_config.solana.sol_price_usd = price;
+        }
+        None
+    }
+
+    /// Whether a stored Solana book has fallen too far behind the slot tip to trade against
+    pub(crate) fn lags_slot_tip(&self, book: &OrderBook) -> bool {
+        is_solana_venue(&book.exchange) && book.slot.is_some_and(|slot| self.slot_clock.is_stale(slot))
+    }
+
+    pub(crate) fn reject_solana_book(&mut self, book: &OrderBook, reason: &str) {
+        self.ingest_stats.record_rejected(&book.exchange);
+        self.publish(
+            Event::new(EventClass::BookRejected, Severity::Info, format!("Rejected {}:{}: {}", book.exchange, book.pair, reason))
+                .with_venue(&book.exchange),
+        );
+    }
+
+    pub(crate) fn log_solana_config(&self) {
+        let fees = &self.fees_config.solana;
+        info!(
+            "   - Solana: {} lamports per swap (${:.4} at SOL ${:.2}), max slot lag {}",
+            fees.lamports_per_swap(),
+            fees.leg_cost_usd(),
+            fees.sol_price_usd,
+            self.slot_clock.max_lag()
+        );
+    }
+}
+
+// Mid price of a SOL/USD-stable book, from any venue
+fn sol_usd_mid(book: &OrderBook) -> Option<f64> {
+    if !matches!(book.pair.as_str(), "SOL/USDC" | "SOL/USDT") {
+        return None;
+    }
+    let ((bid, _), (ask, _)) = (book.best_bid()?, book.best_ask()?);
+    let mid = (bid + ask) / 2.0;
+    (mid.is_finite() && mid > 0.0).then_some(mid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lamport_fees_follow_sol_price() {
+        let mut fees = SolanaFees { signatures_per_swap: 2, priority_fee_lamports: 90_000, sol_price_usd: 100.0 };
+        assert_eq!(fees.lamports_per_swap(), 100_000);
+        assert!((fees.leg_cost_usd() - 0.01).abs() < 1e-12);
+        fees.sol_price_usd = 200.0;
+        assert!((fees.leg_cost_usd() - 0.02).abs() < 1e-12);
+    }
+
+    #[test]
+    fn slot_clock_tracks_the_newest_slot() {
+        let mut clock = SlotClock::new(10);
+        assert!(!clock.is_stale(1));
+        clock.observe(100);
+        clock.observe(95);
+        assert_eq!(clock.lag(95), 5);
+        assert!(!clock.is_stale(90));
+        assert!(clock.is_stale(89));
+    }
+
+    #[test]
+    fn lagging_solana_books_are_dropped() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let mut fresh = OrderBook::for_test("raydium", &format!("{}/{}", WRAPPED_SOL_MINT, USDC_MINT), vec![vec![99.0, 5.0]], vec![vec![101.0, 5.0]]);
+        fresh.slot = Some(1_000);
+        assert_eq!(analyzer.admit_solana_book(&mut fresh), None);
+        assert_eq!(fresh.pair, "SOL/USDC");
+        assert_eq!(analyzer.fees_config.solana.sol_price_usd, 100.0);
+
+        let mut lagging = OrderBook::for_test("orca", "SOL/USDC", vec![vec![99.0, 5.0]], vec![vec![101.0, 5.0]]);
+        lagging.slot = Some(1_000 - DEFAULT_MAX_SLOT_LAG - 1);
+        assert!(analyzer.admit_solana_book(&mut lagging).is_some());
+        assert!(analyzer.lags_slot_tip(&lagging) && !analyzer.lags_slot_tip(&fresh));
+    }
+
+    #[test]
+    fn mints_map_to_symbols() {
+        let tokens = TokenMap::default();
+        assert_eq!(tokens.map_pair(&format!("{}/{}", WRAPPED_SOL_MINT, USDC_MINT)), "SOL/USDC");
+        assert_eq!(tokens.map_pair(&format!("JUP/{}", USDC_MINT)), "JUP/USDC");
+    }
+}
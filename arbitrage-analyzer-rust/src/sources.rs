@@ -0,0 +1,379 @@
+// Redis instances the analyzer reads books from. The default is the single
+// REDIS_ADDR instance; deployments that run one Redis per collector (CEX vs DEX)
+// list them in REDIS_SOURCES. Every source gets its own listener task on the I/O
+// runtime that forwards raw pub/sub messages; the books they point at land in the
+// one cache. A listener that loses Redis reconnects and resubscribes with backoff.
+//
+// Each source authenticates as a Redis 6+ ACL user when `<prefix>USER` is set
+// (REDIS_USER, or REDIS_SOURCE_<NAME>_USER), with `<prefix>PASS`; either can be
+// read from a secret file instead, named by `<prefix>USER_FILE` / `<prefix>PASS_FILE`.
+// A password alone authenticates as the `default` user, as before ACLs. Rejected
+// credentials, missing ones and ACL users lacking a permission are told apart at
+// startup, naming the variables to fix, and stop the analyzer rather than leaving
+// it retrying a connection that can never succeed.
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use futures_util::StreamExt;
+use log::{error, info, warn};
+use redis::{Client, ConnectionAddr, ConnectionInfo, ErrorKind, RedisConnectionInfo, RedisError};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::backoff::Backoff;
+use crate::metrics::Metrics;
+use crate::subscription::{self, SubscriptionConfig};
+
+pub const DEFAULT_SOURCE: &str = "default";
+const DEFAULT_REDIS_ADDR: &str = "127.0.0.1:6379";
+const DEFAULT_ENV_PREFIX: &str = "REDIS_";
+
+#[derive(Debug, Clone)]
+pub struct RedisSource {
+    pub name: String,
+    pub addr: String,
+    pub client: Client,
+    // ACL user the source authenticates as; None for the default user
+    pub user: Option<String>,
+    // `REDIS_` or `REDIS_SOURCE_<NAME>_`, to name the variables in diagnostics
+    pub env_prefix: String,
+    pub subscription: SubscriptionConfig,
+    // Only book keys matching this glob are fetched, e.g. `orderbook:binance:*`
+    pub key_pattern: Option<String>,
+}
+
+/// A message as received on one of the sources, before the book is fetched
+#[derive(Debug, Clone)]
+pub struct SourceMessage {
+    pub source: usize,
+    pub channel: String,
+    pub pattern: Option<String>,
+    pub payload: String,
+}
+
+/// ACL user and password of one source
+#[derive(Clone, Default, PartialEq)]
+pub struct Credentials {
+    pub username: Option<String>,
+    password: Option<String>,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials").field("username", &self.username).field("password", &self.password.as_ref().map(|_| "***")).finish()
+    }
+}
+
+// `<name>` inline, or the contents of the file at `<name>_FILE`, without the trailing newline
+fn secret(name: &str) -> Result<Option<String>> {
+    if let Ok(path) = crate::config::env_var(format!("{}_FILE", name)) {
+        let path = std::path::Path::new(path.trim());
+        let contents = std::fs::read_to_string(path).map_err(|e| anyhow!("{}_FILE {}: {}", name, path.display(), e))?;
+        return Ok(Some(contents.trim_end_matches(['\r', '\n']).to_string()).filter(|value| !value.is_empty()));
+    }
+    Ok(crate::config::env_var(name).ok().filter(|value| !value.is_empty()))
+}
+
+impl Credentials {
+    /// `<prefix>USER` and `<prefix>PASS`, or their `_FILE` variants
+    pub fn from_env(prefix: &str) -> Result<Self> {
+        let credentials = Credentials { username: secret(&format!("{}USER", prefix))?, password: secret(&format!("{}PASS", prefix))? };
+        if let (Some(user), None) = (&credentials.username, &credentials.password) {
+            return Err(anyhow!("{}USER is {} but {}PASS is not set; an ACL user needs a password", prefix, user, prefix));
+        }
+        Ok(credentials)
+    }
+}
+
+fn client_for(addr: &str, credentials: Credentials) -> Result<Client> {
+    let mut parts = addr.split(':');
+    let host = parts.next().unwrap_or("127.0.0.1").to_string();
+    let port: u16 = parts.next().and_then(|p| p.parse().ok()).unwrap_or(6379);
+    let info = ConnectionInfo {
+        addr: ConnectionAddr::Tcp(host, port),
+        redis: RedisConnectionInfo {
+            db: 0,
+            username: credentials.username,
+            password: credentials.password,
+        },
+    };
+    Ok(Client::open(info)?)
+}
+
+impl RedisSource {
+    fn from_env(name: &str) -> Result<Self> {
+        let source = if name == DEFAULT_SOURCE {
+            let addr = crate::config::env_var("REDIS_ADDR").unwrap_or_else(|_| DEFAULT_REDIS_ADDR.to_string());
+            let credentials = Credentials::from_env(DEFAULT_ENV_PREFIX)?;
+            RedisSource {
+                name: name.to_string(),
+                user: credentials.username.clone(),
+                client: client_for(&addr, credentials)?,
+                env_prefix: DEFAULT_ENV_PREFIX.to_string(),
+                addr,
+                subscription: SubscriptionConfig::from_env(),
+                key_pattern: crate::config::env_var("KEY_PATTERN").ok(),
+            }
+        } else {
+            let prefix = format!("REDIS_SOURCE_{}_", name.to_uppercase().replace('-', "_"));
+            let addr = crate::config::env_var(format!("{}ADDR", prefix)).map_err(|_| anyhow!("source {} needs {}ADDR", name, prefix))?;
+            let credentials = Credentials::from_env(&prefix)?;
+            RedisSource {
+                name: name.to_string(),
+                user: credentials.username.clone(),
+                client: client_for(&addr, credentials)?,
+                env_prefix: prefix.clone(),
+                addr,
+                subscription: SubscriptionConfig::from_env_prefixed(&prefix),
+                key_pattern: crate::config::env_var(format!("{}KEY_PATTERN", prefix)).ok(),
+            }
+        };
+
+        if source.subscription.is_empty() {
+            return Err(anyhow!("source {} has no channels or patterns configured", name));
+        }
+        Ok(source)
+    }
+
+    pub fn accepts_key(&self, key: &str) -> bool {
+        self.key_pattern.as_deref().is_none_or(|pattern| glob_match(pattern, key))
+    }
+
+    /// What is wrong with the source's credentials when `e` is an authentication or ACL
+    /// error; None for anything else, e.g. an unreachable server
+    pub fn auth_problem(&self, e: &RedisError) -> Option<String> {
+        let user = self.user.as_deref().map_or("the default user".to_string(), |user| format!("ACL user {}", user));
+        let prefix = &self.env_prefix;
+        if e.kind() == ErrorKind::AuthenticationFailed {
+            return Some(format!(
+                "source {} at {} refused {} (wrong password, unknown or disabled user); check {}USER and {}PASS (or {}USER_FILE / {}PASS_FILE)",
+                self.name, self.addr, user, prefix, prefix, prefix, prefix
+            ));
+        }
+        match e.code() {
+            Some("NOAUTH") => Some(format!(
+                "source {} at {} requires authentication; set {}PASS, and {}USER for an ACL user",
+                self.name, self.addr, prefix, prefix
+            )),
+            Some("WRONGPASS") => Some(format!("source {} at {} refused {}: {}; check {}USER and {}PASS", self.name, self.addr, user, e, prefix, prefix)),
+            Some("NOPERM") => Some(format!(
+                "{} on source {} at {} lacks a permission the analyzer needs: {}; it reads books (GET), subscribes to their channels and publishes on the first source",
+                user, self.name, self.addr, e
+            )),
+            _ => None,
+        }
+    }
+
+    /// Connect and PING. Err only for credentials Redis rejects; an unreachable server is
+    /// logged and left to the listener's reconnects
+    pub fn check_auth(&self) -> Result<()> {
+        let pinged = self.client.get_connection().and_then(|mut con| redis::cmd("PING").query::<String>(&mut con));
+        match pinged {
+            Ok(_) => {
+                info!("  Source {} authenticated as {}", self.name, self.user.as_deref().unwrap_or("default"));
+                Ok(())
+            }
+            Err(e) => match self.auth_problem(&e) {
+                Some(problem) => Err(anyhow!(problem)),
+                None => {
+                    warn!("Source {} at {} is not reachable yet, the listener keeps retrying: {}", self.name, self.addr, e);
+                    Ok(())
+                }
+            },
+        }
+    }
+}
+
+/// Sources named in REDIS_SOURCES, or the single REDIS_ADDR source when unset
+pub fn sources_from_env() -> Result<Vec<RedisSource>> {
+    let names = crate::config::env_var("REDIS_SOURCES")
+        .map(|raw| subscription::split_list(&raw))
+        .unwrap_or_default();
+    if names.is_empty() {
+        return Ok(vec![RedisSource::from_env(DEFAULT_SOURCE)?]);
+    }
+    names.iter().map(|name| RedisSource::from_env(name)).collect()
+}
+
+/// Start one listener task per source on the current runtime. All of them feed the returned receiver
+pub fn spawn_listeners(sources: &[RedisSource], metrics: Arc<Metrics>) -> UnboundedReceiver<SourceMessage> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    for (idx, source) in sources.iter().enumerate() {
+        let tx = tx.clone();
+        let source = source.clone();
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            let mut backoff = Backoff::from_env();
+            loop {
+                match listen(idx, &source.client, &source.subscription, &tx, &mut backoff, &metrics).await {
+                    // The analyzer dropped the receiver, nothing left to do
+                    Ok(()) => return,
+                    Err(e) => {
+                        let delay = backoff.next_delay();
+                        // Credentials can be fixed on the server side, so keep retrying, but say what to fix
+                        match e.downcast_ref::<RedisError>().and_then(|e| source.auth_problem(e)) {
+                            Some(problem) => error!("{}; reconnecting in {}ms", problem, delay.as_millis()),
+                            None => warn!("Source {} lost its subscription: {}; reconnecting in {}ms", source.name, e, delay.as_millis()),
+                        }
+                        Metrics::inc(&metrics.redis_reconnects);
+                        Metrics::inc(&metrics.redis_errors);
+                        tokio::time::sleep(delay).await;
+                    }
+                }
+            }
+        });
+    }
+    rx
+}
+
+async fn listen(
+    source: usize,
+    client: &Client,
+    subscription: &SubscriptionConfig,
+    tx: &UnboundedSender<SourceMessage>,
+    backoff: &mut Backoff,
+    metrics: &Metrics,
+) -> Result<()> {
+    let mut pubsub = client.get_async_connection().await?.into_pubsub();
+    for channel in &subscription.channels {
+        pubsub.subscribe(channel).await?;
+        info!("Subscribed to {} channel", channel);
+    }
+    for pattern in &subscription.patterns {
+        pubsub.psubscribe(pattern).await?;
+        info!("Subscribed to {} pattern", pattern);
+    }
+    backoff.reset();
+
+    // Counted as connected for as long as the subscription stays up
+    metrics.redis_sources_connected.fetch_add(1, Ordering::Relaxed);
+    let forwarded = async {
+        let mut messages = pubsub.on_message();
+        while let Some(msg) = messages.next().await {
+            let message = SourceMessage {
+                source,
+                channel: msg.get_channel_name().to_string(),
+                pattern: if msg.from_pattern() { msg.get_pattern().ok() } else { None },
+                payload: msg.get_payload()?,
+            };
+            if tx.send(message).is_err() {
+                return Ok(());
+            }
+        }
+        Err(anyhow!("connection closed"))
+    }
+    .await;
+    metrics.redis_sources_connected.fetch_sub(1, Ordering::Relaxed);
+    forwarded
+}
+
+/// Redis-style glob where `*` matches any run of characters
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let remaining: Vec<&str> = parts.collect();
+    let Some((last, middle)) = remaining.split_last() else {
+        // No `*` at all: must be an exact match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matches_book_keys() {
+        assert!(glob_match("orderbook:binance:*", "orderbook:binance:BTC/USDT"));
+        assert!(!glob_match("orderbook:binance:*", "orderbook:uniswap-v3-exact:WBTC/USDT"));
+        assert!(glob_match("orderbook:*:*BTC*", "orderbook:uniswap-v3-exact:WBTC/USDT"));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("orderbook:binance:BTC/USDT", "orderbook:binance:BTC/USDT"));
+        assert!(!glob_match("orderbook:binance:BTC", "orderbook:binance:BTC/USDT"));
+        // The suffix must not overlap with what the prefix consumed
+        assert!(!glob_match("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn acl_credentials_are_read_and_diagnosed() {
+        let password_file = std::env::temp_dir().join(format!("swapsleuth-redis-pass-{}", std::process::id()));
+        std::fs::write(&password_file, "s3cret\n").unwrap();
+        std::env::set_var("REDIS_SOURCE_ACLTEST_USER", "analyzer");
+        std::env::set_var("REDIS_SOURCE_ACLTEST_PASS_FILE", &password_file);
+        let credentials = Credentials::from_env("REDIS_SOURCE_ACLTEST_").unwrap();
+        std::fs::remove_file(&password_file).unwrap();
+        assert_eq!(credentials.username.as_deref(), Some("analyzer"));
+        assert_eq!(credentials.password.as_deref(), Some("s3cret"));
+        assert!(!format!("{:?}", credentials).contains("s3cret"));
+        std::env::set_var("REDIS_SOURCE_ACLTEST_NOPASS_USER", "analyzer");
+        assert!(Credentials::from_env("REDIS_SOURCE_ACLTEST_NOPASS_").unwrap_err().to_string().contains("needs a password"));
+
+        let server = crate::mini_redis::MiniRedis::start();
+        server.require_auth("analyzer", "s3cret");
+        let source = |addr: &str, credentials: Credentials| RedisSource {
+            name: "cex".to_string(),
+            addr: addr.to_string(),
+            user: credentials.username.clone(),
+            client: client_for(addr, credentials).unwrap(),
+            env_prefix: "REDIS_SOURCE_CEX_".to_string(),
+            subscription: SubscriptionConfig::default(),
+            key_pattern: None,
+        };
+        assert!(source(&server.addr, credentials.clone()).check_auth().is_ok());
+
+        let wrong = Credentials { password: Some("guess".to_string()), ..credentials.clone() };
+        let refused = source(&server.addr, wrong).check_auth().unwrap_err().to_string();
+        assert!(refused.contains("refused ACL user analyzer") && refused.contains("REDIS_SOURCE_CEX_PASS"), "{}", refused);
+        let missing = source(&server.addr, Credentials::default()).check_auth().unwrap_err().to_string();
+        assert!(missing.contains("requires authentication"), "{}", missing);
+        // Nothing listening: not a credentials problem, the listener will retry
+        assert!(source("127.0.0.1:1", credentials).check_auth().is_ok());
+    }
+
+    #[test]
+    fn listeners_resubscribe_after_losing_redis() {
+        use redis::Commands;
+
+        let server = crate::mini_redis::MiniRedis::start();
+        let source = RedisSource {
+            name: DEFAULT_SOURCE.to_string(),
+            addr: server.addr.clone(),
+            client: server.client(),
+            user: None,
+            env_prefix: DEFAULT_ENV_PREFIX.to_string(),
+            subscription: SubscriptionConfig::default(),
+            key_pattern: None,
+        };
+        let channel = crate::subscription::DEFAULT_CHANNEL;
+        let metrics = Arc::new(Metrics::default());
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(1).enable_all().build().unwrap();
+        let mut messages = {
+            let _entered = runtime.enter();
+            spawn_listeners(&[source], metrics.clone())
+        };
+        let mut received = |payload: &str| {
+            server.wait_for_subscribers(channel, 1, std::time::Duration::from_secs(5)).unwrap();
+            let _: i64 = server.client().get_connection().unwrap().publish(channel, payload).unwrap();
+            let next = runtime.block_on(async { tokio::time::timeout(std::time::Duration::from_secs(5), messages.recv()).await });
+            next.ok().flatten().map(|msg| msg.payload)
+        };
+        assert_eq!(received("before").as_deref(), Some("before"));
+
+        server.disconnect_all();
+        assert_eq!(received("after").as_deref(), Some("after"));
+        assert_eq!(metrics.redis_reconnects.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.redis_sources_connected.load(Ordering::Relaxed), 1);
+    }
+}
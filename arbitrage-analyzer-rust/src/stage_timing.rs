@@ -0,0 +1,252 @@
+// Where the time of one update goes. Each pipeline stage records how long it took
+// per message:
+//  - parse: the notification payload, and the book JSON once fetched,
+//  - fetch: the GET of a book that was not embedded in its notification,
+//  - validate: the admission checks of a decoded book,
+//  - analyze: finding the opportunities of the update,
+//  - enrich: the enrichment stages (see `enrichment`),
+//  - publish: recording and publishing what was found, execution requests included.
+// The last WINDOW samples of each stage give rolling percentiles, exported with
+// the metrics as the `swapsleuth_stage_seconds` summary, served on
+// `GET /stats/pipeline` and printed by `--profile-pipeline` every
+// PROFILE_PIPELINE_SECS (default 10) and once more at shutdown.
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use comfy_table::Cell;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::report::{self, OutputFormat};
+use crate::SpreadAnalyzer;
+
+// Samples per stage the percentiles are taken over
+pub const WINDOW: usize = 1024;
+const QUANTILES: [f64; 3] = [0.5, 0.9, 0.99];
+pub const DEFAULT_PROFILE_SECS: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Parse,
+    Fetch,
+    Validate,
+    Analyze,
+    Enrich,
+    Publish,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [Stage::Parse, Stage::Fetch, Stage::Validate, Stage::Analyze, Stage::Enrich, Stage::Publish];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stage::Parse => "parse",
+            Stage::Fetch => "fetch",
+            Stage::Validate => "validate",
+            Stage::Analyze => "analyze",
+            Stage::Enrich => "enrich",
+            Stage::Publish => "publish",
+        }
+    }
+}
+
+/// One stage over its window, in milliseconds
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StageSummary {
+    pub stage: &'static str,
+    // Every message timed since startup
+    pub messages: u64,
+    pub total_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct StageTimings {
+    // Latest samples in seconds, by stage
+    windows: Mutex<[VecDeque<f64>; 6]>,
+    counts: [AtomicU64; 6],
+    // f64 bits
+    sums: [AtomicU64; 6],
+}
+
+// Nearest-rank percentile of sorted samples
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl StageTimings {
+    pub fn record(&self, stage: Stage, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let index = stage as usize;
+        self.counts[index].fetch_add(1, Ordering::Relaxed);
+        let _ = self.sums[index].fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| Some((f64::from_bits(sum) + seconds).to_bits()));
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        let window = &mut windows[index];
+        if window.len() == WINDOW {
+            window.pop_front();
+        }
+        window.push_back(seconds);
+    }
+
+    /// Run `work` as `stage`
+    pub fn time<T>(&self, stage: Stage, work: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = work();
+        self.record(stage, started.elapsed());
+        result
+    }
+
+    /// Await `work` as `stage`
+    pub async fn time_async<T>(&self, stage: Stage, work: impl std::future::Future<Output = T>) -> T {
+        let started = Instant::now();
+        let result = work.await;
+        self.record(stage, started.elapsed());
+        result
+    }
+
+    // Sorted samples of every stage
+    fn sorted(&self) -> Vec<Vec<f64>> {
+        let windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        windows
+            .iter()
+            .map(|window| {
+                let mut samples: Vec<f64> = window.iter().copied().collect();
+                samples.sort_by(f64::total_cmp);
+                samples
+            })
+            .collect()
+    }
+
+    /// Every stage that has seen a message, in pipeline order
+    pub fn summaries(&self) -> Vec<StageSummary> {
+        let sorted = self.sorted();
+        Stage::ALL
+            .iter()
+            .zip(sorted)
+            .filter(|(stage, _)| self.counts[**stage as usize].load(Ordering::Relaxed) > 0)
+            .map(|(stage, samples)| {
+                let ms = |seconds: f64| seconds * 1_000.0;
+                StageSummary {
+                    stage: stage.as_str(),
+                    messages: self.counts[*stage as usize].load(Ordering::Relaxed),
+                    total_ms: ms(f64::from_bits(self.sums[*stage as usize].load(Ordering::Relaxed))),
+                    p50_ms: ms(quantile(&samples, 0.5)),
+                    p90_ms: ms(quantile(&samples, 0.9)),
+                    p99_ms: ms(quantile(&samples, 0.99)),
+                    max_ms: ms(samples.last().copied().unwrap_or_default()),
+                }
+            })
+            .collect()
+    }
+
+    /// The `swapsleuth_stage_seconds` summary, with `labels` (`{name="value",...}`) on every sample
+    pub fn render(&self, out: &mut String, labels: &str) {
+        let name = "swapsleuth_stage_seconds";
+        let _ = writeln!(out, "# HELP {} Time one message spent in each pipeline stage, over the last {} messages of the stage", name, WINDOW);
+        let _ = writeln!(out, "# TYPE {} summary", name);
+        let with = |extra: String| match labels.strip_suffix('}') {
+            Some(open) => format!("{},{}}}", open, extra),
+            None => format!("{{{}}}", extra),
+        };
+        for (stage, samples) in Stage::ALL.iter().zip(self.sorted()) {
+            for q in QUANTILES {
+                let _ = writeln!(out, "{}{} {}", name, with(format!("stage=\"{}\",quantile=\"{}\"", stage.as_str(), q)), quantile(&samples, q));
+            }
+            let index = *stage as usize;
+            let stage_labels = with(format!("stage=\"{}\"", stage.as_str()));
+            let _ = writeln!(out, "{}_sum{} {}", name, stage_labels, f64::from_bits(self.sums[index].load(Ordering::Relaxed)));
+            let _ = writeln!(out, "{}_count{} {}", name, stage_labels, self.counts[index].load(Ordering::Relaxed));
+        }
+    }
+}
+
+impl SpreadAnalyzer {
+    // Print the stage table when `--profile-pipeline` is due
+    pub(crate) fn report_pipeline_profile_if_due(&mut self) {
+        let Some((every, last)) = &mut self.pipeline_profile else { return };
+        if last.elapsed() < *every {
+            return;
+        }
+        *last = Instant::now();
+        self.report_pipeline_profile();
+    }
+
+    pub(crate) fn report_pipeline_profile(&self) {
+        let summaries = self.metrics.stages.summaries();
+        if self.reporter.format == OutputFormat::Jsonl {
+            report::emit("pipeline_profile", json!({ "window": WINDOW, "stages": summaries }));
+            return;
+        }
+        println!("\n PIPELINE PROFILE (last {} messages per stage)", WINDOW);
+        let mut table = report::table(&["Stage", "Messages", "Total", "p50", "p90", "p99", "Max"], 1..7);
+        for summary in &summaries {
+            let ms = |value: f64| format!("{:.3}ms", value);
+            table.add_row(vec![
+                Cell::new(summary.stage),
+                Cell::new(summary.messages),
+                Cell::new(format!("{:.1}ms", summary.total_ms)),
+                Cell::new(ms(summary.p50_ms)),
+                Cell::new(ms(summary.p90_ms)),
+                Cell::new(ms(summary.p99_ms)),
+                Cell::new(ms(summary.max_ms)),
+            ]);
+        }
+        println!("{}", table);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fetches of 1..=100ms, and one parse
+    fn timings() -> StageTimings {
+        let timings = StageTimings::default();
+        for ms in 1..=100 {
+            timings.record(Stage::Fetch, Duration::from_millis(ms));
+        }
+        assert_eq!(timings.time(Stage::Parse, || 7), 7);
+        timings
+    }
+
+    #[test]
+    fn summarises_each_stage_in_percentiles() {
+        let summaries = timings().summaries();
+        assert_eq!(summaries.iter().map(|s| s.stage).collect::<Vec<_>>(), vec!["parse", "fetch"]);
+        let fetch = &summaries[1];
+        assert_eq!((fetch.messages, fetch.p50_ms, fetch.p90_ms, fetch.p99_ms, fetch.max_ms), (100, 50.0, 90.0, 99.0, 100.0));
+        assert!((fetch.total_ms - 5_050.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn percentiles_roll_over_the_latest_window() {
+        let timings = timings();
+        // Old samples leave the window, the totals keep them
+        for _ in 0..WINDOW {
+            timings.record(Stage::Fetch, Duration::from_millis(2));
+        }
+        let fetch = timings.summaries().remove(1);
+        assert_eq!((fetch.messages, fetch.max_ms), (100 + WINDOW as u64, 2.0));
+    }
+
+    #[test]
+    fn renders_every_stage_for_prometheus() {
+        let timings = StageTimings::default();
+        timings.record(Stage::Fetch, Duration::from_millis(2));
+        let mut out = String::new();
+        timings.render(&mut out, "{version=\"1\"}");
+        assert!(out.contains("swapsleuth_stage_seconds{version=\"1\",stage=\"fetch\",quantile=\"0.99\"} 0.002"));
+        assert!(out.contains("swapsleuth_stage_seconds_count{version=\"1\",stage=\"analyze\"} 0"));
+    }
+}
@@ -0,0 +1,222 @@
+// Book staleness. A book's data time is the older of the collector's `timestamp`
+// and when the analyzer received it, so a book that sat in Redis, or that a
+// collector re-published unchanged, ages from when it was read off the venue.
+// Collector timestamps are epoch seconds, milliseconds, microseconds or
+// nanoseconds, told apart by magnitude; one that is implausible (before 2015, or
+// ahead of the receive time) is ignored and the receive time is used alone.
+//
+//  - MAX_BOOK_AGE_MS: routes with a leg older than this are not analyzed, so a
+//    quote that stopped updating is never compared against fresh ones. Default
+//    STALE_BOOK_AGE_MS (30s); 0 disables.
+//  - MAX_LEG_SKEW_MS: routes whose two legs' data times are further apart than
+//    this are not analyzed either. 0 (default) disables.
+//  - BOOK_EVICT_AGE_MS: books older than this are dropped from the cache, so
+//    pairs and venues that went away don't stay in it for good. Default 10
+//    minutes; 0 disables.
+// Each opportunity carries the age of both legs at the time it was found.
+
+use std::sync::atomic::Ordering;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::book_cache::estimated_size;
+use crate::metrics::Metrics;
+use crate::{config, OrderBook, SpreadAnalyzer, STALE_BOOK_AGE_MS};
+
+const DEFAULT_EVICT_AGE_MS: i64 = 600_000;
+// 2015-01-01T00:00:00Z; no collector timestamp is older
+const EARLIEST_TIMESTAMP_SECS: i64 = 1_420_070_400;
+// Clocks of the collector and analyzer hosts may disagree by this much
+const MAX_CLOCK_AHEAD: Duration = Duration::seconds(5);
+
+/// Age of each leg's book when the opportunity was found
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BookAges {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buy_ms: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sell_ms: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StalenessPolicy {
+    // None disables each check
+    pub max_age_ms: Option<i64>,
+    pub max_skew_ms: Option<i64>,
+    pub evict_age_ms: Option<i64>,
+}
+
+impl Default for StalenessPolicy {
+    fn default() -> Self {
+        StalenessPolicy { max_age_ms: Some(STALE_BOOK_AGE_MS), max_skew_ms: None, evict_age_ms: Some(DEFAULT_EVICT_AGE_MS) }
+    }
+}
+
+fn millis(var: &str, default: i64) -> Option<i64> {
+    Some(config::env_or(var, default)).filter(|ms| *ms > 0)
+}
+
+impl StalenessPolicy {
+    pub fn from_env() -> Self {
+        StalenessPolicy {
+            max_age_ms: millis("MAX_BOOK_AGE_MS", STALE_BOOK_AGE_MS),
+            max_skew_ms: millis("MAX_LEG_SKEW_MS", 0),
+            evict_age_ms: millis("BOOK_EVICT_AGE_MS", DEFAULT_EVICT_AGE_MS),
+        }
+    }
+
+    /// Whether `book` is too old to trade against at `now`
+    pub fn is_stale(&self, book: &OrderBook, now: DateTime<Utc>) -> bool {
+        matches!((self.max_age_ms, data_age_ms(book, now)), (Some(max), Some(age)) if age > max)
+    }
+
+    /// How far apart the legs' data times are, when that is over the limit
+    pub fn excessive_skew(&self, buy: &OrderBook, sell: &OrderBook) -> Option<i64> {
+        let max = self.max_skew_ms?;
+        let skew = (data_time(buy)? - data_time(sell)?).num_milliseconds().abs();
+        (skew > max).then_some(skew)
+    }
+
+    pub fn ages(buy: &OrderBook, sell: &OrderBook, now: DateTime<Utc>) -> Option<BookAges> {
+        let ages = BookAges { buy_ms: data_age_ms(buy, now), sell_ms: data_age_ms(sell, now) };
+        (ages.buy_ms.is_some() || ages.sell_ms.is_some()).then_some(ages)
+    }
+}
+
+/// The collector's timestamp, if it reads as a time the book could have been taken at
+pub fn collector_time(book: &OrderBook) -> Option<DateTime<Utc>> {
+    let ts = book.timestamp;
+    let (secs, nanos) = match ts {
+        ts if ts < 100_000_000_000 => (ts, 0),
+        ts if ts < 100_000_000_000_000 => (ts / 1_000, (ts % 1_000) * 1_000_000),
+        ts if ts < 100_000_000_000_000_000 => (ts / 1_000_000, (ts % 1_000_000) * 1_000),
+        ts => (ts / 1_000_000_000, ts % 1_000_000_000),
+    };
+    if secs < EARLIEST_TIMESTAMP_SECS {
+        return None;
+    }
+    let time = Utc.timestamp_opt(secs, nanos as u32).single()?;
+    // A time after the book arrived is a sequence number or a bad clock
+    match book.received_at {
+        Some(received) if time > received + MAX_CLOCK_AHEAD => None,
+        _ => Some(time),
+    }
+}
+
+/// When the book's data was taken: the older of its collector timestamp and receive time
+pub fn data_time(book: &OrderBook) -> Option<DateTime<Utc>> {
+    match (collector_time(book), book.received_at) {
+        (Some(collected), Some(received)) => Some(collected.min(received)),
+        (collected, received) => collected.or(received),
+    }
+}
+
+pub fn data_age_ms(book: &OrderBook, now: DateTime<Utc>) -> Option<i64> {
+    data_time(book).map(|time| (now - time).num_milliseconds().max(0))
+}
+
+impl SpreadAnalyzer {
+    /// Drop books older than BOOK_EVICT_AGE_MS from the cache
+    pub(crate) fn evict_stale_books(&mut self, now: DateTime<Utc>) {
+        let Some(max) = self.staleness.evict_age_ms else { return };
+        let before = self.books.len();
+        self.books.retain(|key, book| {
+            let keep = data_age_ms(book, now).is_none_or(|age| age <= max);
+            if !keep {
+                info!("Evicted orderbook {}: no update for {}s", key, max / 1000);
+            }
+            keep
+        });
+        let evicted = before - self.books.len();
+        if evicted > 0 {
+            self.metrics.stale_books_evicted.fetch_add(evicted as u64, Ordering::Relaxed);
+            let bytes: usize = self.books.iter().map(|(key, book)| estimated_size(key, book)).sum();
+            self.metrics.book_cache_entries.store(self.books.len() as u64, Ordering::Relaxed);
+            self.metrics.book_cache_bytes.store(bytes as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether the route between `buy` and `sell` may be analyzed at `now`
+    pub(crate) fn fresh_enough(&self, pair: &str, buy: &OrderBook, sell: &OrderBook, now: DateTime<Utc>) -> bool {
+        if let Some(stale) = [buy, sell].into_iter().find(|book| self.staleness.is_stale(book, now)) {
+            Metrics::inc(&self.metrics.stale_routes_skipped);
+            self.log_throttle.warn(
+                &format!("stale_book:{}:{}", stale.exchange, pair),
+                format_args!(
+                    "Skipping {} routes with {}: its book is {}ms old",
+                    pair,
+                    stale.exchange,
+                    data_age_ms(stale, now).unwrap_or_default()
+                ),
+            );
+            return false;
+        }
+        if let Some(skew) = self.staleness.excessive_skew(buy, sell) {
+            Metrics::inc(&self.metrics.leg_skew_rejections);
+            self.log_throttle.warn(
+                &format!("leg_skew:{}:{}:{}", pair, buy.exchange, sell.exchange),
+                format_args!("Skipping {} {}→{}: the legs' books are {}ms apart", pair, buy.exchange, sell.exchange, skew),
+            );
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(timestamp: i64, received_at: DateTime<Utc>) -> OrderBook {
+        let mut book: OrderBook = serde_json::from_str(r#"{"exchange":"binance","pair":"BTC/USDT","bids":[],"asks":[],"timestamp":0}"#).unwrap();
+        book.timestamp = timestamp;
+        book.received_at = Some(received_at);
+        book
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 1, 0).unwrap()
+    }
+
+    // A minute old by its collector, and one 2s old
+    fn old_and_fresh() -> (OrderBook, OrderBook) {
+        let received = now() - Duration::seconds(2);
+        (book(1_704_067_200, received), book(1_704_067_258_000, received))
+    }
+
+    #[test]
+    fn ages_books_by_the_older_of_collector_and_receive_time() {
+        let received = now() - Duration::seconds(2);
+        // Seconds, milliseconds and nanoseconds all read as the same time
+        for ts in [1_704_067_200, 1_704_067_200_000, 1_704_067_200_000_000_000] {
+            assert_eq!(data_age_ms(&book(ts, received), now()), Some(60_000));
+        }
+        // Sequence numbers and missing timestamps fall back to the receive time
+        assert_eq!(data_age_ms(&book(72_000_000_000, received), now()), Some(2_000));
+        assert_eq!(data_age_ms(&book(1, received), now()), Some(2_000));
+    }
+
+    #[test]
+    fn flags_stale_books_and_skewed_pairs() {
+        let policy = StalenessPolicy { max_age_ms: Some(30_000), max_skew_ms: Some(500), evict_age_ms: None };
+        let (old, fresh) = old_and_fresh();
+        assert!(policy.is_stale(&old, now()) && !policy.is_stale(&fresh, now()));
+        assert_eq!(policy.excessive_skew(&old, &fresh), Some(58_000));
+        let received = now() - Duration::seconds(2);
+        assert_eq!(policy.excessive_skew(&fresh, &book(1_704_067_258_400, received)), None);
+        assert_eq!(StalenessPolicy::ages(&old, &fresh, now()), Some(BookAges { buy_ms: Some(60_000), sell_ms: Some(2_000) }));
+    }
+
+    #[test]
+    fn evicts_books_past_the_eviction_age() {
+        let (old, fresh) = old_and_fresh();
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.staleness.evict_age_ms = Some(10_000);
+        analyzer.books.insert("binance:BTC/USDT".to_string(), old);
+        analyzer.books.insert("okx:BTC/USDT".to_string(), fresh);
+        analyzer.evict_stale_books(now());
+        assert_eq!(analyzer.books.keys().collect::<Vec<_>>(), vec!["okx:BTC/USDT"]);
+    }
+}
@@ -0,0 +1,111 @@
+// Postmortem replay of the orderbook stream. With ORDERBOOK_STREAM set, the Go
+// collector also appends every book it publishes to that Redis stream (`key` and
+// `book` fields, capped near ORDERBOOK_STREAM_MAXLEN entries). `swapsleuth replay
+// --from <id> --to <id>` reads that range back with XRANGE from the first Redis
+// source and runs it through a fresh analyzer in observe mode. Stream ids are the
+// time Redis took the entry, in epoch milliseconds, which is when the live
+// analyzer was told about the book; each book is analyzed with the clock at its
+// entry's time, so staleness, expiry and cooldowns play out as they did. Ids are
+// full (`1704067200000-0`), bare milliseconds, or `-` / `+` for either end.
+//
+// The per-pass report follows REPORT_POLICY as in the daemon, followed by the
+// same summary as a backtest. Entries without a parseable book are skipped and
+// counted.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+
+use crate::backtest::{self, RecordedBook};
+use crate::report::OutputFormat;
+use crate::{config, OrderBook};
+
+// Entries per XRANGE call
+const PAGE_SIZE: usize = 1_000;
+
+type StreamEntry = (String, HashMap<String, String>);
+
+/// When Redis took the entry with `id`
+pub fn entry_time(id: &str) -> Option<DateTime<Utc>> {
+    let millis = id.split_once('-').map_or(id, |(millis, _)| millis).parse().ok()?;
+    DateTime::from_timestamp_millis(millis)
+}
+
+// The id right after `id`, to resume a range without repeating it
+fn next_id(id: &str) -> Option<String> {
+    let (millis, seq) = id.split_once('-')?;
+    Some(format!("{}-{}", millis, seq.parse::<u64>().ok()?.checked_add(1)?))
+}
+
+/// The books in `entries`, at their entries' times, and how many entries were skipped
+pub fn parse_entries(entries: Vec<StreamEntry>) -> (Vec<RecordedBook>, usize) {
+    let (mut books, mut skipped) = (Vec::new(), 0);
+    for (id, fields) in entries {
+        let book = fields.get("book").and_then(|raw| serde_json::from_str::<OrderBook>(raw).ok());
+        match (entry_time(&id), book) {
+            (Some(at), Some(book)) => books.push(RecordedBook { at, book }),
+            _ => skipped += 1,
+        }
+    }
+    (books, skipped)
+}
+
+fn read_range(con: &mut redis::Connection, stream: &str, from: &str, to: &str) -> Result<Vec<StreamEntry>> {
+    let mut entries: Vec<StreamEntry> = Vec::new();
+    let mut start = from.to_string();
+    loop {
+        let page: Vec<StreamEntry> = redis::cmd("XRANGE").arg(stream).arg(&start).arg(to).arg("COUNT").arg(PAGE_SIZE).query(con)?;
+        let full = page.len() == PAGE_SIZE;
+        entries.extend(page);
+        match entries.last().and_then(|(id, _)| next_id(id)) {
+            Some(next) if full => start = next,
+            _ => return Ok(entries),
+        }
+    }
+}
+
+/// The `replay` command of `main`
+pub fn run(from: &str, to: &str, stream: Option<String>, output: OutputFormat, config_path: Option<&Path>) -> Result<()> {
+    let stream = stream
+        .or_else(|| config::env_var("ORDERBOOK_STREAM").ok())
+        .ok_or_else(|| anyhow!("no orderbook stream: pass --stream or set ORDERBOOK_STREAM"))?;
+    let mut analyzer = backtest::observer(output, config_path)?;
+    let source = analyzer.sources.first().ok_or_else(|| anyhow!("no Redis source configured"))?;
+    let mut con = source.client.get_connection()?;
+    let entries = read_range(&mut con, &stream, from, to)?;
+    info!("  Replaying {} entries of {} on {} ({} to {})", entries.len(), stream, source.addr, from, to);
+
+    let (books, skipped) = parse_entries(entries);
+    if skipped > 0 {
+        warn!("Skipped {} entries of {} without a parseable book", skipped, stream);
+    }
+    let mut report = backtest::replay(&mut analyzer, books, 0.0)?;
+    report.skipped_lines = skipped;
+    report.print(output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_books_at_their_entry_times() {
+        let entry = |id: &str, book: &str| {
+            let fields = [("key", "orderbook:okx:BTC/USDT"), ("book", book)];
+            (id.to_string(), fields.into_iter().map(|(name, value)| (name.to_string(), value.to_string())).collect())
+        };
+        let (books, skipped) = parse_entries(vec![
+            entry("1704067201500-0", r#"{"exchange":"okx","pair":"BTC/USDT","bids":[[50800.0,1.5]],"asks":[[50810.0,1.1]],"timestamp":1704067201}"#),
+            entry("1704067201500-1", "{truncated"),
+        ]);
+        assert_eq!(skipped, 1);
+        assert_eq!(books[0].at, DateTime::parse_from_rfc3339("2024-01-01T00:00:01.500Z").unwrap());
+        assert_eq!(entry_time("1704067201500"), entry_time("1704067201500-7"));
+        assert_eq!(next_id("1704067201500-7").as_deref(), Some("1704067201500-8"));
+        assert_eq!(entry_time("-"), None);
+    }
+}
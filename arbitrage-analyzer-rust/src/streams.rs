@@ -0,0 +1,209 @@
+// Redis Streams output, for executors that need delivery they can acknowledge
+// rather than fire-and-forget pub/sub. Everything published on the opportunity
+// and execution channels is also appended with XADD to OPPORTUNITY_STREAM
+// (`arb:opportunities`) and EXECUTION_STREAM (`arb:execution_requests`); an
+// empty name turns that stream off. Streams are capped near STREAM_MAXLEN
+// entries, and execution stream entries are held by the publisher through a Redis
+// outage rather than dropped. Each entry is flat so a consumer group can route it
+// without decoding the JSON:
+//   type        `opportunity`, `execution_request` or `opportunity_expired`
+//   id          the opportunity or request id, to deduplicate redeliveries
+//   route       `PAIR:buy>sell`
+//   created_at  RFC 3339
+//   payload     the same JSON as on the channel
+// STREAM_CONSUMER_GROUP is created on both streams at startup (from the first
+// entry, so nothing written before the executor starts is missed). Consumers read
+// with XREADGROUP and XACK once the entry is handled.
+//
+// PUBLISH_COOLDOWN_MS keeps the same route from being re-published, on the
+// channel and the stream alike, while its spread stays within
+// PUBLISH_REPUBLISH_BPS of the last one published; 0 publishes every pass.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::lifecycle::RouteKey;
+use crate::{config, ArbitrageOpportunity, SpreadAnalyzer};
+
+const DEFAULT_OPPORTUNITY_STREAM: &str = "arb:opportunities";
+const DEFAULT_EXECUTION_STREAM: &str = "arb:execution_requests";
+const DEFAULT_STREAM_MAXLEN: usize = 100_000;
+const DEFAULT_REPUBLISH_BPS: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryType {
+    Opportunity,
+    ExecutionRequest,
+    OpportunityExpired,
+}
+
+impl EntryType {
+    fn as_str(self) -> &'static str {
+        match self {
+            EntryType::Opportunity => "opportunity",
+            EntryType::ExecutionRequest => "execution_request",
+            EntryType::OpportunityExpired => "opportunity_expired",
+        }
+    }
+}
+
+/// Where stream entries go; None leaves that stream off
+#[derive(Debug, Clone, Default)]
+pub struct StreamConfig {
+    pub opportunities: Option<String>,
+    pub executions: Option<String>,
+    pub group: Option<String>,
+    pub maxlen: usize,
+}
+
+impl StreamConfig {
+    pub fn from_env() -> Self {
+        // Set but empty turns a stream off
+        let name = |var: &str, default: &str| {
+            Some(config::env_var(var).unwrap_or_else(|_| default.to_string())).filter(|name| !name.trim().is_empty())
+        };
+        StreamConfig {
+            opportunities: name("OPPORTUNITY_STREAM", DEFAULT_OPPORTUNITY_STREAM),
+            executions: name("EXECUTION_STREAM", DEFAULT_EXECUTION_STREAM),
+            group: name("STREAM_CONSUMER_GROUP", ""),
+            maxlen: config::env_or("STREAM_MAXLEN", DEFAULT_STREAM_MAXLEN),
+        }
+    }
+
+    pub fn streams(&self) -> impl Iterator<Item = &str> {
+        self.opportunities.iter().chain(&self.executions).map(String::as_str)
+    }
+}
+
+/// The fields of one stream entry
+pub fn entry(kind: EntryType, id: &str, route: &RouteKey, created_at: DateTime<Utc>, payload: String) -> Vec<(String, String)> {
+    vec![
+        ("type".to_string(), kind.as_str().to_string()),
+        ("id".to_string(), id.to_string()),
+        ("route".to_string(), format!("{}:{}>{}", route.pair, route.buy_exchange, route.sell_exchange)),
+        ("created_at".to_string(), created_at.to_rfc3339()),
+        ("payload".to_string(), payload),
+    ]
+}
+
+/// The last spread published on each route, to hold back repeats
+#[derive(Debug)]
+pub struct PublishCooldown {
+    // None: every opportunity is published
+    window: Option<Duration>,
+    republish_bps: f64,
+    last: HashMap<RouteKey, (DateTime<Utc>, f64)>,
+}
+
+impl Default for PublishCooldown {
+    fn default() -> Self {
+        PublishCooldown { window: None, republish_bps: DEFAULT_REPUBLISH_BPS, last: HashMap::new() }
+    }
+}
+
+impl PublishCooldown {
+    pub fn from_env() -> Self {
+        let ms: i64 = config::env_or("PUBLISH_COOLDOWN_MS", 0);
+        PublishCooldown {
+            window: (ms > 0).then(|| Duration::milliseconds(ms)),
+            republish_bps: config::env_or("PUBLISH_REPUBLISH_BPS", DEFAULT_REPUBLISH_BPS),
+            last: HashMap::new(),
+        }
+    }
+
+    pub fn window(&self) -> Option<Duration> {
+        self.window
+    }
+
+    /// Whether `opp` should go out at `now`, remembering it if so
+    pub fn admit(&mut self, opp: &ArbitrageOpportunity, now: DateTime<Utc>) -> bool {
+        let Some(window) = self.window else { return true };
+        let spread_bps = opp.gross_profit_per_unit / opp.buy_price * 10_000.0;
+        let route = RouteKey::new(&opp.pair, &opp.buy_exchange, &opp.sell_exchange);
+        if let Some((at, last_bps)) = self.last.get(&route) {
+            if now - *at < window && (spread_bps - last_bps).abs() < self.republish_bps {
+                return false;
+            }
+        }
+        self.last.insert(route, (now, spread_bps));
+        // Routes quiet for a whole window would be admitted anyway
+        self.last.retain(|_, (at, _)| now - *at < window);
+        true
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Append `message` to `stream`, if it is set and something publishes
+    pub(crate) fn stream_to<T: Serialize>(
+        &self,
+        stream: Option<&str>,
+        kind: EntryType,
+        id: &str,
+        route: &RouteKey,
+        created_at: DateTime<Utc>,
+        message: &T,
+    ) {
+        let (Some(stream), Some(publisher)) = (stream, &self.publisher) else { return };
+        // A request missing from the execution stream is never executed, so its entries wait out outages
+        let hold = self.streams.executions.as_deref() == Some(stream);
+        match serde_json::to_string(message) {
+            Ok(payload) => publisher.stream(stream, entry(kind, id, route, created_at, payload), self.streams.maxlen, hold),
+            Err(e) => log::error!("Failed to serialize entry for {}: {}", stream, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opportunity(analyzer: &SpreadAnalyzer, buy: &str, sell: &str, bid: f64) -> ArbitrageOpportunity {
+        analyzer.evaluate_opportunity(buy, sell, "BTC/USDT", 50_000.0, bid, 1.0, 1.0).unwrap()
+    }
+
+    #[test]
+    fn repeats_within_the_cooldown_are_held_back() {
+        let analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let opp = opportunity(&analyzer, "binance", "okx", 51_000.0);
+        let now = Utc::now();
+        let mut off = PublishCooldown::default();
+        assert!(off.admit(&opp, now) && off.admit(&opp, now));
+
+        let mut cooldown = PublishCooldown { window: Some(Duration::seconds(5)), ..PublishCooldown::default() };
+        assert!(cooldown.admit(&opp, now));
+        assert!(!cooldown.admit(&opp, now + Duration::seconds(1)));
+        let other_route = opportunity(&analyzer, "okx", "binance", 51_000.0);
+        assert!(cooldown.admit(&other_route, now + Duration::seconds(1)));
+    }
+
+    #[test]
+    fn a_spread_that_moved_is_news() {
+        let analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let now = Utc::now();
+        let mut cooldown = PublishCooldown { window: Some(Duration::seconds(5)), ..PublishCooldown::default() };
+        assert!(cooldown.admit(&opportunity(&analyzer, "binance", "okx", 51_000.0), now));
+        let wider = opportunity(&analyzer, "binance", "okx", 51_100.0);
+        assert!(cooldown.admit(&wider, now + Duration::seconds(2)));
+        assert!(!cooldown.admit(&wider, now + Duration::seconds(3)));
+        assert!(cooldown.admit(&wider, now + Duration::seconds(8)));
+    }
+
+    #[test]
+    fn entries_lead_with_type_and_route() {
+        let fields = entry(EntryType::ExecutionRequest, "req-1", &RouteKey::new("BTC/USDT", "binance", "okx"), Utc::now(), "{}".to_string());
+        assert_eq!(fields[0], ("type".to_string(), "execution_request".to_string()));
+        assert_eq!(fields[2].1, "BTC/USDT:binance>okx");
+    }
+
+    #[test]
+    fn streams_default_on_and_an_empty_name_turns_one_off() {
+        std::env::set_var("EXECUTION_STREAM", "");
+        let config = StreamConfig::from_env();
+        std::env::remove_var("EXECUTION_STREAM");
+        assert_eq!(config.opportunities.as_deref(), Some("arb:opportunities"));
+        assert_eq!(config.executions, None);
+        assert_eq!(config.group, None);
+    }
+}
@@ -0,0 +1,121 @@
+// Which pub/sub channels the analyzer listens on and how each one's payload is read.
+// Collectors that shard updates (per exchange, per asset class) can be consumed by
+// listing extra channels or a PSUBSCRIBE pattern instead of changing code.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use crate::{config, OrderBook};
+
+pub const DEFAULT_CHANNEL: &str = "orderbook_updates";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayloadHandler {
+    // Payload is the book key, either raw or as `{"key": "..."}` (the go collector format)
+    Key,
+    // Payload is a JSON object carrying the book key in the named field
+    JsonField(String),
+}
+
+impl FromStr for PayloadHandler {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once('=') {
+            None if s == "key" => Ok(PayloadHandler::Key),
+            Some(("field", name)) if !name.is_empty() => Ok(PayloadHandler::JsonField(name.to_string())),
+            _ => Err(anyhow!("unknown payload handler: {} (expected key or field=<name>)", s)),
+        }
+    }
+}
+
+/// What a pub/sub message told us: which book changed and, optionally, which version was written
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub key: String,
+    // Timestamp of the book the collector wrote; the fetched book must not be older
+    pub version: Option<i64>,
+    // Set when the collector embedded the book in the message, so no GET is needed
+    pub book: Option<OrderBook>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SubscriptionConfig {
+    pub channels: Vec<String>,
+    pub patterns: Vec<String>,
+    // Keyed by channel name or by pattern; anything unlisted uses `PayloadHandler::Key`
+    pub handlers: HashMap<String, PayloadHandler>,
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        SubscriptionConfig { channels: vec![DEFAULT_CHANNEL.to_string()], patterns: Vec::new(), handlers: HashMap::new() }
+    }
+}
+
+pub fn split_list(raw: &str) -> Vec<String> {
+    raw.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect()
+}
+
+impl SubscriptionConfig {
+    pub fn from_env() -> Self {
+        Self::from_env_prefixed("")
+    }
+
+    /// Same as `from_env`, reading `<prefix>SUBSCRIBE_CHANNELS` and friends
+    pub fn from_env_prefixed(prefix: &str) -> Self {
+        let mut subscription = SubscriptionConfig::default();
+        if let Ok(raw) = config::env_var(format!("{}SUBSCRIBE_CHANNELS", prefix)) {
+            subscription.channels = split_list(&raw);
+        }
+        if let Ok(raw) = config::env_var(format!("{}SUBSCRIBE_PATTERNS", prefix)) {
+            subscription.patterns = split_list(&raw);
+        }
+        subscription.handlers = config::env_map(&format!("{}CHANNEL_HANDLERS", prefix));
+        subscription
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty() && self.patterns.is_empty()
+    }
+
+    /// Handler for a message; pattern subscriptions are looked up by pattern first
+    pub fn handler_for(&self, channel: &str, pattern: Option<&str>) -> &PayloadHandler {
+        pattern
+            .and_then(|p| self.handlers.get(p))
+            .or_else(|| self.handlers.get(channel))
+            .unwrap_or(&PayloadHandler::Key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handlers_parse_from_config_strings() {
+        assert_eq!("key".parse::<PayloadHandler>().unwrap(), PayloadHandler::Key);
+        assert_eq!(
+            "field=book_key".parse::<PayloadHandler>().unwrap(),
+            PayloadHandler::JsonField("book_key".to_string())
+        );
+        assert!("field=".parse::<PayloadHandler>().is_err());
+        assert!("book".parse::<PayloadHandler>().is_err());
+    }
+
+    #[test]
+    fn pattern_handler_wins_over_channel_handler() {
+        let mut subscription = SubscriptionConfig::default();
+        subscription.handlers.insert("orderbook_updates:*".to_string(), PayloadHandler::JsonField("k".to_string()));
+        subscription.handlers.insert("orderbook_updates:binance".to_string(), PayloadHandler::Key);
+
+        assert_eq!(
+            subscription.handler_for("orderbook_updates:binance", Some("orderbook_updates:*")),
+            &PayloadHandler::JsonField("k".to_string())
+        );
+        assert_eq!(subscription.handler_for("orderbook_updates:binance", None), &PayloadHandler::Key);
+        assert_eq!(subscription.handler_for("other", None), &PayloadHandler::Key);
+    }
+}
@@ -0,0 +1,151 @@
+// Learned execution success per route. Every execution request that reaches the
+// executor (is published) is one trial of its route; it succeeds when it fills
+// at its expected profit, i.e. when the realized net profit the executor reports
+// (see `attribution`) falls short of the estimate by no more than
+// SUCCESS_PROFIT_SHORTFALL_PCT. A fill that reports no profit figure counts as a
+// success; failures and expiries count as misses. The success rate of a route is
+// the mean of its Beta posterior:
+//
+//   probability = (prior successes + successes) / (prior successes + prior failures + trials)
+//
+// so routes with no record start at the prior (SUCCESS_PRIOR_SUCCESSES /
+// SUCCESS_PRIOR_FAILURES) and move towards what they actually did as trials come in.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::lifecycle::RouteKey;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Trials {
+    published: u64,
+    succeeded: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SuccessEstimate {
+    // Published requests of the route that reached a terminal state
+    pub published: u64,
+    pub succeeded: u64,
+    pub probability: f64,
+}
+
+#[derive(Debug)]
+pub struct SuccessTracker {
+    prior_successes: f64,
+    prior_failures: f64,
+    // Fraction of the expected net profit a fill may miss by and still succeed
+    max_shortfall: f64,
+    // Published requests not settled yet, by id
+    open: HashMap<String, RouteKey>,
+    routes: HashMap<RouteKey, Trials>,
+}
+
+impl SuccessTracker {
+    pub fn new(prior_successes: f64, prior_failures: f64, max_shortfall_pct: f64) -> Self {
+        SuccessTracker {
+            prior_successes: prior_successes.max(0.0),
+            prior_failures: prior_failures.max(0.0),
+            max_shortfall: max_shortfall_pct.max(0.0) / 100.0,
+            open: HashMap::new(),
+            routes: HashMap::new(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        SuccessTracker::new(
+            config::env_or("SUCCESS_PRIOR_SUCCESSES", 1.0),
+            config::env_or("SUCCESS_PRIOR_FAILURES", 1.0),
+            config::env_or("SUCCESS_PROFIT_SHORTFALL_PCT", 20.0),
+        )
+    }
+
+    /// Start a trial: the request went out to the executor
+    pub fn published(&mut self, request_id: &str, route: RouteKey) {
+        self.open.insert(request_id.to_string(), route);
+    }
+
+    /// Settle a request that reached a terminal state. `profit` is its expected and realized
+    /// net profit, when the fill reported one. Requests that were never published don't count
+    pub fn close(&mut self, request_id: &str, filled: bool, profit: Option<(f64, f64)>) {
+        let Some(route) = self.open.remove(request_id) else { return };
+        let at_expected_profit = profit.is_none_or(|(expected, realized)| realized >= expected - expected.abs() * self.max_shortfall);
+        let trials = self.routes.entry(route).or_default();
+        trials.published += 1;
+        if filled && at_expected_profit {
+            trials.succeeded += 1;
+        }
+    }
+
+    /// The posterior success rate of `route`; the prior's mean when it has no trials yet
+    pub fn estimate(&self, route: &RouteKey) -> SuccessEstimate {
+        let trials = self.routes.get(route).copied().unwrap_or_default();
+        let alpha = self.prior_successes + trials.succeeded as f64;
+        let total = self.prior_successes + self.prior_failures + trials.published as f64;
+        SuccessEstimate {
+            published: trials.published,
+            succeeded: trials.succeeded,
+            // No prior and no trials: nothing argues against the route
+            probability: if total > 0.0 { alpha / total } else { 1.0 },
+        }
+    }
+
+    /// Every route with at least one trial, least likely to succeed first
+    pub fn report(&self) -> Vec<(RouteKey, SuccessEstimate)> {
+        let mut routes: Vec<_> = self.routes.keys().map(|route| (route.clone(), self.estimate(route))).collect();
+        routes.sort_by(|a, b| a.1.probability.total_cmp(&b.1.probability).then_with(|| a.0.cmp(&b.0)));
+        routes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route() -> RouteKey {
+        RouteKey::new("BTC/USDT", "binance", "okx")
+    }
+
+    // Two of four published requests delivered what they promised
+    fn tracker() -> SuccessTracker {
+        let mut tracker = SuccessTracker::new(1.0, 1.0, 20.0);
+        for (id, filled, profit) in [
+            ("a", true, None),
+            ("b", true, Some((100.0, 85.0))),
+            // Filled, but well short of what it promised
+            ("c", true, Some((100.0, 40.0))),
+            ("d", false, None),
+        ] {
+            tracker.published(id, route());
+            tracker.close(id, filled, profit);
+        }
+        tracker
+    }
+
+    #[test]
+    fn unseen_routes_get_the_prior() {
+        let tracker = SuccessTracker::new(1.0, 1.0, 20.0);
+        assert_eq!(tracker.estimate(&route()), SuccessEstimate { published: 0, succeeded: 0, probability: 0.5 });
+    }
+
+    #[test]
+    fn only_published_requests_are_trials() {
+        let mut tracker = tracker();
+        // Never published: not a trial
+        tracker.close("e", false, None);
+        let estimate = tracker.estimate(&route());
+        assert_eq!((estimate.published, estimate.succeeded), (4, 2));
+        assert!((estimate.probability - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn posterior_moves_from_the_prior_towards_observed_fills() {
+        let mut tracker = tracker();
+        tracker.published("f", route());
+        tracker.close("f", true, Some((100.0, 120.0)));
+        assert!((tracker.estimate(&route()).probability - 4.0 / 7.0).abs() < 1e-9);
+        assert_eq!(tracker.report().len(), 1);
+    }
+}
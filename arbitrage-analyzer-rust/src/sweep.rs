@@ -0,0 +1,204 @@
+// Depth-aware sizing. A route is priced by walking both books level by level:
+// buying a size costs the volume-weighted average price (VWAP) of the asks it
+// takes, selling it earns the VWAP of the bids it hits. Between two sizes where
+// a level of either book runs out, gross profit, fees and capital all grow
+// linearly, so the most profitable size is one of those breakpoints or the size
+// cap. The walk stops where the next ask costs at least what the next bid pays;
+// the sizing rules (the conservative share, MAX_USD_SIZE and the hard caps)
+// apply to the depth walked up to there, as they used to apply to the top level.
+//
+// Every size priced on the way that clears the thresholds, plus SIZE_LADDER_STEPS
+// (default 4) even fractions of the chosen size, is published as the
+// opportunity's `size_ladder`: what each size is expected to net and return, so
+// the executor can trade less than `max_size` when its risk appetite or its
+// confidence in filling deep levels calls for it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ArbitrageOpportunity, OrderBook};
+
+/// Where the size of an opportunity came from, when it takes more than the top level
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DepthFill {
+    pub top_buy_price: f64,
+    pub top_sell_price: f64,
+    // Levels each leg takes, the last one possibly in part
+    pub buy_levels: usize,
+    pub sell_levels: usize,
+    // What the VWAPs cost against filling the whole size at the top of both books,
+    // in bps of the top buy price
+    pub slippage_bps: f64,
+}
+
+impl OrderBook {
+    // Well-formed levels from the best one, up to the first that isn't
+    fn usable_levels(levels: &[Vec<f64>]) -> impl Iterator<Item = (f64, f64)> + '_ {
+        (0..levels.len())
+            .map_while(|idx| Self::level(levels, idx))
+            .take_while(|(price, size)| price.is_finite() && size.is_finite() && *price > 0.0 && *size > 0.0)
+    }
+
+    /// Average price of taking `size` from `levels` best first, and how many levels
+    /// that takes; None when they hold less than `size`
+    pub(crate) fn vwap_for_size(levels: &[Vec<f64>], size: f64) -> Option<(f64, usize)> {
+        if !size.is_finite() || size <= 0.0 {
+            return None;
+        }
+        let (mut left, mut cost, mut taken) = (size, 0.0, 0);
+        for (price, available) in Self::usable_levels(levels) {
+            if left <= 0.0 {
+                break;
+            }
+            let take = available.min(left);
+            cost += take * price;
+            left -= take;
+            taken += 1;
+        }
+        // Breakpoints are sums of level sizes, so allow for their rounding
+        (left <= size * 1e-9).then(|| (cost / (size - left), taken))
+    }
+}
+
+/// Cumulative sizes at which a level of `asks` (prices × `price_adjustment`) or `bids`
+/// runs out, while the marginal bid still pays more than the marginal ask. The last
+/// one is the whole profitable depth
+pub(crate) fn breakpoints(asks: &[Vec<f64>], bids: &[Vec<f64>], price_adjustment: f64) -> Vec<f64> {
+    let mut asks = OrderBook::usable_levels(asks).map(|(price, size)| (price * price_adjustment, size));
+    let mut bids = OrderBook::usable_levels(bids);
+    let (Some(mut ask), Some(mut bid)) = (asks.next(), bids.next()) else {
+        return Vec::new();
+    };
+    let mut filled = 0.0;
+    let mut sizes = Vec::new();
+    while bid.0 > ask.0 {
+        let step = ask.1.min(bid.1);
+        filled += step;
+        sizes.push(filled);
+        ask.1 -= step;
+        bid.1 -= step;
+        if ask.1 <= 0.0 {
+            let Some(next) = asks.next() else { break };
+            ask = next;
+        }
+        if bid.1 <= 0.0 {
+            let Some(next) = bids.next() else { break };
+            bid = next;
+        }
+    }
+    sizes
+}
+
+/// One size of a route and what trading it is expected to earn
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LadderPoint {
+    pub size: f64,
+    // The VWAPs it fills at
+    pub buy_price: f64,
+    pub sell_price: f64,
+    pub net_profit: f64,
+    pub roi_percentage: f64,
+}
+
+impl LadderPoint {
+    pub(crate) fn of(opp: &ArbitrageOpportunity) -> Self {
+        LadderPoint {
+            size: opp.max_size,
+            buy_price: opp.buy_price,
+            sell_price: opp.sell_price,
+            net_profit: opp.net_profit,
+            roi_percentage: opp.roi_percentage,
+        }
+    }
+}
+
+/// `steps` even fractions of `max_size` below it
+pub(crate) fn ladder_fractions(max_size: f64, steps: usize) -> Vec<f64> {
+    (1..steps).map(|step| max_size * step as f64 / steps as f64).collect()
+}
+
+/// Both legs of a route filled at one size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Fill {
+    pub buy_price: f64,
+    pub sell_price: f64,
+    pub buy_levels: usize,
+    pub sell_levels: usize,
+}
+
+impl Fill {
+    pub(crate) fn at(asks: &[Vec<f64>], bids: &[Vec<f64>], price_adjustment: f64, size: f64) -> Option<Fill> {
+        let (buy_vwap, buy_levels) = OrderBook::vwap_for_size(asks, size)?;
+        let (sell_price, sell_levels) = OrderBook::vwap_for_size(bids, size)?;
+        Some(Fill { buy_price: buy_vwap * price_adjustment, sell_price, buy_levels, sell_levels })
+    }
+
+    /// What to attach to the opportunity; nothing for a fill at the top of both books
+    pub(crate) fn annotation(&self, top_buy_price: f64, top_sell_price: f64) -> Option<DepthFill> {
+        (self.buy_levels > 1 || self.sell_levels > 1).then(|| DepthFill {
+            top_buy_price,
+            top_sell_price,
+            buy_levels: self.buy_levels,
+            sell_levels: self.sell_levels,
+            slippage_bps: (self.buy_price - top_buy_price + top_sell_price - self.sell_price) / top_buy_price * 10_000.0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpreadAnalyzer;
+
+    fn asks() -> Vec<Vec<f64>> {
+        vec![vec![50_000.0, 0.1], vec![50_050.0, 0.4], vec![50_600.0, 2.0]]
+    }
+
+    fn bids() -> Vec<Vec<f64>> {
+        vec![vec![50_700.0, 0.2], vec![50_650.0, 1.0], vec![50_000.0, 5.0]]
+    }
+
+    #[test]
+    fn vwap_walks_the_levels_a_size_needs() {
+        let (vwap, levels) = OrderBook::vwap_for_size(&asks(), 0.3).unwrap();
+        assert!((vwap - (50_000.0 * 0.1 + 50_050.0 * 0.2) / 0.3).abs() < 1e-6);
+        assert_eq!(levels, 2);
+        assert_eq!(OrderBook::vwap_for_size(&asks(), 3.0), None);
+    }
+
+    #[test]
+    fn breakpoints_stop_where_the_spread_stops_paying() {
+        // 0.1 and 0.2 run out first, then 0.5 of asks; 50_600 ask vs 50_650 bid still pays, 50_000 bid does not
+        let sizes = breakpoints(&asks(), &bids(), 1.0);
+        assert_eq!(sizes.len(), 4);
+        assert!((sizes[3] - 1.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn walks_both_books_to_the_most_profitable_size() {
+        let analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let top = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 50_700.0, 0.1, 0.2).unwrap();
+        let deep = analyzer.evaluate_opportunity_depth("binance", "okx", "BTC/USDT", &asks(), &bids(), 1.0).unwrap();
+        assert!(deep.max_size > top.max_size);
+        assert!(deep.net_profit > top.net_profit);
+        assert!(deep.buy_price > 50_000.0 && deep.sell_price < 50_700.0);
+        let depth = deep.depth.unwrap();
+        assert_eq!((depth.top_buy_price, depth.top_sell_price), (50_000.0, 50_700.0));
+        assert!(depth.buy_levels > 1 && depth.slippage_bps > 0.0);
+        // Never past the conservative share of the profitable depth
+        assert!(deep.max_size <= 0.8 * 1.2 + 1e-9);
+        assert_eq!(top.depth, None);
+    }
+
+    #[test]
+    fn the_size_ladder_ends_at_the_chosen_size() {
+        let analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        let deep = analyzer.evaluate_opportunity_depth("binance", "okx", "BTC/USDT", &asks(), &bids(), 1.0).unwrap();
+        // Breakpoints and quarters of the chosen size, smallest first, ending at it
+        let ladder = &deep.size_ladder;
+        assert!(ladder.len() >= 4);
+        assert!(ladder.windows(2).all(|pair| pair[0].size < pair[1].size));
+        assert_eq!(ladder.last().map(|point| (point.size, point.net_profit)), Some((deep.max_size, deep.net_profit)));
+        assert!(ladder[0].net_profit < deep.net_profit);
+        assert_eq!(ladder_fractions(2.0, 4), vec![0.5, 1.0, 1.5]);
+    }
+}
@@ -0,0 +1,74 @@
+// `{name}` placeholder substitution for operator-configurable message text.
+// Unknown placeholders are left untouched so a typo shows up in the output.
+// Braces around anything but a name are literal, so JSON bodies can be templates.
+
+use crate::ArbitrageOpportunity;
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replace every `{name}` in `template` with its value from `vars`
+pub fn render(template: &str, vars: &[(&str, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}').filter(|end| is_name(&after[..*end])) {
+            Some(end) => {
+                let name = &after[..end];
+                match vars.iter().find(|(var, _)| *var == name) {
+                    Some((_, value)) => out.push_str(value),
+                    None => out.push_str(&rest[start..start + end + 2]),
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The placeholders of an opportunity, shared by the email and webhook templates
+pub fn opportunity_vars(opp: &ArbitrageOpportunity) -> Vec<(&'static str, String)> {
+    let precision = crate::precision::policy();
+    let price = |venue: &str, price: f64| precision.format_price(venue, &opp.pair, price).unwrap_or_else(|| format!("{:.6}", price));
+    let size = precision.format_size(&opp.pair, &[&opp.buy_exchange, &opp.sell_exchange], opp.max_size);
+    vec![
+        ("id", opp.id.clone()),
+        ("pair", opp.pair.clone()),
+        ("buy_exchange", opp.buy_exchange.clone()),
+        ("sell_exchange", opp.sell_exchange.clone()),
+        ("buy_price", price(&opp.buy_exchange, opp.buy_price)),
+        ("sell_price", price(&opp.sell_exchange, opp.sell_price)),
+        ("max_size", size.unwrap_or_else(|| format!("{:.6}", opp.max_size))),
+        ("net_profit", format!("{:.2}", opp.net_profit)),
+        ("estimated_fees", format!("{:.2}", opp.estimated_fees)),
+        ("roi_percentage", format!("{:.3}", opp.roi_percentage)),
+        ("timestamp", opp.timestamp.to_rfc3339()),
+    ]
+}
+
+/// Read a template from the environment; `\n` escapes become newlines since env values are single-line
+pub fn from_env(name: &str, default: &str) -> String {
+    crate::config::env_var(name).map(|raw| raw.replace("\\n", "\n")).unwrap_or_else(|_| default.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitutes_known_placeholders_only() {
+        let vars = [("pair", "BTC/USDT".to_string()), ("net_profit", "12.50".to_string())];
+        assert_eq!(render("{pair} net ${net_profit}", &vars), "BTC/USDT net $12.50");
+        assert_eq!(render("{pair} {nope} {", &vars), "BTC/USDT {nope} {");
+        assert_eq!(render("no placeholders", &vars), "no placeholders");
+        assert_eq!(render(r#"{"text": "{pair}", "value": {net_profit}}"#, &vars), r#"{"text": "BTC/USDT", "value": 12.50}"#);
+    }
+}
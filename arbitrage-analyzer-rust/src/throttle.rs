@@ -0,0 +1,117 @@
+// Deduplicating logger for warnings that repeat on every analysis pass.
+// The first occurrence of a message key is logged immediately; later ones are
+// counted and folded into a single line once per interval.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::Level;
+
+// Keys not seen for this many intervals are forgotten
+const FORGET_AFTER_INTERVALS: u32 = 10;
+const PRUNE_THRESHOLD: usize = 1024;
+
+#[derive(Debug)]
+struct ThrottleEntry {
+    last_emitted: Instant,
+    last_seen: Instant,
+    suppressed: u64,
+}
+
+#[derive(Debug)]
+pub struct LogThrottle {
+    interval: Duration,
+    entries: Mutex<HashMap<String, ThrottleEntry>>,
+}
+
+impl LogThrottle {
+    pub fn new(interval: Duration) -> Self {
+        LogThrottle { interval, entries: Mutex::new(HashMap::new()) }
+    }
+
+    /// Decide whether an occurrence of `key` should be logged now.
+    /// Returns the number of occurrences suppressed since the last emitted line.
+    fn admit(&self, key: &str, now: Instant) -> Option<u64> {
+        let mut entries = self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if entries.len() > PRUNE_THRESHOLD {
+            let horizon = self.interval * FORGET_AFTER_INTERVALS;
+            entries.retain(|_, entry| now.duration_since(entry.last_seen) < horizon);
+        }
+
+        match entries.get_mut(key) {
+            Some(entry) => {
+                entry.last_seen = now;
+                if now.duration_since(entry.last_emitted) >= self.interval {
+                    let suppressed = entry.suppressed;
+                    entry.suppressed = 0;
+                    entry.last_emitted = now;
+                    Some(suppressed)
+                } else {
+                    entry.suppressed += 1;
+                    None
+                }
+            }
+            None => {
+                entries.insert(key.to_string(), ThrottleEntry { last_emitted: now, last_seen: now, suppressed: 0 });
+                Some(0)
+            }
+        }
+    }
+
+    /// Log `message` at `level` unless the same `key` was already logged within the interval
+    pub fn log(&self, level: Level, key: &str, message: fmt::Arguments) {
+        if !log::log_enabled!(level) {
+            return;
+        }
+
+        match self.admit(key, Instant::now()) {
+            Some(0) => log::log!(level, "{}", message),
+            Some(suppressed) => log::log!(
+                level,
+                "{} (repeated {} more times in the last {}s)",
+                message,
+                suppressed,
+                self.interval.as_secs()
+            ),
+            None => {}
+        }
+    }
+
+    pub fn warn(&self, key: &str, message: fmt::Arguments) {
+        self.log(Level::Warn, key, message);
+    }
+
+    pub fn error(&self, key: &str, message: fmt::Arguments) {
+        self.log(Level::Error, key, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_occurrence_then_counts_once_per_interval() {
+        let throttle = LogThrottle::new(Duration::from_secs(30));
+        let start = Instant::now();
+
+        assert_eq!(throttle.admit("empty:binance", start), Some(0));
+        assert_eq!(throttle.admit("empty:binance", start + Duration::from_secs(1)), None);
+        assert_eq!(throttle.admit("empty:binance", start + Duration::from_secs(2)), None);
+        assert_eq!(throttle.admit("empty:binance", start + Duration::from_secs(31)), Some(2));
+        assert_eq!(throttle.admit("empty:binance", start + Duration::from_secs(32)), None);
+    }
+
+    #[test]
+    fn keys_are_throttled_independently() {
+        let throttle = LogThrottle::new(Duration::from_secs(30));
+        let now = Instant::now();
+
+        assert_eq!(throttle.admit("empty:binance", now), Some(0));
+        assert_eq!(throttle.admit("empty:uniswap", now), Some(0));
+        assert_eq!(throttle.admit("empty:binance", now), None);
+    }
+}
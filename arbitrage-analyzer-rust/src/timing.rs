@@ -0,0 +1,171 @@
+// Execution timing advice per route. What decides between crossing the spread
+// and resting orders is whether the spread lasts long enough for a resting order
+// to get filled: compare how long positive spreads on the route have persisted
+// (see `competition`) with our own latency, measured from creating an execution
+// request to the executor reporting it filled.
+//
+//  - `passive_post`: the route's spreads usually outlive our latency by at least
+//    TIMING_PASSIVE_RATIO, so there is time to post maker orders and save the
+//    taker fee,
+//  - `aggressive_ioc`: they don't, or we don't know yet, so take the liquidity
+//    with immediate-or-cancel orders.
+//
+// Persistence is the TIMING_PERSISTENCE_PERCENTILE (default 25th, i.e. three in
+// four spreads lasted at least that long) of the route's last closed spreads,
+// and needs TIMING_MIN_SPREADS of them. Latency is the median of the route's
+// fills, of all fills while the route has none, and TIMING_DEFAULT_LATENCY_MS
+// before anything has filled. The advice is embedded in every execution request;
+// the executor decides what to do with it.
+
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::lifecycle::{RequestState, RouteKey};
+use crate::{config, numeric, SpreadAnalyzer};
+
+// Fill latencies kept per route
+const LATENCIES_PER_ROUTE: usize = 200;
+const DEFAULT_PASSIVE_RATIO: f64 = 4.0;
+const DEFAULT_PERSISTENCE_PERCENTILE: f64 = 25.0;
+const DEFAULT_MIN_SPREADS: usize = 5;
+const DEFAULT_LATENCY_MS: f64 = 500.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionStyle {
+    // Cross the spread with immediate-or-cancel orders
+    AggressiveIoc,
+    // Post maker orders and wait for the fill
+    PassivePost,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencySource {
+    Route,
+    AllRoutes,
+    Default,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TimingAdvice {
+    pub style: ExecutionStyle,
+    // How long the route's spreads persist, None until enough of them closed
+    pub persistence_secs: Option<f64>,
+    pub closed_spreads: usize,
+    pub latency_secs: f64,
+    pub latency_source: LatencySource,
+}
+
+#[derive(Debug)]
+pub struct TimingAdvisor {
+    passive_ratio: f64,
+    persistence_percentile: f64,
+    min_spreads: usize,
+    default_latency_secs: f64,
+    latencies: HashMap<RouteKey, VecDeque<f64>>,
+}
+
+impl TimingAdvisor {
+    pub fn new(passive_ratio: f64, persistence_percentile: f64, min_spreads: usize, default_latency_secs: f64) -> Self {
+        TimingAdvisor { passive_ratio, persistence_percentile, min_spreads, default_latency_secs, latencies: HashMap::new() }
+    }
+
+    pub fn from_env() -> Self {
+        TimingAdvisor::new(
+            config::env_or("TIMING_PASSIVE_RATIO", DEFAULT_PASSIVE_RATIO),
+            config::env_or("TIMING_PERSISTENCE_PERCENTILE", DEFAULT_PERSISTENCE_PERCENTILE),
+            config::env_or("TIMING_MIN_SPREADS", DEFAULT_MIN_SPREADS),
+            config::env_or("TIMING_DEFAULT_LATENCY_MS", DEFAULT_LATENCY_MS) / 1000.0,
+        )
+    }
+
+    pub fn persistence_percentile(&self) -> f64 {
+        self.persistence_percentile
+    }
+
+    /// Record how long a request on `route` took from creation to its fill
+    pub fn observe_fill(&mut self, route: &RouteKey, created_at: DateTime<Utc>, filled_at: DateTime<Utc>) {
+        let latencies = self.latencies.entry(route.clone()).or_default();
+        latencies.push_back((filled_at - created_at).num_milliseconds().max(0) as f64 / 1000.0);
+        while latencies.len() > LATENCIES_PER_ROUTE {
+            latencies.pop_front();
+        }
+    }
+
+    fn latency(&self, route: &RouteKey) -> (f64, LatencySource) {
+        let own: Vec<f64> = self.latencies.get(route).map(|l| l.iter().copied().collect()).unwrap_or_default();
+        if let Some(median) = numeric::percentile(&own, 50.0) {
+            return (median, LatencySource::Route);
+        }
+        let all: Vec<f64> = self.latencies.values().flatten().copied().collect();
+        match numeric::percentile(&all, 50.0) {
+            Some(median) => (median, LatencySource::AllRoutes),
+            None => (self.default_latency_secs, LatencySource::Default),
+        }
+    }
+
+    /// Advice for `route`, given the persistence percentile of its spreads and how many closed
+    pub fn advise(&self, route: &RouteKey, persistence_secs: Option<f64>, closed_spreads: usize) -> TimingAdvice {
+        let (latency_secs, latency_source) = self.latency(route);
+        let persistence_secs = persistence_secs.filter(|_| closed_spreads >= self.min_spreads);
+        let style = match persistence_secs {
+            Some(persistence) if persistence >= latency_secs * self.passive_ratio => ExecutionStyle::PassivePost,
+            _ => ExecutionStyle::AggressiveIoc,
+        };
+        TimingAdvice { style, persistence_secs, closed_spreads, latency_secs, latency_source }
+    }
+}
+
+impl SpreadAnalyzer {
+    pub fn timing_advice(&self, route: &RouteKey) -> TimingAdvice {
+        let (persistence, closed_spreads) = self.competition.persistence(route, self.timing.persistence_percentile());
+        self.timing.advise(route, persistence, closed_spreads)
+    }
+
+    /// Feed the fill latency of a request that just reached `state`
+    pub fn observe_request_timing(&mut self, request_id: &str, state: RequestState) {
+        if state != RequestState::Filled {
+            return;
+        }
+        // Terminal requests go to the front of the lifecycle history
+        if let Some(request) = self.lifecycle.recent().find(|r| r.id == request_id) {
+            self.timing.observe_fill(&request.route, request.created_at, request.updated_at);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn route() -> RouteKey {
+        RouteKey::new("BTC/USDT", "binance", "okx")
+    }
+
+    #[test]
+    fn passive_only_when_spreads_outlive_our_latency() {
+        let mut advisor = TimingAdvisor::new(4.0, 25.0, 5, 0.5);
+        // Nothing known: aggressive, at the default latency
+        let unknown = advisor.advise(&route(), None, 0);
+        assert_eq!((unknown.style, unknown.latency_source), (ExecutionStyle::AggressiveIoc, LatencySource::Default));
+        assert_eq!(advisor.advise(&route(), Some(2.0), 5).style, ExecutionStyle::PassivePost);
+        // Too few closed spreads to go on
+        assert_eq!(advisor.advise(&route(), Some(2.0), 4).persistence_secs, None);
+
+        // Fills on another route take 1s, which 2s spreads don't outlive 4 times
+        let start = Utc::now();
+        let other = RouteKey::new("ETH/USDT", "binance", "okx");
+        advisor.observe_fill(&other, start, start + Duration::seconds(1));
+        let advice = advisor.advise(&route(), Some(2.0), 5);
+        assert_eq!((advice.style, advice.latency_source, advice.latency_secs), (ExecutionStyle::AggressiveIoc, LatencySource::AllRoutes, 1.0));
+
+        // The route's own fills win
+        advisor.observe_fill(&route(), start, start + Duration::milliseconds(200));
+        let advice = advisor.advise(&route(), Some(2.0), 5);
+        assert_eq!((advice.style, advice.latency_source), (ExecutionStyle::PassivePost, LatencySource::Route));
+    }
+}
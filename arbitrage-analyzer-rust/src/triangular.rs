@@ -0,0 +1,416 @@
+// Triangular and multi-hop arbitrage. Every cached book is an edge of a currency
+// graph: its best ask converts the quote asset into the base, its best bid the
+// base into the quote, each at the venue's trading fee. A cycle that starts and
+// ends in the same asset and returns more than it started with is a
+// `MultiLegOpportunity`, e.g. USDT -> BTC -> ETH -> USDT on one exchange.
+//  - MULTI_LEG_ARBITRAGE=true enables the search; it runs over the books around
+//    each update, and over every book in comprehensive passes.
+//  - MULTI_LEG_MAX_LEGS (3 or 4, default 3) bounds the cycle length.
+//  - MULTI_LEG_START_ASSETS (default USDT,USDC,USD,BTC,ETH) are the assets a cycle
+//    may start in, in order of preference; each needs a USD price to be valued.
+//  - MULTI_LEG_CROSS_EXCHANGE=true also allows cycles whose legs are on different
+//    venues. The asset moved between them pays its withdrawal fee in kind, plus
+//    any bridge cost of the route.
+// A cycle is sized at the top level of each book, 80% of what the thinnest leg
+// allows and at most MAX_USD_SIZE, and must clear the same profit and ROI
+// thresholds as two-leg routes after fixed per-leg costs. Found cycles are
+// published on MULTI_LEG_CHANNEL; no execution request is emitted for them.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::metrics::Metrics;
+use crate::notional::NotionalConverter;
+use crate::profiles::StrategyTag;
+use crate::{config, numeric, OrderBook, SpreadAnalyzer, UnknownExchangePolicy};
+
+pub const DEFAULT_CHANNEL: &str = "multi_leg_opportunities";
+const DEFAULT_START_ASSETS: &str = "USDT,USDC,USD,BTC,ETH";
+// Same share of the thinnest level as two-leg routes take
+const CONSERVATIVE_SIZE: f64 = 0.8;
+
+#[derive(Debug, Clone)]
+pub struct MultiLegConfig {
+    pub enabled: bool,
+    pub max_legs: usize,
+    pub start_assets: Vec<String>,
+    pub cross_exchange: bool,
+    pub channel: String,
+}
+
+impl Default for MultiLegConfig {
+    fn default() -> Self {
+        MultiLegConfig {
+            enabled: false,
+            max_legs: 3,
+            start_assets: DEFAULT_START_ASSETS.split(',').map(str::to_string).collect(),
+            cross_exchange: false,
+            channel: DEFAULT_CHANNEL.to_string(),
+        }
+    }
+}
+
+impl MultiLegConfig {
+    pub fn from_env() -> Self {
+        MultiLegConfig {
+            enabled: config::env_or("MULTI_LEG_ARBITRAGE", false),
+            max_legs: config::env_or("MULTI_LEG_MAX_LEGS", 3usize).clamp(3, 4),
+            start_assets: config::env_var("MULTI_LEG_START_ASSETS")
+                .unwrap_or_else(|_| DEFAULT_START_ASSETS.to_string())
+                .split(',')
+                .map(|asset| asset.trim().to_uppercase())
+                .filter(|asset| !asset.is_empty())
+                .collect(),
+            cross_exchange: config::env_or("MULTI_LEG_CROSS_EXCHANGE", false),
+            channel: config::env_var("MULTI_LEG_CHANNEL").unwrap_or_else(|_| DEFAULT_CHANNEL.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+/// One conversion of a cycle, in the order they are executed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Leg {
+    pub exchange: String,
+    pub pair: String,
+    pub side: Side,
+    pub price: f64,
+    // Base quantity traded
+    pub size: f64,
+    pub from_asset: String,
+    pub to_asset: String,
+    pub amount_in: f64,
+    // What reaches the next leg, after the trading fee and any withdrawal fee
+    pub amount_out: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiLegOpportunity {
+    pub id: String,
+    pub start_asset: String,
+    pub legs: Vec<Leg>,
+    // Amounts, profits and fees are in the start asset
+    pub start_amount: f64,
+    pub end_amount: f64,
+    pub gross_profit: f64,
+    pub estimated_fees: f64,
+    pub net_profit: f64,
+    pub net_profit_usd: f64,
+    pub roi_percentage: f64,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub tag: StrategyTag,
+}
+
+impl MultiLegOpportunity {
+    /// `BTC/USDT@binance buy -> ETH/BTC@binance buy -> ETH/USDT@binance sell`
+    pub fn route(&self) -> String {
+        let legs: Vec<String> = self
+            .legs
+            .iter()
+            .map(|leg| format!("{}@{} {}", leg.pair, leg.exchange, if leg.side == Side::Buy { "buy" } else { "sell" }))
+            .collect();
+        legs.join(" -> ")
+    }
+}
+
+// A book read in one direction
+struct Edge<'a> {
+    key: &'a str,
+    exchange: &'a str,
+    pair: String,
+    side: Side,
+    from: String,
+    to: String,
+    price: f64,
+    // Base quantity at the top level
+    size: f64,
+    fee_pct: f64,
+}
+
+impl Edge<'_> {
+    /// Units of `to` per unit of `from`
+    fn rate(&self, with_fee: bool) -> f64 {
+        let rate = match self.side {
+            Side::Buy => 1.0 / self.price,
+            Side::Sell => self.price,
+        };
+        if with_fee {
+            rate * (1.0 - self.fee_pct / 100.0)
+        } else {
+            rate
+        }
+    }
+
+    /// Most of `from` the top level takes
+    fn capacity(&self) -> f64 {
+        match self.side {
+            Side::Buy => self.size * self.price,
+            Side::Sell => self.size,
+        }
+    }
+}
+
+impl SpreadAnalyzer {
+    // Whether a book may be a leg: the same venue and freshness checks as two-leg routes
+    fn multi_leg_tradable(&self, book: &OrderBook, now: DateTime<Utc>) -> bool {
+        let registered = self.fees_config.is_registered_exchange(&book.exchange)
+            || self.fees_config.unknown_exchange_policy == UnknownExchangePolicy::DefaultFee;
+        registered
+            && !self.watchdog.is_suspect(&book.exchange)
+            && !self.quarantined.contains_key(&book.exchange)
+            && !self.lags_slot_tip(book)
+            && !self.staleness.is_stale(book, now)
+    }
+
+    fn multi_leg_edges(&self, now: DateTime<Utc>) -> Vec<Edge<'_>> {
+        let mut edges = Vec::new();
+        for (key, book) in &self.books {
+            if !self.multi_leg_tradable(book, now) {
+                continue;
+            }
+            let pair = book.pair.replace("WBTC", "BTC");
+            let Some((base, quote)) = pair.split_once('/') else { continue };
+            let fee_pct = self.fees_config.trading_fee_pct(&book.exchange);
+            let (base, quote) = (base.to_string(), quote.to_string());
+            if let Some((price, size)) = book.best_ask().filter(|(price, size)| *price > 0.0 && *size > 0.0) {
+                edges.push(Edge { key, exchange: &book.exchange, pair: pair.clone(), side: Side::Buy, from: quote.clone(), to: base.clone(), price, size, fee_pct });
+            }
+            if let Some((price, size)) = book.best_bid().filter(|(price, size)| *price > 0.0 && *size > 0.0) {
+                edges.push(Edge { key, exchange: &book.exchange, pair: pair.clone(), side: Side::Sell, from: base, to: quote, price, size, fee_pct });
+            }
+        }
+        edges.retain(|edge| numeric::all_finite(&[edge.price, edge.size, edge.fee_pct]));
+        // Stable search order, so repeated passes find the same cycles
+        edges.sort_by(|a, b| (a.key, a.side == Side::Sell).cmp(&(b.key, b.side == Side::Sell)));
+        edges
+    }
+
+    /// Profitable cycles over the cached books; with `involving`, only those with a leg on that book
+    pub(crate) fn find_multi_leg(&self, involving: Option<&str>, now: DateTime<Utc>) -> Vec<MultiLegOpportunity> {
+        let edges = self.multi_leg_edges(now);
+        let mut by_asset: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (idx, edge) in edges.iter().enumerate() {
+            by_asset.entry(edge.from.as_str()).or_default().push(idx);
+        }
+
+        let converter = NotionalConverter::new(&self.capital_config.quote_usd, &self.books, self.sizing_config.reference_price);
+        // Each set of book sides is one cycle, reported from the first start asset listed
+        let mut by_sides: BTreeMap<Vec<(&str, bool)>, MultiLegOpportunity> = BTreeMap::new();
+        for start in &self.multi_leg.start_assets {
+            let Some(usd) = self.capital_config.quote_usd.get(start).copied().or_else(|| converter.consensus_price(start)) else {
+                continue;
+            };
+            let mut cycles = Vec::new();
+            self.extend_cycles(&edges, &by_asset, start, &mut vec![], 1.0, &mut cycles);
+            for path in cycles {
+                let legs: Vec<&Edge> = path.iter().map(|idx| &edges[*idx]).collect();
+                if involving.is_some_and(|key| !legs.iter().any(|leg| leg.key == key)) {
+                    continue;
+                }
+                let Some(opp) = self.price_cycle(start, &legs, usd, now) else { continue };
+                let mut sides: Vec<(&str, bool)> = legs.iter().map(|leg| (leg.key, leg.side == Side::Buy)).collect();
+                sides.sort();
+                by_sides.entry(sides).or_insert(opp);
+            }
+        }
+        let mut found: Vec<MultiLegOpportunity> = by_sides.into_values().collect();
+        found.sort_by(|a, b| numeric::cmp_desc(a.net_profit_usd, b.net_profit_usd));
+        found
+    }
+
+    // Depth-first search for paths back to `start` that gain before fixed costs
+    fn extend_cycles(&self, edges: &[Edge], by_asset: &HashMap<&str, Vec<usize>>, start: &str, path: &mut Vec<usize>, rate: f64, cycles: &mut Vec<Vec<usize>>) {
+        let at = path.last().map_or(start, |idx| edges[*idx].to.as_str());
+        for &next in by_asset.get(at).into_iter().flatten() {
+            let edge = &edges[next];
+            // A book is used once, and only the closing leg may return to the start
+            if path.iter().any(|idx| edges[*idx].key == edge.key) {
+                continue;
+            }
+            if !self.multi_leg.cross_exchange && path.first().is_some_and(|first| edges[*first].exchange != edge.exchange) {
+                continue;
+            }
+            let rate = rate * edge.rate(true);
+            path.push(next);
+            if edge.to == start {
+                if path.len() >= 3 && rate > 1.0 {
+                    cycles.push(path.clone());
+                }
+            } else if path.len() < self.multi_leg.max_legs && path.iter().all(|idx| edges[*idx].from != edge.to) {
+                self.extend_cycles(edges, by_asset, start, path, rate, cycles);
+            }
+            path.pop();
+        }
+    }
+
+    // Size and cost a cycle starting in `start`, worth `usd` per unit, if it clears the thresholds
+    fn price_cycle(&self, start: &str, legs: &[&Edge], usd: f64, now: DateTime<Utc>) -> Option<MultiLegOpportunity> {
+        let fees = &self.fees_config;
+        // Withdrawal fee, in kind, for the asset moved onto each leg's venue
+        let mut withdrawals = Vec::with_capacity(legs.len());
+        let mut fixed_usd = 0.0;
+        for (idx, leg) in legs.iter().enumerate() {
+            fixed_usd += fees.fixed_leg_cost(leg.exchange);
+            let previous = idx.checked_sub(1).map(|prev| legs[prev].exchange).filter(|prev| *prev != leg.exchange);
+            let Some(previous) = previous else {
+                withdrawals.push(0.0);
+                continue;
+            };
+            if !fees.can_withdraw(previous, &leg.from) {
+                return None;
+            }
+            withdrawals.push(fees.withdrawal_fees.get(&leg.from).copied().unwrap_or(0.0));
+            fixed_usd += fees.transfer_cost(previous, leg.exchange);
+        }
+
+        // The thinnest level, in start units, and the USD cap
+        let mut reach = 1.0;
+        let mut cap = self.sizing_config.max_usd_size / usd;
+        for leg in legs {
+            cap = cap.min(CONSERVATIVE_SIZE * leg.capacity() / reach);
+            reach *= leg.rate(true);
+        }
+        let start_amount = cap;
+        if !start_amount.is_finite() || start_amount <= 0.0 {
+            return None;
+        }
+
+        let mut amount = start_amount;
+        let mut gross_end = start_amount;
+        let mut executed = Vec::with_capacity(legs.len());
+        for (leg, withdrawal) in legs.iter().zip(withdrawals) {
+            amount -= withdrawal;
+            if amount <= 0.0 {
+                return None;
+            }
+            let amount_out = amount * leg.rate(true);
+            executed.push(Leg {
+                exchange: leg.exchange.to_string(),
+                pair: leg.pair.clone(),
+                side: leg.side,
+                price: leg.price,
+                size: if leg.side == Side::Buy { amount / leg.price } else { amount },
+                from_asset: leg.from.clone(),
+                to_asset: leg.to.clone(),
+                amount_in: amount,
+                amount_out,
+            });
+            amount = amount_out;
+            gross_end *= leg.rate(false);
+        }
+
+        let gross_profit = gross_end - start_amount;
+        let estimated_fees = gross_end - amount + fixed_usd / usd;
+        let net_profit = gross_profit - estimated_fees;
+        let net_profit_usd = net_profit * usd;
+        let roi_percentage = numeric::safe_pct(net_profit, start_amount)?;
+        if !numeric::all_finite(&[gross_profit, estimated_fees, net_profit_usd]) {
+            return None;
+        }
+        if net_profit_usd < self.thresholds.min_profit || roi_percentage < self.thresholds.min_roi_percentage {
+            return None;
+        }
+
+        Some(MultiLegOpportunity {
+            id: Uuid::new_v4().to_string(),
+            start_asset: start.to_string(),
+            legs: executed,
+            start_amount,
+            end_amount: amount,
+            gross_profit,
+            estimated_fees,
+            net_profit,
+            net_profit_usd,
+            roi_percentage,
+            timestamp: now,
+            tag: self.strategy_tag.clone(),
+        })
+    }
+
+    /// Search for cycles after an update to `book_key` (None: every book) and publish them
+    pub(crate) fn analyze_multi_leg(&mut self, book_key: Option<&str>, now: DateTime<Utc>) -> Vec<MultiLegOpportunity> {
+        let found = self.find_multi_leg(book_key, now);
+        for opp in &found {
+            Metrics::inc(&self.metrics.multi_leg_opportunities);
+            info!(
+                "Multi-leg opportunity {}: {} {:.6} {} -> {:.6}, net ${:.2} ({:.3}% ROI)",
+                opp.id,
+                opp.route(),
+                opp.start_amount,
+                opp.start_asset,
+                opp.end_amount,
+                opp.net_profit_usd,
+                opp.roi_percentage
+            );
+            if !self.publishing_paused() && self.mode.publishes_opportunities() {
+                self.publish_to(&self.multi_leg.channel, opp);
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ETH/BTC is priced at 0.05 but ETH/USDT implies 0.0525: buy BTC, then ETH with it, sell ETH
+    fn analyzer() -> SpreadAnalyzer {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.multi_leg.enabled = true;
+        for (exchange, pair, bid, ask) in [
+            ("binance", "BTC/USDT", 59_990.0, 60_000.0),
+            ("binance", "ETH/BTC", 0.04999, 0.05),
+            ("binance", "ETH/USDT", 3_150.0, 3_151.0),
+            // A cheaper ETH elsewhere, only reachable across venues
+            ("okx", "ETH/USDT", 2_900.0, 2_901.0),
+        ] {
+            let book = OrderBook::for_test(exchange, pair, vec![vec![bid, 10.0]], vec![vec![ask, 10.0]]);
+            analyzer.books.insert(format!("{}:{}", exchange, pair), book);
+        }
+        analyzer
+    }
+
+    #[test]
+    fn finds_profitable_triangles_on_one_venue() {
+        let found = analyzer().find_multi_leg(None, Utc::now());
+        assert_eq!(found.len(), 1, "{:?}", found.iter().map(|opp| opp.route()).collect::<Vec<_>>());
+        let opp = &found[0];
+        assert_eq!(opp.route(), "BTC/USDT@binance buy -> ETH/BTC@binance buy -> ETH/USDT@binance sell");
+        assert_eq!(opp.start_asset, "USDT");
+        // The ETH/BTC ask level (10 ETH = 0.5 BTC) is the thinnest, 80% of it taken
+        assert!((opp.legs[1].size - 8.0).abs() < 1e-9, "{:?}", opp.legs[1]);
+        assert!(opp.net_profit > 0.0 && opp.end_amount > opp.start_amount);
+        assert!((opp.gross_profit - opp.estimated_fees - opp.net_profit).abs() < 1e-9);
+    }
+
+    #[test]
+    fn updates_only_search_the_cycles_through_their_book() {
+        let analyzer = analyzer();
+        let now = Utc::now();
+        assert_eq!(analyzer.find_multi_leg(Some("binance:ETH/BTC"), now).len(), 1);
+        assert!(analyzer.find_multi_leg(Some("okx:ETH/USDT"), now).is_empty());
+    }
+
+    #[test]
+    fn cross_venue_cycles_need_three_books() {
+        let mut analyzer = analyzer();
+        // Buying ETH on okx and selling it on binance is only a two-leg route; a
+        // cross-venue cycle needs three books and pays the ETH withdrawal fee
+        analyzer.multi_leg.cross_exchange = true;
+        let crossing = analyzer.find_multi_leg(Some("okx:ETH/USDT"), Utc::now());
+        assert!(crossing.iter().all(|opp| opp.legs.len() >= 3 && opp.legs.iter().any(|leg| leg.exchange == "okx")));
+        assert!(!crossing.is_empty());
+    }
+}
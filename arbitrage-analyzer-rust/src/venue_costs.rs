@@ -0,0 +1,150 @@
+// Cost models for venues the fee model has no schedule for. A proprietary or
+// regional venue implements `VenueCostModel` in `src/plugins.rs` (built with
+// `--features venue-plugins`) and is then priced like a built-in one: its
+// trading fee and per-trade cost go into the fee estimate, its transfer cost
+// into every route it is on, its latency into the ROI bar when the venue isn't
+// probed, and its settlement time into the lockup of the capital at risk. A
+// plugged-in venue counts as registered, so UNKNOWN_EXCHANGE_POLICY leaves it
+// alone; account profiles still override its fee like any other venue's.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use log::warn;
+
+use crate::plugins;
+
+pub trait VenueCostModel: fmt::Debug + Send + Sync {
+    /// Exchange name the venue's books arrive under
+    fn venue(&self) -> &str;
+    /// Trading fee percentage for one leg, taker or maker
+    fn trading_fee_pct(&self, taker: bool) -> f64;
+    /// Per-trade cost independent of size (gas, ticket fees), in USD
+    fn fixed_cost_usd(&self) -> f64 {
+        0.0
+    }
+    /// Cost of moving the asset between this venue and `counterparty` on top of the
+    /// withdrawal fee, in USD. Charged for each plugged-in venue on a route
+    fn transfer_cost_usd(&self, _counterparty: &str) -> f64 {
+        0.0
+    }
+    /// Order round trip, used when the venue has no latency probe
+    fn latency_ms(&self) -> Option<f64> {
+        None
+    }
+    /// How long a fill takes to settle before its proceeds can move, in seconds
+    fn settlement_secs(&self) -> f64 {
+        0.0
+    }
+}
+
+/// Plugged-in cost models by venue. Shared, so fee model copies stay cheap
+#[derive(Clone, Default)]
+pub struct VenueCostModels {
+    models: Arc<BTreeMap<String, Arc<dyn VenueCostModel>>>,
+}
+
+impl fmt::Debug for VenueCostModels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.models.keys()).finish()
+    }
+}
+
+impl VenueCostModels {
+    pub fn new(models: Vec<Arc<dyn VenueCostModel>>) -> Self {
+        let mut by_venue = BTreeMap::new();
+        for model in models {
+            if let Some(replaced) = by_venue.insert(model.venue().to_string(), model) {
+                warn!("Venue cost model for {} registered twice; using the last one", replaced.venue());
+            }
+        }
+        VenueCostModels { models: Arc::new(by_venue) }
+    }
+
+    /// The models this build was compiled with
+    pub fn from_plugins() -> Self {
+        Self::new(plugins::venue_cost_models())
+    }
+
+    pub fn get(&self, venue: &str) -> Option<&dyn VenueCostModel> {
+        self.models.get(venue).map(|model| model.as_ref())
+    }
+
+    pub fn venues(&self) -> Vec<&str> {
+        self.models.keys().map(String::as_str).collect()
+    }
+
+    /// Transfer cost both plugged-in venues of a route charge
+    pub fn transfer_cost_usd(&self, buy_exchange: &str, sell_exchange: &str) -> f64 {
+        self.get(buy_exchange).map_or(0.0, |model| model.transfer_cost_usd(sell_exchange))
+            + self.get(sell_exchange).map_or(0.0, |model| model.transfer_cost_usd(buy_exchange))
+    }
+
+    /// Settlement time a route adds to its capital lockup: the slower of its venues
+    pub fn settlement_secs(&self, buy_exchange: &str, sell_exchange: &str) -> f64 {
+        [buy_exchange, sell_exchange].iter().filter_map(|venue| self.get(venue)).map(|model| model.settlement_secs()).fold(0.0, f64::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SpreadAnalyzer, UnknownExchangePolicy};
+    use chrono::Utc;
+
+    #[derive(Debug)]
+    struct RegionalVenue;
+
+    impl VenueCostModel for RegionalVenue {
+        fn venue(&self) -> &str {
+            "regional"
+        }
+
+        fn trading_fee_pct(&self, taker: bool) -> f64 {
+            if taker {
+                0.2
+            } else {
+                0.05
+            }
+        }
+
+        fn fixed_cost_usd(&self) -> f64 {
+            1.5
+        }
+
+        fn transfer_cost_usd(&self, _counterparty: &str) -> f64 {
+            4.0
+        }
+
+        fn latency_ms(&self) -> Option<f64> {
+            Some(900.0)
+        }
+
+        fn settlement_secs(&self) -> f64 {
+            7_200.0
+        }
+    }
+
+    #[test]
+    fn plugged_in_venues_are_priced_with_their_own_costs() {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.fees_config.unknown_exchange_policy = UnknownExchangePolicy::Reject;
+        assert!(analyzer.evaluate_opportunity("regional", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).is_none());
+
+        analyzer.fees_config.venue_models = VenueCostModels::new(vec![Arc::new(RegionalVenue)]);
+        // Both buy legs pay their fee in quote, so the estimates differ only by the venue's costs
+        analyzer.fees_config.fee_denominations.remove("binance");
+        let opp = analyzer.evaluate_opportunity("regional", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        let baseline = analyzer.evaluate_opportunity("binance", "okx", "BTC/USDT", 50_000.0, 51_000.0, 1.0, 1.0).unwrap();
+        // 0.2% taker against binance's 0.1% on the buy notional, plus 1.5 per trade and 4.0 to move the asset
+        let extra_fee = opp.max_size * 50_000.0 * 0.1 / 100.0;
+        assert!((opp.estimated_fees - baseline.estimated_fees - (extra_fee + 1.5 + 4.0)).abs() < 1e-6);
+        let lockup = |opp: &crate::ArbitrageOpportunity| opp.capital_at_risk.unwrap().lockup_secs;
+        assert_eq!(lockup(&opp), lockup(&baseline) + 7_200.0);
+
+        // Unprobed, so its declared latency sets the bar
+        let (slowest, _) = analyzer.latency_bar(&opp, Utc::now());
+        assert_eq!(slowest, Some(("regional", 900.0)));
+    }
+}
@@ -0,0 +1,341 @@
+// OKX and Bybit venue adapters: mapping between the analyzer's `BASE/QUOTE` pairs
+// and the venues' symbols, parsers for their public order book messages, and
+// direct websocket ingestion for the symbols in OKX_WS_SYMBOLS / BYBIT_WS_SYMBOLS
+// (build with `--features venue-ws`). Their fee schedules live in `FeesConfig`.
+//  - OKX `books5` pushes the full top 5 levels every time; each push replaces the book.
+//  - Bybit `orderbook.<depth>` sends a snapshot, then deltas; a new snapshot (or
+//    update id 1, after a Bybit restart) resets the book.
+
+// Only the parsers' tests use them without the feature
+#![cfg_attr(not(feature = "venue-ws"), allow(dead_code))]
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use crate::config;
+use crate::depth::{LevelBook, Levels};
+
+pub const OKX: &str = "okx";
+pub const BYBIT: &str = "bybit";
+const DEFAULT_OKX_WS_URL: &str = "wss://ws.okx.com:8443/ws/v5/public";
+const DEFAULT_BYBIT_WS_URL: &str = "wss://stream.bybit.com/v5/public/spot";
+// Bybit spot supports 1, 50 and 200 levels
+const DEFAULT_BYBIT_DEPTH: usize = 50;
+const OKX_DEPTH: usize = 5;
+// Quote assets recognised when splitting concatenated symbols like BTCUSDT, longest first
+const QUOTE_ASSETS: [&str; 6] = ["FDUSD", "USDT", "USDC", "USD", "BTC", "ETH"];
+
+/// `BTC-USDT` -> `BTC/USDT`
+pub fn okx_pair(inst_id: &str) -> String {
+    inst_id.replacen('-', "/", 1)
+}
+
+/// `BTCUSDT` -> `BTC/USDT`; symbols with an unknown quote asset are kept as they are
+pub fn bybit_pair(symbol: &str) -> String {
+    QUOTE_ASSETS
+        .iter()
+        .find_map(|quote| symbol.strip_suffix(quote).filter(|base| !base.is_empty()).map(|base| format!("{}/{}", base, quote)))
+        .unwrap_or_else(|| symbol.to_string())
+}
+
+/// A venue book after applying one message
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookUpdate {
+    pub symbol: String,
+    // Venue timestamp, epoch milliseconds
+    pub timestamp: i64,
+    pub bids: Levels,
+    pub asks: Levels,
+}
+
+#[derive(Debug, Deserialize)]
+struct OkxMessage {
+    data: Vec<OkxBook>,
+    arg: OkxArg,
+}
+
+#[derive(Debug, Deserialize)]
+struct OkxArg {
+    #[serde(rename = "instId")]
+    inst_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OkxBook {
+    asks: Vec<Vec<String>>,
+    bids: Vec<Vec<String>>,
+    ts: String,
+}
+
+/// Parse an OKX `books5` push. Ok(None) for subscription acks, errors and other events
+pub fn parse_okx(raw: &str) -> Result<Option<Vec<BookUpdate>>> {
+    let value: serde_json::Value = serde_json::from_str(raw)?;
+    if value.get("data").is_none() {
+        return Ok(None);
+    }
+    let message: OkxMessage = serde_json::from_value(value)?;
+    let updates = message
+        .data
+        .into_iter()
+        .map(|book| {
+            let (bids, asks) = LevelBook::from_levels(&book.bids, &book.asks)?.top(OKX_DEPTH);
+            Ok(BookUpdate { symbol: message.arg.inst_id.clone(), timestamp: book.ts.parse()?, bids, asks })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Some(updates))
+}
+
+#[derive(Debug, Deserialize)]
+struct BybitMessage {
+    #[serde(rename = "type")]
+    kind: String,
+    ts: i64,
+    data: BybitBook,
+}
+
+#[derive(Debug, Deserialize)]
+struct BybitBook {
+    s: String,
+    b: Vec<Vec<String>>,
+    a: Vec<Vec<String>>,
+    u: u64,
+}
+
+/// Bybit books per symbol, kept from snapshot + delta messages
+#[derive(Debug, Default)]
+pub struct BybitBooks {
+    books: HashMap<String, LevelBook>,
+}
+
+impl BybitBooks {
+    /// Apply an `orderbook.*` message. Ok(None) for subscription acks and pongs; on error
+    /// the symbol's book is dropped until the next snapshot
+    pub fn apply(&mut self, raw: &str, depth: usize) -> Result<Option<BookUpdate>> {
+        let value: serde_json::Value = serde_json::from_str(raw)?;
+        if value.get("topic").is_none() {
+            return Ok(None);
+        }
+        let message: BybitMessage = serde_json::from_value(value)?;
+        let symbol = message.data.s;
+
+        let applied = if message.kind == "snapshot" || message.data.u == 1 {
+            LevelBook::from_levels(&message.data.b, &message.data.a).map(|book| {
+                self.books.insert(symbol.clone(), book);
+            })
+        } else {
+            match self.books.get_mut(&symbol) {
+                Some(book) => book.apply(&message.data.b, &message.data.a),
+                None => Err(anyhow!("delta for {} before its snapshot", symbol)),
+            }
+        };
+        if let Err(e) = applied {
+            self.books.remove(&symbol);
+            return Err(e);
+        }
+
+        let (bids, asks) = self.books[&symbol].top(depth);
+        Ok(Some(BookUpdate { symbol, timestamp: message.ts, bids, asks }))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VenueWsConfig {
+    pub venue: &'static str,
+    // (venue symbol, pair name the analyzer sees), e.g. ("BTC-USDT", "BTC/USDT")
+    pub symbols: Vec<(String, String)>,
+    pub ws_url: String,
+    pub depth: usize,
+}
+
+impl VenueWsConfig {
+    fn from_env(venue: &'static str, prefix: &str, default_url: &str, default_depth: usize, pair: fn(&str) -> String) -> Option<Self> {
+        let raw = config::env_var(format!("{}_WS_SYMBOLS", prefix)).ok()?;
+        let symbols: Vec<(String, String)> = raw
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|entry| match entry.split_once(':') {
+                Some((symbol, name)) => (symbol.trim().to_uppercase(), name.trim().to_string()),
+                None => (entry.to_uppercase(), pair(&entry.to_uppercase())),
+            })
+            .collect();
+        if symbols.is_empty() {
+            return None;
+        }
+        Some(VenueWsConfig {
+            venue,
+            symbols,
+            ws_url: config::env_var(format!("{}_WS_URL", prefix)).unwrap_or_else(|_| default_url.to_string()),
+            depth: config::env_or(&format!("{}_WS_DEPTH", prefix), default_depth),
+        })
+    }
+
+    /// Every venue with symbols configured
+    pub fn all_from_env() -> Vec<Self> {
+        [
+            VenueWsConfig::from_env(OKX, "OKX", DEFAULT_OKX_WS_URL, OKX_DEPTH, okx_pair),
+            VenueWsConfig::from_env(BYBIT, "BYBIT", DEFAULT_BYBIT_WS_URL, DEFAULT_BYBIT_DEPTH, bybit_pair),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+#[cfg(feature = "venue-ws")]
+pub use stream::spawn;
+
+/// Without websocket support in the build the symbols are ignored
+#[cfg(not(feature = "venue-ws"))]
+pub fn spawn(config: VenueWsConfig, _queue: std::sync::Arc<crate::pipeline::BookQueue>) {
+    log::warn!(
+        "{} websocket symbols are set but this build has no websocket support (--features venue-ws); ignoring them",
+        config.venue
+    );
+}
+
+#[cfg(feature = "venue-ws")]
+mod stream {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use anyhow::{anyhow, Result};
+    use chrono::Utc;
+    use log::{info, warn};
+    use serde_json::json;
+    use tungstenite::Message;
+
+    use super::{parse_okx, BookUpdate, BybitBooks, VenueWsConfig, BYBIT};
+    #[cfg(feature = "chaos")]
+    use crate::chaos;
+    use crate::pipeline::{BookQueue, IngestEvent};
+    use crate::OrderBook;
+
+    const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+    // Both venues drop connections that stay quiet for 30s
+    const PING_INTERVAL: Duration = Duration::from_secs(20);
+
+    /// One websocket per venue, carrying all of its symbols, on its own thread
+    pub fn spawn(config: VenueWsConfig, queue: Arc<BookQueue>) {
+        thread::spawn(move || loop {
+            if let Err(e) = stream_venue(&config, &queue) {
+                warn!("{} depth stream failed: {}; reconnecting in {}s", config.venue, e, RECONNECT_DELAY.as_secs());
+            }
+            thread::sleep(RECONNECT_DELAY);
+        });
+    }
+
+    fn stream_venue(config: &VenueWsConfig, queue: &BookQueue) -> Result<()> {
+        let (mut socket, _) = tungstenite::connect(config.ws_url.as_str())?;
+        let (subscribe, ping) = if config.venue == BYBIT {
+            let topics: Vec<String> = config.symbols.iter().map(|(symbol, _)| format!("orderbook.{}.{}", config.depth, symbol)).collect();
+            (json!({ "op": "subscribe", "args": topics }), json!({ "op": "ping" }).to_string())
+        } else {
+            let args: Vec<_> = config.symbols.iter().map(|(symbol, _)| json!({ "channel": "books5", "instId": symbol })).collect();
+            (json!({ "op": "subscribe", "args": args }), "ping".to_string())
+        };
+        socket.send(Message::text(subscribe.to_string()))?;
+        info!("  Streaming {} depth for {} symbol(s) from {}", config.venue, config.symbols.len(), config.ws_url);
+
+        let pairs: HashMap<&str, &str> = config.symbols.iter().map(|(symbol, pair)| (symbol.as_str(), pair.as_str())).collect();
+        let source = format!("{}-ws", config.venue);
+        let mut bybit = BybitBooks::default();
+        let mut last_ping = Instant::now();
+        #[cfg(feature = "chaos")]
+        let mut chaos = chaos::Chaos::from_env();
+        loop {
+            if last_ping.elapsed() >= PING_INTERVAL {
+                socket.send(Message::text(ping.clone()))?;
+                last_ping = Instant::now();
+            }
+            let text = match socket.read()? {
+                Message::Text(text) => text,
+                Message::Close(frame) => return Err(anyhow!("closed by server: {:?}", frame)),
+                _ => continue,
+            };
+            #[cfg(feature = "chaos")]
+            let texts = chaos::pass(&mut chaos, text);
+            #[cfg(not(feature = "chaos"))]
+            let texts = [text];
+            for text in texts {
+                // OKX answers a ping with a bare `pong`
+                if text.as_str() == "pong" {
+                    continue;
+                }
+
+                let updates = if config.venue == BYBIT {
+                    bybit.apply(&text, config.depth).map(|update| update.map(|u| vec![u]))
+                } else {
+                    parse_okx(&text)
+                };
+                let updates = match updates {
+                    Ok(updates) => updates.unwrap_or_default(),
+                    // A broken Bybit book stays dropped until the venue sends a snapshot; resubscribing forces one
+                    Err(e) => return Err(anyhow!("unusable message: {}", e)),
+                };
+                for BookUpdate { symbol, timestamp, bids, asks } in updates {
+                    let Some(pair) = pairs.get(symbol.as_str()) else { continue };
+                    let book = OrderBook {
+                        exchange: config.venue.to_string(),
+                        pair: pair.to_string(),
+                        bids,
+                        asks,
+                        timestamp,
+                        slot: None,
+                        received_at: Some(Utc::now()),
+                        source: Some(source.clone()),
+                        anomaly_score: None,
+                    };
+                    queue.push(IngestEvent::Book { key: format!("orderbook:{}:{}", config.venue, pair), book });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_venue_symbols_to_pairs() {
+        assert_eq!(okx_pair("BTC-USDT"), "BTC/USDT");
+        assert_eq!(bybit_pair("BTCUSDT"), "BTC/USDT");
+        assert_eq!(bybit_pair("ETHBTC"), "ETH/BTC");
+        assert_eq!(bybit_pair("BTCFDUSD"), "BTC/FDUSD");
+        assert_eq!(bybit_pair("USDT"), "USDT");
+    }
+
+    #[test]
+    fn parses_okx_books5_fixture() {
+        let updates = parse_okx(include_str!("../fixtures/okx_books5.json")).unwrap().unwrap();
+        assert_eq!(updates.len(), 1);
+        let update = &updates[0];
+        assert_eq!((update.symbol.as_str(), update.timestamp), ("BTC-USDT", 1718000000123));
+        assert_eq!(update.bids.len(), 5);
+        assert_eq!(update.bids[0], vec![64012.4, 0.8731]);
+        assert_eq!(update.asks[0], vec![64012.5, 0.41208]);
+
+        assert!(parse_okx(r#"{"event":"subscribe","arg":{"channel":"books5","instId":"BTC-USDT"}}"#).unwrap().is_none());
+    }
+
+    #[test]
+    fn applies_bybit_snapshot_then_delta_fixtures() {
+        let mut books = BybitBooks::default();
+        assert!(books.apply(include_str!("../fixtures/bybit_orderbook_delta.json"), 50).is_err());
+
+        let snapshot = books.apply(include_str!("../fixtures/bybit_orderbook_snapshot.json"), 50).unwrap().unwrap();
+        assert_eq!(snapshot.bids[0], vec![64011.9, 0.512]);
+
+        let delta = books.apply(include_str!("../fixtures/bybit_orderbook_delta.json"), 2).unwrap().unwrap();
+        assert_eq!(delta.timestamp, 1718000000476);
+        assert_eq!(delta.bids, vec![vec![64011.7, 0.25], vec![64011.5, 1.204]]);
+        assert_eq!(delta.asks, vec![vec![64012.1, 0.1], vec![64012.8, 0.015]]);
+
+        assert!(books.apply(r#"{"success":true,"op":"subscribe"}"#, 50).unwrap().is_none());
+    }
+}
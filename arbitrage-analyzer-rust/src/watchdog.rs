@@ -0,0 +1,87 @@
+// Per-venue heartbeat tracking. A venue that stops sending book updates is
+// marked suspect until its next update arrives, since half-dead feeds are the
+// biggest source of phantom opportunities.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Duration, Utc};
+
+#[derive(Debug)]
+pub struct VenueWatchdog {
+    max_silence: Duration,
+    last_update: HashMap<String, DateTime<Utc>>,
+    suspect: HashSet<String>,
+}
+
+impl VenueWatchdog {
+    pub fn new(max_silence: Duration) -> Self {
+        VenueWatchdog { max_silence, last_update: HashMap::new(), suspect: HashSet::new() }
+    }
+
+    /// Record an update from `venue`. Returns true if the venue was suspect and has now recovered.
+    pub fn heartbeat(&mut self, venue: &str, now: DateTime<Utc>) -> bool {
+        self.last_update.insert(venue.to_string(), now);
+        self.suspect.remove(venue)
+    }
+
+    /// Mark venues silent for longer than the limit as suspect.
+    /// Returns only venues that just became suspect, with how long they have been silent.
+    pub fn check(&mut self, now: DateTime<Utc>) -> Vec<(String, Duration)> {
+        let mut newly_suspect = Vec::new();
+        for (venue, last) in &self.last_update {
+            let silence = now - *last;
+            if silence > self.max_silence && !self.suspect.contains(venue) {
+                newly_suspect.push((venue.clone(), silence));
+            }
+        }
+
+        for (venue, _) in &newly_suspect {
+            self.suspect.insert(venue.clone());
+        }
+        newly_suspect.sort();
+        newly_suspect
+    }
+
+    pub fn is_suspect(&self, venue: &str) -> bool {
+        self.suspect.contains(venue)
+    }
+
+    /// Suspect venues, sorted
+    pub fn suspect_venues(&self) -> Vec<String> {
+        let mut venues: Vec<String> = self.suspect.iter().cloned().collect();
+        venues.sort();
+        venues
+    }
+
+    /// True when every venue we have ever heard from is suspect
+    pub fn all_suspect(&self) -> bool {
+        !self.last_update.is_empty() && self.suspect.len() == self.last_update.len()
+    }
+
+    pub fn max_silence(&self) -> Duration {
+        self.max_silence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silent_venue_becomes_suspect_once_and_recovers() {
+        let mut watchdog = VenueWatchdog::new(Duration::seconds(60));
+        let start = Utc::now();
+        watchdog.heartbeat("binance", start);
+        watchdog.heartbeat("uniswap-v3-exact", start + Duration::seconds(50));
+
+        let stale = watchdog.check(start + Duration::seconds(61));
+        assert_eq!(stale.iter().map(|(v, _)| v.as_str()).collect::<Vec<_>>(), vec!["binance"]);
+        assert!(watchdog.is_suspect("binance"));
+        // Already flagged, not reported again
+        assert!(watchdog.check(start + Duration::seconds(90)).is_empty());
+
+        assert!(watchdog.heartbeat("binance", start + Duration::seconds(95)));
+        assert!(!watchdog.is_suspect("binance"));
+        assert!(!watchdog.heartbeat("binance", start + Duration::seconds(96)));
+    }
+}
@@ -0,0 +1,339 @@
+// Withdrawal fee refresh. The built-in `withdrawal_fees` (and the config file's
+// `[fees]` table) are only a starting point: venues change their fees without
+// notice. With WITHDRAWAL_FEE_SOURCES set, a background thread fetches a fee
+// table every WITHDRAWAL_FEE_REFRESH_SECS (default 3600) from each source:
+//  - `binance`: the default network's `withdrawFee` of every coin in
+//    `capital/config/getall`. Binance only serves it to an account, so this takes
+//    the read-only key in BINANCE_STATUS_API_KEY / BINANCE_STATUS_API_SECRET.
+//  - `feed`: WITHDRAWAL_FEE_FEED_URL, any JSON object of asset to fee in units of
+//    the asset (numbers or numeric strings), found at WITHDRAWAL_FEE_JSON_POINTER
+//    (default: the whole document). Entries that aren't fees are skipped.
+// An asset listed by several sources takes the highest fee: the fee model is per
+// asset, not per venue, so it stays on the safe side.
+//
+// The next housekeeping pass applies the table. Listed assets replace their
+// configured fee, unlisted ones keep it; a failed fetch changes nothing. Every
+// change of an asset's fee is logged and counted in
+// `swapsleuth_withdrawal_fee_changes_total`, and one of more than
+// WITHDRAWAL_FEE_ALERT_PCT (default 0, any change) is raised as a
+// `withdrawal_fee_changed` event. A shadow fee model follows along for the assets
+// its SHADOW_FEES don't override.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::alerts::Severity;
+use crate::events::{Event, EventClass};
+use crate::feasibility::{self, DEFAULT_BINANCE_REST_URL};
+use crate::metrics::Metrics;
+use crate::{config, numeric, SpreadAnalyzer};
+
+const DEFAULT_REFRESH_SECS: u64 = 3_600;
+const DEFAULT_ALERT_PCT: f64 = 0.0;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeSource {
+    Binance,
+    Feed,
+}
+
+impl FeeSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            FeeSource::Binance => "binance",
+            FeeSource::Feed => "feed",
+        }
+    }
+}
+
+/// An asset whose withdrawal fee differs from the one in use
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeeChange {
+    pub asset: String,
+    // None for an asset without a configured fee
+    pub previous: Option<f64>,
+    pub current: f64,
+}
+
+impl FeeChange {
+    /// Whether the change is large enough to alert on
+    pub fn exceeds(&self, alert_pct: f64) -> bool {
+        // A fee going up from zero is always worth a look
+        self.previous.is_some_and(|previous| numeric::safe_pct((self.current - previous).abs(), previous).is_none_or(|pct| pct > alert_pct))
+    }
+}
+
+/// The fees in `fetched` that differ from `current`, by asset
+pub fn diff(current: &HashMap<String, f64>, fetched: &HashMap<String, f64>) -> Vec<FeeChange> {
+    let mut changes: Vec<FeeChange> = fetched
+        .iter()
+        .filter(|(asset, fee)| current.get(*asset) != Some(*fee))
+        .map(|(asset, fee)| FeeChange { asset: asset.clone(), previous: current.get(asset).copied(), current: *fee })
+        .collect();
+    changes.sort_by(|a, b| a.asset.cmp(&b.asset));
+    changes
+}
+
+fn fee(value: &Value) -> Option<f64> {
+    match value {
+        Value::String(s) => s.parse::<f64>().ok(),
+        other => other.as_f64(),
+    }
+    .filter(|fee| fee.is_finite() && *fee >= 0.0)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BinanceNetwork {
+    #[serde(default)]
+    is_default: bool,
+    withdraw_fee: Option<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BinanceCoin {
+    coin: String,
+    #[serde(default)]
+    network_list: Vec<BinanceNetwork>,
+}
+
+/// Default-network withdrawal fee per coin from `GET /sapi/v1/capital/config/getall`
+pub fn parse_binance_withdrawal_fees(raw: &str) -> Result<HashMap<String, f64>> {
+    let coins: Vec<BinanceCoin> = serde_json::from_str(raw)?;
+    Ok(coins
+        .into_iter()
+        .filter_map(|coin| {
+            let network = coin.network_list.iter().find(|network| network.is_default)?;
+            Some((coin.coin.to_uppercase(), fee(network.withdraw_fee.as_ref()?)?))
+        })
+        .collect())
+}
+
+/// The asset-to-fee object at `pointer` in a fee feed
+pub fn parse_fee_feed(raw: &str, pointer: &str) -> Result<HashMap<String, f64>> {
+    let body: Value = serde_json::from_str(raw)?;
+    let table = body.pointer(pointer).and_then(Value::as_object).ok_or_else(|| anyhow!("no fee object at {:?} in the feed", pointer))?;
+    let fees: HashMap<String, f64> = table.iter().filter_map(|(asset, value)| Some((asset.to_uppercase(), fee(value)?))).collect();
+    if fees.is_empty() {
+        bail!("no fees at {:?} in the feed", pointer);
+    }
+    Ok(fees)
+}
+
+#[derive(Debug, Clone)]
+pub struct RefreshConfig {
+    pub sources: Vec<FeeSource>,
+    pub refresh: Duration,
+    feed_url: Option<String>,
+    pointer: String,
+    binance_url: String,
+    binance_key: Option<(String, String)>,
+}
+
+impl RefreshConfig {
+    /// None unless WITHDRAWAL_FEE_SOURCES lists a source
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(raw) = config::env_var("WITHDRAWAL_FEE_SOURCES") else { return Ok(None) };
+        let mut sources = Vec::new();
+        for name in raw.split(',').map(|name| name.trim().to_lowercase()).filter(|name| !name.is_empty()) {
+            sources.push(match name.as_str() {
+                "binance" => FeeSource::Binance,
+                "feed" => FeeSource::Feed,
+                other => bail!("WITHDRAWAL_FEE_SOURCES: unknown source {:?}", other),
+            });
+        }
+        if sources.is_empty() {
+            return Ok(None);
+        }
+        let feed_url = config::env_var("WITHDRAWAL_FEE_FEED_URL").ok();
+        if sources.contains(&FeeSource::Feed) && feed_url.is_none() {
+            bail!("WITHDRAWAL_FEE_SOURCES has `feed` but WITHDRAWAL_FEE_FEED_URL is not set");
+        }
+        Ok(Some(RefreshConfig {
+            sources,
+            refresh: Duration::from_secs(config::env_or("WITHDRAWAL_FEE_REFRESH_SECS", DEFAULT_REFRESH_SECS)),
+            feed_url,
+            pointer: config::env_var("WITHDRAWAL_FEE_JSON_POINTER").unwrap_or_default(),
+            binance_url: config::env_var("BINANCE_REST_URL").unwrap_or_else(|_| DEFAULT_BINANCE_REST_URL.to_string()),
+            binance_key: config::env_var("BINANCE_STATUS_API_KEY").ok().zip(config::env_var("BINANCE_STATUS_API_SECRET").ok()),
+        }))
+    }
+
+    fn fetch(&self, source: FeeSource) -> Result<HashMap<String, f64>> {
+        match source {
+            FeeSource::Binance => {
+                let key = self.binance_key.as_ref().ok_or_else(|| anyhow!("BINANCE_STATUS_API_KEY / BINANCE_STATUS_API_SECRET are not set"))?;
+                parse_binance_withdrawal_fees(&feasibility::binance_signed_get(&self.binance_url, feasibility::BINANCE_CAPITAL_CONFIG_PATH, key)?)
+            }
+            FeeSource::Feed => {
+                let url = self.feed_url.as_deref().unwrap_or_default();
+                let raw = ureq::get(url).timeout(REQUEST_TIMEOUT).call().map_err(|e| anyhow!("{}: {}", url, e))?.into_string()?;
+                parse_fee_feed(&raw, &self.pointer)
+            }
+        }
+    }
+
+    /// The highest fee per asset across the sources that answered, None if none did
+    fn fetch_all(&self) -> Option<HashMap<String, f64>> {
+        let mut merged: Option<HashMap<String, f64>> = None;
+        for source in &self.sources {
+            match self.fetch(*source) {
+                Ok(fees) => {
+                    let table = merged.get_or_insert_with(HashMap::new);
+                    for (asset, fee) in fees {
+                        let entry = table.entry(asset).or_insert(fee);
+                        *entry = entry.max(fee);
+                    }
+                }
+                Err(e) => warn!("Withdrawal fee refresh from {} failed: {}", source.name(), e),
+            }
+        }
+        merged
+    }
+}
+
+// Latest table fetched and not yet applied
+#[derive(Debug, Clone, Default)]
+pub struct PendingFees(Arc<Mutex<Option<HashMap<String, f64>>>>);
+
+impl PendingFees {
+    /// Hand over a fetched table for the next housekeeping pass
+    pub fn offer(&self, fees: HashMap<String, f64>) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = Some(fees);
+    }
+
+    fn take(&self) -> Option<HashMap<String, f64>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+#[derive(Debug)]
+pub struct FeeRefresh {
+    pub config: Option<RefreshConfig>,
+    pub alert_pct: f64,
+    pub pending: PendingFees,
+    pub refreshed_at: Option<DateTime<Utc>>,
+}
+
+impl FeeRefresh {
+    pub fn new(config: Option<RefreshConfig>, alert_pct: f64) -> Self {
+        FeeRefresh { config, alert_pct, pending: PendingFees::default(), refreshed_at: None }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Ok(Self::new(RefreshConfig::from_env()?, config::env_or("WITHDRAWAL_FEE_ALERT_PCT", DEFAULT_ALERT_PCT)))
+    }
+
+    /// Fetch on a background thread, when configured
+    pub fn spawn(&self) {
+        let Some(config) = self.config.clone() else { return };
+        let names: Vec<&str> = config.sources.iter().map(|source| source.name()).collect();
+        info!("  Refreshing withdrawal fees from {} every {}s", names.join(", "), config.refresh.as_secs());
+        let pending = self.pending.clone();
+        thread::spawn(move || loop {
+            if let Some(fees) = config.fetch_all() {
+                pending.offer(fees);
+            }
+            thread::sleep(config.refresh);
+        });
+    }
+}
+
+impl SpreadAnalyzer {
+    /// Apply the latest fetched withdrawal fees, alerting on the ones that moved
+    pub(crate) fn apply_withdrawal_fees(&mut self, now: DateTime<Utc>) {
+        let Some(fetched) = self.withdrawal_fee_refresh.pending.take() else { return };
+        self.withdrawal_fee_refresh.refreshed_at = Some(now);
+        for change in diff(&self.fees_config.withdrawal_fees, &fetched) {
+            // The shadow candidate follows unless SHADOW_FEES pins the asset
+            if let Some(shadow) = self.shadow_fees.as_mut() {
+                if shadow.candidate.withdrawal_fees.get(&change.asset).copied() == change.previous {
+                    shadow.candidate.withdrawal_fees.insert(change.asset.clone(), change.current);
+                }
+            }
+            self.fees_config.withdrawal_fees.insert(change.asset.clone(), change.current);
+            let Some(previous) = change.previous else {
+                info!("Withdrawal fee for {} is {}", change.asset, change.current);
+                continue;
+            };
+            Metrics::inc(&self.metrics.withdrawal_fee_changes);
+            let message = format!("Withdrawal fee for {} changed from {} to {}", change.asset, previous, change.current);
+            if !change.exceeds(self.withdrawal_fee_refresh.alert_pct) {
+                info!("{}", message);
+                continue;
+            }
+            warn!("{}", message);
+            self.publish(Event::new(EventClass::WithdrawalFeeChanged, Severity::Warning, message));
+        }
+    }
+
+    /// Withdrawal fees in use, for the API
+    pub(crate) fn withdrawal_fee_report(&self) -> Value {
+        let fees: BTreeMap<&String, &f64> = self.fees_config.withdrawal_fees.iter().collect();
+        let refresh = &self.withdrawal_fee_refresh;
+        json!({
+            "fees": fees,
+            "sources": refresh.config.as_ref().map(|c| c.sources.iter().map(|s| s.name()).collect::<Vec<_>>()).unwrap_or_default(),
+            "refreshed_at": refresh.refreshed_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    fn fetched() -> HashMap<String, f64> {
+        HashMap::from([("BTC".to_string(), 0.0004), ("USDT".to_string(), 1.0), ("SOL".to_string(), 0.01)])
+    }
+
+    fn fee_analyzer() -> SpreadAnalyzer {
+        let mut analyzer = SpreadAnalyzer::new("127.0.0.1:6379").unwrap();
+        analyzer.withdrawal_fee_refresh = FeeRefresh::new(None, 50.0);
+        analyzer.fees_config.withdrawal_fees = HashMap::from([("BTC".to_string(), 0.0005), ("USDT".to_string(), 10.0)]);
+        analyzer
+    }
+
+    #[test]
+    fn parses_fee_tables() {
+        let binance = parse_binance_withdrawal_fees(include_str!("../fixtures/binance_capital_config.json")).unwrap();
+        assert_eq!(binance, HashMap::from([("BTC".to_string(), 0.0002), ("ETH".to_string(), 0.0012)]));
+        let feed = parse_fee_feed(r#"{"data":{"btc":"0.0003","usdt":1,"xrp":"n/a"}}"#, "/data").unwrap();
+        assert_eq!(feed, HashMap::from([("BTC".to_string(), 0.0003), ("USDT".to_string(), 1.0)]));
+        assert!(parse_fee_feed(r#"{"data":{}}"#, "/data").is_err());
+    }
+
+    #[test]
+    fn flags_changes_over_the_threshold() {
+        let analyzer = fee_analyzer();
+        let changes = diff(&analyzer.fees_config.withdrawal_fees, &fetched());
+        // Down 20% and 90%
+        assert_eq!(changes.iter().map(|c| c.exceeds(50.0)).collect::<Vec<_>>(), vec![false, false, true]);
+    }
+
+    #[test]
+    fn applies_fetched_fees_once() {
+        let mut analyzer = fee_analyzer();
+        let now = Utc::now();
+        analyzer.withdrawal_fee_refresh.pending.offer(fetched());
+        analyzer.apply_withdrawal_fees(now);
+        assert_eq!(analyzer.fees_config.withdrawal_fees.get("USDT"), Some(&1.0));
+        assert_eq!(analyzer.fees_config.withdrawal_fees.get("SOL"), Some(&0.01));
+        assert_eq!(analyzer.metrics.withdrawal_fee_changes.load(Ordering::Relaxed), 2);
+        assert_eq!(analyzer.withdrawal_fee_refresh.refreshed_at, Some(now));
+        // Nothing new to apply
+        analyzer.apply_withdrawal_fees(now + chrono::Duration::seconds(1));
+        assert_eq!(analyzer.withdrawal_fee_refresh.refreshed_at, Some(now));
+    }
+}
@@ -0,0 +1,107 @@
+package connectors
+
+import (
+	"bytes"
+	"encoding/json"
+	"fmt"
+	"io"
+	"math"
+	"net/http"
+	"os"
+)
+
+// ---------- Shared helpers for reserve-based AMM pools (SushiSwap, Balancer) ----------
+//
+// Subgraphs for these pools report balances in human units, so the swap math
+// below works on float64 directly. Every function takes the pool's swap fee as
+// a fraction (0.003 for 0.3%) and charges it on the input amount, like the
+// Uniswap v3 simulation does.
+
+// Synthetic book ladder, same sizes as the Uniswap v3 connector
+var (
+	ammBaseSizes  = []float64{0.001, 0.005, 0.01} // base sold into the pool (bids)
+	ammQuoteSizes = []float64{50, 200, 1000}      // quote spent on the pool (asks)
+)
+
+// constantProductOut returns the output of an x*y=k swap (Uniswap v2 / SushiSwap):
+// out = reserveOut * in' / (reserveIn + in'), with in' = amountIn * (1 - fee)
+func constantProductOut(amountIn, reserveIn, reserveOut, fee float64) (float64, error) {
+	if reserveIn <= 0 || reserveOut <= 0 {
+		return 0, fmt.Errorf("empty pool (reserves %v/%v)", reserveIn, reserveOut)
+	}
+	in := amountIn * (1 - fee)
+	return reserveOut * in / (reserveIn + in), nil
+}
+
+// weightedOut returns the output of a Balancer weighted-pool swap:
+// out = balanceOut * (1 - (balanceIn / (balanceIn + in'))^(weightIn/weightOut))
+func weightedOut(amountIn, balanceIn, weightIn, balanceOut, weightOut, fee float64) (float64, error) {
+	if balanceIn <= 0 || balanceOut <= 0 || weightIn <= 0 || weightOut <= 0 {
+		return 0, fmt.Errorf("invalid pool state (balances %v/%v, weights %v/%v)", balanceIn, balanceOut, weightIn, weightOut)
+	}
+	in := amountIn * (1 - fee)
+	return balanceOut * (1 - math.Pow(balanceIn/(balanceIn+in), weightIn/weightOut)), nil
+}
+
+// syntheticBook prices a size ladder against a pool: bids sell each of baseSizes,
+// asks spend each of quoteSizes. sellBase quotes base -> quote and buyBase quotes
+// quote -> base; both return the amount received.
+func syntheticBook(baseSizes, quoteSizes []float64, sellBase, buyBase func(float64) (float64, error)) (bids, asks [][]float64) {
+	bids = [][]float64{}
+	for _, size := range baseSizes {
+		out, err := sellBase(size)
+		if err != nil || out <= 0 {
+			continue
+		}
+		// price = quote_out / base_in
+		bids = append(bids, []float64{out / size, size})
+	}
+
+	asks = [][]float64{}
+	for _, spend := range quoteSizes {
+		out, err := buyBase(spend)
+		if err != nil || out <= 0 {
+			continue
+		}
+		// price = quote_spent / base_out
+		asks = append(asks, []float64{spend / out, out})
+	}
+	return bids, asks
+}
+
+// querySubgraph posts a GraphQL query and decodes the response into out
+func querySubgraph(url, query string, out interface{}) error {
+	requestBody, _ := json.Marshal(map[string]string{"query": query})
+	req, err := http.NewRequest(http.MethodPost, url, bytes.NewBuffer(requestBody))
+	if err != nil {
+		return err
+	}
+	if apiKey := os.Getenv("SUBGRAPH_API_KEY"); apiKey != "" {
+		req.Header.Set("Authorization", "Bearer "+apiKey)
+	}
+	req.Header.Set("Accept", "application/json")
+	req.Header.Set("Content-Type", "application/json")
+
+	resp, err := http.DefaultClient.Do(req)
+	if err != nil {
+		return err
+	}
+	defer resp.Body.Close()
+	body, err := io.ReadAll(resp.Body)
+	if err != nil {
+		return err
+	}
+	if resp.StatusCode != http.StatusOK {
+		return fmt.Errorf("subgraph returned %s: %s", resp.Status, body)
+	}
+	return json.Unmarshal(body, out)
+}
+
+// parseFloat parses a decimal string from a subgraph response
+func parseFloat(s string) (float64, error) {
+	var f float64
+	if _, err := fmt.Sscan(s, &f); err != nil {
+		return 0, fmt.Errorf("invalid number %q: %w", s, err)
+	}
+	return f, nil
+}
@@ -0,0 +1,117 @@
+package connectors
+
+import (
+	"context"
+	"encoding/json"
+	"fmt"
+	"log"
+	"os"
+	"time"
+
+	"github.com/Jkrish1011/SwapSleuth/arbitrage-bot-go/utils"
+)
+
+// Mainnet token addresses (lowercase, as the subgraph stores them)
+const (
+	wbtcAddress = "0x2260fac5e5542a773aa44fbcfedf7c193bc2c599"
+	usdtAddress = "0xdac17f958d2ee523a2206206994597c13d831ec7"
+)
+
+type balancerToken struct {
+	Symbol  string `json:"symbol"`
+	Balance string `json:"balance"` // human units
+	Weight  string `json:"weight"`  // normalized, e.g. "0.8"
+}
+
+// --- Data types for the Balancer v2 subgraph response (trimmed) ---
+type BalancerResponse struct {
+	Data struct {
+		Pools []struct {
+			ID      string          `json:"id"`
+			SwapFee string          `json:"swapFee"` // fraction, e.g. "0.003"
+			Tokens  []balancerToken `json:"tokens"`
+		} `json:"pools"`
+	} `json:"data"`
+}
+
+// BalancerConnector publishes a synthetic WBTC/USDT book from the deepest
+// Balancer weighted pool holding both tokens. Needs BALANCER_SUBGRAPH_URL;
+// skipped when it is unset.
+func BalancerConnector() {
+	url := os.Getenv("BALANCER_SUBGRAPH_URL")
+	if url == "" {
+		return
+	}
+
+	query := fmt.Sprintf(`{
+		pools(
+			where: { poolType: "Weighted", tokensList_contains: ["%s", "%s"] }
+			orderBy: totalLiquidity
+			orderDirection: desc
+			first: 5
+		) {
+			id
+			swapFee
+			tokens { symbol balance weight }
+		}
+	}`, wbtcAddress, usdtAddress)
+
+	var balResp BalancerResponse
+	if err := querySubgraph(url, query, &balResp); err != nil {
+		log.Println("error fetching balancer subgraph:", err)
+		return
+	}
+	if len(balResp.Data.Pools) == 0 {
+		log.Println("No balancer pools found")
+		return
+	}
+
+	pool := balResp.Data.Pools[0]
+	var base, quote *balancerToken
+	for i := range pool.Tokens {
+		switch pool.Tokens[i].Symbol {
+		case "WBTC":
+			base = &pool.Tokens[i]
+		case "USDT":
+			quote = &pool.Tokens[i]
+		}
+	}
+	if base == nil || quote == nil {
+		log.Println("balancer pool", pool.ID, "has no WBTC/USDT tokens")
+		return
+	}
+
+	// Any parse error leaves a zero, which weightedOut rejects
+	fee, _ := parseFloat(pool.SwapFee)
+	baseBalance, _ := parseFloat(base.Balance)
+	baseWeight, _ := parseFloat(base.Weight)
+	quoteBalance, _ := parseFloat(quote.Balance)
+	quoteWeight, _ := parseFloat(quote.Weight)
+	// The analyzer charges BALANCER_SWAP_FEE per leg; keep it in line with this
+	fmt.Println("Using balancer pool:", pool.ID, "swapFee:", pool.SwapFee)
+
+	bids, asks := syntheticBook(
+		ammBaseSizes, ammQuoteSizes,
+		func(size float64) (float64, error) {
+			return weightedOut(size, baseBalance, baseWeight, quoteBalance, quoteWeight, fee)
+		},
+		func(spend float64) (float64, error) {
+			return weightedOut(spend, quoteBalance, quoteWeight, baseBalance, baseWeight, fee)
+		},
+	)
+
+	ob := utils.NormalizationSchema{
+		Exchange:  "balancer",
+		Pair:      base.Symbol + "/" + quote.Symbol,
+		Bids:      bids,
+		Asks:      asks,
+		Timestamp: time.Now().Unix(),
+	}
+
+	j, _ := json.MarshalIndent(ob, "", "  ")
+	fmt.Println(string(j))
+
+	if err := utils.PushOrderbook(context.Background(), ob); err != nil {
+		fmt.Printf("error pushing orderbook to Redis: %v\n", err)
+	}
+}
@@ -0,0 +1,169 @@
+package connectors
+
+import (
+	"context"
+	"encoding/json"
+	"fmt"
+	"io"
+	"log"
+	"math"
+	"net/http"
+	"os"
+	"strconv"
+	"strings"
+	"time"
+
+	"github.com/Jkrish1011/SwapSleuth/arbitrage-bot-go/utils"
+)
+
+// osmosisDenom maps an on-chain denom to a symbol and its decimal exponent
+type osmosisDenom struct {
+	Symbol   string
+	Exponent int
+}
+
+// --- Data types for the Osmosis LCD gamm pool response (trimmed) ---
+type OsmosisPoolResponse struct {
+	Pool struct {
+		ID         string `json:"id"`
+		PoolParams struct {
+			SwapFee string `json:"swap_fee"` // fraction, e.g. "0.002000000000000000"
+		} `json:"pool_params"`
+		PoolAssets []struct {
+			Token struct {
+				Denom  string `json:"denom"`
+				Amount string `json:"amount"` // base units
+			} `json:"token"`
+			Weight string `json:"weight"`
+		} `json:"pool_assets"`
+	} `json:"pool"`
+}
+
+// osmosisDenoms returns uosmo plus OSMOSIS_DENOMS, e.g.
+// "ibc/27394FB0...=ATOM:6,ibc/D1542AA8...=USDT:6" (IBC denoms contain '/', hence '=')
+func osmosisDenoms() map[string]osmosisDenom {
+	denoms := map[string]osmosisDenom{"uosmo": {Symbol: "OSMO", Exponent: 6}}
+	for _, entry := range strings.Split(os.Getenv("OSMOSIS_DENOMS"), ",") {
+		entry = strings.TrimSpace(entry)
+		if entry == "" {
+			continue
+		}
+		denom, rest, ok := strings.Cut(entry, "=")
+		symbol, exp, ok2 := strings.Cut(rest, ":")
+		exponent, err := strconv.Atoi(exp)
+		if !ok || !ok2 || err != nil {
+			log.Printf("Ignoring invalid OSMOSIS_DENOMS entry %q", entry)
+			continue
+		}
+		denoms[denom] = osmosisDenom{Symbol: symbol, Exponent: exponent}
+	}
+	return denoms
+}
+
+// OsmosisConnector publishes a synthetic book for each pool in OSMOSIS_POOLS
+// ("1:ATOM/OSMO,1135:ATOM/USDT"), read from the LCD at OSMOSIS_LCD_URL.
+// Skipped when either is unset.
+func OsmosisConnector() {
+	lcd := strings.TrimRight(os.Getenv("OSMOSIS_LCD_URL"), "/")
+	pools := os.Getenv("OSMOSIS_POOLS")
+	if lcd == "" || pools == "" {
+		return
+	}
+	denoms := osmosisDenoms()
+
+	for _, entry := range strings.Split(pools, ",") {
+		poolID, pair, ok := strings.Cut(strings.TrimSpace(entry), ":")
+		base, quote, ok2 := strings.Cut(pair, "/")
+		if !ok || !ok2 {
+			log.Printf("Ignoring invalid OSMOSIS_POOLS entry %q", entry)
+			continue
+		}
+		if err := publishOsmosisPool(lcd, poolID, base, quote, denoms); err != nil {
+			log.Printf("osmosis pool %s: %v", poolID, err)
+		}
+	}
+}
+
+func publishOsmosisPool(lcd, poolID, base, quote string, denoms map[string]osmosisDenom) error {
+	resp, err := http.Get(lcd + "/osmosis/gamm/v1beta1/pools/" + poolID)
+	if err != nil {
+		return err
+	}
+	defer resp.Body.Close()
+	body, err := io.ReadAll(resp.Body)
+	if err != nil {
+		return err
+	}
+	if resp.StatusCode != http.StatusOK {
+		return fmt.Errorf("LCD returned %s: %s", resp.Status, body)
+	}
+
+	var poolResp OsmosisPoolResponse
+	if err := json.Unmarshal(body, &poolResp); err != nil {
+		return err
+	}
+
+	// Balances in human units and raw weights (only their ratio matters)
+	var baseBalance, baseWeight, quoteBalance, quoteWeight float64
+	for _, asset := range poolResp.Pool.PoolAssets {
+		denom, known := denoms[asset.Token.Denom]
+		if !known || (denom.Symbol != base && denom.Symbol != quote) {
+			continue
+		}
+		amount, err := parseFloat(asset.Token.Amount)
+		if err != nil {
+			return err
+		}
+		weight, err := parseFloat(asset.Weight)
+		if err != nil {
+			return err
+		}
+		balance := amount / math.Pow10(denom.Exponent)
+		if denom.Symbol == base {
+			baseBalance, baseWeight = balance, weight
+		} else {
+			quoteBalance, quoteWeight = balance, weight
+		}
+	}
+	if baseBalance <= 0 || quoteBalance <= 0 {
+		return fmt.Errorf("pool has no %s/%s assets (check OSMOSIS_DENOMS)", base, quote)
+	}
+
+	fee, err := parseFloat(poolResp.Pool.PoolParams.SwapFee)
+	if err != nil {
+		return err
+	}
+	// The analyzer charges OSMOSIS_SWAP_FEE per leg; keep it in line with this
+	fmt.Println("Using osmosis pool:", poolID, "swapFee:", poolResp.Pool.PoolParams.SwapFee)
+
+	// Sizes are only meaningful in quote terms here (pools range from ATOM/OSMO to
+	// stablecoin pairs), so bids sell the base worth each quote size at spot
+	spot := (quoteBalance / quoteWeight) / (baseBalance / baseWeight)
+	baseSizes := make([]float64, len(ammQuoteSizes))
+	for i, spend := range ammQuoteSizes {
+		baseSizes[i] = spend / spot
+	}
+
+	bids, asks := syntheticBook(
+		baseSizes, ammQuoteSizes,
+		func(size float64) (float64, error) {
+			return weightedOut(size, baseBalance, baseWeight, quoteBalance, quoteWeight, fee)
+		},
+		func(spend float64) (float64, error) {
+			return weightedOut(spend, quoteBalance, quoteWeight, baseBalance, baseWeight, fee)
+		},
+	)
+
+	ob := utils.NormalizationSchema{
+		Exchange:  "osmosis",
+		Pair:      base + "/" + quote,
+		Bids:      bids,
+		Asks:      asks,
+		Timestamp: time.Now().Unix(),
+	}
+
+	j, _ := json.MarshalIndent(ob, "", "  ")
+	fmt.Println(string(j))
+
+	return utils.PushOrderbook(context.Background(), ob)
+}
@@ -0,0 +1,114 @@
+package connectors
+
+import (
+	"context"
+	"encoding/json"
+	"fmt"
+	"log"
+	"os"
+	"time"
+
+	"github.com/Jkrish1011/SwapSleuth/arbitrage-bot-go/utils"
+)
+
+// SushiSwap pools are Uniswap v2 forks with a flat 0.3% swap fee
+const sushiswapFee = 0.003
+
+// --- Data types for the SushiSwap (v2) subgraph response (trimmed) ---
+type SushiswapResponse struct {
+	Data struct {
+		Pairs []struct {
+			ID       string                            `json:"id"`
+			Token0   struct{ Symbol, Decimals string } `json:"token0"`
+			Token1   struct{ Symbol, Decimals string } `json:"token1"`
+			Reserve0 string                            `json:"reserve0"` // human units
+			Reserve1 string                            `json:"reserve1"`
+		} `json:"pairs"`
+	} `json:"data"`
+}
+
+// SushiswapConnector publishes a synthetic WBTC/USDT book from the deepest
+// SushiSwap pair. Needs SUSHISWAP_SUBGRAPH_URL; skipped when it is unset.
+func SushiswapConnector() {
+	url := os.Getenv("SUSHISWAP_SUBGRAPH_URL")
+	if url == "" {
+		return
+	}
+
+	query := `{
+		pairs(
+			where: {
+				or: [
+					{ token0_: {symbol: "WBTC"}, token1_: {symbol: "USDT"} },
+					{ token0_: {symbol: "USDT"}, token1_: {symbol: "WBTC"} }
+				]
+			}
+			orderBy: reserveUSD
+			orderDirection: desc
+			first: 5
+		) {
+			id
+			token0 { symbol decimals }
+			token1 { symbol decimals }
+			reserve0
+			reserve1
+		}
+	}`
+
+	var sushiResp SushiswapResponse
+	if err := querySubgraph(url, query, &sushiResp); err != nil {
+		log.Println("error fetching sushiswap subgraph:", err)
+		return
+	}
+	if len(sushiResp.Data.Pairs) == 0 {
+		log.Println("No sushiswap pairs found")
+		return
+	}
+
+	pair := sushiResp.Data.Pairs[0]
+	fmt.Println("Using sushiswap pair:", pair.ID)
+
+	reserve0, err := parseFloat(pair.Reserve0)
+	if err != nil {
+		log.Println("sushiswap reserve0:", err)
+		return
+	}
+	reserve1, err := parseFloat(pair.Reserve1)
+	if err != nil {
+		log.Println("sushiswap reserve1:", err)
+		return
+	}
+
+	// Orient the pool as base (WBTC) / quote (USDT)
+	base, quote := pair.Token0.Symbol, pair.Token1.Symbol
+	baseReserve, quoteReserve := reserve0, reserve1
+	if base != "WBTC" {
+		base, quote = quote, base
+		baseReserve, quoteReserve = quoteReserve, baseReserve
+	}
+
+	bids, asks := syntheticBook(
+		ammBaseSizes, ammQuoteSizes,
+		func(size float64) (float64, error) {
+			return constantProductOut(size, baseReserve, quoteReserve, sushiswapFee)
+		},
+		func(spend float64) (float64, error) {
+			return constantProductOut(spend, quoteReserve, baseReserve, sushiswapFee)
+		},
+	)
+
+	ob := utils.NormalizationSchema{
+		Exchange:  "sushiswap",
+		Pair:      base + "/" + quote,
+		Bids:      bids,
+		Asks:      asks,
+		Timestamp: time.Now().Unix(),
+	}
+
+	j, _ := json.MarshalIndent(ob, "", "  ")
+	fmt.Println(string(j))
+
+	if err := utils.PushOrderbook(context.Background(), ob); err != nil {
+		fmt.Printf("error pushing orderbook to Redis: %v\n", err)
+	}
+}
@@ -29,6 +29,9 @@ func main() {
 	for {
 		connectors.BinanceConnector()
 		connectors.UniswapConnector()
+		connectors.SushiswapConnector()
+		connectors.BalancerConnector()
+		connectors.OsmosisConnector()
 		time.Sleep(5 * time.Second)
 	}
 
@@ -6,6 +6,7 @@ import (
 	"fmt"
 	"log"
 	"os"
+	"strconv"
 	"time"
 
 	"github.com/redis/go-redis/v9"
@@ -63,10 +64,31 @@ func PushOrderbook(ctx context.Context, ob NormalizationSchema) error {
 		return err
 	}
 
+	// Optionally keep a replayable history for postmortems (see `swapsleuth replay`)
+	if stream := os.Getenv("ORDERBOOK_STREAM"); stream != "" {
+		err = rdb.XAdd(ctx, &redis.XAddArgs{
+			Stream: stream,
+			MaxLen: orderbookStreamMaxLen(),
+			Approx: true,
+			Values: map[string]interface{}{"key": key, "book": data},
+		}).Err()
+		if err != nil {
+			return err
+		}
+	}
+
 	log.Printf("Pushed and Published orderbook to Redis: %s", key)
 	return nil
 }
 
+// orderbookStreamMaxLen caps ORDERBOOK_STREAM near ORDERBOOK_STREAM_MAXLEN entries (default 100000)
+func orderbookStreamMaxLen() int64 {
+	if n, err := strconv.ParseInt(os.Getenv("ORDERBOOK_STREAM_MAXLEN"), 10, 64); err == nil && n > 0 {
+		return n
+	}
+	return 100000
+}
+
 func GetFromOrderBook(ctx context.Context, key string) (NormalizationSchema, error) {
 	if rdb == nil {
 		return NormalizationSchema{}, fmt.Errorf("Redis client not initialized, call InitRedis() first")